@@ -0,0 +1,5 @@
+CREATE TABLE job_locks (
+    name            VARCHAR(64) NOT NULL PRIMARY KEY,
+    holder          VARCHAR(40) NOT NULL,
+    locked_until    DATETIME NOT NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN tenant_id VARCHAR(64);
+ALTER TABLE organizations ADD COLUMN tenant_id VARCHAR(64);
@@ -0,0 +1,11 @@
+CREATE TABLE usage_snapshots (
+    day                 DATE NOT NULL PRIMARY KEY,
+    users               BIGINT NOT NULL,
+    active_devices      BIGINT NOT NULL,
+    ciphers             BIGINT NOT NULL,
+    attachments         BIGINT NOT NULL,
+    attachments_bytes   BIGINT NOT NULL,
+    sends               BIGINT NOT NULL,
+    sends_bytes         BIGINT NOT NULL,
+    events              BIGINT NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE org_domains (
+    uuid                CHAR(36) NOT NULL PRIMARY KEY,
+    org_uuid            CHAR(36) NOT NULL REFERENCES organizations (uuid),
+    domain_name         VARCHAR(255) NOT NULL,
+    txt                 VARCHAR(255) NOT NULL,
+    created_at          DATETIME NOT NULL,
+    verified_at         DATETIME,
+    last_checked_at     DATETIME,
+    UNIQUE (org_uuid, domain_name)
+);
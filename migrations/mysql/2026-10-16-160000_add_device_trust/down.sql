@@ -0,0 +1,2 @@
+ALTER TABLE devices DROP COLUMN trusted;
+ALTER TABLE devices DROP COLUMN blocked;
@@ -0,0 +1,10 @@
+ALTER TABLE users ADD COLUMN support_access_until DATETIME;
+
+CREATE TABLE admin_audit_log (
+    uuid                CHAR(36) NOT NULL PRIMARY KEY,
+    created_at          DATETIME NOT NULL,
+    ip                  VARCHAR(45) NOT NULL,
+    action              VARCHAR(64) NOT NULL,
+    user_uuid           CHAR(36),
+    details             TEXT
+);
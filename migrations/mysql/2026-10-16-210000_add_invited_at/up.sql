@@ -0,0 +1,2 @@
+ALTER TABLE users_organizations ADD COLUMN invited_at DATETIME;
+ALTER TABLE users ADD COLUMN invited_at DATETIME;
@@ -0,0 +1 @@
+ALTER TABLE sends DROP COLUMN preview;
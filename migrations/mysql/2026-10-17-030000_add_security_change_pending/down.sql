@@ -0,0 +1 @@
+ALTER TABLE users DROP COLUMN security_change_pending_at;
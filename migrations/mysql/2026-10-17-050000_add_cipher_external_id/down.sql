@@ -0,0 +1,3 @@
+DROP INDEX ciphers_user_external_id ON ciphers;
+DROP INDEX ciphers_org_external_id ON ciphers;
+ALTER TABLE ciphers DROP COLUMN external_id;
@@ -0,0 +1,5 @@
+ALTER TABLE ciphers ADD COLUMN external_id VARCHAR(300);
+
+-- External ids are unique per owner, NULL values don't conflict with each other
+CREATE UNIQUE INDEX ciphers_user_external_id ON ciphers (user_uuid, external_id);
+CREATE UNIQUE INDEX ciphers_org_external_id ON ciphers (organization_uuid, external_id);
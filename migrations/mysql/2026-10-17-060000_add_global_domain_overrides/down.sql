@@ -0,0 +1 @@
+DROP TABLE global_domain_overrides;
@@ -0,0 +1,9 @@
+CREATE TABLE attachment_rekeys (
+    attachment_id CHAR(36) NOT NULL PRIMARY KEY,
+    cipher_uuid   CHAR(36) NOT NULL,
+    user_uuid     CHAR(36) NOT NULL REFERENCES users (uuid),
+    legacy        BOOLEAN NOT NULL DEFAULT FALSE,
+    replaced_by   CHAR(36),
+    created_at    DATETIME NOT NULL,
+    verified_at   DATETIME
+);
@@ -0,0 +1,2 @@
+ALTER TABLE organizations DROP COLUMN confidential_collections;
+ALTER TABLE collections DROP COLUMN confidential;
@@ -0,0 +1,5 @@
+CREATE TABLE quota_warnings (
+    owner_uuid CHAR(36) NOT NULL PRIMARY KEY,
+    level      INTEGER NOT NULL,
+    updated_at DATETIME NOT NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE users_organizations DROP COLUMN manage_sends;
+ALTER TABLE event DROP COLUMN send_uuid;
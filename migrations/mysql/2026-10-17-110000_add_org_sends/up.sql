@@ -0,0 +1,2 @@
+ALTER TABLE users_organizations ADD COLUMN manage_sends BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE event ADD COLUMN send_uuid CHAR(36);
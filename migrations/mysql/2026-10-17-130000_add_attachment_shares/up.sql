@@ -0,0 +1,10 @@
+CREATE TABLE attachment_shares (
+    token_hash    CHAR(64) NOT NULL PRIMARY KEY,
+    attachment_id CHAR(36) NOT NULL,
+    cipher_uuid   CHAR(36) NOT NULL,
+    user_uuid     CHAR(36) NOT NULL REFERENCES users (uuid),
+    created_at    DATETIME NOT NULL,
+    expires_at    DATETIME NOT NULL,
+    used_at       DATETIME,
+    used_ip       TEXT
+);
@@ -0,0 +1,9 @@
+CREATE TABLE tombstones (
+    uuid        CHAR(36) NOT NULL,
+    owner_uuid  CHAR(36) NOT NULL,
+    atype       INTEGER  NOT NULL,
+    deleted_at  DATETIME NOT NULL,
+    PRIMARY KEY (uuid, owner_uuid)
+);
+
+CREATE INDEX tombstones_owner_deleted ON tombstones (owner_uuid, deleted_at);
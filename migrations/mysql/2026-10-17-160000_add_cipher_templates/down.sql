@@ -0,0 +1,2 @@
+DROP TABLE cipher_templates;
+ALTER TABLE organizations DROP COLUMN enforce_cipher_templates;
@@ -0,0 +1,12 @@
+CREATE TABLE cipher_templates (
+    uuid                    CHAR(36) NOT NULL PRIMARY KEY,
+    org_uuid                CHAR(36) NOT NULL REFERENCES organizations (uuid),
+    name                    TEXT NOT NULL,
+    atype                   INTEGER NOT NULL,
+    fields                  TEXT NOT NULL,
+    default_collection_uuid CHAR(36),
+    created_at              DATETIME NOT NULL,
+    updated_at              DATETIME NOT NULL
+);
+
+ALTER TABLE organizations ADD COLUMN enforce_cipher_templates BOOLEAN NOT NULL DEFAULT FALSE;
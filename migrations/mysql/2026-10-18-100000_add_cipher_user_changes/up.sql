@@ -0,0 +1,8 @@
+CREATE TABLE cipher_user_changes (
+    user_uuid   CHAR(36) NOT NULL,
+    cipher_uuid CHAR(36) NOT NULL,
+    changed_at  DATETIME NOT NULL,
+    PRIMARY KEY (user_uuid, cipher_uuid)
+);
+
+CREATE INDEX cipher_user_changes_user_changed ON cipher_user_changes (user_uuid, changed_at);
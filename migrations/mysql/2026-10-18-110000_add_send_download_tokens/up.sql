@@ -0,0 +1,4 @@
+CREATE TABLE send_download_tokens (
+    uuid       CHAR(36) NOT NULL PRIMARY KEY,
+    expires_at DATETIME NOT NULL
+);
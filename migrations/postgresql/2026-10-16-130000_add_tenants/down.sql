@@ -0,0 +1,2 @@
+ALTER TABLE users DROP COLUMN tenant_id;
+ALTER TABLE organizations DROP COLUMN tenant_id;
@@ -0,0 +1 @@
+ALTER TABLE devices ADD COLUMN push_rejected_at TIMESTAMP;
@@ -0,0 +1,2 @@
+ALTER TABLE organizations DROP COLUMN welcome_message;
+ALTER TABLE collections DROP COLUMN onboarding;
@@ -0,0 +1,2 @@
+ALTER TABLE users_organizations DROP COLUMN invited_at;
+ALTER TABLE users DROP COLUMN invited_at;
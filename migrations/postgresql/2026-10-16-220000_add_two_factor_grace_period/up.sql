@@ -0,0 +1 @@
+ALTER TABLE users_organizations ADD COLUMN two_factor_grace_until TIMESTAMP;
@@ -0,0 +1 @@
+ALTER TABLE sends ADD COLUMN preview TEXT;
@@ -0,0 +1,3 @@
+DROP TABLE send_egress;
+
+ALTER TABLE sends DROP COLUMN download_bytes;
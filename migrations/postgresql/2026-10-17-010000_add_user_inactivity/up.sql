@@ -0,0 +1,6 @@
+ALTER TABLE users ADD COLUMN last_login_at TIMESTAMP;
+ALTER TABLE users ADD COLUMN inactivity_warned_at TIMESTAMP;
+ALTER TABLE users ADD COLUMN inactivity_exempt BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Use the last activity of the devices for the existing users
+UPDATE users SET last_login_at = (SELECT MAX(updated_at) FROM devices WHERE devices.user_uuid = users.uuid);
@@ -0,0 +1,14 @@
+CREATE TABLE login_fingerprints (
+    user_uuid   TEXT NOT NULL REFERENCES users (uuid),
+    device_uuid TEXT NOT NULL,
+    device_type INTEGER NOT NULL,
+    country     TEXT NOT NULL,
+    created_at  TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_uuid, device_uuid, device_type, country)
+);
+
+-- The existing devices are known without a country, so their users aren't notified of their next login
+INSERT INTO login_fingerprints (user_uuid, device_uuid, device_type, country, created_at)
+SELECT user_uuid, uuid, atype, '', created_at FROM devices;
+
+ALTER TABLE users ADD COLUMN login_notifications BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN security_change_pending_at TIMESTAMP;
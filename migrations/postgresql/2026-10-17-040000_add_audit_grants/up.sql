@@ -0,0 +1,9 @@
+CREATE TABLE audit_grants (
+    user_uuid       TEXT NOT NULL REFERENCES users (uuid),
+    collection_uuid TEXT NOT NULL REFERENCES collections (uuid),
+    org_uuid        TEXT NOT NULL REFERENCES organizations (uuid),
+    granted_by_uuid TEXT NOT NULL,
+    created_at      TIMESTAMP NOT NULL,
+    expires_at      TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_uuid, collection_uuid)
+);
@@ -0,0 +1,3 @@
+DROP INDEX ciphers_user_external_id;
+DROP INDEX ciphers_org_external_id;
+ALTER TABLE ciphers DROP COLUMN external_id;
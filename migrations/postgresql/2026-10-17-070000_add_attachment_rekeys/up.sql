@@ -0,0 +1,9 @@
+CREATE TABLE attachment_rekeys (
+    attachment_id TEXT NOT NULL PRIMARY KEY,
+    cipher_uuid   TEXT NOT NULL,
+    user_uuid     TEXT NOT NULL REFERENCES users (uuid),
+    legacy        BOOLEAN NOT NULL DEFAULT FALSE,
+    replaced_by   TEXT,
+    created_at    TIMESTAMP NOT NULL,
+    verified_at   TIMESTAMP
+);
@@ -0,0 +1,5 @@
+CREATE TABLE quota_warnings (
+    owner_uuid TEXT NOT NULL PRIMARY KEY,
+    level      INTEGER NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE organizations DROP COLUMN icon_service;
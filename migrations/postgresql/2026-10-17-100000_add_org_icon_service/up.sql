@@ -0,0 +1 @@
+ALTER TABLE organizations ADD COLUMN icon_service TEXT;
@@ -0,0 +1,10 @@
+CREATE TABLE attachment_shares (
+    token_hash    TEXT NOT NULL PRIMARY KEY,
+    attachment_id TEXT NOT NULL,
+    cipher_uuid   TEXT NOT NULL,
+    user_uuid     TEXT NOT NULL REFERENCES users (uuid),
+    created_at    TIMESTAMP NOT NULL,
+    expires_at    TIMESTAMP NOT NULL,
+    used_at       TIMESTAMP,
+    used_ip       TEXT
+);
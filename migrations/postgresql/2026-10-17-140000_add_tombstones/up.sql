@@ -0,0 +1,9 @@
+CREATE TABLE tombstones (
+    uuid        TEXT    NOT NULL,
+    owner_uuid  TEXT    NOT NULL,
+    atype       INTEGER NOT NULL,
+    deleted_at  TIMESTAMP NOT NULL,
+    PRIMARY KEY (uuid, owner_uuid)
+);
+
+CREATE INDEX tombstones_owner_deleted ON tombstones (owner_uuid, deleted_at);
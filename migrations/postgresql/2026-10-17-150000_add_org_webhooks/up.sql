@@ -0,0 +1,2 @@
+ALTER TABLE organizations ADD COLUMN webhook_url TEXT;
+ALTER TABLE organizations ADD COLUMN webhook_secret TEXT;
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN quota_exempt BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1 @@
+ALTER TABLE attachments ADD COLUMN created_at TIMESTAMP;
@@ -0,0 +1,5 @@
+CREATE TABLE job_locks (
+    name            TEXT NOT NULL PRIMARY KEY,
+    holder          TEXT NOT NULL,
+    locked_until    DATETIME NOT NULL
+);
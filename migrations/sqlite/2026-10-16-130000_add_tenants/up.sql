@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN tenant_id TEXT;
+ALTER TABLE organizations ADD COLUMN tenant_id TEXT;
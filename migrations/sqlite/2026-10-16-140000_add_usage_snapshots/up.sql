@@ -0,0 +1,11 @@
+CREATE TABLE usage_snapshots (
+    day                 DATE NOT NULL PRIMARY KEY,
+    users               INTEGER NOT NULL,
+    active_devices      INTEGER NOT NULL,
+    ciphers             INTEGER NOT NULL,
+    attachments         INTEGER NOT NULL,
+    attachments_bytes   INTEGER NOT NULL,
+    sends               INTEGER NOT NULL,
+    sends_bytes         INTEGER NOT NULL,
+    events              INTEGER NOT NULL
+);
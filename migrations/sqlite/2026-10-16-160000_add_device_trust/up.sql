@@ -0,0 +1,2 @@
+ALTER TABLE devices ADD COLUMN trusted BOOLEAN NOT NULL DEFAULT 0; -- FALSE
+ALTER TABLE devices ADD COLUMN blocked BOOLEAN NOT NULL DEFAULT 0; -- FALSE
@@ -0,0 +1,10 @@
+ALTER TABLE users ADD COLUMN support_access_until DATETIME;
+
+CREATE TABLE admin_audit_log (
+    uuid                TEXT NOT NULL PRIMARY KEY,
+    created_at          DATETIME NOT NULL,
+    ip                  TEXT NOT NULL,
+    action              TEXT NOT NULL,
+    user_uuid           TEXT,
+    details             TEXT
+);
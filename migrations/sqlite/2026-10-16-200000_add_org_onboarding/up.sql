@@ -0,0 +1,2 @@
+ALTER TABLE organizations ADD COLUMN welcome_message TEXT;
+ALTER TABLE collections ADD COLUMN onboarding BOOLEAN NOT NULL DEFAULT 0; -- FALSE
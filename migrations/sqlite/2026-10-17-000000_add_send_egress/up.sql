@@ -0,0 +1,6 @@
+CREATE TABLE send_egress (
+    month   TEXT NOT NULL PRIMARY KEY,
+    bytes   BIGINT NOT NULL
+);
+
+ALTER TABLE sends ADD COLUMN download_bytes BIGINT NOT NULL DEFAULT 0;
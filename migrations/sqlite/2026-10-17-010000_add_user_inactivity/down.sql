@@ -0,0 +1,3 @@
+ALTER TABLE users DROP COLUMN last_login_at;
+ALTER TABLE users DROP COLUMN inactivity_warned_at;
+ALTER TABLE users DROP COLUMN inactivity_exempt;
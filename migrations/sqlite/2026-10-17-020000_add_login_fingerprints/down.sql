@@ -0,0 +1,3 @@
+DROP TABLE login_fingerprints;
+
+ALTER TABLE users DROP COLUMN login_notifications;
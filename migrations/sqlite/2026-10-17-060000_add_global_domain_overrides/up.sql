@@ -0,0 +1,7 @@
+-- Changes of the admin to the built-in global equivalent domains, and the custom sets of the instance
+CREATE TABLE global_domain_overrides (
+    atype      INTEGER NOT NULL PRIMARY KEY,
+    domains    TEXT NOT NULL,
+    disabled   BOOLEAN NOT NULL DEFAULT 0, -- FALSE
+    updated_at DATETIME NOT NULL
+);
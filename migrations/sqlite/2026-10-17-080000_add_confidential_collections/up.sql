@@ -0,0 +1,2 @@
+ALTER TABLE organizations ADD COLUMN confidential_collections BOOLEAN NOT NULL DEFAULT 0; -- FALSE
+ALTER TABLE collections ADD COLUMN confidential BOOLEAN NOT NULL DEFAULT 0; -- FALSE
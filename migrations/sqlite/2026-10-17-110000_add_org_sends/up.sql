@@ -0,0 +1,2 @@
+ALTER TABLE users_organizations ADD COLUMN manage_sends BOOLEAN NOT NULL DEFAULT 0; -- FALSE
+ALTER TABLE event ADD COLUMN send_uuid TEXT;
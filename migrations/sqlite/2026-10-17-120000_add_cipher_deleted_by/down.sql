@@ -0,0 +1 @@
+ALTER TABLE ciphers DROP COLUMN deleted_by;
@@ -0,0 +1 @@
+ALTER TABLE ciphers ADD COLUMN deleted_by TEXT;
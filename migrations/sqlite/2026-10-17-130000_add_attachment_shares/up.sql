@@ -0,0 +1,10 @@
+CREATE TABLE attachment_shares (
+    token_hash    TEXT NOT NULL PRIMARY KEY,
+    attachment_id TEXT NOT NULL,
+    cipher_uuid   TEXT NOT NULL,
+    user_uuid     TEXT NOT NULL REFERENCES users (uuid),
+    created_at    DATETIME NOT NULL,
+    expires_at    DATETIME NOT NULL,
+    used_at       DATETIME,
+    used_ip       TEXT
+);
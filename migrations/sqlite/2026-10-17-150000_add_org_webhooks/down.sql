@@ -0,0 +1,2 @@
+ALTER TABLE organizations DROP COLUMN webhook_url;
+ALTER TABLE organizations DROP COLUMN webhook_secret;
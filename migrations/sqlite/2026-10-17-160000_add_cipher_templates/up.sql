@@ -0,0 +1,12 @@
+CREATE TABLE cipher_templates (
+    uuid                    TEXT NOT NULL PRIMARY KEY,
+    org_uuid                TEXT NOT NULL REFERENCES organizations (uuid),
+    name                    TEXT NOT NULL,
+    atype                   INTEGER NOT NULL,
+    fields                  TEXT NOT NULL,
+    default_collection_uuid TEXT,
+    created_at              DATETIME NOT NULL,
+    updated_at              DATETIME NOT NULL
+);
+
+ALTER TABLE organizations ADD COLUMN enforce_cipher_templates BOOLEAN NOT NULL DEFAULT 0; -- FALSE
@@ -0,0 +1,4 @@
+CREATE TABLE send_download_tokens (
+    uuid       TEXT     NOT NULL PRIMARY KEY,
+    expires_at DATETIME NOT NULL
+);
@@ -0,0 +1,586 @@
+//
+// Built-in ACME (RFC 8555) client
+//
+// When `ACME_ENABLED` is set, the certificate of the DOMAIN is requested from the configured CA and Rocket serves HTTPS
+// with it. The account key, the certificate and its key are stored in `ACME_FOLDER`.
+//
+// The control of the domain is proven with one of these challenges:
+// - http-01: A small plain HTTP server, always running on `ACME_HTTP_LISTEN`, answers the challenges of the CA.
+//   Every other request to it is redirected to the DOMAIN.
+// - tls-alpn-01: The CA connects to the HTTPS port and expects a special certificate for the `acme-tls/1` protocol.
+//   Rocket can't answer it, so the certificate is requested before Rocket starts listening, on the address Rocket uses.
+//
+// Rocket can't reload its TLS configuration, so once a new certificate is available, Rocket is shut down gracefully
+// and launched again by `launch_rocket`, which picks up the new files.
+//
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::{self, Write},
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+use data_encoding::BASE64URL_NOPAD;
+use openssl::{
+    asn1::{Asn1Object, Asn1OctetString, Asn1Time},
+    bn::{BigNum, BigNumContext},
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    hash::{MessageDigest, hash},
+    nid::Nid,
+    pkey::{PKey, Private},
+    sign::Signer,
+    ssl::{AlpnError, SslAcceptor, SslMethod, select_next_proto},
+    stack::Stack,
+    x509::{X509, X509Extension, X509NameBuilder, X509Req, extension::SubjectAlternativeName},
+};
+use reqwest::{Method, Response, header};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::{CONFIG, api::EmptyResult, error::Error, http_client::make_http_request, storage};
+
+// ALPN protocol of the tls-alpn-01 challenge, in wire format
+const ACME_TLS_ALPN: &[u8] = b"\x0aacme-tls/1";
+// OID of the acmeIdentifier extension (RFC 8737)
+const ACME_IDENTIFIER_OID: &str = "1.3.6.1.5.5.7.1.31";
+
+fn account_key_path() -> String {
+    storage::join_path(&CONFIG.acme_folder(), "account.pem")
+}
+
+fn certificate_path() -> String {
+    storage::join_path(&CONFIG.acme_folder(), "cert.pem")
+}
+
+fn certificate_key_path() -> String {
+    storage::join_path(&CONFIG.acme_folder(), "key.pem")
+}
+
+fn acme_domain() -> Result<String, Error> {
+    match url::Url::parse(&CONFIG.domain()).ok().and_then(|url| url.host_str().map(str::to_owned)) {
+        Some(domain) => Ok(domain),
+        None => err!("Unable to get the host of the DOMAIN"),
+    }
+}
+
+/// Creates the ACME folder and starts the http-01 responder
+pub fn init() {
+    if let Err(e) = std::fs::create_dir_all(CONFIG.acme_folder()) {
+        error!("Error creating ACME folder '{}': {e}", CONFIG.acme_folder());
+    }
+    if CONFIG.acme_challenge() == "http-01" {
+        tokio::spawn(start_http_listener(CONFIG.acme_http_listen()));
+    }
+}
+
+pub fn has_certificate() -> bool {
+    Path::new(&certificate_path()).exists() && Path::new(&certificate_key_path()).exists()
+}
+
+pub fn tls_config() -> rocket::config::TlsConfig {
+    rocket::config::TlsConfig::from_paths(certificate_path(), certificate_key_path())
+}
+
+// A certificate is needed when there is none, when it expires soon, or when the DOMAIN changed
+fn needs_certificate() -> bool {
+    let Ok(domain) = acme_domain() else {
+        return false;
+    };
+    let Ok(cert) = std::fs::read(certificate_path()).map_err(Error::from).and_then(|pem| Ok(X509::from_pem(&pem)?))
+    else {
+        return true;
+    };
+    let Ok(renew_after) = Asn1Time::days_from_now(CONFIG.acme_renew_days()) else {
+        return true;
+    };
+    let matches_domain =
+        cert.subject_alt_names().is_some_and(|names| names.iter().any(|name| name.dnsname() == Some(domain.as_str())));
+    !matches_domain || cert.not_after() < renew_after
+}
+
+/// Requests a certificate if there is none yet, or if the current one needs to be renewed.
+/// `address` is the address Rocket is going to listen on, which answers the tls-alpn-01 challenges.
+pub async fn ensure_certificate(address: SocketAddr) -> EmptyResult {
+    if !needs_certificate() {
+        return Ok(());
+    }
+    order_certificate(Some(address)).await
+}
+
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Returns true once after a renewed certificate needs Rocket to be launched again
+pub fn take_reload_request() -> bool {
+    RELOAD_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+fn request_reload() {
+    RELOAD_REQUESTED.store(true, Ordering::Relaxed);
+    CONFIG.shutdown();
+}
+
+pub async fn renew_job() {
+    if !needs_certificate() {
+        return;
+    }
+
+    // Rocket needs to release its port first, the certificate is requested before it is launched again
+    if CONFIG.acme_challenge() == "tls-alpn-01" {
+        info!(
+            "The ACME certificate needs to be renewed, restarting the web server to answer the tls-alpn-01 challenge"
+        );
+        request_reload();
+        return;
+    }
+
+    match order_certificate(None).await {
+        Ok(()) => {
+            info!("Restarting the web server to load the renewed ACME certificate");
+            request_reload();
+        }
+        Err(e) => error!("Unable to renew the ACME certificate: {e:?}"),
+    }
+}
+
+async fn order_certificate(address: Option<SocketAddr>) -> EmptyResult {
+    let domain = acme_domain()?;
+    info!("Requesting a certificate for {domain} from {}", CONFIG.acme_directory_url());
+
+    let mut client = AcmeClient::new().await?;
+
+    let contact: Vec<String> = CONFIG.acme_email().into_iter().map(|email| format!("mailto:{email}")).collect();
+    let new_account = client.directory_url("newAccount")?;
+    let (_, account_url) =
+        client.post_json(&new_account, Some(&json!({ "termsOfServiceAgreed": true, "contact": contact }))).await?;
+    let Some(account_url) = account_url else {
+        err!("The ACME server didn't return the account URL")
+    };
+    client.account_url = Some(account_url);
+
+    let new_order = client.directory_url("newOrder")?;
+    let (order, order_url) =
+        client.post_json(&new_order, Some(&json!({ "identifiers": [{ "type": "dns", "value": domain }] }))).await?;
+    let Some(order_url) = order_url else {
+        err!("The ACME server didn't return the order URL")
+    };
+
+    for authorization_url in json_strings(&order["authorizations"]) {
+        client.authorize(&authorization_url, &domain, address).await?;
+    }
+
+    let certificate_key = generate_key()?;
+    let csr = certificate_request(&domain, &certificate_key)?;
+    let Some(finalize_url) = order["finalize"].as_str() else {
+        err!("The ACME order has no finalize URL")
+    };
+    client.post_json(finalize_url, Some(&json!({ "csr": BASE64URL_NOPAD.encode(&csr) }))).await?;
+
+    let order = client.poll(&order_url, &["pending", "ready", "processing"]).await?;
+    let Some(certificate_url) = order["certificate"].as_str().filter(|_| order["status"] == "valid") else {
+        err!(format!("The ACME order of {domain} failed: {order}"))
+    };
+    let certificate = client.post(certificate_url, None).await?.text().await?;
+    if X509::stack_from_pem(certificate.as_bytes())?.is_empty() {
+        err!("The ACME server returned an empty certificate chain")
+    }
+
+    write_private(&certificate_key_path(), &certificate_key.private_key_to_pem_pkcs8()?)?;
+    std::fs::write(certificate_path(), certificate)?;
+    info!("ACME certificate for {domain} saved to '{}'", certificate_path());
+    Ok(())
+}
+
+fn json_strings(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|values| values.iter().filter_map(Value::as_str).map(str::to_owned).collect())
+        .unwrap_or_default()
+}
+
+struct AcmeClient {
+    key: PKey<Private>,
+    jwk: Value,
+    thumbprint: String,
+    directory: Value,
+    nonce: Option<String>,
+    account_url: Option<String>,
+}
+
+impl AcmeClient {
+    async fn new() -> Result<Self, Error> {
+        let key = load_or_create_account_key()?;
+        let (jwk, thumbprint) = jwk(&key)?;
+        let directory = make_http_request(Method::GET, &CONFIG.acme_directory_url())?
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        Ok(Self {
+            key,
+            jwk,
+            thumbprint,
+            directory,
+            nonce: None,
+            account_url: None,
+        })
+    }
+
+    fn directory_url(&self, name: &str) -> Result<String, Error> {
+        match self.directory[name].as_str() {
+            Some(url) => Ok(url.to_owned()),
+            None => err!(format!("The ACME directory has no `{name}` URL")),
+        }
+    }
+
+    async fn nonce(&mut self) -> Result<String, Error> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+        let response = make_http_request(Method::HEAD, &self.directory_url("newNonce")?)?.send().await?;
+        match replay_nonce(&response) {
+            Some(nonce) => Ok(nonce),
+            None => err!("The ACME server didn't return a nonce"),
+        }
+    }
+
+    // Sends a signed request, without payload this is a POST-as-GET
+    async fn post(&mut self, url: &str, payload: Option<&Value>) -> Result<Response, Error> {
+        let mut retries = 0;
+        loop {
+            let mut protected = json!({ "alg": "ES256", "nonce": self.nonce().await?, "url": url });
+            match &self.account_url {
+                Some(account_url) => protected["kid"] = json!(account_url),
+                None => protected["jwk"] = self.jwk.clone(),
+            }
+            let body = sign(&self.key, &protected, payload)?;
+
+            let response = make_http_request(Method::POST, url)?
+                .header(header::CONTENT_TYPE, "application/jose+json")
+                .body(body.to_string())
+                .send()
+                .await?;
+            self.nonce = replay_nonce(&response);
+            if response.status().is_success() {
+                return Ok(response);
+            }
+
+            // Nonces expire, the error contains a fresh one to retry with
+            let problem = response.json::<Value>().await.unwrap_or_default();
+            if problem["type"] == "urn:ietf:params:acme:error:badNonce" && retries < 3 {
+                retries += 1;
+                continue;
+            }
+            err!(format!("ACME request to {url} failed: {problem}"))
+        }
+    }
+
+    // Returns the JSON response and its Location header
+    async fn post_json(&mut self, url: &str, payload: Option<&Value>) -> Result<(Value, Option<String>), Error> {
+        let response = self.post(url, payload).await?;
+        let location = response.headers().get(header::LOCATION).and_then(|l| l.to_str().ok()).map(str::to_owned);
+        Ok((response.json().await?, location))
+    }
+
+    // Fetches the object until its status isn't one of the `pending` statuses anymore
+    async fn poll(&mut self, url: &str, pending: &[&str]) -> Result<Value, Error> {
+        for _ in 0..30 {
+            let (object, _) = self.post_json(url, None).await?;
+            if !pending.iter().any(|status| object["status"] == *status) {
+                return Ok(object);
+            }
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+        err!(format!("Timeout waiting for the ACME object {url}"))
+    }
+
+    async fn authorize(&mut self, url: &str, domain: &str, address: Option<SocketAddr>) -> EmptyResult {
+        let (authorization, _) = self.post_json(url, None).await?;
+        if authorization["status"] == "valid" {
+            return Ok(());
+        }
+
+        let kind = CONFIG.acme_challenge();
+        let challenge = authorization["challenges"]
+            .as_array()
+            .and_then(|challenges| challenges.iter().find(|challenge| challenge["type"] == kind.as_str()));
+        let Some((token, challenge_url)) = challenge.and_then(|challenge| {
+            Some((challenge["token"].as_str()?.to_owned(), challenge["url"].as_str()?.to_owned()))
+        }) else {
+            err!(format!("The ACME server doesn't offer a valid {kind} challenge for {domain}"))
+        };
+        let key_authorization = format!("{token}.{}", self.thumbprint);
+
+        let responder = if kind == "tls-alpn-01" {
+            let Some(address) = address else {
+                err!("The tls-alpn-01 challenge can only be answered before the web server is started")
+            };
+            Some(start_tls_alpn_responder(address, domain, &key_authorization).await?)
+        } else {
+            if let Ok(mut challenges) = HTTP_CHALLENGES.lock() {
+                challenges.insert(token.clone(), key_authorization);
+            }
+            None
+        };
+
+        let result = async {
+            self.post_json(&challenge_url, Some(&json!({}))).await?;
+            self.poll(url, &["pending", "processing"]).await
+        }
+        .await;
+
+        if let Some(responder) = responder {
+            responder.abort();
+        }
+        if let Ok(mut challenges) = HTTP_CHALLENGES.lock() {
+            challenges.remove(&token);
+        }
+
+        let authorization = result?;
+        if authorization["status"] != "valid" {
+            err!(format!("The ACME authorization of {domain} failed: {}", authorization["challenges"]))
+        }
+        Ok(())
+    }
+}
+
+fn replay_nonce(response: &Response) -> Option<String> {
+    response.headers().get("Replay-Nonce").and_then(|nonce| nonce.to_str().ok()).map(str::to_owned)
+}
+
+fn generate_key() -> Result<PKey<Private>, Error> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    Ok(PKey::from_ec_key(EcKey::generate(&group)?)?)
+}
+
+fn load_or_create_account_key() -> Result<PKey<Private>, Error> {
+    let path = account_key_path();
+    if let Ok(pem) = std::fs::read(&path) {
+        return Ok(PKey::private_key_from_pem(&pem)?);
+    }
+    let key = generate_key()?;
+    write_private(&path, &key.private_key_to_pem_pkcs8()?)?;
+    info!("ACME account key '{path}' created");
+    Ok(key)
+}
+
+fn write_private(path: &str, data: &[u8]) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options.open(path)?.write_all(data)
+}
+
+// Returns the JWK of the account key and its thumbprint (RFC 7638)
+fn jwk(key: &PKey<Private>) -> Result<(Value, String), Error> {
+    let ec_key = key.ec_key()?;
+    let mut ctx = BigNumContext::new()?;
+    let (mut x, mut y) = (BigNum::new()?, BigNum::new()?);
+    ec_key.public_key().affine_coordinates_gfp(ec_key.group(), &mut x, &mut y, &mut ctx)?;
+    let x = BASE64URL_NOPAD.encode(&x.to_vec_padded(32)?);
+    let y = BASE64URL_NOPAD.encode(&y.to_vec_padded(32)?);
+
+    // The thumbprint is computed over the required members, in lexicographic order and without whitespace
+    let canonical = format!(r#"{{"crv":"P-256","kty":"EC","x":"{x}","y":"{y}"}}"#);
+    let thumbprint = BASE64URL_NOPAD.encode(&hash(MessageDigest::sha256(), canonical.as_bytes())?);
+    Ok((json!({ "crv": "P-256", "kty": "EC", "x": x, "y": y }), thumbprint))
+}
+
+// Creates a flattened JWS, ES256 signatures are the raw r and s values instead of the DER encoding of OpenSSL
+fn sign(key: &PKey<Private>, protected: &Value, payload: Option<&Value>) -> Result<Value, Error> {
+    let protected = BASE64URL_NOPAD.encode(protected.to_string().as_bytes());
+    let payload = payload.map(|payload| BASE64URL_NOPAD.encode(payload.to_string().as_bytes())).unwrap_or_default();
+
+    let mut signer = Signer::new(MessageDigest::sha256(), key)?;
+    signer.update(format!("{protected}.{payload}").as_bytes())?;
+    let signature = EcdsaSig::from_der(&signer.sign_to_vec()?)?;
+    let mut raw_signature = signature.r().to_vec_padded(32)?;
+    raw_signature.extend(signature.s().to_vec_padded(32)?);
+
+    Ok(json!({
+        "protected": protected,
+        "payload": payload,
+        "signature": BASE64URL_NOPAD.encode(&raw_signature),
+    }))
+}
+
+fn certificate_request(domain: &str, key: &PKey<Private>) -> Result<Vec<u8>, Error> {
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, domain)?;
+    let name = name.build();
+
+    let mut request = X509Req::builder()?;
+    request.set_pubkey(key)?;
+    request.set_subject_name(&name)?;
+    let san = SubjectAlternativeName::new().dns(domain).build(&request.x509v3_context(None))?;
+    let mut extensions = Stack::new()?;
+    extensions.push(san)?;
+    request.add_extensions(&extensions)?;
+    request.sign(key, MessageDigest::sha256())?;
+    Ok(request.build().to_der()?)
+}
+
+//
+// http-01
+//
+// Tokens of the pending challenges, mapped to their key authorization
+static HTTP_CHALLENGES: LazyLock<Mutex<HashMap<String, String>>> = LazyLock::new(Default::default);
+
+async fn start_http_listener(listen: String) {
+    let listener = match TcpListener::bind(&listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Unable to listen for ACME http-01 challenges on {listen}: {e}");
+            return;
+        }
+    };
+    info!("Answering ACME http-01 challenges on {listen}");
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                accept_error_backoff("ACME http-01", &e).await;
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            if let Err(e) = tokio::time::timeout(Duration::from_secs(10), answer_http(stream)).await {
+                debug!("ACME http-01 connection timed out: {e}");
+            }
+        });
+    }
+}
+
+// Errors like EMFILE persist until connections are closed elsewhere, retrying at once would spin on them
+async fn accept_error_backoff(listener: &str, e: &io::Error) {
+    warn!("Error accepting {listener} connection: {e}");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+}
+
+async fn answer_http(mut stream: TcpStream) -> io::Result<()> {
+    let mut request_line = String::new();
+    {
+        let mut reader = BufReader::new((&mut stream).take(16 * 1024));
+        reader.read_line(&mut request_line).await?;
+        // Read the headers as well, closing a connection with unread data could reset it before the response arrives
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).await? == 0 || header.trim_end().is_empty() {
+                break;
+            }
+        }
+    }
+
+    let path = request_line.split(' ').nth(1).filter(|path| path.starts_with('/') && !path.contains(char::is_control));
+    let response = match path.and_then(|path| path.strip_prefix("/.well-known/acme-challenge/")) {
+        Some(token) => match HTTP_CHALLENGES.lock().ok().and_then(|challenges| challenges.get(token).cloned()) {
+            Some(key_authorization) => format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{key_authorization}",
+                key_authorization.len()
+            ),
+            None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned(),
+        },
+        None => format!(
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: {}{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            CONFIG.domain_origin(),
+            path.unwrap_or("/")
+        ),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+//
+// tls-alpn-01
+//
+async fn start_tls_alpn_responder(
+    address: SocketAddr,
+    domain: &str,
+    key_authorization: &str,
+) -> Result<JoinHandle<()>, Error> {
+    let (certificate, key) = challenge_certificate(domain, key_authorization)?;
+    let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+    acceptor.set_certificate(&certificate)?;
+    acceptor.set_private_key(&key)?;
+    acceptor
+        .set_alpn_select_callback(|_, client| select_next_proto(ACME_TLS_ALPN, client).ok_or(AlpnError::ALERT_FATAL));
+    let acceptor = Arc::new(acceptor.build());
+
+    let listener = TcpListener::bind(address).await?;
+    info!("Answering the ACME tls-alpn-01 challenge on {address}");
+
+    Ok(tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    accept_error_backoff("ACME tls-alpn-01", &e).await;
+                    continue;
+                }
+            };
+            let acceptor = Arc::clone(&acceptor);
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = answer_tls_alpn(stream, &acceptor) {
+                    debug!("ACME tls-alpn-01 connection failed: {e}");
+                }
+            });
+        }
+    }))
+}
+
+// The CA only checks the certificate presented during the handshake
+fn answer_tls_alpn(stream: TcpStream, acceptor: &SslAcceptor) -> io::Result<()> {
+    let stream = stream.into_std()?;
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut stream = acceptor.accept(stream).map_err(|e| io::Error::other(e.to_string()))?;
+    stream.shutdown().map_err(io::Error::other)?;
+    Ok(())
+}
+
+// Self-signed certificate with the SHA-256 of the key authorization in the critical acmeIdentifier extension
+fn challenge_certificate(domain: &str, key_authorization: &str) -> Result<(X509, PKey<Private>), Error> {
+    let key = generate_key()?;
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, domain)?;
+    let name = name.build();
+
+    let mut certificate = X509::builder()?;
+    certificate.set_version(2)?;
+    certificate.set_serial_number(&BigNum::from_u32(1)?.to_asn1_integer()?)?;
+    certificate.set_subject_name(&name)?;
+    certificate.set_issuer_name(&name)?;
+    certificate.set_pubkey(&key)?;
+    certificate.set_not_before(&Asn1Time::days_from_now(0)?)?;
+    certificate.set_not_after(&Asn1Time::days_from_now(7)?)?;
+    let san = SubjectAlternativeName::new().dns(domain).build(&certificate.x509v3_context(None, None))?;
+    certificate.append_extension(san)?;
+
+    // The extension value is the DER encoded OCTET STRING of the digest
+    let mut identifier = vec![0x04, 0x20];
+    identifier.extend_from_slice(&hash(MessageDigest::sha256(), key_authorization.as_bytes())?);
+    let oid = Asn1Object::from_str(ACME_IDENTIFIER_OID)?;
+    certificate.append_extension(X509Extension::new_from_der(
+        &oid,
+        true,
+        &Asn1OctetString::new_from_bytes(&identifier)?,
+    )?)?;
+    certificate.sign(&key, MessageDigest::sha256())?;
+    Ok((certificate.build(), key))
+}
@@ -1,10 +1,12 @@
 use std::{env, sync::LazyLock};
 
+use chrono::{NaiveDateTime, TimeDelta, Utc};
 use reqwest::Method;
 use rocket::{
-    Catcher, Route,
+    Catcher, Route, State,
     form::Form,
-    http::{Cookie, CookieJar, MediaType, SameSite, Status},
+    fs::NamedFile,
+    http::{Cookie, CookieJar, Header, MediaType, SameSite, Status},
     request::{FromRequest, Outcome, Request},
     response::{Redirect, content::RawHtml as Html},
     serde::json::Json,
@@ -16,16 +18,24 @@ use crate::{
     CONFIG, VERSION,
     api::{
         ApiResult, EmptyResult, JsonResult, Notify,
-        core::{log_event, two_factor},
-        unregister_push_device,
+        core::{
+            CUSTOM_GLOBAL_DOMAINS_START, builtin_global_domains, last_ldap_sync, log_event, sync_ldap_directory,
+            two_factor,
+        },
+        push_reregistration_status, reregister_push_devices, start_push_reregistration, unregister_push_device,
+    },
+    auth::{
+        ClientIp, Secure, decode_admin, decode_support_session, encode_jwt, generate_admin_claims,
+        generate_support_session_claims,
     },
-    auth::{ClientIp, Secure, decode_admin, encode_jwt, generate_admin_claims},
     config::ConfigBuilder,
     db::{
-        ACTIVE_DB_TYPE, DbConn, DbConnType, backup_sqlite, get_sql_server_version,
+        DbConn, DbPool, active_db_type_name, backup_sqlite, get_sql_server_version,
         models::{
-            Attachment, Cipher, Collection, Device, Event, EventType, Group, Invitation, Membership, MembershipId,
-            MembershipType, OrgPolicy, Organization, OrganizationId, SsoUser, TwoFactor, User, UserId,
+            AdminAuditLog, Attachment, Cipher, Collection, Device, DeviceId, DeviceType, Event, EventType,
+            GlobalDomainOverride, Group, Invitation, JobLock, Membership, MembershipId, MembershipType, OrgPolicy,
+            OrgPolicyType, Organization, OrganizationId, Send, SendEgress, SsoUser, TwoFactor, UsageSnapshot, User,
+            UserId,
         },
     },
     error::{Error, MapResult},
@@ -33,8 +43,8 @@ use crate::{
     mail,
     sso::FAKE_SSO_IDENTIFIER,
     util::{
-        FeatureFlagFilter, NumberOrString, container_base_image, format_naive_datetime_local, get_active_web_release,
-        get_display_size, is_running_in_container, parse_experimental_client_feature_flags,
+        FeatureFlagFilter, NumberOrString, container_base_image, format_date, format_naive_datetime_local,
+        get_active_web_release, get_display_size, is_running_in_container, parse_experimental_client_feature_flags,
     },
 };
 
@@ -55,11 +65,19 @@ pub fn routes() -> Vec<Route> {
         delete_user,
         delete_sso_user,
         deauth_user,
+        get_user_devices,
+        revoke_user_device,
         disable_user,
         enable_user,
         remove_2fa,
         update_membership_type,
         update_revision_users,
+        get_global_domains,
+        create_global_domains,
+        update_global_domains,
+        delete_global_domains,
+        get_push_reregistration,
+        reregister_push,
         post_config,
         delete_config,
         backup_db,
@@ -67,10 +85,29 @@ pub fn routes() -> Vec<Route> {
         users_overview,
         organizations_overview,
         delete_organization,
+        ldap_overview,
+        ldap_sync,
         diagnostics,
         get_diagnostics_config,
+        get_diagnostics_config_audit,
+        get_diagnostics_usage_statistics,
+        get_diagnostics_integrity,
+        get_orphans,
+        download_anonymized_dump,
+        cleanup_orphans,
         resend_user_invite,
+        get_invitations,
+        resend_expired_invitations,
+        get_inactive_users,
+        set_inactivity_exemption,
+        set_quota_exemption,
         get_diagnostics_http,
+        download_dr_bundle,
+        store_dr_bundle,
+        get_usage,
+        create_support_session,
+        get_support_session,
+        get_audit_log,
     ]
 }
 
@@ -82,18 +119,11 @@ pub fn catchers() -> Vec<Catcher> {
     }
 }
 
-static DB_TYPE: LazyLock<&str> = LazyLock::new(|| match ACTIVE_DB_TYPE.get() {
-    #[cfg(mysql)]
-    Some(DbConnType::Mysql) => "MySQL",
-    #[cfg(postgresql)]
-    Some(DbConnType::Postgresql) => "PostgreSQL",
-    #[cfg(sqlite)]
-    Some(DbConnType::Sqlite) => "SQLite",
-    _ => "Unknown",
-});
+static DB_TYPE: LazyLock<&str> = LazyLock::new(active_db_type_name);
 
 #[cfg(sqlite)]
-static CAN_BACKUP: LazyLock<bool> = LazyLock::new(|| ACTIVE_DB_TYPE.get().is_some_and(|t| *t == DbConnType::Sqlite));
+static CAN_BACKUP: LazyLock<bool> =
+    LazyLock::new(|| crate::db::ACTIVE_DB_TYPE.get().is_some_and(|t| *t == crate::db::DbConnType::Sqlite));
 #[cfg(not(sqlite))]
 static CAN_BACKUP: LazyLock<bool> = LazyLock::new(|| false);
 
@@ -108,7 +138,7 @@ const DT_FMT: &str = "%Y-%m-%d %H:%M:%S %Z";
 
 const BASE_TEMPLATE: &str = "admin/base";
 
-const ACTING_ADMIN_USER: &str = "vaultwarden-admin-00000-000000000000";
+pub const ACTING_ADMIN_USER: &str = "vaultwarden-admin-00000-000000000000";
 pub const FAKE_ADMIN_UUID: &str = "00000000-0000-0000-0000-000000000000";
 
 fn admin_path() -> String {
@@ -182,15 +212,23 @@ struct LoginForm {
 }
 
 #[post("/", format = "application/x-www-form-urlencoded", data = "<data>")]
-fn post_admin_login(
+async fn post_admin_login(
     data: Form<LoginForm>,
     cookies: &CookieJar<'_>,
     ip: ClientIp,
     secure: Secure,
+    conn: DbConn,
 ) -> Result<Redirect, AdminResponse> {
     let data = data.into_inner();
     let redirect = data.redirect;
 
+    if crate::ratelimit::check_admin_lockout(&ip.ip).is_err() {
+        return Err(AdminResponse::TooManyRequests(render_admin_login(
+            Some("Too many failed attempts, the admin panel is locked for a while."),
+            redirect.as_deref(),
+        )));
+    }
+
     if crate::ratelimit::check_limit_admin(&ip.ip).is_err() {
         return Err(AdminResponse::TooManyRequests(render_admin_login(
             Some("Too many requests, try again later."),
@@ -200,6 +238,9 @@ fn post_admin_login(
 
     // If the token is invalid, redirect to login page
     if validate_token(&data.token) {
+        crate::ratelimit::clear_admin_login_failures(&ip.ip);
+        login_audit_log(&ip, "admin_login", None, &conn).await;
+
         // If the token received is valid, generate JWT and save it as a cookie
         let claims = generate_admin_claims();
         let jwt = encode_jwt(&claims);
@@ -219,6 +260,22 @@ fn post_admin_login(
         }
     } else {
         error!("Invalid admin token. IP: {}", ip.ip);
+        login_audit_log(&ip, "admin_login_failed", None, &conn).await;
+
+        if crate::ratelimit::record_admin_login_failure(&ip.ip) {
+            let attempts = CONFIG.admin_lockout_attempts();
+            let minutes = CONFIG.admin_lockout_minutes();
+            warn!("Admin panel locked for {minutes} minutes for IP {} after {attempts} failed attempts", ip.ip);
+            login_audit_log(&ip, "admin_lockout", Some(format!("{attempts} failed attempts")), &conn).await;
+
+            if let Some(address) = CONFIG.admin_lockout_email()
+                && CONFIG.mail_enabled()
+                && let Err(e) = mail::send_admin_lockout(&address, &ip.ip.to_string(), attempts, minutes).await
+            {
+                error!("Error sending admin lockout email: {e:#?}");
+            }
+        }
+
         Err(AdminResponse::Unauthorized(render_admin_login(
             Some("Invalid admin token, please try again."),
             redirect.as_deref(),
@@ -226,6 +283,13 @@ fn post_admin_login(
     }
 }
 
+// Login attempts are audited without an admin session, failing to store them shouldn't block the login
+async fn login_audit_log(ip: &ClientIp, action: &str, details: Option<String>, conn: &DbConn) {
+    if let Err(e) = AdminAuditLog::new(&ip.ip, action, None, details).save(conn).await {
+        error!("Error saving admin audit log entry: {e:#?}");
+    }
+}
+
 fn validate_token(token: &str) -> bool {
     match CONFIG.admin_token().as_ref() {
         None => false,
@@ -275,6 +339,7 @@ fn render_admin_page() -> ApiResult<Html<String>> {
     let settings_json = json!({
         "config": CONFIG.prepare_json(),
         "can_backup": *CAN_BACKUP,
+        "dr_bundle_folder": CONFIG.dr_bundle_folder(),
     });
     let text = AdminTemplateData::new("admin/settings", settings_json).render()?;
     Ok(Html(text))
@@ -306,6 +371,13 @@ async fn get_user_or_404(user_id: &UserId, conn: &DbConn) -> ApiResult<User> {
 
 #[post("/invite", format = "application/json", data = "<data>")]
 async fn invite_user(data: Json<InviteData>, _token: AdminToken, conn: DbConn) -> JsonResult {
+    let data: InviteData = data.into_inner();
+    let user = create_invited_user(&data.email, &conn).await?;
+    Ok(Json(user.to_json(&conn).await))
+}
+
+/// Creates a user for the email address and invites it, also used by the `admin create-invite` command
+pub async fn create_invited_user(email: &str, conn: &DbConn) -> ApiResult<User> {
     async fn generate_invite(user: &User, conn: &DbConn) -> EmptyResult {
         if CONFIG.mail_enabled() {
             let org_id: OrganizationId = if CONFIG.sso_enabled() {
@@ -321,17 +393,17 @@ async fn invite_user(data: Json<InviteData>, _token: AdminToken, conn: DbConn) -
         }
     }
 
-    let data: InviteData = data.into_inner();
-    if User::find_by_mail(&data.email, &conn).await.is_some() {
+    if User::find_by_mail_or_subaddress(email, conn).await.is_some() {
         err_code!("User already exists", Status::Conflict.code)
     }
 
-    let mut user = User::new(&data.email, None);
+    let mut user = User::new(email, None);
+    user.invited_at = Some(Utc::now().naive_utc());
 
-    generate_invite(&user, &conn).await.map_err(|e| e.with_code(Status::InternalServerError.code))?;
-    user.save(&conn).await.map_err(|e| e.with_code(Status::InternalServerError.code))?;
+    generate_invite(&user, conn).await.map_err(|e| e.with_code(Status::InternalServerError.code))?;
+    user.save(conn).await.map_err(|e| e.with_code(Status::InternalServerError.code))?;
 
-    Ok(Json(user.to_json(&conn).await))
+    Ok(user)
 }
 
 #[post("/test/smtp", format = "application/json", data = "<data>")]
@@ -412,6 +484,7 @@ async fn get_user_json(user_id: UserId, _token: AdminToken, conn: DbConn) -> Jso
     let mut usr = u.to_json(&conn).await;
     usr["userEnabled"] = json!(u.enabled);
     usr["createdAt"] = json!(format_naive_datetime_local(&u.created_at, DT_FMT));
+    usr["quotaExempt"] = json!(u.quota_exempt);
     Ok(Json(usr))
 }
 
@@ -481,6 +554,51 @@ async fn deauth_user(user_id: UserId, _token: AdminToken, conn: DbConn, nt: Noti
     user.save(&conn).await
 }
 
+#[get("/users/<user_id>/devices")]
+async fn get_user_devices(user_id: UserId, _token: AdminToken, conn: DbConn) -> JsonResult {
+    let user = get_user_or_404(&user_id, &conn).await?;
+    let shared = Device::find_shared_by_user(&user.uuid, &conn).await;
+    let devices: Vec<Value> = Device::find_by_user_latest_first(&user.uuid, &conn)
+        .await
+        .iter()
+        .map(|device| {
+            let mut device_json = device.to_json();
+            device_json["typeName"] = json!(DeviceType::from_i32(device.atype).to_string());
+            device_json["lastSeen"] = json!(format_naive_datetime_local(&device.updated_at, DT_FMT));
+            device_json["sharedWith"] = json!(
+                shared.iter().filter(|(uuid, _)| uuid == &device.uuid).map(|(_, email)| email).collect::<Vec<_>>()
+            );
+            device_json
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "data": devices,
+        "object": "list",
+    })))
+}
+
+/// Logs out a single device, for example a stolen one, without ending the sessions of the other devices.
+/// Removing the device revokes its refresh token and the access tokens it still has.
+#[post("/users/<user_id>/devices/<device_id>/revoke", format = "application/json")]
+async fn revoke_user_device(user_id: UserId, device_id: DeviceId, token: AdminToken, conn: DbConn) -> EmptyResult {
+    let user = get_user_or_404(&user_id, &conn).await?;
+    let Some(device) = Device::find_by_uuid_and_user(&device_id, &user.uuid, &conn).await else {
+        err_code!("Device doesn't exist", Status::NotFound.code)
+    };
+
+    if device.push_token.is_some()
+        && let Err(e) = unregister_push_device(device.push_uuid.as_ref()).await
+    {
+        warn!("Unable to unregister the push device {}: {e:?}", device.uuid);
+    }
+    let details = format!("{} ({})", device.name, device.uuid);
+    device.delete(&conn).await?;
+
+    info!("Admin action 'device_revoked' for user {} and device {details}. IP: {}", user.uuid, token.ip.ip);
+    AdminAuditLog::new(&token.ip.ip, "device_revoked", Some(user.uuid), Some(details)).save(&conn).await
+}
+
 #[post("/users/<user_id>/disable", format = "application/json")]
 async fn disable_user(user_id: UserId, _token: AdminToken, conn: DbConn, nt: Notify<'_>) -> EmptyResult {
     let mut user = get_user_or_404(&user_id, &conn).await?;
@@ -500,6 +618,8 @@ async fn disable_user(user_id: UserId, _token: AdminToken, conn: DbConn, nt: Not
 async fn enable_user(user_id: UserId, _token: AdminToken, conn: DbConn) -> EmptyResult {
     let mut user = get_user_or_404(&user_id, &conn).await?;
     user.enabled = true;
+    // Start counting the inactivity again, otherwise an account disabled because of it is disabled again by the next run
+    user.inactivity_warned_at = None;
 
     user.save(&conn).await
 }
@@ -521,22 +641,171 @@ async fn resend_user_invite(user_id: UserId, _token: AdminToken, conn: DbConn) -
             err_code!("User already accepted invitation", Status::BadRequest.code);
         }
 
-        if CONFIG.mail_enabled() {
-            let org_id: OrganizationId = if CONFIG.sso_enabled() {
-                FAKE_SSO_IDENTIFIER.into()
-            } else {
-                FAKE_ADMIN_UUID.into()
-            };
-            let member_id: MembershipId = FAKE_ADMIN_UUID.to_owned().into();
-            mail::send_invite(&user, org_id, member_id, &CONFIG.invitation_org_name(), None).await
-        } else {
-            Ok(())
-        }
+        resend_invite(user, &conn).await
     } else {
         err_code!("User doesn't exist", Status::NotFound.code);
     }
 }
 
+async fn resend_invite(mut user: User, conn: &DbConn) -> EmptyResult {
+    if CONFIG.mail_enabled() {
+        let org_id: OrganizationId = if CONFIG.sso_enabled() {
+            FAKE_SSO_IDENTIFIER.into()
+        } else {
+            FAKE_ADMIN_UUID.into()
+        };
+        let member_id: MembershipId = FAKE_ADMIN_UUID.to_owned().into();
+        mail::send_invite(&user, org_id, member_id, &CONFIG.invitation_org_name(), None).await?;
+    }
+
+    // The expiration of the invitation starts again
+    user.invited_at = Some(Utc::now().naive_utc());
+    user.save(conn).await
+}
+
+/// Lists the instance invitations which weren't accepted yet, and the number of expired organization invitations
+#[get("/invitations")]
+async fn get_invitations(_token: AdminToken, conn: DbConn) -> JsonResult {
+    let now = Utc::now().naive_utc();
+    let invitations: Vec<Value> = User::find_invited(&conn)
+        .await
+        .iter()
+        .map(|user| {
+            let expiration = user.invite_expiration();
+            json!({
+                "id": user.uuid,
+                "email": user.email,
+                "invitedDate": user.invited_at.as_ref().map(format_date),
+                "expirationDate": expiration.as_ref().map(format_date),
+                "expired": expiration.is_some_and(|exp| exp < now),
+            })
+        })
+        .collect();
+    let expired_count = invitations.iter().filter(|i| i["expired"] == true).count();
+
+    Ok(Json(json!({
+        "data": invitations,
+        "expiredCount": expired_count,
+        "expiredOrganizationInvitations": Membership::count_invited_before(&invite_expiration_cutoff(), &conn).await,
+        "expiredInvitationsRejected": crate::auth::expired_invites_rejected(),
+    })))
+}
+
+/// Invitations sent before this date have expired
+fn invite_expiration_cutoff() -> NaiveDateTime {
+    Utc::now().naive_utc() - TimeDelta::try_hours(i64::from(CONFIG.invitation_expiration_hours())).unwrap()
+}
+
+/// Sends a new invitation to all users whose instance invitation has expired
+#[post("/invitations/resend-expired", format = "application/json")]
+async fn resend_expired_invitations(_token: AdminToken, conn: DbConn) -> JsonResult {
+    let now = Utc::now().naive_utc();
+    let mut resent = 0;
+    let mut failed = Vec::new();
+    for user in User::find_invited(&conn).await {
+        if !user.invite_expiration().is_some_and(|exp| exp < now) {
+            continue;
+        }
+        let email = user.email.clone();
+        match resend_invite(user, &conn).await {
+            Ok(()) => resent += 1,
+            Err(e) => {
+                error!("Error resending the invitation of {email}: {e:#?}");
+                failed.push(email);
+            }
+        }
+    }
+
+    Ok(Json(json!({
+        "resent": resent,
+        "failed": failed,
+    })))
+}
+
+/// Lists the accounts without a login for `INACTIVE_USERS_MONTHS` months, and when the configured action applies to them
+#[get("/users/inactive")]
+async fn get_inactive_users(_token: AdminToken, conn: DbConn) -> JsonResult {
+    let Some(cutoff) = crate::api::core::accounts::inactive_users_cutoff() else {
+        err!("`INACTIVE_USERS_MONTHS` is not configured")
+    };
+    let action = CONFIG.inactive_users_action();
+    let grace_period = TimeDelta::try_days(CONFIG.inactive_users_grace_days()).unwrap_or_default();
+    let users: Vec<Value> = User::find_inactive_since(&cutoff, &conn)
+        .await
+        .iter()
+        .map(|user| {
+            let action_date = match action.as_str() {
+                "disable" | "delete" if !user.inactivity_exempt => {
+                    user.inactivity_warned_at.map(|dt| dt + grace_period)
+                }
+                _ => None,
+            };
+            json!({
+                "id": user.uuid,
+                "email": user.email,
+                "lastLoginDate": format_date(&user.inactive_since()),
+                "warnedDate": user.inactivity_warned_at.as_ref().map(format_date),
+                "actionDate": action_date.as_ref().map(format_date),
+                "exempt": user.inactivity_exempt,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "data": users,
+        "action": action,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct InactivityExemptionData {
+    exempt: bool,
+}
+
+#[post("/users/<user_id>/inactivity_exemption", format = "application/json", data = "<data>")]
+async fn set_inactivity_exemption(
+    user_id: UserId,
+    data: Json<InactivityExemptionData>,
+    token: AdminToken,
+    conn: DbConn,
+) -> EmptyResult {
+    let mut user = get_user_or_404(&user_id, &conn).await?;
+    user.inactivity_exempt = data.exempt;
+    user.inactivity_warned_at = None;
+    user.save(&conn).await?;
+
+    let action = if data.exempt {
+        "inactivity_exempted"
+    } else {
+        "inactivity_exemption_removed"
+    };
+    audit_log(&token, action, Some(&user.uuid), &conn).await
+}
+
+#[derive(Debug, Deserialize)]
+struct QuotaExemptionData {
+    exempt: bool,
+}
+
+#[post("/users/<user_id>/quota_exemption", format = "application/json", data = "<data>")]
+async fn set_quota_exemption(
+    user_id: UserId,
+    data: Json<QuotaExemptionData>,
+    token: AdminToken,
+    conn: DbConn,
+) -> EmptyResult {
+    let mut user = get_user_or_404(&user_id, &conn).await?;
+    user.quota_exempt = data.exempt;
+    user.save(&conn).await?;
+
+    let action = if data.exempt {
+        "quota_exempted"
+    } else {
+        "quota_exemption_removed"
+    };
+    audit_log(&token, action, Some(&user.uuid), &conn).await
+}
+
 #[derive(Debug, Deserialize)]
 struct MembershipTypeData {
     user_type: NumberOrString,
@@ -589,6 +858,133 @@ async fn update_revision_users(_token: AdminToken, conn: DbConn) -> EmptyResult
     User::update_all_revisions(&conn).await
 }
 
+#[get("/global_domains")]
+async fn get_global_domains(_token: AdminToken, conn: DbConn) -> Json<Value> {
+    let overrides = GlobalDomainOverride::find_all(&conn).await;
+
+    let mut domains: Vec<Value> = builtin_global_domains()
+        .iter()
+        .map(|global| {
+            let o = overrides.iter().find(|o| o.atype == global.r#type);
+            let source = match o {
+                Some(o) if o.disabled => "disabled",
+                Some(_) => "override",
+                None => "builtin",
+            };
+            json!({
+                "type": global.r#type,
+                "domains": o.filter(|o| !o.disabled).map_or_else(|| global.domains.clone(), GlobalDomainOverride::domain_list),
+                "builtinDomains": global.domains,
+                "source": source,
+            })
+        })
+        .collect();
+
+    // Overrides of types which aren't in the built-in list are the custom sets of this instance
+    domains.extend(overrides.iter().filter(|o| !builtin_global_domains().iter().any(|g| g.r#type == o.atype)).map(
+        |o| {
+            json!({
+                "type": o.atype,
+                "domains": o.domain_list(),
+                "builtinDomains": [],
+                "source": if o.disabled { "disabled" } else { "custom" },
+            })
+        },
+    ));
+
+    Json(Value::Array(domains))
+}
+
+#[derive(Debug, Deserialize)]
+struct GlobalDomainsData {
+    domains: Vec<String>,
+    #[serde(default)]
+    disabled: bool,
+}
+
+impl GlobalDomainsData {
+    fn domain_list(&self) -> ApiResult<Vec<String>> {
+        let mut domains: Vec<String> =
+            self.domains.iter().map(|d| d.trim().to_lowercase()).filter(|d| !d.is_empty()).collect();
+        domains.sort();
+        domains.dedup();
+
+        if !self.disabled && domains.len() < 2 {
+            err!("A set of equivalent domains needs at least two domains")
+        }
+        Ok(domains)
+    }
+}
+
+#[post("/global_domains", format = "application/json", data = "<data>")]
+async fn create_global_domains(data: Json<GlobalDomainsData>, token: AdminToken, conn: DbConn) -> JsonResult {
+    let domains = data.domain_list()?;
+
+    let atype = GlobalDomainOverride::find_all(&conn)
+        .await
+        .iter()
+        .map(|o| o.atype + 1)
+        .chain(std::iter::once(CUSTOM_GLOBAL_DOMAINS_START))
+        .max()
+        .unwrap_or(CUSTOM_GLOBAL_DOMAINS_START);
+
+    GlobalDomainOverride::new(atype, &domains, data.disabled).save(&conn).await?;
+    audit_log(&token, &format!("global_domains_created_{atype}"), None, &conn).await?;
+    // The equivalent domains are part of the sync, let the clients fetch the new list
+    User::update_all_revisions(&conn).await?;
+
+    Ok(Json(json!({ "type": atype })))
+}
+
+#[post("/global_domains/<atype>", format = "application/json", data = "<data>")]
+async fn update_global_domains(
+    atype: i32,
+    data: Json<GlobalDomainsData>,
+    token: AdminToken,
+    conn: DbConn,
+) -> EmptyResult {
+    let domains = data.domain_list()?;
+    let is_builtin = builtin_global_domains().iter().any(|g| g.r#type == atype);
+    if !is_builtin && GlobalDomainOverride::find_by_type(atype, &conn).await.is_none() {
+        err_code!("Global equivalent domains not found", Status::NotFound.code)
+    }
+
+    GlobalDomainOverride::new(atype, &domains, data.disabled).save(&conn).await?;
+    audit_log(&token, &format!("global_domains_updated_{atype}"), None, &conn).await?;
+    User::update_all_revisions(&conn).await
+}
+
+// Restores a built-in set to the shipped domains, or removes a custom set
+#[post("/global_domains/<atype>/delete", format = "application/json")]
+async fn delete_global_domains(atype: i32, token: AdminToken, conn: DbConn) -> EmptyResult {
+    let Some(o) = GlobalDomainOverride::find_by_type(atype, &conn).await else {
+        err_code!("Global equivalent domains override not found", Status::NotFound.code)
+    };
+
+    o.delete(&conn).await?;
+    audit_log(&token, &format!("global_domains_reset_{atype}"), None, &conn).await?;
+    User::update_all_revisions(&conn).await
+}
+
+#[get("/push/reregister")]
+fn get_push_reregistration(_token: AdminToken) -> Json<Value> {
+    Json(push_reregistration_status())
+}
+
+#[post("/push/reregister", format = "application/json")]
+async fn reregister_push(token: AdminToken, pool: &State<DbPool>, conn: DbConn) -> JsonResult {
+    if !CONFIG.push_enabled() {
+        err!("Push notifications are not enabled")
+    }
+    if !start_push_reregistration() {
+        err!("The push devices are already being registered again")
+    }
+    audit_log(&token, "push_reregister", None, &conn).await?;
+
+    tokio::spawn(reregister_push_devices(pool.inner().clone()));
+    Ok(Json(push_reregistration_status()))
+}
+
 #[get("/organizations/overview")]
 async fn organizations_overview(_token: AdminToken, conn: DbConn) -> ApiResult<Html<String>> {
     let organizations = Organization::get_all(&conn).await;
@@ -615,6 +1011,53 @@ async fn delete_organization(org_id: OrganizationId, _token: AdminToken, conn: D
     org.delete(&conn).await
 }
 
+#[get("/ldap")]
+async fn ldap_overview(_token: AdminToken, conn: DbConn) -> ApiResult<Html<String>> {
+    let org_id: OrganizationId = CONFIG.ldap_org_id().into();
+    let org_name = Organization::find_by_uuid(&org_id, &conn).await.map(|org| org.name);
+    let page_data = json!({
+        "enabled": CONFIG.ldap_sync_enabled(),
+        "url": CONFIG.ldap_url(),
+        "base_dn": CONFIG.ldap_base_dn(),
+        "user_filter": CONFIG.ldap_user_filter(),
+        "org_id": org_id,
+        "org_name": org_name,
+        "schedule": CONFIG.ldap_sync_schedule(),
+        "revoke_removed": CONFIG.ldap_revoke_removed(),
+        "last_sync": last_ldap_sync(),
+    });
+
+    let text = AdminTemplateData::new("admin/ldap", page_data).render()?;
+    Ok(Html(text))
+}
+
+#[post("/ldap/sync", format = "application/json")]
+async fn ldap_sync(token: AdminToken, conn: DbConn) -> JsonResult {
+    if !CONFIG.ldap_sync_enabled() {
+        err!("The LDAP sync is not enabled")
+    }
+    // Shares the lock of the scheduled job, so it doesn't run at the same time on this or another instance
+    if !JobLock::try_acquire("ldap_sync", CONFIG.job_lock_timeout(), &conn).await {
+        err!("The LDAP sync is already running")
+    }
+    let result = sync_ldap_directory(&conn).await;
+    if let Err(e) = JobLock::release("ldap_sync", &conn).await {
+        warn!("Unable to release the lock of the LDAP sync: {e:?}");
+    }
+    let result = result?;
+
+    let details = format!("{} invited, {} restored, {} revoked", result.invited, result.restored, result.revoked);
+    info!("Admin action 'ldap_sync': {details}. IP: {}", token.ip.ip);
+    AdminAuditLog::new(&token.ip.ip, "ldap_sync", None, Some(details)).save(&conn).await?;
+
+    Ok(Json(json!({
+        "users": result.users,
+        "invited": result.invited,
+        "restored": result.restored,
+        "revoked": result.revoked,
+    })))
+}
+
 #[derive(Deserialize)]
 struct GitRelease {
     tag_name: String,
@@ -748,6 +1191,50 @@ async fn diagnostics(_token: AdminToken, ip_header: IpHeader, conn: DbConn) -> A
     .into_keys()
     .collect();
 
+    let (tmp_reclaimed_files, tmp_reclaimed_bytes) = crate::storage::tmp_reclaimed_stats();
+    let (push_tokens_deregistered, stale_devices_removed) = crate::api::core::accounts::device_cleanup_stats();
+    let client_versions: Vec<Value> = super::identity::client_version_stats()
+        .into_iter()
+        .map(|(client_type, version, count)| {
+            json!({
+                "type": client_type,
+                "version": version,
+                "count": count,
+                "min_version": CONFIG.min_client_version(client_type).map(|v| v.to_string()),
+            })
+        })
+        .collect();
+
+    let (admin_login_failures, admin_lockouts, admin_locked_ips) = crate::ratelimit::admin_login_failure_stats();
+    let send_egress_ips: Vec<Value> = crate::api::core::send_egress_ip_stats(5)
+        .into_iter()
+        .map(|(ip, bytes)| json!({ "ip": ip.to_string(), "size": get_display_size(i64::try_from(bytes).unwrap_or(i64::MAX)) }))
+        .collect();
+    let send_egress_sends: Vec<Value> = Send::find_most_downloaded(5, &conn)
+        .await
+        .iter()
+        .map(|send| json!({ "id": send.uuid, "size": get_display_size(send.download_bytes) }))
+        .collect();
+
+    let expired_invites_before = invite_expiration_cutoff();
+    let expired_invites_pending = User::find_invited(&conn)
+        .await
+        .iter()
+        .filter(|u| u.invited_at.is_some_and(|at| at < expired_invites_before))
+        .count();
+
+    let job_locks: Vec<Value> = JobLock::find_active(&conn)
+        .await
+        .into_iter()
+        .map(|l| {
+            json!({
+                "name": l.name,
+                "locked_until": format_naive_datetime_local(&l.locked_until, DT_FMT),
+                "this_instance": l.is_held_by_us(),
+            })
+        })
+        .collect();
+
     let diagnostics_json = json!({
         "dns_resolved": dns_resolved,
         "current_release": VERSION,
@@ -770,7 +1257,31 @@ async fn diagnostics(_token: AdminToken, ip_header: IpHeader, conn: DbConn) -> A
         "db_version": get_sql_server_version(&conn).await,
         "admin_url": format!("{}/diagnostics", admin_url()),
         "overrides": &CONFIG.get_overrides().join(", "),
+        "config_findings": CONFIG.audit(),
         "invalid_feature_flags": invalid_feature_flags,
+        "tmp_reclaimed_files": tmp_reclaimed_files,
+        "tmp_reclaimed_size": get_display_size(i64::try_from(tmp_reclaimed_bytes).unwrap_or(i64::MAX)),
+        "push_enabled": CONFIG.push_enabled(),
+        "push_registered_devices": Device::count_push_registered(&conn).await,
+        "push_tokens_deregistered": push_tokens_deregistered,
+        "stale_devices_removed": stale_devices_removed,
+        "push_reregistration": push_reregistration_status(),
+        "revisions_reconciled": crate::api::core::accounts::revisions_reconciled(),
+        "send_egress_month": get_display_size(SendEgress::current_month_bytes(&conn).await),
+        "send_egress_limit": CONFIG.send_monthly_egress_limit().map(|kb| get_display_size(kb.saturating_mul(1024))),
+        "send_egress_ips": send_egress_ips,
+        "send_egress_sends": send_egress_sends,
+        "expired_invites_rejected": crate::auth::expired_invites_rejected(),
+        "expired_invites_pending": expired_invites_pending,
+        "expired_org_invites_pending": Membership::count_invited_before(&expired_invites_before, &conn).await,
+        "client_versions": client_versions,
+        "admin_login_failures": admin_login_failures,
+        "admin_lockouts": admin_lockouts,
+        "admin_locked_ips": admin_locked_ips.iter().map(ToString::to_string).collect::<Vec<String>>(),
+        "integrity_report": crate::db::integrity::last_report(),
+        "orphans": crate::db::orphans::report(&conn).await,
+        "job_lock_holder": JobLock::holder_id(),
+        "job_locks": job_locks,
         "host_arch": env::consts::ARCH,
         "host_os":  env::consts::OS,
         "tz_env": env::var("TZ").unwrap_or_default(),
@@ -789,6 +1300,192 @@ fn get_diagnostics_config(_token: AdminToken) -> Json<Value> {
     Json(support_json)
 }
 
+#[get("/diagnostics/config-audit")]
+fn get_diagnostics_config_audit(_token: AdminToken) -> Json<Value> {
+    Json(json!(CONFIG.audit()))
+}
+
+// The statistics exactly as they would be posted to `USAGE_STATISTICS_URL`
+#[get("/diagnostics/usage-statistics")]
+async fn get_diagnostics_usage_statistics(_token: AdminToken, conn: DbConn) -> Json<Value> {
+    Json(json!({
+        "url": CONFIG.usage_statistics_url(),
+        "payload": crate::telemetry::usage_statistics(&conn).await,
+    }))
+}
+
+#[get("/diagnostics/integrity")]
+fn get_diagnostics_integrity(_token: AdminToken) -> Json<Value> {
+    Json(crate::db::integrity::last_report().unwrap_or(Value::Null))
+}
+
+#[get("/diagnostics/orphans")]
+async fn get_orphans(_token: AdminToken, conn: DbConn) -> Json<Value> {
+    Json(Value::Array(crate::db::orphans::report(&conn).await))
+}
+
+#[post("/diagnostics/orphans/<category>/delete", format = "application/json")]
+async fn cleanup_orphans(category: &str, token: AdminToken, conn: DbConn) -> JsonResult {
+    let removed = crate::db::orphans::cleanup(category, &conn).await?;
+    audit_log(&token, &format!("orphans_cleanup_{category}"), None, &conn).await?;
+    Ok(Json(json!({ "removed": removed })))
+}
+
+#[derive(Responder)]
+#[response(content_type = "json")]
+struct DumpDownload(String, Header<'static>);
+
+// Anonymized copy of the database, which can be attached to bug reports
+#[get("/diagnostics/anonymized-dump")]
+async fn download_anonymized_dump(token: AdminToken, conn: DbConn) -> ApiResult<DumpDownload> {
+    let dump = crate::db::anonymize::create_dump(&conn).await?;
+    audit_log(&token, "anonymized_dump", None, &conn).await?;
+
+    let file_name = format!("vaultwarden_anonymized_{}.json", Utc::now().format("%Y%m%d_%H%M%S"));
+    Ok(DumpDownload(
+        serde_json::to_string_pretty(&dump)?,
+        Header::new("Content-Disposition", format!("attachment; filename=\"{file_name}\"")),
+    ))
+}
+
+// Returns the current usage totals, and the daily snapshots of the last `days` days (default 90)
+#[get("/usage?<days>")]
+async fn get_usage(days: Option<u32>, _token: AdminToken, conn: DbConn) -> Json<Value> {
+    let days = days.unwrap_or(90).min(CONFIG.usage_snapshot_days_retain());
+    let snapshots = UsageSnapshot::find_since_days(i64::from(days), &conn).await;
+
+    Json(json!({
+        "current": UsageSnapshot::capture(&conn).await.to_json(),
+        "snapshots": snapshots.iter().map(UsageSnapshot::to_json).collect::<Vec<Value>>(),
+    }))
+}
+
+//
+// Support sessions
+// Short-lived read-only access to the account metadata of a user, used to debug sync issues.
+// Vault contents are never part of it. The user needs to allow it first, and every access is added to the audit log.
+//
+struct SupportSession {
+    user_id: UserId,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SupportSession {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(token) = request.headers().get_one("Authorization").and_then(|h| h.strip_prefix("Bearer ")) else {
+            err_handler!("No support session token provided")
+        };
+        match decode_support_session(token) {
+            Ok(claims) => Outcome::Success(Self {
+                user_id: claims.sub.into(),
+            }),
+            Err(_) => err_handler!("Invalid or expired support session"),
+        }
+    }
+}
+
+async fn audit_log(token: &AdminToken, action: &str, user_id: Option<&UserId>, conn: &DbConn) -> EmptyResult {
+    info!("Admin action '{action}' for user {user_id:?}. IP: {}", token.ip.ip);
+    AdminAuditLog::new(&token.ip.ip, action, user_id.cloned(), None).save(conn).await
+}
+
+#[post("/users/<user_id>/support-session", format = "application/json")]
+async fn create_support_session(user_id: UserId, token: AdminToken, conn: DbConn) -> JsonResult {
+    let user = get_user_or_404(&user_id, &conn).await?;
+    if !user.has_support_access() {
+        err!("The user did not allow support access to their account")
+    }
+
+    let claims = generate_support_session_claims(&user.uuid);
+    let expiration = chrono::DateTime::from_timestamp(claims.exp, 0).map(|d| format_date(&d.naive_utc()));
+    audit_log(&token, "support_session_created", Some(&user.uuid), &conn).await?;
+
+    Ok(Json(json!({
+        "token": encode_jwt(&claims),
+        "expirationDate": expiration,
+    })))
+}
+
+#[get("/support-session")]
+async fn get_support_session(session: SupportSession, token: AdminToken, conn: DbConn) -> JsonResult {
+    let user = get_user_or_404(&session.user_id, &conn).await?;
+    // Revoking the consent also ends the sessions which are still valid
+    if !user.has_support_access() {
+        err!("The user did not allow support access to their account")
+    }
+    audit_log(&token, "support_session_viewed", Some(&user.uuid), &conn).await?;
+
+    let devices: Vec<Value> = Device::find_by_user(&user.uuid, &conn).await.iter().map(Device::to_json).collect();
+
+    let mut memberships = Vec::new();
+    for member in Membership::find_any_state_by_user(&user.uuid, &conn).await {
+        let org_name = Organization::find_by_uuid(&member.org_uuid, &conn).await.map(|o| o.name);
+        memberships.push(json!({
+            "organizationId": member.org_uuid,
+            "organizationName": org_name,
+            "status": member.status,
+            "type": member.atype,
+        }));
+    }
+
+    let mut policies = Vec::new();
+    for policy_type in [
+        OrgPolicyType::TwoFactorAuthentication,
+        OrgPolicyType::SingleOrg,
+        OrgPolicyType::RequireSso,
+        OrgPolicyType::PersonalOwnership,
+        OrgPolicyType::DisableSend,
+        OrgPolicyType::SendOptions,
+    ] {
+        policies.push(json!({
+            "type": policy_type as i32,
+            "applicable": OrgPolicy::is_applicable_to_user(&user.uuid, policy_type, None, &conn).await,
+        }));
+    }
+
+    Ok(Json(json!({
+        "user": {
+            "id": user.uuid,
+            "email": user.email,
+            "enabled": user.enabled,
+            "emailVerified": user.verified_at.is_some(),
+            "creationDate": format_date(&user.created_at),
+            "revisionDate": format_date(&user.updated_at),
+            "lastActive": Device::find_latest_active_by_user(&user.uuid, &conn).await.map(|d| format_date(&d.updated_at)),
+            "supportAccessUntil": user.support_access_until.as_ref().map(format_date),
+        },
+        "devices": devices,
+        "memberships": memberships,
+        "policies": policies,
+    })))
+}
+
+// Returns the most recent entries of the admin audit log
+#[get("/audit-log")]
+async fn get_audit_log(_token: AdminToken, conn: DbConn) -> Json<Value> {
+    let entries = AdminAuditLog::find_recent(500, &conn).await;
+    Json(json!({
+        "data": entries.iter().map(AdminAuditLog::to_json).collect::<Vec<Value>>(),
+    }))
+}
+
+pub async fn usage_snapshot_job(pool: DbPool) {
+    debug!("Storing usage snapshot");
+    let Ok(conn) = pool.get().await else {
+        error!("Failed to get DB connection while storing the usage snapshot");
+        return;
+    };
+
+    if let Err(e) = UsageSnapshot::capture(&conn).await.save(&conn).await {
+        error!("Failed to store the usage snapshot: {e:?}");
+    }
+    if let Err(e) = UsageSnapshot::delete_older_than(CONFIG.usage_snapshot_days_retain(), &conn).await {
+        error!("Failed to remove old usage snapshots: {e:?}");
+    }
+}
+
 #[get("/diagnostics/http?<code>")]
 fn get_diagnostics_http(code: u16, _token: AdminToken) -> EmptyResult {
     err_code!(format!("Testing error {code} response"), code);
@@ -823,6 +1520,38 @@ fn backup_db(_token: AdminToken) -> ApiResult<String> {
     }
 }
 
+#[derive(Responder)]
+#[response(content_type = "application/x-tar")]
+struct BundleDownload(NamedFile, Header<'static>);
+
+#[get("/recovery/export")]
+async fn download_dr_bundle(_token: AdminToken) -> ApiResult<BundleDownload> {
+    let bundle_path = crate::recovery::create_bundle().await?;
+    let file = NamedFile::open(&bundle_path).await?;
+    // The open file handle keeps the data available, and this makes sure the bundle doesn't linger in the temp folder.
+    // If removing fails, the temp file cleanup job will take care of it.
+    tokio::fs::remove_file(&bundle_path).await.ok();
+
+    let file_name = crate::storage::file_name(&bundle_path).unwrap_or_default();
+    Ok(BundleDownload(file, Header::new("Content-Disposition", format!("attachment; filename=\"{file_name}\""))))
+}
+
+#[post("/recovery/export", format = "application/json")]
+async fn store_dr_bundle(_token: AdminToken) -> ApiResult<String> {
+    if CONFIG.dr_bundle_folder().is_empty() {
+        err!("`DR_BUNDLE_FOLDER` is not configured, download the bundle instead")
+    }
+
+    let bundle_path = crate::recovery::create_bundle().await?;
+    match crate::recovery::upload_bundle(&bundle_path).await {
+        Ok(location) => Ok(format!("Disaster recovery bundle stored at '{location}'")),
+        Err(e) => {
+            tokio::fs::remove_file(&bundle_path).await.ok();
+            err!(format!("Storing the disaster recovery bundle failed: {e}"))
+        }
+    }
+}
+
 pub struct AdminToken {
     ip: ClientIp,
 }
@@ -836,6 +1565,10 @@ impl<'r> FromRequest<'r> for AdminToken {
             err_handler!("Error getting Client IP")
         };
 
+        if crate::ratelimit::check_admin_lockout(&ip.ip).is_err() {
+            return Outcome::Error((Status::TooManyRequests, "Too many failed attempts"));
+        }
+
         if !CONFIG.disable_admin_token() {
             let cookies = request.cookies();
 
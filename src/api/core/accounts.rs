@@ -1,6 +1,9 @@
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-use chrono::Utc;
+use chrono::{Months, NaiveDateTime, TimeDelta, Utc};
 use rocket::{
     http::Status,
     request::{FromRequest, Outcome, Request},
@@ -12,20 +15,22 @@ use crate::{
     CONFIG,
     api::{
         AnonymousNotify, ApiResult, EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType,
-        core::{accept_org_invite, log_user_event, two_factor::email},
-        master_password_policy, register_push_device, unregister_push_device,
+        core::{accept_org_invite, invite_to_claimed_domain_org, log_user_event, two_factor::email},
+        master_password_policy, merge_master_password_policies, register_push_device, unregister_push_device,
     },
     auth::{ClientHeaders, Headers, decode_delete, decode_invite, decode_verify_email},
     crypto,
     db::{
         DbConn, DbPool,
         models::{
-            AuthRequest, AuthRequestId, Cipher, CipherId, Device, DeviceId, DeviceType, DeviceWithAuthRequest,
-            EmergencyAccess, EmergencyAccessId, EventType, Folder, FolderId, Invitation, Membership, MembershipId,
-            OrgPolicy, OrgPolicyType, Organization, OrganizationId, Send, SendId, User, UserId, UserKdfType,
+            Attachment, AttachmentId, AttachmentRekey, AuthRequest, AuthRequestId, Cipher, CipherId, Device, DeviceId,
+            DeviceType, EmergencyAccess, EmergencyAccessId, EventType, Folder, FolderId, Invitation, Membership,
+            MembershipId, MembershipType, OrgDomain, OrgPolicy, OrgPolicyType, Organization, OrganizationId, Send,
+            SendId, User, UserId, UserKdfType,
         },
     },
     mail,
+    tenant::RequestTenant,
     util::{NumberOrString, deser_opt_nonempty_str, format_date},
 };
 
@@ -61,9 +66,16 @@ pub fn routes() -> Vec<rocket::Route> {
         verify_password,
         post_api_key,
         rotate_api_key,
+        get_support_access,
+        post_support_access,
+        delete_support_access,
+        get_login_notifications,
+        put_login_notifications,
         get_known_device,
         get_all_devices,
         get_device,
+        put_device,
+        post_device,
         post_device_token,
         put_device_token,
         put_clear_device_token,
@@ -75,6 +87,7 @@ pub fn routes() -> Vec<rocket::Route> {
         get_auth_request_response,
         get_auth_requests,
         get_auth_requests_pending,
+        get_auth_requests_history,
     ]
 }
 
@@ -150,9 +163,26 @@ fn clean_password_hint(password_hint: Option<&String>) -> Option<String> {
     }
 }
 
-fn enforce_password_hint_setting(password_hint: Option<&String>) -> EmptyResult {
-    if password_hint.is_some() && !CONFIG.password_hints_allowed() {
-        err!("Password hints have been disabled by the administrator. Remove the hint and try again.");
+/// Checks the hint against the settings of the instance, and the policies of the organizations of the user if there is one
+async fn enforce_password_hint_setting(
+    password_hint: Option<&String>,
+    user_id: Option<&UserId>,
+    conn: &DbConn,
+) -> EmptyResult {
+    if !CONFIG.password_hints_allowed() {
+        if password_hint.is_some() {
+            err!("Password hints have been disabled by the administrator. Remove the hint and try again.");
+        }
+        // The policies of the organizations can't require a hint then
+        return Ok(());
+    }
+    if let Some(max_length) = CONFIG.password_hint_max_length()
+        && password_hint.is_some_and(|hint| hint.chars().count() > max_length)
+    {
+        err!(format!("The password hint can't be longer than {max_length} characters."));
+    }
+    if let Some(user_id) = user_id {
+        OrgPolicy::password_hint_policy(user_id, conn).await.check(password_hint)?;
     }
     Ok(())
 }
@@ -169,7 +199,12 @@ async fn is_email_2fa_required(member_id: Option<MembershipId>, conn: &DbConn) -
     false
 }
 
-pub async fn register(data: Json<RegisterData>, email_verification: bool, conn: DbConn) -> JsonResult {
+pub async fn register(
+    data: Json<RegisterData>,
+    email_verification: bool,
+    tenant: RequestTenant,
+    conn: DbConn,
+) -> JsonResult {
     let mut data: RegisterData = data.into_inner();
     let email = data.email.to_lowercase();
 
@@ -249,11 +284,11 @@ pub async fn register(data: Json<RegisterData>, email_verification: bool, conn:
     // Check against the password hint setting here so if it fails, the user
     // can retry without losing their invitation below.
     let password_hint = clean_password_hint(data.master_password_hint.as_ref());
-    enforce_password_hint_setting(password_hint.as_ref())?;
+    enforce_password_hint_setting(password_hint.as_ref(), None, &conn).await?;
 
     let mut user = match User::find_by_mail(&email, &conn).await {
         Some(user) => {
-            if !user.password_hash.is_empty() {
+            if !user.password_hash.is_empty() || !tenant.matches(user.tenant_id.as_deref()) {
                 err!("Registration not allowed or user already exists")
             }
 
@@ -269,7 +304,7 @@ pub async fn register(data: Json<RegisterData>, email_verification: bool, conn:
             } else if Invitation::take(&email, &conn).await {
                 Membership::accept_user_invitations(&user.uuid, &conn).await?;
                 user
-            } else if CONFIG.is_signup_allowed(&email)
+            } else if tenant.is_signup_allowed(&email)
                 || (CONFIG.emergency_access_allowed()
                     && EmergencyAccess::find_invited_by_grantee_email(&email, &conn).await.is_some())
             {
@@ -279,14 +314,21 @@ pub async fn register(data: Json<RegisterData>, email_verification: bool, conn:
             }
         }
         None => {
+            // Another subaddress of the email already has an account
+            if User::find_by_mail_or_subaddress(&email, &conn).await.is_some() {
+                err!("Registration not allowed or user already exists")
+            }
+
             // Order is important here; the invitation check must come first
             // because the vaultwarden admin can invite anyone, regardless
             // of other signup restrictions.
             if Invitation::take(&email, &conn).await
-                || CONFIG.is_signup_allowed(&email)
+                || tenant.is_signup_allowed(&email)
                 || pending_emergency_access.is_some()
             {
-                User::new(&email, None)
+                let mut user = User::new(&email, None);
+                user.tenant_id = tenant.id();
+                user
             } else {
                 err!("Registration not allowed or user already exists")
             }
@@ -332,6 +374,13 @@ pub async fn register(data: Json<RegisterData>, email_verification: bool, conn:
 
     user.save(&conn).await?;
 
+    // Users signing up with an address of a domain claimed by an organization join it, pending confirmation by an admin
+    if let Some(member) = invite_to_claimed_domain_org(&user, &conn).await
+        && let Err(e) = accept_org_invite(&user, member, None, &conn).await
+    {
+        warn!("Unable to join the organization claiming the domain of {}: {e:?}", user.email);
+    }
+
     // accept any open emergency access invitations
     if !CONFIG.mail_enabled() && CONFIG.emergency_access_allowed() {
         for mut emergency_invite in EmergencyAccess::find_all_invited_by_grantee_email(&user.email, &conn).await {
@@ -357,7 +406,7 @@ async fn post_set_password(data: Json<SetPasswordData>, headers: Headers, conn:
     // Check against the password hint setting here so if it fails,
     // the user can retry without losing their invitation below.
     let password_hint = clean_password_hint(data.master_password_hint.as_ref());
-    enforce_password_hint_setting(password_hint.as_ref())?;
+    enforce_password_hint_setting(password_hint.as_ref(), Some(&user.uuid), &conn).await?;
 
     set_kdf_data(&mut user, &data.kdf)?;
 
@@ -520,7 +569,7 @@ async fn post_password(data: Json<ChangePassData>, headers: Headers, conn: DbCon
     }
 
     user.password_hint = clean_password_hint(data.master_password_hint.as_ref());
-    enforce_password_hint_setting(user.password_hint.as_ref())?;
+    enforce_password_hint_setting(user.password_hint.as_ref(), Some(&user.uuid), &conn).await?;
 
     log_user_event(EventType::UserChangedPassword as i32, &user.uuid, headers.device.atype, &headers.ip.ip, &conn)
         .await;
@@ -878,6 +927,16 @@ async fn post_rotatekey(data: Json<KeyData>, headers: Headers, conn: DbConn, nt:
         update_send_from_data(send, send_data, &headers, &conn, &nt, UpdateType::None).await?;
     }
 
+    // The attachments which got a new key in this rotation, all the other files are still encrypted with the old keys
+    let rotated_attachment_ids: HashSet<AttachmentId> = data
+        .account_data
+        .ciphers
+        .iter()
+        .filter(|c| c.organization_id.is_none())
+        .filter_map(|c| c.attachments2.as_ref())
+        .flat_map(|a| a.keys().cloned())
+        .collect();
+
     // Update cipher data
     for cipher_data in data.account_data.ciphers {
         if cipher_data.organization_id.is_none() {
@@ -893,6 +952,8 @@ async fn post_rotatekey(data: Json<KeyData>, headers: Headers, conn: DbConn, nt:
         }
     }
 
+    track_attachment_rekeys(&existing_ciphers, &rotated_attachment_ids, user_id, &conn).await?;
+
     // Update user data
     let mut user = headers.user;
 
@@ -916,6 +977,33 @@ async fn post_rotatekey(data: Json<KeyData>, headers: Headers, conn: DbConn, nt:
     save_result
 }
 
+// Keeps track of the attachments the client still needs to re-upload with a new attachment key after the rotation.
+// Any progress of an earlier rotation is replaced, those replacements are encrypted with the keys which were just rotated.
+async fn track_attachment_rekeys(
+    ciphers: &[Cipher],
+    rotated_attachment_ids: &HashSet<AttachmentId>,
+    user_id: &UserId,
+    conn: &DbConn,
+) -> EmptyResult {
+    AttachmentRekey::delete_all_by_user(user_id, conn).await?;
+
+    let mut pending = 0;
+    for cipher in ciphers {
+        for attachment in Attachment::find_by_cipher(&cipher.uuid, conn).await {
+            let legacy = attachment.akey.is_none();
+            if legacy || !rotated_attachment_ids.contains(&attachment.id) {
+                AttachmentRekey::new(attachment.id, cipher.uuid.clone(), user_id.clone(), legacy).save(conn).await?;
+                pending += 1;
+            }
+        }
+    }
+
+    if pending > 0 {
+        info!("User {user_id} rotated their keys with {pending} attachment(s) still needing to be re-encrypted");
+    }
+    Ok(())
+}
+
 #[post("/accounts/security-stamp", data = "<data>")]
 async fn post_sstamp(data: Json<PasswordOrOtpData>, headers: Headers, conn: DbConn, nt: Notify<'_>) -> EmptyResult {
     let data: PasswordOrOtpData = data.into_inner();
@@ -986,11 +1074,38 @@ async fn post_email_token(data: Json<EmailTokenData>, headers: Headers, conn: Db
         debug!("Email change request for user ({}) to email ({}) with token ({token})", user.uuid, data.new_email);
     }
 
+    start_security_change(&mut user, "email address").await;
     user.email_new = Some(data.new_email);
     user.email_new_token = Some(token);
     user.save(&conn).await
 }
 
+/// Marks an email or two-step login change as pending, the caller needs to save the user.
+/// Until it is confirmed, other protected actions need the master password, see `PasswordOrOtpData::validate`.
+/// Only the first change notifies the current email address, so repeated requests don't flood it.
+pub async fn start_security_change(user: &mut User, change: &str) {
+    if CONFIG.security_change_lockout_hours() == 0 {
+        return;
+    }
+
+    let already_pending = user.has_pending_security_change();
+    user.security_change_pending_at = Some(Utc::now().naive_utc());
+
+    if !already_pending
+        && CONFIG.mail_enabled()
+        && let Err(e) = mail::send_security_change_pending(&user.email, change).await
+    {
+        error!("Error sending security-change-pending email: {e:#?}");
+    }
+}
+
+/// Ends the lockout once a change is confirmed, unless an email change is still waiting for its token
+pub fn finish_security_change(user: &mut User) {
+    if user.email_new.is_none() {
+        user.security_change_pending_at = None;
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ChangeEmailData {
@@ -1044,6 +1159,7 @@ async fn post_email(data: Json<ChangeEmailData>, headers: Headers, conn: DbConn,
     user.email = data.new_email;
     user.email_new = None;
     user.email_new_token = None;
+    finish_security_change(&mut user);
 
     user.set_password(&data.new_master_password_hash, Some(data.key), true, None, &conn).await?;
 
@@ -1207,7 +1323,13 @@ async fn password_hint(data: Json<PasswordHintData>, conn: DbConn) -> EmptyResul
             }
         }
         Some(user) => {
-            let hint: Option<String> = user.password_hint;
+            // The organizations of the user can disable the hints, even if one was set before
+            let hint: Option<String> = if OrgPolicy::password_hint_policy(&user.uuid, &conn).await.disable_password_hint
+            {
+                None
+            } else {
+                user.password_hint
+            };
             if CONFIG.mail_enabled() {
                 mail::send_password_hint(email, hint).await?;
                 Ok(())
@@ -1227,23 +1349,71 @@ pub struct PreloginData {
 }
 
 #[post("/accounts/prelogin", data = "<data>")]
-async fn post_prelogin(data: Json<PreloginData>, conn: DbConn) -> Json<Value> {
-    prelogin(data, conn).await
+async fn post_prelogin(data: Json<PreloginData>, tenant: RequestTenant, conn: DbConn) -> Json<Value> {
+    prelogin(data, tenant, conn).await
 }
 
-pub async fn prelogin(data: Json<PreloginData>, conn: DbConn) -> Json<Value> {
+pub async fn prelogin(data: Json<PreloginData>, tenant: RequestTenant, conn: DbConn) -> Json<Value> {
     let data: PreloginData = data.into_inner();
 
-    let (kdf_type, kdf_iter, kdf_mem, kdf_para) = match User::find_by_mail(&data.email, &conn).await {
+    // Users of other tenants are handled as if they don't exist
+    let user = User::find_by_mail(&data.email, &conn).await.filter(|u| tenant.matches(u.tenant_id.as_deref()));
+
+    let (kdf_type, kdf_iter, kdf_mem, kdf_para) = match &user {
         Some(user) => (user.client_kdf_type, user.client_kdf_iter, user.client_kdf_memory, user.client_kdf_parallelism),
         None => (User::CLIENT_KDF_TYPE_DEFAULT, User::CLIENT_KDF_ITER_DEFAULT, None, None),
     };
 
+    // The organization which claimed the domain of the address, if any
+    let claimed_org = match OrgDomain::find_verified_by_email(&data.email, &conn).await {
+        Some(domain) => Organization::find_by_uuid(&domain.org_uuid, &conn)
+            .await
+            .filter(|org| tenant.matches(org.tenant_id.as_deref())),
+        None => None,
+    };
+
+    // Organization driven hints, which the clients use to steer the user into the right login flow.
+    // Existing users get the hints of the organizations they are a member of, new users those of the claimed domain.
+    let mut sso_required = CONFIG.sso_enabled() && CONFIG.sso_only();
+    let mut sso_org_identifier = None;
+    if CONFIG.sso_enabled() {
+        if let Some(org) = &claimed_org {
+            sso_org_identifier = Some(org.uuid.clone());
+            sso_required |= OrgPolicy::find_by_org_and_type(&org.uuid, OrgPolicyType::RequireSso, &conn)
+                .await
+                .is_some_and(|p| p.enabled);
+        }
+        if let Some(user) = &user {
+            let memberships = Membership::find_by_user_and_policy(&user.uuid, OrgPolicyType::RequireSso, &conn).await;
+            if let Some(member) = memberships.iter().find(|m| m.atype < MembershipType::Admin) {
+                sso_required = true;
+                sso_org_identifier.get_or_insert_with(|| member.org_uuid.clone());
+            }
+        }
+    }
+
+    let master_password_policy = match (&user, &claimed_org) {
+        (Some(user), _) => master_password_policy(user, &conn).await,
+        (None, Some(org)) => merge_master_password_policies(
+            OrgPolicy::find_by_org_and_type(&org.uuid, OrgPolicyType::MasterPassword, &conn)
+                .await
+                .filter(|p| p.enabled)
+                .into_iter()
+                .collect(),
+        ),
+        (None, None) => merge_master_password_policies(Vec::new()),
+    };
+
     Json(json!({
         "kdf": kdf_type,
         "kdfIterations": kdf_iter,
         "kdfMemory": kdf_mem,
         "kdfParallelism": kdf_para,
+        "ssoRequired": sso_required,
+        "ssoOrganizationIdentifier": sso_org_identifier,
+        // Key Connector is not supported
+        "keyConnectorUrl": null,
+        "masterPasswordPolicy": master_password_policy,
     }))
 }
 
@@ -1309,6 +1479,71 @@ async fn rotate_api_key(data: Json<PasswordOrOtpData>, headers: Headers, conn: D
     update_api_key(data, true, headers, conn).await
 }
 
+fn support_access_json(user: &User) -> Value {
+    json!({
+        "enabled": user.has_support_access(),
+        "expirationDate": user.support_access_until.filter(|_| user.has_support_access()).as_ref().map(format_date),
+    })
+}
+
+#[get("/accounts/support-access")]
+fn get_support_access(headers: Headers) -> Json<Value> {
+    Json(support_access_json(&headers.user))
+}
+
+// Allows the instance admin to open a read-only support session for the account for a limited time
+#[post("/accounts/support-access", data = "<data>")]
+async fn post_support_access(data: Json<PasswordOrOtpData>, headers: Headers, conn: DbConn) -> JsonResult {
+    let data: PasswordOrOtpData = data.into_inner();
+    let mut user = headers.user;
+
+    data.validate(&user, true, &conn).await?;
+
+    let hours = TimeDelta::try_hours(i64::from(CONFIG.support_access_hours())).unwrap_or_default();
+    user.support_access_until = Some(Utc::now().naive_utc() + hours);
+    user.save(&conn).await?;
+
+    Ok(Json(support_access_json(&user)))
+}
+
+#[delete("/accounts/support-access")]
+async fn delete_support_access(headers: Headers, conn: DbConn) -> JsonResult {
+    let mut user = headers.user;
+
+    user.support_access_until = None;
+    user.save(&conn).await?;
+
+    Ok(Json(support_access_json(&user)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginNotificationsData {
+    enabled: bool,
+}
+
+// Emails about logins from a device, device type or country which wasn't seen before for the account
+#[get("/accounts/login-notifications")]
+fn get_login_notifications(headers: Headers) -> Json<Value> {
+    Json(json!({
+        "enabled": headers.user.login_notifications,
+        "required": CONFIG.require_device_email(),
+    }))
+}
+
+#[put("/accounts/login-notifications", data = "<data>")]
+async fn put_login_notifications(data: Json<LoginNotificationsData>, headers: Headers, conn: DbConn) -> JsonResult {
+    let mut user = headers.user;
+
+    user.login_notifications = data.enabled;
+    user.save(&conn).await?;
+
+    Ok(Json(json!({
+        "enabled": user.login_notifications,
+        "required": CONFIG.require_device_email(),
+    })))
+}
+
 #[get("/devices/knowndevice")]
 async fn get_known_device(device: KnownDevice, conn: DbConn) -> JsonResult {
     let result = if let Some(user) = User::find_by_mail(&device.email, &conn).await {
@@ -1364,7 +1599,17 @@ impl<'r> FromRequest<'r> for KnownDevice {
 #[get("/devices")]
 async fn get_all_devices(headers: Headers, conn: DbConn) -> JsonResult {
     let devices = Device::find_with_auth_request_by_user(&headers.user.uuid, &conn).await;
-    let devices = devices.iter().map(DeviceWithAuthRequest::to_json).collect::<Vec<Value>>();
+    // Only the number of other accounts is shown, their emails are not shared with the user
+    let shared = Device::find_shared_by_user(&headers.user.uuid, &conn).await;
+    let devices = devices
+        .iter()
+        .map(|device| {
+            let mut device_json = device.to_json();
+            device_json["otherAccountCount"] =
+                json!(shared.iter().filter(|(uuid, _)| uuid == &device.device.uuid).count());
+            device_json
+        })
+        .collect::<Vec<Value>>();
 
     Ok(Json(json!({
         "data": devices,
@@ -1409,7 +1654,18 @@ async fn put_device_token(device_id: DeviceId, data: Json<PushToken>, headers: H
         return Ok(());
     }
 
+    // The token now belongs to this device, a record of another device still holding it,
+    // possibly of another account, would otherwise receive the notifications of that account here.
+    for mut other in Device::find_by_push_token_of_other_devices(&token, &device.uuid, &conn).await {
+        if let Err(e) = unregister_push_device(other.push_uuid.as_ref()).await {
+            warn!("Unable to unregister the push device {}: {e:?}", other.uuid);
+        }
+        other.push_token = None;
+        other.save(false, &conn).await?;
+    }
+
     device.push_token = Some(token);
+    device.push_rejected_at = None;
     if let Err(e) = device.save(true, &conn).await {
         err!(format!("An error occurred while trying to save the device push token: {e}"));
     }
@@ -1419,22 +1675,80 @@ async fn put_device_token(device_id: DeviceId, data: Json<PushToken>, headers: H
     Ok(())
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateDeviceData {
+    name: Option<String>,
+    trusted: Option<bool>,
+    blocked: Option<bool>,
+}
+
+#[put("/devices/identifier/<device_id>", data = "<data>")]
+async fn put_device(device_id: DeviceId, data: Json<UpdateDeviceData>, headers: Headers, conn: DbConn) -> JsonResult {
+    let data = data.into_inner();
+
+    let Some(mut device) = Device::find_by_uuid_and_user(&device_id, &headers.user.uuid, &conn).await else {
+        err!("No device found");
+    };
+
+    if let Some(name) = data.name {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > 100 {
+            err!("The device name must be between 1 and 100 characters long")
+        }
+        name.clone_into(&mut device.name);
+    }
+
+    if let Some(trusted) = data.trusted {
+        device.trusted = trusted;
+    }
+
+    if let Some(blocked) = data.blocked
+        && blocked != device.blocked
+    {
+        if blocked {
+            if device.uuid == headers.device.uuid {
+                err!("You can't block the device you are currently using")
+            }
+            // Invalidate the current session and the remembered 2FA of the device
+            device.refresh_token = Device::generate_refresh_token();
+            device.delete_twofactor_remember();
+            if device.push_token.is_some() {
+                device.push_token = None;
+                unregister_push_device(device.push_uuid.as_ref()).await?;
+            }
+        }
+        device.blocked = blocked;
+    }
+
+    device.save(false, &conn).await?;
+
+    Ok(Json(device.to_json()))
+}
+
+#[post("/devices/identifier/<device_id>", data = "<data>")]
+async fn post_device(device_id: DeviceId, data: Json<UpdateDeviceData>, headers: Headers, conn: DbConn) -> JsonResult {
+    put_device(device_id, data, headers, conn).await
+}
+
 #[put("/devices/identifier/<device_id>/clear-token")]
-async fn put_clear_device_token(device_id: DeviceId, conn: DbConn) -> EmptyResult {
+async fn put_clear_device_token(device_id: DeviceId, headers: Headers, conn: DbConn) -> EmptyResult {
     // This only clears push token
     // https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Api/Controllers/DevicesController.cs#L215
     // https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Core/Services/Implementations/DeviceService.cs#L37
     // This is somehow not implemented in any app, added it in case it is required
-    // 2025: Also, it looks like it only clears the first found device upstream, which is probably faulty.
-    //       This because currently multiple accounts could be on the same device/app and that would cause issues.
-    //       Vaultwarden removes the push-token for all devices, but this probably means we should also unregister all these devices.
+    // Upstream this is anonymous and clears the first device found, which could belong to another account on the same app.
+    // Vaultwarden only clears and unregisters the device of the authenticated user.
     if !CONFIG.push_enabled() {
         return Ok(());
     }
 
-    if let Some(device) = Device::find_by_uuid(&device_id, &conn).await {
-        Device::clear_push_token_by_uuid(&device_id, &conn).await?;
+    if let Some(mut device) = Device::find_by_uuid_and_user(&device_id, &headers.user.uuid, &conn).await
+        && device.push_token.is_some()
+    {
         unregister_push_device(device.push_uuid.as_ref()).await?;
+        device.push_token = None;
+        device.save(false, &conn).await?;
     }
 
     Ok(())
@@ -1442,8 +1756,8 @@ async fn put_clear_device_token(device_id: DeviceId, conn: DbConn) -> EmptyResul
 
 // On upstream server, both PUT and POST are declared. Implementing the POST method in case it would be useful somewhere
 #[post("/devices/identifier/<device_id>/clear-token")]
-async fn post_clear_device_token(device_id: DeviceId, conn: DbConn) -> EmptyResult {
-    put_clear_device_token(device_id, conn).await
+async fn post_clear_device_token(device_id: DeviceId, headers: Headers, conn: DbConn) -> EmptyResult {
+    put_clear_device_token(device_id, headers, conn).await
 }
 
 #[get("/tasks")]
@@ -1577,15 +1891,20 @@ async fn put_auth_request(
         err!("An authentication request with the same device already exists")
     }
 
+    if auth_request.is_expired() {
+        err!("The authentication request has expired")
+    }
+
     let response_date = Utc::now().naive_utc();
     let response_date_utc = format_date(&response_date);
 
+    auth_request.approved = Some(data.request_approved);
+    auth_request.response_device_id = Some(data.device_identifier.clone());
+    auth_request.response_date = Some(response_date);
+
     if data.request_approved {
-        auth_request.approved = Some(data.request_approved);
         auth_request.enc_key = Some(data.key);
         auth_request.master_password_hash = data.master_password_hash;
-        auth_request.response_device_id = Some(data.device_identifier.clone());
-        auth_request.response_date = Some(response_date);
         auth_request.save(&conn).await?;
 
         ant.send_auth_response(&auth_request.user_uuid, &auth_request.uuid).await;
@@ -1600,8 +1919,9 @@ async fn put_auth_request(
         )
         .await;
     } else {
-        // If denied, there's no reason to keep the request
-        auth_request.delete(&conn).await?;
+        // Kept without the access code, only for the history of the user
+        auth_request.access_code = String::new();
+        auth_request.save(&conn).await?;
         log_user_event(
             EventType::OrganizationUserRejectedAuthRequest as i32,
             &headers.user.uuid,
@@ -1676,7 +1996,7 @@ async fn get_auth_requests_pending(headers: Headers, conn: DbConn) -> JsonResult
     Ok(Json(json!({
         "data": auth_requests
             .iter()
-            .filter(|request| request.approved.is_none())
+            .filter(|request| request.approved.is_none() && !request.is_expired())
             .map(|request| {
             let response_date_utc = request.response_date.map(|response_date| format_date(&response_date));
 
@@ -1699,6 +2019,196 @@ async fn get_auth_requests_pending(headers: Headers, conn: DbConn) -> JsonResult
     })))
 }
 
+/// Lists the login with device requests of the last days and how they were answered, for reviewing them
+#[get("/auth-requests/history")]
+async fn get_auth_requests_history(headers: Headers, conn: DbConn) -> Json<Value> {
+    let auth_requests = AuthRequest::find_recent_by_user(&headers.user.uuid, &conn).await;
+
+    Json(json!({
+        "data": auth_requests.iter().map(AuthRequest::to_json_history).collect::<Vec<Value>>(),
+        "continuationToken": null,
+        "object": "list"
+    }))
+}
+
+// Totals of the device cleanup job since startup, shown on the admin diagnostics page
+static PUSH_TOKENS_DEREGISTERED: AtomicU64 = AtomicU64::new(0);
+static STALE_DEVICES_REMOVED: AtomicU64 = AtomicU64::new(0);
+static REVISIONS_RECONCILED: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of rejected push tokens deregistered and stale devices removed since startup
+pub fn device_cleanup_stats() -> (u64, u64) {
+    (PUSH_TOKENS_DEREGISTERED.load(Ordering::Relaxed), STALE_DEVICES_REMOVED.load(Ordering::Relaxed))
+}
+
+/// Deregisters the push tokens refused by the push relay and removes the devices which weren't used for
+/// `DEVICES_DAYS_RETAIN` days, so they aren't part of the push notifications sent to a user anymore.
+pub async fn device_cleanup_job(pool: DbPool) {
+    debug!("Cleaning up devices");
+    let Ok(conn) = pool.get().await else {
+        error!("Failed to get DB connection while cleaning up devices");
+        return;
+    };
+
+    let mut deregistered = 0;
+    for mut device in Device::find_push_rejected(&conn).await {
+        if let Err(e) = unregister_push_device(device.push_uuid.as_ref()).await {
+            warn!("Unable to unregister the push device {}: {e:?}", device.uuid);
+            continue;
+        }
+        device.push_token = None;
+        device.push_rejected_at = None;
+        match device.save(false, &conn).await {
+            Ok(()) => deregistered += 1,
+            Err(e) => error!("Error removing a rejected push token: {e:?}"),
+        }
+    }
+
+    let mut removed = 0;
+    if let Some(days) = CONFIG.devices_days_retain() {
+        let since = Utc::now().naive_utc() - TimeDelta::try_days(days).unwrap_or_default();
+        for device in Device::find_unused_since(since, &conn).await {
+            if device.push_token.is_some()
+                && let Err(e) = unregister_push_device(device.push_uuid.as_ref()).await
+            {
+                warn!("Unable to unregister the push device {}: {e:?}", device.uuid);
+            }
+            match device.delete(&conn).await {
+                Ok(()) => removed += 1,
+                Err(e) => error!("Error removing a stale device: {e:?}"),
+            }
+        }
+    }
+
+    PUSH_TOKENS_DEREGISTERED.fetch_add(deregistered, Ordering::Relaxed);
+    STALE_DEVICES_REMOVED.fetch_add(removed, Ordering::Relaxed);
+    if deregistered > 0 || removed > 0 {
+        info!("Deregistered {deregistered} rejected push token(s) and removed {removed} stale device(s)");
+    }
+}
+
+/// Removes the accounts which registered, but didn't verify their email address within `SIGNUPS_VERIFY_DAYS_RETAIN` days.
+/// Accounts which logged in before email verification was required are kept.
+pub async fn purge_unverified_users(pool: DbPool) {
+    if !CONFIG.mail_enabled() || !CONFIG.signups_verify() {
+        return;
+    }
+    let Some(days) = CONFIG.signups_verify_days_retain() else {
+        return;
+    };
+    debug!("Purging unverified users");
+    let Ok(conn) = pool.get().await else {
+        error!("Failed to get DB connection while purging unverified users");
+        return;
+    };
+
+    let before = Utc::now().naive_utc() - TimeDelta::try_days(days).unwrap_or_default();
+    for user in User::find_unverified_registered_before(&before, &conn).await {
+        let email = user.email.clone();
+        match user.delete(&conn).await {
+            Ok(()) => info!("Removed the unverified account {email}"),
+            Err(e) => error!("Error removing the unverified account {email}: {e:?}"),
+        }
+    }
+}
+
+/// Returns the number of accounts whose stale revision date was repaired since startup
+pub fn revisions_reconciled() -> u64 {
+    REVISIONS_RECONCILED.load(Ordering::Relaxed)
+}
+
+/// Bumps the revision date of the users which are older than the newest change of their ciphers,
+/// so their clients sync again. This happens for example after restoring a partial backup.
+pub async fn reconcile_revision_dates(pool: DbPool) {
+    debug!("Reconciling revision dates");
+    let Ok(conn) = pool.get().await else {
+        error!("Failed to get DB connection while reconciling revision dates");
+        return;
+    };
+
+    let user_ids = User::find_with_stale_revision(&conn).await;
+    if user_ids.is_empty() {
+        return;
+    }
+    for user_id in &user_ids {
+        User::update_uuid_revision(user_id, &conn).await;
+    }
+    REVISIONS_RECONCILED.fetch_add(user_ids.len() as u64, Ordering::Relaxed);
+    info!("Repaired the revision date of {} account(s) older than their newest item change", user_ids.len());
+}
+
+/// Returns the date before which the last login of an account makes it inactive, if inactive accounts are detected
+pub fn inactive_users_cutoff() -> Option<NaiveDateTime> {
+    let months = CONFIG.inactive_users_months()?;
+    let months = Months::new(u32::try_from(months).unwrap_or(u32::MAX));
+    Utc::now().naive_utc().checked_sub_months(months)
+}
+
+/// Warns the owners of the accounts without a login for `INACTIVE_USERS_MONTHS` months.
+/// Depending on `INACTIVE_USERS_ACTION`, the account is disabled or deleted when there was still no login within the grace period.
+pub async fn inactive_users_job(pool: DbPool) {
+    let Some(cutoff) = inactive_users_cutoff() else {
+        return;
+    };
+    debug!("Checking inactive users");
+    let Ok(conn) = pool.get().await else {
+        error!("Failed to get DB connection while checking inactive users");
+        return;
+    };
+
+    let now = Utc::now().naive_utc();
+    let action = CONFIG.inactive_users_action();
+    let grace_period = TimeDelta::try_days(CONFIG.inactive_users_grace_days()).unwrap_or_default();
+    for mut user in User::find_inactive_since(&cutoff, &conn).await {
+        if user.inactivity_exempt {
+            continue;
+        }
+
+        let Some(warned_at) = user.inactivity_warned_at else {
+            user.inactivity_warned_at = Some(now);
+            if let Err(e) = user.save(&conn).await {
+                error!("Error saving the inactivity warning of {}: {e:?}", user.email);
+                continue;
+            }
+            if CONFIG.mail_enabled()
+                && let Err(e) = mail::send_inactive_account_warning(
+                    &user.email,
+                    &user.inactive_since(),
+                    &action,
+                    &(now + grace_period),
+                )
+                .await
+            {
+                error!("Error sending the inactivity warning to {}: {e:?}", user.email);
+            }
+            continue;
+        };
+        if warned_at + grace_period > now {
+            continue;
+        }
+
+        let email = user.email.clone();
+        match action.as_str() {
+            "disable" => match disable_inactive_user(&mut user, &conn).await {
+                Ok(()) => info!("Disabled the inactive account {email}"),
+                Err(e) => error!("Error disabling the inactive account {email}: {e:?}"),
+            },
+            "delete" => match user.delete(&conn).await {
+                Ok(()) => info!("Removed the inactive account {email}"),
+                Err(e) => error!("Error removing the inactive account {email}: {e:?}"),
+            },
+            _ => {}
+        }
+    }
+}
+
+async fn disable_inactive_user(user: &mut User, conn: &DbConn) -> EmptyResult {
+    user.reset_security_stamp(conn).await?;
+    user.enabled = false;
+    user.save(conn).await?;
+    Device::delete_all_by_user(&user.uuid, conn).await
+}
+
 pub async fn purge_auth_requests(pool: DbPool) {
     debug!("Purging auth requests");
     if let Ok(conn) = pool.get().await {
@@ -0,0 +1,96 @@
+use rocket::request::{FromRequest, Outcome, Request};
+use serde_json::Value;
+
+use crate::{
+    auth::ClientVersion,
+    db::models::{Cipher, OrgPolicy, OrgPolicyType},
+};
+
+// Features of the server which older clients don't know about yet.
+// Some clients fail to decode the whole sync when they find an unknown item type, policy type or field,
+// so these are removed from the responses based on the version sent by the client.
+// Clients which don't send their version are handled as old clients.
+#[derive(Clone, Copy)]
+pub enum Capability {
+    // SSH key items
+    SshKeys,
+    // The client requests the email 2FA code itself through `/two-factor/send-email-login`
+    SendEmailLogin,
+    // The `permissions` object of ciphers
+    CipherPermissions,
+    // The `archivedDate` of ciphers
+    CipherArchive,
+    // The policy types added after the clients started sending their version
+    PolicyRemoveUnlockWithPin,
+    PolicyRestrictedItemTypes,
+    PolicyUriMatchDefaults,
+}
+
+impl Capability {
+    // The first client version which supports the capability
+    fn min_version(self) -> semver::Version {
+        let (major, minor, patch) = match self {
+            Self::SshKeys => (2024, 12, 0),
+            Self::PolicyRemoveUnlockWithPin => (2025, 2, 0),
+            Self::SendEmailLogin => (2025, 5, 0),
+            Self::CipherPermissions | Self::PolicyRestrictedItemTypes => (2025, 6, 0),
+            Self::PolicyUriMatchDefaults => (2025, 9, 0),
+            Self::CipherArchive => (2025, 10, 0),
+        };
+        semver::Version::new(major, minor, patch)
+    }
+
+    fn for_policy_type(atype: i32) -> Option<Self> {
+        match num_traits::FromPrimitive::from_i32(atype) {
+            Some(OrgPolicyType::RemoveUnlockWithPin) => Some(Self::PolicyRemoveUnlockWithPin),
+            Some(OrgPolicyType::RestrictedItemTypes) => Some(Self::PolicyRestrictedItemTypes),
+            Some(OrgPolicyType::UriMatchDefaults) => Some(Self::PolicyUriMatchDefaults),
+            _ => None,
+        }
+    }
+}
+
+pub struct ClientCapabilities(Option<semver::Version>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientCapabilities {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let version = ClientVersion::from_request(request).await.succeeded();
+        Outcome::Success(Self::new(version.as_ref()))
+    }
+}
+
+impl ClientCapabilities {
+    pub fn new(client_version: Option<&ClientVersion>) -> Self {
+        Self(client_version.map(|v| v.0.clone()))
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.0.as_ref().is_some_and(|version| *version >= capability.min_version())
+    }
+
+    pub fn supports_cipher(&self, cipher: &Cipher) -> bool {
+        // SshKey = 5
+        cipher.atype != 5 || self.supports(Capability::SshKeys)
+    }
+
+    pub fn supports_policy(&self, policy: &OrgPolicy) -> bool {
+        policy.is_known_to_clients()
+            && Capability::for_policy_type(policy.atype).is_none_or(|capability| self.supports(capability))
+    }
+
+    /// Removes the fields the client doesn't know about from a cipher
+    pub fn filter_cipher_json(&self, cipher_json: &mut Value) {
+        let Some(cipher_json) = cipher_json.as_object_mut() else {
+            return;
+        };
+        if !self.supports(Capability::CipherPermissions) {
+            cipher_json.remove("permissions");
+        }
+        if !self.supports(Capability::CipherArchive) {
+            cipher_json.remove("archivedDate");
+        }
+    }
+}
@@ -1,34 +1,46 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+};
 
-use chrono::{NaiveDateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use num_traits::ToPrimitive;
 use rocket::{
     Route,
+    data::{Data, Limits, ToByteUnit},
     form::{Form, FromForm},
     fs::TempFile,
+    http::{ContentType, Status},
+    request::{FromRequest, Outcome, Request},
+    response::stream::TextStream,
     serde::json::Json,
 };
-use serde_json::Value;
+use serde_json::{Map, Value};
+use tokio::sync::{Semaphore, SemaphorePermit};
 
 use crate::{
     CONFIG,
-    api::{self, EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType, core::log_event},
-    auth::ClientVersion,
+    api::{self, EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType, WS_USERS, core::log_event},
     auth::{Headers, OrgIdGuard, OwnerHeaders},
     config::PathType,
     crypto,
     db::{
         DbConn, DbPool,
         models::{
-            Archive, Attachment, AttachmentId, Cipher, CipherId, Collection, CollectionCipher, CollectionGroup,
-            CollectionId, CollectionUser, EventType, Favorite, Folder, FolderCipher, FolderId, Group, Membership,
-            MembershipType, OrgPolicy, OrgPolicyType, OrganizationId, RepromptType, Send, UserId,
+            Archive, Attachment, AttachmentId, AttachmentRekey, AttachmentShare, Cipher, CipherId, CipherTemplate,
+            CipherUserChange, Collection, CollectionCipher, CollectionGroup, CollectionId, CollectionUser, EventType,
+            Favorite, Folder, FolderCipher, FolderId, Group, JobLock, Membership, MembershipType, OrgPolicy,
+            OrgPolicyType, Organization, OrganizationId, QuotaWarning, RepromptType, Send, Tombstone, User, UserId,
         },
     },
-    util::{NumberOrString, deser_opt_nonempty_str, save_temp_file},
+    error::{Error, ErrorCode},
+    mail,
+    ratelimit::ItemCreationCharge,
+    util::{NumberOrString, convert_json_key_lcase_first, deser_opt_nonempty_str, get_display_size, save_temp_file},
 };
 
-use super::folders::FolderData;
+use super::{capabilities::ClientCapabilities, folders::FolderData, organizations::log_audit_grant_reads, strict};
 
 pub fn routes() -> Vec<Route> {
     // Note that many routes have an `admin` variant; this seems to be
@@ -44,7 +56,9 @@ pub fn routes() -> Vec<Route> {
     routes![
         sync,
         get_ciphers,
+        get_ciphers_export,
         get_cipher,
+        get_cipher_by_external_id,
         get_cipher_admin,
         get_cipher_details,
         post_ciphers,
@@ -53,8 +67,12 @@ pub fn routes() -> Vec<Route> {
         post_ciphers_create,
         post_ciphers_import,
         get_attachment,
+        post_attachment_download_link,
         post_attachment_v2,
         post_attachment_v2_data,
+        get_attachment_upload,
+        patch_attachment_upload,
+        post_attachment_upload_complete,
         post_attachment,       // legacy
         post_attachment_admin, // legacy
         post_attachment_share,
@@ -62,6 +80,8 @@ pub fn routes() -> Vec<Route> {
         delete_attachment_post_admin,
         delete_attachment,
         delete_attachment_admin,
+        get_attachment_rekeys,
+        post_attachment_rekey,
         post_cipher_admin,
         post_cipher_share,
         put_cipher_share,
@@ -103,10 +123,64 @@ pub fn routes() -> Vec<Route> {
     ]
 }
 
+// Confirms that the files which were re-uploaded after a key rotation are completely stored
+pub async fn verify_attachment_rekeys(pool: DbPool) {
+    debug!("Verifying re-encrypted attachments");
+    let Ok(conn) = pool.get().await else {
+        error!("Failed to get DB connection while verifying re-encrypted attachments");
+        return;
+    };
+    let backend = match CONFIG.storage_backend(&PathType::Attachments) {
+        Ok(backend) => backend,
+        Err(e) => {
+            error!("Unable to verify re-encrypted attachments: {e:?}");
+            return;
+        }
+    };
+
+    for mut rekey in AttachmentRekey::find_unverified(&conn).await {
+        let attachment_id = rekey.attachment_id.clone();
+        let result = match rekey.replaced_by.clone() {
+            // The attachment was removed in the meantime, so there is nothing left to re-encrypt
+            None if Attachment::find_by_id(&attachment_id, &conn).await.is_none() => rekey.delete(&conn).await,
+            None => continue,
+            Some(replacement_id) => match Attachment::find_by_id(&replacement_id, &conn).await {
+                // The replacement is gone as well, the client has to upload the file again
+                None => {
+                    rekey.replaced_by = None;
+                    rekey.save(&conn).await
+                }
+                Some(replacement) => match backend.stat(&replacement.get_file_path()).await {
+                    Ok(Some(stat)) if i64::try_from(stat.size).is_ok_and(|size| size == replacement.file_size) => {
+                        rekey.verified_at = Some(Utc::now().naive_utc());
+                        rekey.save(&conn).await
+                    }
+                    // Not (completely) uploaded yet
+                    Ok(_) => continue,
+                    Err(e) => {
+                        warn!("Unable to check the file of attachment '{replacement_id}': {e:?}");
+                        continue;
+                    }
+                },
+            },
+        };
+
+        if let Err(e) = result {
+            error!("Error verifying the re-encryption of attachment '{attachment_id}': {e:?}");
+        }
+    }
+}
+
 pub async fn purge_trashed_ciphers(pool: DbPool) {
     debug!("Purging trashed ciphers");
     if let Ok(conn) = pool.get().await {
         Cipher::purge_trash(&conn).await;
+        if let Err(e) = Tombstone::purge(&conn).await {
+            error!("Failed to purge tombstones: {e:?}");
+        }
+        if let Err(e) = CipherUserChange::purge(&conn).await {
+            error!("Failed to purge cipher changes: {e:?}");
+        }
     } else {
         error!("Failed to get DB connection while purging trashed ciphers");
     }
@@ -116,34 +190,100 @@ pub async fn purge_trashed_ciphers(pool: DbPool) {
 struct SyncData {
     #[field(name = "excludeDomains")]
     exclude_domains: bool, // Default: 'false'
+    since: Option<String>,
+}
+
+// Limits the number of vaults held in memory at the same time by full syncs, see `SYNC_MAX_CONCURRENT`
+static SYNC_PERMITS: LazyLock<Option<Semaphore>> = LazyLock::new(|| match CONFIG.sync_max_concurrent() {
+    0 => None,
+    permits => Some(Semaphore::new(permits)),
+});
+
+/// Waits for a permit of `SYNC_MAX_CONCURRENT`. Needs to be the first guard of the route, before the guards which
+/// take a database connection, otherwise the waiting requests hold the connections the running syncs need.
+/// Requests without a valid access token don't wait, they are refused by the `Headers` guard right after.
+struct SyncPermit {
+    _permit: Option<SemaphorePermit<'static>>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SyncPermit {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let has_valid_token = || {
+            request
+                .headers()
+                .get_one("Authorization")
+                .and_then(|a| a.strip_prefix("Bearer "))
+                .is_some_and(|token| crate::auth::decode_login(token).is_ok())
+        };
+        let permit = match SYNC_PERMITS.as_ref() {
+            Some(permits) if has_valid_token() => permits.acquire().await.ok(),
+            _ => None,
+        };
+        Outcome::Success(Self {
+            _permit: permit,
+        })
+    }
+}
+
+/// Parses the revision date of a delta sync, either as milliseconds like `/accounts/revision-date` or as RFC 3339
+fn parse_sync_since(since: &str) -> Option<NaiveDateTime> {
+    if let Ok(millis) = since.parse::<i64>() {
+        return DateTime::from_timestamp_millis(millis).map(|d| d.naive_utc());
+    }
+    DateTime::parse_from_rfc3339(since).ok().map(|d| d.naive_utc())
 }
 
+/// With `since` only the ciphers, folders and sends changed after that date are returned, together with the
+/// tombstones of the items deleted since then. Ciphers also count as changed for a user when their folder, favorite
+/// or archive status changed or when they were added to a collection of the user.
+/// Collections, policies and the profile are always returned in full.
+/// When deletions from that long ago aren't remembered anymore, or the user may have lost access to items since then,
+/// the whole vault is returned and `incremental` is false.
 #[get("/sync?<data..>")]
-async fn sync(data: SyncData, headers: Headers, client_version: Option<ClientVersion>, conn: DbConn) -> JsonResult {
+async fn sync(
+    _permit: SyncPermit,
+    data: SyncData,
+    headers: Headers,
+    capabilities: ClientCapabilities,
+    conn: DbConn,
+) -> JsonResult {
+    let since = match data.since.as_deref().map(parse_sync_since) {
+        None => None,
+        Some(None) => err!("Invalid revision date to sync from"),
+        Some(Some(since)) => {
+            let complete = Tombstone::covers(since, &conn).await
+                && !Tombstone::has_access_change_since(headers.user.uuid.as_ref(), since, &conn).await;
+            complete.then_some(since)
+        }
+    };
+    let changed = |date: &NaiveDateTime| since.is_none_or(|since| *date >= since);
+    let changed_for_user = match since {
+        Some(since) => CipherUserChange::find_cipher_uuids_by_user_since(&headers.user.uuid, since, &conn).await,
+        None => HashSet::new(),
+    };
+
     let user_json = headers.user.to_json(&conn).await;
 
-    // Get all ciphers which are visible by the user
+    // Get all ciphers which are visible by the user, without the item types the client doesn't support
     let mut ciphers = Cipher::find_by_user_visible(&headers.user.uuid, &conn).await;
-
-    // Filter out SSH keys if the client version is less than 2024.12.0
-    let show_ssh_keys = if let Some(client_version) = client_version {
-        let ver_match = semver::VersionReq::parse(">=2024.12.0").unwrap();
-        ver_match.matches(&client_version.0)
-    } else {
-        false
-    };
-    if !show_ssh_keys {
-        ciphers.retain(|c| c.atype != 5);
-    }
+    ciphers
+        .retain(|c| capabilities.supports_cipher(c) && (changed(&c.updated_at) || changed_for_user.contains(&c.uuid)));
 
     let cipher_sync_data = CipherSyncData::new(&headers.user.uuid, CipherSyncType::User, &conn).await;
 
+    let read_collections = ciphers.iter().filter_map(|c| cipher_sync_data.cipher_collections.get(&c.uuid)).flatten();
+    log_audit_grant_reads(&headers, read_collections, &conn).await;
+
     // Lets generate the ciphers_json using all the gathered info
     let mut ciphers_json = Vec::with_capacity(ciphers.len());
     for c in ciphers {
-        ciphers_json.push(
-            c.to_json(&headers.host, &headers.user.uuid, Some(&cipher_sync_data), CipherSyncType::User, &conn).await?,
-        );
+        let mut cipher_json =
+            c.to_json(&headers.host, &headers.user.uuid, Some(&cipher_sync_data), CipherSyncType::User, &conn).await?;
+        capabilities.filter_cipher_json(&mut cipher_json);
+        ciphers_json.push(cipher_json);
     }
 
     let collections = Collection::find_by_user_uuid(headers.user.uuid.clone(), &conn).await;
@@ -152,19 +292,44 @@ async fn sync(data: SyncData, headers: Headers, client_version: Option<ClientVer
         collections_json.push(c.to_json_details(&headers.user.uuid, Some(&cipher_sync_data), &conn).await);
     }
 
-    let folders_json: Vec<Value> =
-        Folder::find_by_user(&headers.user.uuid, &conn).await.iter().map(Folder::to_json).collect();
-
-    let sends_json: Vec<Value> =
-        Send::find_by_user(&headers.user.uuid, &conn).await.iter().map(Send::to_json).collect();
+    let folders_json: Vec<Value> = Folder::find_by_user(&headers.user.uuid, &conn)
+        .await
+        .iter()
+        .filter(|f| changed(&f.updated_at))
+        .map(Folder::to_json)
+        .collect();
+
+    let sends_json: Vec<Value> = Send::find_by_user(&headers.user.uuid, &conn)
+        .await
+        .iter()
+        .filter(|s| changed(&s.revision_date))
+        .map(Send::to_json)
+        .collect();
+
+    let tombstones_json: Vec<Value> = if let Some(since) = since {
+        let mut owners = vec![headers.user.uuid.to_string()];
+        owners.extend(
+            Membership::find_confirmed_by_user(&headers.user.uuid, &conn)
+                .await
+                .into_iter()
+                .map(|m| m.org_uuid.to_string()),
+        );
+        Tombstone::find_by_owners_since(owners, since, &conn).await.iter().map(Tombstone::to_json).collect()
+    } else {
+        Vec::new()
+    };
 
-    let policies_json: Vec<Value> =
-        OrgPolicy::find_confirmed_by_user(&headers.user.uuid, &conn).await.iter().map(OrgPolicy::to_json).collect();
+    let policies_json: Vec<Value> = OrgPolicy::find_confirmed_by_user(&headers.user.uuid, &conn)
+        .await
+        .iter()
+        .filter(|p| capabilities.supports_policy(p))
+        .map(OrgPolicy::to_json)
+        .collect();
 
     let domains_json = if data.exclude_domains {
         Value::Null
     } else {
-        api::core::get_eq_domains(&headers, true).into_inner()
+        api::core::get_eq_domains(&headers, true, &conn).await.into_inner()
     };
 
     // This is very similar to the the userDecryptionOptions sent in connect/token,
@@ -196,6 +361,8 @@ async fn sync(data: SyncData, headers: Headers, client_version: Option<ClientVer
         "ciphers": ciphers_json,
         "domains": domains_json,
         "sends": sends_json,
+        "tombstones": tombstones_json,
+        "incremental": since.is_some(),
         "userDecryption": {
             "masterPasswordUnlock": master_password_unlock,
         },
@@ -203,10 +370,32 @@ async fn sync(data: SyncData, headers: Headers, client_version: Option<ClientVer
     })))
 }
 
-#[get("/ciphers")]
-async fn get_ciphers(headers: Headers, conn: DbConn) -> JsonResult {
-    let ciphers = Cipher::find_by_user_visible(&headers.user.uuid, &conn).await;
+// Number of ciphers per page of `/ciphers`, when paging is requested
+const CIPHERS_MAX_PAGE_SIZE: i64 = 1000;
+
+#[derive(FromForm)]
+struct CiphersPage {
+    #[field(name = "continuationToken")]
+    continuation_token: Option<CipherId>,
+    limit: Option<i64>,
+}
+
+/// Without a `limit` or `continuationToken` the whole vault is returned, like the clients expect.
+/// Otherwise the ciphers are returned page by page, the `continuationToken` of the response needs to be passed to get the next page.
+#[get("/ciphers?<page..>")]
+async fn get_ciphers(_permit: SyncPermit, page: CiphersPage, headers: Headers, conn: DbConn) -> JsonResult {
+    let (ciphers, continuation_token) = if page.limit.is_none() && page.continuation_token.is_none() {
+        (Cipher::find_by_user_visible(&headers.user.uuid, &conn).await, None)
+    } else {
+        let limit = page.limit.unwrap_or(CIPHERS_MAX_PAGE_SIZE);
+        if !(1..=CIPHERS_MAX_PAGE_SIZE).contains(&limit) {
+            err!(format!("The limit needs to be between 1 and {CIPHERS_MAX_PAGE_SIZE}"))
+        }
+        Cipher::find_by_user_visible_page(&headers.user.uuid, page.continuation_token.as_ref(), limit, &conn).await
+    };
     let cipher_sync_data = CipherSyncData::new(&headers.user.uuid, CipherSyncType::User, &conn).await;
+    let read_collections = ciphers.iter().filter_map(|c| cipher_sync_data.cipher_collections.get(&c.uuid)).flatten();
+    log_audit_grant_reads(&headers, read_collections, &conn).await;
 
     let mut ciphers_json = Vec::with_capacity(ciphers.len());
     for c in ciphers {
@@ -218,10 +407,105 @@ async fn get_ciphers(headers: Headers, conn: DbConn) -> JsonResult {
     Ok(Json(json!({
       "data": ciphers_json,
       "object": "list",
-      "continuationToken": null
+      "continuationToken": continuation_token,
     })))
 }
 
+#[derive(FromForm)]
+struct ExportQuery {
+    // A random value encrypted by the client with the user key, the importer checks it can decrypt it
+    #[field(name = "encKeyValidation")]
+    enc_key_validation: String,
+}
+
+/// The fields of an item in the encrypted JSON export of the Bitwarden clients
+const EXPORT_CIPHER_FIELDS: [&str; 19] = [
+    "id",
+    "organizationId",
+    "folderId",
+    "type",
+    "reprompt",
+    "name",
+    "notes",
+    "favorite",
+    "login",
+    "secureNote",
+    "card",
+    "identity",
+    "sshKey",
+    "fields",
+    "passwordHistory",
+    "revisionDate",
+    "creationDate",
+    "deletedDate",
+    "key",
+];
+
+/// Exports the personal vault of the user in the account restricted encrypted JSON format of the Bitwarden clients.
+/// The response is written one item at a time, so very large vaults don't need to be assembled by the web-vault.
+/// The values stay encrypted with the user key, so the client passes the `encKeyValidation_DO_NOT_EDIT` value.
+/// Every item also has the metadata of its attachments, the importers ignore it.
+#[get("/ciphers/export?<query..>")]
+async fn get_ciphers_export(
+    permit: SyncPermit,
+    query: ExportQuery,
+    headers: Headers,
+    conn: DbConn,
+) -> Result<(ContentType, TextStream![String]), Error> {
+    if !strict::is_enc_string(&query.enc_key_validation) {
+        err!("The key validation needs to be an encrypted string")
+    }
+
+    let folders = Folder::find_by_user(&headers.user.uuid, &conn).await;
+    let mut ciphers = Cipher::find_owned_by_user(&headers.user.uuid, &conn).await;
+    ciphers.retain(|c| c.deleted_at.is_none());
+    let cipher_sync_data = CipherSyncData::new(&headers.user.uuid, CipherSyncType::User, &conn).await;
+    let (host, user_id) = (headers.host, headers.user.uuid);
+
+    // When an item can't be converted the stream ends early, leaving an invalid document instead of an incomplete export
+    let stream = TextStream! {
+        let _permit = permit;
+        let folders_json = folders.iter().map(|f| json!({"id": f.uuid, "name": f.name})).collect::<Value>();
+        yield format!(
+            "{{\"encrypted\":true,\"encKeyValidation_DO_NOT_EDIT\":{},\"folders\":{folders_json},\"items\":[",
+            Value::String(query.enc_key_validation),
+        );
+
+        for (i, cipher) in ciphers.iter().enumerate() {
+            let cipher_json = match cipher.to_json(&host, &user_id, Some(&cipher_sync_data), CipherSyncType::User, &conn).await {
+                Ok(cipher_json) => export_cipher_json(&convert_json_key_lcase_first(cipher_json)),
+                Err(e) => {
+                    error!("Error exporting the cipher {}: {e:?}", cipher.uuid);
+                    return;
+                }
+            };
+            let separator = if i == 0 { "" } else { "," };
+            yield format!("{separator}{cipher_json}");
+        }
+
+        yield "]}".to_owned();
+    };
+    Ok((ContentType::JSON, stream))
+}
+
+fn export_cipher_json(cipher_json: &Value) -> Value {
+    let mut export = Map::new();
+    for field in EXPORT_CIPHER_FIELDS {
+        let value = &cipher_json[field];
+        if !value.is_null() {
+            export.insert(field.to_owned(), value.clone());
+        }
+    }
+    // Personal items are exported without collections
+    export.insert("collectionIds".to_owned(), Value::Null);
+    if let Some(attachments) = cipher_json["attachments"].as_array() {
+        let attachments =
+            attachments.iter().map(|a| json!({"id": a["id"], "fileName": a["fileName"], "size": a["size"]})).collect();
+        export.insert("attachments".to_owned(), attachments);
+    }
+    Value::Object(export)
+}
+
 #[get("/ciphers/<cipher_id>")]
 async fn get_cipher(cipher_id: CipherId, headers: Headers, conn: DbConn) -> JsonResult {
     let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
@@ -231,14 +515,92 @@ async fn get_cipher(cipher_id: CipherId, headers: Headers, conn: DbConn) -> Json
     if !cipher.is_accessible_to_user(&headers.user.uuid, &conn).await {
         err!("Cipher is not owned by user")
     }
+    if cipher.organization_uuid.is_some() {
+        let read_collections = CollectionCipher::find_collection_uuids_by_cipher(&cipher.uuid, &conn).await;
+        log_audit_grant_reads(&headers, &read_collections, &conn).await;
+    }
+
+    Ok(Json(cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, &conn).await?))
+}
+
+#[derive(FromForm)]
+struct ExternalIdData {
+    #[field(name = "organizationId")]
+    organization_id: Option<OrganizationId>,
+}
+
+/// Looks up an item by the external ID automation tools set on it, in the personal vault or in the given organization
+#[get("/ciphers/by-external-id/<external_id>?<data..>", rank = 1)]
+async fn get_cipher_by_external_id(
+    external_id: &str,
+    data: ExternalIdData,
+    headers: Headers,
+    conn: DbConn,
+) -> JsonResult {
+    let cipher = match data.organization_id {
+        Some(org_id) => Cipher::find_by_org_and_external_id(&org_id, external_id, &conn).await,
+        None => Cipher::find_by_user_and_external_id(&headers.user.uuid, external_id, &conn).await,
+    };
+    // Items in the trash are returned as well, their external ID is still taken
+    let Some(cipher) = cipher else {
+        err!("Cipher doesn't exist")
+    };
+
+    if !cipher.is_accessible_to_user(&headers.user.uuid, &conn).await {
+        err!("Cipher doesn't exist")
+    }
 
     Ok(Json(cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, &conn).await?))
 }
 
+/// Used by the admin console, owners and admins can fetch every item of their organization, also when they aren't assigned to its collections.
+/// The response doesn't contain the personal fields like the folder and favorite, these don't apply to the organization vault.
+// https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Api/Vault/Controllers/CiphersController.cs#L139
 #[get("/ciphers/<cipher_id>/admin")]
 async fn get_cipher_admin(cipher_id: CipherId, headers: Headers, conn: DbConn) -> JsonResult {
-    // TODO: Implement this correctly
-    get_cipher(cipher_id, headers, conn).await
+    let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
+        err!("Cipher doesn't exist")
+    };
+    let Some(org_id) = &cipher.organization_uuid else {
+        err!("Cipher doesn't exist", "Cipher is not owned by an organization")
+    };
+    check_org_cipher_access(&cipher, org_id, &headers.user.uuid, &conn).await?;
+
+    let mut cipher_json =
+        cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::Organization, &conn).await?;
+    if let Some(cipher_json) = cipher_json.as_object_mut() {
+        for key in ["folderId", "favorite", "archivedDate", "edit", "viewPassword", "manage", "permissions"] {
+            cipher_json.remove(key);
+        }
+        cipher_json.insert(String::from("object"), json!("cipherMiniDetails"));
+    }
+    Ok(Json(cipher_json))
+}
+
+/// Checks an organization item is visible to the user in the admin console
+async fn check_org_cipher_access(
+    cipher: &Cipher,
+    org_id: &OrganizationId,
+    user_id: &UserId,
+    conn: &DbConn,
+) -> EmptyResult {
+    // Admins only see all items when `allowAdminAccessToAllCollectionItems` is enabled, otherwise they need a collection
+    let has_full_item_access = match (
+        Organization::find_by_uuid(org_id, conn).await,
+        Membership::find_confirmed_by_user_and_org(user_id, org_id, conn).await,
+    ) {
+        (Some(org), Some(member)) => member.atype >= MembershipType::Admin && org.has_full_item_access(&member),
+        _ => false,
+    };
+    if has_full_item_access {
+        // Full access to the organization doesn't include the items of confidential collections
+        if Cipher::find_hidden_confidential_uuids(user_id, conn).await.contains(&cipher.uuid) {
+            err!("Cipher doesn't exist", "Cipher is in a confidential collection the user isn't assigned to")
+        }
+    } else if !cipher.is_accessible_to_user(user_id, conn).await {
+        err!("Cipher doesn't exist", "Cipher is not accessible to the user")
+    }
+    Ok(())
 }
 
 #[get("/ciphers/<cipher_id>/details")]
@@ -281,6 +643,8 @@ pub struct CipherData {
 
     favorite: Option<bool>,
     reprompt: Option<i32>,
+    // Not sent by the official clients, an empty value removes it
+    external_id: Option<String>,
 
     pub password_history: Option<Value>,
 
@@ -288,7 +652,7 @@ pub struct CipherData {
     // 'Attachments' is unused, contains map of {id: filename}
     #[allow(dead_code)]
     attachments: Option<Value>,
-    attachments2: Option<HashMap<AttachmentId, Attachments2Data>>,
+    pub attachments2: Option<HashMap<AttachmentId, Attachments2Data>>,
 
     // The revision datetime (in ISO 8601 format) of the client's local copy
     // of the cipher. This is used to prevent a client from updating a cipher
@@ -298,6 +662,44 @@ pub struct CipherData {
     // updating an existing cipher.
     last_known_revision_date: Option<String>,
     archived_date: Option<String>,
+
+    // Only checked with STRICT_REQUEST_PARSING enabled
+    #[serde(flatten)]
+    unknown_fields: Map<String, Value>,
+}
+
+// Fields of the upstream request model which are not used
+const CIPHER_IGNORED_FIELDS: &[&str] = &["encryptedFor", "data"];
+
+impl CipherData {
+    /// Validates the fields and the encrypted strings, only when STRICT_REQUEST_PARSING is enabled
+    fn check_strict(&self) -> EmptyResult {
+        if !strict::enabled() {
+            return Ok(());
+        }
+        strict::check_unknown_fields("item", &self.unknown_fields, CIPHER_IGNORED_FIELDS)?;
+        strict::check_enc_string("name", Some(&self.name))?;
+        strict::check_enc_string("notes", self.notes.as_deref())?;
+        strict::check_enc_string("key", self.key.as_deref())?;
+        for (field, value) in [
+            ("fields", &self.fields),
+            ("login", &self.login),
+            ("card", &self.card),
+            ("identity", &self.identity),
+            ("secureNote", &self.secure_note),
+            ("sshKey", &self.ssh_key),
+            ("passwordHistory", &self.password_history),
+        ] {
+            strict::check_enc_strings_in(field, value.as_ref())?;
+        }
+        if let Some(attachments) = &self.attachments2 {
+            for attachment in attachments.values() {
+                strict::check_enc_string("attachments2.fileName", Some(&attachment.file_name))?;
+                strict::check_enc_string("attachments2.key", Some(&attachment.key))?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -337,6 +739,7 @@ async fn post_ciphers_create(
     // need it here as well to avoid creating an empty cipher in the call to
     // cipher.save() below.
     enforce_personal_ownership_policy(Some(&data.cipher), &headers, &conn).await?;
+    let quota_charge = enforce_item_quota(&headers.user, 1, data.cipher.organization_id.is_none(), &conn).await?;
 
     let mut cipher = Cipher::new(data.cipher.r#type, data.cipher.name.clone());
     cipher.user_uuid = Some(headers.user.uuid.clone());
@@ -353,6 +756,8 @@ async fn post_ciphers_create(
     let res = share_cipher_by_uuid(&cipher.uuid, data, &headers, &conn, &nt, None).await;
     if res.is_err() {
         cipher.delete(&conn).await?;
+    } else {
+        quota_charge.keep();
     }
     res
 }
@@ -367,13 +772,35 @@ async fn post_ciphers(data: Json<CipherData>, headers: Headers, conn: DbConn, nt
     // which results in a warning message being logged. This field isn't
     // needed when creating a new cipher, so just ignore it unconditionally.
     data.last_known_revision_date = None;
+    let quota_charge = enforce_item_quota(&headers.user, 1, data.organization_id.is_none(), &conn).await?;
 
     let mut cipher = Cipher::new(data.r#type, data.name.clone());
     update_cipher_from_data(&mut cipher, data, &headers, None, &conn, &nt, UpdateType::SyncCipherCreate).await?;
+    quota_charge.keep();
 
     Ok(Json(cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, &conn).await?))
 }
 
+/// Enforces the per-user item quotas before `items` new items are created, `personal` when the user owns them.
+/// The returned charge needs to be kept once the items are created, otherwise they don't count.
+pub(super) async fn enforce_item_quota(
+    user: &User,
+    items: usize,
+    personal: bool,
+    conn: &DbConn,
+) -> Result<ItemCreationCharge, Error> {
+    if user.quota_exempt || items == 0 {
+        return Ok(ItemCreationCharge::default());
+    }
+    if personal && let Some(limit) = CONFIG.user_item_limit() {
+        let count = Cipher::count_owned_by_user(&user.uuid, conn).await;
+        if count.saturating_add(i64::try_from(items).unwrap_or(i64::MAX)) > limit {
+            err!(format!("Your vault can't contain more than {limit} items"), ErrorCode::QuotaExceeded)
+        }
+    }
+    crate::ratelimit::check_limit_item_creation(&user.uuid, u64::try_from(items).unwrap_or(u64::MAX))
+}
+
 /// Enforces the personal ownership policy on user-owned ciphers, if applicable.
 /// A non-owner/admin user belonging to an org with the personal ownership policy
 /// enabled isn't allowed to create new user-owned ciphers or modify existing ones
@@ -386,12 +813,49 @@ async fn enforce_personal_ownership_policy(data: Option<&CipherData>, headers: &
         let user_id = &headers.user.uuid;
         let policy_type = OrgPolicyType::PersonalOwnership;
         if OrgPolicy::is_applicable_to_user(user_id, policy_type, None, conn).await {
-            err!("Due to an Enterprise Policy, you are restricted from saving items to your personal vault.")
+            err!(
+                "Due to an Enterprise Policy, you are restricted from saving items to your personal vault.",
+                ErrorCode::PolicyPersonalOwnership
+            )
         }
     }
     Ok(())
 }
 
+// Ciphers which are being written right now, only used when CIPHER_WRITE_LOCK is enabled
+static CIPHER_WRITES: LazyLock<Mutex<HashSet<CipherId>>> = LazyLock::new(Default::default);
+
+/// Serializes the writes to a single cipher, so the cipher itself, its folder and the favorite flag are saved together.
+/// A second write to the same cipher is not queued, it fails with a retryable 409 instead.
+struct CipherWriteGuard(Option<CipherId>);
+
+impl CipherWriteGuard {
+    fn acquire(cipher_id: &CipherId) -> Result<Self, Error> {
+        if !CONFIG.cipher_write_lock() {
+            return Ok(Self(None));
+        }
+        let Ok(mut writes) = CIPHER_WRITES.lock() else {
+            return Ok(Self(None));
+        };
+        if !writes.insert(cipher_id.clone()) {
+            return Err(Error::new_msg("This item is being saved by another client, please try again.")
+                .with_code(409)
+                .with_error_code(ErrorCode::CipherWriteConflict));
+        }
+        Ok(Self(Some(cipher_id.clone())))
+    }
+}
+
+impl Drop for CipherWriteGuard {
+    fn drop(&mut self) {
+        if let Some(cipher_id) = self.0.take()
+            && let Ok(mut writes) = CIPHER_WRITES.lock()
+        {
+            writes.remove(&cipher_id);
+        }
+    }
+}
+
 pub async fn update_cipher_from_data(
     cipher: &mut Cipher,
     data: CipherData,
@@ -401,6 +865,114 @@ pub async fn update_cipher_from_data(
     nt: &Notify<'_>,
     ut: UpdateType,
 ) -> EmptyResult {
+    let _write_guard = CipherWriteGuard::acquire(&cipher.uuid)?;
+
+    // Check that the client isn't updating an existing cipher with stale data.
+    // And only perform this check when not importing ciphers, else the date/time check will fail.
+    if ut != UpdateType::None
+        && let Some(dt) = &data.last_known_revision_date
+    {
+        match NaiveDateTime::parse_from_str(dt, "%+") {
+            // ISO 8601 format
+            Err(err) => warn!("Error parsing LastKnownRevisionDate '{dt}': {err}"),
+            Ok(dt) if cipher.updated_at.signed_duration_since(dt).num_seconds() > 1 => {
+                err!(
+                    "The client copy of this cipher is out of date. Resync the client and try again.",
+                    ErrorCode::CipherStale
+                )
+            }
+            Ok(_) => (),
+        }
+    }
+
+    let extras = apply_cipher_data(cipher, data, headers, shared_to_collections.is_some(), conn).await?;
+    enforce_reprompt_policy(cipher, shared_to_collections.as_deref(), conn).await;
+
+    cipher.save(conn).await?;
+    cipher.move_to_folder(extras.folder_id, &headers.user.uuid, conn).await?;
+    save_cipher_user_data(cipher, extras.favorite, extras.archived_date, &headers.user.uuid, conn).await?;
+
+    if ut != UpdateType::None {
+        // Only log events for organizational ciphers
+        if let Some(org_id) = &cipher.organization_uuid {
+            let event_type = match (&ut, extras.transfer_cipher) {
+                (UpdateType::SyncCipherCreate, true) => EventType::CipherCreated,
+                (UpdateType::SyncCipherUpdate, true) => EventType::CipherShared,
+                (_, _) => EventType::CipherUpdated,
+            };
+
+            log_event(
+                event_type as i32,
+                &cipher.uuid,
+                org_id,
+                &headers.user.uuid,
+                headers.device.atype,
+                &headers.ip.ip,
+                conn,
+            )
+            .await;
+        }
+        nt.send_cipher_update(
+            ut,
+            cipher,
+            &cipher.update_users_revision(conn).await,
+            &headers.device,
+            shared_to_collections,
+            conn,
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// Requires the master password for the cipher when a reprompt policy of its organization applies to it.
+/// A cipher which is being shared isn't in its new collections yet, those are given instead.
+async fn enforce_reprompt_policy(cipher: &mut Cipher, collections: Option<&[CollectionId]>, conn: &DbConn) {
+    let Some(org_id) = &cipher.organization_uuid else {
+        return;
+    };
+    let Some(policy) = OrgPolicy::reprompt_policy(org_id, conn).await else {
+        return;
+    };
+
+    let applies = match collections {
+        Some(collections) => policy.applies_to(collections),
+        None => policy.applies_to(&CollectionCipher::find_collection_uuids_by_cipher(&cipher.uuid, conn).await),
+    };
+    if applies {
+        cipher.reprompt = Some(RepromptType::Password as i32);
+    }
+}
+
+/// Applies the reprompt policy after the collections of an existing cipher changed, the cipher is saved when it changes
+pub(super) async fn enforce_reprompt_policy_on_collections(cipher: &mut Cipher, conn: &DbConn) -> EmptyResult {
+    let reprompt = cipher.reprompt;
+    enforce_reprompt_policy(cipher, None, conn).await;
+    if cipher.reprompt != reprompt {
+        cipher.save(conn).await?;
+    }
+    Ok(())
+}
+
+// The parts of the cipher data which aren't stored in the cipher itself
+struct CipherExtras {
+    folder_id: Option<FolderId>,
+    favorite: Option<bool>,
+    archived_date: Option<String>,
+    // The cipher is being transferred from a personal to an organization vault
+    transfer_cipher: bool,
+}
+
+/// Validates the cipher data and applies it to the cipher, without saving the cipher itself.
+async fn apply_cipher_data(
+    cipher: &mut Cipher,
+    data: CipherData,
+    headers: &Headers,
+    shared_to_collections: bool,
+    conn: &DbConn,
+) -> Result<CipherExtras, Error> {
+    data.check_strict()?;
+
     // Cleanup cipher data, like removing the 'Response' key.
     // This key is somewhere generated during Javascript so no way for us this fix this.
     // Also, upstream only retrieves keys they actually want to store, and thus skip the 'Response' key.
@@ -417,21 +989,6 @@ pub async fn update_cipher_from_data(
 
     enforce_personal_ownership_policy(Some(&data), headers, conn).await?;
 
-    // Check that the client isn't updating an existing cipher with stale data.
-    // And only perform this check when not importing ciphers, else the date/time check will fail.
-    if ut != UpdateType::None
-        && let Some(dt) = data.last_known_revision_date
-    {
-        match NaiveDateTime::parse_from_str(&dt, "%+") {
-            // ISO 8601 format
-            Err(err) => warn!("Error parsing LastKnownRevisionDate '{dt}': {err}"),
-            Ok(dt) if cipher.updated_at.signed_duration_since(dt).num_seconds() > 1 => {
-                err!("The client copy of this cipher is out of date. Resync the client and try again.")
-            }
-            Ok(_) => (),
-        }
-    }
-
     if cipher.organization_uuid.is_some() && cipher.organization_uuid != data.organization_id {
         err!("Organization mismatch. Please resync the client before updating the cipher")
     }
@@ -445,12 +1002,15 @@ pub async fn update_cipher_from_data(
 
     // Check if this cipher is being transferred from a personal to an organization vault
     let transfer_cipher = cipher.organization_uuid.is_none() && data.organization_id.is_some();
+    if transfer_cipher {
+        enforce_cipher_templates(&data, conn).await?;
+    }
 
     if let Some(org_id) = data.organization_id {
         match Membership::find_confirmed_by_user_and_org(&headers.user.uuid, &org_id, conn).await {
             None => err!("You don't have permission to add item to organization"),
             Some(member) => {
-                if shared_to_collections.is_some()
+                if shared_to_collections
                     || member.has_full_access()
                     || cipher.is_write_accessible_to_user(&headers.user.uuid, conn).await
                 {
@@ -531,46 +1091,47 @@ pub async fn update_cipher_from_data(
     cipher.password_history = data.password_history.map(|f| f.to_string());
     cipher.reprompt = data.reprompt.filter(|r| *r == RepromptType::None as i32 || *r == RepromptType::Password as i32);
 
-    cipher.save(conn).await?;
-    cipher.move_to_folder(data.folder_id, &headers.user.uuid, conn).await?;
-    cipher.set_favorite(data.favorite, &headers.user.uuid, conn).await?;
-
-    if let Some(dt_str) = data.archived_date {
-        match NaiveDateTime::parse_from_str(&dt_str, "%+") {
-            Ok(dt) => cipher.set_archived_at(dt, &headers.user.uuid, conn).await?,
-            Err(err) => warn!("Error parsing ArchivedDate '{dt_str}': {err}"),
+    if let Some(external_id) = data.external_id {
+        let external_id = external_id.trim();
+        if external_id.chars().count() > 300 {
+            err!("The external ID can't be longer than 300 characters")
+        }
+        cipher.external_id = Some(external_id.to_owned()).filter(|e| !e.is_empty());
+    }
+    // Checked for every save, moving an item to an organization changes the owner the id needs to be unique for
+    if let Some(external_id) = &cipher.external_id {
+        let existing = match (&cipher.organization_uuid, &cipher.user_uuid) {
+            (Some(org_id), _) => Cipher::find_by_org_and_external_id(org_id, external_id, conn).await,
+            (None, Some(user_id)) => Cipher::find_by_user_and_external_id(user_id, external_id, conn).await,
+            (None, None) => None,
+        };
+        if existing.is_some_and(|c| c.uuid != cipher.uuid) {
+            err!("An item with this external ID already exists")
         }
     }
 
-    if ut != UpdateType::None {
-        // Only log events for organizational ciphers
-        if let Some(org_id) = &cipher.organization_uuid {
-            let event_type = match (&ut, transfer_cipher) {
-                (UpdateType::SyncCipherCreate, true) => EventType::CipherCreated,
-                (UpdateType::SyncCipherUpdate, true) => EventType::CipherShared,
-                (_, _) => EventType::CipherUpdated,
-            };
+    Ok(CipherExtras {
+        folder_id: data.folder_id,
+        favorite: data.favorite,
+        archived_date: data.archived_date,
+        transfer_cipher,
+    })
+}
 
-            log_event(
-                event_type as i32,
-                &cipher.uuid,
-                org_id,
-                &headers.user.uuid,
-                headers.device.atype,
-                &headers.ip.ip,
-                conn,
-            )
-            .await;
+async fn save_cipher_user_data(
+    cipher: &Cipher,
+    favorite: Option<bool>,
+    archived_date: Option<String>,
+    user_id: &UserId,
+    conn: &DbConn,
+) -> EmptyResult {
+    cipher.set_favorite(favorite, user_id, conn).await?;
+
+    if let Some(dt_str) = archived_date {
+        match NaiveDateTime::parse_from_str(&dt_str, "%+") {
+            Ok(dt) => cipher.set_archived_at(dt, user_id, conn).await?,
+            Err(err) => warn!("Error parsing ArchivedDate '{dt_str}': {err}"),
         }
-        nt.send_cipher_update(
-            ut,
-            cipher,
-            &cipher.update_users_revision(conn).await,
-            &headers.device,
-            shared_to_collections,
-            conn,
-        )
-        .await;
     }
     Ok(())
 }
@@ -603,39 +1164,64 @@ async fn post_ciphers_import(data: Json<ImportData>, headers: Headers, conn: DbC
     // Since we check for the size of the encrypted note length, we need to do that here to pre-validate it.
     // TODO: See if we can optimize the whole cipher adding/importing and prevent duplicate code and checks.
     Cipher::validate_cipher_data(&data.ciphers)?;
+    let quota_charge = enforce_item_quota(&headers.user, data.ciphers.len(), true, &conn).await?;
+
+    // Everything is imported in one transaction, a failure halfway leaves the vault as it was
+    conn.transaction(async |conn| {
+        // Read and create the folders
+        let existing_folders: HashSet<Option<FolderId>> =
+            Folder::find_by_user(&headers.user.uuid, conn).await.into_iter().map(|f| Some(f.uuid)).collect();
+        let mut folders: Vec<FolderId> = Vec::with_capacity(data.folders.len());
+        for folder in data.folders {
+            let folder_id = if existing_folders.contains(&folder.id) {
+                folder.id.unwrap()
+            } else {
+                let mut new_folder = Folder::new(headers.user.uuid.clone(), folder.name);
+                new_folder.save(conn).await?;
+                new_folder.uuid
+            };
 
-    // Read and create the folders
-    let existing_folders: HashSet<Option<FolderId>> =
-        Folder::find_by_user(&headers.user.uuid, &conn).await.into_iter().map(|f| Some(f.uuid)).collect();
-    let mut folders: Vec<FolderId> = Vec::with_capacity(data.folders.len());
-    for folder in data.folders {
-        let folder_id = if existing_folders.contains(&folder.id) {
-            folder.id.unwrap()
-        } else {
-            let mut new_folder = Folder::new(headers.user.uuid.clone(), folder.name);
-            new_folder.save(&conn).await?;
-            new_folder.uuid
-        };
+            folders.push(folder_id);
+        }
 
-        folders.push(folder_id);
-    }
+        // Read the relations between folders and ciphers
+        // Ciphers can only be in one folder at the same time
+        let mut relations_map = HashMap::with_capacity(data.folder_relationships.len());
+        for relation in data.folder_relationships {
+            relations_map.insert(relation.key, relation.value);
+        }
 
-    // Read the relations between folders and ciphers
-    // Ciphers can only be in one folder at the same time
-    let mut relations_map = HashMap::with_capacity(data.folder_relationships.len());
-    for relation in data.folder_relationships {
-        relations_map.insert(relation.key, relation.value);
-    }
+        // Read the ciphers, these are all stored at once afterwards
+        let mut ciphers = Vec::with_capacity(data.ciphers.len());
+        let mut folder_ciphers = Vec::with_capacity(relations_map.len());
+        let mut user_data = Vec::new();
+        for (index, mut cipher_data) in data.ciphers.into_iter().enumerate() {
+            // The folders are known to exist and belong to the user, no need to check them again for every cipher
+            cipher_data.folder_id = None;
 
-    // Read and create the ciphers
-    for (index, mut cipher_data) in data.ciphers.into_iter().enumerate() {
-        let folder_id = relations_map.get(&index).map(|i| folders[*i].clone());
-        cipher_data.folder_id = folder_id;
+            let mut cipher = Cipher::new(cipher_data.r#type, cipher_data.name.clone());
+            let extras = apply_cipher_data(&mut cipher, cipher_data, &headers, false, conn).await?;
 
-        let mut cipher = Cipher::new(cipher_data.r#type, cipher_data.name.clone());
-        update_cipher_from_data(&mut cipher, cipher_data, &headers, None, &conn, &nt, UpdateType::None).await?;
-    }
+            if let Some(i) = relations_map.get(&index) {
+                folder_ciphers.push(FolderCipher::new(folders[*i].clone(), cipher.uuid.clone()));
+            }
+            if extras.favorite == Some(true) || extras.archived_date.is_some() {
+                user_data.push((ciphers.len(), extras.favorite, extras.archived_date));
+            }
+            ciphers.push(cipher);
+        }
 
+        Cipher::insert_many(&mut ciphers, conn).await?;
+        FolderCipher::insert_many(&folder_ciphers, conn).await?;
+        for (index, favorite, archived_date) in user_data {
+            save_cipher_user_data(&ciphers[index], favorite, archived_date, &headers.user.uuid, conn).await?;
+        }
+        Ok(())
+    })
+    .await?;
+    quota_charge.keep();
+
+    // The revision of the user is only updated once, instead of for every cipher
     let mut user = headers.user;
     user.update_revision(&conn).await?;
     nt.send_user_update(UpdateType::SyncVault, &user, headers.device.push_uuid.as_ref(), &conn).await;
@@ -691,13 +1277,40 @@ async fn put_cipher(
         err!("Cipher doesn't exist")
     };
 
-    // TODO: Check if only the folder ID or favorite status is being changed.
-    // These are per-user properties that technically aren't part of the
-    // cipher itself, so the user shouldn't need write access to change these.
-    // Interestingly, upstream Bitwarden doesn't properly handle this either.
-
+    // The folder and favorite status are per-user properties that aren't part of the cipher itself,
+    // so members with read-only access can still change these, like with `put_cipher_partial`.
+    // The clients encrypt all fields again on every save, so an edit of those can't be detected.
+    // Only a save of the current version with the same item key which moves the item or changes its favorite
+    // status is accepted then, anything else is refused instead of silently dropping the edit.
     if !cipher.is_write_accessible_to_user(&headers.user.uuid, &conn).await {
-        err!("Cipher is not write accessible")
+        if !cipher.is_accessible_to_user(&headers.user.uuid, &conn).await {
+            err!("Cipher is not write accessible")
+        }
+        let reprompt = |r: Option<i32>| r.unwrap_or(RepromptType::None as i32);
+        let is_current = data
+            .last_known_revision_date
+            .as_deref()
+            .and_then(|dt| NaiveDateTime::parse_from_str(dt, "%+").ok())
+            .is_some_and(|dt| cipher.updated_at.signed_duration_since(dt).num_seconds() <= 1);
+        if !is_current
+            || data.key != cipher.key
+            || data.r#type != cipher.atype
+            || data.organization_id != cipher.organization_uuid
+            || reprompt(data.reprompt) != reprompt(cipher.reprompt)
+            || data.attachments2.is_some()
+        {
+            err!("Cipher is not write accessible")
+        }
+
+        let moved = data.folder_id != cipher.get_folder_uuid(&headers.user.uuid, &conn).await;
+        let favorite_changed = match data.favorite {
+            Some(favorite) => favorite != cipher.is_favorite(&headers.user.uuid, &conn).await,
+            None => false,
+        };
+        if !moved && !favorite_changed {
+            err!("Cipher is not write accessible", "Only the folder and favorite status can be changed")
+        }
+        return update_cipher_user_properties(&cipher, data.folder_id, data.favorite, &headers, &conn).await;
     }
 
     update_cipher_from_data(&mut cipher, data, &headers, None, &conn, &nt, UpdateType::SyncCipherUpdate).await?;
@@ -733,18 +1346,30 @@ async fn put_cipher_partial(
         err!("Cipher does not exist", "Cipher is not accessible for the current user")
     }
 
-    if let Some(ref folder_id) = data.folder_id
-        && Folder::find_by_uuid_and_user(folder_id, &headers.user.uuid, &conn).await.is_none()
+    update_cipher_user_properties(&cipher, data.folder_id, Some(data.favorite), &headers, &conn).await
+}
+
+/// Moves the cipher to a folder of the user and updates its favorite status, which only requires read access to the cipher
+async fn update_cipher_user_properties(
+    cipher: &Cipher,
+    folder_id: Option<FolderId>,
+    favorite: Option<bool>,
+    headers: &Headers,
+    conn: &DbConn,
+) -> JsonResult {
+    if let Some(ref folder_id) = folder_id
+        && Folder::find_by_uuid_and_user(folder_id, &headers.user.uuid, conn).await.is_none()
     {
         err!("Invalid folder", "Folder does not exist or belongs to another user");
     }
 
+    let _write_guard = CipherWriteGuard::acquire(&cipher.uuid)?;
     // Move cipher
-    cipher.move_to_folder(data.folder_id.clone(), &headers.user.uuid, &conn).await?;
+    cipher.move_to_folder(folder_id, &headers.user.uuid, conn).await?;
     // Update favorite
-    cipher.set_favorite(Some(data.favorite), &headers.user.uuid, &conn).await?;
+    cipher.set_favorite(favorite, &headers.user.uuid, conn).await?;
 
-    Ok(Json(cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, &conn).await?))
+    Ok(Json(cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, conn).await?))
 }
 
 #[derive(Deserialize)]
@@ -802,7 +1427,7 @@ async fn post_collections_update(
 ) -> JsonResult {
     let data: CollectionsAdminData = data.into_inner();
 
-    let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
+    let Some(mut cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
         err!("Cipher doesn't exist")
     };
 
@@ -810,7 +1435,7 @@ async fn post_collections_update(
         err!("Collection cannot be changed")
     }
 
-    let Some(ref org_uuid) = cipher.organization_uuid else {
+    let Some(org_uuid) = cipher.organization_uuid.clone() else {
         err!("Cipher is not owned by an organization")
     };
 
@@ -819,7 +1444,7 @@ async fn post_collections_update(
         HashSet::<CollectionId>::from_iter(cipher.get_collections(headers.user.uuid.clone(), &conn).await);
 
     for collection in posted_collections.symmetric_difference(&current_collections) {
-        match Collection::find_by_uuid_and_org(collection, org_uuid, &conn).await {
+        match Collection::find_by_uuid_and_org(collection, &org_uuid, &conn).await {
             None => err!("Invalid collection ID provided"),
             Some(collection) => {
                 if collection.is_writable_by_user(&headers.user.uuid, &conn).await {
@@ -836,6 +1461,7 @@ async fn post_collections_update(
             }
         }
     }
+    enforce_reprompt_policy_on_collections(&mut cipher, &conn).await?;
 
     nt.send_cipher_update(
         UpdateType::SyncCipherUpdate,
@@ -850,7 +1476,7 @@ async fn post_collections_update(
     log_event(
         EventType::CipherUpdatedCollections as i32,
         &cipher.uuid,
-        org_uuid,
+        &org_uuid,
         &headers.user.uuid,
         headers.device.atype,
         &headers.ip.ip,
@@ -882,7 +1508,7 @@ async fn post_collections_admin(
 ) -> EmptyResult {
     let data: CollectionsAdminData = data.into_inner();
 
-    let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
+    let Some(mut cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
         err!("Cipher doesn't exist")
     };
 
@@ -890,7 +1516,7 @@ async fn post_collections_admin(
         err!("Collection cannot be changed")
     }
 
-    let Some(ref org_uuid) = cipher.organization_uuid else {
+    let Some(org_uuid) = cipher.organization_uuid.clone() else {
         err!("Cipher is not owned by an organization")
     };
 
@@ -899,7 +1525,7 @@ async fn post_collections_admin(
         HashSet::<CollectionId>::from_iter(cipher.get_admin_collections(headers.user.uuid.clone(), &conn).await);
 
     for collection in posted_collections.symmetric_difference(&current_collections) {
-        match Collection::find_by_uuid_and_org(collection, org_uuid, &conn).await {
+        match Collection::find_by_uuid_and_org(collection, &org_uuid, &conn).await {
             None => err!("Invalid collection ID provided"),
             Some(collection) => {
                 if collection.is_writable_by_user(&headers.user.uuid, &conn).await {
@@ -916,6 +1542,7 @@ async fn post_collections_admin(
             }
         }
     }
+    enforce_reprompt_policy_on_collections(&mut cipher, &conn).await?;
 
     nt.send_cipher_update(
         UpdateType::SyncCipherUpdate,
@@ -930,7 +1557,7 @@ async fn post_collections_admin(
     log_event(
         EventType::CipherUpdatedCollections as i32,
         &cipher.uuid,
-        org_uuid,
+        &org_uuid,
         &headers.user.uuid,
         headers.device.atype,
         &headers.ip.ip,
@@ -948,6 +1575,10 @@ struct ShareCipherData {
     cipher: CipherData,
     #[serde(alias = "CollectionIds")]
     collection_ids: Vec<CollectionId>,
+
+    // Only checked with STRICT_REQUEST_PARSING enabled
+    #[serde(flatten)]
+    unknown_fields: Map<String, Value>,
 }
 
 #[post("/ciphers/<cipher_id>/share", data = "<data>")]
@@ -1010,6 +1641,7 @@ async fn put_cipher_share_selected(
         let mut shared_cipher_data = ShareCipherData {
             cipher,
             collection_ids: data.collection_ids.clone(),
+            unknown_fields: Map::new(),
         };
 
         if let Some(id) = shared_cipher_data.cipher.id.take() {
@@ -1033,6 +1665,10 @@ async fn share_cipher_by_uuid(
     nt: &Notify<'_>,
     override_ut: Option<UpdateType>,
 ) -> JsonResult {
+    if strict::enabled() {
+        strict::check_unknown_fields("share request", &data.unknown_fields, &[])?;
+    }
+
     let mut cipher = if let Some(cipher) = Cipher::find_by_uuid(cipher_id, conn).await {
         if cipher.is_write_accessible_to_user(&headers.user.uuid, conn).await {
             cipher
@@ -1104,6 +1740,80 @@ async fn get_attachment(
     }
 }
 
+// How long a download link stays valid when no expiration is requested, and the longest allowed
+const DOWNLOAD_LINK_DEFAULT_HOURS: i64 = 24;
+const DOWNLOAD_LINK_MAX_HOURS: i64 = 7 * 24;
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AttachmentDownloadLinkData {
+    expiration_hours: Option<i64>,
+}
+
+/// Creates a link to download a single attachment once, without an account.
+/// Unlike a Send the file isn't copied, the recipient gets the encrypted file as stored.
+/// The client has to pass the attachment key along itself, for example in the fragment of the link.
+#[post("/ciphers/<cipher_id>/attachment/<attachment_id>/download-link", data = "<data>")]
+async fn post_attachment_download_link(
+    cipher_id: CipherId,
+    attachment_id: AttachmentId,
+    data: Option<Json<AttachmentDownloadLinkData>>,
+    headers: Headers,
+    conn: DbConn,
+) -> JsonResult {
+    let data = data.map(Json::into_inner).unwrap_or_default();
+    let user_id = &headers.user.uuid;
+
+    // These links are a way to share files outside of the vault, so they follow the same rules as Sends
+    if !CONFIG.sends_allowed()
+        || OrgPolicy::is_applicable_to_user(user_id, OrgPolicyType::DisableSend, None, &conn).await
+    {
+        err!("Due to an Enterprise Policy, you are not allowed to share attachments.", ErrorCode::PolicyDisableSend)
+    }
+
+    let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
+        err!("Cipher doesn't exist")
+    };
+
+    // Only the owner of a personal item, or an admin of the organization owning it, may share its files
+    let is_owner = match &cipher.organization_uuid {
+        None => cipher.user_uuid.as_ref() == Some(user_id),
+        Some(org_id) => Membership::find_confirmed_by_user_and_org(user_id, org_id, &conn)
+            .await
+            .is_some_and(|member| member.atype >= MembershipType::Admin),
+    };
+    if !is_owner {
+        err!("Only the owner of an item can share its attachments")
+    }
+    // An admin can only share the files of the items the admin console shows them
+    if let Some(org_id) = &cipher.organization_uuid {
+        check_org_cipher_access(&cipher, org_id, user_id, &conn).await?;
+    }
+
+    let attachment = match Attachment::find_by_id(&attachment_id, &conn).await {
+        Some(attachment) if cipher_id == attachment.cipher_uuid => attachment,
+        Some(_) => err!("Attachment doesn't belong to cipher"),
+        None => err!("Attachment doesn't exist"),
+    };
+
+    let hours = data.expiration_hours.unwrap_or(DOWNLOAD_LINK_DEFAULT_HOURS);
+    if !(1..=DOWNLOAD_LINK_MAX_HOURS).contains(&hours) {
+        err!(format!("The expiration has to be between 1 and {DOWNLOAD_LINK_MAX_HOURS} hours"))
+    }
+
+    AttachmentShare::delete_expired(&conn).await?;
+    let (share, token) =
+        AttachmentShare::new(attachment.id, cipher.uuid, user_id.clone(), chrono::TimeDelta::hours(hours));
+    share.save(&conn).await?;
+
+    info!(
+        "User {} created a download link for attachment {} of cipher {}, valid until {}",
+        user_id, share.attachment_id, share.cipher_uuid, share.expires_at
+    );
+
+    Ok(Json(share.to_json(&format!("{}/attachments/share/{token}", headers.host))))
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct AttachmentRequestData {
@@ -1136,6 +1846,7 @@ async fn post_attachment_v2(
     if !cipher.is_write_accessible_to_user(&headers.user.uuid, &conn).await {
         err!("Cipher is not write accessible")
     }
+    enforce_attachment_policy(&cipher, &headers, &conn).await?;
 
     let data: AttachmentRequestData = data.into_inner();
     let file_size = data.file_size.into_i64()?;
@@ -1143,24 +1854,72 @@ async fn post_attachment_v2(
     if file_size < 0 {
         err!("Attachment size can't be negative")
     }
-    let attachment_id = crypto::generate_attachment_id();
-    let attachment =
-        Attachment::new(attachment_id.clone(), cipher.uuid.clone(), data.file_name, file_size, Some(data.key));
-    attachment.save(&conn).await.expect("Error saving attachment");
-
-    let url = format!("/ciphers/{}/attachment/{attachment_id}", cipher.uuid);
-    let response_key = match data.admin_request {
-        Some(b) if b => "cipherMiniResponse",
-        _ => "cipherResponse",
-    };
-
-    Ok(Json(json!({ // AttachmentUploadDataResponseModel
-        "object": "attachment-fileUpload",
-        "attachmentId": attachment_id,
-        "url": url,
-        "fileUploadType": FileUploadType::Direct as i32,
-        response_key: cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, &conn).await?,
-    })))
+    let attachment_id = crypto::generate_attachment_id();
+    let attachment =
+        Attachment::new(attachment_id.clone(), cipher.uuid.clone(), data.file_name, file_size, Some(data.key));
+    attachment.save(&conn).await.expect("Error saving attachment");
+
+    let url = format!("/ciphers/{}/attachment/{attachment_id}", cipher.uuid);
+    let response_key = match data.admin_request {
+        Some(b) if b => "cipherMiniResponse",
+        _ => "cipherResponse",
+    };
+
+    Ok(Json(json!({ // AttachmentUploadDataResponseModel
+        "object": "attachment-fileUpload",
+        "attachmentId": attachment_id,
+        "url": url,
+        "fileUploadType": FileUploadType::Direct as i32,
+        response_key: cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, &conn).await?,
+    })))
+}
+
+/// Organizations enforcing their templates only accept new items with the required custom fields of one of the templates of their type.
+/// This applies to the items created in or moved to the organization, not to updates of existing items.
+async fn enforce_cipher_templates(data: &CipherData, conn: &DbConn) -> EmptyResult {
+    let Some(org_id) = &data.organization_id else {
+        return Ok(());
+    };
+    if !Organization::find_by_uuid(org_id, conn).await.is_some_and(|org| org.enforce_cipher_templates) {
+        return Ok(());
+    }
+    let templates = CipherTemplate::find_by_org_and_type(org_id, data.r#type, conn).await;
+    if templates.is_empty() {
+        return Ok(());
+    }
+
+    let fields = data.fields.as_ref().and_then(Value::as_array).map_or(&[][..], Vec::as_slice);
+    if !templates.iter().any(|t| t.is_satisfied_by(fields)) {
+        err!(
+            "This organization requires the custom fields of one of its item templates.",
+            ErrorCode::CipherTemplateFields
+        )
+    }
+    Ok(())
+}
+
+/// The BlockAttachments policy of the organization owning the cipher can forbid attachments on its type,
+/// a refused attachment is logged as an event of the organization
+async fn enforce_attachment_policy(cipher: &Cipher, headers: &Headers, conn: &DbConn) -> EmptyResult {
+    if let Some(ref org_id) = cipher.organization_uuid
+        && OrgPolicy::is_attachment_blocked(org_id, cipher.atype, conn).await
+    {
+        log_event(
+            EventType::CipherAttachmentBlocked as i32,
+            &cipher.uuid,
+            org_id,
+            &headers.user.uuid,
+            headers.device.atype,
+            &headers.ip.ip,
+            conn,
+        )
+        .await;
+        err!(
+            "Due to an Enterprise Policy, attachments can't be added to this type of item.",
+            ErrorCode::PolicyBlockAttachments
+        )
+    }
+    Ok(())
 }
 
 #[derive(FromForm)]
@@ -1184,7 +1943,7 @@ async fn save_attachment(
     headers: &Headers,
     conn: DbConn,
     nt: Notify<'_>,
-) -> Result<(Cipher, DbConn), crate::error::Error> {
+) -> Result<(Cipher, DbConn), Error> {
     let data = data.into_inner();
 
     let Some(size) = data.data.len().to_i64() else {
@@ -1201,6 +1960,7 @@ async fn save_attachment(
     if !cipher.is_write_accessible_to_user(&headers.user.uuid, &conn).await {
         err!("Cipher is not write accessible")
     }
+    enforce_attachment_policy(&cipher, headers, &conn).await?;
 
     // In the v2 API, the attachment record has already been created,
     // so the size limit needs to be adjusted to account for that.
@@ -1208,12 +1968,50 @@ async fn save_attachment(
         None => 0,              // Legacy API
         Some(a) => a.file_size, // v2 API
     };
+    check_attachment_size_limit(&cipher, size, size_adjust, &conn).await?;
+
+    let file_id = match &attachment {
+        Some(attachment) => attachment.id.clone(), // v2 API
+        None => crypto::generate_attachment_id(),  // Legacy API
+    };
+
+    if let Some(attachment) = &mut attachment {
+        // v2 API
+        check_attachment_declared_size(attachment, size, &conn).await?;
+    } else {
+        // Legacy API
+
+        // SAFETY: This value is only stored in the database and is not used to access the file system.
+        // As a result, the conditions specified by Rocket [0] are met and this is safe to use.
+        // [0]: https://docs.rs/rocket/latest/rocket/fs/struct.FileName.html#-danger-
+        let encrypted_filename = data.data.raw_name().map(|s| s.dangerous_unsafe_unsanitized_raw().to_string());
+
+        if encrypted_filename.is_none() {
+            err!("No filename provided")
+        }
+        if data.key.is_none() {
+            err!("No attachment key provided")
+        }
+        let attachment =
+            Attachment::new(file_id.clone(), cipher_id.clone(), encrypted_filename.unwrap(), size, data.key);
+        attachment.save(&conn).await.expect("Error saving attachment");
+    }
+
+    save_temp_file(&PathType::Attachments, &format!("{cipher_id}/{file_id}"), data.data, true).await?;
+
+    attachment_saved(&cipher, headers, &conn, &nt).await;
 
+    Ok((cipher, conn))
+}
+
+/// Checks the size of a new attachment against the `USER_ATTACHMENT_LIMIT` or `ORG_ATTACHMENT_LIMIT` of the owner of the cipher.
+/// `size_adjust` is the size already accounted for by the attachment record, if it exists.
+async fn check_attachment_size_limit(cipher: &Cipher, size: i64, size_adjust: i64, conn: &DbConn) -> EmptyResult {
     let size_limit = if let Some(ref user_id) = cipher.user_uuid {
         match CONFIG.user_attachment_limit() {
             Some(0) => err!("Attachments are disabled"),
             Some(limit_kb) => {
-                let already_used = Attachment::size_by_user(user_id, &conn).await;
+                let already_used = Attachment::size_by_user(user_id, conn).await;
                 let left = limit_kb
                     .checked_mul(1024)
                     .and_then(|l| l.checked_sub(already_used))
@@ -1224,7 +2022,10 @@ async fn save_attachment(
                 };
 
                 if left <= 0 {
-                    err!("Attachment storage limit reached! Delete some attachments to free up space")
+                    err!(
+                        "Attachment storage limit reached! Delete some attachments to free up space",
+                        ErrorCode::QuotaExceeded
+                    )
                 }
 
                 Some(left)
@@ -1235,7 +2036,7 @@ async fn save_attachment(
         match CONFIG.org_attachment_limit() {
             Some(0) => err!("Attachments are disabled"),
             Some(limit_kb) => {
-                let already_used = Attachment::size_by_org(org_id, &conn).await;
+                let already_used = Attachment::size_by_org(org_id, conn).await;
                 let left = limit_kb
                     .checked_mul(1024)
                     .and_then(|l| l.checked_sub(already_used))
@@ -1246,7 +2047,10 @@ async fn save_attachment(
                 };
 
                 if left <= 0 {
-                    err!("Attachment storage limit reached! Delete some attachments to free up space")
+                    err!(
+                        "Attachment storage limit reached! Delete some attachments to free up space",
+                        ErrorCode::QuotaExceeded
+                    )
                 }
 
                 Some(left)
@@ -1260,67 +2064,48 @@ async fn save_attachment(
     if let Some(size_limit) = size_limit
         && size > size_limit
     {
-        err!("Attachment storage limit exceeded with this file");
+        err!("Attachment storage limit exceeded with this file", ErrorCode::QuotaExceeded);
     }
+    Ok(())
+}
 
-    let file_id = match &attachment {
-        Some(attachment) => attachment.id.clone(), // v2 API
-        None => crypto::generate_attachment_id(),  // Legacy API
-    };
-
-    if let Some(attachment) = &mut attachment {
-        // v2 API
-
-        // Check the actual size against the size initially provided by
-        // the client. Upstream allows +/- 1 MiB deviation from this
-        // size, but it's not clear when or why this is needed.
-        const LEEWAY: i64 = 1024 * 1024; // 1 MiB
-        let Some(max_size) = attachment.file_size.checked_add(LEEWAY) else {
-            err!("Invalid attachment size max")
-        };
-        let Some(min_size) = attachment.file_size.checked_sub(LEEWAY) else {
-            err!("Invalid attachment size min")
-        };
+// Upstream allows +/- 1 MiB deviation from the size provided by the client
+// when creating a v2 attachment, but it's not clear when or why this is needed.
+const ATTACHMENT_SIZE_LEEWAY: i64 = 1024 * 1024; // 1 MiB
 
-        if min_size <= size && size <= max_size {
-            if size != attachment.file_size {
-                // Update the attachment with the actual file size.
-                attachment.file_size = size;
-                attachment.save(&conn).await.expect("Error updating attachment");
-            }
-        } else {
-            attachment.delete(&conn).await.ok();
+/// Checks the actual size of a v2 attachment against the size initially provided by the client,
+/// the attachment record is updated with the actual size or removed when it doesn't match.
+async fn check_attachment_declared_size(attachment: &mut Attachment, size: i64, conn: &DbConn) -> EmptyResult {
+    let Some(max_size) = attachment.file_size.checked_add(ATTACHMENT_SIZE_LEEWAY) else {
+        err!("Invalid attachment size max")
+    };
+    let Some(min_size) = attachment.file_size.checked_sub(ATTACHMENT_SIZE_LEEWAY) else {
+        err!("Invalid attachment size min")
+    };
 
-            err!(format!("Attachment size mismatch (expected within [{min_size}, {max_size}], got {size})"));
+    if min_size <= size && size <= max_size {
+        if size != attachment.file_size {
+            // Update the attachment with the actual file size.
+            attachment.file_size = size;
+            attachment.save(conn).await.expect("Error updating attachment");
         }
     } else {
-        // Legacy API
-
-        // SAFETY: This value is only stored in the database and is not used to access the file system.
-        // As a result, the conditions specified by Rocket [0] are met and this is safe to use.
-        // [0]: https://docs.rs/rocket/latest/rocket/fs/struct.FileName.html#-danger-
-        let encrypted_filename = data.data.raw_name().map(|s| s.dangerous_unsafe_unsanitized_raw().to_string());
+        attachment.delete(conn).await.ok();
 
-        if encrypted_filename.is_none() {
-            err!("No filename provided")
-        }
-        if data.key.is_none() {
-            err!("No attachment key provided")
-        }
-        let attachment =
-            Attachment::new(file_id.clone(), cipher_id.clone(), encrypted_filename.unwrap(), size, data.key);
-        attachment.save(&conn).await.expect("Error saving attachment");
+        err!(format!("Attachment size mismatch (expected within [{min_size}, {max_size}], got {size})"));
     }
+    Ok(())
+}
 
-    save_temp_file(&PathType::Attachments, &format!("{cipher_id}/{file_id}"), data.data, true).await?;
-
+/// Notifies the clients and logs the event once the data of a new attachment is stored
+async fn attachment_saved(cipher: &Cipher, headers: &Headers, conn: &DbConn, nt: &Notify<'_>) {
     nt.send_cipher_update(
         UpdateType::SyncCipherUpdate,
-        &cipher,
-        &cipher.update_users_revision(&conn).await,
+        cipher,
+        &cipher.update_users_revision(conn).await,
         &headers.device,
         None,
-        &conn,
+        conn,
     )
     .await;
 
@@ -1332,12 +2117,129 @@ async fn save_attachment(
             &headers.user.uuid,
             headers.device.atype,
             &headers.ip.ip,
-            &conn,
+            conn,
         )
         .await;
     }
 
-    Ok((cipher, conn))
+    check_attachment_quota(cipher, conn).await;
+}
+
+// The percentages of the attachment storage limit at which the user, or the owners of the organization, are warned
+const QUOTA_WARNING_LEVELS: [i32; 2] = [95, 80];
+
+pub async fn attachment_quota_warning_job(pool: DbPool) {
+    debug!("Checking the attachment storage quotas");
+    if CONFIG.user_attachment_limit().is_none() && CONFIG.org_attachment_limit().is_none() {
+        return;
+    }
+    let Ok(conn) = pool.get().await else {
+        error!("Failed to get DB connection while checking the attachment storage quotas");
+        return;
+    };
+
+    if CONFIG.user_attachment_limit().is_some() {
+        for (user, _) in User::get_all(&conn).await {
+            check_user_attachment_quota(&user, &conn).await;
+        }
+    }
+    if CONFIG.org_attachment_limit().is_some() {
+        for org in Organization::get_all(&conn).await {
+            check_org_attachment_quota(&org, &conn).await;
+        }
+    }
+}
+
+async fn check_attachment_quota(cipher: &Cipher, conn: &DbConn) {
+    if let Some(user_id) = &cipher.user_uuid
+        && let Some(user) = User::find_by_uuid(user_id, conn).await
+    {
+        check_user_attachment_quota(&user, conn).await;
+    } else if let Some(org_id) = &cipher.organization_uuid
+        && let Some(org) = Organization::find_by_uuid(org_id, conn).await
+    {
+        check_org_attachment_quota(&org, conn).await;
+    }
+}
+
+async fn check_user_attachment_quota(user: &User, conn: &DbConn) {
+    let used = Attachment::size_by_user(&user.uuid, conn).await;
+    let Some((level, limit)) =
+        new_quota_warning_level(user.uuid.as_ref(), used, CONFIG.user_attachment_limit(), conn).await
+    else {
+        return;
+    };
+    send_quota_warning(user, None, "your account", level, used, limit).await;
+}
+
+async fn check_org_attachment_quota(org: &Organization, conn: &DbConn) {
+    let used = Attachment::size_by_org(&org.uuid, conn).await;
+    let Some((level, limit)) =
+        new_quota_warning_level(org.uuid.as_ref(), used, CONFIG.org_attachment_limit(), conn).await
+    else {
+        return;
+    };
+    let owner = format!("the organization {}", org.name);
+    for member in Membership::find_by_org_and_type(&org.uuid, MembershipType::Owner, conn).await {
+        if let Some(user) = User::find_by_uuid(&member.user_uuid, conn).await {
+            send_quota_warning(&user, Some(&org.uuid), &owner, level, used, limit).await;
+        }
+    }
+}
+
+/// Returns the warning level the usage newly reached, together with the limit in bytes.
+/// The level is remembered so every level is only warned about once, a lower usage lowers it again.
+async fn new_quota_warning_level(
+    owner_uuid: &str,
+    used: i64,
+    limit_kb: Option<i64>,
+    conn: &DbConn,
+) -> Option<(i32, i64)> {
+    let limit = limit_kb.filter(|l| *l > 0)?.saturating_mul(1024);
+    let percent = used.saturating_mul(100) / limit;
+    let level = QUOTA_WARNING_LEVELS.into_iter().find(|l| percent >= i64::from(*l)).unwrap_or(0);
+
+    let warned = QuotaWarning::find_level(owner_uuid, conn).await;
+    if level == warned {
+        return None;
+    }
+    if let Err(e) = QuotaWarning::set_level(owner_uuid, level, conn).await {
+        error!("Error saving the attachment quota warning of {owner_uuid}: {e:?}");
+        return None;
+    }
+    (level > warned).then_some((level, limit))
+}
+
+async fn send_quota_warning(
+    user: &User,
+    org_id: Option<&OrganizationId>,
+    owner: &str,
+    level: i32,
+    used: i64,
+    limit: i64,
+) {
+    let used = get_display_size(used);
+    let limit = get_display_size(limit);
+    // Only shown by the clients which negotiated version 2 of the websocket payload schema, the mail reaches the others
+    WS_USERS
+        .send_notification(
+            &user.uuid,
+            org_id,
+            "Attachment storage almost full",
+            &format!("The attachments of {owner} use more than {level}% of the available storage ({used} of {limit})."),
+        )
+        .await;
+
+    // The upload which crossed the level shouldn't wait for the SMTP server
+    if CONFIG.mail_enabled() {
+        let email = user.email.clone();
+        let owner = owner.to_owned();
+        tokio::spawn(async move {
+            if let Err(e) = mail::send_attachment_quota_warning(&email, &owner, level, &used, &limit).await {
+                error!("Error sending the attachment quota warning to {email}: {e:?}");
+            }
+        });
+    }
 }
 
 /// v2 API for uploading the actual data content of an attachment.
@@ -1364,6 +2266,240 @@ async fn post_attachment_v2_data(
     Ok(())
 }
 
+// Resumable uploads of the data of v2 attachments, for large files over unreliable connections.
+// Instead of the single multipart upload, the client sends the data in chunks with `PATCH .../upload`,
+// each with the `Upload-Offset` header set to the number of bytes already received, which `GET .../upload` returns.
+// Once all the data is sent, `POST .../upload` with its SHA-256 checks and stores the assembled file.
+// The partial data is kept in the temp folder, where the cleanup job removes abandoned uploads.
+// That folder belongs to the instance receiving the chunks, so when multiple instances share the database,
+// a lock in the database pins the upload to the instance holding its data until it is completed or abandoned.
+// The other instances refuse the upload, the reverse proxy needs to route all of its requests to the same instance.
+
+/// The `Upload-Offset` header of a chunk of a resumable upload
+struct UploadOffset(u64);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UploadOffset {
+    type Error = &'static str;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.headers().get_one("Upload-Offset").map(str::parse::<u64>) {
+            Some(Ok(offset)) => Outcome::Success(Self(offset)),
+            Some(Err(_)) => Outcome::Error((Status::BadRequest, "Upload-Offset value is not a valid number")),
+            None => Outcome::Error((Status::BadRequest, "Upload-Offset value is required")),
+        }
+    }
+}
+
+// Resumable uploads which are receiving a chunk or being completed right now
+static UPLOADS_IN_PROGRESS: LazyLock<Mutex<HashSet<AttachmentId>>> = LazyLock::new(Default::default);
+
+/// Makes sure the chunks of an upload are appended one at a time, a concurrent request gets a 409 instead
+struct UploadGuard(AttachmentId);
+
+impl UploadGuard {
+    fn acquire(attachment_id: &AttachmentId) -> Result<Self, Error> {
+        let Ok(mut uploads) = UPLOADS_IN_PROGRESS.lock() else {
+            err!("Unable to lock the upload")
+        };
+        if !uploads.insert(attachment_id.clone()) {
+            return Err(Error::new_msg("Another request is writing this upload, please try again.").with_code(409));
+        }
+        Ok(Self(attachment_id.clone()))
+    }
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        if let Ok(mut uploads) = UPLOADS_IN_PROGRESS.lock() {
+            uploads.remove(&self.0);
+        }
+    }
+}
+
+fn partial_upload_path(attachment_id: &AttachmentId) -> PathBuf {
+    Path::new(&CONFIG.tmp_folder()).join(format!("{attachment_id}.upload"))
+}
+
+fn upload_on_other_instance() -> Error {
+    Error::new_msg("This upload was started on another server instance and can only be continued there.").with_code(409)
+}
+
+/// Pins the upload to this instance for as long as its partial data is kept, and extends it with every chunk.
+/// An upload abandoned by another instance is taken over, starting again from the beginning.
+async fn claim_upload(attachment_id: &AttachmentId, conn: &DbConn) -> EmptyResult {
+    let lock_name = Attachment::upload_lock_name(attachment_id);
+    let ttl = CONFIG.tmp_cleanup_max_age();
+    if JobLock::renew(&lock_name, ttl, conn).await {
+        return Ok(());
+    }
+    if !JobLock::try_acquire(&lock_name, ttl, conn).await {
+        return Err(upload_on_other_instance());
+    }
+
+    // Data left over from an earlier claim of this instance may be missing the chunks received by another one
+    match tokio::fs::remove_file(partial_upload_path(attachment_id)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+async fn release_upload(attachment_id: &AttachmentId, conn: &DbConn) {
+    tokio::fs::remove_file(partial_upload_path(attachment_id)).await.ok();
+    if let Err(e) = JobLock::release(&Attachment::upload_lock_name(attachment_id), conn).await {
+        warn!("Unable to release the upload of attachment {attachment_id}: {e:#?}");
+    }
+}
+
+/// Returns the v2 attachment, and its cipher, whose data is still missing and can be uploaded in chunks
+async fn find_resumable_upload(
+    cipher_id: &CipherId,
+    attachment_id: &AttachmentId,
+    headers: &Headers,
+    conn: &DbConn,
+) -> Result<(Cipher, Attachment), Error> {
+    let attachment = match Attachment::find_by_id(attachment_id, conn).await {
+        Some(attachment) if *cipher_id == attachment.cipher_uuid => attachment,
+        Some(_) => err!("Attachment doesn't belong to cipher"),
+        None => err!("Attachment doesn't exist"),
+    };
+    let Some(cipher) = Cipher::find_by_uuid(cipher_id, conn).await else {
+        err!("Cipher doesn't exist")
+    };
+    if !cipher.is_write_accessible_to_user(&headers.user.uuid, conn).await {
+        err!("Cipher is not write accessible")
+    }
+    enforce_attachment_policy(&cipher, headers, conn).await?;
+
+    let backend = CONFIG.storage_backend(&PathType::Attachments)?;
+    if backend.stat(&format!("{cipher_id}/{attachment_id}")).await?.is_some() {
+        err!("The data of this attachment has already been uploaded")
+    }
+    Ok((cipher, attachment))
+}
+
+fn upload_json(attachment: &Attachment, offset: u64) -> Value {
+    json!({
+        "attachmentId": attachment.id,
+        "offset": offset,
+        "fileSize": attachment.file_size,
+        "object": "attachment-upload",
+    })
+}
+
+#[get("/ciphers/<cipher_id>/attachment/<attachment_id>/upload")]
+async fn get_attachment_upload(
+    cipher_id: CipherId,
+    attachment_id: AttachmentId,
+    headers: Headers,
+    conn: DbConn,
+) -> JsonResult {
+    let (_, attachment) = find_resumable_upload(&cipher_id, &attachment_id, &headers, &conn).await?;
+    let offset = match JobLock::find_active_by_name(&Attachment::upload_lock_name(&attachment.id), &conn).await {
+        Some(lock) if !lock.is_held_by_us() => return Err(upload_on_other_instance()),
+        Some(_) => tokio::fs::metadata(partial_upload_path(&attachment.id)).await.map_or(0, |m| m.len()),
+        None => 0,
+    };
+
+    Ok(Json(upload_json(&attachment, offset)))
+}
+
+#[patch("/ciphers/<cipher_id>/attachment/<attachment_id>/upload", data = "<data>")]
+async fn patch_attachment_upload(
+    cipher_id: CipherId,
+    attachment_id: AttachmentId,
+    offset: UploadOffset,
+    data: Data<'_>,
+    limits: &Limits,
+    headers: Headers,
+    conn: DbConn,
+) -> JsonResult {
+    use tokio::io::AsyncWriteExt as _;
+
+    let (_, attachment) = find_resumable_upload(&cipher_id, &attachment_id, &headers, &conn).await?;
+    let _upload_guard = UploadGuard::acquire(&attachment.id)?;
+    claim_upload(&attachment.id, &conn).await?;
+
+    let path = partial_upload_path(&attachment.id);
+    let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&path).await?;
+    let received = file.metadata().await?.len();
+    if offset.0 != received {
+        return Err(Error::new_msg(format!("The upload continues at offset {received}")).with_code(409));
+    }
+
+    // The data can't grow beyond the size given when the attachment was created
+    let max_size = attachment
+        .file_size
+        .checked_add(ATTACHMENT_SIZE_LEEWAY)
+        .and_then(|s| u64::try_from(s).ok())
+        .unwrap_or_default();
+    let left = max_size.saturating_sub(received);
+    let limit = limits.get("file").map_or(left, |l| l.as_u64().min(left));
+
+    let written = data.open(limit.bytes()).stream_to(&mut file).await?;
+    file.flush().await?;
+    if !written.complete {
+        file.set_len(received).await?;
+        err!("The chunk is too large, or the data exceeds the size of the attachment")
+    }
+
+    Ok(Json(upload_json(&attachment, received + written.written)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CompleteUploadData {
+    // Hex encoded SHA-256 of all the (encrypted) data of the attachment
+    sha256: String,
+}
+
+#[post("/ciphers/<cipher_id>/attachment/<attachment_id>/upload", data = "<data>")]
+async fn post_attachment_upload_complete(
+    cipher_id: CipherId,
+    attachment_id: AttachmentId,
+    data: Json<CompleteUploadData>,
+    headers: Headers,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    let (cipher, mut attachment) = find_resumable_upload(&cipher_id, &attachment_id, &headers, &conn).await?;
+    let _upload_guard = UploadGuard::acquire(&attachment.id)?;
+    match JobLock::find_active_by_name(&Attachment::upload_lock_name(&attachment.id), &conn).await {
+        Some(lock) if lock.is_held_by_us() => (),
+        Some(_) => return Err(upload_on_other_instance()),
+        None => err!("No data has been uploaded for this attachment"),
+    }
+
+    let path = partial_upload_path(&attachment.id);
+    let Ok(metadata) = tokio::fs::metadata(&path).await else {
+        err!("No data has been uploaded for this attachment")
+    };
+    let Some(size) = metadata.len().to_i64() else {
+        err!("Attachment data size overflow");
+    };
+
+    // A corrupted upload can't be fixed by sending more chunks, it needs to start over
+    let sha256 = crypto::sha256_hex_file(&path).await?;
+    if !crypto::ct_eq(&sha256, data.into_inner().sha256.to_lowercase()) {
+        release_upload(&attachment.id, &conn).await;
+        err!("The SHA-256 of the uploaded data doesn't match, the upload needs to be started again")
+    }
+
+    check_attachment_size_limit(&cipher, size, attachment.file_size, &conn).await?;
+    if let Err(e) = check_attachment_declared_size(&mut attachment, size, &conn).await {
+        release_upload(&attachment.id, &conn).await;
+        return Err(e);
+    }
+
+    let backend = CONFIG.storage_backend(&PathType::Attachments)?;
+    backend.put_local_file(&format!("{cipher_id}/{}", attachment.id), &path, true).await?;
+    release_upload(&attachment.id, &conn).await;
+
+    attachment_saved(&cipher, &headers, &conn, &nt).await;
+
+    Ok(Json(cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, &conn).await?))
+}
+
 /// Legacy API for creating an attachment associated with a cipher.
 #[post("/ciphers/<cipher_id>/attachment", format = "multipart/form-data", data = "<data>")]
 async fn post_attachment(
@@ -1450,6 +2586,69 @@ async fn delete_attachment_admin(
     delete_cipher_attachment_by_id(&cipher_id, &attachment_id, &headers, &conn, &nt).await
 }
 
+// The attachments which still need to be re-encrypted after the last key rotation of the user
+#[get("/ciphers/attachments/rekey")]
+async fn get_attachment_rekeys(headers: Headers, conn: DbConn) -> Json<Value> {
+    let rekeys = AttachmentRekey::find_by_user(&headers.user.uuid, &conn).await;
+    let replaced = rekeys.iter().filter(|r| r.replaced_by.is_some()).count();
+    let verified = rekeys.iter().filter(|r| r.verified_at.is_some()).count();
+
+    Json(json!({
+        "total": rekeys.len(),
+        "replaced": replaced,
+        "verified": verified,
+        "remaining": rekeys.len() - replaced,
+        "data": rekeys.iter().map(AttachmentRekey::to_json).collect::<Vec<Value>>(),
+        "object": "attachmentRekeyStatus",
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AttachmentRekeyData {
+    attachment_id: AttachmentId,
+}
+
+// Called by the client after it uploaded a re-encrypted copy of an attachment as a new attachment of the same cipher.
+// The old attachment is removed, the upload of the new one is confirmed by the `attachment_rekey_verification` job.
+#[post("/ciphers/<cipher_id>/attachment/<attachment_id>/rekey", data = "<data>")]
+async fn post_attachment_rekey(
+    cipher_id: CipherId,
+    attachment_id: AttachmentId,
+    data: Json<AttachmentRekeyData>,
+    headers: Headers,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    let Some(mut rekey) = AttachmentRekey::find_by_attachment_and_user(&attachment_id, &headers.user.uuid, &conn).await
+    else {
+        err!("Attachment doesn't need to be re-encrypted")
+    };
+    if rekey.cipher_uuid != cipher_id {
+        err!("Attachment from other cipher")
+    }
+    if rekey.replaced_by.is_some() {
+        err!("Attachment has already been replaced")
+    }
+
+    let replacement_id = data.into_inner().attachment_id;
+    let Some(replacement) = Attachment::find_by_id(&replacement_id, &conn).await else {
+        err!("Replacement attachment doesn't exist")
+    };
+    if replacement.cipher_uuid != cipher_id || replacement.id == attachment_id {
+        err!("Invalid replacement attachment")
+    }
+    if replacement.akey.is_none() {
+        err!("The replacement attachment needs its own key")
+    }
+
+    delete_cipher_attachment_by_id(&cipher_id, &attachment_id, &headers, &conn, &nt).await?;
+
+    rekey.replaced_by = Some(replacement_id);
+    rekey.save(&conn).await?;
+    Ok(Json(rekey.to_json()))
+}
+
 #[post("/ciphers/<cipher_id>/delete")]
 async fn delete_cipher_post(cipher_id: CipherId, headers: Headers, conn: DbConn, nt: Notify<'_>) -> EmptyResult {
     delete_cipher_by_uuid(&cipher_id, &headers, &conn, &CipherDeleteOptions::HardSingle, &nt).await
@@ -1778,12 +2977,13 @@ async fn delete_cipher_by_uuid(
         err!("Cipher doesn't exist")
     };
 
-    if !cipher.is_write_accessible_to_user(&headers.user.uuid, conn).await {
+    if !cipher.is_deletable_by_user(&headers.user.uuid, conn).await {
         err!("Cipher can't be deleted by user")
     }
 
     if *delete_options == CipherDeleteOptions::SoftSingle || *delete_options == CipherDeleteOptions::SoftMulti {
         cipher.deleted_at = Some(Utc::now().naive_utc());
+        cipher.deleted_by = Some(headers.user.uuid.clone());
         cipher.save(conn).await?;
         if *delete_options == CipherDeleteOptions::SoftSingle {
             nt.send_cipher_update(
@@ -1870,6 +3070,7 @@ async fn restore_cipher_by_uuid(
     }
 
     cipher.deleted_at = None;
+    cipher.deleted_by = None;
     cipher.save(conn).await?;
 
     if !multi_restore {
@@ -2104,9 +3305,11 @@ pub struct CipherSyncData {
     pub cipher_collections: HashMap<CipherId, Vec<CollectionId>>,
     pub cipher_archives: HashMap<CipherId, NaiveDateTime>,
     pub members: HashMap<OrganizationId, Membership>,
+    pub organizations: HashMap<OrganizationId, Organization>,
     pub user_collections: HashMap<CollectionId, CollectionUser>,
     pub user_collections_groups: HashMap<CollectionId, CollectionGroup>,
     pub user_group_full_access_for_organizations: HashSet<OrganizationId>,
+    pub confidential_ciphers: HashSet<CipherId>,
 }
 
 #[derive(Eq, PartialEq)]
@@ -2164,6 +3367,14 @@ impl CipherSyncData {
             .map(|m| (m.org_uuid.clone(), m))
             .collect();
 
+        // Generate a HashMap with the Organization UUID as key and the Organization record, for its collection management settings
+        let mut organizations: HashMap<OrganizationId, Organization> = HashMap::with_capacity(members.len());
+        for org_id in members.keys() {
+            if let Some(org) = Organization::find_by_uuid(org_id, conn).await {
+                organizations.insert(org_id.clone(), org);
+            }
+        }
+
         // Generate a HashMap with the User_Collections UUID as key and the CollectionUser record
         let user_collections: HashMap<CollectionId, CollectionUser> = CollectionUser::find_by_user(user_id, conn)
             .await
@@ -2199,6 +3410,9 @@ impl CipherSyncData {
             HashSet::new()
         };
 
+        // Get all the ciphers in confidential collections, full access to the organization doesn't include these
+        let confidential_ciphers = Cipher::find_confidential_uuids_by_user(user_id, conn).await;
+
         Self {
             cipher_attachments,
             cipher_folders,
@@ -2206,9 +3420,11 @@ impl CipherSyncData {
             cipher_collections,
             cipher_archives,
             members,
+            organizations,
             user_collections,
             user_collections_groups,
             user_group_full_access_for_organizations,
+            confidential_ciphers,
         }
     }
 }
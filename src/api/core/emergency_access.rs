@@ -1,19 +1,19 @@
 use chrono::{TimeDelta, Utc};
-use rocket::{Route, serde::json::Json};
+use rocket::{Route, form::FromForm, serde::json::Json};
 use serde_json::Value;
 
 use crate::{
     CONFIG,
     api::{
-        EmptyResult, JsonResult,
+        ApiResult, EmptyResult, JsonResult,
         core::{CipherSyncData, CipherSyncType},
     },
     auth::{Headers, decode_emergency_access_invite},
     db::{
         DbConn, DbPool,
         models::{
-            Cipher, EmergencyAccess, EmergencyAccessId, EmergencyAccessStatus, EmergencyAccessType, Invitation,
-            Membership, MembershipType, OrgPolicy, TwoFactor, User, UserId,
+            Attachment, AttachmentId, Cipher, CipherId, EmergencyAccess, EmergencyAccessId, EmergencyAccessStatus,
+            EmergencyAccessType, Invitation, Membership, MembershipType, OrgPolicy, TwoFactor, User, UserId,
         },
     },
     mail,
@@ -39,6 +39,8 @@ pub fn routes() -> Vec<Route> {
         takeover_emergency_access,
         password_emergency_access,
         view_emergency_access,
+        view_emergency_access_ciphers,
+        view_emergency_access_attachment,
         policies_emergency_access,
     ]
 }
@@ -222,7 +224,7 @@ async fn send_invite(data: Json<EmergencyAccessInviteData>, headers: Headers, co
         err!("You can not set yourself as an emergency contact.")
     }
 
-    let (grantee_user, new_user) = match User::find_by_mail(&email, &conn).await {
+    let (grantee_user, new_user) = match User::find_by_mail_or_subaddress(&email, &conn).await {
         None => {
             if !CONFIG.invitations_allowed() {
                 err!(format!("Grantee user does not exist: {email}"))
@@ -238,9 +240,14 @@ async fn send_invite(data: Json<EmergencyAccessInviteData>, headers: Headers, co
             }
 
             let mut user = User::new(&email, None);
+            user.tenant_id.clone_from(&grantor_user.tenant_id);
             user.save(&conn).await?;
             (user, true)
         }
+        Some(user) if user.uuid == grantor_user.uuid => err!("You can not set yourself as an emergency contact."),
+        Some(user) if user.tenant_id != grantor_user.tenant_id => {
+            err!(format!("Grantee user belongs to another tenant: {email}"))
+        }
         Some(user) if user.password_hash.is_empty() => (user, true),
         Some(user) => (user, false),
     };
@@ -554,17 +561,7 @@ async fn reject_emergency_access(emer_id: EmergencyAccessId, headers: Headers, c
 
 #[post("/emergency-access/<emer_id>/view")]
 async fn view_emergency_access(emer_id: EmergencyAccessId, headers: Headers, conn: DbConn) -> JsonResult {
-    check_emergency_access_enabled()?;
-
-    let Some(emergency_access) =
-        EmergencyAccess::find_by_uuid_and_grantee_uuid(&emer_id, &headers.user.uuid, &conn).await
-    else {
-        err!("Emergency access not valid.")
-    };
-
-    if !is_valid_request(&emergency_access, &headers.user.uuid, EmergencyAccessType::View) {
-        err!("Emergency access not valid.")
-    }
+    let emergency_access = find_view_grant(&emer_id, &headers, &conn).await?;
 
     let ciphers = Cipher::find_owned_by_user(&emergency_access.grantor_uuid, &conn).await;
     let cipher_sync_data = CipherSyncData::new(&emergency_access.grantor_uuid, CipherSyncType::User, &conn).await;
@@ -590,6 +587,93 @@ async fn view_emergency_access(emer_id: EmergencyAccessId, headers: Headers, con
     })))
 }
 
+// Number of ciphers returned per page when browsing the vault of the grantor
+const VIEW_PAGE_SIZE: i64 = 100;
+
+#[derive(FromForm)]
+struct ViewPage {
+    #[field(name = "continuationToken")]
+    continuation_token: Option<CipherId>,
+}
+
+/// Lists the ciphers of the grantor page by page, instead of all at once like `view_emergency_access`.
+/// The `continuationToken` of the response needs to be passed to get the next page.
+#[get("/emergency-access/<emer_id>/ciphers?<page..>")]
+async fn view_emergency_access_ciphers(
+    emer_id: EmergencyAccessId,
+    page: ViewPage,
+    headers: Headers,
+    conn: DbConn,
+) -> JsonResult {
+    let emergency_access = find_view_grant(&emer_id, &headers, &conn).await?;
+    let grantor_id = &emergency_access.grantor_uuid;
+
+    let ciphers =
+        Cipher::find_owned_by_user_page(grantor_id, page.continuation_token.as_ref(), VIEW_PAGE_SIZE, &conn).await;
+    let cipher_sync_data = CipherSyncData::new(grantor_id, CipherSyncType::User, &conn).await;
+
+    // When a full page is returned, there probably are more ciphers
+    let continuation_token = match ciphers.last() {
+        Some(last) if i64::try_from(ciphers.len()) == Ok(VIEW_PAGE_SIZE) => Some(last.uuid.clone()),
+        _ => None,
+    };
+
+    let mut ciphers_json = Vec::with_capacity(ciphers.len());
+    for c in ciphers {
+        ciphers_json
+            .push(c.to_json(&headers.host, grantor_id, Some(&cipher_sync_data), CipherSyncType::User, &conn).await?);
+    }
+
+    Ok(Json(json!({
+        "data": ciphers_json,
+        "keyEncrypted": &emergency_access.key_encrypted,
+        "object": "list",
+        "continuationToken": continuation_token,
+    })))
+}
+
+/// Returns the download information of an attachment of the grantor.
+/// Only attachments of ciphers owned by the grantor of this emergency access can be downloaded.
+#[get("/emergency-access/<emer_id>/<cipher_id>/attachment/<attachment_id>")]
+async fn view_emergency_access_attachment(
+    emer_id: EmergencyAccessId,
+    cipher_id: CipherId,
+    attachment_id: AttachmentId,
+    headers: Headers,
+    conn: DbConn,
+) -> JsonResult {
+    let emergency_access = find_view_grant(&emer_id, &headers, &conn).await?;
+
+    let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
+        err!("Cipher doesn't exist")
+    };
+    if cipher.user_uuid.as_ref() != Some(&emergency_access.grantor_uuid) || cipher.organization_uuid.is_some() {
+        err!("Cipher is not accessible")
+    }
+
+    match Attachment::find_by_id(&attachment_id, &conn).await {
+        Some(attachment) if cipher_id == attachment.cipher_uuid => Ok(Json(attachment.to_json(&headers.host).await?)),
+        Some(_) => err!("Attachment doesn't belong to cipher"),
+        None => err!("Attachment doesn't exist"),
+    }
+}
+
+/// Returns the emergency access if it grants the requesting user view access to the vault of the grantor
+async fn find_view_grant(emer_id: &EmergencyAccessId, headers: &Headers, conn: &DbConn) -> ApiResult<EmergencyAccess> {
+    check_emergency_access_enabled()?;
+
+    let Some(emergency_access) =
+        EmergencyAccess::find_by_uuid_and_grantee_uuid(emer_id, &headers.user.uuid, conn).await
+    else {
+        err!("Emergency access not valid.")
+    };
+
+    if !is_valid_request(&emergency_access, &headers.user.uuid, EmergencyAccessType::View) {
+        err!("Emergency access not valid.")
+    }
+    Ok(emergency_access)
+}
+
 #[post("/emergency-access/<emer_id>/takeover")]
 async fn takeover_emergency_access(emer_id: EmergencyAccessId, headers: Headers, conn: DbConn) -> JsonResult {
     check_emergency_access_enabled()?;
@@ -692,7 +776,8 @@ async fn policies_emergency_access(emer_id: EmergencyAccessId, headers: Headers,
     };
 
     let policies = OrgPolicy::find_confirmed_by_user(&grantor_user.uuid, &conn);
-    let policies_json: Vec<Value> = policies.await.iter().map(OrgPolicy::to_json).collect();
+    let policies_json: Vec<Value> =
+        policies.await.iter().filter(|p| p.is_known_to_clients()).map(OrgPolicy::to_json).collect();
 
     Ok(Json(json!({
         "data": policies_json,
@@ -1,6 +1,6 @@
 use std::net::IpAddr;
 
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, TimeDelta, Utc};
 use rocket::{Route, form::FromForm, serde::json::Json};
 use serde_json::Value;
 
@@ -10,15 +10,16 @@ use crate::{
     auth::{AdminHeaders, Headers},
     db::{
         DbConn, DbPool,
-        models::{Cipher, CipherId, Event, Membership, MembershipId, OrganizationId, UserId},
+        models::{Cipher, CipherId, Event, EventActivity, Membership, MembershipId, OrganizationId, UserId},
     },
     util::parse_date,
+    webhook,
 };
 
 /// ###############################################################################################################
 /// /api routes
 pub fn routes() -> Vec<Route> {
-    routes![get_org_events, get_cipher_events, get_user_events,]
+    routes![get_org_events, get_org_event_activity, get_cipher_events, get_user_events,]
 }
 
 #[derive(FromForm)]
@@ -62,6 +63,46 @@ async fn get_org_events(org_id: OrganizationId, data: EventRange, headers: Admin
     })))
 }
 
+/// Longest window of the activity overview, in days
+const ACTIVITY_MAX_DAYS: i64 = 366;
+
+/// The number of events per day and event type of the last `days` days (30 by default),
+/// to show whether the organization is actually being used
+#[get("/organizations/<org_id>/events/activity?<days>")]
+async fn get_org_event_activity(
+    org_id: OrganizationId,
+    days: Option<i64>,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let days = days.unwrap_or(30);
+    if !(1..=ACTIVITY_MAX_DAYS).contains(&days) {
+        err!(format!("The number of days must be between 1 and {ACTIVITY_MAX_DAYS}"))
+    }
+
+    let end_date = Utc::now().naive_utc();
+    let start_date = end_date - TimeDelta::days(days);
+    let activity_json: Vec<Value> = if CONFIG.org_events_enabled() {
+        Event::count_by_org_per_day(&org_id, start_date, end_date, &conn)
+            .await?
+            .iter()
+            .map(EventActivity::to_json)
+            .collect()
+    } else {
+        Vec::with_capacity(0)
+    };
+
+    Ok(Json(json!({
+        "data": activity_json,
+        "startDate": crate::util::format_date(&start_date),
+        "endDate": crate::util::format_date(&end_date),
+        "object": "list",
+    })))
+}
+
 #[get("/ciphers/<cipher_id>/events?<data..>")]
 async fn get_cipher_events(cipher_id: CipherId, data: EventRange, headers: Headers, conn: DbConn) -> JsonResult {
     // Return an empty vec when org events are disabled.
@@ -163,7 +204,7 @@ struct EventCollection {
 // https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Core/AdminConsole/Services/Implementations/EventService.cs
 #[post("/collect", format = "application/json", data = "<data>")]
 async fn post_events_collect(data: Json<Vec<EventCollection>>, headers: Headers, conn: DbConn) -> EmptyResult {
-    if !CONFIG.org_events_enabled() {
+    if !CONFIG.org_events_enabled() && !webhook::is_enabled() {
         return Ok(());
     }
 
@@ -220,7 +261,7 @@ async fn post_events_collect(data: Json<Vec<EventCollection>>, headers: Headers,
 }
 
 pub async fn log_user_event(event_type: i32, user_id: &UserId, device_type: i32, ip: &IpAddr, conn: &DbConn) {
-    if !CONFIG.org_events_enabled() {
+    if !CONFIG.org_events_enabled() && !webhook::is_enabled() {
         return;
     }
     log_user_event_impl(event_type, user_id, device_type, None, ip, conn).await;
@@ -257,7 +298,10 @@ async fn log_user_event_impl(
         events.push(event);
     }
 
-    Event::save_user_event(events, conn).await.unwrap_or(());
+    webhook::send_events(&events, conn).await;
+    if CONFIG.org_events_enabled() {
+        Event::save_user_event(events, conn).await.unwrap_or(());
+    }
 }
 
 pub async fn log_event(
@@ -269,7 +313,7 @@ pub async fn log_event(
     ip: &IpAddr,
     conn: &DbConn,
 ) {
-    if !CONFIG.org_events_enabled() {
+    if !CONFIG.org_events_enabled() && !webhook::is_enabled() {
         return;
     }
     log_event_impl(event_type, source_uuid, org_id, act_user_id, device_type, None, ip, conn).await;
@@ -311,6 +355,14 @@ async fn log_event_impl(
         1700..=1799 => {
             event.policy_uuid = Some(source_uuid.to_owned().into());
         }
+        // Organization Send Events
+        2200..=2299 => {
+            event.send_uuid = Some(source_uuid.to_owned().into());
+        }
+        // Audit Grant Events
+        2400..=2499 => {
+            event.collection_uuid = Some(source_uuid.to_owned().into());
+        }
         // Ignore others
         _ => {}
     }
@@ -319,7 +371,10 @@ async fn log_event_impl(
     event.act_user_uuid = Some(act_user_id.clone());
     event.device_type = Some(device_type);
     event.ip_address = Some(ip.to_string());
-    event.save(conn).await.unwrap_or(());
+    webhook::send_events(std::slice::from_ref(&event), conn).await;
+    if CONFIG.org_events_enabled() {
+        event.save(conn).await.unwrap_or(());
+    }
 }
 
 pub async fn event_cleanup_job(pool: DbPool) {
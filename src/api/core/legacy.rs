@@ -0,0 +1,122 @@
+//
+// Compatibility routes for old third-party clients
+//
+// Before 2019 the clients logged in with a JSON body on `POST /api/auth/token`, instead of the form encoded
+// `POST /identity/connect/token`. These routes translate such a login to a `password` grant and add the fields
+// those clients expect to the response. Their other calls, like `GET /api/sync`, are served by the current routes.
+// They are only mounted when `LEGACY_TOKEN_API_ENABLED` is set and every use is logged, see `legacy_route_enabled`.
+//
+use rocket::serde::json::Json;
+use serde_json::Value;
+
+use crate::{
+    api::{
+        JsonResult,
+        identity::{LegacyPasswordLogin, legacy_password_login},
+    },
+    auth::{ClientCountry, ClientHeaders, ClientVersion},
+    db::{
+        DbConn,
+        models::{Device, DeviceId, User},
+    },
+    tenant::RequestTenant,
+    util::{LowerCase, get_uuid},
+};
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![legacy_auth_token, legacy_auth_token_two_factor]
+}
+
+// Name of the device registered for the clients which don't send one
+const LEGACY_DEVICE_NAME: &str = "Legacy client";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LegacyTokenData {
+    email: String,
+    master_password_hash: String,
+    device: Option<LegacyDeviceData>,
+    two_factor_provider: Option<i32>,
+    two_factor_token: Option<String>,
+    #[serde(default)]
+    two_factor_remember: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LegacyDeviceData {
+    r#type: i32,
+    name: String,
+    identifier: DeviceId,
+}
+
+#[post("/auth/token", data = "<data>")]
+async fn legacy_auth_token(
+    data: Json<LowerCase<LegacyTokenData>>,
+    client_header: ClientHeaders,
+    client_version: Option<ClientVersion>,
+    country: ClientCountry,
+    tenant: RequestTenant,
+    conn: DbConn,
+) -> JsonResult {
+    let data = data.into_inner().data;
+    let email = data.email.clone();
+
+    // Some of these clients didn't send a device, they reuse the last such device of the client type of the user,
+    // so every login doesn't register a new one
+    let (device_identifier, device_name, device_type) = match data.device {
+        Some(device) => (device.identifier, device.name, device.r#type),
+        None => {
+            let device_type = client_header.device_type;
+            let known_device = match User::find_by_mail(&email, &conn).await {
+                Some(user) => Device::find_by_user_latest_first(&user.uuid, &conn)
+                    .await
+                    .into_iter()
+                    .find(|d| d.name == LEGACY_DEVICE_NAME && d.atype == device_type),
+                None => None,
+            };
+            let device_identifier = known_device.map_or_else(|| DeviceId::from(get_uuid()), |d| d.uuid);
+            (device_identifier, String::from(LEGACY_DEVICE_NAME), device_type)
+        }
+    };
+
+    let login = LegacyPasswordLogin {
+        email: data.email,
+        master_password_hash: data.master_password_hash,
+        device_identifier,
+        device_name,
+        device_type,
+        two_factor_provider: data.two_factor_provider,
+        two_factor_token: data.two_factor_token,
+        two_factor_remember: data.two_factor_remember,
+    };
+    let Json(mut result) = legacy_password_login(login, client_header, client_version, country, tenant, &conn).await?;
+
+    // The old response used PascalCase and included the profile of the user
+    let profile = match User::find_by_mail(&email, &conn).await {
+        Some(user) => user.to_json(&conn).await,
+        None => Value::Null,
+    };
+    let token = result["access_token"].clone();
+    let refresh_token = result["refresh_token"].clone();
+    if let Some(result) = result.as_object_mut() {
+        result.insert(String::from("Token"), token);
+        result.insert(String::from("RefreshToken"), refresh_token);
+        result.insert(String::from("Profile"), profile);
+    }
+
+    Ok(Json(result))
+}
+
+// The two-factor step was a separate route with the same body, including the token and provider
+#[post("/auth/token/two-factor", data = "<data>")]
+async fn legacy_auth_token_two_factor(
+    data: Json<LowerCase<LegacyTokenData>>,
+    client_header: ClientHeaders,
+    client_version: Option<ClientVersion>,
+    country: ClientCountry,
+    tenant: RequestTenant,
+    conn: DbConn,
+) -> JsonResult {
+    legacy_auth_token(data, client_header, client_version, country, tenant, conn).await
+}
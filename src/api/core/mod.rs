@@ -1,19 +1,32 @@
 pub mod accounts;
+pub mod capabilities;
+pub mod response_cache;
 pub mod two_factor;
 
 mod ciphers;
 mod emergency_access;
 mod events;
 mod folders;
+mod legacy;
 mod organizations;
 mod public;
 mod sends;
+mod strict;
 
-pub use accounts::purge_auth_requests;
-pub use ciphers::{CipherData, CipherSyncData, CipherSyncType, purge_trashed_ciphers};
+pub use accounts::{
+    device_cleanup_job, inactive_users_job, purge_auth_requests, purge_unverified_users, reconcile_revision_dates,
+};
+pub use ciphers::{
+    CipherData, CipherSyncData, CipherSyncType, attachment_quota_warning_job, purge_trashed_ciphers,
+    verify_attachment_rekeys,
+};
 pub use emergency_access::{emergency_notification_reminder_job, emergency_request_timeout_job};
 pub use events::{event_cleanup_job, log_event, log_user_event};
-pub use sends::purge_sends;
+pub use organizations::expire_audit_grants;
+pub use public::{last_ldap_sync, ldap_sync_job, provision_member, revoke_unless_last_owner, sync_ldap_directory};
+pub use sends::{purge_sends, send_egress_ip_stats};
+
+use std::sync::LazyLock;
 
 use reqwest::Method;
 use rocket::{Catcher, Route, serde::json::Json, serde::json::Value};
@@ -24,14 +37,16 @@ use crate::{
     auth::Headers,
     db::{
         DbConn,
-        models::{Membership, MembershipStatus, OrgPolicy, Organization, User},
+        models::{GlobalDomainOverride, Membership, MembershipStatus, OrgDomain, OrgPolicy, Organization, User},
     },
     error::Error,
     http_client::make_http_request,
     mail,
-    util::{FeatureFlagFilter, parse_experimental_client_feature_flags},
+    util::{ETagged, FeatureFlagFilter, parse_experimental_client_feature_flags},
 };
 
+use response_cache::CacheKey;
+
 pub fn routes() -> Vec<Route> {
     let mut eq_domains_routes = routes![get_settings_domains, post_settings_domains, put_settings_domains];
     let mut hibp_routes = routes![hibp_breach];
@@ -43,6 +58,7 @@ pub fn routes() -> Vec<Route> {
     routes.append(&mut emergency_access::routes());
     routes.append(&mut events::routes());
     routes.append(&mut folders::routes());
+    routes.append(&mut legacy::routes());
     routes.append(&mut organizations::routes());
     routes.append(&mut two_factor::routes());
     routes.append(&mut sends::routes());
@@ -51,9 +67,29 @@ pub fn routes() -> Vec<Route> {
     routes.append(&mut hibp_routes);
     routes.append(&mut meta_routes);
 
+    // Deprecated routes are not mounted at all once they are disabled
+    routes.retain(|r| r.name.as_deref().and_then(legacy_route_enabled).unwrap_or(true));
+
     routes
 }
 
+/// Returns if a deprecated route is still served, or `None` if the route isn't deprecated
+pub fn legacy_route_enabled(name: &str) -> Option<bool> {
+    match name {
+        // Replaced by `POST /ciphers/<cipher_id>/attachment/v2` followed by the upload of the file
+        "post_attachment" | "post_attachment_admin" => Some(!CONFIG.disable_legacy_attachment_upload()),
+        // Replaced by `PUT /ciphers/<cipher_id>/admin`
+        "post_cipher_admin" => Some(!CONFIG.disable_legacy_cipher_admin_routes()),
+        // Replaced by the `PUT` variants of the same routes
+        "activate_authenticator" | "activate_duo" | "activate_webauthn" | "activate_yubikey" | "disable_twofactor" => {
+            Some(!CONFIG.disable_legacy_two_factor_routes())
+        }
+        // The login of pre-2019 clients, replaced by `POST /identity/connect/token`
+        "legacy_auth_token" | "legacy_auth_token_two_factor" => Some(CONFIG.legacy_token_api_enabled()),
+        _ => None,
+    }
+}
+
 pub fn events_routes() -> Vec<Route> {
     let mut routes = Vec::new();
     routes.append(&mut events::main_routes());
@@ -61,23 +97,51 @@ pub fn events_routes() -> Vec<Route> {
     routes
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct GlobalDomain {
-    r#type: i32,
-    domains: Vec<String>,
-    excluded: bool,
+pub struct GlobalDomain {
+    pub r#type: i32,
+    pub domains: Vec<String>,
+    pub excluded: bool,
 }
 
-const GLOBAL_DOMAINS: &str = include_str!("../../static/global_domains.json");
+static GLOBAL_DOMAINS: LazyLock<Vec<GlobalDomain>> = LazyLock::new(|| {
+    serde_json::from_str(include_str!("../../static/global_domains.json")).expect("Invalid global_domains.json")
+});
+
+/// The custom sets of the instance use the types from this number on, so they don't clash with the types added to the built-in list
+pub const CUSTOM_GLOBAL_DOMAINS_START: i32 = 10_000;
+
+pub fn builtin_global_domains() -> &'static [GlobalDomain] {
+    &GLOBAL_DOMAINS
+}
+
+/// The built-in global equivalent domains, with the changes made by the admin applied and the custom sets of the instance added
+pub async fn global_domains(conn: &DbConn) -> Vec<GlobalDomain> {
+    let mut globals = GLOBAL_DOMAINS.clone();
+    for o in GlobalDomainOverride::find_all(conn).await {
+        if o.disabled {
+            globals.retain(|g| g.r#type != o.atype);
+        } else if let Some(global) = globals.iter_mut().find(|g| g.r#type == o.atype) {
+            global.domains = o.domain_list();
+        } else {
+            globals.push(GlobalDomain {
+                r#type: o.atype,
+                domains: o.domain_list(),
+                excluded: false,
+            });
+        }
+    }
+    globals
+}
 
-#[expect(clippy::needless_pass_by_value, reason = "Not beneficial for Headers")]
 #[get("/settings/domains")]
-fn get_settings_domains(headers: Headers) -> Json<Value> {
-    get_eq_domains(&headers, false)
+async fn get_settings_domains(headers: Headers, conn: DbConn) -> ETagged {
+    let key = CacheKey::Domains(headers.user.uuid.clone());
+    ETagged(response_cache::get_or_load(key, async { get_eq_domains(&headers, false, &conn).await.into_inner() }).await)
 }
 
-fn get_eq_domains(headers: &Headers, no_excluded: bool) -> Json<Value> {
+async fn get_eq_domains(headers: &Headers, no_excluded: bool, conn: &DbConn) -> Json<Value> {
     use serde_json::from_str;
 
     let user = &headers.user;
@@ -85,7 +149,7 @@ fn get_eq_domains(headers: &Headers, no_excluded: bool) -> Json<Value> {
     let equivalent_domains: Vec<Vec<String>> = from_str(&user.equivalent_domains).unwrap();
     let excluded_globals: Vec<i32> = from_str(&user.excluded_globals).unwrap();
 
-    let mut globals: Vec<GlobalDomain> = from_str(GLOBAL_DOMAINS).unwrap();
+    let mut globals = global_domains(conn).await;
 
     for global in &mut globals {
         global.excluded = excluded_globals.contains(&global.r#type);
@@ -208,7 +272,7 @@ fn get_api_webauthn(_headers: Headers) -> Json<Value> {
 }
 
 #[get("/config")]
-fn config() -> Json<Value> {
+fn config() -> ETagged {
     let domain = CONFIG.domain();
     // Official available feature flags can be found here:
     // Server (v2026.2.1): https://github.com/bitwarden/server/blob/0e42725d0837bd1c0dabd864ff621a579959744b/src/Core/Constants.cs#L135
@@ -221,7 +285,7 @@ fn config() -> Json<Value> {
     );
     feature_states.insert("pm-19148-innovation-archive".to_owned(), true);
 
-    Json(json!({
+    ETagged(json!({
         // Note: The clients use this version to handle backwards compatibility concerns
         // This means they expect a version that closely matches the Bitwarden server version
         // We should make sure that we keep this updated when we support the new server features
@@ -270,6 +334,27 @@ fn api_not_found() -> Json<Value> {
     }))
 }
 
+/// Invites a newly registered user to the organization which verified the domain of their email address, if any.
+/// The membership still has to be accepted and then confirmed by an admin of the organization like any other invite.
+pub(crate) async fn invite_to_claimed_domain_org(user: &User, conn: &DbConn) -> Option<Membership> {
+    let domain = OrgDomain::find_verified_by_email(&user.email, conn).await?;
+    let org = Organization::find_by_uuid(&domain.org_uuid, conn).await?;
+    if org.tenant_id != user.tenant_id || Membership::find_by_user_and_org(&user.uuid, &org.uuid, conn).await.is_some()
+    {
+        return None;
+    }
+
+    let mut member = Membership::new(user.uuid.clone(), org.uuid, None);
+    member.status = MembershipStatus::Invited as i32;
+    if let Err(e) = member.save(conn).await {
+        error!("Error adding {} to the organization of their domain: {e:#?}", user.email);
+        return None;
+    }
+    info!("Invited {} to the organization claiming the domain {}", user.email, domain.domain_name);
+
+    Some(member)
+}
+
 async fn accept_org_invite(
     user: &User,
     mut member: Membership,
@@ -1,28 +1,38 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, Ipv4Addr},
+};
 
 use num_traits::FromPrimitive;
-use rocket::{Route, serde::json::Json};
+use rocket::{Route, http::ContentType, response::stream::TextStream, serde::json::Json};
 use serde_json::Value;
 
 use crate::{
     CONFIG,
     api::admin::FAKE_ADMIN_UUID,
     api::{
-        EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType,
-        core::{CipherSyncData, CipherSyncType, accept_org_invite, log_event, two_factor},
+        ApiResult, EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType,
+        core::{
+            CipherSyncData, CipherSyncType, accept_org_invite, log_event,
+            response_cache::{self, CacheKey},
+            two_factor,
+        },
     },
     auth::{AdminHeaders, Headers, ManagerHeaders, ManagerHeadersLoose, OrgMemberHeaders, OwnerHeaders, decode_invite},
     db::{
-        DbConn,
+        DbConn, DbPool,
         models::{
-            Cipher, CipherId, Collection, CollectionCipher, CollectionGroup, CollectionId, CollectionUser, EventType,
-            Group, GroupId, GroupUser, Invitation, Membership, MembershipId, MembershipStatus, MembershipType,
-            OrgPolicy, OrgPolicyType, Organization, OrganizationApiKey, OrganizationId, User, UserId,
+            Attachment, AttachmentPolicyData, AuditGrant, Cipher, CipherId, CipherTemplate, CipherTemplateId,
+            Collection, CollectionCipher, CollectionGroup, CollectionId, CollectionUser, DeviceType, EmergencyAccess,
+            EventType, Group, GroupId, GroupUser, Invitation, Membership, MembershipId, MembershipStatus,
+            MembershipType, OrgApiKeyType, OrgDomain, OrgDomainId, OrgPolicy, OrgPolicyType, Organization,
+            OrganizationApiKey, OrganizationId, PasswordHintPolicyData, RepromptPolicyData, TemplateField,
+            TrashRetentionPolicyData, TwoFactorPolicyData, User, UserId,
         },
     },
     mail,
     sso::FAKE_SSO_IDENTIFIER,
-    util::{NumberOrString, convert_json_key_lcase_first},
+    util::{ETagged, NumberOrString, convert_json_key_lcase_first, format_date, get_display_size},
 };
 
 pub fn routes() -> Vec<Route> {
@@ -39,6 +49,18 @@ pub fn routes() -> Vec<Route> {
         get_collection_users,
         put_organization,
         post_organization,
+        put_organization_collection_management,
+        get_organization_onboarding,
+        put_organization_onboarding,
+        get_confidential_collections,
+        put_confidential_collections,
+        get_organization_icon_service,
+        put_organization_icon_service,
+        get_organization_webhook,
+        put_organization_webhook,
+        get_audit_grants,
+        post_audit_grants,
+        delete_audit_grants,
         post_organization_collections,
         post_bulk_access_collections,
         post_organization_collection_update,
@@ -48,11 +70,17 @@ pub fn routes() -> Vec<Route> {
         bulk_delete_organization_collections,
         post_bulk_collections,
         get_org_details,
+        get_org_details_assigned,
+        get_org_trash,
+        restore_org_trash,
+        delete_org_trash,
         get_org_domain_sso_verified,
         get_members,
         send_invite,
         reinvite_member,
         bulk_reinvite_members,
+        get_pending_invites,
+        reinvite_expired_members,
         confirm_invite,
         bulk_confirm_invite,
         accept_invite,
@@ -63,6 +91,8 @@ pub fn routes() -> Vec<Route> {
         delete_member,
         bulk_delete_member,
         post_org_import,
+        get_collection_export,
+        post_collection_import,
         list_policies,
         list_policies_token,
         get_dummy_master_password_policy,
@@ -70,16 +100,33 @@ pub fn routes() -> Vec<Route> {
         get_policy,
         put_policy,
         put_policy_vnext,
+        list_org_domains,
+        get_org_domain,
+        post_org_domain,
+        verify_org_domain,
+        delete_org_domain,
+        post_delete_org_domain,
+        list_cipher_templates,
+        post_cipher_template,
+        put_cipher_template,
+        delete_cipher_template,
+        get_cipher_template_enforcement,
+        put_cipher_template_enforcement,
         get_plans,
         post_org_keys,
         get_organization_keys,
         get_organization_public_key,
         bulk_public_keys,
         revoke_member,
+        offboard_member,
         bulk_revoke_members,
+        patch_revoke_member,
+        patch_bulk_revoke_members,
         restore_member,
         restore_member_vnext,
         bulk_restore_members,
+        patch_restore_member,
+        patch_bulk_restore_members,
         get_groups,
         get_groups_details,
         post_groups,
@@ -97,8 +144,12 @@ pub fn routes() -> Vec<Route> {
         get_reset_password_details,
         put_reset_password,
         get_org_export,
+        get_org_ciphers_export,
         post_api_key,
         rotate_api_key,
+        get_subscription,
+        get_billing,
+        get_billing_history,
         get_billing_metadata,
         get_billing_warnings,
         get_auto_enroll_status,
@@ -125,6 +176,43 @@ struct OrganizationUpdateData {
     name: String,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CollectionManagementData {
+    limit_collection_creation: bool,
+    limit_collection_deletion: bool,
+    limit_item_deletion: bool,
+    allow_admin_access_to_all_collection_items: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnboardingData {
+    welcome_message: Option<String>,
+    collection_ids: Vec<CollectionId>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfidentialCollectionsData {
+    enabled: bool,
+    collection_ids: Vec<CollectionId>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IconServiceData {
+    icon_service: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookData {
+    url: Option<String>,
+    /// Kept when not given, the current secret is never returned
+    secret: Option<String>,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct FullCollectionData {
@@ -208,7 +296,8 @@ async fn create_organization(headers: Headers, data: Json<OrgData>, conn: DbConn
         (None, None)
     };
 
-    let org = Organization::new(data.name, &data.billing_email, private_key, public_key);
+    let mut org = Organization::new(data.name, &data.billing_email, private_key, public_key);
+    org.tenant_id.clone_from(&headers.user.tenant_id);
     let mut member = Membership::new(headers.user.uuid, org.uuid.clone(), None);
     let collection = Collection::new(org.uuid.clone(), data.collection_name, None);
 
@@ -282,15 +371,18 @@ async fn leave_organization(org_id: OrganizationId, headers: OrgMemberHeaders, c
 }
 
 #[get("/organizations/<org_id>")]
-async fn get_organization(org_id: OrganizationId, headers: OwnerHeaders, conn: DbConn) -> JsonResult {
+async fn get_organization(org_id: OrganizationId, headers: OwnerHeaders, conn: DbConn) -> ApiResult<ETagged> {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
     }
-    if let Some(organization) = Organization::find_by_uuid(&org_id, &conn).await {
-        Ok(Json(organization.to_json()))
-    } else {
+    let org_json = response_cache::get_or_load(CacheKey::Organization(org_id.clone()), async {
+        Organization::find_by_uuid(&org_id, &conn).await.map_or(Value::Null, |org| org.to_json())
+    })
+    .await;
+    if org_json.is_null() {
         err!("Can't find organization details")
     }
+    Ok(ETagged(org_json))
 }
 
 #[put("/organizations/<org_id>", data = "<data>")]
@@ -339,182 +431,168 @@ async fn post_organization(
     Ok(Json(org.to_json()))
 }
 
-// GET /api/collections?writeOnly=false
-#[get("/collections")]
-async fn get_user_collections(headers: Headers, conn: DbConn) -> Json<Value> {
-    Json(json!({
-        "data":
-            Collection::find_by_user_uuid(headers.user.uuid, &conn).await
-            .iter()
-            .map(Collection::to_json)
-            .collect::<Value>(),
-        "object": "list",
-        "continuationToken": null,
-    }))
-}
+// https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Api/AdminConsole/Controllers/OrganizationsController.cs
+#[put("/organizations/<org_id>/collection-management", data = "<data>")]
+async fn put_organization_collection_management(
+    org_id: OrganizationId,
+    headers: OwnerHeaders,
+    data: Json<CollectionManagementData>,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
 
-// Called during the SSO enrollment
-// The `identifier` should be the value returned by `get_org_domain_sso_verified`
-// The returned `Id` will then be passed to `get_master_password_policy` which will mainly ignore it
-#[get("/organizations/<identifier>/auto-enroll-status")]
-async fn get_auto_enroll_status(identifier: &str, headers: Headers, conn: DbConn) -> JsonResult {
-    let org = if identifier == FAKE_SSO_IDENTIFIER {
-        match Membership::find_main_user_org(&headers.user.uuid, &conn).await {
-            Some(member) => Organization::find_by_uuid(&member.org_uuid, &conn).await,
-            None => None,
-        }
-    } else {
-        Organization::find_by_uuid(&identifier.into(), &conn).await
-    };
+    let data: CollectionManagementData = data.into_inner();
 
-    let (id, identifier, rp_auto_enroll) = match org {
-        None => (identifier.to_owned(), identifier.to_owned(), false),
-        Some(org) => (
-            org.uuid.to_string(),
-            org.uuid.to_string(),
-            OrgPolicy::org_is_reset_password_auto_enroll(&org.uuid, &conn).await,
-        ),
+    let Some(mut org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
     };
 
-    Ok(Json(json!({
-        "id": id,
-        "identifier": identifier,
-        "resetPasswordEnabled": rp_auto_enroll,
-    })))
+    org.limit_collection_creation = data.limit_collection_creation;
+    org.limit_collection_deletion = data.limit_collection_deletion;
+    org.limit_item_deletion = data.limit_item_deletion;
+    org.allow_admin_access_to_all_collection_items = data.allow_admin_access_to_all_collection_items;
+
+    org.save(&conn).await?;
+
+    log_event(
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(org.to_json()))
 }
 
-#[get("/organizations/<org_id>/collections")]
-async fn get_org_collections(org_id: OrganizationId, headers: ManagerHeadersLoose, conn: DbConn) -> JsonResult {
-    if org_id != headers.membership.org_uuid {
-        err!("Organization not found", "Organization id's do not match");
-    }
+async fn onboarding_json(org: &Organization, conn: &DbConn) -> Value {
+    let collection_ids: Vec<CollectionId> =
+        Collection::find_onboarding_by_organization(&org.uuid, conn).await.into_iter().map(|c| c.uuid).collect();
+    json!({
+        "welcomeMessage": org.welcome_message,
+        "collectionIds": collection_ids,
+        "object": "organizationOnboarding",
+    })
+}
 
-    if !headers.membership.has_full_access() {
-        err_code!("Resource not found.", "User does not have full access", rocket::http::Status::NotFound.code);
+/// The onboarding settings of an organization: a message added to the email new members get when they are confirmed,
+/// and the collections new members get read-only access to, like a collection with the Wi-Fi passwords.
+/// Folders can't be created for new members, because their names are encrypted with the key of the member.
+#[get("/organizations/<org_id>/onboarding")]
+async fn get_organization_onboarding(org_id: OrganizationId, headers: OwnerHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
     }
+    let Some(org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
 
-    Ok(Json(json!({
-        "data": get_org_collections_impl(&org_id, &conn).await,
-        "object": "list",
-        "continuationToken": null,
-    })))
+    Ok(Json(onboarding_json(&org, &conn).await))
 }
 
-#[get("/organizations/<org_id>/collections/details")]
-async fn get_org_collections_details(org_id: OrganizationId, headers: ManagerHeadersLoose, conn: DbConn) -> JsonResult {
-    if org_id != headers.membership.org_uuid {
+#[put("/organizations/<org_id>/onboarding", data = "<data>")]
+async fn put_organization_onboarding(
+    org_id: OrganizationId,
+    headers: OwnerHeaders,
+    data: Json<OnboardingData>,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
     }
 
-    let Some(member) = Membership::find_by_user_and_org(&headers.user.uuid, &org_id, &conn).await else {
-        err!("User is not part of organization")
-    };
+    let data: OnboardingData = data.into_inner();
 
-    // get all collection memberships for the current organization
-    let col_users = CollectionUser::find_by_organization_swap_user_uuid_with_member_uuid(&org_id, &conn).await;
-    // Generate a HashMap to get the correct MembershipType per user to determine the manage permission
-    // We use the uuid instead of the user_uuid here, since that is what is used in CollectionUser
-    let membership_type: HashMap<MembershipId, i32> =
-        Membership::find_confirmed_by_org(&org_id, &conn).await.into_iter().map(|m| (m.uuid, m.atype)).collect();
+    let Some(mut org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
 
-    // check if current user has full access to the organization (either directly or via any group)
-    let has_full_access_to_org = member.has_full_access()
-        || (CONFIG.org_groups_enabled() && GroupUser::has_full_access_by_member(&org_id, &member.uuid, &conn).await);
+    let welcome_message = data.welcome_message.map(|m| m.trim().to_owned()).filter(|m| !m.is_empty());
+    if welcome_message.as_ref().is_some_and(|m| m.chars().count() > 1000) {
+        err!("The welcome message can't be longer than 1000 characters")
+    }
 
-    // Get all admins, owners and managers who can manage/access all
-    // Those are currently not listed in the col_users but need to be listed too.
-    let manage_all_members: Vec<Value> = Membership::find_confirmed_and_manage_all_by_org(&org_id, &conn)
-        .await
-        .into_iter()
-        .map(|member| {
-            json!({
-                "id": member.uuid,
-                "readOnly": false,
-                "hidePasswords": false,
-                "manage": true,
-            })
-        })
-        .collect();
+    let org_collections: HashSet<CollectionId> =
+        Collection::find_by_organization(&org_id, &conn).await.into_iter().map(|c| c.uuid).collect();
+    if data.collection_ids.iter().any(|col_id| !org_collections.contains(col_id)) {
+        err!("Invalid collection ID provided")
+    }
 
-    let mut data = Vec::new();
-    for col in Collection::find_by_organization(&org_id, &conn).await {
-        // check whether the current user has access to the given collection
-        let assigned = has_full_access_to_org
-            || CollectionUser::has_access_to_collection_by_user(&col.uuid, &member.user_uuid, &conn).await
-            || (CONFIG.org_groups_enabled()
-                && GroupUser::has_access_to_collection_by_member(&col.uuid, &member.uuid, &conn).await);
+    org.welcome_message = welcome_message;
+    org.save(&conn).await?;
+    Collection::set_onboarding_by_organization(&org_id, &data.collection_ids, &conn).await?;
 
-        // If the user is a manager, and is not assigned to this collection, skip this and continue with the next collection
-        if !assigned {
-            continue;
-        }
+    log_event(
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
 
-        // get the users assigned directly to the given collection
-        let mut users: Vec<Value> = col_users
-            .iter()
-            .filter(|collection_member| collection_member.collection_uuid == col.uuid)
-            .map(|collection_member| {
-                collection_member.to_json_details_for_member(
-                    *membership_type.get(&collection_member.membership_uuid).unwrap_or(&(MembershipType::User as i32)),
-                )
-            })
-            .collect();
-        users.extend_from_slice(&manage_all_members);
+    Ok(Json(onboarding_json(&org, &conn).await))
+}
 
-        // get the group details for the given collection
-        let groups: Vec<Value> = if CONFIG.org_groups_enabled() {
-            CollectionGroup::find_by_collection(&col.uuid, &conn)
-                .await
-                .iter()
-                .map(CollectionGroup::to_json_details_for_group)
-                .collect()
-        } else {
-            Vec::with_capacity(0)
-        };
+fn icon_service_json(org: &Organization) -> Value {
+    json!({
+        "iconService": org.icon_service,
+        "instanceIconService": CONFIG.icon_service(),
+        "object": "organizationIconService",
+    })
+}
 
-        let mut json_object = col.to_json_details(&headers.user.uuid, None, &conn).await;
-        json_object["assigned"] = json!(assigned);
-        json_object["users"] = json!(users);
-        json_object["groups"] = json!(groups);
-        json_object["object"] = json!("collectionAccessDetails");
-        json_object["unmanaged"] = json!(false);
-        data.push(json_object);
+/// The icon service used for the icon requests which name the organization, see `icon_org` in icons.rs.
+/// Either one of the external values of `ICON_SERVICE`, "none" to disable the icons,
+/// or null to use the icon service of the instance.
+#[get("/organizations/<org_id>/icon-service")]
+async fn get_organization_icon_service(org_id: OrganizationId, headers: OwnerHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
     }
+    let Some(org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
 
-    Ok(Json(json!({
-        "data": data,
-        "object": "list",
-        "continuationToken": null,
-    })))
-}
-
-async fn get_org_collections_impl(org_id: &OrganizationId, conn: &DbConn) -> Value {
-    Collection::find_by_organization(org_id, conn).await.iter().map(Collection::to_json).collect::<Value>()
+    Ok(Json(icon_service_json(&org)))
 }
 
-#[post("/organizations/<org_id>/collections", data = "<data>")]
-async fn post_organization_collections(
+#[put("/organizations/<org_id>/icon-service", data = "<data>")]
+async fn put_organization_icon_service(
     org_id: OrganizationId,
-    headers: ManagerHeadersLoose,
-    data: Json<FullCollectionData>,
+    headers: OwnerHeaders,
+    data: Json<IconServiceData>,
     conn: DbConn,
 ) -> JsonResult {
-    if org_id != headers.membership.org_uuid {
+    if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
     }
-    let data: FullCollectionData = data.into_inner();
-    data.validate(&org_id, &conn).await?;
 
-    if headers.membership.atype == MembershipType::Manager && !headers.membership.access_all {
-        err!("You don't have permission to create collections")
+    let icon_service = data.into_inner().icon_service.map(|s| s.trim().to_owned()).filter(|s| !s.is_empty());
+    match icon_service.as_deref() {
+        None | Some("none") => (),
+        // The icon route of the organization can't be authenticated, the server must not fetch icons only because
+        // an organization asked for it
+        Some("internal") => err!("The internal icon service can only be enabled for the whole instance"),
+        Some(service) => crate::config::validate_icon_service(service)?,
     }
 
-    let collection = Collection::new(org_id.clone(), data.name, data.external_id);
-    collection.save(&conn).await?;
+    let Some(mut org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+    org.icon_service = icon_service;
+    org.save(&conn).await?;
 
     log_event(
-        EventType::CollectionCreated as i32,
-        &collection.uuid,
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
         &org_id,
         &headers.user.uuid,
         headers.device.atype,
@@ -523,23 +601,563 @@ async fn post_organization_collections(
     )
     .await;
 
-    for group in data.groups {
-        CollectionGroup::new(collection.uuid.clone(), group.id, group.read_only, group.hide_passwords, group.manage)
-            .save(&org_id, &conn)
-            .await?;
-    }
-
-    for user in data.users {
-        let Some(member) = Membership::find_by_uuid_and_org(&user.id, &org_id, &conn).await else {
-            err!("User is not part of organization")
-        };
+    Ok(Json(icon_service_json(&org)))
+}
 
-        if member.access_all {
-            continue;
-        }
+fn webhook_json(org: &Organization) -> Value {
+    json!({
+        "url": org.webhook_url,
+        "secretSet": org.webhook_secret.is_some(),
+        "allowed": CONFIG.org_webhooks_allowed(),
+        "object": "organizationWebhook",
+    })
+}
 
-        CollectionUser::save(
-            &member.user_uuid,
+/// The webhook receiving the events of the organization, signed with its own secret, see webhook.rs.
+/// Only used when the admin allows organization webhooks with `ORG_WEBHOOKS_ALLOWED`.
+#[get("/organizations/<org_id>/webhook")]
+async fn get_organization_webhook(org_id: OrganizationId, headers: OwnerHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+
+    Ok(Json(webhook_json(&org)))
+}
+
+#[put("/organizations/<org_id>/webhook", data = "<data>")]
+async fn put_organization_webhook(
+    org_id: OrganizationId,
+    headers: OwnerHeaders,
+    data: Json<WebhookData>,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    if !CONFIG.org_webhooks_allowed() {
+        err!("Organization webhooks are not allowed on this server")
+    }
+
+    let data = data.into_inner();
+    let url = data.url.map(|s| s.trim().to_owned()).filter(|s| !s.is_empty());
+    let secret = data.secret.filter(|s| !s.is_empty());
+    if let Some(url) = url.as_deref() {
+        crate::config::validate_webhook_url(url)?;
+    }
+
+    let Some(mut org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+    if url.is_some() {
+        if let Some(secret) = secret {
+            org.webhook_secret = Some(crate::crypto::encrypt_secret(&secret));
+        }
+        if org.webhook_secret.is_none() {
+            err!("A secret is needed to sign the webhook requests")
+        }
+    } else {
+        org.webhook_secret = None;
+    }
+    org.webhook_url = url;
+    org.save(&conn).await?;
+
+    log_event(
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(webhook_json(&org)))
+}
+
+async fn confidential_collections_json(org: &Organization, conn: &DbConn) -> Value {
+    let collection_ids: Vec<CollectionId> =
+        Collection::find_confidential_by_organization(&org.uuid, conn).await.into_iter().map(|c| c.uuid).collect();
+    json!({
+        "enabled": org.confidential_collections,
+        "collectionIds": collection_ids,
+        "object": "organizationConfidentialCollections",
+    })
+}
+
+/// Confidential collections, like the ones of HR or legal, whose items are hidden from owners, admins and members with access to all items.
+/// Only the members assigned to the collection, directly or through a group, can see its items.
+/// Owners can still change these settings or assign themselves to the collection, which is recorded in the event log.
+#[get("/organizations/<org_id>/confidential-collections")]
+async fn get_confidential_collections(org_id: OrganizationId, headers: OwnerHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+
+    Ok(Json(confidential_collections_json(&org, &conn).await))
+}
+
+#[put("/organizations/<org_id>/confidential-collections", data = "<data>")]
+async fn put_confidential_collections(
+    org_id: OrganizationId,
+    headers: OwnerHeaders,
+    data: Json<ConfidentialCollectionsData>,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let data: ConfidentialCollectionsData = data.into_inner();
+
+    let Some(mut org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+
+    let org_collections: HashSet<CollectionId> =
+        Collection::find_by_organization(&org_id, &conn).await.into_iter().map(|c| c.uuid).collect();
+    if data.collection_ids.iter().any(|col_id| !org_collections.contains(col_id)) {
+        err!("Invalid collection ID provided")
+    }
+
+    org.confidential_collections = data.enabled;
+    org.save(&conn).await?;
+    Collection::set_confidential_by_organization(&org_id, &data.collection_ids, &conn).await?;
+
+    // The members with full access gain or lose items
+    for member in Membership::find_confirmed_by_org(&org_id, &conn).await {
+        User::update_uuid_revision(&member.user_uuid, &conn).await;
+    }
+
+    log_event(
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(confidential_collections_json(&org, &conn).await))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditGrantData {
+    member_id: MembershipId,
+    collection_ids: Vec<CollectionId>,
+    hours: u32,
+}
+
+/// Read-only, time-boxed access for auditors to selected collections.
+/// The auditor needs to be a confirmed member with the User role, which gives the access through regular collection rows.
+/// Those rows are removed again by the `AUDIT_GRANT_EXPIRY_SCHEDULE` job, and the grants and expirations are logged as events.
+#[get("/organizations/<org_id>/audit-grants")]
+async fn get_audit_grants(org_id: OrganizationId, headers: OwnerHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let grants: Vec<Value> = AuditGrant::find_by_org(&org_id, &conn).await.iter().map(AuditGrant::to_json).collect();
+    Ok(Json(json!({
+        "data": grants,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+#[post("/organizations/<org_id>/audit-grants", data = "<data>")]
+async fn post_audit_grants(
+    org_id: OrganizationId,
+    headers: OwnerHeaders,
+    data: Json<AuditGrantData>,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let data: AuditGrantData = data.into_inner();
+    if data.hours < 1 || data.hours > CONFIG.audit_grant_max_hours() {
+        err!(format!("The access can be granted for 1 to {} hours", CONFIG.audit_grant_max_hours()))
+    }
+    if data.collection_ids.is_empty() {
+        err!("No collections selected")
+    }
+
+    let Some(member) = Membership::find_by_uuid_and_org(&data.member_id, &org_id, &conn).await else {
+        err!("Member not found in organization")
+    };
+    // Other roles can already see more than the granted collections, so the grant wouldn't limit anything
+    if member.status != MembershipStatus::Confirmed as i32 || member.atype != MembershipType::User || member.access_all
+    {
+        err!("The auditor needs to be a confirmed member with the User role, without access to all collections")
+    }
+
+    let org_collections: HashSet<CollectionId> =
+        Collection::find_by_organization(&org_id, &conn).await.into_iter().map(|c| c.uuid).collect();
+    if data.collection_ids.iter().any(|col_id| !org_collections.contains(col_id)) {
+        err!("Invalid collection ID provided")
+    }
+
+    // Extending a grant is fine, but an expiring grant must not replace a regular access to the collection
+    let granted: HashSet<CollectionId> = AuditGrant::find_by_org_and_user(&org_id, &member.user_uuid, &conn)
+        .await
+        .into_iter()
+        .map(|g| g.collection_uuid)
+        .collect();
+    for col_id in &data.collection_ids {
+        if !granted.contains(col_id)
+            && CollectionUser::find_by_collection_and_user(col_id, &member.user_uuid, &conn).await.is_some()
+        {
+            err!("The member already has regular access to one of the collections")
+        }
+    }
+
+    let expires_at =
+        chrono::Utc::now().naive_utc() + chrono::TimeDelta::try_hours(i64::from(data.hours)).unwrap_or_default();
+    for col_id in data.collection_ids {
+        CollectionUser::save(&member.user_uuid, &col_id, true, false, false, &conn).await?;
+        AuditGrant::new(member.user_uuid.clone(), col_id, org_id.clone(), headers.user.uuid.clone(), expires_at)
+            .save(&conn)
+            .await?;
+    }
+
+    log_event(
+        EventType::OrganizationUserUpdated as i32,
+        &member.uuid,
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    let grants: Vec<Value> = AuditGrant::find_by_org_and_user(&org_id, &member.user_uuid, &conn)
+        .await
+        .iter()
+        .map(AuditGrant::to_json)
+        .collect();
+    Ok(Json(json!({
+        "data": grants,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+#[delete("/organizations/<org_id>/audit-grants/<member_id>")]
+async fn delete_audit_grants(
+    org_id: OrganizationId,
+    member_id: MembershipId,
+    headers: OwnerHeaders,
+    conn: DbConn,
+) -> EmptyResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(member) = Membership::find_by_uuid_and_org(&member_id, &org_id, &conn).await else {
+        err!("Member not found in organization")
+    };
+
+    for grant in AuditGrant::find_by_org_and_user(&org_id, &member.user_uuid, &conn).await {
+        revoke_audit_grant(grant, &conn).await?;
+    }
+
+    log_event(
+        EventType::OrganizationUserUpdated as i32,
+        &member.uuid,
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+    Ok(())
+}
+
+async fn revoke_audit_grant(grant: AuditGrant, conn: &DbConn) -> EmptyResult {
+    // Only the access given by the grant is removed, not one which was changed since
+    if grant.has_audit_access(conn).await
+        && let Some(col_user) =
+            CollectionUser::find_by_collection_and_user(&grant.collection_uuid, &grant.user_uuid, conn).await
+    {
+        col_user.delete(conn).await?;
+    }
+    grant.delete(conn).await
+}
+
+/// Logs an event for every granted collection an auditor read items of
+pub async fn log_audit_grant_reads<'a>(
+    headers: &Headers,
+    read_collections: impl IntoIterator<Item = &'a CollectionId>,
+    conn: &DbConn,
+) {
+    let grants = AuditGrant::find_active_by_user(&headers.user.uuid, &chrono::Utc::now().naive_utc(), conn).await;
+    if grants.is_empty() {
+        return;
+    }
+
+    let read_collections: HashSet<&CollectionId> = read_collections.into_iter().collect();
+    for grant in grants.iter().filter(|g| read_collections.contains(&g.collection_uuid)) {
+        log_event(
+            EventType::CollectionAuditGrantRead as i32,
+            &grant.collection_uuid,
+            &grant.org_uuid,
+            &headers.user.uuid,
+            headers.device.atype,
+            &headers.ip.ip,
+            conn,
+        )
+        .await;
+    }
+}
+
+/// Removes the collection access of the auditors whose grant expired
+pub async fn expire_audit_grants(pool: DbPool) {
+    debug!("Expiring audit grants");
+    let Ok(conn) = pool.get().await else {
+        error!("Failed to get DB connection while expiring audit grants");
+        return;
+    };
+
+    for grant in AuditGrant::find_expired(&chrono::Utc::now().naive_utc(), &conn).await {
+        let (user_uuid, org_uuid, granted_by) =
+            (grant.user_uuid.clone(), grant.org_uuid.clone(), grant.granted_by_uuid.clone());
+        if let Err(e) = revoke_audit_grant(grant, &conn).await {
+            error!("Error removing the expired audit access of {user_uuid}: {e:?}");
+            continue;
+        }
+        info!("Removed the expired audit access of {user_uuid} in organization {org_uuid}");
+
+        if let Some(member) = Membership::find_by_user_and_org(&user_uuid, &org_uuid, &conn).await {
+            log_event(
+                EventType::OrganizationUserUpdated as i32,
+                &member.uuid,
+                &org_uuid,
+                &granted_by,
+                DeviceType::UnknownBrowser as i32,
+                &IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                &conn,
+            )
+            .await;
+        }
+    }
+}
+
+// GET /api/collections?writeOnly=false
+#[get("/collections")]
+async fn get_user_collections(headers: Headers, conn: DbConn) -> Json<Value> {
+    Json(json!({
+        "data":
+            Collection::find_by_user_uuid(headers.user.uuid, &conn).await
+            .iter()
+            .map(Collection::to_json)
+            .collect::<Value>(),
+        "object": "list",
+        "continuationToken": null,
+    }))
+}
+
+// Called during the SSO enrollment
+// The `identifier` should be the value returned by `get_org_domain_sso_verified`
+// The returned `Id` will then be passed to `get_master_password_policy` which will mainly ignore it
+#[get("/organizations/<identifier>/auto-enroll-status")]
+async fn get_auto_enroll_status(identifier: &str, headers: Headers, conn: DbConn) -> JsonResult {
+    let org = if identifier == FAKE_SSO_IDENTIFIER {
+        match Membership::find_main_user_org(&headers.user.uuid, &conn).await {
+            Some(member) => Organization::find_by_uuid(&member.org_uuid, &conn).await,
+            None => None,
+        }
+    } else {
+        Organization::find_by_uuid(&identifier.into(), &conn).await
+    };
+
+    let (id, identifier, rp_auto_enroll) = match org {
+        None => (identifier.to_owned(), identifier.to_owned(), false),
+        Some(org) => (
+            org.uuid.to_string(),
+            org.uuid.to_string(),
+            OrgPolicy::org_is_reset_password_auto_enroll(&org.uuid, &conn).await,
+        ),
+    };
+
+    Ok(Json(json!({
+        "id": id,
+        "identifier": identifier,
+        "resetPasswordEnabled": rp_auto_enroll,
+    })))
+}
+
+#[get("/organizations/<org_id>/collections")]
+async fn get_org_collections(org_id: OrganizationId, headers: ManagerHeadersLoose, conn: DbConn) -> JsonResult {
+    if org_id != headers.membership.org_uuid {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    if !headers.membership.has_full_access() {
+        err_code!("Resource not found.", "User does not have full access", rocket::http::Status::NotFound.code);
+    }
+
+    Ok(Json(json!({
+        "data": get_org_collections_impl(&org_id, &conn).await,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+#[get("/organizations/<org_id>/collections/details")]
+async fn get_org_collections_details(org_id: OrganizationId, headers: ManagerHeadersLoose, conn: DbConn) -> JsonResult {
+    if org_id != headers.membership.org_uuid {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let Some(member) = Membership::find_by_user_and_org(&headers.user.uuid, &org_id, &conn).await else {
+        err!("User is not part of organization")
+    };
+
+    // get all collection memberships for the current organization
+    let col_users = CollectionUser::find_by_organization_swap_user_uuid_with_member_uuid(&org_id, &conn).await;
+    // Generate a HashMap to get the correct MembershipType per user to determine the manage permission
+    // We use the uuid instead of the user_uuid here, since that is what is used in CollectionUser
+    let membership_type: HashMap<MembershipId, i32> =
+        Membership::find_confirmed_by_org(&org_id, &conn).await.into_iter().map(|m| (m.uuid, m.atype)).collect();
+
+    // check if current user has full access to the organization (either directly or via any group)
+    let has_full_access_to_org = member.has_full_access()
+        || (CONFIG.org_groups_enabled() && GroupUser::has_full_access_by_member(&org_id, &member.uuid, &conn).await);
+
+    // Get all admins, owners and managers who can manage/access all
+    // Those are currently not listed in the col_users but need to be listed too.
+    let manage_all_members: Vec<Value> = Membership::find_confirmed_and_manage_all_by_org(&org_id, &conn)
+        .await
+        .into_iter()
+        .map(|member| {
+            json!({
+                "id": member.uuid,
+                "readOnly": false,
+                "hidePasswords": false,
+                "manage": true,
+            })
+        })
+        .collect();
+
+    let mut data = Vec::new();
+    for col in Collection::find_by_organization(&org_id, &conn).await {
+        // check whether the current user has access to the given collection
+        let assigned = has_full_access_to_org
+            || CollectionUser::has_access_to_collection_by_user(&col.uuid, &member.user_uuid, &conn).await
+            || (CONFIG.org_groups_enabled()
+                && GroupUser::has_access_to_collection_by_member(&col.uuid, &member.uuid, &conn).await);
+
+        // If the user is a manager, and is not assigned to this collection, skip this and continue with the next collection
+        if !assigned {
+            continue;
+        }
+
+        // get the users assigned directly to the given collection
+        let mut users: Vec<Value> = col_users
+            .iter()
+            .filter(|collection_member| collection_member.collection_uuid == col.uuid)
+            .map(|collection_member| {
+                collection_member.to_json_details_for_member(
+                    *membership_type.get(&collection_member.membership_uuid).unwrap_or(&(MembershipType::User as i32)),
+                )
+            })
+            .collect();
+        users.extend_from_slice(&manage_all_members);
+
+        // get the group details for the given collection
+        let groups: Vec<Value> = if CONFIG.org_groups_enabled() {
+            CollectionGroup::find_by_collection(&col.uuid, &conn)
+                .await
+                .iter()
+                .map(CollectionGroup::to_json_details_for_group)
+                .collect()
+        } else {
+            Vec::with_capacity(0)
+        };
+
+        let mut json_object = col.to_json_details(&headers.user.uuid, None, &conn).await;
+        json_object["assigned"] = json!(assigned);
+        json_object["users"] = json!(users);
+        json_object["groups"] = json!(groups);
+        json_object["object"] = json!("collectionAccessDetails");
+        json_object["unmanaged"] = json!(false);
+        data.push(json_object);
+    }
+
+    Ok(Json(json!({
+        "data": data,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+async fn get_org_collections_impl(org_id: &OrganizationId, conn: &DbConn) -> Value {
+    Collection::find_by_organization(org_id, conn).await.iter().map(Collection::to_json).collect::<Value>()
+}
+
+#[post("/organizations/<org_id>/collections", data = "<data>")]
+async fn post_organization_collections(
+    org_id: OrganizationId,
+    headers: ManagerHeadersLoose,
+    data: Json<FullCollectionData>,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.membership.org_uuid {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let data: FullCollectionData = data.into_inner();
+    data.validate(&org_id, &conn).await?;
+
+    let Some(org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+    if !org.can_create_collections(&headers.membership) {
+        err!("You don't have permission to create collections")
+    }
+
+    let collection = Collection::new(org_id.clone(), data.name, data.external_id);
+    collection.save(&conn).await?;
+
+    log_event(
+        EventType::CollectionCreated as i32,
+        &collection.uuid,
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    for group in data.groups {
+        CollectionGroup::new(collection.uuid.clone(), group.id, group.read_only, group.hide_passwords, group.manage)
+            .save(&org_id, &conn)
+            .await?;
+    }
+
+    for user in data.users {
+        let Some(member) = Membership::find_by_uuid_and_org(&user.id, &org_id, &conn).await else {
+            err!("User is not part of organization")
+        };
+
+        if member.access_all {
+            continue;
+        }
+
+        CollectionUser::save(
+            &member.user_uuid,
             &collection.uuid,
             user.read_only,
             user.hide_passwords,
@@ -620,6 +1238,7 @@ async fn post_bulk_access_collections(
                 .await?;
         }
     }
+    AuditGrant::delete_stale_by_org(&org_id, &conn).await?;
 
     Ok(())
 }
@@ -698,6 +1317,7 @@ async fn post_organization_collection_update(
         CollectionUser::save(&member.user_uuid, &col_id, user.read_only, user.hide_passwords, user.manage, &conn)
             .await?;
     }
+    AuditGrant::delete_stale_by_org(&org_id, &conn).await?;
 
     Ok(Json(collection.to_json_details(&headers.user.uuid, None, &conn).await))
 }
@@ -711,6 +1331,15 @@ async fn delete_organization_collection_impl(
     if org_id != &headers.org_id {
         err!("Organization not found", "Organization id's do not match");
     }
+    let (Some(org), Some(member)) = (
+        Organization::find_by_uuid(org_id, conn).await,
+        Membership::find_confirmed_by_user_and_org(&headers.user.uuid, org_id, conn).await,
+    ) else {
+        err!("Organization not found")
+    };
+    if !org.can_delete_collections(&member) {
+        err!("You don't have permission to delete collections")
+    }
     let Some(collection) = Collection::find_by_uuid_and_org(col_id, org_id, conn).await else {
         err!("Collection not found", "Collection does not exist or does not belong to this organization")
     };
@@ -893,13 +1522,41 @@ async fn get_org_details(data: OrgIdData, headers: ManagerHeadersLoose, conn: Db
     })))
 }
 
+/// Lists only the items in the collections the member can manage, for the admin console views of members without full access
+#[get("/ciphers/organization-details/assigned?<data..>")]
+async fn get_org_details_assigned(data: OrgIdData, headers: OrgMemberHeaders, conn: DbConn) -> JsonResult {
+    if data.organization_id != headers.membership.org_uuid {
+        err_code!("Resource not found.", "Organization id's do not match", rocket::http::Status::NotFound.code);
+    }
+
+    let ciphers = Cipher::find_managed_by_user_and_org(&headers.user.uuid, &data.organization_id, &conn).await;
+    let cipher_sync_data = CipherSyncData::new(&headers.user.uuid, CipherSyncType::Organization, &conn).await;
+
+    let mut ciphers_json = Vec::with_capacity(ciphers.len());
+    for c in ciphers {
+        ciphers_json.push(
+            c.to_json(&headers.host, &headers.user.uuid, Some(&cipher_sync_data), CipherSyncType::Organization, &conn)
+                .await?,
+        );
+    }
+
+    Ok(Json(json!({
+        "data": ciphers_json,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
 async fn get_org_details_impl(
     org_id: &OrganizationId,
     host: &str,
     user_id: &UserId,
     conn: &DbConn,
 ) -> Result<Value, crate::Error> {
-    let ciphers = Cipher::find_by_org(org_id, conn).await;
+    let mut ciphers = Cipher::find_by_org(org_id, conn).await;
+    // The items of confidential collections are only listed for the members assigned to them
+    let hidden = Cipher::find_hidden_confidential_uuids(user_id, conn).await;
+    ciphers.retain(|c| !hidden.contains(&c.uuid));
     let cipher_sync_data = CipherSyncData::new(user_id, CipherSyncType::Organization, conn).await;
 
     let mut ciphers_json = Vec::with_capacity(ciphers.len());
@@ -909,6 +1566,161 @@ async fn get_org_details_impl(
     Ok(json!(ciphers_json))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrgTrashData {
+    ids: Vec<CipherId>,
+}
+
+/// Lists every item of the organization in the trash, with who deleted it.
+/// The trash of the admin console only shows the items in the collections the admin can see.
+#[get("/organizations/<org_id>/trash")]
+async fn get_org_trash(org_id: OrganizationId, headers: AdminHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let mut ciphers = Cipher::find_deleted_by_org(&org_id, &conn).await;
+    // The items of confidential collections are only listed for the members assigned to them
+    let hidden = Cipher::find_hidden_confidential_uuids(&headers.user.uuid, &conn).await;
+    ciphers.retain(|c| !hidden.contains(&c.uuid));
+    let cipher_sync_data = CipherSyncData::new(&headers.user.uuid, CipherSyncType::Organization, &conn).await;
+
+    let mut deleted_by_users: HashMap<UserId, Value> = HashMap::new();
+    let mut ciphers_json = Vec::with_capacity(ciphers.len());
+    for c in ciphers {
+        let deleted_by = if let Some(user_id) = &c.deleted_by {
+            if let Some(user_json) = deleted_by_users.get(user_id) {
+                user_json.clone()
+            } else {
+                let user_json = User::find_by_uuid(user_id, &conn).await.map_or(Value::Null, |u| {
+                    json!({
+                        "id": u.uuid,
+                        "name": u.name,
+                        "email": u.email,
+                    })
+                });
+                deleted_by_users.insert(user_id.clone(), user_json.clone());
+                user_json
+            }
+        } else {
+            Value::Null
+        };
+
+        let mut cipher_json = c
+            .to_json(&headers.host, &headers.user.uuid, Some(&cipher_sync_data), CipherSyncType::Organization, &conn)
+            .await?;
+        cipher_json["deletedBy"] = deleted_by;
+        ciphers_json.push(cipher_json);
+    }
+
+    Ok(Json(json!({
+        "data": ciphers_json,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+// All the selected items need to be in the trash of the organization, otherwise nothing is changed.
+// Like in the listing, the items of confidential collections the member isn't assigned to can't be selected.
+async fn find_org_trash(
+    org_id: &OrganizationId,
+    user_id: &UserId,
+    ids: &[CipherId],
+    conn: &DbConn,
+) -> ApiResult<Vec<Cipher>> {
+    let hidden = Cipher::find_hidden_confidential_uuids(user_id, conn).await;
+    let mut ciphers = Vec::with_capacity(ids.len());
+    for cipher_id in ids {
+        match Cipher::find_by_uuid_and_org(cipher_id, org_id, conn).await {
+            Some(cipher) if cipher.deleted_at.is_some() && !hidden.contains(&cipher.uuid) => ciphers.push(cipher),
+            _ => err!(
+                "Item not found in the trash of the organization",
+                format!("Cipher {cipher_id} is not in the trash")
+            ),
+        }
+    }
+    Ok(ciphers)
+}
+
+#[post("/organizations/<org_id>/trash/restore", data = "<data>")]
+async fn restore_org_trash(
+    org_id: OrganizationId,
+    data: Json<OrgTrashData>,
+    headers: AdminHeaders,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    for mut cipher in find_org_trash(&org_id, &headers.user.uuid, &data.into_inner().ids, &conn).await? {
+        cipher.deleted_at = None;
+        cipher.deleted_by = None;
+        cipher.save(&conn).await?;
+        nt.send_cipher_update(
+            UpdateType::SyncCipherUpdate,
+            &cipher,
+            &cipher.update_users_revision(&conn).await,
+            &headers.device,
+            None,
+            &conn,
+        )
+        .await;
+
+        log_event(
+            EventType::CipherRestored as i32,
+            &cipher.uuid,
+            &org_id,
+            &headers.user.uuid,
+            headers.device.atype,
+            &headers.ip.ip,
+            &conn,
+        )
+        .await;
+    }
+    Ok(())
+}
+
+#[post("/organizations/<org_id>/trash/delete", data = "<data>")]
+async fn delete_org_trash(
+    org_id: OrganizationId,
+    data: Json<OrgTrashData>,
+    headers: AdminHeaders,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    for cipher in find_org_trash(&org_id, &headers.user.uuid, &data.into_inner().ids, &conn).await? {
+        cipher.delete(&conn).await?;
+        nt.send_cipher_update(
+            UpdateType::SyncLoginDelete,
+            &cipher,
+            &cipher.update_users_revision(&conn).await,
+            &headers.device,
+            None,
+            &conn,
+        )
+        .await;
+
+        log_event(
+            EventType::CipherDeleted as i32,
+            &cipher.uuid,
+            &org_id,
+            &headers.user.uuid,
+            headers.device.atype,
+            &headers.ip.ip,
+            &conn,
+        )
+        .await;
+    }
+    Ok(())
+}
+
 // Returning a Domain/Organization here allow to prefill it and prevent prompting the user
 // So we return a dummy value, since we only support a single SSO integration, and do not use the response anywhere
 // In use since `v2025.6.0`, appears to use only the first `organizationIdentifier`
@@ -1065,7 +1877,8 @@ async fn send_invite(
     let mut user_created: bool = false;
     for email in &data.emails {
         let mut member_status = MembershipStatus::Invited as i32;
-        let user = match User::find_by_mail(email, &conn).await {
+        // With subaddress normalization an invite to another subaddress goes to the existing account
+        let user = match User::find_by_mail_or_subaddress(email, &conn).await {
             None => {
                 if !CONFIG.invitations_allowed() {
                     err!(format!("User does not exist: {email}"))
@@ -1080,11 +1893,15 @@ async fn send_invite(
                 }
 
                 let mut new_user = User::new(email, None);
+                new_user.tenant_id.clone_from(&headers.user.tenant_id);
                 new_user.save(&conn).await?;
                 user_created = true;
                 new_user
             }
             Some(user) => {
+                if user.tenant_id != headers.user.tenant_id {
+                    err!(format!("User belongs to another tenant: {email}"))
+                }
                 if Membership::find_by_user_and_org(&user.uuid, &org_id, &conn).await.is_some() {
                     err!(format!("User already in organization: {email}"))
                 }
@@ -1205,6 +2022,76 @@ async fn bulk_reinvite_members(
     })))
 }
 
+/// Lists the invitations which weren't accepted yet, with their expiration
+#[get("/organizations/<org_id>/users/invites")]
+async fn get_pending_invites(org_id: OrganizationId, headers: AdminHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let mut invites = Vec::new();
+    let mut expired_count = 0;
+    for member in Membership::find_invited_by_org(&org_id, &conn).await {
+        let Some(user) = User::find_by_uuid(&member.user_uuid, &conn).await else {
+            continue;
+        };
+        let expiration = member.invite_expiration();
+        let expired = expiration.is_some_and(|exp| exp < now);
+        if expired {
+            expired_count += 1;
+        }
+        invites.push(json!({
+            "id": member.uuid,
+            "email": user.email,
+            "invitedDate": member.invited_at.as_ref().map(format_date),
+            "expirationDate": expiration.as_ref().map(format_date),
+            "expired": expired,
+            "object": "organizationPendingInvite",
+        }));
+    }
+
+    Ok(Json(json!({
+        "data": invites,
+        "expiredCount": expired_count,
+        "object": "list",
+        "continuationToken": null
+    })))
+}
+
+/// Sends a new invitation to all members whose invitation has expired
+#[post("/organizations/<org_id>/users/reinvite-expired")]
+async fn reinvite_expired_members(org_id: OrganizationId, headers: AdminHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let mut bulk_response = Vec::new();
+    for member in Membership::find_invited_by_org(&org_id, &conn).await {
+        if !member.is_invite_expired() {
+            continue;
+        }
+        let err_msg = match reinvite_member_impl(&org_id, &member.uuid, &headers.user.email, &conn).await {
+            Ok(()) => String::new(),
+            Err(e) => format!("{e:?}"),
+        };
+
+        bulk_response.push(json!(
+            {
+                "object": "OrganizationBulkConfirmResponseModel",
+                "id": member.uuid,
+                "error": err_msg
+            }
+        ));
+    }
+
+    Ok(Json(json!({
+        "data": bulk_response,
+        "object": "list",
+        "continuationToken": null
+    })))
+}
+
 #[post("/organizations/<org_id>/users/<member_id>/reinvite")]
 async fn reinvite_member(
     org_id: OrganizationId,
@@ -1218,7 +2105,7 @@ async fn reinvite_member(
     reinvite_member_impl(&org_id, &member_id, &headers.user.email, &conn).await
 }
 
-async fn reinvite_member_impl(
+pub async fn reinvite_member_impl(
     org_id: &OrganizationId,
     member_id: &MembershipId,
     invited_by_email: &str,
@@ -1246,19 +2133,21 @@ async fn reinvite_member_impl(
         err!("Error looking up organization.")
     };
 
+    let mut member = member;
     if CONFIG.mail_enabled() {
-        mail::send_invite(&user, org_id.clone(), member.uuid, &org_name, Some(invited_by_email.to_owned())).await?;
+        mail::send_invite(&user, org_id.clone(), member.uuid.clone(), &org_name, Some(invited_by_email.to_owned()))
+            .await?;
     } else if user.password_hash.is_empty() {
         let invitation = Invitation::new(&user.email);
         invitation.save(conn).await?;
     } else {
         Invitation::take(&user.email, conn).await;
-        let mut member = member;
         member.status = MembershipStatus::Accepted as i32;
-        member.save(conn).await?;
     }
 
-    Ok(())
+    // The expiration of the invitation starts again
+    member.invited_at = Some(chrono::Utc::now().naive_utc());
+    member.save(conn).await
 }
 
 #[derive(Deserialize)]
@@ -1317,7 +2206,7 @@ async fn accept_invite(
     } else if CONFIG.mail_enabled() {
         // User was invited from /admin, so they are automatically confirmed
         let org_name = CONFIG.invitation_org_name();
-        mail::send_invite_confirmed(&claims.email, &org_name).await?;
+        mail::send_invite_confirmed(&claims.email, &org_name, None).await?;
     }
 
     Ok(())
@@ -1404,6 +2293,23 @@ async fn confirm_invite_impl(
     if org_id != &headers.org_id {
         err!("Organization not found", "Organization id's do not match");
     }
+    confirm_member(org_id, member_id, key, Some(headers), &headers.ip.ip, conn, nt).await
+}
+
+// Acting user of the events of the changes made through the public API of an organization
+const ACTING_PUBLIC_API_USER: &str = "vaultwarden-public-api-0000000000000";
+
+/// Confirms an accepted member with the organization key encrypted with the public key of the member.
+/// Without `headers` the member is confirmed through the public API, which may confirm members of any type.
+pub async fn confirm_member(
+    org_id: &OrganizationId,
+    member_id: &MembershipId,
+    key: &str,
+    headers: Option<&AdminHeaders>,
+    ip: &IpAddr,
+    conn: &DbConn,
+    nt: &Notify<'_>,
+) -> EmptyResult {
     if key.is_empty() || member_id.is_empty() {
         err!("Key or UserId is not set, unable to process request");
     }
@@ -1412,7 +2318,10 @@ async fn confirm_invite_impl(
         err!("The specified user isn't a member of the organization")
     };
 
-    if member_to_confirm.atype != MembershipType::User && headers.membership_type != MembershipType::Owner {
+    if let Some(headers) = headers
+        && member_to_confirm.atype != MembershipType::User
+        && headers.membership_type != MembershipType::Owner
+    {
         err!("Only Owners can confirm Managers, Admins or Owners")
     }
 
@@ -1430,31 +2339,37 @@ async fn confirm_invite_impl(
         EventType::OrganizationUserConfirmed as i32,
         &member_to_confirm.uuid,
         org_id,
-        &headers.user.uuid,
-        headers.device.atype,
-        &headers.ip.ip,
+        &headers.map_or_else(|| ACTING_PUBLIC_API_USER.into(), |h| h.user.uuid.clone()),
+        headers.map_or(DeviceType::UnknownBrowser as i32, |h| h.device.atype),
+        ip,
         conn,
     )
     .await;
 
+    let Some(org) = Organization::find_by_uuid(org_id, conn).await else {
+        err!("Error looking up organization.")
+    };
+
     if CONFIG.mail_enabled() {
-        let org_name = if let Some(org) = Organization::find_by_uuid(org_id, conn).await {
-            org.name
-        } else {
-            err!("Error looking up organization.")
-        };
         let address = if let Some(user) = User::find_by_uuid(&member_to_confirm.user_uuid, conn).await {
             user.email
         } else {
             err!("Error looking up user.")
         };
-        mail::send_invite_confirmed(&address, &org_name).await?;
+        mail::send_invite_confirmed(&address, &org.name, org.welcome_message.as_deref()).await?;
     }
 
-    let save_result = member_to_confirm.save(conn).await;
+    // Members with access to all collections already see the onboarding collections
+    let onboarding_collections: Vec<CollectionId> = if member_to_confirm.access_all {
+        Vec::new()
+    } else {
+        Collection::find_onboarding_by_organization(org_id, conn).await.into_iter().map(|c| c.uuid).collect()
+    };
+    let save_result = member_to_confirm.confirm_with_collections(&onboarding_collections, conn).await;
 
     if let Some(user) = User::find_by_uuid(&member_to_confirm.user_uuid, conn).await {
-        nt.send_user_update(UpdateType::SyncOrgKeys, &user, headers.device.push_uuid.as_ref(), conn).await;
+        let push_uuid = headers.and_then(|h| h.device.push_uuid.as_ref());
+        nt.send_user_update(UpdateType::SyncOrgKeys, &user, push_uuid, conn).await;
     }
 
     save_result
@@ -1495,7 +2410,20 @@ async fn get_user(
     // In this case, when groups are requested we also need to include collections.
     // Else these will not be shown in the interface, and could lead to missing collections when saved.
     let include_groups = data.include_groups.unwrap_or(false);
-    Ok(Json(user.to_json_user_details(data.include_collections.unwrap_or(include_groups), include_groups, &conn).await))
+    let mut member_json =
+        user.to_json_user_details(data.include_collections.unwrap_or(include_groups), include_groups, &conn).await;
+
+    // Members of organizations which require a password hint share it with the admins
+    let hint_required = OrgPolicy::find_by_org_and_type(&org_id, OrgPolicyType::MasterPassword, &conn)
+        .await
+        .filter(|p| p.enabled)
+        .and_then(|p| serde_json::from_str::<PasswordHintPolicyData>(&p.data).ok())
+        .is_some_and(|p| p.require_password_hint);
+    if hint_required && user.status >= MembershipStatus::Accepted as i32 {
+        member_json["passwordHint"] =
+            json!(User::find_by_uuid(&user.user_uuid, &conn).await.and_then(|u| u.password_hint));
+    }
+    Ok(Json(member_json))
 }
 
 #[derive(Deserialize)]
@@ -1506,6 +2434,8 @@ struct EditUserData {
     groups: Option<Vec<GroupId>>,
     #[serde(default)]
     permissions: HashMap<String, Value>,
+    // Vaultwarden specific, allows the member to create and list the Sends of the organization
+    manage_sends: Option<bool>,
 }
 
 #[put("/organizations/<org_id>/users/<member_id>", data = "<data>", rank = 1)]
@@ -1576,6 +2506,9 @@ async fn edit_member(
 
     member_to_edit.access_all = access_all;
     member_to_edit.atype = new_type as i32;
+    if let Some(manage_sends) = data.manage_sends {
+        member_to_edit.manage_sends = manage_sends;
+    }
 
     // This check is also done at accept_invite, _confirm_invite, _activate_member, edit_member, admin::update_membership_type
     // We need to perform the check after changing the type since `admin` is exempt.
@@ -1606,6 +2539,8 @@ async fn edit_member(
         }
     }
 
+    AuditGrant::delete_stale_by_org(&org_id, &conn).await?;
+
     GroupUser::delete_all_by_member(&member_to_edit.uuid, &conn).await?;
 
     for group_id in data.groups.iter().flatten() {
@@ -1759,7 +2694,7 @@ async fn bulk_public_keys(
 }
 
 use super::ciphers::CipherData;
-use super::ciphers::update_cipher_from_data;
+use super::ciphers::{enforce_item_quota, enforce_reprompt_policy_on_collections, update_cipher_from_data};
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -1792,12 +2727,16 @@ async fn post_org_import(
         err!("Organization not found", "Organization id's do not match");
     }
     let data: ImportData = data.into_inner();
+    let Some(org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
 
     // Validate the import before continuing
     // Bitwarden does not process the import if there is one item invalid.
     // Since we check for the size of the encrypted note length, we need to do that here to pre-validate it.
     // TODO: See if we can optimize the whole cipher adding/importing and prevent duplicate code and checks.
     Cipher::validate_cipher_data(&data.ciphers)?;
+    let mut quota_charge = enforce_item_quota(&headers.user, data.ciphers.len(), false, &conn).await?;
 
     let existing_collections: HashSet<Option<CollectionId>> =
         Collection::find_by_organization(&org_id, &conn).await.into_iter().map(|c| Some(c.uuid)).collect();
@@ -1813,9 +2752,10 @@ async fn post_org_import(
             }
             col_id
         } else {
-            // We do not allow users or managers which can not manage all collections to create new collections
+            // Members which can manage all collections can always import new collections,
+            // other members only when the collection management settings of the organization allow it.
             // If there is any collection other than an existing import collection, abort the import.
-            if headers.membership.atype <= MembershipType::Manager && !headers.membership.has_full_access() {
+            if !headers.membership.has_full_access() && !org.can_create_collections(&headers.membership) {
                 err!(Compact, "The current user isn't allowed to create new collections")
             }
             let new_collection = Collection::new(org_id.clone(), col.name, col.external_id);
@@ -1829,28 +2769,36 @@ async fn post_org_import(
     // Read the relations between collections and ciphers
     // Ciphers can be in multiple collections at the same time
     let mut relations = Vec::with_capacity(data.collection_relationships.len());
+    let mut cipher_collections: HashMap<usize, Vec<CollectionId>> = HashMap::new();
     for relation in data.collection_relationships {
+        if let Some(col_id) = collections.get(relation.value) {
+            cipher_collections.entry(relation.key).or_default().push(col_id.clone());
+        }
         relations.push((relation.key, relation.value));
     }
 
     let headers: Headers = headers.into();
 
     let mut ciphers: Vec<CipherId> = Vec::with_capacity(data.ciphers.len());
-    for mut cipher_data in data.ciphers {
+    for (index, mut cipher_data) in data.ciphers.into_iter().enumerate() {
         // Always clear folder_id's via an organization import
         cipher_data.folder_id = None;
         let mut cipher = Cipher::new(cipher_data.r#type, cipher_data.name.clone());
-        update_cipher_from_data(
+        // Only the collections of this cipher decide whether the reprompt policy applies to it
+        if update_cipher_from_data(
             &mut cipher,
             cipher_data,
             &headers,
-            Some(collections.clone()),
+            Some(cipher_collections.remove(&index).unwrap_or_default()),
             &conn,
             &nt,
             UpdateType::None,
         )
         .await
-        .ok();
+        .is_ok()
+        {
+            quota_charge.created(1);
+        }
         ciphers.push(cipher.uuid);
     }
 
@@ -1865,6 +2813,116 @@ async fn post_org_import(
     user.update_revision(&conn).await
 }
 
+// Exports the items of one collection in the format of `get_org_details`, ready for `post_collection_import`.
+// The items stay encrypted with the key of the organization, so they can only be imported in the same organization.
+#[get("/organizations/<org_id>/collections/<col_id>/export")]
+async fn get_collection_export(
+    org_id: OrganizationId,
+    col_id: CollectionId,
+    headers: ManagerHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(collection) = Collection::find_by_uuid_and_org(&col_id, &org_id, &conn).await else {
+        err!("Collection not found", "Collection does not exist or does not belong to this organization")
+    };
+
+    let mut ciphers = Cipher::find_by_collection(&collection.uuid, &conn).await;
+    let hidden = Cipher::find_hidden_confidential_uuids(&headers.user.uuid, &conn).await;
+    ciphers.retain(|c| !hidden.contains(&c.uuid));
+    let cipher_sync_data = CipherSyncData::new(&headers.user.uuid, CipherSyncType::Organization, &conn).await;
+
+    let mut ciphers_json = Vec::with_capacity(ciphers.len());
+    for c in ciphers {
+        ciphers_json.push(
+            c.to_json(&headers.host, &headers.user.uuid, Some(&cipher_sync_data), CipherSyncType::Organization, &conn)
+                .await?,
+        );
+    }
+
+    Ok(Json(json!({
+        "collection": collection.to_json(),
+        "ciphers": ciphers_json,
+        "object": "collectionExport",
+    })))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CollectionImportData {
+    ciphers: Vec<CipherData>,
+}
+
+// Creates the items directly in the collection, they need to be encrypted with the key of the organization.
+// The items of an export are copied, the originals are not moved or removed from their collections.
+#[post("/organizations/<org_id>/collections/<col_id>/import", data = "<data>")]
+async fn post_collection_import(
+    org_id: OrganizationId,
+    col_id: CollectionId,
+    data: Json<CollectionImportData>,
+    headers: ManagerHeaders,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(collection) = Collection::find_by_uuid_and_org(&col_id, &org_id, &conn).await else {
+        err!("Collection not found", "Collection does not exist or does not belong to this organization")
+    };
+    let data: CollectionImportData = data.into_inner();
+
+    // Like the organization import, nothing is imported if one of the items is invalid
+    Cipher::validate_cipher_data(&data.ciphers)?;
+    let mut quota_charge = enforce_item_quota(&headers.user, data.ciphers.len(), false, &conn).await?;
+
+    let headers: Headers = headers.into();
+    let mut imported = 0;
+    for mut cipher_data in data.ciphers {
+        // An exported item keeps its id, organization and folder, these never apply to the new copy
+        cipher_data.id = None;
+        cipher_data.folder_id = None;
+        cipher_data.organization_id = Some(org_id.clone());
+
+        let mut cipher = Cipher::new(cipher_data.r#type, cipher_data.name.clone());
+        update_cipher_from_data(
+            &mut cipher,
+            cipher_data,
+            &headers,
+            Some(vec![collection.uuid.clone()]),
+            &conn,
+            &nt,
+            UpdateType::None,
+        )
+        .await?;
+        quota_charge.created(1);
+        CollectionCipher::save(&cipher.uuid, &collection.uuid, &conn).await?;
+        imported += 1;
+    }
+
+    log_event(
+        EventType::CollectionUpdated as i32,
+        &collection.uuid,
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    collection.update_users_revision(&conn).await;
+    let mut user = headers.user;
+    user.update_revision(&conn).await?;
+
+    Ok(Json(json!({
+        "imported": imported,
+        "object": "collectionImport",
+    })))
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct BulkCollectionsData {
@@ -1910,7 +2968,7 @@ async fn post_bulk_collections(data: Json<BulkCollectionsData>, headers: Headers
     for cipher_id in &data.cipher_ids {
         // Only act on existing cipher uuid's
         // Do not abort the operation just ignore it, it could be a cipher was just deleted for example
-        if let Some(cipher) = Cipher::find_by_uuid_and_org(cipher_id, &data.organization_id, &conn).await
+        if let Some(mut cipher) = Cipher::find_by_uuid_and_org(cipher_id, &data.organization_id, &conn).await
             && cipher.is_write_accessible_to_user(&headers.user.uuid, &conn).await
         {
             // When selecting a specific collection from the left filter list, and use the bulk option, you can remove an item from that collection
@@ -1923,6 +2981,7 @@ async fn post_bulk_collections(data: Json<BulkCollectionsData>, headers: Headers
                 for collection in &data.collection_ids {
                     CollectionCipher::save(&cipher.uuid, collection, &conn).await?;
                 }
+                enforce_reprompt_policy_on_collections(&mut cipher, &conn).await?;
             }
         }
     }
@@ -1931,18 +2990,23 @@ async fn post_bulk_collections(data: Json<BulkCollectionsData>, headers: Headers
 }
 
 #[get("/organizations/<org_id>/policies")]
-async fn list_policies(org_id: OrganizationId, headers: AdminHeaders, conn: DbConn) -> JsonResult {
+async fn list_policies(org_id: OrganizationId, headers: AdminHeaders, conn: DbConn) -> ApiResult<ETagged> {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
     }
-    let policies = OrgPolicy::find_by_org(&org_id, &conn).await;
-    let policies_json: Vec<Value> = policies.iter().map(OrgPolicy::to_json).collect();
+    let policies_json = response_cache::get_or_load(CacheKey::Policies(org_id.clone()), async {
+        let policies = OrgPolicy::find_by_org(&org_id, &conn).await;
+        let policies_json: Vec<Value> =
+            policies.iter().filter(|p| p.is_known_to_clients()).map(OrgPolicy::to_json).collect();
+        json!({
+            "data": policies_json,
+            "object": "list",
+            "continuationToken": null
+        })
+    })
+    .await;
 
-    Ok(Json(json!({
-        "data": policies_json,
-        "object": "list",
-        "continuationToken": null
-    })))
+    Ok(ETagged(policies_json))
 }
 
 #[get("/organizations/<org_id>/policies/token?<token>")]
@@ -1960,7 +3024,8 @@ async fn list_policies_token(org_id: OrganizationId, token: &str, conn: DbConn)
 
     // TODO: We receive the invite token as ?token=<>, validate it contains the org id
     let policies = OrgPolicy::find_by_org(&org_id, &conn).await;
-    let policies_json: Vec<Value> = policies.iter().map(OrgPolicy::to_json).collect();
+    let policies_json: Vec<Value> =
+        policies.iter().filter(|p| p.is_known_to_clients()).map(OrgPolicy::to_json).collect();
 
     Ok(Json(json!({
         "data": policies_json,
@@ -2069,10 +3134,59 @@ async fn put_policy(
         }
     }
 
-    // When enabling the TwoFactorAuthentication policy, revoke all members that do not have 2FA
+    // The password hint rules are sent along with the data of the MasterPassword policy
+    if pol_type_enum == OrgPolicyType::MasterPassword
+        && let Some(value) = data.data.as_ref().filter(|v| !v.is_null())
+    {
+        let Ok(hint_data) = serde_json::from_value::<PasswordHintPolicyData>(value.clone()) else {
+            err!("Invalid password hint policy data")
+        };
+        if hint_data.disable_password_hint && hint_data.require_password_hint {
+            err!("Password hints can't be disabled and required at the same time")
+        }
+        if hint_data.max_password_hint_length == Some(0) {
+            err!("The maximum length of the password hints must be at least 1")
+        }
+    }
+
+    if pol_type_enum == OrgPolicyType::BlockAttachments
+        && let Some(value) = data.data.as_ref().filter(|v| !v.is_null())
+    {
+        let Ok(attachment_data) = serde_json::from_value::<AttachmentPolicyData>(value.clone()) else {
+            err!("Invalid attachment policy data")
+        };
+        if attachment_data.cipher_types.iter().any(|t| !(1..=5).contains(t)) {
+            err!("Invalid cipher type in the attachment policy data")
+        }
+    }
+
+    if pol_type_enum == OrgPolicyType::TrashRetention && data.enabled {
+        let Some(retention_data) =
+            data.data.clone().and_then(|v| serde_json::from_value::<TrashRetentionPolicyData>(v).ok())
+        else {
+            err!("Invalid trash retention policy data")
+        };
+        if !(1..=3650).contains(&retention_data.days) {
+            err!("The trash retention needs to be between 1 and 3650 days")
+        }
+    }
+
+    // When enabling the TwoFactorAuthentication policy, revoke all members that do not have 2FA,
+    // or give them until the end of the grace period to enable it
     if pol_type_enum == OrgPolicyType::TwoFactorAuthentication && data.enabled {
+        let policy_data: TwoFactorPolicyData = match &data.data {
+            Some(Value::Null) | None => TwoFactorPolicyData::default(),
+            Some(value) => match serde_json::from_value(value.clone()) {
+                Ok(policy_data) => policy_data,
+                Err(_) => err!("Invalid two-step login policy data"),
+            },
+        };
+        if policy_data.grace_period_days.is_some_and(|days| !(0..=365).contains(&days)) {
+            err!("The grace period must be between 0 and 365 days")
+        }
         two_factor::enforce_2fa_policy_for_org(
             &org_id,
+            policy_data.grace_period(),
             &headers.user.uuid,
             headers.device.atype,
             &headers.ip.ip,
@@ -2081,6 +3195,27 @@ async fn put_policy(
         .await?;
     }
 
+    // When enabling the CipherReprompt policy, require the master password for the existing items it applies to.
+    // New and edited items are covered when they are saved.
+    if pol_type_enum == OrgPolicyType::CipherReprompt && data.enabled {
+        let policy_data: RepromptPolicyData = match &data.data {
+            Some(Value::Null) | None => RepromptPolicyData::default(),
+            Some(value) => match serde_json::from_value(value.clone()) {
+                Ok(policy_data) => policy_data,
+                Err(_) => err!("Invalid reprompt policy data"),
+            },
+        };
+        for col_id in &policy_data.collection_ids {
+            if Collection::find_by_uuid_and_org(col_id, &org_id, &conn).await.is_none() {
+                err!("Collection not found", "Collection is not part of this organization")
+            }
+        }
+        Cipher::force_reprompt_by_org(&org_id, policy_data.collection_ids, &conn).await?;
+        for member in Membership::find_by_org(&org_id, &conn).await {
+            User::update_uuid_revision(&member.user_uuid, &conn).await;
+        }
+    }
+
     // When enabling the SingleOrg policy, remove this org's members that are members of other orgs
     if pol_type_enum == OrgPolicyType::SingleOrg && data.enabled {
         for mut member in Membership::find_by_org(&org_id, &conn).await {
@@ -2110,24 +3245,363 @@ async fn put_policy(
                 )
                 .await;
 
-                member.revoke();
-                member.save(&conn).await?;
-            }
-        }
+                member.revoke();
+                member.save(&conn).await?;
+            }
+        }
+    }
+
+    let mut policy = match OrgPolicy::find_by_org_and_type(&org_id, pol_type_enum, &conn).await {
+        Some(p) => p,
+        None => OrgPolicy::new(org_id.clone(), pol_type_enum, false, "{}".to_owned()),
+    };
+
+    policy.enabled = data.enabled;
+    policy.data = serde_json::to_string(&data.data)?;
+    policy.save(&conn).await?;
+
+    log_event(
+        EventType::PolicyUpdated as i32,
+        policy.uuid.as_ref(),
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(policy.to_json()))
+}
+
+#[derive(Deserialize)]
+struct PolicyDataVnext {
+    policy: PolicyData,
+    // Ignore metadata for now as we do not yet support this
+    // "metadata": {
+    //     "defaultUserCollectionName": "2.xx|xx==|xx="
+    // }
+}
+
+#[put("/organizations/<org_id>/policies/<pol_type>/vnext", data = "<data>")]
+async fn put_policy_vnext(
+    org_id: OrganizationId,
+    pol_type: i32,
+    data: Json<PolicyDataVnext>,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    let data: PolicyDataVnext = data.into_inner();
+    let policy: PolicyData = data.policy;
+    put_policy(org_id, pol_type, Json(policy), headers, conn).await
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrgDomainData {
+    domain_name: String,
+}
+
+#[get("/organizations/<org_id>/domains")]
+async fn list_org_domains(org_id: OrganizationId, headers: AdminHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let domains = OrgDomain::find_by_org(&org_id, &conn).await;
+    let domains_json: Vec<Value> = domains.iter().map(OrgDomain::to_json).collect();
+
+    Ok(Json(json!({
+        "data": domains_json,
+        "object": "list",
+        "continuationToken": null
+    })))
+}
+
+#[get("/organizations/<org_id>/domains/<domain_id>")]
+async fn get_org_domain(
+    org_id: OrganizationId,
+    domain_id: OrgDomainId,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(domain) = OrgDomain::find_by_uuid_and_org(&domain_id, &org_id, &conn).await else {
+        err!("Domain not found")
+    };
+
+    Ok(Json(domain.to_json()))
+}
+
+#[post("/organizations/<org_id>/domains", data = "<data>")]
+async fn post_org_domain(
+    org_id: OrganizationId,
+    data: Json<OrgDomainData>,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let domain_name = data.into_inner().domain_name.trim().trim_end_matches('.').to_lowercase();
+
+    if !domain_name.contains('.')
+        || !matches!(crate::http_client::get_valid_host(&domain_name), Ok(url::Host::Domain(_)))
+    {
+        err!("Invalid domain name")
+    }
+    if OrgDomain::find_by_org_and_name(&org_id, &domain_name, &conn).await.is_some() {
+        err!("The domain has already been added to this organization")
+    }
+    if OrgDomain::find_verified_by_name(&domain_name, &conn).await.is_some() {
+        err!("The domain has already been claimed by another organization")
+    }
+
+    let domain = OrgDomain::new(org_id.clone(), &domain_name);
+    domain.save(&conn).await?;
+
+    log_event(
+        EventType::OrganizationDomainAdded as i32,
+        &domain.uuid,
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(domain.to_json()))
+}
+
+#[post("/organizations/<org_id>/domains/<domain_id>/verify")]
+async fn verify_org_domain(
+    org_id: OrganizationId,
+    domain_id: OrgDomainId,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(mut domain) = OrgDomain::find_by_uuid_and_org(&domain_id, &org_id, &conn).await else {
+        err!("Domain not found")
+    };
+    if domain.is_verified() {
+        return Ok(Json(domain.to_json()));
+    }
+    if OrgDomain::find_verified_by_name(&domain.domain_name, &conn).await.is_some() {
+        err!("The domain has already been claimed by another organization")
+    }
+    // Otherwise every check would be recorded as a failed verification
+    if !crate::http_client::supports_txt_lookups() {
+        err!("Domains can't be verified, the server is unable to look up DNS TXT records")
+    }
+
+    let records = crate::http_client::lookup_txt(&domain.domain_name).await.unwrap_or_else(|e| {
+        debug!("{e:?}");
+        Vec::new()
+    });
+    let now = chrono::Utc::now().naive_utc();
+    domain.last_checked_at = Some(now);
+    let event_type = if records.iter().any(|r| r.trim() == domain.txt) {
+        domain.verified_at = Some(now);
+        EventType::OrganizationDomainVerified
+    } else {
+        EventType::OrganizationDomainNotVerified
+    };
+    domain.save(&conn).await?;
+
+    log_event(
+        event_type as i32,
+        &domain.uuid,
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(domain.to_json()))
+}
+
+#[delete("/organizations/<org_id>/domains/<domain_id>")]
+async fn delete_org_domain(
+    org_id: OrganizationId,
+    domain_id: OrgDomainId,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> EmptyResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(domain) = OrgDomain::find_by_uuid_and_org(&domain_id, &org_id, &conn).await else {
+        err!("Domain not found")
+    };
+    domain.delete(&conn).await?;
+
+    log_event(
+        EventType::OrganizationDomainRemoved as i32,
+        &domain_id,
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(())
+}
+
+#[post("/organizations/<org_id>/domains/<domain_id>/remove")]
+async fn post_delete_org_domain(
+    org_id: OrganizationId,
+    domain_id: OrgDomainId,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> EmptyResult {
+    delete_org_domain(org_id, domain_id, headers, conn).await
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CipherTemplateData {
+    name: String,
+    r#type: i32,
+    #[serde(default)]
+    fields: Vec<TemplateField>,
+    default_collection_id: Option<CollectionId>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CipherTemplateEnforcementData {
+    enabled: bool,
+}
+
+/// The templates are readable by every confirmed member, so clients and scripts can create consistent items
+#[get("/organizations/<org_id>/cipher-templates")]
+async fn list_cipher_templates(org_id: OrganizationId, headers: OrgMemberHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.membership.org_uuid || headers.membership.status != MembershipStatus::Confirmed as i32 {
+        err!("Organization not found", "Organization id's do not match or member isn't confirmed");
+    }
+    let templates = CipherTemplate::find_by_org(&org_id, &conn).await;
+    let templates_json: Vec<Value> = templates.iter().map(CipherTemplate::to_json).collect();
+
+    Ok(Json(json!({
+        "data": templates_json,
+        "object": "list",
+        "continuationToken": null
+    })))
+}
+
+async fn apply_cipher_template_data(
+    template: &mut CipherTemplate,
+    data: CipherTemplateData,
+    org_id: &OrganizationId,
+    conn: &DbConn,
+) -> EmptyResult {
+    if data.name.trim().is_empty() {
+        err!("The template needs a name")
+    }
+    // Login, SecureNote, Card, Identity and SshKey
+    if !(1..=5).contains(&data.r#type) {
+        err!("Invalid item type")
+    }
+    if let Some(col_id) = &data.default_collection_id
+        && Collection::find_by_uuid_and_org(col_id, org_id, conn).await.is_none()
+    {
+        err!("Collection not found")
+    }
+
+    template.name = data.name;
+    template.atype = data.r#type;
+    template.set_fields(&data.fields);
+    template.default_collection_uuid = data.default_collection_id;
+    Ok(())
+}
+
+#[post("/organizations/<org_id>/cipher-templates", data = "<data>")]
+async fn post_cipher_template(
+    org_id: OrganizationId,
+    data: Json<CipherTemplateData>,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let data = data.into_inner();
+    let mut template = CipherTemplate::new(org_id.clone(), data.name.clone(), data.r#type);
+    apply_cipher_template_data(&mut template, data, &org_id, &conn).await?;
+    template.save(&conn).await?;
+
+    log_event(
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(template.to_json()))
+}
+
+#[put("/organizations/<org_id>/cipher-templates/<template_id>", data = "<data>", rank = 2)]
+async fn put_cipher_template(
+    org_id: OrganizationId,
+    template_id: CipherTemplateId,
+    data: Json<CipherTemplateData>,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(mut template) = CipherTemplate::find_by_uuid_and_org(&template_id, &org_id, &conn).await else {
+        err!("Template not found")
+    };
+    apply_cipher_template_data(&mut template, data.into_inner(), &org_id, &conn).await?;
+    template.save(&conn).await?;
+
+    log_event(
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(template.to_json()))
+}
+
+#[delete("/organizations/<org_id>/cipher-templates/<template_id>")]
+async fn delete_cipher_template(
+    org_id: OrganizationId,
+    template_id: CipherTemplateId,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> EmptyResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
     }
-
-    let mut policy = match OrgPolicy::find_by_org_and_type(&org_id, pol_type_enum, &conn).await {
-        Some(p) => p,
-        None => OrgPolicy::new(org_id.clone(), pol_type_enum, false, "{}".to_owned()),
+    let Some(template) = CipherTemplate::find_by_uuid_and_org(&template_id, &org_id, &conn).await else {
+        err!("Template not found")
     };
-
-    policy.enabled = data.enabled;
-    policy.data = serde_json::to_string(&data.data)?;
-    policy.save(&conn).await?;
+    template.delete(&conn).await?;
 
     log_event(
-        EventType::PolicyUpdated as i32,
-        policy.uuid.as_ref(),
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
         &org_id,
         &headers.user.uuid,
         headers.device.atype,
@@ -2136,29 +3610,58 @@ async fn put_policy(
     )
     .await;
 
-    Ok(Json(policy.to_json()))
+    Ok(())
 }
 
-#[derive(Deserialize)]
-struct PolicyDataVnext {
-    policy: PolicyData,
-    // Ignore metadata for now as we do not yet support this
-    // "metadata": {
-    //     "defaultUserCollectionName": "2.xx|xx==|xx="
-    // }
+fn cipher_template_enforcement_json(org: &Organization) -> Value {
+    json!({
+        "enabled": org.enforce_cipher_templates,
+        "object": "cipherTemplateEnforcement",
+    })
 }
 
-#[put("/organizations/<org_id>/policies/<pol_type>/vnext", data = "<data>")]
-async fn put_policy_vnext(
+#[get("/organizations/<org_id>/cipher-templates/enforcement")]
+async fn get_cipher_template_enforcement(org_id: OrganizationId, headers: AdminHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+
+    Ok(Json(cipher_template_enforcement_json(&org)))
+}
+
+/// When enabled, new items of the organization need the required custom fields of one of the templates of their type.
+/// Items of a type without any template are not restricted.
+#[put("/organizations/<org_id>/cipher-templates/enforcement", data = "<data>", rank = 1)]
+async fn put_cipher_template_enforcement(
     org_id: OrganizationId,
-    pol_type: i32,
-    data: Json<PolicyDataVnext>,
+    data: Json<CipherTemplateEnforcementData>,
     headers: AdminHeaders,
     conn: DbConn,
 ) -> JsonResult {
-    let data: PolicyDataVnext = data.into_inner();
-    let policy: PolicyData = data.policy;
-    put_policy(org_id, pol_type, Json(policy), headers, conn).await
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(mut org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+    org.enforce_cipher_templates = data.into_inner().enabled;
+    org.save(&conn).await?;
+
+    log_event(
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(cipher_template_enforcement_json(&org)))
 }
 
 #[get("/plans")]
@@ -2189,10 +3692,77 @@ fn get_plans() -> Json<Value> {
     }))
 }
 
-#[get("/organizations/<_org_id>/billing/metadata")]
-fn get_billing_metadata(_org_id: OrganizationId, _headers: OrgMemberHeaders) -> Json<Value> {
-    // Prevent a 404 error, which also causes Javascript errors.
-    Json(empty_data_json())
+// There is no billing in Vaultwarden, these only report the seats and storage of the instance config,
+// so the billing and subscription pages of the clients keep working.
+// https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Api/Billing/Models/Responses/OrganizationSubscriptionResponseModel.cs
+#[get("/organizations/<org_id>/subscription")]
+async fn get_subscription(org_id: OrganizationId, headers: AdminHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+
+    let storage_bytes = Attachment::size_by_org(&org_id, &conn).await;
+    #[expect(clippy::cast_precision_loss, reason = "Only used to display the storage in GB")]
+    let storage_gb = storage_bytes as f64 / 1024.0 / 1024.0 / 1024.0;
+
+    let mut json = org.to_json();
+    json["storageName"] = json!(get_display_size(storage_bytes));
+    json["storageGb"] = json!((storage_gb * 100.0).round() / 100.0);
+    json["subscription"] = Value::Null;
+    json["upcomingInvoice"] = Value::Null;
+    json["customerDiscount"] = Value::Null;
+    json["expiration"] = Value::Null;
+    json["expirationWithoutGracePeriod"] = Value::Null;
+    json["secretsManagerBeta"] = json!(false);
+    json["object"] = json!("organizationSubscription");
+    Ok(Json(json))
+}
+
+#[get("/organizations/<org_id>/billing")]
+fn get_billing(org_id: OrganizationId, headers: AdminHeaders) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    Ok(Json(json!({
+        "balance": 0,
+        "paymentSource": null,
+        "invoices": [],
+        "transactions": [],
+        "object": "billing",
+    })))
+}
+
+#[get("/organizations/<org_id>/billing/history")]
+fn get_billing_history(org_id: OrganizationId, headers: AdminHeaders) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    Ok(Json(json!({
+        "invoices": [],
+        "transactions": [],
+        "object": "billingHistory",
+    })))
+}
+
+// https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Api/Billing/Models/Responses/OrganizationMetadataResponse.cs
+#[get("/organizations/<org_id>/billing/metadata")]
+async fn get_billing_metadata(org_id: OrganizationId, _headers: OrgMemberHeaders, conn: DbConn) -> Json<Value> {
+    Json(json!({
+        "isEligibleForSelfHost": true,
+        "isManaged": false,
+        "isOnSecretsManagerStandalone": false, // Secrets Manager is not supported by Vaultwarden
+        "isSubscriptionUnpaid": false,
+        "hasSubscription": false,
+        "hasOpenInvoice": false,
+        "isSubscriptionCanceled": false,
+        "invoiceDueDate": null,
+        "invoiceCreatedDate": null,
+        "subPeriodEndDate": null,
+        "organizationOccupiedSeats": Membership::count_by_org(&org_id, &conn).await,
+    }))
 }
 
 #[get("/organizations/<_org_id>/billing/vnext/warnings")]
@@ -2205,23 +3775,18 @@ fn get_billing_warnings(_org_id: OrganizationId, _headers: OrgMemberHeaders) ->
     }))
 }
 
-#[get("/organizations/<_org_id>/billing/vnext/self-host/metadata")]
-fn get_self_host_billing_metadata(_org_id: OrganizationId, _headers: OrgMemberHeaders) -> Json<Value> {
-    // Prevent a 404 error, which also causes Javascript errors.
+#[get("/organizations/<org_id>/billing/vnext/self-host/metadata")]
+async fn get_self_host_billing_metadata(
+    org_id: OrganizationId,
+    _headers: OrgMemberHeaders,
+    conn: DbConn,
+) -> Json<Value> {
     Json(json!({
         "isOnSecretsManagerStandalone": false, // Secrets Manager is not supported by Vaultwarden
-        "organizationOccupiedSeats": 0 // Vaultwarden does not count seats
+        "organizationOccupiedSeats": Membership::count_by_org(&org_id, &conn).await,
     }))
 }
 
-fn empty_data_json() -> Value {
-    json!({
-        "object": "list",
-        "data": [],
-        "continuationToken": null
-    })
-}
-
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct BulkRevokeMembershipIds {
@@ -2234,8 +3799,32 @@ async fn revoke_member(
     member_id: MembershipId,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    revoke_member_impl(&org_id, &member_id, &headers, &conn, &nt).await
+}
+
+// Upstream moved these routes to PATCH, the PUT routes are kept for older clients
+#[patch("/organizations/<org_id>/users/<member_id>/revoke")]
+async fn patch_revoke_member(
+    org_id: OrganizationId,
+    member_id: MembershipId,
+    headers: AdminHeaders,
+    conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
-    revoke_member_impl(&org_id, &member_id, &headers, &conn).await
+    revoke_member_impl(&org_id, &member_id, &headers, &conn, &nt).await
+}
+
+#[patch("/organizations/<org_id>/users/revoke", data = "<data>")]
+async fn patch_bulk_revoke_members(
+    org_id: OrganizationId,
+    data: Json<BulkRevokeMembershipIds>,
+    headers: AdminHeaders,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    bulk_revoke_members(org_id, data, headers, conn, nt).await
 }
 
 #[put("/organizations/<org_id>/users/revoke", data = "<data>")]
@@ -2244,6 +3833,7 @@ async fn bulk_revoke_members(
     data: Json<BulkRevokeMembershipIds>,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2254,7 +3844,7 @@ async fn bulk_revoke_members(
     match data.ids {
         Some(members) => {
             for member_id in members {
-                let err_msg = match revoke_member_impl(&org_id, &member_id, &headers, &conn).await {
+                let err_msg = match revoke_member_impl(&org_id, &member_id, &headers, &conn, &nt).await {
                     Ok(()) => String::new(),
                     Err(e) => format!("{e:?}"),
                 };
@@ -2283,6 +3873,7 @@ async fn revoke_member_impl(
     member_id: &MembershipId,
     headers: &AdminHeaders,
     conn: &DbConn,
+    nt: &Notify<'_>,
 ) -> EmptyResult {
     if org_id != &headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2304,6 +3895,11 @@ async fn revoke_member_impl(
             member.revoke();
             member.save(conn).await?;
 
+            // Let the clients of the member drop the organization data right away
+            if let Some(user) = User::find_by_uuid(&member.user_uuid, conn).await {
+                nt.send_user_update(UpdateType::SyncOrgKeys, &user, headers.device.push_uuid.as_ref(), conn).await;
+            }
+
             log_event(
                 EventType::OrganizationUserRevoked as i32,
                 &member.uuid,
@@ -2321,16 +3917,154 @@ async fn revoke_member_impl(
     Ok(())
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OffboardMemberData {
+    // Receives the items which only the member could access
+    collection_id: Option<CollectionId>,
+}
+
+/// Offboards a member in one transaction: revokes the membership, removes the group assignments,
+/// adds the items of the collections only the member could access to the given collection,
+/// and removes the emergency access between the member and the other members of the organization.
+/// Items in the personal vault of the member are encrypted with their own key and stay where they are.
+#[post("/organizations/<org_id>/users/<member_id>/offboard", data = "<data>")]
+async fn offboard_member(
+    org_id: OrganizationId,
+    member_id: MembershipId,
+    data: Json<OffboardMemberData>,
+    headers: AdminHeaders,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let data: OffboardMemberData = data.into_inner();
+    let Some(org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+    let Some(mut member) = Membership::find_by_uuid_and_org(&member_id, &org_id, &conn).await else {
+        err!("User not found in organization")
+    };
+    let Some(user) = User::find_by_uuid(&member.user_uuid, &conn).await else {
+        err!("User not found")
+    };
+    if member.user_uuid == headers.user.uuid {
+        err!("You cannot offboard yourself")
+    }
+    if member.atype == MembershipType::Owner && headers.membership_type != MembershipType::Owner {
+        err!("Only owners can offboard other owners")
+    }
+    let target = match &data.collection_id {
+        Some(col_id) => match Collection::find_by_uuid_and_org(col_id, &org_id, &conn).await {
+            Some(collection) => Some(collection),
+            None => err!("Collection not found", "Collection does not exist or does not belong to this organization"),
+        },
+        None => None,
+    };
+
+    let (revoked, groups, items, grants) = conn
+        .transaction(async |conn| {
+            let revoked = member.status > MembershipStatus::Revoked as i32;
+            if revoked {
+                if member.atype == MembershipType::Owner
+                    && Membership::count_confirmed_by_org_and_type(&org_id, MembershipType::Owner, conn).await <= 1
+                {
+                    err!("Organization must have at least one confirmed owner")
+                }
+                member.revoke();
+                member.save(conn).await?;
+            }
+
+            let groups = GroupUser::find_by_member(&member.uuid, conn).await.len();
+            GroupUser::delete_all_by_member(&member.uuid, conn).await?;
+
+            let mut items = 0;
+            if let Some(target) = &target {
+                for assignment in CollectionUser::find_by_organization_and_user_uuid(&org_id, &user.uuid, conn).await {
+                    let col_id = &assignment.collection_uuid;
+                    if col_id == &target.uuid
+                        || CollectionUser::find_by_collection(col_id, conn).await.len() > 1
+                        || !CollectionGroup::find_by_collection(col_id, conn).await.is_empty()
+                    {
+                        continue;
+                    }
+                    for cipher in Cipher::find_by_collection(col_id, conn).await {
+                        CollectionCipher::save(&cipher.uuid, &target.uuid, conn).await?;
+                        items += 1;
+                    }
+                }
+            }
+
+            let mut grants = 0;
+            let mut emergency_access = EmergencyAccess::find_all_by_grantor_uuid(&user.uuid, conn).await;
+            emergency_access.extend(EmergencyAccess::find_all_by_grantee_uuid(&user.uuid, conn).await);
+            for ea in emergency_access {
+                let other = if ea.grantor_uuid == user.uuid {
+                    ea.grantee_uuid.clone()
+                } else {
+                    Some(ea.grantor_uuid.clone())
+                };
+                if let Some(other) = other
+                    && Membership::find_by_user_and_org(&other, &org_id, conn).await.is_some()
+                {
+                    ea.delete(conn).await?;
+                    grants += 1;
+                }
+            }
+
+            Ok((revoked, groups, items, grants))
+        })
+        .await?;
+
+    if revoked {
+        nt.send_user_update(UpdateType::SyncOrgKeys, &user, headers.device.push_uuid.as_ref(), &conn).await;
+        log_event(
+            EventType::OrganizationUserRevoked as i32,
+            &member.uuid,
+            &org_id,
+            &headers.user.uuid,
+            headers.device.atype,
+            &headers.ip.ip,
+            &conn,
+        )
+        .await;
+    }
+    if let Some(target) = &target
+        && items > 0
+    {
+        target.update_users_revision(&conn).await;
+    }
+
+    if CONFIG.mail_enabled()
+        && let Err(e) =
+            mail::send_member_offboarded(&headers.user.email, &org.name, &user.email, revoked, groups, items, grants)
+                .await
+    {
+        error!("Error sending the offboarding summary email: {e:#?}");
+    }
+
+    Ok(Json(json!({
+        "revoked": revoked,
+        "groupsRemoved": groups,
+        "itemsReassigned": items,
+        "emergencyAccessRemoved": grants,
+        "object": "memberOffboarding",
+    })))
+}
+
 #[put("/organizations/<org_id>/users/<member_id>/restore/vnext")]
 async fn restore_member_vnext(
     org_id: OrganizationId,
     member_id: MembershipId,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
     // Vaultwarden does not (yet) support the per User Collection linked to the `Enforce organization data ownership` policy.
     // Therefor we ignore the `defaultUserCollectionName` data sent and just call restore_member
-    restore_member_impl(&org_id, &member_id, &headers, &conn).await
+    restore_member_impl(&org_id, &member_id, &headers, &conn, &nt).await
 }
 
 #[put("/organizations/<org_id>/users/<member_id>/restore")]
@@ -2339,8 +4073,31 @@ async fn restore_member(
     member_id: MembershipId,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    restore_member_impl(&org_id, &member_id, &headers, &conn, &nt).await
+}
+
+#[patch("/organizations/<org_id>/users/<member_id>/restore")]
+async fn patch_restore_member(
+    org_id: OrganizationId,
+    member_id: MembershipId,
+    headers: AdminHeaders,
+    conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
-    restore_member_impl(&org_id, &member_id, &headers, &conn).await
+    restore_member_impl(&org_id, &member_id, &headers, &conn, &nt).await
+}
+
+#[patch("/organizations/<org_id>/users/restore", data = "<data>")]
+async fn patch_bulk_restore_members(
+    org_id: OrganizationId,
+    data: Json<BulkMembershipIds>,
+    headers: AdminHeaders,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    bulk_restore_members(org_id, data, headers, conn, nt).await
 }
 
 #[put("/organizations/<org_id>/users/restore", data = "<data>")]
@@ -2349,6 +4106,7 @@ async fn bulk_restore_members(
     data: Json<BulkMembershipIds>,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2357,7 +4115,7 @@ async fn bulk_restore_members(
 
     let mut bulk_response = Vec::new();
     for member_id in data.ids {
-        let err_msg = match restore_member_impl(&org_id, &member_id, &headers, &conn).await {
+        let err_msg = match restore_member_impl(&org_id, &member_id, &headers, &conn, &nt).await {
             Ok(()) => String::new(),
             Err(e) => format!("{e:?}"),
         };
@@ -2383,6 +4141,7 @@ async fn restore_member_impl(
     member_id: &MembershipId,
     headers: &AdminHeaders,
     conn: &DbConn,
+    nt: &Notify<'_>,
 ) -> EmptyResult {
     if org_id != &headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2402,6 +4161,10 @@ async fn restore_member_impl(
             OrgPolicy::check_user_allowed(&member, "restore", conn).await?;
             member.save(conn).await?;
 
+            if let Some(user) = User::find_by_uuid(&member.user_uuid, conn).await {
+                nt.send_user_update(UpdateType::SyncOrgKeys, &user, headers.device.push_uuid.as_ref(), conn).await;
+            }
+
             log_event(
                 EventType::OrganizationUserRestored as i32,
                 &member.uuid,
@@ -3110,9 +4873,97 @@ async fn get_org_export(org_id: OrganizationId, headers: AdminHeaders, conn: DbC
     })))
 }
 
+/// Exports the collections and items of the organization the member has access to.
+/// Members with access to all collections export everything, the others only the items of their collections.
+/// The items stay encrypted with the organization key, the client decrypts them for a plaintext export
+/// or encrypts them again for an encrypted one, so the response is the same for both.
+#[get("/organizations/<org_id>/ciphers/export")]
+async fn get_org_ciphers_export(
+    org_id: OrganizationId,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> Result<(ContentType, TextStream![String]), crate::Error> {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let (Some(org), Some(member)) = (
+        Organization::find_by_uuid(&org_id, &conn).await,
+        Membership::find_confirmed_by_user_and_org(&headers.user.uuid, &org_id, &conn).await,
+    ) else {
+        err!("Organization not found")
+    };
+
+    let cipher_sync_data = CipherSyncData::new(&headers.user.uuid, CipherSyncType::Organization, &conn).await;
+    let full_access = member.access_all
+        || (member.atype >= MembershipType::Admin && org.allow_admin_access_to_all_collection_items)
+        || cipher_sync_data.user_group_full_access_for_organizations.contains(&org_id);
+
+    let (collections, mut ciphers) = if full_access {
+        (Collection::find_by_organization(&org_id, &conn).await, Cipher::find_by_org(&org_id, &conn).await)
+    } else {
+        let collections = Collection::find_by_organization_and_user_uuid(&org_id, &headers.user.uuid, &conn).await;
+        let collection_ids: HashSet<&CollectionId> = collections.iter().map(|c| &c.uuid).collect();
+        let mut ciphers = Cipher::find_by_org(&org_id, &conn).await;
+        ciphers.retain(|c| {
+            cipher_sync_data
+                .cipher_collections
+                .get(&c.uuid)
+                .is_some_and(|cipher_collections| cipher_collections.iter().any(|id| collection_ids.contains(id)))
+        });
+        (collections, ciphers)
+    };
+    // The items of confidential collections are only listed for the members assigned to them
+    let hidden = Cipher::find_hidden_confidential_uuids(&headers.user.uuid, &conn).await;
+    ciphers.retain(|c| !hidden.contains(&c.uuid));
+
+    org_export_stream(collections, ciphers, cipher_sync_data, &headers.host, &headers.user.uuid, &conn).await
+}
+
+/// Converts all items before the response starts, so an error fails the whole export instead of ending it with an
+/// invalid JSON document, and no database connection is held while it is sent. Only the serialized items are kept
+/// in memory, they are written one at a time.
+pub(super) async fn org_export_stream(
+    collections: Vec<Collection>,
+    ciphers: Vec<Cipher>,
+    cipher_sync_data: CipherSyncData,
+    host: &str,
+    user_id: &UserId,
+    conn: &DbConn,
+) -> Result<(ContentType, TextStream![String]), crate::Error> {
+    let collections_json = collections.iter().map(Collection::to_json).collect::<Value>();
+    let header = format!("{{\"collections\":{},\"ciphers\":[", convert_json_key_lcase_first(collections_json));
+
+    let mut ciphers_json = Vec::with_capacity(ciphers.len());
+    for cipher in ciphers {
+        let cipher_json =
+            cipher.to_json(host, user_id, Some(&cipher_sync_data), CipherSyncType::Organization, conn).await?;
+        ciphers_json.push(convert_json_key_lcase_first(cipher_json).to_string());
+    }
+
+    let stream = TextStream! {
+        yield header;
+        for (i, cipher_json) in ciphers_json.into_iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            yield format!("{separator}{cipher_json}");
+        }
+        yield "]}".to_owned();
+    };
+    Ok((ContentType::JSON, stream))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrgApiKeyData {
+    #[serde(flatten)]
+    password_or_otp: PasswordOrOtpData,
+    // Without it, the key of the public API
+    #[serde(rename = "type")]
+    atype: Option<i32>,
+}
+
 async fn api_key(
     org_id: &OrganizationId,
-    data: Json<PasswordOrOtpData>,
+    data: Json<OrgApiKeyData>,
     rotate: bool,
     headers: AdminHeaders,
     conn: DbConn,
@@ -3120,25 +4971,34 @@ async fn api_key(
     if org_id != &headers.org_id {
         err!("Organization not found", "Organization id's do not match");
     }
-    let data: PasswordOrOtpData = data.into_inner();
+    let data: OrgApiKeyData = data.into_inner();
     let user = headers.user;
 
-    // Validate the admin users password/otp
-    data.validate(&user, true, &conn).await?;
-
-    let org_api_key = if let Some(mut org_api_key) = OrganizationApiKey::find_by_org_uuid(org_id, &conn).await {
-        if rotate {
-            org_api_key.api_key = crate::crypto::generate_api_key();
-            org_api_key.revision_date = chrono::Utc::now().naive_utc();
-            org_api_key.save(&conn).await.expect("Error rotating organization API Key");
-        }
-        org_api_key
-    } else {
-        let api_key = crate::crypto::generate_api_key();
-        let new_org_api_key = OrganizationApiKey::new(org_id.clone(), api_key);
-        new_org_api_key.save(&conn).await.expect("Error creating organization API Key");
-        new_org_api_key
+    let Some(atype) = OrgApiKeyType::from_i32(data.atype.unwrap_or(OrgApiKeyType::Default as i32)) else {
+        err!("Unsupported API key type")
     };
+    // The SCIM key allows to add and remove members, only owners may manage it
+    if atype == OrgApiKeyType::Scim && headers.membership_type != MembershipType::Owner {
+        err!("Only owners can manage the SCIM API key")
+    }
+
+    // Validate the admin users password/otp
+    data.password_or_otp.validate(&user, true, &conn).await?;
+
+    let org_api_key =
+        if let Some(mut org_api_key) = OrganizationApiKey::find_by_org_uuid_and_type(org_id, atype, &conn).await {
+            if rotate {
+                org_api_key.api_key = crate::crypto::generate_api_key();
+                org_api_key.revision_date = chrono::Utc::now().naive_utc();
+                org_api_key.save(&conn).await.expect("Error rotating organization API Key");
+            }
+            org_api_key
+        } else {
+            let api_key = crate::crypto::generate_api_key();
+            let new_org_api_key = OrganizationApiKey::new(org_id.clone(), atype, api_key);
+            new_org_api_key.save(&conn).await.expect("Error creating organization API Key");
+            new_org_api_key
+        };
 
     Ok(Json(json!({
       "apiKey": org_api_key.api_key,
@@ -3150,7 +5010,7 @@ async fn api_key(
 #[post("/organizations/<org_id>/api-key", data = "<data>")]
 async fn post_api_key(
     org_id: OrganizationId,
-    data: Json<PasswordOrOtpData>,
+    data: Json<OrgApiKeyData>,
     headers: AdminHeaders,
     conn: DbConn,
 ) -> JsonResult {
@@ -3160,7 +5020,7 @@ async fn post_api_key(
 #[post("/organizations/<org_id>/rotate-api-key", data = "<data>")]
 async fn rotate_api_key(
     org_id: OrganizationId,
-    data: Json<PasswordOrOtpData>,
+    data: Json<OrgApiKeyData>,
     headers: AdminHeaders,
     conn: DbConn,
 ) -> JsonResult {
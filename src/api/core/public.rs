@@ -1,28 +1,44 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, sync::Mutex};
 
-use chrono::Utc;
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
 use rocket::{
     Request, Route,
+    form::FromForm,
+    http::ContentType,
     request::{FromRequest, Outcome},
+    response::stream::TextStream,
     serde::json::Json,
 };
+use serde_json::Value;
 
+use super::{
+    CipherSyncData, CipherSyncType,
+    organizations::{confirm_member, org_export_stream, reinvite_member_impl},
+};
 use crate::{
     CONFIG,
-    api::EmptyResult,
-    auth,
+    api::{ApiResult, EmptyResult, JsonResult, Notify},
+    auth::{self, ClientIp, Host},
     db::{
-        DbConn,
+        DbConn, DbPool,
         models::{
-            Group, GroupUser, Invitation, Membership, MembershipStatus, MembershipType, Organization,
-            OrganizationApiKey, OrganizationId, User,
+            Cipher, CipherId, Collection, Event, Group, GroupUser, Invitation, Membership, MembershipId,
+            MembershipStatus, MembershipType, Organization, OrganizationApiKey, OrganizationId, User, UserId,
         },
     },
-    mail,
+    ldap, mail,
+    util::format_date,
 };
 
 pub fn routes() -> Vec<Route> {
-    routes![ldap_import]
+    routes![
+        ldap_import,
+        get_events,
+        get_member_public_key,
+        reinvite_member,
+        confirm_member_with_key,
+        export_organization
+    ]
 }
 
 #[derive(Deserialize)]
@@ -59,24 +75,10 @@ async fn ldap_import(data: Json<OrgImportData>, token: PublicToken, conn: DbConn
     let data = data.into_inner();
 
     for user_data in &data.members {
-        let mut user_created: bool = false;
         if user_data.deleted {
             // If user is marked for deletion and it exists, revoke it
             if let Some(mut member) = Membership::find_by_email_and_org(&user_data.email, &org_id, &conn).await {
-                // Only revoke a user if it is not the last confirmed owner
-                let revoked = if member.atype == MembershipType::Owner
-                    && member.status == MembershipStatus::Confirmed as i32
-                {
-                    if Membership::count_confirmed_by_org_and_type(&org_id, MembershipType::Owner, &conn).await <= 1 {
-                        warn!("Can't revoke the last owner");
-                        false
-                    } else {
-                        member.revoke()
-                    }
-                } else {
-                    member.revoke()
-                };
-
+                let revoked = revoke_unless_last_owner(&mut member, &conn).await;
                 let ext_modified = member.set_external_id(Some(user_data.external_id.clone()));
                 if revoked || ext_modified {
                     member.save(&conn).await?;
@@ -91,52 +93,7 @@ async fn ldap_import(data: Json<OrgImportData>, token: PublicToken, conn: DbConn
             }
         } else {
             // If user is not part of the organization
-            let user = if let Some(user) = User::find_by_mail(&user_data.email, &conn).await {
-                user
-            } else {
-                // User does not exist yet
-                let mut new_user = User::new(&user_data.email, None);
-                new_user.save(&conn).await?;
-
-                if !CONFIG.mail_enabled() {
-                    Invitation::new(&new_user.email).save(&conn).await?;
-                }
-                user_created = true;
-                new_user
-            };
-            let member_status = if CONFIG.mail_enabled() || user.password_hash.is_empty() {
-                MembershipStatus::Invited as i32
-            } else {
-                MembershipStatus::Accepted as i32 // Automatically mark user as accepted if no email invites
-            };
-
-            let (org_name, org_email) = if let Some(org) = Organization::find_by_uuid(&org_id, &conn).await {
-                (org.name, org.billing_email)
-            } else {
-                err!("Error looking up organization")
-            };
-
-            let mut new_member = Membership::new(user.uuid.clone(), org_id.clone(), Some(org_email.clone()));
-            new_member.set_external_id(Some(user_data.external_id.clone()));
-            new_member.access_all = false;
-            new_member.atype = MembershipType::User as i32;
-            new_member.status = member_status;
-
-            new_member.save(&conn).await?;
-
-            if CONFIG.mail_enabled()
-                && let Err(e) =
-                    mail::send_invite(&user, org_id.clone(), new_member.uuid.clone(), &org_name, Some(org_email)).await
-            {
-                // Upon error delete the user, invite and org member records when needed
-                if user_created {
-                    user.delete(&conn).await?;
-                } else {
-                    new_member.delete(&conn).await?;
-                }
-
-                err!(format!("Error sending invite: {e:?} "));
-            }
+            provision_member(&org_id, &user_data.email, &user_data.external_id, &conn).await?;
         }
     }
 
@@ -189,6 +146,338 @@ async fn ldap_import(data: Json<OrgImportData>, token: PublicToken, conn: DbConn
     Ok(())
 }
 
+static LAST_LDAP_SYNC: Mutex<Option<Value>> = Mutex::new(None);
+
+/// Totals of an LDAP directory sync
+#[derive(Default)]
+pub struct LdapSyncResult {
+    pub users: usize,
+    pub invited: usize,
+    pub restored: usize,
+    pub revoked: usize,
+}
+
+pub async fn ldap_sync_job(pool: DbPool) {
+    debug!("Syncing the LDAP directory");
+    if let Ok(conn) = pool.get().await {
+        if let Err(e) = sync_ldap_directory(&conn).await {
+            error!("LDAP sync failed: {e:?}");
+        }
+    } else {
+        error!("Failed to get DB connection while syncing the LDAP directory");
+    }
+}
+
+/// Invites the users of the LDAP directory to `LDAP_ORG_ID`, restores the revoked ones which are back in the directory
+/// and, with `LDAP_REVOKE_REMOVED`, revokes the members synced before which aren't in the directory anymore.
+/// Members with an external id which isn't a DN below `LDAP_BASE_DN` are left alone.
+pub async fn sync_ldap_directory(conn: &DbConn) -> ApiResult<LdapSyncResult> {
+    let result = sync_ldap_users(conn).await;
+    let mut last_sync = json!({ "date": format_date(&Utc::now().naive_utc()) });
+    match &result {
+        Ok(r) => {
+            info!(
+                "LDAP sync finished, {} users found, {} invited, {} restored and {} revoked",
+                r.users, r.invited, r.restored, r.revoked
+            );
+            last_sync["users"] = json!(r.users);
+            last_sync["invited"] = json!(r.invited);
+            last_sync["restored"] = json!(r.restored);
+            last_sync["revoked"] = json!(r.revoked);
+        }
+        Err(e) => last_sync["error"] = json!(e.to_string()),
+    }
+    *LAST_LDAP_SYNC.lock().unwrap() = Some(last_sync);
+    result
+}
+
+/// Returns the outcome of the last LDAP sync run by this instance
+pub fn last_ldap_sync() -> Option<Value> {
+    LAST_LDAP_SYNC.lock().unwrap().clone()
+}
+
+async fn sync_ldap_users(conn: &DbConn) -> ApiResult<LdapSyncResult> {
+    let org_id: OrganizationId = CONFIG.ldap_org_id().into();
+    if Organization::find_by_uuid(&org_id, conn).await.is_none() {
+        err!(format!("The organization {org_id} of the LDAP sync doesn't exist"))
+    }
+
+    let users = ldap::search_users().await?;
+    let mut result = LdapSyncResult {
+        users: users.len(),
+        ..Default::default()
+    };
+
+    // The members of the sync are the ones with the DN of an entry below the base DN as external id.
+    // Members provisioned otherwise, like by SCIM or the Directory Connector, are never changed by the sync.
+    let base_dn = CONFIG.ldap_base_dn();
+    let is_synced =
+        |member: &Membership| member.external_id.as_deref().is_some_and(|id| ldap::is_dn_below(id, &base_dn));
+
+    for user in &users {
+        if let Some(mut member) = Membership::find_by_email_and_org(&user.email, &org_id, conn).await {
+            if member.external_id.is_some() && !is_synced(&member) {
+                continue;
+            }
+            // Only the members revoked by the sync itself are restored, not the ones an admin revoked
+            let restored = is_synced(&member) && member.restore();
+            let ext_modified = member.set_external_id(Some(user.dn.clone()));
+            if restored || ext_modified {
+                member.save(conn).await?;
+            }
+            if restored {
+                result.restored += 1;
+            }
+        } else {
+            provision_member(&org_id, &user.email, &user.dn, conn).await?;
+            result.invited += 1;
+        }
+    }
+
+    if CONFIG.ldap_revoke_removed() {
+        // An empty result is more likely a changed filter or base DN than an empty directory
+        if users.is_empty() {
+            warn!("The LDAP search returned no users, no member is revoked");
+            return Ok(result);
+        }
+        let synced: HashSet<&str> = users.iter().map(|user| user.dn.as_str()).collect();
+        for mut member in Membership::find_by_org(&org_id, conn).await {
+            if is_synced(&member)
+                && member.external_id.as_deref().is_some_and(|ext_id| !synced.contains(ext_id))
+                && revoke_unless_last_owner(&mut member, conn).await
+            {
+                member.save(conn).await?;
+                result.revoked += 1;
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Only revokes a member if it is not the last confirmed owner, returns whether the member was revoked
+pub async fn revoke_unless_last_owner(member: &mut Membership, conn: &DbConn) -> bool {
+    if member.atype == MembershipType::Owner
+        && member.status == MembershipStatus::Confirmed as i32
+        && Membership::count_confirmed_by_org_and_type(&member.org_uuid, MembershipType::Owner, conn).await <= 1
+    {
+        warn!("Can't revoke the last owner");
+        return false;
+    }
+    member.revoke()
+}
+
+/// Adds the user with the email to the organization as a member, used by the directory connector and by SCIM.
+/// The user is created first when it doesn't exist yet, and invited by mail when mail is enabled.
+pub async fn provision_member(
+    org_id: &OrganizationId,
+    email: &str,
+    external_id: &str,
+    conn: &DbConn,
+) -> ApiResult<Membership> {
+    let mut user_created: bool = false;
+    let Some(org) = Organization::find_by_uuid(org_id, conn).await else {
+        err!("Error looking up organization")
+    };
+
+    let user = if let Some(user) = User::find_by_mail_or_subaddress(email, conn).await {
+        if user.tenant_id != org.tenant_id {
+            err!(format!("User belongs to another tenant: {email}"))
+        }
+        // With subaddress normalization the account can already be a member under another subaddress
+        if let Some(mut member) = Membership::find_by_user_and_org(&user.uuid, org_id, conn).await {
+            if member.set_external_id(Some(external_id.to_owned())) {
+                member.save(conn).await?;
+            }
+            return Ok(member);
+        }
+        user
+    } else {
+        // User does not exist yet
+        let mut new_user = User::new(email, None);
+        new_user.tenant_id.clone_from(&org.tenant_id);
+        new_user.save(conn).await?;
+
+        if !CONFIG.mail_enabled() {
+            Invitation::new(&new_user.email).save(conn).await?;
+        }
+        user_created = true;
+        new_user
+    };
+    let member_status = if CONFIG.mail_enabled() || user.password_hash.is_empty() {
+        MembershipStatus::Invited as i32
+    } else {
+        MembershipStatus::Accepted as i32 // Automatically mark user as accepted if no email invites
+    };
+
+    let (org_name, org_email) = (org.name, org.billing_email);
+
+    let mut new_member = Membership::new(user.uuid.clone(), org_id.clone(), Some(org_email.clone()));
+    new_member.set_external_id(Some(external_id.to_owned()));
+    new_member.access_all = false;
+    new_member.atype = MembershipType::User as i32;
+    new_member.status = member_status;
+
+    new_member.save(conn).await?;
+
+    if CONFIG.mail_enabled()
+        && let Err(e) =
+            mail::send_invite(&user, org_id.clone(), new_member.uuid.clone(), &org_name, Some(org_email)).await
+    {
+        // Upon error delete the user, invite and org member records when needed
+        if user_created {
+            user.delete(conn).await?;
+        } else {
+            new_member.delete(conn).await?;
+        }
+
+        err!(format!("Error sending invite: {e:?} "));
+    }
+    Ok(new_member)
+}
+
+#[derive(FromForm)]
+struct PublicEventFilter {
+    start: Option<String>,
+    end: Option<String>,
+    #[field(name = "actingUserId")]
+    acting_user_id: Option<UserId>,
+    #[field(name = "itemId")]
+    item_id: Option<CipherId>,
+    #[field(name = "continuationToken")]
+    continuation_token: Option<String>,
+}
+
+fn parse_filter_date(date: Option<&str>, name: &str) -> Result<Option<NaiveDateTime>, crate::Error> {
+    match date {
+        Some(date) => match DateTime::parse_from_rfc3339(date) {
+            Ok(date) => Ok(Some(date.naive_utc())),
+            Err(_) => err!(format!("Invalid {name} date")),
+        },
+        None => Ok(None),
+    }
+}
+
+// Upstream: https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Api/AdminConsole/Public/Controllers/EventsController.cs
+// Without a date range the events of the last 30 days are returned, the same as upstream.
+#[get("/public/events?<data..>")]
+async fn get_events(data: PublicEventFilter, token: PublicToken, conn: DbConn) -> JsonResult {
+    let end_date = parse_filter_date(data.end.as_deref(), "end")?.unwrap_or_else(|| Utc::now().naive_utc());
+    let start_date = parse_filter_date(data.start.as_deref(), "start")?
+        .unwrap_or_else(|| end_date - TimeDelta::try_days(30).unwrap());
+    if start_date > end_date {
+        err!("Start date must be before end date")
+    }
+
+    // Return an empty list when org events are disabled, so collectors keep working
+    let (events_json, continuation_token): (Vec<Value>, Option<String>) = if CONFIG.org_events_enabled() {
+        let Some(events) = Event::find_by_org_public(
+            &token.0,
+            &start_date,
+            &end_date,
+            data.acting_user_id.as_ref(),
+            data.item_id.as_ref(),
+            data.continuation_token.as_deref(),
+            &conn,
+        )
+        .await
+        else {
+            err!("Invalid continuation token")
+        };
+
+        // When the page is full there probably is more data
+        #[expect(clippy::cast_possible_truncation, reason = "PUBLIC_PAGE_SIZE fits within usize")]
+        let continuation_token = if events.len() == Event::PUBLIC_PAGE_SIZE as usize {
+            events.last().map(Event::public_continuation_token)
+        } else {
+            None
+        };
+        (events.iter().map(Event::to_public_json).collect(), continuation_token)
+    } else {
+        (Vec::with_capacity(0), None)
+    };
+
+    Ok(Json(json!({
+        "object": "list",
+        "data": events_json,
+        "continuationToken": continuation_token,
+    })))
+}
+
+// The public key of an accepted member, so a provisioning pipeline can encrypt the organization key for it.
+#[get("/public/members/<member_id>/public-key")]
+async fn get_member_public_key(member_id: MembershipId, token: PublicToken, conn: DbConn) -> JsonResult {
+    let Some(member) = Membership::find_by_uuid_and_org(&member_id, &token.0, &conn).await else {
+        err!("The specified user isn't a member of the organization")
+    };
+    if member.status != MembershipStatus::Accepted as i32 {
+        err!("User in invalid state")
+    }
+    let Some(user) = User::find_by_uuid(&member.user_uuid, &conn).await else {
+        err!("User doesn't exist")
+    };
+
+    Ok(Json(json!({
+        "id": member.uuid,
+        "userId": user.uuid,
+        "publicKey": user.public_key,
+        "object": "memberPublicKey",
+    })))
+}
+
+#[post("/public/members/<member_id>/reinvite")]
+async fn reinvite_member(member_id: MembershipId, token: PublicToken, conn: DbConn) -> EmptyResult {
+    let org_id = token.0;
+    let Some(org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Error looking up organization")
+    };
+    reinvite_member_impl(&org_id, &member_id, &org.billing_email, &conn).await
+}
+
+// The same export as `/organizations/<org_id>/ciphers/export`, with all collections and items of the organization.
+// The items are converted as seen by the first confirmed owner, as the API key doesn't belong to a user.
+#[get("/public/organization/export")]
+async fn export_organization(
+    token: PublicToken,
+    host: Host,
+    conn: DbConn,
+) -> Result<(ContentType, TextStream![String]), crate::Error> {
+    let org_id = token.0;
+    let Some(owner) = Membership::find_by_org_and_type(&org_id, MembershipType::Owner, &conn)
+        .await
+        .into_iter()
+        .find(|m| m.status == MembershipStatus::Confirmed as i32)
+    else {
+        err!("The organization has no confirmed owner")
+    };
+
+    let collections = Collection::find_by_organization(&org_id, &conn).await;
+    let mut ciphers = Cipher::find_by_org(&org_id, &conn).await;
+    // The items of confidential collections are only exported when the owner is assigned to them
+    let hidden = Cipher::find_hidden_confidential_uuids(&owner.user_uuid, &conn).await;
+    ciphers.retain(|c| !hidden.contains(&c.uuid));
+    let cipher_sync_data = CipherSyncData::new(&owner.user_uuid, CipherSyncType::Organization, &conn).await;
+    org_export_stream(collections, ciphers, cipher_sync_data, &host.host, &owner.user_uuid, &conn).await
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicConfirmData {
+    key: String, // The organization key, encrypted with the public key of the member
+}
+
+#[post("/public/members/<member_id>/confirm", data = "<data>")]
+async fn confirm_member_with_key(
+    member_id: MembershipId,
+    data: Json<PublicConfirmData>,
+    token: PublicToken,
+    ip: ClientIp,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    confirm_member(&token.0, &member_id, &data.into_inner().key, None, &ip.ip, &conn, &nt).await
+}
+
 pub struct PublicToken(OrganizationId);
 
 #[rocket::async_trait]
@@ -0,0 +1,77 @@
+use std::{
+    sync::{
+        LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use moka::sync::Cache;
+use serde_json::Value;
+
+use crate::{
+    CONFIG,
+    db::models::{OrganizationId, UserId},
+};
+
+// Short lived in-process cache of responses the clients poll often, but which rarely change.
+// The entries of a user are cleared with every user update sent through the notifications, the entries of
+// an organization whenever it or one of its policies is saved. The TTL limits how long changes done by
+// other instances sharing the same database can go unnoticed.
+// The invalidations need to happen after the changes are written, so no request can cache the old values again.
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub enum CacheKey {
+    Domains(UserId),
+    Policies(OrganizationId),
+    Organization(OrganizationId),
+}
+
+static RESPONSES: LazyLock<Cache<CacheKey, Value>> = LazyLock::new(|| {
+    Cache::builder().max_capacity(10_000).time_to_live(Duration::from_secs(CONFIG.response_cache_ttl().max(1))).build()
+});
+
+// Increased by every invalidation, a response loaded while one happened might be outdated and isn't cached
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn is_enabled() -> bool {
+    CONFIG.response_cache_ttl() > 0
+}
+
+/// Returns the cached response, or loads and caches it
+pub async fn get_or_load(key: CacheKey, load: impl Future<Output = Value>) -> Value {
+    if !is_enabled() {
+        return load.await;
+    }
+    if let Some(value) = RESPONSES.get(&key) {
+        return value;
+    }
+    let generation = GENERATION.load(Ordering::Acquire);
+    let value = load.await;
+    if GENERATION.load(Ordering::Acquire) == generation {
+        RESPONSES.insert(key, value.clone());
+    }
+    value
+}
+
+pub fn invalidate_user(user_id: &UserId) {
+    if is_enabled() {
+        GENERATION.fetch_add(1, Ordering::AcqRel);
+        RESPONSES.invalidate(&CacheKey::Domains(user_id.clone()));
+    }
+}
+
+pub fn invalidate_org(org_id: &OrganizationId) {
+    if is_enabled() {
+        GENERATION.fetch_add(1, Ordering::AcqRel);
+        RESPONSES.invalidate(&CacheKey::Policies(org_id.clone()));
+        RESPONSES.invalidate(&CacheKey::Organization(org_id.clone()));
+    }
+}
+
+/// Needed when something changes which is part of the responses of every user, like the global domains
+pub fn invalidate_all() {
+    if is_enabled() {
+        GENERATION.fetch_add(1, Ordering::AcqRel);
+        RESPONSES.invalidate_all();
+    }
+}
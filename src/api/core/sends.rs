@@ -1,4 +1,10 @@
-use std::{path::Path, sync::LazyLock, time::Duration};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::Path,
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
 
 use chrono::{DateTime, TimeDelta, Utc};
 use num_traits::ToPrimitive;
@@ -7,20 +13,25 @@ use rocket::{
     fs::{NamedFile, TempFile},
     serde::json::Json,
 };
-use serde_json::Value;
+use serde_json::{Map, Value};
 
 use crate::{
     CONFIG,
-    api::{ApiResult, EmptyResult, JsonResult, Notify, UpdateType},
-    auth::{ClientIp, Headers, Host},
+    api::{ApiResult, EmptyResult, JsonResult, Notify, UpdateType, WS_USERS, core::log_event},
+    auth::{AdminHeaders, ClientIp, Headers, Host, OrgMemberHeaders},
     config::PathType,
     db::{
         DbConn, DbPool,
-        models::{Device, OrgPolicy, OrgPolicyType, Send, SendFileId, SendId, SendType, UserId},
+        models::{
+            Device, EventType, OrgPolicy, OrgPolicyType, OrganizationId, Send, SendDownloadToken, SendEgress,
+            SendFileId, SendId, SendType, UserId,
+        },
     },
     util::{NumberOrString, save_temp_file},
 };
 
+use super::strict;
+
 const SEND_INACCESSIBLE_MSG: &str = "Send does not exist or is no longer available";
 static ANON_PUSH_DEVICE: LazyLock<Device> = LazyLock::new(|| {
     let dt = crate::util::parse_date("1970-01-01T00:00:00.000000Z");
@@ -35,9 +46,17 @@ static ANON_PUSH_DEVICE: LazyLock<Device> = LazyLock::new(|| {
         push_token: None,
         refresh_token: String::new(),
         twofactor_remember: None,
+        trusted: false,
+        blocked: false,
+        push_rejected_at: None,
     }
 });
 
+// Bytes served by the anonymous Send downloads per IP since startup, shown on the diagnostics page
+static SEND_EGRESS_BY_IP: LazyLock<Mutex<HashMap<IpAddr, u64>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+// Prevents the map from growing indefinitely when many different IPs download Sends
+const MAX_SEND_EGRESS_IPS: usize = 10_000;
+
 // The max file size allowed by Bitwarden clients and add an extra 5% to avoid issues
 const SIZE_525_MB: i64 = 550_502_400;
 
@@ -52,16 +71,23 @@ pub fn routes() -> Vec<rocket::Route> {
         put_send,
         delete_send,
         put_remove_password,
+        put_remove_preview,
         download_send,
         post_send_file_v2,
-        post_send_file_v2_data
+        post_send_file_v2_data,
+        get_org_sends,
+        post_org_send,
+        delete_org_send
     ]
 }
 
 pub async fn purge_sends(pool: DbPool) {
     debug!("Purging sends");
     if let Ok(conn) = pool.get().await {
-        Send::purge(&conn).await;
+        for send in Send::purge(&conn).await {
+            let user_ids: Vec<UserId> = send.user_uuid.iter().cloned().collect();
+            WS_USERS.send_send_update(UpdateType::SyncSendDelete, &send, &user_ids, &ANON_PUSH_DEVICE, &conn).await;
+        }
     } else {
         error!("Failed to get DB connection while purging sends");
     }
@@ -85,9 +111,109 @@ pub struct SendData {
     text: Option<Value>,
     file: Option<Value>,
     file_length: Option<NumberOrString>,
+    // Vaultwarden specific, marks an image file Send as previewable
+    preview: Option<SendPreviewData>,
+    // Vaultwarden specific, SHA-256 hash (hex) of the unencrypted file, checked against the SEND_FILE_HASH_BLOCKLIST
+    file_hash: Option<String>,
 
     // Used for key rotations
     pub id: Option<SendId>,
+
+    // Only checked with STRICT_REQUEST_PARSING enabled
+    #[serde(flatten)]
+    unknown_fields: Map<String, Value>,
+}
+
+/// Metadata of an image file Send, provided by the creator and returned on the access page,
+/// so the image can be previewed without downloading the whole file.
+/// The thumbnail is encrypted with the key of the Send like the file itself.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SendPreviewData {
+    width: u32,
+    height: u32,
+    mime_type: String,
+    thumbnail: Option<String>,
+}
+
+// Larger thumbnails are better served by downloading the file itself
+const MAX_THUMBNAIL_LENGTH: usize = 64 * 1024;
+
+impl SendPreviewData {
+    fn validate(&self) -> EmptyResult {
+        if !self.mime_type.starts_with("image/") {
+            err!("Only images can be previewed")
+        }
+        if self.width == 0 || self.height == 0 || self.width > 65_535 || self.height > 65_535 {
+            err!("Invalid image dimensions")
+        }
+        if let Some(thumbnail) = &self.thumbnail {
+            if thumbnail.len() > MAX_THUMBNAIL_LENGTH {
+                err!("The thumbnail is too large")
+            }
+            if !strict::is_enc_string(thumbnail) {
+                err!("The thumbnail needs to be encrypted")
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SendData {
+    /// Validates the fields and the encrypted strings, only when STRICT_REQUEST_PARSING is enabled
+    fn check_strict(&self) -> EmptyResult {
+        if !strict::enabled() {
+            return Ok(());
+        }
+        strict::check_unknown_fields("Send", &self.unknown_fields, &[])?;
+        strict::check_enc_string("name", Some(&self.name))?;
+        strict::check_enc_string("notes", self.notes.as_deref())?;
+        strict::check_enc_string("key", Some(&self.key))?;
+        strict::check_enc_strings_in("text", self.text.as_ref())?;
+        // The other fields of a file are filled by the server
+        strict::check_enc_string("file.fileName", self.file.as_ref().and_then(|f| f["fileName"].as_str()))
+    }
+}
+
+/// Returns the IPs which downloaded the most bytes from Sends since startup
+pub fn send_egress_ip_stats(limit: usize) -> Vec<(IpAddr, u64)> {
+    let mut stats: Vec<_> =
+        SEND_EGRESS_BY_IP.lock().map(|m| m.iter().map(|(ip, bytes)| (*ip, *bytes)).collect()).unwrap_or_default();
+    stats.sort_by(|a, b| b.1.cmp(&a.1));
+    stats.truncate(limit);
+    stats
+}
+
+/// Checks whether serving `bytes` more would exceed the `SEND_MONTHLY_EGRESS_LIMIT`
+async fn send_egress_limit_reached(bytes: i64, conn: &DbConn) -> bool {
+    let Some(limit) = CONFIG.send_monthly_egress_limit() else {
+        return false;
+    };
+    SendEgress::current_month_bytes(conn).await.saturating_add(bytes) > limit.saturating_mul(1024)
+}
+
+/// Accounts a download of the file of a Send per IP and for the current month,
+/// the bytes per Send are counted together with the access count
+async fn account_send_egress(bytes: i64, ip: IpAddr, conn: &DbConn) {
+    if let Ok(mut by_ip) = SEND_EGRESS_BY_IP.lock()
+        && (by_ip.len() < MAX_SEND_EGRESS_IPS || by_ip.contains_key(&ip))
+    {
+        *by_ip.entry(ip).or_default() += bytes.unsigned_abs();
+    }
+    SendEgress::add(bytes, conn).await;
+}
+
+/// Enforces `USER_SEND_COUNT_LIMIT` before a new Send is created
+async fn enforce_send_quota(headers: &Headers, conn: &DbConn) -> EmptyResult {
+    if headers.user.quota_exempt {
+        return Ok(());
+    }
+    if let Some(limit) = CONFIG.user_send_count_limit()
+        && Send::count_by_user(&headers.user.uuid, conn).await >= limit
+    {
+        err!(format!("You can't have more than {limit} Sends, delete some Sends first"), ErrorCode::QuotaExceeded)
+    }
+    Ok(())
 }
 
 /// Enforces the `Disable Send` policy. A non-owner/admin user belonging to
@@ -103,7 +229,7 @@ async fn enforce_disable_send_policy(headers: &Headers, conn: &DbConn) -> EmptyR
     if !CONFIG.sends_allowed()
         || OrgPolicy::is_applicable_to_user(user_id, OrgPolicyType::DisableSend, None, conn).await
     {
-        err!("Due to an Enterprise Policy, you are only able to delete an existing Send.")
+        err!("Due to an Enterprise Policy, you are only able to delete an existing Send.", ErrorCode::PolicyDisableSend)
     }
     Ok(())
 }
@@ -120,13 +246,16 @@ async fn enforce_disable_hide_email_policy(data: &SendData, headers: &Headers, c
     if hide_email && OrgPolicy::is_hide_email_disabled(user_id, conn).await {
         err!(
             "Due to an Enterprise Policy, you are not allowed to hide your email address \
-              from recipients when creating or editing a Send."
+              from recipients when creating or editing a Send.",
+            ErrorCode::PolicySendOptions
         )
     }
     Ok(())
 }
 
 fn create_send(data: SendData, user_id: UserId) -> ApiResult<Send> {
+    data.check_strict()?;
+
     let data_val = if data.r#type == SendType::Text as i32 {
         data.text
     } else if data.r#type == SendType::File as i32 {
@@ -148,6 +277,26 @@ fn create_send(data: SendData, user_id: UserId) -> ApiResult<Send> {
         );
     }
 
+    if data.r#type == SendType::File as i32
+        && let Some(file_hash) = &data.file_hash
+        && CONFIG.is_send_file_hash_blocked(file_hash)
+    {
+        err!(
+            "This file can't be shared with a Send",
+            format!("File Send refused, hash {file_hash} is blocked (user {user_id})"),
+            ErrorCode::SendFileBlocked
+        )
+    }
+
+    let preview = match data.preview {
+        Some(_) if data.r#type != SendType::File as i32 => err!("Only file Sends can be previewed"),
+        Some(preview) => {
+            preview.validate()?;
+            Some(serde_json::to_string(&preview)?)
+        }
+        None => None,
+    };
+
     let mut send = Send::new(data.r#type, data.name, data_str, data.key, data.deletion_date.naive_utc());
     send.user_uuid = Some(user_id);
     send.notes = data.notes;
@@ -159,6 +308,7 @@ fn create_send(data: SendData, user_id: UserId) -> ApiResult<Send> {
     send.disabled = data.disabled;
     send.hide_email = data.hide_email;
     send.atype = data.r#type;
+    send.preview = preview;
 
     send.set_password(data.password.as_deref());
 
@@ -189,6 +339,7 @@ async fn get_send(send_id: SendId, headers: Headers, conn: DbConn) -> JsonResult
 #[post("/sends", data = "<data>")]
 async fn post_send(data: Json<SendData>, headers: Headers, conn: DbConn, nt: Notify<'_>) -> JsonResult {
     enforce_disable_send_policy(&headers, &conn).await?;
+    enforce_send_quota(&headers, &conn).await?;
 
     let data: SendData = data.into_inner();
     enforce_disable_hide_email_policy(&data, &headers, &conn).await?;
@@ -230,6 +381,7 @@ struct UploadDataV2<'f> {
 #[post("/sends/file", format = "multipart/form-data", data = "<data>")]
 async fn post_send_file(data: Form<UploadData<'_>>, headers: Headers, conn: DbConn, nt: Notify<'_>) -> JsonResult {
     enforce_disable_send_policy(&headers, &conn).await?;
+    enforce_send_quota(&headers, &conn).await?;
 
     let UploadData {
         model,
@@ -256,7 +408,7 @@ async fn post_send_file(data: Form<UploadData<'_>>, headers: Headers, conn: DbCo
                 err!("Send size overflow");
             };
             if left <= 0 {
-                err!("Send storage limit reached! Delete some sends to free up space")
+                err!("Send storage limit reached! Delete some sends to free up space", ErrorCode::QuotaExceeded)
             }
             i64::clamp(left, 0, SIZE_525_MB)
         }
@@ -264,7 +416,7 @@ async fn post_send_file(data: Form<UploadData<'_>>, headers: Headers, conn: DbCo
     };
 
     if size > size_limit {
-        err!("Send storage limit exceeded with this file");
+        err!("Send storage limit exceeded with this file", ErrorCode::QuotaExceeded);
     }
 
     let mut send = create_send(model, headers.user.uuid)?;
@@ -302,6 +454,7 @@ async fn post_send_file(data: Form<UploadData<'_>>, headers: Headers, conn: DbCo
 #[post("/sends/file/v2", data = "<data>")]
 async fn post_send_file_v2(data: Json<SendData>, headers: Headers, conn: DbConn) -> JsonResult {
     enforce_disable_send_policy(&headers, &conn).await?;
+    enforce_send_quota(&headers, &conn).await?;
 
     let data = data.into_inner();
 
@@ -330,7 +483,7 @@ async fn post_send_file_v2(data: Json<SendData>, headers: Headers, conn: DbConn)
                 err!("Send size overflow");
             };
             if left <= 0 {
-                err!("Send storage limit reached! Delete some sends to free up space")
+                err!("Send storage limit reached! Delete some sends to free up space", ErrorCode::QuotaExceeded)
             }
             i64::clamp(left, 0, SIZE_525_MB)
         }
@@ -338,7 +491,7 @@ async fn post_send_file_v2(data: Json<SendData>, headers: Headers, conn: DbConn)
     };
 
     if file_length > size_limit {
-        err!("Send storage limit exceeded with this file");
+        err!("Send storage limit exceeded with this file", ErrorCode::QuotaExceeded);
     }
 
     let mut send = create_send(data, headers.user.uuid)?;
@@ -487,22 +640,21 @@ async fn post_access(
         }
     }
 
-    // Files are incremented during the download
+    // Files are incremented during the download, nothing changes for them here so the owner isn't notified
     if send.atype == SendType::Text as i32 {
         send.access_count += 1;
+        send.save(&conn).await?;
+
+        nt.send_send_update(
+            UpdateType::SyncSendUpdate,
+            &send,
+            &send.update_users_revision(&conn).await,
+            &ANON_PUSH_DEVICE,
+            &conn,
+        )
+        .await;
     }
 
-    send.save(&conn).await?;
-
-    nt.send_send_update(
-        UpdateType::SyncSendUpdate,
-        &send,
-        &send.update_users_revision(&conn).await,
-        &ANON_PUSH_DEVICE,
-        &conn,
-    )
-    .await;
-
     Ok(Json(send.to_json_access(&conn).await))
 }
 
@@ -512,6 +664,7 @@ async fn post_access_file(
     file_id: SendFileId,
     data: Json<SendAccessData>,
     host: Host,
+    ip: ClientIp,
     conn: DbConn,
     nt: Notify<'_>,
 ) -> JsonResult {
@@ -547,9 +700,22 @@ async fn post_access_file(
         }
     }
 
-    send.access_count += 1;
+    // The download itself can be served by the storage backend, so it's accounted when it's granted
+    let file_size = send.file_size().unwrap_or(0);
+    if send_egress_limit_reached(file_size, &conn).await {
+        warn!("Refusing the download of Send {send_id}, the monthly Send egress limit has been reached");
+        err_code!("Send downloads are temporarily unavailable, please try again later", 503)
+    }
 
-    send.save(&conn).await?;
+    // Another download could have used the last access since the Send was loaded
+    let now = Utc::now().naive_utc();
+    if !Send::register_file_access(&send_id, file_size, now, &conn).await? {
+        err_code!(SEND_INACCESSIBLE_MSG, 404)
+    }
+    // The clients ignore updates which aren't newer than the Send they have
+    send.access_count += 1;
+    send.revision_date = now;
+    account_send_egress(file_size, ip.ip, &conn).await;
 
     nt.send_send_update(
         UpdateType::SyncSendUpdate,
@@ -563,31 +729,37 @@ async fn post_access_file(
     Ok(Json(json!({
         "object": "send-fileDownload",
         "id": file_id,
-        "url": download_url(&host, &send_id, &file_id).await?,
+        "url": download_url(&host, &send_id, &file_id, &conn).await?,
     })))
 }
 
-async fn download_url(host: &Host, send_id: &SendId, file_id: &SendFileId) -> Result<String, crate::Error> {
-    let operator = CONFIG.opendal_operator_for_path_type(&PathType::Sends)?;
+async fn download_url(
+    host: &Host,
+    send_id: &SendId,
+    file_id: &SendFileId,
+    conn: &DbConn,
+) -> Result<String, crate::Error> {
+    let backend = CONFIG.storage_backend(&PathType::Sends)?;
 
-    if crate::storage::is_fs_operator(&operator) {
-        let token_claims = crate::auth::generate_send_claims(send_id, file_id);
+    if let Some(url) = backend.presign(&format!("{send_id}/{file_id}"), Duration::from_mins(5)).await? {
+        Ok(url)
+    } else {
+        let token_id = SendDownloadToken::create(*crate::auth::SEND_DOWNLOAD_LIFETIME, conn).await?;
+        let token_claims = crate::auth::generate_send_claims(send_id, file_id, &token_id);
         let token = crate::auth::encode_jwt(&token_claims);
 
         Ok(format!("{}/api/sends/{send_id}/{file_id}?t={token}", host.host))
-    } else {
-        Ok(operator.presign_read(&format!("{send_id}/{file_id}"), Duration::from_mins(5)).await?.uri().to_string())
     }
 }
 
 #[get("/sends/<send_id>/<file_id>?<t>")]
-async fn download_send(send_id: SendId, file_id: SendFileId, t: &str) -> Option<NamedFile> {
-    if let Ok(claims) = crate::auth::decode_send(t)
-        && claims.sub == format!("{send_id}/{file_id}")
-    {
-        return NamedFile::open(Path::new(&CONFIG.sends_folder()).join(send_id).join(file_id)).await.ok();
+async fn download_send(send_id: SendId, file_id: SendFileId, t: &str, conn: DbConn) -> Option<NamedFile> {
+    let claims = crate::auth::decode_send(t).ok()?;
+    let token_id = claims.sub.strip_prefix(&format!("{send_id}/{file_id}/"))?;
+    if !SendDownloadToken::consume(token_id, &conn).await {
+        return None;
     }
-    None
+    NamedFile::open(Path::new(&CONFIG.sends_folder()).join(send_id).join(file_id)).await.ok()
 }
 
 #[put("/sends/<send_id>", data = "<data>")]
@@ -617,6 +789,7 @@ pub async fn update_send_from_data(
     if send.user_uuid.as_ref() != Some(&headers.user.uuid) {
         err!("Send is not owned by user")
     }
+    data.check_strict()?;
 
     if send.atype != data.r#type {
         err!("Sends can't change type")
@@ -657,6 +830,15 @@ pub async fn update_send_from_data(
         send.set_password(Some(&password));
     }
 
+    // Only change the value if it's present, like the password it's removed with its own endpoint
+    if let Some(preview) = data.preview {
+        if send.atype != SendType::File as i32 {
+            err!("Only file Sends can be previewed")
+        }
+        preview.validate()?;
+        send.preview = Some(serde_json::to_string(&preview)?);
+    }
+
     send.save(conn).await?;
     if ut != UpdateType::None {
         nt.send_send_update(ut, send, &send.update_users_revision(conn).await, &headers.device, conn).await;
@@ -704,3 +886,117 @@ async fn put_remove_password(send_id: SendId, headers: Headers, conn: DbConn, nt
 
     Ok(Json(send.to_json()))
 }
+
+#[put("/sends/<send_id>/remove-preview")]
+async fn put_remove_preview(send_id: SendId, headers: Headers, conn: DbConn, nt: Notify<'_>) -> JsonResult {
+    enforce_disable_send_policy(&headers, &conn).await?;
+
+    let Some(mut send) = Send::find_by_uuid_and_user(&send_id, &headers.user.uuid, &conn).await else {
+        err!("Send not found", "Invalid send uuid, or does not belong to user")
+    };
+
+    send.preview = None;
+    send.save(&conn).await?;
+    nt.send_send_update(
+        UpdateType::SyncSendUpdate,
+        &send,
+        &send.update_users_revision(&conn).await,
+        &headers.device,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(send.to_json()))
+}
+
+//
+// Organization Sends (Vaultwarden specific)
+//
+// These are owned by the organization instead of a user, so they stay available when the member who created them leaves.
+// Members with the `manageSends` permission and admins can create and list them, only admins can delete them.
+// They aren't synced to the clients, the access page shows the name of the organization as the creator.
+//
+
+fn org_send_json(send: &Send) -> Value {
+    let mut send_json = send.to_json();
+    send_json["organizationId"] = json!(send.organization_uuid);
+    send_json
+}
+
+#[get("/organizations/<org_id>/sends")]
+async fn get_org_sends(org_id: OrganizationId, headers: OrgMemberHeaders, conn: DbConn) -> JsonResult {
+    if !headers.membership.can_manage_sends() {
+        err!("You don't have permission to manage the Sends of this organization")
+    }
+
+    let sends_json: Vec<Value> = Send::find_by_org(&org_id, &conn).await.iter().map(org_send_json).collect();
+    Ok(Json(json!({
+      "data": sends_json,
+      "object": "list",
+      "continuationToken": null
+    })))
+}
+
+#[post("/organizations/<org_id>/sends", data = "<data>")]
+async fn post_org_send(
+    org_id: OrganizationId,
+    data: Json<SendData>,
+    headers: OrgMemberHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    if !headers.membership.can_manage_sends() {
+        err!("You don't have permission to manage the Sends of this organization")
+    }
+    let headers: Headers = headers.into();
+    enforce_disable_send_policy(&headers, &conn).await?;
+
+    let data: SendData = data.into_inner();
+    enforce_disable_hide_email_policy(&data, &headers, &conn).await?;
+
+    if data.r#type == SendType::File as i32 {
+        err!("Organization Sends can only be text Sends")
+    }
+
+    let mut send = create_send(data, headers.user.uuid.clone())?;
+    send.user_uuid = None;
+    send.organization_uuid = Some(org_id.clone());
+    send.save(&conn).await?;
+
+    log_event(
+        EventType::OrganizationSendCreated as i32,
+        &send.uuid,
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(org_send_json(&send)))
+}
+
+#[delete("/organizations/<org_id>/sends/<send_id>")]
+async fn delete_org_send(org_id: OrganizationId, send_id: SendId, headers: AdminHeaders, conn: DbConn) -> EmptyResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(send) = Send::find_by_uuid_and_org(&send_id, &org_id, &conn).await else {
+        err!("Send not found", "Invalid send uuid, or does not belong to the organization")
+    };
+
+    send.delete(&conn).await?;
+
+    log_event(
+        EventType::OrganizationSendDeleted as i32,
+        &send.uuid,
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(())
+}
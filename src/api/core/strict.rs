@@ -0,0 +1,153 @@
+//
+// Strict parsing of the request models
+//
+// By default unknown fields are ignored and the encrypted values are stored the way the client sends them.
+// With STRICT_REQUEST_PARSING enabled, items and Sends are rejected when they contain fields the server doesn't know,
+// or values which aren't valid encrypted strings, so malformed third-party clients are noticed before they store garbage.
+//
+use data_encoding::BASE64;
+use serde_json::{Map, Value};
+
+use crate::{CONFIG, api::EmptyResult};
+
+pub fn enabled() -> bool {
+    CONFIG.strict_request_parsing()
+}
+
+/// Checks the format of an encrypted string: `<type>.<base64>|<base64>|...`
+/// The number of parts depends on the encryption type.
+/// Upstream: https://github.com/bitwarden/clients/blob/main/libs/common/src/platform/enums/encryption-type.enum.ts
+pub fn is_enc_string(value: &str) -> bool {
+    let Some((enc_type, data)) = value.split_once('.') else {
+        return false;
+    };
+    let expected_parts = match enc_type {
+        "0" => 2,             // AesCbc256_B64: iv|data
+        "1" | "2" => 3,       // AesCbc128_HmacSha256_B64, AesCbc256_HmacSha256_B64: iv|data|mac
+        "3" | "4" | "7" => 1, // Rsa2048_OaepSha256_B64, Rsa2048_OaepSha1_B64, CoseEncrypt0: data
+        "5" | "6" => 2,       // Rsa2048_OaepSha256_HmacSha256_B64, Rsa2048_OaepSha1_HmacSha256_B64: data|mac
+        _ => return false,
+    };
+
+    data.split('|').count() == expected_parts
+        && data.split('|').all(|p| !p.is_empty() && BASE64.decode(p.as_bytes()).is_ok())
+}
+
+/// Rejects the fields collected by a `#[serde(flatten)]` map, those are not known by the model.
+/// `ignored` lists the fields which the official clients send, but which are not used by the server.
+pub fn check_unknown_fields(model: &str, unknown: &Map<String, Value>, ignored: &[&str]) -> EmptyResult {
+    let unknown: Vec<&str> = unknown.keys().map(String::as_str).filter(|k| !ignored.contains(k)).collect();
+    if !unknown.is_empty() {
+        err!(format!("The {model} contains unknown fields: {}", unknown.join(", ")), ErrorCode::MalformedRequest)
+    }
+    Ok(())
+}
+
+pub fn check_enc_string(field: &str, value: Option<&str>) -> EmptyResult {
+    if let Some(value) = value
+        && !is_enc_string(value)
+    {
+        err!(format!("The field {field} is not a valid encrypted string"), ErrorCode::MalformedRequest)
+    }
+    Ok(())
+}
+
+/// Checks all the strings nested in the value.
+/// Dates are the only values which are sent in plain text, all other strings need to be encrypted.
+/// The `response` objects some clients add are skipped, those are removed before the data is stored.
+pub fn check_enc_strings_in(field: &str, value: Option<&Value>) -> EmptyResult {
+    match value {
+        Some(Value::String(s)) => check_enc_string(field, Some(s)),
+        Some(Value::Array(values)) => values.iter().try_for_each(|v| check_enc_strings_in(field, Some(v))),
+        Some(Value::Object(map)) => map
+            .iter()
+            .filter(|(key, _)| !key.ends_with("Date") && *key != "response")
+            .try_for_each(|(key, v)| check_enc_strings_in(&format!("{field}.{key}"), Some(v))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngExt;
+
+    use super::*;
+
+    #[test]
+    fn test_enc_string() {
+        assert!(is_enc_string("2.AAAAAAAAAAAAAAAAAAAAAA==|c2VjcmV0|bWFj"));
+        assert!(is_enc_string("4.c2VjcmV0"));
+        assert!(!is_enc_string("2.AAAAAAAAAAAAAAAAAAAAAA==|c2VjcmV0"));
+        assert!(!is_enc_string("2.AAAA|not base64!|bWFj"));
+        assert!(!is_enc_string("9.c2VjcmV0"));
+        assert!(!is_enc_string("plain text"));
+
+        let login = json!({ "username": "4.c2VjcmV0", "passwordRevisionDate": "2025-01-01T00:00:00Z" });
+        assert!(check_enc_strings_in("login", Some(&login)).is_ok());
+        let login = json!({ "uris": [{ "uri": "https://example.com", "match": 0 }] });
+        assert!(check_enc_strings_in("login", Some(&login)).is_err());
+    }
+
+    // Number of `|` separated parts of every encryption type, from the upstream enum
+    const PARTS: [(&str, usize); 8] = [("0", 2), ("1", 3), ("2", 3), ("3", 1), ("4", 1), ("5", 2), ("6", 2), ("7", 1)];
+
+    // Characters which are neither base64 nor separators
+    const NOT_ENCODED: [char; 8] = [' ', '-', '_', '!', '\0', '\n', '\u{e9}', '\u{1f512}'];
+
+    fn random_enc_string(rng: &mut impl rand::Rng, enc_type: &str, parts: usize) -> String {
+        let parts: Vec<String> = (0..parts)
+            .map(|_| {
+                let bytes: Vec<u8> = (0..rng.random_range(1..48)).map(|_| rng.random()).collect();
+                BASE64.encode(&bytes)
+            })
+            .collect();
+        format!("{enc_type}.{}", parts.join("|"))
+    }
+
+    #[test]
+    fn fuzz_enc_string() {
+        let mut rng = rand::rng();
+        for _ in 0..10_000 {
+            let (enc_type, parts) = PARTS[rng.random_range(0..PARTS.len())];
+
+            // Any base64 encoded data with the right number of parts is accepted
+            let valid = random_enc_string(&mut rng, enc_type, parts);
+            assert!(is_enc_string(&valid), "{valid}");
+
+            // A part more or less is not
+            let wrong_parts = if rng.random() {
+                parts + 1
+            } else {
+                parts - 1
+            };
+            if wrong_parts > 0 {
+                let invalid = random_enc_string(&mut rng, enc_type, wrong_parts);
+                assert!(!is_enc_string(&invalid), "{invalid}");
+            }
+
+            // A character which can't be part of an encrypted string makes it invalid, wherever it is
+            let mut chars: Vec<char> = valid.chars().collect();
+            let pos = rng.random_range(0..chars.len());
+            chars[pos] = NOT_ENCODED[rng.random_range(0..NOT_ENCODED.len())];
+            let invalid: String = chars.into_iter().collect();
+            assert!(!is_enc_string(&invalid), "{invalid:?}");
+
+            // Random bytes never panic, and are only accepted with a known type and the right number of parts
+            let mut bytes = valid.into_bytes();
+            for _ in 0..rng.random_range(1..4) {
+                let pos = rng.random_range(0..bytes.len());
+                bytes[pos] = rng.random();
+            }
+            let mutated = String::from_utf8_lossy(&bytes).into_owned();
+            if is_enc_string(&mutated) {
+                let (enc_type, data) = mutated.split_once('.').unwrap();
+                let expected = PARTS.iter().find(|(t, _)| *t == enc_type).map(|(_, parts)| *parts);
+                assert_eq!(expected, Some(data.split('|').count()), "{mutated:?}");
+                assert!(
+                    data.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=' | '|')),
+                    "{mutated:?}"
+                );
+            }
+        }
+    }
+}
@@ -5,7 +5,11 @@ use crate::{
     CONFIG,
     api::{
         EmptyResult, JsonResult, PasswordOrOtpData,
-        core::{log_user_event, two_factor::generate_recover_code},
+        core::{
+            accounts::{finish_security_change, start_security_change},
+            log_user_event,
+            two_factor::generate_recover_code,
+        },
     },
     auth::{ClientHeaders, Headers},
     crypto,
@@ -159,7 +163,7 @@ struct SendEmailData {
 #[post("/two-factor/send-email", data = "<data>")]
 async fn send_email(data: Json<SendEmailData>, headers: Headers, conn: DbConn) -> EmptyResult {
     let data: SendEmailData = data.into_inner();
-    let user = headers.user;
+    let mut user = headers.user;
 
     PasswordOrOtpData {
         master_password_hash: data.master_password_hash,
@@ -185,6 +189,9 @@ async fn send_email(data: Json<SendEmailData>, headers: Headers, conn: DbConn) -
     let twofactor = TwoFactor::new(user.uuid, TwoFactorType::EmailVerificationChallenge, twofactor_data.to_json());
     twofactor.save(&conn).await?;
 
+    start_security_change(&mut user, "two-step login").await;
+    user.save(&conn).await?;
+
     mail::send_token(&twofactor_data.email, &twofactor_data.last_token.map_res("Token is empty")?).await?;
 
     Ok(())
@@ -232,6 +239,8 @@ async fn email(data: Json<EmailData>, headers: Headers, conn: DbConn) -> JsonRes
     twofactor.data = email_data.to_json();
     twofactor.save(&conn).await?;
 
+    finish_security_change(&mut user);
+    user.save(&conn).await?;
     generate_recover_code(&mut user, &conn).await;
 
     log_user_event(EventType::UserUpdated2fa as i32, &user.uuid, headers.device.atype, &headers.ip.ip, &conn).await;
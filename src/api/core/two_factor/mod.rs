@@ -1,3 +1,5 @@
+use std::net::{IpAddr, Ipv4Addr};
+
 use chrono::{TimeDelta, Utc};
 use data_encoding::BASE32;
 use num_traits::FromPrimitive;
@@ -16,8 +18,8 @@ use crate::{
     db::{
         DbConn, DbPool,
         models::{
-            DeviceType, EventType, Membership, MembershipType, OrgPolicyType, Organization, OrganizationId, TwoFactor,
-            TwoFactorIncomplete, TwoFactorType, User, UserId,
+            DeviceType, EventType, Membership, MembershipType, OrgPolicy, OrgPolicyType, Organization, OrganizationId,
+            TwoFactor, TwoFactorIncomplete, TwoFactorPolicyData, TwoFactorType, User, UserId,
         },
     },
     mail,
@@ -181,11 +183,23 @@ pub async fn enforce_2fa_policy(
     for member in Membership::find_by_user_and_policy(&user.uuid, OrgPolicyType::TwoFactorAuthentication, conn).await {
         // Policy only applies to non-Owner/non-Admin members who have accepted joining the org
         if member.atype < MembershipType::Admin {
+            let org = Organization::find_by_uuid(&member.org_uuid, conn).await.unwrap();
+            let mut member = member;
+            // Members within the grace period keep their access, it is only revoked once it has passed
+            if let Some(grace_period) = OrgPolicy::two_factor_grace_period(&org.uuid, conn).await {
+                match member.two_factor_grace_until {
+                    None => {
+                        start_2fa_grace_period(&mut member, &user.email, &org.name, grace_period, conn).await?;
+                        continue;
+                    }
+                    Some(grace_until) if grace_until > Utc::now().naive_utc() => continue,
+                    Some(_) => (),
+                }
+            }
+
             if CONFIG.mail_enabled() {
-                let org = Organization::find_by_uuid(&member.org_uuid, conn).await.unwrap();
                 mail::send_2fa_removed_from_org(&user.email, &org.name).await?;
             }
-            let mut member = member;
             member.revoke();
             member.save(conn).await?;
 
@@ -205,8 +219,11 @@ pub async fn enforce_2fa_policy(
     Ok(())
 }
 
+/// Revokes the members without 2FA, or starts their grace period when the policy has one.
+/// The grace period is passed in, because this runs before the policy is saved.
 pub async fn enforce_2fa_policy_for_org(
     org_id: &OrganizationId,
+    grace_period: Option<TimeDelta>,
     act_user_id: &UserId,
     device_type: i32,
     ip: &std::net::IpAddr,
@@ -216,11 +233,18 @@ pub async fn enforce_2fa_policy_for_org(
     for member in Membership::find_confirmed_by_org(org_id, conn).await {
         // Don't enforce the policy for Admins and Owners.
         if member.atype < MembershipType::Admin && TwoFactor::find_by_user(&member.user_uuid, conn).await.is_empty() {
+            let user = User::find_by_uuid(&member.user_uuid, conn).await.unwrap();
+            let mut member = member;
+            if let Some(grace_period) = grace_period {
+                if member.two_factor_grace_until.is_none() {
+                    start_2fa_grace_period(&mut member, &user.email, &org.name, grace_period, conn).await?;
+                }
+                continue;
+            }
+
             if CONFIG.mail_enabled() {
-                let user = User::find_by_uuid(&member.user_uuid, conn).await.unwrap();
                 mail::send_2fa_removed_from_org(&user.email, &org.name).await?;
             }
-            let mut member = member;
             member.revoke();
             member.save(conn).await?;
 
@@ -240,6 +264,118 @@ pub async fn enforce_2fa_policy_for_org(
     Ok(())
 }
 
+async fn start_2fa_grace_period(
+    member: &mut Membership,
+    email: &str,
+    org_name: &str,
+    grace_period: TimeDelta,
+    conn: &DbConn,
+) -> EmptyResult {
+    let grace_until = Utc::now().naive_utc() + grace_period;
+    member.two_factor_grace_until = Some(grace_until);
+    member.save(conn).await?;
+
+    if CONFIG.mail_enabled() {
+        mail::send_2fa_grace_period(email, org_name, &grace_until).await?;
+    }
+    Ok(())
+}
+
+/// Sends reminders to the members which still need to enable 2FA, and revokes them once their grace period has passed.
+/// Members who joined while the policy was already enabled start their grace period here.
+pub async fn enforce_2fa_grace_periods(pool: DbPool) {
+    debug!("Enforcing the 2FA grace periods of organizations");
+
+    let Ok(conn) = pool.get().await else {
+        error!("Failed to get DB connection in enforce_2fa_grace_periods()");
+        return;
+    };
+
+    let now = Utc::now().naive_utc();
+    for mut member in Membership::find_in_two_factor_grace_period(&conn).await {
+        let Some(grace_until) = member.two_factor_grace_until else {
+            continue;
+        };
+        let policy_enabled =
+            OrgPolicy::find_by_org_and_type(&member.org_uuid, OrgPolicyType::TwoFactorAuthentication, &conn)
+                .await
+                .is_some_and(|p| p.enabled);
+        if !policy_enabled
+            || member.atype >= MembershipType::Admin
+            || !TwoFactor::find_by_user(&member.user_uuid, &conn).await.is_empty()
+        {
+            member.two_factor_grace_until = None;
+            if let Err(e) = member.save(&conn).await {
+                error!("Error ending the 2FA grace period of member {}: {e:#?}", member.uuid);
+            }
+            continue;
+        }
+
+        let (Some(user), Some(org)) = (
+            User::find_by_uuid(&member.user_uuid, &conn).await,
+            Organization::find_by_uuid(&member.org_uuid, &conn).await,
+        ) else {
+            continue;
+        };
+
+        if grace_until > now {
+            if CONFIG.mail_enabled()
+                && let Err(e) = mail::send_2fa_grace_period(&user.email, &org.name, &grace_until).await
+            {
+                error!("Error sending 2FA grace period reminder: {e:#?}");
+            }
+            continue;
+        }
+
+        info!("Revoking {} from organization {} because the 2FA grace period has passed", user.email, org.name);
+        member.revoke();
+        if let Err(e) = member.save(&conn).await {
+            error!("Error revoking member {}: {e:#?}", member.uuid);
+            continue;
+        }
+        if CONFIG.mail_enabled()
+            && let Err(e) = mail::send_2fa_removed_from_org(&user.email, &org.name).await
+        {
+            error!("Error sending 2FA removed email: {e:#?}");
+        }
+        log_event(
+            EventType::OrganizationUserRevoked as i32,
+            &member.uuid,
+            &member.org_uuid,
+            &member.user_uuid,
+            DeviceType::UnknownBrowser as i32,
+            &IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            &conn,
+        )
+        .await;
+    }
+
+    for policy in OrgPolicy::find_enabled_by_type(OrgPolicyType::TwoFactorAuthentication, &conn).await {
+        let Some(grace_period) =
+            serde_json::from_str::<TwoFactorPolicyData>(&policy.data).ok().and_then(|data| data.grace_period())
+        else {
+            continue;
+        };
+        let Some(org) = Organization::find_by_uuid(&policy.org_uuid, &conn).await else {
+            continue;
+        };
+        for mut member in Membership::find_confirmed_by_org(&policy.org_uuid, &conn).await {
+            if member.atype >= MembershipType::Admin
+                || member.two_factor_grace_until.is_some()
+                || !TwoFactor::find_by_user(&member.user_uuid, &conn).await.is_empty()
+            {
+                continue;
+            }
+            let Some(user) = User::find_by_uuid(&member.user_uuid, &conn).await else {
+                continue;
+            };
+            if let Err(e) = start_2fa_grace_period(&mut member, &user.email, &org.name, grace_period, &conn).await {
+                error!("Error starting the 2FA grace period of member {}: {e:#?}", member.uuid);
+            }
+        }
+    }
+}
+
 pub async fn send_incomplete_2fa_notifications(pool: DbPool) {
     debug!("Sending notifications for incomplete 2FA logins");
 
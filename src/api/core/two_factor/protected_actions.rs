@@ -61,9 +61,16 @@ impl ProtectedActionData {
     }
 }
 
+/// Sends a code to verify sensitive actions, like an export, a purge or viewing the API key.
+/// Used by users without a master password, or which logged in with a device instead of their master password.
 #[post("/accounts/request-otp")]
 async fn request_otp(headers: Headers, conn: DbConn) -> EmptyResult {
     if !CONFIG.mail_enabled() {
+        if headers.user.password_hash.is_empty() {
+            err!(
+                "Email is disabled for this server. Accounts without a master password can't verify sensitive actions."
+            );
+        }
         err!(
             "Email is disabled for this server. Either enable email or login using your master password instead of login via device."
         );
@@ -13,22 +13,26 @@ use reqwest::{
     Client, Response,
     header::{self, HeaderMap, HeaderValue},
 };
-use rocket::{Route, http::ContentType, response::Redirect};
+use rocket::{Either, Route, State, http::ContentType, response::Redirect};
 use svg_hush::{Filter, data_url_filter};
 
 use crate::{
     CONFIG,
-    config::PathType,
+    config::{PathType, generate_icon_service_url},
+    db::{
+        DbPool,
+        models::{Organization, OrganizationId},
+    },
     error::Error,
     http_client::{CustomHttpClientError, get_reqwest_client_builder, get_valid_host, should_block_host},
-    util::Cached,
+    util::{Cached, content_etag},
 };
 
 pub fn routes() -> Vec<Route> {
     if CONFIG.icon_service().as_str() == "internal" {
-        routes![icon_internal]
+        routes![icon_internal, icon_org]
     } else {
-        routes![icon_external]
+        routes![icon_external, icon_org]
     }
 }
 
@@ -79,22 +83,26 @@ static CLIENT: LazyLock<Client> = LazyLock::new(|| {
 // Build Regex only once since this takes a lot of time.
 static ICON_SIZE_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?x)(\d+)\D*(\d+)").unwrap());
 
-// The function name `icon_external` is checked in the `on_response` function in `AppHeaders`
+// The function names `icon_external` and `icon_org` are checked in the `on_response` function in `AppHeaders`
 // It is used to prevent sending a specific header which breaks icon downloads.
-// If this function needs to be renamed, also adjust the code in `util.rs`
+// If these functions need to be renamed, also adjust the code in `util.rs`
 #[get("/<host>/icon.png")]
 fn icon_external(host: &str) -> Cached<Option<Redirect>> {
     let Ok(host) = get_valid_host(host) else {
         warn!("Invalid host: {host}");
-        return Cached::ttl(None, CONFIG.icon_cache_negttl(), true);
+        return Cached::ttl(None, CONFIG.icon_http_negttl(), true);
     };
 
     if should_block_host(&host).is_err() {
         warn!("Blocked address: {host}");
-        return Cached::ttl(None, CONFIG.icon_cache_negttl(), true);
+        return Cached::ttl(None, CONFIG.icon_http_negttl(), true);
     }
 
-    let url = CONFIG._icon_service_url().replace("{}", &host.to_string());
+    icon_redirect(&CONFIG._icon_service_url(), &host.to_string())
+}
+
+fn icon_redirect(icon_service_url: &str, host: &str) -> Cached<Option<Redirect>> {
+    let url = icon_service_url.replace("{}", host);
     let redir = match CONFIG.icon_redirect_code() {
         301 => Some(Redirect::moved(url)), // legacy permanent redirect
         302 => Some(Redirect::found(url)), // legacy temporary redirect
@@ -105,40 +113,79 @@ fn icon_external(host: &str) -> Cached<Option<Redirect>> {
             None
         }
     };
-    Cached::ttl(redir, CONFIG.icon_cache_ttl(), true)
+    Cached::ttl(redir, CONFIG.icon_http_ttl(), true)
+}
+
+/// The same as the other icon routes, but uses the icon service of the organization when it has one.
+/// The Bitwarden clients only send the host of an item, so this is only used by clients which add the organization,
+/// the icons of other requests for items of the organization still use the icon service of the instance.
+/// Icon requests can't be authenticated, so an organization can't enable the internal service for itself.
+/// With the CSP of the web-vault, an external service other than `ICON_SERVICE` is only shown in the other clients.
+#[get("/<host>/icon.png?<org>")]
+async fn icon_org(
+    host: &str,
+    org: OrganizationId,
+    pool: &State<DbPool>,
+) -> Either<Cached<Option<Redirect>>, Cached<(ContentType, Vec<u8>)>> {
+    let icon_service = match pool.get().await {
+        Ok(conn) => Organization::find_by_uuid(&org, &conn).await.and_then(|org| org.icon_service),
+        Err(e) => {
+            error!("Unable to get a database connection for the icon service of an organization: {e:?}");
+            None
+        }
+    };
+
+    // The route isn't authenticated, so an organization can only choose between redirects.
+    // The server only fetches icons itself when the instance uses the internal service.
+    let icon_service = icon_service.filter(|s| s != "internal").unwrap_or_else(|| CONFIG.icon_service());
+    match icon_service.as_str() {
+        "none" => Either::Left(Cached::ttl(None, CONFIG.icon_http_negttl(), true)),
+        "internal" => Either::Right(icon_internal(host).await),
+        service => {
+            let Ok(valid_host) = get_valid_host(host) else {
+                warn!("Invalid host: {host}");
+                return Either::Left(Cached::ttl(None, CONFIG.icon_http_negttl(), true));
+            };
+            if should_block_host(&valid_host).is_err() {
+                warn!("Blocked address: {valid_host}");
+                return Either::Left(Cached::ttl(None, CONFIG.icon_http_negttl(), true));
+            }
+            Either::Left(icon_redirect(&generate_icon_service_url(service), &valid_host.to_string()))
+        }
+    }
 }
 
 #[get("/<host>/icon.png")]
 async fn icon_internal(host: &str) -> Cached<(ContentType, Vec<u8>)> {
-    const FALLBACK_ICON: &[u8] = include_bytes!("../static/images/fallback-icon.png");
-
     let Ok(host) = get_valid_host(host) else {
         warn!("Invalid host: {host}");
-        return Cached::ttl(
-            (ContentType::new("image", "png"), FALLBACK_ICON.to_vec()),
-            CONFIG.icon_cache_negttl(),
-            true,
-        );
+        return fallback_icon();
     };
 
     if should_block_host(&host).is_err() {
         warn!("Blocked address: {host}");
-        return Cached::ttl(
-            (ContentType::new("image", "png"), FALLBACK_ICON.to_vec()),
-            CONFIG.icon_cache_negttl(),
-            true,
-        );
+        return fallback_icon();
     }
 
     match get_icon(&host.to_string()).await {
-        Some((icon, icon_type)) => {
-            Cached::ttl((ContentType::new("image", icon_type), icon), CONFIG.icon_cache_ttl(), true)
+        Some((icon, icon_type, last_modified)) => {
+            let etag = content_etag(&icon);
+            Cached::ttl((ContentType::new("image", icon_type), icon), CONFIG.icon_http_ttl(), true)
+                .with_validators(etag, Some(last_modified))
         }
-        _ => Cached::ttl((ContentType::new("image", "png"), FALLBACK_ICON.to_vec()), CONFIG.icon_cache_negttl(), true),
+        _ => fallback_icon(),
     }
 }
 
-async fn get_icon(domain: &str) -> Option<(Vec<u8>, String)> {
+fn fallback_icon() -> Cached<(ContentType, Vec<u8>)> {
+    const FALLBACK_ICON: &[u8] = include_bytes!("../static/images/fallback-icon.png");
+
+    Cached::ttl((ContentType::new("image", "png"), FALLBACK_ICON.to_vec()), CONFIG.icon_http_negttl(), true)
+        .with_validators(content_etag(FALLBACK_ICON), None)
+}
+
+/// Returns the icon, its type and when it was downloaded
+async fn get_icon(domain: &str) -> Option<(Vec<u8>, String, SystemTime)> {
     let path = format!("{domain}.png");
 
     // Check for expiration of negatively cached copy
@@ -146,9 +193,9 @@ async fn get_icon(domain: &str) -> Option<(Vec<u8>, String)> {
         return None;
     }
 
-    if let Some(icon) = get_cached_icon(&path).await {
+    if let Some((icon, last_modified)) = get_cached_icon(&path).await {
         let icon_type = get_icon_type(&icon).unwrap_or("x-icon");
-        return Some((icon, icon_type.to_owned()));
+        return Some((icon, icon_type.to_owned(), last_modified));
     }
 
     if CONFIG.disable_icon_download() {
@@ -159,7 +206,7 @@ async fn get_icon(domain: &str) -> Option<(Vec<u8>, String)> {
     match download_icon(domain).await {
         Ok((icon, icon_type)) => {
             save_icon(&path, icon.to_vec()).await;
-            Some((icon.to_vec(), icon_type.unwrap_or("x-icon").to_owned()))
+            Some((icon.to_vec(), icon_type.unwrap_or("x-icon").to_owned(), SystemTime::now()))
         }
         Err(e) => {
             // If this error comes from the custom resolver, this means this is a blocked domain
@@ -177,32 +224,44 @@ async fn get_icon(domain: &str) -> Option<(Vec<u8>, String)> {
     }
 }
 
-async fn get_cached_icon(path: &str) -> Option<Vec<u8>> {
+/// Returns the cached icon and when it was downloaded
+async fn get_cached_icon(path: &str) -> Option<(Vec<u8>, SystemTime)> {
     // Check for expiration of successfully cached copy
-    if icon_is_expired(path).await {
+    let last_modified = file_last_modified(path).await.ok()?;
+    if is_expired(last_modified, CONFIG.icon_cache_ttl()).unwrap_or(true) {
         return None;
     }
 
     // Try to read the cached icon, and return it if it exists
-    if let Ok(operator) = CONFIG.opendal_operator_for_path_type(&PathType::IconCache)
-        && let Ok(buf) = operator.read(path).await
+    if let Ok(backend) = CONFIG.storage_backend(&PathType::IconCache)
+        && let Ok(Some(buf)) = backend.get(path).await
     {
-        return Some(buf.to_vec());
+        return Some((buf, last_modified));
     }
 
     None
 }
 
-async fn file_is_expired(path: &str, ttl: u64) -> Result<bool, Error> {
-    let operator = CONFIG.opendal_operator_for_path_type(&PathType::IconCache)?;
-    let meta = operator.stat(path).await?;
+async fn file_last_modified(path: &str) -> Result<SystemTime, Error> {
+    let backend = CONFIG.storage_backend(&PathType::IconCache)?;
+    let stat = backend
+        .stat(path)
+        .await?
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("`{path}` does not exist")))?;
     let modified =
-        meta.last_modified().ok_or_else(|| std::io::Error::other(format!("No last modified time for `{path}`")))?;
-    let age = SystemTime::now().duration_since(modified.into())?;
+        stat.last_modified.ok_or_else(|| std::io::Error::other(format!("No last modified time for `{path}`")))?;
+    Ok(modified)
+}
 
+fn is_expired(last_modified: SystemTime, ttl: u64) -> Result<bool, Error> {
+    let age = SystemTime::now().duration_since(last_modified)?;
     Ok(ttl > 0 && ttl <= age.as_secs())
 }
 
+async fn file_is_expired(path: &str, ttl: u64) -> Result<bool, Error> {
+    is_expired(file_last_modified(path).await?, ttl)
+}
+
 async fn icon_is_negcached(path: &str) -> bool {
     let miss_indicator = path.to_owned() + ".miss";
     let expired = file_is_expired(&miss_indicator, CONFIG.icon_cache_negttl()).await;
@@ -210,9 +269,9 @@ async fn icon_is_negcached(path: &str) -> bool {
     match expired {
         // No longer negatively cached, drop the marker
         Ok(true) => {
-            match CONFIG.opendal_operator_for_path_type(&PathType::IconCache) {
-                Ok(operator) => {
-                    if let Err(e) = operator.delete(&miss_indicator).await {
+            match CONFIG.storage_backend(&PathType::IconCache) {
+                Ok(backend) => {
+                    if let Err(e) = backend.delete(&miss_indicator, false).await {
                         error!("Could not remove negative cache indicator for icon {path:?}: {e:?}");
                     }
                 }
@@ -227,11 +286,6 @@ async fn icon_is_negcached(path: &str) -> bool {
     }
 }
 
-async fn icon_is_expired(path: &str) -> bool {
-    let expired = file_is_expired(path, CONFIG.icon_cache_ttl()).await;
-    expired.unwrap_or(true)
-}
-
 struct Icon {
     priority: u8,
     href: String,
@@ -570,15 +624,15 @@ async fn download_icon(domain: &str) -> Result<(Bytes, Option<&str>), Error> {
 }
 
 async fn save_icon(path: &str, icon: Vec<u8>) {
-    let operator = match CONFIG.opendal_operator_for_path_type(&PathType::IconCache) {
-        Ok(operator) => operator,
+    let backend = match CONFIG.storage_backend(&PathType::IconCache) {
+        Ok(backend) => backend,
         Err(e) => {
-            warn!("Failed to get OpenDAL operator while saving icon: {e}");
+            warn!("Failed to get storage backend while saving icon: {e}");
             return;
         }
     };
 
-    if let Err(e) = operator.write(path, icon).await {
+    if let Err(e) = backend.put(path, icon).await {
         warn!("Unable to save icon: {e:?}");
     }
 }
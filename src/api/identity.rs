@@ -1,3 +1,8 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
 use chrono::Utc;
 use num_traits::FromPrimitive;
 use rocket::{
@@ -15,7 +20,8 @@ use crate::{
         ApiResult, EmptyResult, JsonResult,
         core::{
             accounts::{PreloginData, RegisterData, kdf_upgrade, prelogin, register},
-            log_user_event,
+            capabilities::{Capability, ClientCapabilities},
+            invite_to_claimed_domain_org, log_user_event,
             two_factor::{
                 authenticator, duo, duo_oidc, email, enforce_2fa_policy, is_twofactor_provider_usable, webauthn,
                 yubikey,
@@ -25,19 +31,24 @@ use crate::{
         push::register_push_device,
     },
     auth,
-    auth::{AuthMethod, ClientHeaders, ClientIp, ClientVersion, Secure, generate_organization_api_key_login_claims},
+    auth::{
+        AuthMethod, ClientCountry, ClientHeaders, ClientIp, ClientVersion, Secure,
+        generate_organization_api_key_login_claims,
+    },
+    config::CLIENT_TYPES,
     crypto,
     db::{
         DbConn,
         models::{
-            AuthRequest, AuthRequestId, Device, DeviceId, EventType, Invitation, OIDCCodeResponseError,
-            OrganizationApiKey, OrganizationId, SsoAuth, SsoUser, TwoFactor, TwoFactorIncomplete, TwoFactorType, User,
-            UserId,
+            AuthRequest, AuthRequestId, Device, DeviceId, DeviceType, EventType, FingerprintStatus, Invitation,
+            LoginFingerprint, OIDCCodeResponseError, OrgPolicy, OrgPolicyType, OrganizationApiKey, OrganizationId,
+            SsoAuth, SsoUser, TwoFactor, TwoFactorIncomplete, TwoFactorType, User, UserId,
         },
     },
     error::MapResult,
     mail, sso,
     sso::{OIDCCode, OIDCCodeChallenge, OIDCCodeVerifier, OIDCState},
+    tenant::RequestTenant,
     util,
 };
 
@@ -61,16 +72,70 @@ async fn login(
     data: Form<ConnectData>,
     client_header: ClientHeaders,
     client_version: Option<ClientVersion>,
+    country: ClientCountry,
+    tenant: RequestTenant,
     conn: DbConn,
 ) -> JsonResult {
-    let data: ConnectData = data.into_inner();
+    token_request(data.into_inner(), client_header, client_version, country, tenant, &conn).await
+}
+
+/// The password login of the old `POST /api/auth/token` route, see `api/core/legacy.rs`
+pub struct LegacyPasswordLogin {
+    pub email: String,
+    pub master_password_hash: String,
+    pub device_identifier: DeviceId,
+    pub device_name: String,
+    pub device_type: i32,
+    pub two_factor_provider: Option<i32>,
+    pub two_factor_token: Option<String>,
+    pub two_factor_remember: bool,
+}
+
+/// Handles an old style login as a `password` grant of `/identity/connect/token`
+pub async fn legacy_password_login(
+    login: LegacyPasswordLogin,
+    client_header: ClientHeaders,
+    client_version: Option<ClientVersion>,
+    country: ClientCountry,
+    tenant: RequestTenant,
+    conn: &DbConn,
+) -> JsonResult {
+    let client_id = DeviceType::from_i32(login.device_type).client_type().unwrap_or("web");
+    let data = ConnectData {
+        grant_type: String::from("password"),
+        client_id: Some(client_id.to_owned()),
+        password: Some(login.master_password_hash),
+        scope: Some(String::from("api offline_access")),
+        username: Some(login.email),
+        device_identifier: Some(login.device_identifier),
+        device_name: Some(login.device_name),
+        device_type: Some(login.device_type.to_string()),
+        two_factor_provider: login.two_factor_provider,
+        two_factor_token: login.two_factor_token,
+        two_factor_remember: Some(i32::from(login.two_factor_remember)),
+        ..Default::default()
+    };
+    token_request(data, client_header, client_version, country, tenant, conn).await
+}
+
+async fn token_request(
+    data: ConnectData,
+    client_header: ClientHeaders,
+    client_version: Option<ClientVersion>,
+    country: ClientCountry,
+    tenant: RequestTenant,
+    conn: &DbConn,
+) -> JsonResult {
+    let country = country.0.as_deref();
+
+    check_client_version(&data, &client_header, client_version.as_ref())?;
 
     let mut user_id: Option<UserId> = None;
 
     let login_result = match data.grant_type.as_ref() {
         "refresh_token" => {
             check_is_some(data.refresh_token.as_ref(), "refresh_token cannot be blank")?;
-            refresh_login(data, &conn, &client_header.ip).await
+            refresh_login(data, conn, &client_header.ip).await
         }
         "password" if CONFIG.sso_enabled() && CONFIG.sso_only() => err!("SSO sign-in is required"),
         "password" => {
@@ -83,7 +148,7 @@ async fn login(
             check_is_some(data.device_name.as_ref(), "device_name cannot be blank")?;
             check_is_some(data.device_type.as_ref(), "device_type cannot be blank")?;
 
-            password_login(data, &mut user_id, &conn, &client_header.ip, client_version.as_ref()).await
+            password_login(data, &mut user_id, &tenant, conn, &client_header.ip, country, client_version.as_ref()).await
         }
         "client_credentials" => {
             check_is_some(data.client_id.as_ref(), "client_id cannot be blank")?;
@@ -94,7 +159,7 @@ async fn login(
             check_is_some(data.device_name.as_ref(), "device_name cannot be blank")?;
             check_is_some(data.device_type.as_ref(), "device_type cannot be blank")?;
 
-            api_key_login(data, &mut user_id, &conn, &client_header.ip).await
+            api_key_login(data, &mut user_id, conn, &client_header.ip, country).await
         }
         "authorization_code" if CONFIG.sso_enabled() => {
             check_is_some(data.client_id.as_ref(), "client_id cannot be blank")?;
@@ -105,7 +170,7 @@ async fn login(
             check_is_some(data.device_name.as_ref(), "device_name cannot be blank")?;
             check_is_some(data.device_type.as_ref(), "device_type cannot be blank")?;
 
-            sso_login(data, &mut user_id, &conn, &client_header.ip, client_version.as_ref()).await
+            sso_login(data, &mut user_id, &tenant, conn, &client_header.ip, country, client_version.as_ref()).await
         }
         "authorization_code" => err!("SSO sign-in is not available"),
         t => err!("Invalid type", t),
@@ -119,13 +184,13 @@ async fn login(
                     &user_id,
                     client_header.device_type,
                     &client_header.ip.ip,
-                    &conn,
+                    conn,
                 )
                 .await;
             }
             Err(e) => {
                 if let Some(ev) = e.get_event() {
-                    log_user_event(ev.event as i32, &user_id, client_header.device_type, &client_header.ip.ip, &conn)
+                    log_user_event(ev.event as i32, &user_id, client_header.device_type, &client_header.ip.ip, conn)
                         .await;
                 }
             }
@@ -161,6 +226,7 @@ async fn refresh_login(data: ConnectData, conn: &DbConn, ip: &ClientIp) -> JsonR
         Ok((mut device, auth_tokens)) => {
             // Save to update `device.updated_at` to track usage and toggle new status
             device.save(true, conn).await?;
+            User::update_last_login(&device.user_uuid, conn).await?;
 
             let result = json!({
                 "refresh_token": auth_tokens.refresh_token(),
@@ -179,8 +245,10 @@ async fn refresh_login(data: ConnectData, conn: &DbConn, ip: &ClientIp) -> JsonR
 async fn sso_login(
     data: ConnectData,
     user_id: &mut Option<UserId>,
+    tenant: &RequestTenant,
     conn: &DbConn,
     ip: &ClientIp,
+    country: Option<&str>,
     client_version: Option<&ClientVersion>,
 ) -> JsonResult {
     AuthMethod::Sso.check_scope(data.scope.as_ref())?;
@@ -295,12 +363,23 @@ async fn sso_login(
 
             let mut user = User::new(&user_infos.email, user_infos.user_name.clone());
             user.verified_at = Some(now);
+            user.tenant_id = tenant.id();
             user.save(conn).await?;
+            invite_to_claimed_domain_org(&user, conn).await;
 
             let device = get_device(&data, conn, &user).await?;
 
             (user, device, None, None)
         }
+        Some((user, _)) if !tenant.matches(user.tenant_id.as_deref()) => {
+            err!(
+                "This user belongs to another tenant",
+                format!("IP: {}. Username: {}.", ip.ip, user.display_name()),
+                ErrorEvent {
+                    event: EventType::UserFailedLogIn
+                }
+            )
+        }
         Some((user, _)) if !user.enabled => {
             err!(
                 "This user has been disabled",
@@ -342,14 +421,16 @@ async fn sso_login(
     // We passed 2FA get auth tokens
     let auth_tokens = sso::redeem(&device, &user, data.client_id, sso_user, sso_auth, user_infos, conn).await?;
 
-    authenticated_response(&user, &mut device, auth_tokens, twofactor_token, conn, ip).await
+    authenticated_response(&user, &mut device, auth_tokens, twofactor_token, conn, ip, country).await
 }
 
 async fn password_login(
     data: ConnectData,
     user_id: &mut Option<UserId>,
+    tenant: &RequestTenant,
     conn: &DbConn,
     ip: &ClientIp,
+    country: Option<&str>,
     client_version: Option<&ClientVersion>,
 ) -> JsonResult {
     // Validate scope
@@ -360,7 +441,9 @@ async fn password_login(
 
     // Get the user
     let username = data.username.as_ref().unwrap().trim();
-    let Some(mut user) = User::find_by_mail(username, conn).await else {
+    // Users of other tenants are handled as if they don't exist
+    let Some(mut user) = User::find_by_mail(username, conn).await.filter(|u| tenant.matches(u.tenant_id.as_deref()))
+    else {
         err!("Username or password is incorrect. Try again", format!("IP: {}. Username: {username}.", ip.ip))
     };
 
@@ -382,7 +465,7 @@ async fn password_login(
 
     // If we get an auth request, we don't check the user's password, but the access code of the auth request
     if let Some(ref auth_request_id) = data.auth_request {
-        let Some(auth_request) = AuthRequest::find_by_uuid_and_user(auth_request_id, &user.uuid, conn).await else {
+        let Some(mut auth_request) = AuthRequest::find_by_uuid_and_user(auth_request_id, &user.uuid, conn).await else {
             err!(
                 "Auth request not found. Try again.",
                 format!("IP: {}. Username: {username}.", ip.ip),
@@ -409,6 +492,10 @@ async fn password_login(
                 }
             )
         }
+
+        // Shown in the auth request history of the user
+        auth_request.authentication_date = Some(Utc::now().naive_utc());
+        auth_request.save(conn).await?;
     } else if !user.check_valid_password(password) {
         err!(
             "Username or password is incorrect. Try again",
@@ -419,6 +506,18 @@ async fn password_login(
         )
     }
 
+    // Members of an organization requiring SSO need to use it, owners and admins are exempt
+    if CONFIG.sso_enabled() && OrgPolicy::is_applicable_to_user(&user.uuid, OrgPolicyType::RequireSso, None, conn).await
+    {
+        err!(
+            "SSO sign-in is required",
+            format!("IP: {}. Username: {username}.", ip.ip),
+            ErrorEvent {
+                event: EventType::UserFailedLogIn,
+            }
+        )
+    }
+
     // Change the KDF Iterations (only when not logging in with an auth request)
     if data.auth_request.is_none() {
         kdf_upgrade(&mut user, password, conn).await?;
@@ -464,7 +563,48 @@ async fn password_login(
 
     let auth_tokens = auth::AuthTokens::new(&device, &user, AuthMethod::Password, data.client_id);
 
-    authenticated_response(&user, &mut device, auth_tokens, twofactor_token, conn, ip).await
+    authenticated_response(&user, &mut device, auth_tokens, twofactor_token, conn, ip, country).await
+}
+
+/// Notifies the user of a login from a device, device type or country which wasn't seen before for the account.
+/// The login is only remembered once the email was sent, so a failed email is sent again on the next login.
+async fn notify_new_login(
+    user: &User,
+    device: &Device,
+    ip: &ClientIp,
+    country: Option<&str>,
+    conn: &DbConn,
+) -> EmptyResult {
+    match LoginFingerprint::find_status(device, country, conn).await {
+        FingerprintStatus::Known => return Ok(()),
+        FingerprintStatus::KnownWithoutCountry => return LoginFingerprint::save(device, country, conn).await,
+        FingerprintStatus::New => (),
+    }
+
+    if !CONFIG.mail_enabled() || !(user.login_notifications || CONFIG.require_device_email()) {
+        return LoginFingerprint::save(device, country, conn).await;
+    }
+    // The login itself is still remembered, only the email is skipped
+    if crate::ratelimit::is_trusted_network(&ip.ip) {
+        info!("New device login of {} from {} in TRUSTED_NETWORKS, no email is sent", user.email, ip.ip);
+        return LoginFingerprint::save(device, country, conn).await;
+    }
+
+    let now = Utc::now().naive_utc();
+    if let Err(e) = mail::send_new_device_logged_in(&user.email, &ip.ip.to_string(), country, &now, device).await {
+        error!("Error sending new device email: {e:#?}");
+
+        if CONFIG.require_device_email() {
+            err!(
+                "Could not send login notification email. Please contact your administrator.",
+                ErrorEvent {
+                    event: EventType::UserFailedLogIn
+                }
+            )
+        }
+        return Ok(());
+    }
+    LoginFingerprint::save(device, country, conn).await
 }
 
 async fn authenticated_response(
@@ -474,22 +614,9 @@ async fn authenticated_response(
     twofactor_token: Option<String>,
     conn: &DbConn,
     ip: &ClientIp,
+    country: Option<&str>,
 ) -> JsonResult {
-    if CONFIG.mail_enabled() && device.is_new() {
-        let now = Utc::now().naive_utc();
-        if let Err(e) = mail::send_new_device_logged_in(&user.email, &ip.ip.to_string(), &now, device).await {
-            error!("Error sending new device email: {e:#?}");
-
-            if CONFIG.require_device_email() {
-                err!(
-                    "Could not send login notification email. Please contact your administrator.",
-                    ErrorEvent {
-                        event: EventType::UserFailedLogIn
-                    }
-                )
-            }
-        }
-    }
+    notify_new_login(user, device, ip, country, conn).await?;
 
     // register push device
     if !device.is_new() {
@@ -498,6 +625,7 @@ async fn authenticated_response(
 
     // Save to update `device.updated_at` to track usage and toggle new status
     device.save(true, conn).await?;
+    User::update_last_login(&user.uuid, conn).await?;
 
     let master_password_policy = master_password_policy(user, conn).await;
 
@@ -567,13 +695,21 @@ async fn authenticated_response(
     Ok(Json(result))
 }
 
-async fn api_key_login(data: ConnectData, user_id: &mut Option<UserId>, conn: &DbConn, ip: &ClientIp) -> JsonResult {
+async fn api_key_login(
+    data: ConnectData,
+    user_id: &mut Option<UserId>,
+    conn: &DbConn,
+    ip: &ClientIp,
+    country: Option<&str>,
+) -> JsonResult {
     // Ratelimit the login
     crate::ratelimit::check_limit_login(&ip.ip)?;
 
     // Validate scope
     match data.scope.as_ref() {
-        Some(scope) if scope == &AuthMethod::UserApiKey.scope() => user_api_key_login(data, user_id, conn, ip).await,
+        Some(scope) if scope == &AuthMethod::UserApiKey.scope() => {
+            user_api_key_login(data, user_id, conn, ip, country).await
+        }
         Some(scope) if scope == &AuthMethod::OrgApiKey.scope() => organization_api_key_login(data, conn, ip).await,
         _ => err!("Scope not supported"),
     }
@@ -584,6 +720,7 @@ async fn user_api_key_login(
     user_id: &mut Option<UserId>,
     conn: &DbConn,
     ip: &ClientIp,
+    country: Option<&str>,
 ) -> JsonResult {
     // Get the user via the client_id
     let client_id = data.client_id.as_ref().unwrap();
@@ -621,24 +758,21 @@ async fn user_api_key_login(
         )
     }
 
-    let mut device = get_device(&data, conn, &user).await?;
-
-    if CONFIG.mail_enabled() && device.is_new() {
-        let now = Utc::now().naive_utc();
-        if let Err(e) = mail::send_new_device_logged_in(&user.email, &ip.ip.to_string(), &now, &device).await {
-            error!("Error sending new device email: {e:#?}");
-
-            if CONFIG.require_device_email() {
-                err!(
-                    "Could not send login notification email. Please contact your administrator.",
-                    ErrorEvent {
-                        event: EventType::UserFailedLogIn
-                    }
-                )
+    // Accounts which didn't verify their email address can't get any tokens
+    if user.verified_at.is_none() && CONFIG.mail_enabled() && CONFIG.signups_verify() {
+        err!(
+            "Please verify your email before trying again.",
+            format!("IP: {}. Username: {}.", ip.ip, user.email),
+            ErrorEvent {
+                event: EventType::UserFailedLogIn
             }
-        }
+        )
     }
 
+    let mut device = get_device(&data, conn, &user).await?;
+
+    notify_new_login(&user, &device, ip, country, conn).await?;
+
     // ---
     // Disabled this variable, it was used to generate the JWT
     // Because this might get used in the future, and is add by the Bitwarden Server, lets keep it, but then commented out
@@ -649,6 +783,7 @@ async fn user_api_key_login(
 
     // Save to update `device.updated_at` to track usage and toggle new status
     device.save(true, conn).await?;
+    User::update_last_login(&user.uuid, conn).await?;
 
     info!("User {} logged in successfully via API key. IP: {}", user.email, ip.ip);
 
@@ -749,6 +884,9 @@ async fn get_device(data: &ConnectData, conn: &DbConn, user: &User) -> ApiResult
 
     // Find device or create new
     if let Some(device) = Device::find_by_uuid_and_user(&device_id, &user.uuid, conn).await {
+        if device.blocked {
+            err!("This device has been blocked", format!("Device {device_id} of user {} is blocked", user.uuid))
+        }
         Ok(device)
     } else {
         let mut device = Device::new(device_id, user.uuid.clone(), device_name, device_type);
@@ -969,13 +1107,8 @@ async fn json_err_twofactor(
                     err!("No twofactor email registered")
                 };
 
-                // Starting with version 2025.5.0 the client will call `/api/two-factor/send-email-login`.
-                let disabled_send = if let Some(cv) = client_version {
-                    let ver_match = semver::VersionReq::parse(">=2025.5.0").unwrap();
-                    ver_match.matches(&cv.0)
-                } else {
-                    false
-                };
+                // Newer clients call `/api/two-factor/send-email-login` themselves
+                let disabled_send = ClientCapabilities::new(client_version).supports(Capability::SendEmailLogin);
 
                 // Send email immediately if email is the only 2FA option.
                 if providers.len() == 1 && !disabled_send {
@@ -1010,18 +1143,18 @@ async fn json_err_twofactor(
 }
 
 #[post("/accounts/prelogin", data = "<data>")]
-async fn post_prelogin(data: Json<PreloginData>, conn: DbConn) -> Json<Value> {
-    prelogin(data, conn).await
+async fn post_prelogin(data: Json<PreloginData>, tenant: RequestTenant, conn: DbConn) -> Json<Value> {
+    prelogin(data, tenant, conn).await
 }
 
 #[post("/accounts/prelogin/password", data = "<data>")]
-async fn prelogin_password(data: Json<PreloginData>, conn: DbConn) -> Json<Value> {
-    prelogin(data, conn).await
+async fn prelogin_password(data: Json<PreloginData>, tenant: RequestTenant, conn: DbConn) -> Json<Value> {
+    prelogin(data, tenant, conn).await
 }
 
 #[post("/accounts/register", data = "<data>")]
-async fn identity_register(data: Json<RegisterData>, conn: DbConn) -> JsonResult {
-    register(data, false, conn).await
+async fn identity_register(data: Json<RegisterData>, tenant: RequestTenant, conn: DbConn) -> JsonResult {
+    register(data, false, tenant, conn).await
 }
 
 #[derive(Debug, Deserialize)]
@@ -1042,12 +1175,13 @@ enum RegisterVerificationResponse {
 #[post("/accounts/register/send-verification-email", data = "<data>")]
 async fn register_verification_email(
     data: Json<RegisterVerificationData>,
+    tenant: RequestTenant,
     conn: DbConn,
 ) -> ApiResult<RegisterVerificationResponse> {
     let data = data.into_inner();
 
     // the registration can only continue if signup is allowed or there exists an invitation
-    if !(CONFIG.is_signup_allowed(&data.email)
+    if !(tenant.is_signup_allowed(&data.email)
         || (!CONFIG.mail_enabled() && Invitation::find_by_mail(&data.email, &conn).await.is_some()))
     {
         err!("Registration not allowed or user already exists")
@@ -1069,7 +1203,7 @@ async fn register_verification_email(
             let sleep_ms: u64 = rng.random_range(900..=1100);
             tokio::time::sleep(tokio::time::Duration::from_millis(sleep_ms)).await;
         } else {
-            mail::send_register_verify_email(&data.email, &token).await?;
+            mail::send_register_verify_email(&data.email, &token, tenant.mail()).await?;
         }
 
         Ok(RegisterVerificationResponse::NoContent(()))
@@ -1081,8 +1215,8 @@ async fn register_verification_email(
 }
 
 #[post("/accounts/register/finish", data = "<data>")]
-async fn register_finish(data: Json<RegisterData>, conn: DbConn) -> JsonResult {
-    register(data, true, conn).await
+async fn register_finish(data: Json<RegisterData>, tenant: RequestTenant, conn: DbConn) -> JsonResult {
+    register(data, true, tenant, conn).await
 }
 
 // https://github.com/bitwarden/jslib/blob/master/common/src/models/request/tokenRequest.ts
@@ -1145,6 +1279,65 @@ struct ConnectData {
     #[field(name = uncased("code_verifier"))]
     code_verifier: Option<OIDCCodeVerifier>,
 }
+// Number of token requests per client type and version since startup, shown on the admin diagnostics page
+static CLIENT_VERSIONS: LazyLock<Mutex<HashMap<(&'static str, String), u64>>> = LazyLock::new(Default::default);
+// Prevents the map from growing indefinitely when clients send random versions
+const MAX_CLIENT_VERSIONS: usize = 500;
+
+pub fn client_version_stats() -> Vec<(&'static str, String, u64)> {
+    let mut stats: Vec<_> = CLIENT_VERSIONS
+        .lock()
+        .map(|m| m.iter().map(|((t, v), count)| (*t, v.clone(), *count)).collect())
+        .unwrap_or_default();
+    stats.sort();
+    stats
+}
+
+fn check_client_version(
+    data: &ConnectData,
+    client_header: &ClientHeaders,
+    client_version: Option<&ClientVersion>,
+) -> EmptyResult {
+    // API keys are used by scripts and the Directory Connector, which don't identify as one of the client types.
+    // Their client_id is the key, so they would be checked as a web vault otherwise.
+    if data.grant_type == "client_credentials" {
+        return Ok(());
+    }
+
+    // The official clients send their type as `client_id`, older clients only send the device type
+    let client_type =
+        data.client_id.as_deref().and_then(|id| CLIENT_TYPES.iter().find(|t| **t == id).copied()).or_else(|| {
+            let device_type =
+                data.device_type.as_deref().and_then(|t| t.parse().ok()).unwrap_or(client_header.device_type);
+            DeviceType::from_i32(device_type).client_type()
+        });
+    let Some(client_type) = client_type else {
+        return Ok(());
+    };
+
+    if let Ok(mut versions) = CLIENT_VERSIONS.lock() {
+        let key = (client_type, client_version.map_or_else(|| "unknown".to_owned(), |v| v.0.to_string()));
+        if versions.len() < MAX_CLIENT_VERSIONS || versions.contains_key(&key) {
+            *versions.entry(key).or_default() += 1;
+        }
+    }
+
+    if let Some(min_version) = CONFIG.min_client_version(client_type) {
+        match client_version {
+            Some(version) if version.0 >= min_version => {}
+            Some(version) => err!(format!(
+                "This {client_type} client version ({}) is no longer supported by this server, please update to version {min_version} or newer",
+                version.0
+            )),
+            None => err!(format!(
+                "This {client_type} client is no longer supported by this server, please update to version {min_version} or newer"
+            )),
+        }
+    }
+
+    Ok(())
+}
+
 fn check_is_some<T>(value: Option<&T>, msg: &str) -> EmptyResult {
     if value.is_none() {
         err!(msg)
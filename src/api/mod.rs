@@ -4,6 +4,7 @@ mod icons;
 mod identity;
 mod notifications;
 mod push;
+mod scim;
 mod web;
 
 use rocket::serde::json::Json;
@@ -12,12 +13,23 @@ use serde_json::Value;
 pub use crate::api::{
     admin::catchers as admin_catchers,
     admin::routes as admin_routes,
+    admin::usage_snapshot_job,
+    admin::{ACTING_ADMIN_USER, create_invited_user},
+    core::attachment_quota_warning_job,
     core::catchers as core_catchers,
+    core::device_cleanup_job,
+    core::expire_audit_grants,
+    core::inactive_users_job,
+    core::ldap_sync_job,
+    core::legacy_route_enabled,
     core::purge_auth_requests,
     core::purge_sends,
     core::purge_trashed_ciphers,
+    core::purge_unverified_users,
+    core::reconcile_revision_dates,
     core::routes as core_routes,
-    core::two_factor::send_incomplete_2fa_notifications,
+    core::two_factor::{enforce_2fa_grace_periods, send_incomplete_2fa_notifications},
+    core::verify_attachment_rekeys,
     core::{emergency_notification_reminder_job, emergency_request_timeout_job},
     core::{event_cleanup_job, events_routes as core_events_routes},
     icons::routes as icons_routes,
@@ -25,9 +37,11 @@ pub use crate::api::{
     notifications::routes as notifications_routes,
     notifications::{AnonymousNotify, Notify, UpdateType, WS_ANONYMOUS_SUBSCRIPTIONS, WS_USERS},
     push::{
-        push_cipher_update, push_folder_update, push_logout, push_send_update, push_user_update, register_push_device,
+        push_cipher_update, push_folder_update, push_logout, push_reregistration_status, push_send_update,
+        push_user_update, register_push_device, reregister_push_devices, start_push_reregistration,
         unregister_push_device,
     },
+    scim::routes as scim_routes,
     web::catchers as web_catchers,
     web::routes as web_routes,
     web::static_files,
@@ -58,18 +72,31 @@ impl PasswordOrOtpData {
     /// Tokens used via this struct can be used multiple times during the process
     /// First for the validation to continue, after that to enable or validate the following actions
     /// This is different per caller, so it can be adjusted to delete the token or not
+    ///
+    /// Users without a master password, like users which joined via SSO or only unlock with a trusted device,
+    /// can only validate with a code requested via `/accounts/request-otp`.
     pub async fn validate(&self, user: &User, delete_if_valid: bool, conn: &DbConn) -> EmptyResult {
         use crate::api::core::two_factor::protected_actions::validate_protected_action_otp;
 
+        let has_master_password = !user.password_hash.is_empty();
         match (self.master_password_hash.as_deref(), self.otp.as_deref()) {
+            (Some(_), None) if !has_master_password => {
+                err!("This account has no master password, verify with the code sent to your email instead")
+            }
             (Some(pw_hash), None) => {
                 if !user.check_valid_password(pw_hash) {
                     err!("Invalid password");
                 }
             }
+            (None, Some(_)) if has_master_password && user.has_pending_security_change() => {
+                err!("An email or two-step login change is pending, verify this action with your master password")
+            }
             (None, Some(otp)) => {
                 validate_protected_action_otp(otp, &user.uuid, delete_if_valid, conn).await?;
             }
+            (None, None) if !has_master_password && !CONFIG.mail_enabled() => {
+                err!("Email is disabled for this server, accounts without a master password can't verify this action")
+            }
             _ => err!("No validation provided"),
         }
         Ok(())
@@ -91,16 +118,18 @@ pub struct MasterPasswordPolicy {
 
 // Fetch all valid Master Password Policies and merge them into one with all trues and largest numbers as one policy
 async fn master_password_policy(user: &User, conn: &DbConn) -> Value {
+    let policies = OrgPolicy::find_accepted_and_confirmed_by_user_and_active_policy(
+        &user.uuid,
+        OrgPolicyType::MasterPassword,
+        conn,
+    )
+    .await;
+    merge_master_password_policies(policies)
+}
+
+fn merge_master_password_policies(policies: Vec<OrgPolicy>) -> Value {
     let master_password_policies: Vec<MasterPasswordPolicy> =
-        OrgPolicy::find_accepted_and_confirmed_by_user_and_active_policy(
-            &user.uuid,
-            OrgPolicyType::MasterPassword,
-            conn,
-        )
-        .await
-        .into_iter()
-        .filter_map(|p| serde_json::from_str(&p.data).ok())
-        .collect();
+        policies.into_iter().filter_map(|p| serde_json::from_str(&p.data).ok()).collect();
 
     let mut mpp_json = if !master_password_policies.is_empty() {
         json!(master_password_policies.into_iter().reduce(|acc, policy| {
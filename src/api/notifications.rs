@@ -1,32 +1,43 @@
 use std::{
     net::IpAddr,
-    sync::{Arc, LazyLock},
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicUsize, Ordering},
+    },
     time::Duration,
 };
 
 use chrono::{NaiveDateTime, Utc};
 use rmpv::Value;
-use rocket::{Route, futures::StreamExt};
-use rocket_ws::{Message, WebSocket};
-use tokio::sync::mpsc::Sender;
+use rocket::{Route, Shutdown, futures::StreamExt, serde::json::Json};
+use rocket_ws::{
+    Message, WebSocket,
+    frame::{CloseCode, CloseFrame},
+};
+use tokio::{sync::mpsc::Sender, time::Instant};
 
 use crate::{
     CONFIG, Error,
+    api::core::response_cache,
     auth::{ClientIp, WsAccessTokenHeader},
     db::{
         DbConn,
-        models::{AuthRequestId, Cipher, CollectionId, Device, DeviceId, Folder, PushId, Send as DbSend, User, UserId},
+        models::{
+            AuthRequestId, Cipher, CollectionId, Device, DeviceId, Folder, OrganizationId, PushId, Send as DbSend,
+            User, UserId,
+        },
     },
 };
 
 use super::{
-    push::push_auth_request, push::push_auth_response, push_cipher_update, push_folder_update, push_logout,
+    JsonResult, push::push_auth_request, push::push_auth_response, push_cipher_update, push_folder_update, push_logout,
     push_send_update, push_user_update,
 };
 
 pub static WS_USERS: LazyLock<Arc<WebSocketUsers>> = LazyLock::new(|| {
     Arc::new(WebSocketUsers {
         map: Arc::new(dashmap::DashMap::new()),
+        sequences: Arc::new(dashmap::DashMap::new()),
     })
 });
 
@@ -40,16 +51,62 @@ static NOTIFICATIONS_DISABLED: LazyLock<bool> = LazyLock::new(|| !CONFIG.enable_
 
 pub fn routes() -> Vec<Route> {
     if CONFIG.enable_websocket() {
-        routes![websockets_hub, anonymous_websockets_hub]
+        routes![websockets_hub, websockets_hub_negotiate, anonymous_websockets_hub, anonymous_websockets_hub_negotiate]
     } else {
         info!("WebSocket are disabled, realtime sync functionality will not work!");
         routes![]
     }
 }
 
+// Number of open websocket connections, limited by `WEBSOCKET_MAX_CONNECTIONS`
+static WS_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+struct WsConnectionSlot;
+
+impl WsConnectionSlot {
+    fn acquire() -> Result<Self, Error> {
+        let max = CONFIG.websocket_max_connections();
+        if WS_CONNECTIONS.fetch_add(1, Ordering::Relaxed) >= max && max > 0 {
+            WS_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
+            err_code!("Too many websocket connections", 503)
+        }
+        Ok(Self)
+    }
+}
+
+impl Drop for WsConnectionSlot {
+    fn drop(&mut self) {
+        WS_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Dead connections are only noticed when writing to them, unless they are closed once nothing was received for a while
+fn is_idle(last_seen: Instant) -> bool {
+    let idle_timeout = CONFIG.websocket_idle_timeout();
+    idle_timeout > 0 && last_seen.elapsed() >= Duration::from_secs(idle_timeout)
+}
+
 #[derive(FromForm, Debug)]
 struct WsAccessToken {
     access_token: Option<String>,
+    // Payload schema version the client supports, see `WS_SCHEMA_VERSION`
+    schema: Option<u8>,
+}
+
+// Version of the payloads sent over the user hub.
+// Version 1 is the format of the upstream server which every client understands, newer versions add fields and update types.
+// Every connection negotiates its version with the `schema` query parameter, without it version 1 is used.
+// Updates are always created in the latest format, `WsUpdate::encode()` downgrades them again for older connections.
+//
+// Version 2: the `Notification` update type and the `ArchivedDate` of personal cipher updates
+// Version 3: the `Sequence` of every update and the `Heartbeat` messages, see `create_heartbeat()`
+const WS_SCHEMA_VERSION: u8 = 3;
+
+// Changes when the server restarts, the sequences of the users start again from 0 then
+static WS_EPOCH: LazyLock<String> = LazyLock::new(crate::util::get_uuid);
+
+fn negotiate_schema(requested: Option<u8>) -> u8 {
+    requested.unwrap_or(1).clamp(1, WS_SCHEMA_VERSION)
 }
 
 struct WSEntryMapGuard {
@@ -74,7 +131,7 @@ impl Drop for WSEntryMapGuard {
     fn drop(&mut self) {
         info!("Closing WS connection from {}", self.addr);
         if let Some(mut entry) = self.users.map.get_mut(self.user_uuid.as_ref()) {
-            entry.retain(|(uuid, _)| uuid != &self.entry_uuid);
+            entry.retain(|(uuid, _, _, _)| uuid != &self.entry_uuid);
         }
     }
 }
@@ -109,6 +166,7 @@ fn websockets_hub<'r>(
     data: WsAccessToken,
     ip: ClientIp,
     header_token: WsAccessTokenHeader,
+    shutdown: Shutdown,
 ) -> Result<rocket_ws::Stream!['r], Error> {
     info!("Accepting Rocket WS connection from {}", ip.ip);
 
@@ -123,6 +181,9 @@ fn websockets_hub<'r>(
     let Ok(claims) = crate::auth::decode_login(&token) else {
         err_code!("Invalid token", 401)
     };
+    let slot = WsConnectionSlot::acquire()?;
+    let schema = negotiate_schema(data.schema);
+    debug!("Using WS payload schema version {schema} for {}", ip.ip);
 
     let (mut rx, guard) = {
         let users = Arc::clone(&WS_USERS);
@@ -130,22 +191,27 @@ fn websockets_hub<'r>(
         // Add a channel to send messages to this client to the map
         let entry_uuid = uuid::Uuid::new_v4();
         let (tx, rx) = tokio::sync::mpsc::channel::<Message>(100);
-        users.map.entry(claims.sub.to_string()).or_default().push((entry_uuid, tx));
+        users.map.entry(claims.sub.to_string()).or_default().push((entry_uuid, claims.device, schema, tx));
 
         // Once the guard goes out of scope, the connection will have been closed and the entry will be deleted from the map
-        (rx, WSEntryMapGuard::new(users, claims.sub, entry_uuid, ip.ip))
+        (rx, WSEntryMapGuard::new(users, claims.sub.clone(), entry_uuid, ip.ip))
     };
+    let user_id = claims.sub;
 
     Ok({
         rocket_ws::Stream! { ws => {
             let mut ws = ws;
+            let mut shutdown = shutdown;
             let _guard = guard;
-            let mut interval = tokio::time::interval(Duration::from_secs(15));
+            let _slot = slot;
+            let mut interval = tokio::time::interval(Duration::from_secs(CONFIG.websocket_ping_interval()));
+            let mut last_seen = Instant::now();
             loop {
                 tokio::select! {
                     res = ws.next() =>  {
                         match res {
                             Some(Ok(message)) => {
+                                last_seen = Instant::now();
                                 match message {
                                     // Respond to any pings
                                     Message::Ping(ping) => yield Message::Pong(ping),
@@ -157,6 +223,10 @@ fn websockets_hub<'r>(
 
                                         if serde_json::from_str(msg).ok() == Some(INITIAL_MESSAGE) {
                                             yield Message::binary(INITIAL_RESPONSE);
+                                            // Tells a reconnecting client right away whether it missed updates
+                                            if schema >= 3 {
+                                                yield Message::binary(create_heartbeat(WS_USERS.sequence(&user_id)));
+                                            }
                                         }
                                     }
 
@@ -179,17 +249,71 @@ fn websockets_hub<'r>(
                         }
                     }
 
-                    _ = interval.tick() => yield Message::Ping(create_ping())
+                    _ = interval.tick() => {
+                        if is_idle(last_seen) {
+                            break;
+                        }
+                        yield Message::Ping(create_ping());
+                        if schema >= 3 {
+                            yield Message::binary(create_heartbeat(WS_USERS.sequence(&user_id)));
+                        }
+                    }
+
+                    // Ask the client to reconnect, hopefully to another instance or after the restart
+                    _ = &mut shutdown => {
+                        yield shutdown_close_message();
+                        break;
+                    }
                 }
             }
         }}
     })
 }
 
+// SignalR clients which don't skip the negotiation first ask which transports they can use.
+// Only WebSockets with the binary MessagePack protocol are supported, the connection token isn't needed afterwards.
+// The latest payload schema version is advertised too, clients which know it can request it when connecting.
+fn negotiate_response() -> Json<serde_json::Value> {
+    let connection_id = uuid::Uuid::new_v4().to_string();
+    Json(json!({
+        "connectionId": connection_id,
+        "connectionToken": connection_id,
+        "negotiateVersion": 1,
+        "vaultwardenSchemaVersion": WS_SCHEMA_VERSION,
+        "availableTransports": [{
+            "transport": "WebSockets",
+            "transferFormats": ["Binary"],
+        }],
+    }))
+}
+
+#[post("/hub/negotiate?<data..>")]
+fn websockets_hub_negotiate(data: WsAccessToken, header_token: WsAccessTokenHeader) -> JsonResult {
+    let Some(token) = data.access_token.or(header_token.access_token) else {
+        err_code!("Invalid claim", 401)
+    };
+    if crate::auth::decode_login(&token).is_err() {
+        err_code!("Invalid token", 401)
+    }
+    Ok(negotiate_response())
+}
+
+// Anyone can subscribe to the anonymous hub, the token of the auth request is only needed to connect
+#[post("/anonymous-hub/negotiate")]
+fn anonymous_websockets_hub_negotiate() -> Json<serde_json::Value> {
+    negotiate_response()
+}
+
 #[expect(tail_expr_drop_order)]
 #[get("/anonymous-hub?<token..>")]
-fn anonymous_websockets_hub<'r>(ws: WebSocket, token: String, ip: ClientIp) -> Result<rocket_ws::Stream!['r], Error> {
+fn anonymous_websockets_hub<'r>(
+    ws: WebSocket,
+    token: String,
+    ip: ClientIp,
+    shutdown: Shutdown,
+) -> Result<rocket_ws::Stream!['r], Error> {
     info!("Accepting Anonymous Rocket WS connection from {}", ip.ip);
+    let slot = WsConnectionSlot::acquire()?;
 
     let (mut rx, guard) = {
         let subscriptions = Arc::clone(&WS_ANONYMOUS_SUBSCRIPTIONS);
@@ -205,13 +329,17 @@ fn anonymous_websockets_hub<'r>(ws: WebSocket, token: String, ip: ClientIp) -> R
     Ok({
         rocket_ws::Stream! { ws => {
             let mut ws = ws;
+            let mut shutdown = shutdown;
             let _guard = guard;
-            let mut interval = tokio::time::interval(Duration::from_secs(15));
+            let _slot = slot;
+            let mut interval = tokio::time::interval(Duration::from_secs(CONFIG.websocket_ping_interval()));
+            let mut last_seen = Instant::now();
             loop {
                 tokio::select! {
                     res = ws.next() =>  {
                         match res {
                             Some(Ok(message)) => {
+                                last_seen = Instant::now();
                                 match message {
                                     // Respond to any pings
                                     Message::Ping(ping) => yield Message::Pong(ping),
@@ -245,7 +373,18 @@ fn anonymous_websockets_hub<'r>(ws: WebSocket, token: String, ip: ClientIp) -> R
                         }
                     }
 
-                    _ = interval.tick() => yield Message::Ping(create_ping())
+                    _ = interval.tick() => {
+                        if is_idle(last_seen) {
+                            break;
+                        }
+                        yield Message::Ping(create_ping());
+                    }
+
+                    // Ask the client to reconnect, hopefully to another instance or after the restart
+                    _ = &mut shutdown => {
+                        yield shutdown_close_message();
+                        break;
+                    }
                 }
             }
         }}
@@ -256,6 +395,13 @@ fn anonymous_websockets_hub<'r>(ws: WebSocket, token: String, ip: ClientIp) -> R
 // Websockets server
 //
 
+fn shutdown_close_message() -> Message {
+    Message::Close(Some(CloseFrame {
+        code: CloseCode::Restart,
+        reason: "Server is restarting, please reconnect".into(),
+    }))
+}
+
 fn serialize(val: &Value) -> Vec<u8> {
     use rmpv::encode::write_value;
 
@@ -320,17 +466,55 @@ static INITIAL_MESSAGE: InitialMessage<'static> = InitialMessage {
     version: 1,
 };
 
-// We attach the UUID to the sender so we can differentiate them when we need to remove them from the Vec
-type UserSenders = (uuid::Uuid, Sender<Message>);
+// We attach the UUID to the sender so we can differentiate them when we need to remove them from the Vec,
+// the device of the login so updates can be limited to some devices,
+// and the negotiated payload schema version so every client only receives what it understands
+type UserSenders = (uuid::Uuid, DeviceId, u8, Sender<Message>);
 #[derive(Clone)]
 pub struct WebSocketUsers {
     map: Arc<dashmap::DashMap<String, Vec<UserSenders>>>,
+    // Number of updates sent to every user since the start of the server, also while they weren't connected
+    sequences: Arc<dashmap::DashMap<String, u64>>,
 }
 
 impl WebSocketUsers {
-    async fn send_update(&self, user_id: &UserId, data: &[u8]) {
+    fn sequence(&self, user_id: &UserId) -> u64 {
+        self.sequences.get(user_id.as_ref()).map_or(0, |s| *s)
+    }
+
+    fn next_sequence(&self, user_id: &UserId) -> u64 {
+        let mut sequence = self.sequences.entry(user_id.to_string()).or_insert(0);
+        *sequence += 1;
+        *sequence
+    }
+
+    async fn send_update(&self, user_id: &UserId, update: &WsUpdate) {
+        self.send_update_to_devices(user_id, update, None).await;
+    }
+
+    /// Same as `send_update`, but only to the connections of the given devices if there are any
+    async fn send_update_to_devices(&self, user_id: &UserId, update: &WsUpdate, devices: Option<&[DeviceId]>) {
+        // Updates for some devices only aren't counted, the other devices would see them as missed
+        let sequence = devices.is_none().then(|| self.next_sequence(user_id));
         if let Some(user) = self.map.get(user_id.as_ref()).map(|v| v.clone()) {
-            for (_, sender) in &user {
+            // Encoded only once for every schema version in use
+            let mut encoded: Vec<(u8, Option<Vec<u8>>)> = Vec::new();
+            for (_, device_id, schema, sender) in &user {
+                if devices.is_some_and(|devices| !devices.contains(device_id)) {
+                    continue;
+                }
+                let data = if let Some((_, data)) = encoded.iter().find(|(s, _)| s == schema) {
+                    data.clone()
+                } else {
+                    let data = update.encode(*schema, sequence);
+                    encoded.push((*schema, data.clone()));
+                    data
+                };
+
+                // The client doesn't know this type of update at all
+                let Some(data) = data else {
+                    continue;
+                };
                 if let Err(e) = sender.send(Message::binary(data)).await {
                     error!("Error sending WS update {e}");
                 }
@@ -340,6 +524,8 @@ impl WebSocketUsers {
 
     // NOTE: The last modified date needs to be updated before calling these methods
     pub async fn send_user_update(&self, ut: UpdateType, user: &User, push_uuid: Option<&PushId>, conn: &DbConn) {
+        response_cache::invalidate_user(&user.uuid);
+
         // Skip any processing if both WebSockets and Push are not active
         if *NOTIFICATIONS_DISABLED {
             return;
@@ -430,6 +616,14 @@ impl WebSocketUsers {
             (convert_option(cipher.user_uuid.as_deref()), Value::Nil, serialize_date(cipher.updated_at))
         };
 
+        // Archives are personal, so only the owner of a personal cipher gets its archive state
+        let archived_date = match &cipher.user_uuid {
+            Some(owner) if CONFIG.enable_websocket() => {
+                convert_option(cipher.get_archived_at(owner, conn).await.map(serialize_date))
+            }
+            _ => Value::Nil,
+        };
+
         let data = create_update(
             vec![
                 ("Id".into(), cipher.uuid.to_string().into()),
@@ -437,6 +631,7 @@ impl WebSocketUsers {
                 ("OrganizationId".into(), org_id),
                 ("CollectionIds".into(), collection_uuids),
                 ("RevisionDate".into(), revision_date),
+                ("ArchivedDate".into(), archived_date),
             ],
             ut,
             Some(device.uuid.clone()), // Acting device id (unique device/app uuid)
@@ -474,7 +669,7 @@ impl WebSocketUsers {
                 ("RevisionDate".into(), serialize_date(send.revision_date)),
             ],
             ut,
-            None,
+            Some(device.uuid.clone()),
         );
 
         if CONFIG.enable_websocket() {
@@ -487,6 +682,8 @@ impl WebSocketUsers {
         }
     }
 
+    /// When the user marked some devices as trusted, only those are asked to approve the request over the WebSocket.
+    /// The push relay can't address single devices, so push notifications still reach all mobile devices of the user.
     pub async fn send_auth_request(&self, user_id: &UserId, auth_request_uuid: &str, device: &Device, conn: &DbConn) {
         // Skip any processing if both WebSockets and Push are not active
         if *NOTIFICATIONS_DISABLED {
@@ -498,7 +695,10 @@ impl WebSocketUsers {
             Some(device.uuid.clone()),
         );
         if CONFIG.enable_websocket() {
-            self.send_update(user_id, &data).await;
+            let trusted: Vec<DeviceId> =
+                Device::find_trusted_by_user(user_id, conn).await.into_iter().map(|d| d.uuid).collect();
+            let targets = (!trusted.is_empty()).then_some(trusted.as_slice());
+            self.send_update_to_devices(user_id, &data, targets).await;
         }
 
         if CONFIG.push_enabled() {
@@ -530,6 +730,33 @@ impl WebSocketUsers {
             push_auth_response(user_id, auth_request_id, device, conn).await;
         }
     }
+
+    /// Shows a message in the notification center of the clients which support it, which are the connections
+    /// that negotiated version 2 or later of the payload schema. Older connections don't receive it at all.
+    /// These notifications aren't stored, so they are only sent over the WebSockets and not to the push relay.
+    pub async fn send_notification(&self, user_id: &UserId, org_id: Option<&OrganizationId>, title: &str, body: &str) {
+        if !CONFIG.enable_websocket() {
+            return;
+        }
+        let now = Utc::now().naive_utc();
+        let data = create_update(
+            vec![
+                ("Id".into(), crate::util::get_uuid().into()),
+                ("Priority".into(), 0.into()),
+                ("Global".into(), false.into()),
+                ("ClientType".into(), 0.into()),
+                ("UserId".into(), user_id.to_string().into()),
+                ("OrganizationId".into(), org_id.map_or(Value::Nil, |id| id.to_string().into())),
+                ("Title".into(), title.into()),
+                ("Body".into(), body.into()),
+                ("CreationDate".into(), serialize_date(now)),
+                ("RevisionDate".into(), serialize_date(now)),
+            ],
+            UpdateType::Notification,
+            None,
+        );
+        self.send_update(user_id, &data).await;
+    }
 }
 
 #[derive(Clone)]
@@ -574,22 +801,53 @@ impl AnonymousWebSocketSubscriptions {
     ]
 ]
 */
-fn create_update(payload: Vec<(Value, Value)>, ut: UpdateType, acting_device_id: Option<DeviceId>) -> Vec<u8> {
-    use rmpv::Value as V;
+struct WsUpdate {
+    ut: UpdateType,
+    acting_device_id: Option<DeviceId>,
+    payload: Vec<(Value, Value)>,
+}
 
-    let value = V::Array(vec![
-        1.into(),
-        V::Map(vec![]),
-        V::Nil,
-        "ReceiveMessage".into(),
-        V::Array(vec![V::Map(vec![
-            ("ContextId".into(), acting_device_id.map_or(V::Nil, |v| v.to_string().into())),
-            ("Type".into(), (ut as i32).into()),
+// Payload fields which were added after version 1 of the schema, with the version they were added in
+const PAYLOAD_FIELD_SCHEMAS: [(&str, u8); 1] = [("ArchivedDate", 2)];
+
+fn payload_field_schema(key: &Value) -> u8 {
+    PAYLOAD_FIELD_SCHEMAS.iter().find(|(field, _)| key.as_str() == Some(field)).map_or(1, |(_, schema)| *schema)
+}
+
+impl WsUpdate {
+    /// Encodes the update in the format of the given schema version, fields unknown to that version are left out.
+    /// Returns `None` when the update type itself is unknown to it, older clients fail on those.
+    fn encode(&self, schema: u8, sequence: Option<u64>) -> Option<Vec<u8>> {
+        use rmpv::Value as V;
+
+        if self.ut.schema() > schema {
+            return None;
+        }
+        let payload: Vec<(V, V)> =
+            self.payload.iter().filter(|(key, _)| payload_field_schema(key) <= schema).cloned().collect();
+
+        let mut message = vec![
+            ("ContextId".into(), self.acting_device_id.as_ref().map_or(V::Nil, |v| v.to_string().into())),
+            ("Type".into(), (self.ut as i32).into()),
             ("Payload".into(), payload.into()),
-        ])]),
-    ]);
+        ];
+        if schema >= 3 {
+            message.push(("Sequence".into(), sequence.map_or(V::Nil, V::from)));
+        }
 
-    serialize(&value)
+        let value =
+            V::Array(vec![1.into(), V::Map(vec![]), V::Nil, "ReceiveMessage".into(), V::Array(vec![V::Map(message)])]);
+
+        Some(serialize(&value))
+    }
+}
+
+fn create_update(payload: Vec<(Value, Value)>, ut: UpdateType, acting_device_id: Option<DeviceId>) -> WsUpdate {
+    WsUpdate {
+        ut,
+        acting_device_id,
+        payload,
+    }
 }
 
 fn create_anonymous_update(payload: Vec<(Value, Value)>, ut: UpdateType, user_id: &UserId) -> Vec<u8> {
@@ -617,6 +875,27 @@ fn create_ping() -> Vec<u8> {
     serialize(&Value::Array(vec![6.into()]))
 }
 
+/// Sent with every ping and after the handshake to clients of schema version 3, with the sequence of the last update of the user.
+/// A client which received a lower sequence, or a different epoch because the server restarted, missed some updates.
+/// It can then do a delta sync with `/api/sync?since=<RevisionDate of its last update>` instead of a full sync.
+fn create_heartbeat(sequence: u64) -> Vec<u8> {
+    use rmpv::Value as V;
+
+    let value = V::Array(vec![
+        1.into(),
+        V::Map(vec![]),
+        V::Nil,
+        "Heartbeat".into(),
+        V::Array(vec![V::Map(vec![
+            ("Sequence".into(), sequence.into()),
+            ("Epoch".into(), WS_EPOCH.as_str().into()),
+            ("Date".into(), serialize_date(Utc::now().naive_utc())),
+        ])]),
+    ]);
+
+    serialize(&value)
+}
+
 // https://github.com/bitwarden/server/blob/375af7c43b10d9da03525d41452f95de3f921541/src/Core/Enums/PushType.cs
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum UpdateType {
@@ -645,13 +924,22 @@ pub enum UpdateType {
     // SyncOrganizations = 17, // Not supported
     // SyncOrganizationStatusChanged = 18, // Not supported
     // SyncOrganizationCollectionSettingChanged = 19, // Not supported
-
-    // Notification = 20, // Not supported
+    Notification = 20, // Only sent by Vaultwarden itself, see `send_notification()`
     // NotificationStatus = 21, // Not supported
 
     // RefreshSecurityTasks = 22, // Not supported
     None = 100,
 }
 
+impl UpdateType {
+    // The payload schema version in which this type was added, see `WS_SCHEMA_VERSION`
+    const fn schema(self) -> u8 {
+        match self {
+            Self::Notification => 2,
+            _ => 1,
+        }
+    }
+}
+
 pub type Notify<'a> = &'a rocket::State<Arc<WebSocketUsers>>;
 pub type AnonymousNotify<'a> = &'a rocket::State<Arc<AnonymousWebSocketSubscriptions>>;
@@ -1,10 +1,11 @@
 use std::{
-    sync::LazyLock,
+    sync::{LazyLock, Mutex},
     time::{Duration, Instant},
 };
 
+use chrono::{NaiveDateTime, Utc};
 use reqwest::{
-    Method,
+    Method, StatusCode,
     header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
 };
 use serde_json::Value;
@@ -14,8 +15,8 @@ use crate::{
     CONFIG,
     api::{ApiResult, EmptyResult, UpdateType},
     db::{
-        DbConn,
-        models::{AuthRequestId, Cipher, Device, Folder, PushId, Send, User, UserId},
+        DbConn, DbPool,
+        models::{AuthRequestId, Cipher, Device, DeviceId, Folder, PushId, Send, User, UserId},
     },
     http_client::make_http_request,
     util::{format_date, get_uuid},
@@ -116,15 +117,25 @@ pub async fn register_push_device(device: &mut Device, conn: &DbConn) -> EmptyRe
     let auth_api_token = get_auth_api_token().await?;
     let auth_header = format!("Bearer {auth_api_token}");
 
-    if let Err(e) = make_http_request(Method::POST, &(CONFIG.push_relay_uri() + "/push/register"))?
+    let res = make_http_request(Method::POST, &(CONFIG.push_relay_uri() + "/push/register"))?
         .header(CONTENT_TYPE, "application/json")
         .header(ACCEPT, "application/json")
         .header(AUTHORIZATION, auth_header)
         .json(&data)
         .send()
-        .await?
-        .error_for_status()
-    {
+        .await?;
+    if let Err(e) = res.error_for_status_ref() {
+        // The relay refused the push token itself, stop using it until the device sends a new one.
+        // The device cleanup job deregisters the token from the relay.
+        if matches!(res.status(), StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND | StatusCode::GONE) {
+            device.push_rejected_at = Some(Utc::now().naive_utc());
+            if let Err(e) = device.save(false, conn).await {
+                error!(
+                    "An error occurred while trying to mark the push token of device {} as rejected: {e}",
+                    device.uuid
+                );
+            }
+        }
         err!(format!("An error occurred while proceeding registration of a device: {e}"));
     }
 
@@ -135,6 +146,101 @@ pub async fn register_push_device(device: &mut Device, conn: &DbConn) -> EmptyRe
     Ok(())
 }
 
+/// Progress of the last re-registration of all push devices, shown on the admin diagnostics page
+#[derive(Default)]
+struct PushReregistration {
+    running: bool,
+    started_at: Option<NaiveDateTime>,
+    finished_at: Option<NaiveDateTime>,
+    total: usize,
+    registered: usize,
+    failed: usize,
+    failures: Vec<(DeviceId, String)>,
+}
+
+static PUSH_REREGISTRATION: LazyLock<Mutex<PushReregistration>> = LazyLock::new(Default::default);
+// Only the first failures are kept, the others are only logged
+const MAX_REREGISTRATION_FAILURES: usize = 100;
+
+pub fn push_reregistration_status() -> Value {
+    let Ok(status) = PUSH_REREGISTRATION.lock() else {
+        return Value::Null;
+    };
+    json!({
+        "running": status.running,
+        "startedAt": status.started_at.as_ref().map(format_date),
+        "finishedAt": status.finished_at.as_ref().map(format_date),
+        "total": status.total,
+        "registered": status.registered,
+        "failed": status.failed,
+        "failures": status.failures.iter().map(|(device_id, error)| json!({
+            "deviceId": device_id,
+            "error": error,
+        })).collect::<Vec<Value>>(),
+    })
+}
+
+/// Marks the re-registration as started, returns false when one is already running
+pub fn start_push_reregistration() -> bool {
+    let Ok(mut status) = PUSH_REREGISTRATION.lock() else {
+        return false;
+    };
+    if status.running {
+        return false;
+    }
+    *status = PushReregistration {
+        running: true,
+        started_at: Some(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    true
+}
+
+fn update_push_reregistration(update: impl FnOnce(&mut PushReregistration)) {
+    if let Ok(mut status) = PUSH_REREGISTRATION.lock() {
+        update(&mut status);
+    }
+}
+
+/// Registers all the push devices again, for when the relay or its credentials changed.
+/// Otherwise the devices are only registered with the new relay when they log in again.
+/// `start_push_reregistration` needs to be called first.
+pub async fn reregister_push_devices(pool: DbPool) {
+    let Ok(conn) = pool.get().await else {
+        error!("Failed to get DB connection while re-registering the push devices");
+        update_push_reregistration(|s| s.running = false);
+        return;
+    };
+
+    let devices = Device::find_push_registered(&conn).await;
+    update_push_reregistration(|s| s.total = devices.len());
+    info!("Re-registering {} push device(s) with the push relay", devices.len());
+
+    // Spread the requests so the relay doesn't rate limit them
+    let delay = Duration::from_millis(1000 / CONFIG.push_reregister_rate());
+    for mut device in devices {
+        match register_push_device(&mut device, &conn).await {
+            Ok(()) => update_push_reregistration(|s| s.registered += 1),
+            Err(e) => {
+                warn!("Unable to re-register the push device {}: {e:?}", device.uuid);
+                update_push_reregistration(|s| {
+                    s.failed += 1;
+                    if s.failures.len() < MAX_REREGISTRATION_FAILURES {
+                        s.failures.push((device.uuid.clone(), e.to_string()));
+                    }
+                });
+            }
+        }
+        tokio::time::sleep(delay).await;
+    }
+
+    update_push_reregistration(|s| {
+        s.running = false;
+        s.finished_at = Some(Utc::now().naive_utc());
+        info!("Re-registered {} push device(s), {} failed", s.registered, s.failed);
+    });
+}
+
 pub async fn unregister_push_device(push_id: Option<&PushId>) -> EmptyResult {
     if !CONFIG.push_enabled() || push_id.is_none() {
         return Ok(());
@@ -0,0 +1,638 @@
+//
+// SCIM 2.0 provisioning of organization members and groups
+//
+// Identity providers like Azure AD (Entra ID) and Okta use it to add, revoke and remove the members of an organization,
+// and to keep its groups in sync, without running the directory connector.
+// They authenticate with the SCIM API key of the organization, which owners create via `/api/organizations/<org_id>/api-key`
+// with `{"type": 2}`, sent either as bearer token or as `x-api-key` header.
+// Only the attributes and filters used by these providers are supported:
+// https://github.com/bitwarden/server/tree/9ebe16587175b1c0e9208f84397bb75d0d595510/bitwarden_license/src/Scim/Controllers/v2
+//
+use rocket::{
+    Request, Route,
+    form::FromForm,
+    http::Status,
+    request::{FromRequest, Outcome},
+    serde::json::Json,
+};
+use serde_json::Value;
+
+use crate::{
+    CONFIG,
+    api::{
+        ApiResult, EmptyResult, JsonResult,
+        core::{provision_member, revoke_unless_last_owner},
+    },
+    db::{
+        DbConn,
+        models::{
+            Group, GroupId, GroupUser, Membership, MembershipId, MembershipStatus, MembershipType, OrgApiKeyType,
+            OrganizationApiKey, OrganizationId, User,
+        },
+    },
+    util::format_date,
+};
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        get_users,
+        get_user,
+        post_user,
+        put_user,
+        patch_user,
+        delete_user,
+        get_groups,
+        get_group,
+        post_group,
+        put_group,
+        patch_group,
+        delete_group,
+    ]
+}
+
+const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+const LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+
+// Page size when the provider doesn't ask for one
+const DEFAULT_COUNT: usize = 50;
+
+/// The organization of a valid SCIM API key, checked against the organization in the path
+pub struct ScimToken(OrganizationId);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ScimToken {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        // Azure AD sends the key as bearer token, Okta as custom header
+        let Some(api_key) = headers
+            .get_one("Authorization")
+            .and_then(|auth| auth.strip_prefix("Bearer "))
+            .or_else(|| headers.get_one("x-api-key"))
+        else {
+            err_handler!("No SCIM API key provided")
+        };
+
+        // The organization is the segment after `/scim/v2`
+        let Some(org_id) = request.routed_segment(1) else {
+            err_handler!("No organization provided")
+        };
+        let org_id: OrganizationId = org_id.to_owned().into();
+
+        let Outcome::Success(conn) = DbConn::from_request(request).await else {
+            err_handler!("Error getting DB")
+        };
+        let Some(org_api_key) =
+            OrganizationApiKey::find_by_org_uuid_and_type(&org_id, OrgApiKeyType::Scim, &conn).await
+        else {
+            err_handler!("Invalid SCIM API key", format!("The organization {org_id} has no SCIM API key"))
+        };
+        if !org_api_key.check_valid_api_key(api_key) {
+            err_handler!("Invalid SCIM API key", format!("Organization: {org_id}"))
+        }
+
+        Outcome::Success(Self(org_id))
+    }
+}
+
+#[derive(FromForm)]
+struct ScimListQuery {
+    filter: Option<String>,
+    #[field(name = "startIndex")]
+    start_index: Option<usize>,
+    count: Option<usize>,
+}
+
+/// Parses the only kind of filter the providers use, `<attribute> eq "<value>"`, the attribute is lowercased
+fn parse_eq_filter(filter: &str) -> Option<(String, String)> {
+    let (attribute, rest) = filter.trim().split_once(' ')?;
+    let (operator, value) = rest.trim().split_once(' ')?;
+    if !operator.eq_ignore_ascii_case("eq") {
+        return None;
+    }
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((attribute.to_lowercase(), value.to_owned()))
+}
+
+/// Returns one page of the resources, `startIndex` is 1-based
+fn list_response(resources: Vec<Value>, query: &ScimListQuery) -> Json<Value> {
+    let total = resources.len();
+    let start_index = query.start_index.unwrap_or(1).max(1);
+    let page: Vec<Value> =
+        resources.into_iter().skip(start_index - 1).take(query.count.unwrap_or(DEFAULT_COUNT)).collect();
+
+    Json(json!({
+        "schemas": [LIST_SCHEMA],
+        "totalResults": total,
+        "startIndex": start_index,
+        "itemsPerPage": page.len(),
+        "Resources": page,
+    }))
+}
+
+fn check_org(org_id: &OrganizationId, token: &ScimToken) -> EmptyResult {
+    if org_id != &token.0 {
+        err_code!("Organization not found", "Organization id's do not match", Status::NotFound.code);
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ScimPatchData {
+    #[serde(rename = "Operations")]
+    operations: Vec<ScimPatchOperation>,
+}
+
+#[derive(Deserialize)]
+struct ScimPatchOperation {
+    op: String,
+    path: Option<String>,
+    value: Option<Value>,
+}
+
+impl ScimPatchOperation {
+    fn op(&self) -> String {
+        self.op.to_lowercase()
+    }
+
+    fn path(&self) -> Option<String> {
+        self.path.as_ref().map(|path| path.to_lowercase())
+    }
+}
+
+// Azure AD sends booleans as strings, like `"False"`
+fn parse_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::String(s) => s.to_lowercase().parse().ok(),
+        _ => None,
+    }
+}
+
+//
+// Users
+//
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimUserData {
+    user_name: Option<String>,
+    external_id: Option<String>,
+    emails: Option<Vec<ScimEmail>>,
+    active: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ScimEmail {
+    value: String,
+    primary: Option<bool>,
+}
+
+impl ScimUserData {
+    /// The primary email, otherwise the first one or the user name
+    fn email(&self) -> Option<String> {
+        let emails = self.emails.as_deref().unwrap_or_default();
+        emails
+            .iter()
+            .find(|email| email.primary.unwrap_or(false))
+            .or_else(|| emails.first())
+            .map(|email| email.value.clone())
+            .or_else(|| self.user_name.clone())
+            .map(|email| email.trim().to_lowercase())
+            .filter(|email| crate::util::is_valid_email(email))
+    }
+
+    /// The same as upstream, the user name identifies the user when the provider doesn't send an external id
+    fn external_id(&self) -> Option<String> {
+        self.external_id.clone().or_else(|| self.user_name.clone())
+    }
+}
+
+fn user_json(member: &Membership, user: &User) -> Value {
+    json!({
+        "schemas": [USER_SCHEMA],
+        "id": member.uuid,
+        "externalId": member.external_id,
+        "userName": user.email,
+        "displayName": user.name,
+        "name": {
+            "formatted": user.name,
+        },
+        "emails": [{
+            "primary": true,
+            "type": "work",
+            "value": user.email,
+        }],
+        "active": member.status >= MembershipStatus::Invited as i32,
+        "meta": {
+            "resourceType": "User",
+            "created": member.invited_at.as_ref().map(format_date),
+        },
+    })
+}
+
+async fn member_json(member: &Membership, conn: &DbConn) -> ApiResult<Value> {
+    let Some(user) = User::find_by_uuid(&member.user_uuid, conn).await else {
+        err!("User doesn't exist")
+    };
+    Ok(user_json(member, &user))
+}
+
+async fn find_member(member_id: &MembershipId, org_id: &OrganizationId, conn: &DbConn) -> ApiResult<Membership> {
+    match Membership::find_by_uuid_and_org(member_id, org_id, conn).await {
+        Some(member) => Ok(member),
+        None => err_code!("User not found", Status::NotFound.code),
+    }
+}
+
+/// Revokes or restores the member, returns whether it changed
+async fn set_active(member: &mut Membership, active: bool, conn: &DbConn) -> bool {
+    if active {
+        member.restore()
+    } else {
+        revoke_unless_last_owner(member, conn).await
+    }
+}
+
+#[get("/v2/<org_id>/Users?<query..>")]
+async fn get_users(org_id: OrganizationId, query: ScimListQuery, token: ScimToken, conn: DbConn) -> JsonResult {
+    check_org(&org_id, &token)?;
+
+    let members = match query.filter.as_deref() {
+        None => Membership::find_by_org(&org_id, &conn).await,
+        Some(filter) => match parse_eq_filter(filter) {
+            Some((attribute, value)) if attribute == "username" => {
+                Membership::find_by_email_and_org(&value.to_lowercase(), &org_id, &conn).await.into_iter().collect()
+            }
+            Some((attribute, value)) if attribute == "externalid" => {
+                Membership::find_by_external_id_and_org(&value, &org_id, &conn).await.into_iter().collect()
+            }
+            _ => err!(format!("Unsupported filter: {filter}")),
+        },
+    };
+
+    let mut resources = Vec::with_capacity(members.len());
+    for member in &members {
+        resources.push(member_json(member, &conn).await?);
+    }
+    Ok(list_response(resources, &query))
+}
+
+#[get("/v2/<org_id>/Users/<member_id>")]
+async fn get_user(org_id: OrganizationId, member_id: MembershipId, token: ScimToken, conn: DbConn) -> JsonResult {
+    check_org(&org_id, &token)?;
+    let member = find_member(&member_id, &org_id, &conn).await?;
+    Ok(Json(member_json(&member, &conn).await?))
+}
+
+#[post("/v2/<org_id>/Users", data = "<data>")]
+async fn post_user(
+    org_id: OrganizationId,
+    data: Json<ScimUserData>,
+    token: ScimToken,
+    conn: DbConn,
+) -> ApiResult<(Status, Json<Value>)> {
+    check_org(&org_id, &token)?;
+    let data = data.into_inner();
+    let Some(email) = data.email() else {
+        err!("A valid email address is required")
+    };
+    let Some(external_id) = data.external_id() else {
+        err!("An external id or user name is required")
+    };
+
+    if Membership::find_by_email_and_org(&email, &org_id, &conn).await.is_some()
+        || Membership::find_by_external_id_and_org(&external_id, &org_id, &conn).await.is_some()
+    {
+        err_code!("User already exists", Status::Conflict.code)
+    }
+
+    let mut member = provision_member(&org_id, &email, &external_id, &conn).await?;
+    if data.active == Some(false) && set_active(&mut member, false, &conn).await {
+        member.save(&conn).await?;
+    }
+
+    Ok((Status::Created, Json(member_json(&member, &conn).await?)))
+}
+
+#[put("/v2/<org_id>/Users/<member_id>", data = "<data>")]
+async fn put_user(
+    org_id: OrganizationId,
+    member_id: MembershipId,
+    data: Json<ScimUserData>,
+    token: ScimToken,
+    conn: DbConn,
+) -> JsonResult {
+    check_org(&org_id, &token)?;
+    let data = data.into_inner();
+    let mut member = find_member(&member_id, &org_id, &conn).await?;
+
+    let mut changed = false;
+    if let Some(active) = data.active {
+        changed |= set_active(&mut member, active, &conn).await;
+    }
+    if let Some(external_id) = data.external_id {
+        changed |= member.set_external_id(Some(external_id));
+    }
+    if changed {
+        member.save(&conn).await?;
+    }
+
+    Ok(Json(member_json(&member, &conn).await?))
+}
+
+#[patch("/v2/<org_id>/Users/<member_id>", data = "<data>")]
+async fn patch_user(
+    org_id: OrganizationId,
+    member_id: MembershipId,
+    data: Json<ScimPatchData>,
+    token: ScimToken,
+    conn: DbConn,
+) -> JsonResult {
+    check_org(&org_id, &token)?;
+    let mut member = find_member(&member_id, &org_id, &conn).await?;
+
+    let mut changed = false;
+    for operation in &data.operations {
+        if operation.op() != "replace" {
+            continue;
+        }
+        let Some(ref value) = operation.value else {
+            continue;
+        };
+        // Either the path names the attribute, or the value is an object of the replaced attributes
+        let (active, external_id) = match operation.path().as_deref() {
+            Some("active") => (parse_bool(value), None),
+            Some("externalid") => (None, value.as_str()),
+            None => (value.get("active").and_then(parse_bool), value.get("externalId").and_then(Value::as_str)),
+            Some(_) => (None, None),
+        };
+        if let Some(active) = active {
+            changed |= set_active(&mut member, active, &conn).await;
+        }
+        if let Some(external_id) = external_id {
+            changed |= member.set_external_id(Some(external_id.to_owned()));
+        }
+    }
+    if changed {
+        member.save(&conn).await?;
+    }
+
+    Ok(Json(member_json(&member, &conn).await?))
+}
+
+#[delete("/v2/<org_id>/Users/<member_id>")]
+async fn delete_user(
+    org_id: OrganizationId,
+    member_id: MembershipId,
+    token: ScimToken,
+    conn: DbConn,
+) -> ApiResult<Status> {
+    check_org(&org_id, &token)?;
+    let member = find_member(&member_id, &org_id, &conn).await?;
+
+    if member.atype == MembershipType::Owner
+        && member.status == MembershipStatus::Confirmed as i32
+        && Membership::count_confirmed_by_org_and_type(&org_id, MembershipType::Owner, &conn).await <= 1
+    {
+        err!("Can't delete the last owner")
+    }
+    member.delete(&conn).await?;
+
+    Ok(Status::NoContent)
+}
+
+//
+// Groups
+//
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimGroupData {
+    display_name: String,
+    external_id: Option<String>,
+    members: Option<Vec<ScimGroupMember>>,
+}
+
+#[derive(Deserialize)]
+struct ScimGroupMember {
+    value: MembershipId,
+}
+
+fn check_groups_enabled() -> EmptyResult {
+    if !CONFIG.org_groups_enabled() {
+        err_code!("Group support is disabled", Status::NotFound.code);
+    }
+    Ok(())
+}
+
+async fn group_json(group: &Group, conn: &DbConn) -> Value {
+    let members: Vec<Value> = GroupUser::find_by_group(&group.uuid, &group.organizations_uuid, conn)
+        .await
+        .iter()
+        .map(|group_user| json!({"value": group_user.users_organizations_uuid}))
+        .collect();
+
+    json!({
+        "schemas": [GROUP_SCHEMA],
+        "id": group.uuid,
+        "externalId": group.external_id,
+        "displayName": group.name,
+        "members": members,
+        "meta": {
+            "resourceType": "Group",
+            "created": format_date(&group.creation_date),
+            "lastModified": format_date(&group.revision_date),
+        },
+    })
+}
+
+async fn find_group(group_id: &GroupId, org_id: &OrganizationId, conn: &DbConn) -> ApiResult<Group> {
+    match Group::find_by_uuid_and_org(group_id, org_id, conn).await {
+        Some(group) => Ok(group),
+        None => err_code!("Group not found", Status::NotFound.code),
+    }
+}
+
+/// Adds the members to the group, ignoring the ones which aren't part of the organization
+async fn add_group_members(group: &Group, member_ids: Vec<MembershipId>, conn: &DbConn) -> EmptyResult {
+    for member_id in member_ids {
+        if Membership::find_by_uuid_and_org(&member_id, &group.organizations_uuid, conn).await.is_some() {
+            GroupUser::new(group.uuid.clone(), member_id).save(conn).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn replace_group_members(group: &Group, member_ids: Vec<MembershipId>, conn: &DbConn) -> EmptyResult {
+    GroupUser::delete_all_by_group(&group.uuid, &group.organizations_uuid, conn).await?;
+    add_group_members(group, member_ids, conn).await
+}
+
+/// The member ids of the `members` of a patch operation
+fn patch_member_ids(value: Option<&Value>) -> Vec<MembershipId> {
+    value
+        .and_then(Value::as_array)
+        .map(|members| {
+            members
+                .iter()
+                .filter_map(|member| member.get("value").and_then(Value::as_str))
+                .map(|member_id| MembershipId::from(member_id.to_owned()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[get("/v2/<org_id>/Groups?<query..>")]
+async fn get_groups(org_id: OrganizationId, query: ScimListQuery, token: ScimToken, conn: DbConn) -> JsonResult {
+    check_org(&org_id, &token)?;
+    check_groups_enabled()?;
+
+    let groups = match query.filter.as_deref() {
+        None => Group::find_by_organization(&org_id, &conn).await,
+        Some(filter) => match parse_eq_filter(filter) {
+            Some((attribute, value)) if attribute == "displayname" => Group::find_by_organization(&org_id, &conn)
+                .await
+                .into_iter()
+                .filter(|group| group.name == value)
+                .collect(),
+            Some((attribute, value)) if attribute == "externalid" => {
+                Group::find_by_external_id_and_org(&value, &org_id, &conn).await.into_iter().collect()
+            }
+            _ => err!(format!("Unsupported filter: {filter}")),
+        },
+    };
+
+    let mut resources = Vec::with_capacity(groups.len());
+    for group in &groups {
+        resources.push(group_json(group, &conn).await);
+    }
+    Ok(list_response(resources, &query))
+}
+
+#[get("/v2/<org_id>/Groups/<group_id>")]
+async fn get_group(org_id: OrganizationId, group_id: GroupId, token: ScimToken, conn: DbConn) -> JsonResult {
+    check_org(&org_id, &token)?;
+    check_groups_enabled()?;
+    let group = find_group(&group_id, &org_id, &conn).await?;
+    Ok(Json(group_json(&group, &conn).await))
+}
+
+#[post("/v2/<org_id>/Groups", data = "<data>")]
+async fn post_group(
+    org_id: OrganizationId,
+    data: Json<ScimGroupData>,
+    token: ScimToken,
+    conn: DbConn,
+) -> ApiResult<(Status, Json<Value>)> {
+    check_org(&org_id, &token)?;
+    check_groups_enabled()?;
+    let data = data.into_inner();
+
+    if let Some(ref external_id) = data.external_id
+        && Group::find_by_external_id_and_org(external_id, &org_id, &conn).await.is_some()
+    {
+        err_code!("Group already exists", Status::Conflict.code)
+    }
+
+    let mut group = Group::new(org_id, data.display_name, false, data.external_id);
+    group.save(&conn).await?;
+    let member_ids = data.members.unwrap_or_default().into_iter().map(|member| member.value).collect();
+    add_group_members(&group, member_ids, &conn).await?;
+
+    Ok((Status::Created, Json(group_json(&group, &conn).await)))
+}
+
+#[put("/v2/<org_id>/Groups/<group_id>", data = "<data>")]
+async fn put_group(
+    org_id: OrganizationId,
+    group_id: GroupId,
+    data: Json<ScimGroupData>,
+    token: ScimToken,
+    conn: DbConn,
+) -> JsonResult {
+    check_org(&org_id, &token)?;
+    check_groups_enabled()?;
+    let data = data.into_inner();
+    let mut group = find_group(&group_id, &org_id, &conn).await?;
+
+    group.name = data.display_name;
+    if data.external_id.is_some() {
+        group.set_external_id(data.external_id);
+    }
+    group.save(&conn).await?;
+    if let Some(members) = data.members {
+        replace_group_members(&group, members.into_iter().map(|member| member.value).collect(), &conn).await?;
+    }
+
+    Ok(Json(group_json(&group, &conn).await))
+}
+
+#[patch("/v2/<org_id>/Groups/<group_id>", data = "<data>")]
+async fn patch_group(
+    org_id: OrganizationId,
+    group_id: GroupId,
+    data: Json<ScimPatchData>,
+    token: ScimToken,
+    conn: DbConn,
+) -> ApiResult<Status> {
+    check_org(&org_id, &token)?;
+    check_groups_enabled()?;
+    let mut group = find_group(&group_id, &org_id, &conn).await?;
+
+    for operation in &data.operations {
+        let path = operation.path();
+        match (operation.op().as_str(), path.as_deref()) {
+            ("replace", Some("displayname")) => {
+                if let Some(name) = operation.value.as_ref().and_then(Value::as_str) {
+                    group.name = name.to_owned();
+                    group.save(&conn).await?;
+                }
+            }
+            ("replace", Some("members")) => {
+                replace_group_members(&group, patch_member_ids(operation.value.as_ref()), &conn).await?;
+            }
+            // Okta replaces the name with an object of the replaced attributes
+            ("replace", None) => {
+                if let Some(name) = operation.value.as_ref().and_then(|v| v.get("displayName")).and_then(Value::as_str)
+                {
+                    group.name = name.to_owned();
+                    group.save(&conn).await?;
+                }
+            }
+            ("add", Some("members")) => {
+                add_group_members(&group, patch_member_ids(operation.value.as_ref()), &conn).await?;
+            }
+            ("remove", Some("members")) => {
+                for member_id in patch_member_ids(operation.value.as_ref()) {
+                    GroupUser::delete_by_group_and_member(&group.uuid, &member_id, &conn).await?;
+                }
+            }
+            // Azure AD removes single members with a filter in the path: `members[value eq "<id>"]`
+            ("remove", Some(path)) if path.starts_with("members[") => {
+                let filter = operation.path.as_deref().unwrap_or_default();
+                let filter = filter.get(8..filter.len() - 1).unwrap_or_default();
+                if let Some((attribute, member_id)) = parse_eq_filter(filter)
+                    && attribute == "value"
+                {
+                    GroupUser::delete_by_group_and_member(&group.uuid, &member_id.into(), &conn).await?;
+                }
+            }
+            _ => warn!("Unsupported SCIM group operation: {} {:?}", operation.op, operation.path),
+        }
+    }
+
+    Ok(Status::NoContent)
+}
+
+#[delete("/v2/<org_id>/Groups/<group_id>")]
+async fn delete_group(org_id: OrganizationId, group_id: GroupId, token: ScimToken, conn: DbConn) -> ApiResult<Status> {
+    check_org(&org_id, &token)?;
+    check_groups_enabled()?;
+    let group = find_group(&group_id, &org_id, &conn).await?;
+    group.delete(&org_id, &conn).await?;
+
+    Ok(Status::NoContent)
+}
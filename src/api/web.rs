@@ -1,9 +1,12 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use rocket::{
-    Catcher, Route,
+    Catcher, Either, Route,
     fs::NamedFile,
-    http::ContentType,
+    http::{ContentType, Status},
     response::{Redirect, content::RawCss as Css, content::RawHtml as Html},
     serde::json::Json,
 };
@@ -12,19 +15,21 @@ use serde_json::Value;
 use crate::{
     CONFIG,
     api::{ApiResult, EmptyResult, core::now},
-    auth::decode_file_download,
+    auth::{ClientIp, decode_file_download},
+    config::PathType,
     db::{
         DbConn,
-        models::{AttachmentId, CipherId},
+        models::{Attachment, AttachmentId, AttachmentShare, CipherId},
     },
     error::Error,
+    ratelimit::check_limit_attachment_share,
     util::Cached,
 };
 
 pub fn routes() -> Vec<Route> {
     // If adding more routes here, consider also adding them to
     // crate::utils::LOGGED_ROUTES to make sure they appear in the log
-    let mut routes = routes![attachments, alive, alive_head, static_files];
+    let mut routes = routes![attachments, shared_attachment, alive, alive_head, readyz, static_files];
     if CONFIG.web_vault_enabled() {
         routes.append(&mut routes![
             web_index,
@@ -206,6 +211,39 @@ async fn attachments(cipher_id: CipherId, file_id: AttachmentId, token: String)
     NamedFile::open(Path::new(&CONFIG.attachments_folder()).join(cipher_id.as_ref()).join(file_id.as_ref())).await.ok()
 }
 
+/// Downloads an attachment through a link created with `post_attachment_download_link`, every link works only once.
+/// When there is no query token this doesn't match the `attachments` route above, so it can share its prefix.
+#[get("/attachments/share/<token>")]
+async fn shared_attachment(token: &str, ip: ClientIp, conn: DbConn) -> Result<Either<Redirect, NamedFile>, Error> {
+    check_limit_attachment_share(&ip.ip)?;
+
+    let Some(share) = AttachmentShare::take(token, &ip.ip.to_string(), &conn).await else {
+        info!("Download of an invalid, used or expired attachment link from {}", ip.ip);
+        err_code!("Link not found", Status::NotFound.code)
+    };
+    let Some(attachment) =
+        Attachment::find_by_id(&share.attachment_id, &conn).await.filter(|a| a.cipher_uuid == share.cipher_uuid)
+    else {
+        err_code!("Link not found", Status::NotFound.code)
+    };
+
+    info!(
+        "Attachment {} of cipher {} downloaded through a shared link from {}",
+        attachment.id, share.cipher_uuid, ip.ip
+    );
+
+    let file_path = attachment.get_file_path();
+    if let Some(url) =
+        CONFIG.storage_backend(&PathType::Attachments)?.presign(&file_path, Duration::from_mins(5)).await?
+    {
+        return Ok(Either::Left(Redirect::to(url)));
+    }
+    match NamedFile::open(Path::new(&CONFIG.attachments_folder()).join(file_path)).await {
+        Ok(file) => Ok(Either::Right(file)),
+        Err(_) => err_code!("Link not found", Status::NotFound.code),
+    }
+}
+
 // We use DbConn here to let the alive healthcheck also verify the database connection.
 #[get("/alive")]
 fn alive(_conn: DbConn) -> Json<String> {
@@ -219,6 +257,39 @@ fn alive_head(_conn: DbConn) -> EmptyResult {
     Ok(())
 }
 
+// Readiness probe, besides the database this also verifies every storage backend is usable.
+#[get("/readyz")]
+async fn readyz(_conn: DbConn) -> (Status, Json<Value>) {
+    let mut ready = true;
+    let mut storage = serde_json::Map::new();
+    for (path_type, backend, result) in CONFIG.storage_health().await {
+        if let Err(e) = &result {
+            warn!("Storage backend for {} is not ready: {e}", path_type.as_str());
+            ready = false;
+        }
+        storage.insert(
+            path_type.as_str().to_owned(),
+            json!({
+                "backend": backend,
+                "ok": result.is_ok(),
+            }),
+        );
+    }
+
+    let status = if ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    (
+        status,
+        Json(json!({
+            "ready": ready,
+            "storage": storage,
+        })),
+    )
+}
+
 // This endpoint/function is used during development and development only.
 // It allows to easily develop the admin interface by always loading the files from disk instead from a slice of bytes
 // This will only be active during a debug build and only when `RELOAD_TEMPLATES` is set to `true`
@@ -260,6 +331,7 @@ pub fn static_files(filename: &str) -> Result<(ContentType, &'static [u8]), Erro
         "admin_organizations.js" => {
             Ok((ContentType::JavaScript, include_bytes!("../static/scripts/admin_organizations.js")))
         }
+        "admin_ldap.js" => Ok((ContentType::JavaScript, include_bytes!("../static/scripts/admin_ldap.js"))),
         "admin_diagnostics.js" => {
             Ok((ContentType::JavaScript, include_bytes!("../static/scripts/admin_diagnostics.js")))
         }
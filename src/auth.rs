@@ -1,10 +1,13 @@
 use std::{
     env,
-    net::IpAddr,
-    sync::{LazyLock, OnceLock},
+    net::{IpAddr, Ipv4Addr},
+    sync::{
+        LazyLock, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
 };
 
-use chrono::{DateTime, TimeDelta, Utc};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
 use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, errors::ErrorKind};
 use num_traits::FromPrimitive;
 use openssl::rsa::Rsa;
@@ -23,12 +26,13 @@ use crate::{
         DbConn,
         models::{
             AttachmentId, CipherId, Collection, CollectionId, Device, DeviceId, DeviceType, EmergencyAccessId,
-            Membership, MembershipId, MembershipStatus, MembershipType, OrgApiKeyId, OrganizationId, SendFileId,
-            SendId, User, UserId, UserStampException,
+            Membership, MembershipId, MembershipStatus, MembershipType, OrgApiKeyId, Organization, OrganizationId,
+            SendFileId, SendId, User, UserId, UserStampException,
         },
     },
-    error::Error,
+    error::{Error, ErrorCode},
     sso,
+    tenant::{self, RequestTenant},
 };
 
 const JWT_ALGORITHM: Algorithm = Algorithm::RS256;
@@ -56,6 +60,8 @@ static JWT_FILE_DOWNLOAD_ISSUER: LazyLock<String> =
 static JWT_REGISTER_VERIFY_ISSUER: LazyLock<String> =
     LazyLock::new(|| format!("{}|register_verify", CONFIG.domain_origin()));
 static JWT_2FA_REMEMBER_ISSUER: LazyLock<String> = LazyLock::new(|| format!("{}|2faremember", CONFIG.domain_origin()));
+static JWT_SUPPORT_SESSION_ISSUER: LazyLock<String> =
+    LazyLock::new(|| format!("{}|support_session", CONFIG.domain_origin()));
 
 static PRIVATE_RSA_KEY: OnceLock<EncodingKey> = OnceLock::new();
 static PUBLIC_RSA_KEY: OnceLock<DecodingKey> = OnceLock::new();
@@ -66,24 +72,19 @@ pub async fn initialize_keys() -> Result<(), Error> {
     let rsa_key_filename = crate::storage::file_name(&CONFIG.private_rsa_key())
         .ok_or_else(|| IoError::other("Private RSA key path missing filename"))?;
 
-    let operator = CONFIG.opendal_operator_for_path_type(&PathType::RsaKey).map_err(IoError::other)?;
-
-    let priv_key_buffer = match operator.read(&rsa_key_filename).await {
-        Ok(buffer) => Some(buffer),
-        Err(e) if e.kind() == opendal::ErrorKind::NotFound => None,
-        Err(e) => return Err(e.into()),
-    };
+    let backend = CONFIG.storage_backend(&PathType::RsaKey).map_err(IoError::other)?;
 
-    let (priv_key, priv_key_buffer) = if let Some(priv_key_buffer) = priv_key_buffer {
-        (Rsa::private_key_from_pem(priv_key_buffer.to_vec().as_slice())?, priv_key_buffer.to_vec())
+    let (priv_key, priv_key_buffer) = if let Some(priv_key_buffer) = backend.get(&rsa_key_filename).await? {
+        (Rsa::private_key_from_pem(priv_key_buffer.as_slice())?, priv_key_buffer)
     } else {
         let rsa_key = Rsa::generate(2048)?;
         let priv_key_buffer = rsa_key.private_key_to_pem()?;
-        operator.write(&rsa_key_filename, priv_key_buffer.clone()).await?;
+        backend.put(&rsa_key_filename, priv_key_buffer.clone()).await?;
         info!("Private key '{}' created correctly", CONFIG.private_rsa_key());
         (rsa_key, priv_key_buffer)
     };
     let pub_key_buffer = priv_key.public_key_to_pem()?;
+    crate::crypto::init_secrets_key(&priv_key_buffer);
 
     let enc = EncodingKey::from_rsa_pem(&priv_key_buffer)?;
     let dec: DecodingKey = DecodingKey::from_rsa_pem(&pub_key_buffer)?;
@@ -116,7 +117,7 @@ pub fn decode_jwt<T: DeserializeOwned>(token: &str, issuer: String) -> Result<T,
         Err(err) => match *err.kind() {
             ErrorKind::InvalidToken => err!("Token is invalid"),
             ErrorKind::InvalidIssuer => err!("Issuer is invalid"),
-            ErrorKind::ExpiredSignature => err!("Token has expired"),
+            ErrorKind::ExpiredSignature => err!("Token has expired", ErrorCode::TokenExpired),
             _ => err!(format!("Error decoding JWT: {:?}", err)),
         },
     }
@@ -130,8 +131,23 @@ pub fn decode_login(token: &str) -> Result<LoginJwtClaims, Error> {
     decode_jwt(token, JWT_LOGIN_ISSUER.to_string())
 }
 
+static EXPIRED_INVITES_REJECTED: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of expired invitations which were used since startup
+pub fn expired_invites_rejected() -> u64 {
+    EXPIRED_INVITES_REJECTED.load(Ordering::Relaxed)
+}
+
 pub fn decode_invite(token: &str) -> Result<InviteJwtClaims, Error> {
-    decode_jwt(token, JWT_INVITE_ISSUER.to_string())
+    decode_jwt(token, JWT_INVITE_ISSUER.to_string()).map_err(|e| {
+        if e.error_code() == Some(ErrorCode::TokenExpired) {
+            EXPIRED_INVITES_REJECTED.fetch_add(1, Ordering::Relaxed);
+            Error::new_msg("This invitation has expired, please ask for a new invitation")
+                .with_error_code(ErrorCode::InvitationExpired)
+        } else {
+            e
+        }
+    })
 }
 
 pub fn decode_emergency_access_invite(token: &str) -> Result<EmergencyAccessInviteJwtClaims, Error> {
@@ -170,6 +186,10 @@ pub fn decode_2fa_remember(token: &str) -> Result<TwoFactorRememberClaims, Error
     decode_jwt(token, JWT_2FA_REMEMBER_ISSUER.to_string())
 }
 
+pub fn decode_support_session(token: &str) -> Result<BasicJwtClaims, Error> {
+    decode_jwt(token, JWT_SUPPORT_SESSION_ISSUER.to_string())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginJwtClaims {
     // Not before
@@ -328,6 +348,11 @@ pub fn generate_invite_claims(
     }
 }
 
+/// When an invitation sent at the given date expires, the same validity as the one of the invitation tokens
+pub fn invite_expiration(invited_at: &NaiveDateTime) -> NaiveDateTime {
+    *invited_at + TimeDelta::try_hours(i64::from(CONFIG.invitation_expiration_hours())).unwrap()
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EmergencyAccessInviteJwtClaims {
     // Not before
@@ -519,13 +544,27 @@ pub fn generate_admin_claims() -> BasicJwtClaims {
     }
 }
 
-pub fn generate_send_claims(send_id: &SendId, file_id: &SendFileId) -> BasicJwtClaims {
+// Read-only access to the account metadata of a user, opened from the admin panel
+pub fn generate_support_session_claims(user_id: &UserId) -> BasicJwtClaims {
+    let time_now = Utc::now();
+    BasicJwtClaims {
+        nbf: time_now.timestamp(),
+        exp: (time_now + TimeDelta::try_minutes(15).unwrap()).timestamp(),
+        iss: JWT_SUPPORT_SESSION_ISSUER.to_string(),
+        sub: user_id.to_string(),
+    }
+}
+
+pub static SEND_DOWNLOAD_LIFETIME: LazyLock<TimeDelta> = LazyLock::new(|| TimeDelta::try_minutes(2).unwrap());
+
+// The token id refers to a `SendDownloadToken`, which makes the download link single-use
+pub fn generate_send_claims(send_id: &SendId, file_id: &SendFileId, token_id: &str) -> BasicJwtClaims {
     let time_now = Utc::now();
     BasicJwtClaims {
         nbf: time_now.timestamp(),
-        exp: (time_now + TimeDelta::try_minutes(2).unwrap()).timestamp(),
+        exp: (time_now + *SEND_DOWNLOAD_LIFETIME).timestamp(),
         iss: JWT_SEND_ISSUER.to_string(),
-        sub: format!("{send_id}/{file_id}"),
+        sub: format!("{send_id}/{file_id}/{token_id}"),
     }
 }
 
@@ -646,10 +685,21 @@ impl<'r> FromRequest<'r> for Headers {
             err_handler!("Invalid device id")
         };
 
+        if device.blocked {
+            err_handler!("This device has been blocked")
+        }
+
         let Some(user) = User::find_by_uuid(&user_id, &conn).await else {
             err_handler!("Device has no user associated")
         };
 
+        let Outcome::Success(tenant) = RequestTenant::from_request(request).await else {
+            err_handler!("Error getting tenant")
+        };
+        if !tenant.matches(user.tenant_id.as_deref()) {
+            err_handler!("User belongs to another tenant")
+        }
+
         if user.security_stamp != claims.sstamp {
             if let Some(stamp_exception) =
                 user.stamp_exception.as_deref().and_then(|s| serde_json::from_str::<UserStampException>(s).ok())
@@ -767,6 +817,17 @@ impl<'r> FromRequest<'r> for OrgHeaders {
                     err_handler!("The current user isn't member of the organization");
                 };
 
+                // Organizations can only be used from the tenant they were created in
+                if tenant::is_enabled() {
+                    let Outcome::Success(request_tenant) = RequestTenant::from_request(request).await else {
+                        err_handler!("Error getting tenant")
+                    };
+                    let org_tenant = Organization::find_by_uuid(&org_id, &conn).await.and_then(|org| org.tenant_id);
+                    if !request_tenant.matches(org_tenant.as_deref()) {
+                        err_handler!("The organization belongs to another tenant")
+                    }
+                }
+
                 Outcome::Success(Self {
                     host: headers.host,
                     device: headers.device,
@@ -1044,25 +1105,53 @@ impl<'r> FromRequest<'r> for ClientIp {
     type Error = ();
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let ip = if CONFIG._ip_header_enabled() {
-            req.headers().get_one(&CONFIG.ip_header()).and_then(|ip| {
-                match ip.find(',') {
-                    Some(idx) => &ip[..idx],
-                    None => ip,
-                }
-                .parse()
-                .map_err(|_| warn!("'{}' header is malformed: {ip}", CONFIG.ip_header()))
-                .ok()
-            })
-        } else {
-            None
-        };
+        // Resolved only once per request, so the events, rate limits and logs all use the same IP
+        let client_ip = req.local_cache(|| {
+            let peer = req.remote().map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |remote| {
+                crate::proxy::proxied_peer(&remote).unwrap_or_else(|| remote.ip())
+            });
+            let header = if CONFIG._ip_header_enabled() {
+                req.headers().get_one(&CONFIG.ip_header())
+            } else {
+                None
+            };
 
-        let ip = ip.or_else(|| req.remote().map(|r| r.ip())).unwrap_or_else(|| "0.0.0.0".parse().unwrap());
+            ClientIp {
+                ip: crate::proxy::resolve_client_ip(peer, header),
+            }
+        });
 
-        Outcome::Success(ClientIp {
-            ip,
-        })
+        Outcome::Success(*client_ip)
+    }
+}
+
+/// Two letter country code of the client, set by the reverse proxy in the `COUNTRY_HEADER`
+pub struct ClientCountry(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientCountry {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let header = CONFIG.country_header();
+        if header.is_empty() {
+            return Outcome::Success(ClientCountry(None));
+        }
+        let peer = req.remote().map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |remote| {
+            crate::proxy::proxied_peer(&remote).unwrap_or_else(|| remote.ip())
+        });
+        // Unlike the IP header, any client could set it without a proxy overwriting it
+        if !crate::proxy::is_trusted_proxy(&peer) {
+            return Outcome::Success(ClientCountry(None));
+        }
+
+        let country = req
+            .headers()
+            .get_one(&header)
+            .map(str::trim)
+            .filter(|c| c.len() == 2 && c.chars().all(|c| c.is_ascii_alphabetic()))
+            .map(str::to_uppercase);
+        Outcome::Success(ClientCountry(country))
     }
 }
 
@@ -1276,6 +1365,10 @@ pub async fn refresh_tokens(
         err!("Invalid refresh token")
     };
 
+    if device.blocked {
+        err!("This device has been blocked")
+    }
+
     // Save to update `updated_at`.
     device.save(true, conn).await?;
 
@@ -0,0 +1,159 @@
+//
+// Offline administration
+//
+// `vaultwarden admin <task>` runs a single administrative task directly against the configured database,
+// without starting the server. These are meant for recovery situations where the admin page can't be reached.
+// Every change is recorded in the admin audit log with an unspecified IP address.
+//
+use std::net::{IpAddr, Ipv4Addr};
+
+use crate::{
+    CONFIG,
+    api::{ACTING_ADMIN_USER, core::two_factor, create_invited_user},
+    config::PathType,
+    crypto,
+    db::{
+        self, DbConn,
+        models::{AdminAuditLog, Attachment, Device, TwoFactor, User},
+    },
+    error::Error,
+};
+
+const CLI_IP: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+/// Runs the task named by the next argument, and returns the message to show on success
+pub async fn run_admin_task(pargs: &mut pico_args::Arguments) -> Result<String, Error> {
+    let Ok(Some(task)) = pargs.subcommand() else {
+        err_silent!("Missing the admin task to run, see --help for the available tasks")
+    };
+
+    // These don't need a database connection
+    match task.as_str() {
+        "backup" => {
+            let file = tokio::task::spawn_blocking(db::backup_sqlite).await.map_err(std::io::Error::other)??;
+            return Ok(format!("Backup to '{file}' was successful"));
+        }
+        "rotate-admin-token" => return rotate_admin_token().await,
+        _ => {}
+    }
+
+    let pool = db::DbPool::from_config()?;
+    let conn = pool.get().await?;
+    match task.as_str() {
+        "create-invite" => {
+            let user = create_invited_user(&email_arg(pargs)?, &conn).await?;
+            audit(&conn, "cli_create_invite", Some(&user)).await?;
+            Ok(format!("Invited '{}'", user.email))
+        }
+        "disable-user" => {
+            let mut user = user_arg(pargs, &conn).await?;
+            user.reset_security_stamp(&conn).await?;
+            user.enabled = false;
+            user.save(&conn).await?;
+            Device::delete_all_by_user(&user.uuid, &conn).await?;
+            audit(&conn, "cli_disable_user", Some(&user)).await?;
+            Ok(format!("Disabled '{}', all of its devices are logged out", user.email))
+        }
+        "remove-2fa" => {
+            let mut user = user_arg(pargs, &conn).await?;
+            TwoFactor::delete_all_by_user(&user.uuid, &conn).await?;
+            two_factor::enforce_2fa_policy(&user, &ACTING_ADMIN_USER.into(), 14, &CLI_IP, &conn).await?;
+            user.totp_recover = None;
+            user.save(&conn).await?;
+            audit(&conn, "cli_remove_2fa", Some(&user)).await?;
+            Ok(format!("Removed all two-factor methods of '{}'", user.email))
+        }
+        "vacuum" => {
+            db::vacuum(&conn).await?;
+            audit(&conn, "cli_vacuum", None).await?;
+            Ok(String::from("Vacuum of the database was successful"))
+        }
+        "verify-attachments" => verify_attachments(&conn).await,
+        _ => err_silent!(format!("Unknown admin task '{task}', see --help for the available tasks")),
+    }
+}
+
+fn email_arg(pargs: &mut pico_args::Arguments) -> Result<String, Error> {
+    match pargs.free_from_str::<String>() {
+        Ok(email) if email.contains('@') => Ok(email.to_lowercase()),
+        _ => err_silent!("Missing the email address of the user"),
+    }
+}
+
+async fn user_arg(pargs: &mut pico_args::Arguments, conn: &DbConn) -> Result<User, Error> {
+    let email = email_arg(pargs)?;
+    match User::find_by_mail(&email, conn).await {
+        Some(user) => Ok(user),
+        None => err_silent!(format!("User '{email}' doesn't exist")),
+    }
+}
+
+async fn audit(conn: &DbConn, action: &str, user: Option<&User>) -> Result<(), Error> {
+    AdminAuditLog::new(&CLI_IP, action, user.map(|u| u.uuid.clone()), None).save(conn).await
+}
+
+/// Hashes an admin token into an Argon2id PHC string, with the parameters of the OWASP or the Bitwarden preset
+pub fn hash_admin_token(token: &str, owasp: bool) -> Option<String> {
+    use argon2::{
+        Algorithm::Argon2id, Argon2, ParamsBuilder, PasswordHasher, Version::V0x13, password_hash::SaltString,
+    };
+
+    let mut argon2_params = ParamsBuilder::new();
+    if owasp {
+        argon2_params.m_cost(19456);
+        argon2_params.t_cost(2);
+        argon2_params.p_cost(1);
+    } else {
+        argon2_params.m_cost(65540);
+        argon2_params.t_cost(3);
+        argon2_params.p_cost(4);
+    }
+
+    let argon2 = Argon2::new(Argon2id, V0x13, argon2_params.build().ok()?);
+    let salt = SaltString::encode_b64(&crypto::get_random_bytes::<32>()).ok()?;
+    argon2.hash_password(token.as_bytes(), &salt).ok().map(|h| h.to_string())
+}
+
+// Only the hash is stored, the new token itself is shown once
+async fn rotate_admin_token() -> Result<String, Error> {
+    let token = crypto::encode_random_bytes::<48>(&data_encoding::BASE64URL_NOPAD);
+    let Some(hash) = hash_admin_token(&token, false) else {
+        err_silent!("Unable to generate Argon2id PHC hash")
+    };
+    CONFIG.set_admin_token(hash).await?;
+
+    let mut msg = format!("The admin token was replaced and saved in the config file, the new token is:\n\n{token}");
+    if CONFIG.disable_admin_token() {
+        msg.push_str("\n\nNote: `DISABLE_ADMIN_TOKEN` is enabled, so the admin page does not ask for it");
+    }
+    Ok(msg)
+}
+
+// Read-only, the admin diagnostics and the startup integrity check can remove the attachments without a file
+async fn verify_attachments(conn: &DbConn) -> Result<String, Error> {
+    let backend = CONFIG.storage_backend(&PathType::Attachments)?;
+    let attachments = Attachment::find_all(conn).await;
+
+    let mut issues = Vec::new();
+    for attachment in &attachments {
+        match backend.stat(&attachment.get_file_path()).await? {
+            None => issues.push(format!("The file of attachment '{}' is missing", attachment.id)),
+            Some(stat) if i64::try_from(stat.size).ok() != Some(attachment.file_size) => issues.push(format!(
+                "The file of attachment '{}' has {} bytes, but {} bytes were expected",
+                attachment.id, stat.size, attachment.file_size
+            )),
+            Some(_) => {}
+        }
+    }
+
+    if issues.is_empty() {
+        Ok(format!("Verified {} attachment(s), no issues found", attachments.len()))
+    } else {
+        err_silent!(format!(
+            "Verified {} attachment(s), found {} issue(s):\n{}",
+            attachments.len(),
+            issues.len(),
+            issues.join("\n")
+        ))
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     fmt,
     process::exit,
     sync::{
-        LazyLock, RwLock,
+        Arc, LazyLock, RwLock,
         atomic::{AtomicBool, Ordering},
     },
 };
@@ -104,7 +104,8 @@ macro_rules! make_config {
         struct Inner {
             rocket_shutdown_handle: Option<rocket::Shutdown>,
 
-            templates: Handlebars<'static>,
+            // Not loaded until the first render in low memory mode
+            templates: Option<Handlebars<'static>>,
             config: ConfigItems,
 
             _env: ConfigBuilder,
@@ -309,6 +310,7 @@ macro_rules! make_config {
                 config.domain = config.domain.trim_end_matches('/').to_string();
 
                 config.signups_domains_whitelist = config.signups_domains_whitelist.trim().to_lowercase();
+                config.email_subaddress_domains = config.email_subaddress_domains.trim().to_lowercase();
                 config.org_creation_users = config.org_creation_users.trim().to_lowercase();
 
 
@@ -513,16 +515,32 @@ make_config! {
         sends_folder:           String, false,  auto,   |c| storage::join_path(&c.data_folder, "sends");
         /// Temp folder |> Used for storing temporary file uploads
         tmp_folder:             String, false,  auto,   |c| storage::join_path(&c.data_folder, "tmp");
+        /// Temp quarantine folder |> Failed uploads are moved here so they can be inspected before being cleaned up
+        tmp_quarantine_folder:  String, false,  auto,   |c| storage::join_path(&c.tmp_folder, "quarantine");
+        /// Disaster recovery bundle folder |> Where bundles exported from the admin page are stored, this can be an S3 location.
+        /// If empty, bundles are downloaded by the admin directly.
+        dr_bundle_folder:       String, false,  def,    String::new();
         /// Templates folder
         templates_folder:       String, false,  auto,   |c| storage::join_path(&c.data_folder, "templates");
         /// Session JWT key
         rsa_key_filename:       String, false,  auto,   |c| storage::join_path(&c.data_folder, "rsa_key");
+        /// ACME folder |> Where the ACME account key and the certificate are stored, needs to be a local folder
+        acme_folder:            String, false,  auto,   |c| storage::join_path(&c.data_folder, "acme");
         /// Web vault folder
         web_vault_folder:       String, false,  def,    "web-vault/".to_owned();
     },
     ws {
         /// Enable websocket notifications
         enable_websocket:       bool,   false,  def,    true;
+        /// Websocket ping interval |> Number of seconds between the pings sent to websocket clients (min: 5).
+        /// Lower it when clients behind a NAT or a proxy lose their connection while it's idle.
+        websocket_ping_interval: u64,   false,  def,    15;
+        /// Websocket idle timeout |> Close a websocket connection when nothing, not even the answer to a ping, was received for this number of seconds.
+        /// Removes the connections of clients which disappeared without closing them. Set to 0 to disable.
+        websocket_idle_timeout: u64,    false,  def,    0;
+        /// Max websocket connections |> Maximum number of open websocket connections, new connections are refused once it is reached. Set to 0 for no limit.
+        /// Only websocket connections are limited, use a reverse proxy to limit the number of all connections.
+        websocket_max_connections: usize, false, def,   0;
     },
     push {
         /// Enable push notifications
@@ -535,6 +553,24 @@ make_config! {
         push_installation_id:   Pass,   false,  def,    String::new();
         /// Installation key |> The installation key from https://bitwarden.com/host
         push_installation_key:  Pass,   false,  def,    String::new();
+        /// Re-registration rate |> Number of devices per second which are registered again with the push relay when the admin re-registers all devices,
+        /// for example after changing the relay or the installation credentials
+        push_reregister_rate:   u64,    true,   def,    10;
+    },
+    acme {
+        /// Enable ACME |> Request and renew the TLS certificate of the DOMAIN from an ACME CA, like Let's Encrypt, and serve HTTPS directly.
+        /// Renewing the certificate restarts the web server, which closes the open connections.
+        acme_enabled:           bool,   false,  def,    false;
+        /// ACME email |> Contact address of the ACME account, used by the CA for expiration notices
+        acme_email:             String, false,  option;
+        /// ACME directory URL |> Use `https://acme-staging-v02.api.letsencrypt.org/directory` for testing
+        acme_directory_url:     String, false,  def,    "https://acme-v02.api.letsencrypt.org/directory".to_owned();
+        /// ACME challenge |> "http-01" (needs port 80 to be reachable) or "tls-alpn-01" (needs the Rocket port to be reachable on 443)
+        acme_challenge:         String, false,  def,    "http-01".to_owned();
+        /// ACME HTTP listen address |> Address of the plain HTTP server answering the http-01 challenges, other requests are redirected to the DOMAIN
+        acme_http_listen:       String, false,  def,    "0.0.0.0:80".to_owned();
+        /// ACME renewal days |> Renew the certificate when it expires in less than this number of days (min: 1)
+        acme_renew_days:        u32,    false,  def,    30;
     },
     jobs {
         /// Job scheduler poll interval |> How often the job scheduler thread checks for jobs to run.
@@ -558,19 +594,75 @@ make_config! {
         /// Event cleanup schedule |> Cron schedule of the job that cleans old events from the event table.
         /// Defaults to daily. Set blank to disable this job.
         event_cleanup_schedule:   String, false,  def,    "0 10 0 * * *".to_owned();
-        /// Auth Request cleanup schedule |> Cron schedule of the job that cleans old auth requests from the auth request.
-        /// Defaults to every minute. Set blank to disable this job.
+        /// Auth Request cleanup schedule |> Cron schedule of the job that expires unanswered auth requests after 15 minutes, removing their keys,
+        /// and deletes the ones older than 30 days. Defaults to every minute. Set blank to disable this job.
         auth_request_purge_schedule:   String, false,  def,    "30 * * * * *".to_owned();
+        /// Device cleanup schedule |> Cron schedule of the job that deregisters the push tokens refused by the push relay,
+        /// and removes the devices which weren't used for `DEVICES_DAYS_RETAIN` days. Defaults to daily. Set blank to disable this job.
+        device_cleanup_schedule:   String, false,  def,    "0 25 0 * * *".to_owned();
         /// Duo Auth context cleanup schedule |> Cron schedule of the job that cleans expired Duo contexts from the database. Does nothing if Duo MFA is disabled or set to use the legacy iframe prompt.
         /// Defaults to once every minute. Set blank to disable this job.
         duo_context_purge_schedule:   String, false,  def,    "30 * * * * *".to_owned();
         /// Purge incomplete SSO auth. |> Cron schedule of the job that cleans leftover auth in db due to incomplete SSO login.
         /// Defaults to daily. Set blank to disable this job.
         purge_incomplete_sso_auth: String, false,  def,   "0 20 0 * * *".to_owned();
+        /// Unverified users purge schedule |> Cron schedule of the job that removes the accounts which weren't verified within `SIGNUPS_VERIFY_DAYS_RETAIN` days.
+        /// Defaults to daily. Set blank to disable this job.
+        unverified_users_purge_schedule: String, false, def, "0 30 0 * * *".to_owned();
+        /// 2FA grace period schedule |> Cron schedule of the job that reminds the members of organizations requiring 2FA to enable it,
+        /// and revokes them once the grace period of the policy has passed. Defaults to daily. Set blank to disable this job.
+        two_factor_grace_period_schedule: String, false, def, "0 35 0 * * *".to_owned();
+        /// Revision reconcile schedule |> Cron schedule of the job that bumps the revision date of the users which is older than the newest change of their items,
+        /// so their clients sync those changes. Defaults to daily. Set blank to disable this job.
+        revision_reconcile_schedule: String, false, def, "0 40 0 * * *".to_owned();
+        /// Inactive users schedule |> Cron schedule of the job that warns, disables or deletes the accounts without a login for `INACTIVE_USERS_MONTHS` months.
+        /// Defaults to daily. Set blank to disable this job.
+        inactive_users_schedule: String, false, def, "0 45 0 * * *".to_owned();
+        /// Audit grant expiry schedule |> Cron schedule of the job that removes the read-only collection access of auditors once their grant expires.
+        /// Defaults to once every minute. Set blank to disable this job.
+        audit_grant_expiry_schedule: String, false, def, "15 * * * * *".to_owned();
+        /// Attachment quota warning schedule |> Cron schedule of the job that warns the users and organization owners whose attachments use 80% or 95% of
+        /// `USER_ATTACHMENT_LIMIT` or `ORG_ATTACHMENT_LIMIT`. Uploads are checked right away as well. Defaults to daily. Set blank to disable this job.
+        attachment_quota_warning_schedule: String, false, def, "0 50 0 * * *".to_owned();
+        /// Attachment re-key verification schedule |> Cron schedule of the job that confirms the uploads of the attachments re-encrypted after a key rotation.
+        /// Defaults to every 5 minutes. Set blank to disable this job.
+        attachment_rekey_verification_schedule: String, false, def, "30 */5 * * * *".to_owned();
+        /// Temp file cleanup schedule |> Cron schedule of the job that removes stale partial uploads from the temp and quarantine folders.
+        /// Defaults to hourly (15 minutes after the hour). Set blank to disable this job.
+        tmp_cleanup_schedule:   String, false,  def,    "0 15 * * * *".to_owned();
+        /// Temp file max age |> Number of seconds after which a leftover temporary or quarantined upload is considered stale and removed (min: 3600)
+        tmp_cleanup_max_age:    u64,    false,  def,    86_400;
+        /// Database backup schedule |> Cron schedule of the job that creates a backup of the SQLite database next to the database file.
+        /// Only works for SQLite. Disabled by default.
+        db_backup_schedule:     String, false,  def,    String::new();
+        /// Usage snapshot schedule |> Cron schedule of the job that stores the daily usage totals (users, devices, ciphers, storage, events).
+        /// The snapshots are available from the admin usage report. Defaults to daily. Set blank to disable this job.
+        usage_snapshot_schedule: String, false, def,    "0 55 23 * * *".to_owned();
+        /// Usage snapshot retention |> Number of days the usage snapshots are kept (min: 1)
+        usage_snapshot_days_retain: u32, false, def,    730;
+        /// Usage statistics schedule |> Cron schedule of the job that posts the anonymized usage statistics to `USAGE_STATISTICS_URL`.
+        /// Only used when that URL is set. Defaults to daily. Set blank to disable this job.
+        usage_statistics_schedule: String, false, def,  "0 20 4 * * *".to_owned();
+        /// LDAP sync schedule |> Cron schedule of the job that syncs the users of the LDAP directory into `LDAP_ORG_ID`.
+        /// Only used when the LDAP sync is enabled. Defaults to every 15 minutes. Set blank to disable this job.
+        ldap_sync_schedule:     String, false,  def,    "0 */15 * * * *".to_owned();
+        /// ACME renewal schedule |> Cron schedule of the job that checks if the ACME certificate needs to be renewed.
+        /// Only used when ACME is enabled. Defaults to daily. Set blank to disable this job.
+        acme_renew_schedule:    String, false,  def,    "0 40 3 * * *".to_owned();
+        /// Job start jitter |> Maximum number of seconds a scheduled job waits before it starts, a random delay is chosen on every run.
+        /// Spreads the load of the jobs when multiple instances share the same database. Set to 0 to disable.
+        job_start_jitter:       u64,    false,  def,    0;
+        /// Job lock timeout |> Number of seconds after which the database lock of a running job is considered stale (min: 60).
+        /// The lock prevents multiple instances sharing the same database from running the same job concurrently.
+        job_lock_timeout:       u64,    false,  def,    3_600;
     },
 
     /// General settings
     settings {
+        /// Low memory mode |> Lowers the defaults of the settings which use the most memory, for hosts like a Raspberry Pi:
+        /// a smaller database pool, no organization or response cache, fewer concurrent syncs and templates compiled only when first used.
+        /// Settings which are set explicitly still take precedence.
+        low_memory:             bool,   false,  def,    false;
         /// Domain URL |> This needs to be set to the URL used to access the server, including 'http[s]://'
         /// and port, if it's different than the default. Some server functions don't work correctly without this value
         domain:                 String, true,   def,    "http://localhost".to_owned();
@@ -594,8 +686,23 @@ make_config! {
         user_attachment_limit:  i64,    true,   option;
         /// Per-organization attachment storage limit (KB) |> Max kilobytes of attachment storage allowed per org. When this limit is reached, org members will not be allowed to upload further attachments for ciphers owned by that org.
         org_attachment_limit:   i64,    true,   option;
+        /// Organization seats |> Number of seats reported to the organization admins. This is only informational, the number of members is not limited by it.
+        /// If unset, unlimited seats are reported.
+        org_seats:              i32,    true,   option;
         /// Per-user send storage limit (KB) |> Max kilobytes of sends storage allowed per user. When this limit is reached, the user will not be allowed to upload further sends.
         user_send_limit:   i64,    true,   option;
+        /// Per-user item limit |> Max number of items in the personal vault of a user, creating or importing more items is refused. Users can be exempted by the admin.
+        user_item_limit:   i64,    true,   option;
+        /// Per-user hourly item limit |> Max number of items a user can create per hour, including the items of organizations and imports. Users can be exempted by the admin.
+        user_item_hourly_limit: u64, true, option;
+        /// Per-user Send limit |> Max number of Sends of a user, creating more Sends is refused. Users can be exempted by the admin.
+        user_send_count_limit: i64, true, option;
+        /// Send file hash blocklist |> Comma separated list of SHA-256 hashes (hex) of files which can't be shared with a file Send.
+        /// The hash of the unencrypted file is provided by the client as `fileHash`, the server can't check the encrypted content itself.
+        send_file_hash_blocklist: String, true, option;
+        /// Monthly Send egress limit (KB) |> Max kilobytes served by the downloads of file Sends per calendar month (UTC).
+        /// When this limit is reached, Send files are not served anymore until the next month.
+        send_monthly_egress_limit: i64, true, option;
 
         /// Trash auto-delete days |> Number of days to wait before auto-deleting a trashed item.
         /// If unset, trashed items are not auto-deleted. This setting applies globally, so make
@@ -623,13 +730,27 @@ make_config! {
         signups_verify_resend_time: u64, true,  def,    3_600;
         /// If signups require email verification, limit how many emails are automatically sent when login is attempted (0 means no limit)
         signups_verify_resend_limit: u32, true, def,    6;
+        /// Unverified accounts days retain |> If signups require email verification, accounts which weren't verified within this number of days
+        /// are removed by the unverified users purge job. Only accounts which never logged in are removed. If unset, accounts are kept indefinitely
+        signups_verify_days_retain: i64, true, option;
         /// Email domain whitelist |> Allow signups only from this list of comma-separated domains, even when signups are otherwise disabled
         signups_domains_whitelist: String, true, def,   String::new();
+        /// Email subaddress normalization |> Treat `user+tag@example.com` as the same account as `user@example.com` at registration and invite time,
+        /// so an invite to either address goes to the existing account and no second account can be registered
+        email_subaddress_normalization: bool, true, def, false;
+        /// Email subaddress domains |> Comma-separated list of domains the normalization applies to, blank for all domains.
+        /// A domain prefixed with `!` is excluded, like `!example.com` to apply it to all domains except that one
+        email_subaddress_domains: String, true, def,    String::new();
+        /// Tenants file |> Path to a JSON file with tenant definitions, which enables multi-tenant mode.
+        /// Each tenant is selected by the hostname of the request and has its own users, organizations, signup rules, mail links, branding and optionally SMTP server
+        tenants_file:           String, false,  def,    String::new();
         /// Enable event logging |> Enables event logging for organizations.
         org_events_enabled:     bool,   false,  def,    false;
         /// Org creation users |> Allow org creation only by this list of comma-separated user emails.
         /// Blank or 'all' means all users can create orgs; 'none' means no users can create orgs.
         org_creation_users:     String, true,   def,    String::new();
+        /// Audit grant max hours |> The longest time, in hours, organization owners can give auditors read-only access to selected collections (must be at least 1)
+        audit_grant_max_hours:  u32,    true,   def,    720;
         /// Allow invitations |> Controls whether users can be invited by organization admins, even when signups are otherwise disabled
         invitations_allowed:    bool,   true,   def,    true;
         /// Invitation token expiration time (in hours) |> The number of hours after which an organization invite token, emergency access invite token,
@@ -639,15 +760,26 @@ make_config! {
         emergency_access_allowed:    bool,   true,   def,    true;
         /// Allow email change |> Controls whether users can change their email. This setting applies globally to all users.
         email_change_allowed:    bool,   true,   def,    true;
+        /// Security change lockout hours |> While an email or two-step login change isn't confirmed, for at most this number of hours,
+        /// the other protected actions need the master password instead of a code sent by email, and the current email address is notified. Set to 0 to disable
+        security_change_lockout_hours: u32, true, def,  24;
         /// Password iterations |> Number of server-side passwords hashing iterations for the password hash.
         /// The default for new users. If changed, it will be updated during login for existing users.
         password_iterations:    i32,    true,   def,    600_000;
         /// Allow password hints |> Controls whether users can set or show password hints. This setting applies globally to all users.
         password_hints_allowed: bool,   true,   def,    true;
+        /// Password hint max length |> Maximum number of characters of the password hints. If unset, the length isn't limited
+        password_hint_max_length: usize, true,  option;
         /// Show password hint (Know the risks!) |> Controls whether a password hint should be shown directly in the web page
         /// if SMTP service is not configured and password hints are allowed. Not recommended for publicly-accessible instances
         /// because this provides unauthenticated access to potentially sensitive data.
         show_password_hint:     bool,   true,   def,    false;
+        /// Strict request parsing |> Reject items and Sends which contain unknown fields or values which aren't valid encrypted strings,
+        /// instead of storing them as sent. Clients which send extra fields will not be able to save these items.
+        strict_request_parsing: bool,   true,   def,    false;
+        /// Serialize item writes |> Only allow one save of the same item at a time. Another client saving the same item simultaneously
+        /// gets a 409 response and can retry, instead of both writes being interleaved.
+        cipher_write_lock:      bool,   true,   def,    false;
 
         /// Admin token/Argon2 PHC |> The plain text token or Argon2 PHC string used to authenticate in this very same page. Changing it here will not deauthorize the current session!
         admin_token:            Pass,   true,   option;
@@ -657,6 +789,21 @@ make_config! {
 
         /// Events days retain |> Number of days to retain events stored in the database. If unset, events are kept indefinitely.
         events_days_retain:     i64,    false,   option;
+
+        /// Devices days retain |> Number of days after which devices which weren't used anymore are removed. If unset, devices are kept indefinitely.
+        devices_days_retain:    i64,    false,   option;
+
+        /// Inactive users months |> Number of months without a login after which an account is considered inactive.
+        /// If unset, inactive accounts are not detected
+        inactive_users_months:  i64,    true,    option;
+        /// Inactive users action |> What happens to inactive accounts: `warn` only emails a warning, `disable` and `delete` also
+        /// disable or delete the account when there was still no login within the grace period after the warning
+        inactive_users_action:  String, true,    def,    "warn".to_owned();
+        /// Inactive users grace days |> Number of days between the warning and disabling or deleting an inactive account
+        inactive_users_grace_days: i64, true,    def,    30;
+
+        /// Support access hours |> Number of hours a user allows the admin to open read-only support sessions for their account
+        support_access_hours:   u32,    true,    def,    24;
     },
 
     /// Advanced settings
@@ -666,6 +813,21 @@ make_config! {
         ip_header:              String, true,   def,    "X-Real-IP".to_owned();
         /// Internal IP header property, used to avoid recomputing each time
         _ip_header_enabled:     bool,   false,  generated,    |c| &c.ip_header.trim().to_lowercase() != "none";
+        /// Trusted proxies |> Comma separated list of IPs or CIDR ranges of the reverse proxies in front of Vaultwarden.
+        /// When set, the IP header is only used for requests coming from these proxies, and the rightmost address in it which isn't a trusted proxy is used as the client IP.
+        trusted_proxies:        String, false,  def,    String::new();
+        /// Client country header |> Header in which the reverse proxy sets the two letter country code of the client, like `CF-IPCountry`.
+        /// Used to notify the users of logins from a new country. It is only used for requests coming from the trusted proxies, and ignored when those aren't set
+        country_header:         String, true,   def,    String::new();
+        /// PROXY protocol listen address |> Address, like `0.0.0.0:8081`, on which connections using the HAProxy PROXY protocol (v1 or v2) are accepted and forwarded to Vaultwarden
+        proxy_protocol_listen:  String, false,  option;
+        /// Unix socket path |> Accept the connections on this Unix socket, Rocket then only listens on a random port of the loopback interface instead of `ROCKET_ADDRESS` and `ROCKET_PORT`
+        unix_socket_path:       String, false,  option;
+        /// Unix socket mode |> Permissions of the Unix socket, in octal
+        unix_socket_mode:       String, false,  def,    "660".to_owned();
+        /// Socket activation |> Accept the connections on the listening socket systemd passes with `LISTEN_FDS`, from a socket unit with a single `ListenStream=`.
+        /// Rocket then only listens on a random port of the loopback interface instead of `ROCKET_ADDRESS` and `ROCKET_PORT`, and refuses the requests which didn't come through the socket
+        socket_activation:      bool,   false,  def,    false;
         /// Icon service |> The predefined icon services are: internal, bitwarden, duckduckgo, google.
         /// To specify a custom icon service, set a URL template with exactly one instance of `{}`,
         /// which is replaced with the domain. For example: `https://icon.example.com/domain/{}`.
@@ -687,6 +849,10 @@ make_config! {
         icon_cache_ttl:         u64,    true,   def,    2_592_000;
         /// Negative icon cache expiry |> Number of seconds before trying to download an icon that failed again.
         icon_cache_negttl:      u64,    true,   def,    259_200;
+        /// Icon client cache expiry |> Number of seconds the clients may use an icon before asking for it again, with a conditional request answered by an empty `304 Not Modified` when it didn't change. Defaults to the positive icon cache expiry, or 30 days when that is 0
+        icon_http_ttl:          u64,    true,   auto,   |c| if c.icon_cache_ttl == 0 { 2_592_000 } else { c.icon_cache_ttl };
+        /// Icon client cache expiry of missing icons |> The same for the fallback icon of domains without one. Defaults to the negative icon cache expiry, or 3 days when that is 0
+        icon_http_negttl:       u64,    true,   auto,   |c| if c.icon_cache_negttl == 0 { 259_200 } else { c.icon_cache_negttl };
         /// Icon download timeout |> Number of seconds when to stop attempting to download an icon.
         icon_download_timeout:  u64,    true,   def,    10;
 
@@ -701,6 +867,9 @@ make_config! {
         /// Block non global IPs |> Enabling this will cause the internal HTTP client to refuse to connect to any non global IP address.
         /// Useful to secure your internal environment: See https://en.wikipedia.org/wiki/Reserved_IP_addresses for a list of IPs which it will block
         http_request_block_non_global_ips:  bool,   true,   auto, |c| c.icon_blacklist_non_global_ips;
+        /// Allow non global IPs for these hosts |> Comma-separated list of domains or IPs the internal HTTP client may still connect to when they are non global IPs,
+        /// like an internal SIEM receiving the webhooks. The `http_request_block_regex` still applies to them
+        http_request_allow_non_global_hosts: String, true, def, String::new();
 
         /// Disable Two-Factor remember |> Enabling this would force the users to use a second factor to login every time.
         /// Note that the checkbox would still be present, but ignored.
@@ -713,10 +882,27 @@ make_config! {
         /// Customize the enabled feature flags on the clients |> This is a comma separated list of feature flags to enable.
         experimental_client_feature_flags: String, false, def, String::new();
 
-        /// Require new device emails |> When a user logs in an email is required to be sent.
+        /// Require new device emails |> When a user logs in from a new device or country an email is required to be sent, even if the user disabled these emails.
         /// If sending the email fails the login attempt will fail.
         require_device_email:   bool,   true,   def,     false;
 
+        /// Disable legacy attachment upload |> Removes the single step attachment upload used by older clients, newer clients create the attachment first and upload the file afterwards
+        disable_legacy_attachment_upload:   bool,   false,  def,    false;
+        /// Disable legacy cipher admin routes |> Removes the `POST /ciphers/<id>/admin` alias, newer clients use `PUT`
+        disable_legacy_cipher_admin_routes: bool,   false,  def,    false;
+        /// Disable legacy two-factor routes |> Removes the `POST` variants used by older clients to enable or disable two-factor providers, newer clients use `PUT`
+        disable_legacy_two_factor_routes:   bool,   false,  def,    false;
+        /// Enable legacy token API |> Adds the `POST /api/auth/token` login of pre-2019 third-party clients, translated to the current login
+        legacy_token_api_enabled:           bool,   false,  def,    false;
+
+        /// Minimum client versions |> Comma separated list of `type=version` pairs, where type is one of web, browser, desktop, mobile or cli.
+        /// Older clients, and clients which don't send their version, are refused when logging in or refreshing their session.
+        min_client_versions:    String, true,   def,    String::new();
+
+        /// Usage statistics URL |> Opt-in, anonymized statistics of this instance (versions, database type, number of users rounded to a bucket and enabled features) are posted as JSON to this URL.
+        /// The payload can be inspected at /admin/diagnostics/usage-statistics. An internal host needs to be listed in `HTTP_REQUEST_ALLOW_NON_GLOBAL_HOSTS`.
+        usage_statistics_url:   String, true,   option;
+
         /// Reload templates (Dev) |> When this is set to true, the templates get reloaded with every request.
         /// ONLY use this during development, as it can slow down the server
         reload_templates:       bool,   true,   def,    false;
@@ -746,14 +932,22 @@ make_config! {
         database_idle_timeout:  u64,    false, def,     600;
 
         /// Database connection max pool size
-        database_max_conns:     u32,    false,  def,    10;
+        database_max_conns:     u32,    false,  auto,   |c| if c.low_memory {4} else {10};
 
         /// Database connection min pool size
-        database_min_conns:     u32,    false,  def,    2;
+        database_min_conns:     u32,    false,  auto,   |c| if c.low_memory {1} else {2};
 
         /// Database connection init |> SQL statements to run when creating a new database connection, mainly useful for connection-scoped pragmas. If empty, a database-specific default is used.
         database_conn_init:     String, false,  def,    String::new();
 
+        /// Startup integrity check |> Verify the database schema version and look for known inconsistencies in the data after startup.
+        /// The checks run in the background, the resulting report is shown on the admin diagnostics page.
+        startup_integrity_check: bool,  false,  def,    true;
+
+        /// Startup integrity repair |> Automatically fix trivial inconsistencies found by the startup integrity check,
+        /// like removing attachment records of which the file no longer exists. Uploads which may still be going on are left alone.
+        startup_integrity_repair: bool, false,  def,    false;
+
         /// Bypass admin page security (Know the risks!) |> Disables the Admin Token for the admin page so you may use your own auth in-front
         disable_admin_token:    bool,   false,  def,    false;
 
@@ -767,12 +961,27 @@ make_config! {
         login_ratelimit_seconds:       u64, false, def, 60;
         /// Max burst size for login requests |> Allow a burst of requests of up to this size, while maintaining the average indicated by `login_ratelimit_seconds`. Note that this applies to both the login and the 2FA, so it's recommended to allow a burst size of at least 2
         login_ratelimit_max_burst:     u32, false, def, 10;
+        /// Trusted networks |> Comma separated list of IPs or CIDR ranges, like an office VPN or health check probes, which are exempt from the login rate limit and the new device emails.
+        /// The logins and their events are still logged
+        trusted_networks:              String, false, def, String::new();
 
         /// Seconds between admin login requests |> Number of seconds, on average, between admin requests from the same IP address before rate limiting kicks in
         admin_ratelimit_seconds:       u64, false, def, 300;
         /// Max burst size for admin login requests |> Allow a burst of requests of up to this size, while maintaining the average indicated by `admin_ratelimit_seconds`
         admin_ratelimit_max_burst:     u32, false, def, 3;
 
+        /// Seconds between shared attachment downloads |> Number of seconds, on average, between downloads of shared attachment links from the same IP address before rate limiting kicks in
+        attachment_share_ratelimit_seconds:   u64, false, def, 10;
+        /// Max burst size for shared attachment downloads |> Allow a burst of requests of up to this size, while maintaining the average indicated by `attachment_share_ratelimit_seconds`
+        attachment_share_ratelimit_max_burst: u32, false, def, 5;
+
+        /// Admin lockout attempts |> Lock the admin panel for an IP address after this number of failed admin token attempts from it. Set to 0 to disable.
+        admin_lockout_attempts:        u32, false, def, 0;
+        /// Admin lockout duration |> Number of minutes the admin panel stays locked for that IP address, failed attempts are counted over the same period (min: 1)
+        admin_lockout_minutes:         u64, false, def, 60;
+        /// Admin lockout email |> Address which is notified when an IP address gets locked out of the admin panel
+        admin_lockout_email:           String, true, option;
+
         /// Admin session lifetime |> Set the lifetime of admin sessions to this value (in minutes).
         admin_session_lifetime:        i64, true,  def, 20;
 
@@ -793,6 +1002,26 @@ make_config! {
         /// Prefer IPv6 (AAAA) resolving |> This settings configures the DNS resolver to resolve IPv6 first, and if not available try IPv4
         /// This could be useful in IPv6 only environments.
         dns_prefer_ipv6: bool, true, def, false;
+
+        /// Shutdown drain window |> Number of seconds to wait on shutdown for in-flight requests, like attachment uploads, and running background jobs to finish.
+        /// Websocket clients are told to reconnect. Anything still running after this window is aborted.
+        shutdown_drain_timeout: u64, false, def, 30;
+
+        /// HTTP keep-alive |> Number of seconds an idle HTTP connection is kept open for the next request. Set to 0 to disable keep-alive.
+        /// Overrides `ROCKET_KEEP_ALIVE`, which defaults to 5. The embedded server only speaks HTTP/1.1, HTTP/2 needs a reverse proxy.
+        http_keep_alive: u32, false, option;
+
+        /// Organization cache TTL |> Number of seconds organization policy and membership lookups are cached in memory.
+        /// Speeds up imports and bulk edits. Changes made through this instance clear the cache right away. Set to 0 to disable.
+        org_cache_ttl:          u64,    false,  auto,   |c| if c.low_memory {0} else {10};
+
+        /// Response cache TTL |> Number of seconds the domain settings, policies and organization details are cached in memory.
+        /// Changes made through this instance clear the cached responses right away. Set to 0 to disable.
+        response_cache_ttl:     u64,    false,  auto,   |c| if c.low_memory {0} else {30};
+
+        /// Max concurrent syncs |> Number of full vault syncs which are built at the same time, others wait for their turn.
+        /// Every sync holds the whole vault of the user in memory while it is built. Set to 0 for no limit. Must be smaller than `DATABASE_MAX_CONNS`.
+        sync_max_concurrent:    usize,  false,  auto,   |c| if c.low_memory {2} else {0};
     },
 
     /// OpenID Connect SSO settings
@@ -831,6 +1060,48 @@ make_config! {
         sso_debug_tokens:               bool,   true,   def,    false;
     },
 
+    /// LDAP directory sync settings
+    ldap {
+        /// Enabled |> Invite the users of the LDAP directory to an organization, and revoke the ones removed from it.
+        /// The DN of a user is stored as the external id of the member, members with another external id are left alone.
+        ldap_sync_enabled:      bool,   true,   def,    false;
+        /// Server URL |> `ldap://` or `ldaps://`, the certificate is verified against the system trust store
+        ldap_url:               String, true,   def,    String::new();
+        /// Use StartTLS |> Upgrade an `ldap://` connection to TLS before binding
+        ldap_starttls:          bool,   true,   def,    false;
+        /// Bind DN |> Account used to search the directory, requires the password. Leave both empty for an anonymous bind
+        ldap_bind_dn:           String, true,   def,    String::new();
+        /// Bind password
+        ldap_bind_password:     Pass,   true,   def,    String::new();
+        /// Base DN |> Where the users are searched, including the subtree
+        ldap_base_dn:           String, true,   def,    String::new();
+        /// User filter |> LDAP filter selecting the users to sync
+        ldap_user_filter:       String, true,   def,    "(&(objectClass=person)(mail=*))".to_owned();
+        /// Email attribute
+        ldap_email_attribute:   String, true,   def,    "mail".to_owned();
+        /// Organization id |> The organization the users are invited to
+        ldap_org_id:            String, true,   def,    String::new();
+        /// Revoke removed users |> Revoke the members synced before which aren't returned by the directory anymore.
+        /// Nothing is revoked when the search returns no users at all.
+        ldap_revoke_removed:    bool,   true,   def,    true;
+        /// Timeout |> Number of seconds to wait for the LDAP server
+        ldap_timeout:           u64,    true,   def,    30;
+    },
+
+    /// Webhook settings
+    webhook {
+        /// Webhook URL |> Every event is POSTed as signed JSON to this URL, independently of `ORG_EVENTS_ENABLED`. Blank to disable
+        webhook_url:            String, true,   def,    String::new();
+        /// Webhook secret |> Key of the HMAC-SHA256 signature sent in the `X-Vaultwarden-Signature` header
+        webhook_secret:         Pass,   true,   def,    String::new();
+        /// Webhook events |> Comma-separated list of the event types to send, like `1000,1100,1500`. Blank for all events
+        webhook_events:         String, true,   def,    String::new();
+        /// Allow organization webhooks |> Let organization owners set a webhook receiving the events of their organization
+        org_webhooks_allowed:   bool,   true,   def,    false;
+        /// Timeout |> Number of seconds to wait for a webhook to respond
+        webhook_timeout:        u64,    true,   def,    10;
+    },
+
     /// Yubikey settings
     yubico: _enable_yubico {
         /// Enabled
@@ -959,6 +1230,13 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         err!("`DATABASE_MIN_CONNS` must be smaller than or equal to `DATABASE_MAX_CONNS`.");
     }
 
+    // Every running sync holds a connection, the other requests and the jobs need some as well
+    if cfg.sync_max_concurrent != 0 && cfg.sync_max_concurrent >= cfg.database_max_conns as usize {
+        err!(
+            "`SYNC_MAX_CONCURRENT` must be smaller than `DATABASE_MAX_CONNS`, otherwise the syncs can take all database connections"
+        );
+    }
+
     if let Some(log_file) = &cfg.log_file
         && std::fs::OpenOptions::new().append(true).create(true).open(log_file).is_err()
     {
@@ -986,6 +1264,12 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         err!("`SIGNUPS_DOMAINS_WHITELIST` contains empty tokens");
     }
 
+    if !cfg.tenants_file.is_empty()
+        && let Err(e) = crate::tenant::load_tenants(&cfg.tenants_file)
+    {
+        err!(format!("`TENANTS_FILE` is invalid: {e}"));
+    }
+
     let org_creation_users = cfg.org_creation_users.trim().to_lowercase();
     if !(org_creation_users.is_empty() || org_creation_users == "all" || org_creation_users == "none")
         && org_creation_users.split(',').any(|u| !u.contains('@'))
@@ -1013,6 +1297,10 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
     }
 
     if cfg.push_enabled {
+        if cfg.push_reregister_rate == 0 || cfg.push_reregister_rate > 1000 {
+            err!("`PUSH_REREGISTER_RATE` must be between 1 and 1000")
+        }
+
         let push_relay_uri = cfg.push_relay_uri.to_lowercase();
         if !push_relay_uri.starts_with("https://") {
             err!("`PUSH_RELAY_URI` must start with 'https://'.")
@@ -1048,6 +1336,20 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         println!("[WARNING] {feature_flags_error}");
     }
 
+    for entry in cfg.min_client_versions.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let Some((client_type, version)) = entry.split_once('=') else {
+            err!(format!("`MIN_CLIENT_VERSIONS` entry `{entry}` is not in the `type=version` format"))
+        };
+        if !CLIENT_TYPES.contains(&client_type.trim()) {
+            err!(format!(
+                "`MIN_CLIENT_VERSIONS` contains an unknown client type `{client_type}`, supported types: {CLIENT_TYPES:?}"
+            ))
+        }
+        if semver::Version::parse(version.trim()).is_err() {
+            err!(format!("`MIN_CLIENT_VERSIONS` contains an invalid version `{version}`"))
+        }
+    }
+
     #[expect(clippy::items_after_statements, reason = "Keep this close to where it is used")]
     const MAX_FILESIZE_KB: i64 = i64::MAX >> 10;
 
@@ -1063,12 +1365,127 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         err!("`ORG_ATTACHMENT_LIMIT` is out of bounds");
     }
 
+    for proxy in cfg.trusted_proxies.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        if !crate::proxy::is_valid_proxy(proxy) {
+            err!(format!("`TRUSTED_PROXIES` contains an invalid IP or CIDR range `{proxy}`"))
+        }
+    }
+
+    for network in cfg.trusted_networks.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        if !crate::proxy::is_valid_proxy(network) {
+            err!(format!("`TRUSTED_NETWORKS` contains an invalid IP or CIDR range `{network}`"))
+        }
+    }
+
+    if let Some(listen) = &cfg.proxy_protocol_listen
+        && listen.parse::<std::net::SocketAddr>().is_err()
+    {
+        err!("`PROXY_PROTOCOL_LISTEN` is not a valid socket address, like `0.0.0.0:8081`")
+    }
+
+    if cfg.admin_lockout_minutes < 1 {
+        err!("`ADMIN_LOCKOUT_MINUTES` must be at least 1")
+    }
+
+    if let Some(email) = &cfg.admin_lockout_email
+        && !email.contains('@')
+    {
+        err!("`ADMIN_LOCKOUT_EMAIL` is not a valid email address")
+    }
+
+    #[cfg(not(s3))]
+    for (name, folder) in [
+        ("DATA_FOLDER", &cfg.data_folder),
+        ("ICON_CACHE_FOLDER", &cfg.icon_cache_folder),
+        ("ATTACHMENTS_FOLDER", &cfg.attachments_folder),
+        ("SENDS_FOLDER", &cfg.sends_folder),
+    ] {
+        if folder.starts_with("s3://") {
+            err!(format!("`{name}` is an S3 location, but this build doesn't include the `s3` feature"))
+        }
+    }
+
+    #[cfg(not(azure))]
+    for (name, folder) in [
+        ("DATA_FOLDER", &cfg.data_folder),
+        ("ICON_CACHE_FOLDER", &cfg.icon_cache_folder),
+        ("ATTACHMENTS_FOLDER", &cfg.attachments_folder),
+        ("SENDS_FOLDER", &cfg.sends_folder),
+    ] {
+        if folder.starts_with("azblob://") {
+            err!(format!("`{name}` is an Azure location, but this build doesn't include the `azure` feature"))
+        }
+    }
+
+    if cfg.websocket_ping_interval < 5 {
+        err!("`WEBSOCKET_PING_INTERVAL` must be at least 5")
+    }
+
+    if cfg.websocket_idle_timeout != 0 && cfg.websocket_idle_timeout <= cfg.websocket_ping_interval {
+        err!(
+            "`WEBSOCKET_IDLE_TIMEOUT` must be longer than `WEBSOCKET_PING_INTERVAL`, otherwise connections are closed before they can answer a ping"
+        )
+    }
+
+    if cfg.acme_enabled {
+        if !cfg.domain_set || !cfg.domain.starts_with("https://") {
+            err!("`ACME_ENABLED` needs `DOMAIN` to be set to an https:// URL")
+        }
+        if cfg.acme_folder.contains("://") {
+            err!("`ACME_FOLDER` needs to be a local folder")
+        }
+        if !matches!(cfg.acme_challenge.as_str(), "http-01" | "tls-alpn-01") {
+            err!("`ACME_CHALLENGE` must be either `http-01` or `tls-alpn-01`")
+        }
+        if cfg.acme_challenge == "http-01" && cfg.acme_http_listen.parse::<std::net::SocketAddr>().is_err() {
+            err!("`ACME_HTTP_LISTEN` is not a valid socket address, like `0.0.0.0:80`")
+        }
+        if cfg.acme_renew_days < 1 {
+            err!("`ACME_RENEW_DAYS` must be at least 1")
+        }
+    }
+
+    if cfg.unix_socket_path.is_some() || cfg.socket_activation {
+        if cfg!(not(unix)) {
+            err!("`UNIX_SOCKET_PATH` and `SOCKET_ACTIVATION` are only supported on Unix systems")
+        }
+        if cfg.unix_socket_path.is_some() && cfg.socket_activation {
+            err!("`UNIX_SOCKET_PATH` and `SOCKET_ACTIVATION` can't be used together")
+        }
+        if cfg.acme_enabled {
+            err!(
+                "`ACME_ENABLED` can't be used with `UNIX_SOCKET_PATH` or `SOCKET_ACTIVATION`, let the reverse proxy handle TLS"
+            )
+        }
+    }
+
+    if !u32::from_str_radix(&cfg.unix_socket_mode, 8).is_ok_and(|mode| mode <= 0o777) {
+        err!("`UNIX_SOCKET_MODE` must be octal permissions, like `660`")
+    }
+
+    if cfg.org_seats.is_some_and(|seats| seats < 1) {
+        err!("`ORG_SEATS` must be at least 1");
+    }
+
     if let Some(limit) = cfg.user_send_limit
         && !(0i64..=MAX_FILESIZE_KB).contains(&limit)
     {
         err!("`USER_SEND_LIMIT` is out of bounds");
     }
 
+    if cfg.send_monthly_egress_limit.is_some_and(|limit| limit < 0) {
+        err!("`SEND_MONTHLY_EGRESS_LIMIT` can't be negative");
+    }
+
+    if let Some(blocklist) = &cfg.send_file_hash_blocklist
+        && blocklist
+            .split(',')
+            .map(str::trim)
+            .any(|hash| hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        err!("`SEND_FILE_HASH_BLOCKLIST` must be a comma separated list of SHA-256 hashes in hex");
+    }
+
     if cfg._enable_duo
         && (cfg.duo_host.is_some() || cfg.duo_ikey.is_some() || cfg.duo_skey.is_some())
         && !(cfg.duo_host.is_some() && cfg.duo_ikey.is_some() && cfg.duo_skey.is_some())
@@ -1086,6 +1503,35 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         validate_sso_master_password_policy(cfg.sso_master_password_policy.as_ref())?;
     }
 
+    if cfg.ldap_sync_enabled {
+        if cfg.ldap_url.is_empty() || cfg.ldap_base_dn.is_empty() || cfg.ldap_org_id.is_empty() {
+            err!("`LDAP_URL`, `LDAP_BASE_DN` and `LDAP_ORG_ID` must be set for the LDAP sync")
+        }
+        if !(cfg.ldap_url.starts_with("ldap://") || cfg.ldap_url.starts_with("ldaps://")) {
+            err!("`LDAP_URL` must start with `ldap://` or `ldaps://`")
+        }
+        if cfg.ldap_starttls && cfg.ldap_url.starts_with("ldaps://") {
+            err!("`LDAP_STARTTLS` can only be used with an `ldap://` URL")
+        }
+        if crate::ldap::parse_filter(&cfg.ldap_user_filter).is_err() {
+            err!("`LDAP_USER_FILTER` is not a valid LDAP filter")
+        }
+        // A bind DN without a password is an unauthenticated bind, which most servers accept without any check
+        if !cfg.ldap_bind_dn.is_empty() && cfg.ldap_bind_password.is_empty() {
+            err!("`LDAP_BIND_PASSWORD` must be set when `LDAP_BIND_DN` is set, leave both empty for an anonymous bind")
+        }
+    }
+
+    if !cfg.webhook_url.is_empty() {
+        validate_webhook_url(&cfg.webhook_url)?;
+        if cfg.webhook_secret.is_empty() {
+            err!("`WEBHOOK_SECRET` must be set when `WEBHOOK_URL` is set")
+        }
+    }
+    if cfg.webhook_events.split(',').map(str::trim).any(|t| !t.is_empty() && t.parse::<i32>().is_err()) {
+        err!("`WEBHOOK_EVENTS` must be a comma-separated list of event type numbers")
+    }
+
     if cfg._enable_yubico {
         if cfg.yubico_client_id.is_some() != cfg.yubico_secret_key.is_some() {
             err!("Both `YUBICO_CLIENT_ID` and `YUBICO_SECRET_KEY` must be set for Yubikey OTP support")
@@ -1183,21 +1629,7 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         }
     }
 
-    // Check if the icon service is valid
-    let icon_service = cfg.icon_service.as_str();
-    match icon_service {
-        "internal" | "bitwarden" | "duckduckgo" | "google" => (),
-        _ => {
-            if !icon_service.starts_with("http") {
-                err!(format!("Icon service URL `{icon_service}` must start with \"http\""))
-            }
-            match icon_service.matches("{}").count() {
-                1 => (), // nominal
-                0 => err!(format!("Icon service URL `{icon_service}` has no placeholder \"{{}}\"")),
-                _ => err!(format!("Icon service URL `{icon_service}` has more than one placeholder \"{{}}\"")),
-            }
-        }
-    }
+    validate_icon_service(&cfg.icon_service)?;
 
     // Check if the icon redirect code is valid
     match cfg.icon_redirect_code {
@@ -1242,6 +1674,137 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         err!("`AUTH_REQUEST_PURGE_SCHEDULE` is not a valid cron expression")
     }
 
+    if !cfg.device_cleanup_schedule.is_empty() && cfg.device_cleanup_schedule.parse::<Schedule>().is_err() {
+        err!("`DEVICE_CLEANUP_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.ldap_sync_schedule.is_empty() && cfg.ldap_sync_schedule.parse::<Schedule>().is_err() {
+        err!("`LDAP_SYNC_SCHEDULE` is not a valid cron expression")
+    }
+
+    if cfg.devices_days_retain.is_some_and(|days| days < 1) {
+        err!("`DEVICES_DAYS_RETAIN` must be at least 1")
+    }
+
+    if !cfg.unverified_users_purge_schedule.is_empty()
+        && cfg.unverified_users_purge_schedule.parse::<Schedule>().is_err()
+    {
+        err!("`UNVERIFIED_USERS_PURGE_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.two_factor_grace_period_schedule.is_empty()
+        && cfg.two_factor_grace_period_schedule.parse::<Schedule>().is_err()
+    {
+        err!("`TWO_FACTOR_GRACE_PERIOD_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.revision_reconcile_schedule.is_empty() && cfg.revision_reconcile_schedule.parse::<Schedule>().is_err() {
+        err!("`REVISION_RECONCILE_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.inactive_users_schedule.is_empty() && cfg.inactive_users_schedule.parse::<Schedule>().is_err() {
+        err!("`INACTIVE_USERS_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.audit_grant_expiry_schedule.is_empty() && cfg.audit_grant_expiry_schedule.parse::<Schedule>().is_err() {
+        err!("`AUDIT_GRANT_EXPIRY_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.attachment_quota_warning_schedule.is_empty()
+        && cfg.attachment_quota_warning_schedule.parse::<Schedule>().is_err()
+    {
+        err!("`ATTACHMENT_QUOTA_WARNING_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.attachment_rekey_verification_schedule.is_empty()
+        && cfg.attachment_rekey_verification_schedule.parse::<Schedule>().is_err()
+    {
+        err!("`ATTACHMENT_REKEY_VERIFICATION_SCHEDULE` is not a valid cron expression")
+    }
+
+    if cfg.audit_grant_max_hours < 1 {
+        err!("`AUDIT_GRANT_MAX_HOURS` must be at least 1")
+    }
+
+    if cfg.password_hint_max_length == Some(0) {
+        err!("`PASSWORD_HINT_MAX_LENGTH` must be at least 1")
+    }
+
+    if cfg.inactive_users_months.is_some_and(|months| months < 1) {
+        err!("`INACTIVE_USERS_MONTHS` must be at least 1")
+    }
+
+    if !["warn", "disable", "delete"].contains(&cfg.inactive_users_action.as_str()) {
+        err!("`INACTIVE_USERS_ACTION` must be one of `warn`, `disable` or `delete`")
+    }
+
+    if cfg.inactive_users_grace_days < 1 {
+        err!("`INACTIVE_USERS_GRACE_DAYS` must be at least 1")
+    }
+
+    if cfg.signups_verify_days_retain.is_some_and(|days| days < 1) {
+        err!("`SIGNUPS_VERIFY_DAYS_RETAIN` must be at least 1")
+    }
+
+    if cfg.support_access_hours < 1 {
+        err!("`SUPPORT_ACCESS_HOURS` must be at least 1")
+    }
+
+    if !cfg.tmp_cleanup_schedule.is_empty() && cfg.tmp_cleanup_schedule.parse::<Schedule>().is_err() {
+        err!("`TMP_CLEANUP_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.purge_incomplete_sso_auth.is_empty() && cfg.purge_incomplete_sso_auth.parse::<Schedule>().is_err() {
+        err!("`PURGE_INCOMPLETE_SSO_AUTH` is not a valid cron expression")
+    }
+
+    if !cfg.db_backup_schedule.is_empty() && cfg.db_backup_schedule.parse::<Schedule>().is_err() {
+        err!("`DB_BACKUP_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.usage_snapshot_schedule.is_empty() && cfg.usage_snapshot_schedule.parse::<Schedule>().is_err() {
+        err!("`USAGE_SNAPSHOT_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.usage_statistics_schedule.is_empty() && cfg.usage_statistics_schedule.parse::<Schedule>().is_err() {
+        err!("`USAGE_STATISTICS_SCHEDULE` is not a valid cron expression")
+    }
+
+    if let Some(ref url) = cfg.usage_statistics_url
+        && !(url.starts_with("https://") || url.starts_with("http://"))
+    {
+        err!("`USAGE_STATISTICS_URL` must be an http:// or https:// URL")
+    }
+
+    if !cfg.acme_renew_schedule.is_empty() && cfg.acme_renew_schedule.parse::<Schedule>().is_err() {
+        err!("`ACME_RENEW_SCHEDULE` is not a valid cron expression")
+    }
+
+    if cfg.usage_snapshot_days_retain < 1 {
+        err!("`USAGE_SNAPSHOT_DAYS_RETAIN` must be at least 1")
+    }
+
+    if cfg.org_cache_ttl > 3_600 {
+        err!("`ORG_CACHE_TTL` has a maximum of 3600 seconds")
+    }
+
+    if cfg.response_cache_ttl > 3_600 {
+        err!("`RESPONSE_CACHE_TTL` has a maximum of 3600 seconds")
+    }
+
+    if cfg.job_start_jitter > 86_400 {
+        err!("`JOB_START_JITTER` has a maximum of 86400 seconds")
+    }
+
+    if !(60..=604_800).contains(&cfg.job_lock_timeout) {
+        err!("`JOB_LOCK_TIMEOUT` must be between 60 and 604800 seconds")
+    }
+
+    // Uploads which are still being received live in the temp folder too, so don't allow a too aggressive cleanup
+    if cfg.tmp_cleanup_max_age < 3_600 {
+        err!("`TMP_CLEANUP_MAX_AGE` has a minimum duration of 3600 seconds")
+    }
+
     if !cfg.disable_admin_token {
         match cfg.admin_token.as_ref() {
             Some(t) if t.starts_with("$argon2") => {
@@ -1335,9 +1898,35 @@ fn generate_sso_callback_path(domain: &str) -> String {
     format!("{base_url}/identity/connect/oidc-signin")
 }
 
+/// Check if the icon service is valid, also used for the icon service of an organization
+pub fn validate_icon_service(icon_service: &str) -> Result<(), Error> {
+    match icon_service {
+        "internal" | "bitwarden" | "duckduckgo" | "google" => (),
+        _ => {
+            if !icon_service.starts_with("http") {
+                err!(format!("Icon service URL `{icon_service}` must start with \"http\""))
+            }
+            match icon_service.matches("{}").count() {
+                1 => (), // nominal
+                0 => err!(format!("Icon service URL `{icon_service}` has no placeholder \"{{}}\"")),
+                _ => err!(format!("Icon service URL `{icon_service}` has more than one placeholder \"{{}}\"")),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check if the webhook URL is valid, also used for the webhook of an organization
+pub fn validate_webhook_url(webhook_url: &str) -> Result<(), Error> {
+    match url::Url::parse(webhook_url) {
+        Ok(u) if matches!(u.scheme(), "http" | "https") && u.host().is_some() => Ok(()),
+        _ => err!(format!("Webhook URL `{webhook_url}` must be a valid http or https URL")),
+    }
+}
+
 /// Generate the correct URL for the icon service.
 /// This will be used within icons.rs to call the external icon service.
-fn generate_icon_service_url(icon_service: &str) -> String {
+pub fn generate_icon_service_url(icon_service: &str) -> String {
     match icon_service {
         "internal" => String::new(),
         "bitwarden" => "https://icons.bitwarden.net/{}/icon.png".to_owned(),
@@ -1385,6 +1974,24 @@ pub enum PathType {
     RsaKey,
 }
 
+impl PathType {
+    pub const ALL: [PathType; 5] =
+        [PathType::Data, PathType::IconCache, PathType::Attachments, PathType::Sends, PathType::RsaKey];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PathType::Data => "data",
+            PathType::IconCache => "icon_cache",
+            PathType::Attachments => "attachments",
+            PathType::Sends => "sends",
+            PathType::RsaKey => "rsa_key",
+        }
+    }
+}
+
+// Client types matched by `MIN_CLIENT_VERSIONS`, the official clients also use these as their `client_id`
+pub const CLIENT_TYPES: &[&str] = &["web", "browser", "desktop", "mobile", "cli"];
+
 // Official available feature flags can be found here:
 // Server (v2026.2.1): https://github.com/bitwarden/server/blob/0e42725d0837bd1c0dabd864ff621a579959744b/src/Core/Constants.cs#L135
 // Client (v2026.2.1): https://github.com/bitwarden/clients/blob/f96380c3138291a028bdd2c7a5fee540d5c98ba5/libs/common/src/enums/feature-flag.enum.ts#L12
@@ -1414,6 +2021,36 @@ pub const SUPPORTED_FEATURE_FLAGS: &[&str] = &[
     "pm-30529-webauthn-related-origins",
 ];
 
+/// A risky or deprecated combination of settings found in the live configuration
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigFinding {
+    pub id: &'static str,
+    pub severity: &'static str, // "danger" or "warning"
+    pub message: &'static str,
+    pub remediation: &'static str,
+}
+
+impl ConfigFinding {
+    const fn danger(id: &'static str, message: &'static str, remediation: &'static str) -> Self {
+        Self {
+            id,
+            severity: "danger",
+            message,
+            remediation,
+        }
+    }
+
+    const fn warning(id: &'static str, message: &'static str, remediation: &'static str) -> Self {
+        Self {
+            id,
+            severity: "warning",
+            message,
+            remediation,
+        }
+    }
+}
+
 impl Config {
     pub async fn load() -> Result<Self, Error> {
         // Loading from env and file
@@ -1433,7 +2070,7 @@ impl Config {
         Ok(Config {
             inner: RwLock::new(Inner {
                 rocket_shutdown_handle: None,
-                templates: load_templates(&config.templates_folder),
+                templates: (!config.low_memory).then(|| load_templates(&config.templates_folder)),
                 config,
                 _env: env,
                 _usr: usr,
@@ -1504,6 +2141,28 @@ impl Config {
         whitelist.is_empty() || whitelist.split(',').any(|d| d.trim() == email_domain)
     }
 
+    /// Returns the local part without the `+tag` and the domain of an email address,
+    /// when subaddress normalization is enabled for its domain
+    pub fn email_subaddress_base(&self, email: &str) -> Option<(String, String)> {
+        if !self.email_subaddress_normalization() {
+            return None;
+        }
+        let (local, domain) = email.rsplit_once('@')?;
+        let domain = domain.to_lowercase();
+        let rules = self.email_subaddress_domains();
+        let rules: Vec<&str> = rules.split(',').map(str::trim).filter(|r| !r.is_empty()).collect();
+        let mut included = rules.iter().filter(|r| !r.starts_with('!')).peekable();
+        let excluded = rules.iter().any(|r| r.strip_prefix('!') == Some(domain.as_str()));
+        if excluded || (included.peek().is_some() && !included.any(|r| *r == domain)) {
+            return None;
+        }
+        let base = local.split_once('+').map_or(local, |(base, _)| base);
+        if base.is_empty() {
+            return None;
+        }
+        Some((base.to_lowercase(), domain))
+    }
+
     /// Tests whether signup is allowed for an email address, taking into
     /// account the signups_allowed and signups_domains_whitelist settings.
     pub fn is_signup_allowed(&self, email: &str) -> bool {
@@ -1591,6 +2250,22 @@ impl Config {
         Url::parse(&self.domain()).expect("DOMAIN not a valid URL").domain().is_some()
     }
 
+    /// Tests whether the SHA-256 hash (hex) of a file is in `SEND_FILE_HASH_BLOCKLIST`
+    pub fn is_send_file_hash_blocked(&self, hash: &str) -> bool {
+        self.send_file_hash_blocklist().is_some_and(|blocklist| {
+            blocklist.split(',').any(|blocked| blocked.trim().eq_ignore_ascii_case(hash.trim()))
+        })
+    }
+
+    /// Stores a new admin token in the config file, which takes precedence over the environment.
+    pub async fn set_admin_token(&self, token: String) -> Result<(), Error> {
+        let builder = ConfigBuilder {
+            admin_token: Some(token),
+            ..Default::default()
+        };
+        self.update_config_partial(builder).await
+    }
+
     /// Tests whether the admin token is set to a non-empty value.
     pub fn is_admin_token_set(&self) -> bool {
         let token = self.admin_token();
@@ -1598,17 +2273,114 @@ impl Config {
         token.is_some() && !token.unwrap().trim().is_empty()
     }
 
-    pub fn opendal_operator_for_path_type(&self, path_type: &PathType) -> Result<opendal::Operator, Error> {
-        let path = match path_type {
+    /// Evaluates the current configuration for risky or deprecated settings.
+    /// Unlike `validate_config` these are allowed, but should be reviewed by the admin.
+    pub fn audit(&self) -> Vec<ConfigFinding> {
+        let mut findings = Vec::new();
+
+        let admin_enabled = self.disable_admin_token() || self.is_admin_token_set();
+        if admin_enabled && !self.domain().starts_with("https://") {
+            findings.push(ConfigFinding::danger(
+                "admin_without_https",
+                "The admin page is enabled, but `DOMAIN` is not an https:// URL, so the admin token can be sent unencrypted.",
+                "Serve Vaultwarden via HTTPS and set `DOMAIN` to the https:// URL, or unset `ADMIN_TOKEN` to disable the admin page.",
+            ));
+        }
+        if self.disable_admin_token() {
+            findings.push(ConfigFinding::danger(
+                "admin_without_token",
+                "`DISABLE_ADMIN_TOKEN` is enabled, so anyone who can reach the admin page can manage this instance.",
+                "Only use this behind a reverse proxy which authenticates the admin page, otherwise set an `ADMIN_TOKEN`.",
+            ));
+        } else if self.admin_token().is_some_and(|t| !t.trim().is_empty() && !t.starts_with("$argon2")) {
+            findings.push(ConfigFinding::warning(
+                "admin_token_plain_text",
+                "The `ADMIN_TOKEN` is stored as plain text.",
+                "Generate an Argon2 PHC string with `vaultwarden hash` and use that as `ADMIN_TOKEN`.",
+            ));
+        }
+
+        let signups_open = self.signups_allowed() || !self.signups_domains_whitelist().is_empty();
+        if signups_open && !self.mail_enabled() {
+            findings.push(ConfigFinding::warning(
+                "signups_without_mail",
+                "Signups are open, but mail is not configured, so the email address of new accounts can't be verified.",
+                "Configure SMTP and enable `SIGNUPS_VERIFY`, or disable `SIGNUPS_ALLOWED` and invite users instead.",
+            ));
+        } else if signups_open && !self.signups_verify() {
+            findings.push(ConfigFinding::warning(
+                "signups_without_verification",
+                "Signups are open without verification of the email address.",
+                "Enable `SIGNUPS_VERIFY`, or restrict the signups with `SIGNUPS_DOMAINS_WHITELIST`.",
+            ));
+        }
+        if self.show_password_hint() && self.password_hints_allowed() {
+            findings.push(ConfigFinding::warning(
+                "password_hint_shown",
+                "`SHOW_PASSWORD_HINT` is enabled, so anyone who knows an email address can see its password hint.",
+                "Disable `SHOW_PASSWORD_HINT`, the hints are then only sent by mail.",
+            ));
+        }
+
+        if self.icon_service() == "internal" && !self.http_request_block_non_global_ips() {
+            findings.push(ConfigFinding::danger(
+                "icons_from_private_ips",
+                "Icons can be fetched from non-global IP addresses, which allows users to probe the local network of the server.",
+                "Enable `HTTP_REQUEST_BLOCK_NON_GLOBAL_IPS`, and use `HTTP_REQUEST_BLOCK_REGEX` for any other internal hosts.",
+            ));
+        }
+        if self.smtp_accept_invalid_certs() || self.smtp_accept_invalid_hostnames() {
+            findings.push(ConfigFinding::warning(
+                "smtp_invalid_certs",
+                "Invalid certificates or hostnames of the SMTP server are accepted, which allows the mail to be intercepted.",
+                "Disable `SMTP_ACCEPT_INVALID_CERTS` and `SMTP_ACCEPT_INVALID_HOSTNAMES`, and use a valid certificate.",
+            ));
+        }
+
+        if self.icon_blacklist_regex().is_some() {
+            findings.push(ConfigFinding::warning(
+                "deprecated_icon_blacklist_regex",
+                "The deprecated `ICON_BLACKLIST_REGEX` is set.",
+                "Use `HTTP_REQUEST_BLOCK_REGEX` instead, which also applies to the other requests of the server.",
+            ));
+        }
+        if self.smtp_ssl().is_some() || self.smtp_explicit_tls().is_some() {
+            findings.push(ConfigFinding::warning(
+                "deprecated_smtp_ssl",
+                "The deprecated `SMTP_SSL` or `SMTP_EXPLICIT_TLS` is set.",
+                "Use `SMTP_SECURITY` instead, with either \"starttls\", \"force_tls\" or \"off\".",
+            ));
+        }
+
+        findings
+    }
+
+    fn path_for_path_type(&self, path_type: &PathType) -> Result<String, Error> {
+        Ok(match path_type {
             PathType::Data => self.data_folder(),
             PathType::IconCache => self.icon_cache_folder(),
             PathType::Attachments => self.attachments_folder(),
             PathType::Sends => self.sends_folder(),
             PathType::RsaKey => storage::parent(&self.private_rsa_key())
                 .ok_or_else(|| std::io::Error::other("Failed to get directory of RSA key file"))?,
-        };
+        })
+    }
+
+    pub fn storage_backend(&self, path_type: &PathType) -> Result<Arc<dyn storage::StorageBackend>, Error> {
+        storage::backend_for_path(&self.path_for_path_type(path_type)?)
+    }
 
-        storage::operator_for_path(&path)
+    /// Runs the health check of every configured storage backend, used by the readiness probe
+    pub async fn storage_health(&self) -> Vec<(PathType, &'static str, Result<(), Error>)> {
+        let mut results = Vec::with_capacity(PathType::ALL.len());
+        for path_type in PathType::ALL {
+            let result = match self.storage_backend(&path_type) {
+                Ok(backend) => (path_type, backend.kind(), backend.health_check().await),
+                Err(e) => (path_type, "unknown", Err(e)),
+            };
+            results.push(result);
+        }
+        results
     }
 
     pub fn render_template<T: serde::ser::Serialize>(&self, name: &str, data: &T) -> Result<String, Error> {
@@ -1617,14 +2389,21 @@ impl Config {
             let hb = load_templates(CONFIG.templates_folder());
             hb.render(name, data).map_err(Into::into)
         } else {
-            let hb = &self.inner.read().unwrap().templates;
-            hb.render(name, data).map_err(Into::into)
+            self.with_templates(|hb| hb.render(name, data).map_err(Into::into))
         }
     }
 
     pub fn render_fallback_template<T: serde::ser::Serialize>(&self, name: &str, data: &T) -> Result<String, Error> {
-        let hb = &self.inner.read().unwrap().templates;
-        hb.render(&format!("fallback_{name}"), data).map_err(Into::into)
+        self.with_templates(|hb| hb.render(&format!("fallback_{name}"), data).map_err(Into::into))
+    }
+
+    fn with_templates<R>(&self, f: impl FnOnce(&Handlebars<'static>) -> R) -> R {
+        if let Some(hb) = &self.inner.read().unwrap().templates {
+            return f(hb);
+        }
+        let mut inner = self.inner.write().unwrap();
+        let templates_folder = inner.config.templates_folder.clone();
+        f(inner.templates.get_or_insert_with(|| load_templates(templates_folder)))
     }
 
     pub fn set_rocket_shutdown_handle(&self, handle: rocket::Shutdown) {
@@ -1658,6 +2437,18 @@ impl Config {
     pub fn sso_authorize_extra_params_vec(&self) -> Vec<(String, String)> {
         url::form_urlencoded::parse(self.sso_authorize_extra_params().as_bytes()).into_owned().collect()
     }
+
+    /// Returns the minimum version configured for a client type, if any
+    pub fn min_client_version(&self, client_type: &str) -> Option<semver::Version> {
+        self.min_client_versions().split(',').find_map(|entry| {
+            let (t, version) = entry.split_once('=')?;
+            if t.trim() == client_type {
+                semver::Version::parse(version.trim()).ok()
+            } else {
+                None
+            }
+        })
+    }
 }
 
 use handlebars::{
@@ -1699,7 +2490,9 @@ where
     reg!("email/email_footer");
     reg!("email/email_footer_text");
 
+    reg!("email/admin_lockout", ".html");
     reg!("email/admin_reset_password", ".html");
+    reg!("email/attachment_quota_warning", ".html");
     reg!("email/change_email_existing", ".html");
     reg!("email/change_email_invited", ".html");
     reg!("email/change_email", ".html");
@@ -1711,6 +2504,7 @@ where
     reg!("email/emergency_access_recovery_rejected", ".html");
     reg!("email/emergency_access_recovery_reminder", ".html");
     reg!("email/emergency_access_recovery_timed_out", ".html");
+    reg!("email/inactive_account_warning", ".html");
     reg!("email/incomplete_2fa_login", ".html");
     reg!("email/invite_accepted", ".html");
     reg!("email/invite_confirmed", ".html");
@@ -1719,6 +2513,9 @@ where
     reg!("email/pw_hint_none", ".html");
     reg!("email/pw_hint_some", ".html");
     reg!("email/register_verify_email", ".html");
+    reg!("email/security_change_pending", ".html");
+    reg!("email/member_offboarded", ".html");
+    reg!("email/send_2fa_grace_period", ".html");
     reg!("email/send_2fa_removed_from_org", ".html");
     reg!("email/send_emergency_access_invite", ".html");
     reg!("email/send_org_invite", ".html");
@@ -1735,6 +2532,7 @@ where
     reg!("admin/settings");
     reg!("admin/users");
     reg!("admin/organizations");
+    reg!("admin/ldap");
     reg!("admin/diagnostics");
 
     reg!("404");
@@ -1,10 +1,10 @@
 //
 // PBKDF2 derivation
 //
-use std::num::NonZeroU32;
+use std::{num::NonZeroU32, sync::OnceLock};
 
-use data_encoding::{Encoding, HEXLOWER};
-use ring::{digest, hmac, pbkdf2};
+use data_encoding::{BASE64, Encoding, HEXLOWER};
+use ring::{aead, digest, hmac, pbkdf2};
 
 const DIGEST_ALG: pbkdf2::Algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
 const OUTPUT_LEN: usize = digest::SHA256_OUTPUT_LEN;
@@ -33,6 +33,62 @@ pub fn hmac_sign(key: &str, data: &str) -> String {
     HEXLOWER.encode(signature.as_ref())
 }
 
+pub fn hmac_sha256_sign(key: &str, data: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes());
+    let signature = hmac::sign(&key, data.as_bytes());
+
+    HEXLOWER.encode(signature.as_ref())
+}
+
+//
+// Secrets at rest
+//
+static SECRETS_KEY: OnceLock<aead::LessSafeKey> = OnceLock::new();
+
+// Prefix of the encrypted secrets, in case the format ever needs to change
+const SECRET_PREFIX: &str = "1.";
+
+/// Derives the key of the secrets which the server needs in plain text, like the secrets of the webhooks,
+/// from the private RSA key. That key isn't stored in the database, so a database dump doesn't reveal the secrets.
+pub fn init_secrets_key(rsa_key: &[u8]) {
+    let key = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, rsa_key), b"vaultwarden secrets at rest");
+    let key = aead::UnboundKey::new(&aead::AES_256_GCM, key.as_ref()).expect("Error creating the secrets key");
+    // Only the first key is used, like the RSA keys
+    SECRETS_KEY.set(aead::LessSafeKey::new(key)).ok();
+}
+
+fn seal_secret(key: &aead::LessSafeKey, secret: &str) -> String {
+    let nonce = get_random_bytes::<{ aead::NONCE_LEN }>();
+    let mut data = secret.as_bytes().to_vec();
+    key.seal_in_place_append_tag(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut data)
+        .expect("Error encrypting the secret");
+
+    let mut sealed = nonce.to_vec();
+    sealed.append(&mut data);
+    format!("{SECRET_PREFIX}{}", BASE64.encode(&sealed))
+}
+
+fn open_secret(key: &aead::LessSafeKey, sealed: &str) -> Option<String> {
+    let sealed = BASE64.decode(sealed.strip_prefix(SECRET_PREFIX)?.as_bytes()).ok()?;
+    if sealed.len() < aead::NONCE_LEN {
+        return None;
+    }
+    let (nonce, data) = sealed.split_at(aead::NONCE_LEN);
+    let nonce = aead::Nonce::try_assume_unique_for_key(nonce).ok()?;
+    let mut data = data.to_vec();
+    let secret = key.open_in_place(nonce, aead::Aad::empty(), &mut data).ok()?;
+    String::from_utf8(secret.to_vec()).ok()
+}
+
+pub fn encrypt_secret(secret: &str) -> String {
+    seal_secret(SECRETS_KEY.get().expect("The secrets key is not initialized"), secret)
+}
+
+/// Returns `None` when the secret can't be decrypted, for example because the private RSA key was replaced
+pub fn decrypt_secret(sealed: &str) -> Option<String> {
+    open_secret(SECRETS_KEY.get()?, sealed)
+}
+
 //
 // Random values
 //
@@ -120,3 +176,64 @@ pub fn ct_eq<T: AsRef<[u8]>, U: AsRef<[u8]>>(a: T, b: U) -> bool {
 pub fn sha256_hex(data: &[u8]) -> String {
     HEXLOWER.encode(digest::digest(&digest::SHA256, data).as_ref())
 }
+
+/// Same as `sha256_hex`, but reads the data from a file without loading it in memory
+pub async fn sha256_hex_file(path: &std::path::Path) -> std::io::Result<String> {
+    use tokio::io::AsyncReadExt as _;
+
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut context = digest::Context::new(&digest::SHA256);
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
+    }
+    Ok(HEXLOWER.encode(context.finish().as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_key(material: &[u8]) -> aead::LessSafeKey {
+        let key = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, material), b"test");
+        aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, key.as_ref()).unwrap())
+    }
+
+    #[test]
+    fn test_hmac_sha256_sign() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            hmac_sha256_sign("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_secret_roundtrip() {
+        let key = test_key(b"first");
+        let sealed = seal_secret(&key, "webhook secret");
+        assert!(sealed.starts_with(SECRET_PREFIX));
+        assert!(!sealed.contains("webhook secret"));
+        assert_eq!(open_secret(&key, &sealed).as_deref(), Some("webhook secret"));
+        // Every encryption uses a new nonce
+        assert_ne!(seal_secret(&key, "webhook secret"), sealed);
+    }
+
+    #[test]
+    fn test_secret_rejected() {
+        let sealed = seal_secret(&test_key(b"first"), "webhook secret");
+        assert_eq!(open_secret(&test_key(b"second"), &sealed), None);
+
+        let key = test_key(b"first");
+        let mut tampered = BASE64.decode(sealed[SECRET_PREFIX.len()..].as_bytes()).unwrap();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert_eq!(open_secret(&key, &format!("{SECRET_PREFIX}{}", BASE64.encode(&tampered))), None);
+        assert_eq!(open_secret(&key, "webhook secret"), None);
+        assert_eq!(open_secret(&key, "1.AAAA"), None);
+    }
+}
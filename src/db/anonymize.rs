@@ -0,0 +1,912 @@
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde_json::{Map, Value};
+
+use crate::{
+    api::ApiResult,
+    crypto,
+    db::{
+        DbConn, DbConnInner,
+        schema::{
+            admin_audit_log, archives, attachment_rekeys, attachment_shares, attachments, audit_grants, auth_requests,
+            cipher_templates, cipher_user_changes, ciphers, ciphers_collections, collections, collections_groups,
+            devices, emergency_access, favorites, folders, folders_ciphers, global_domain_overrides, groups,
+            groups_users, invitations, login_fingerprints, org_domains, org_policies, organization_api_key,
+            organizations, quota_warnings, send_egress, sends, sso_users, tombstones, twofactor, usage_snapshots,
+            users, users_collections, users_organizations,
+        },
+    },
+    error::MapResult,
+    util::format_date,
+};
+
+// Anonymized copy of the database, which users can attach to bug reports.
+// All ids and the relations between the rows are kept, as well as the number of rows and the settings which change the behavior.
+// Emails are replaced by a salted hash, the same address always gives the same hash within one dump, but can't be looked up.
+// Names are replaced by random ones, encrypted strings keep their type and number of parts, but their content is truncated.
+// Secrets which are not needed to reproduce an issue, like password hashes, tokens and two-step login data, are left out.
+// Every table is dumped, except for the ones below which only hold short-lived login state, locks or the event log.
+
+/// The tables which are not part of the dump
+pub const EXCLUDED_TABLES: &[&str] =
+    &["event", "job_locks", "send_download_tokens", "sso_auth", "twofactor_duo_ctx", "twofactor_incomplete"];
+
+struct Anonymizer {
+    salt: String,
+}
+
+impl Anonymizer {
+    /// A salted hash of a value which identifies someone or something outside of Vaultwarden
+    fn hash(&self, value: &str) -> String {
+        let hash = crypto::sha256_hex(format!("{}{}", self.salt, value.to_lowercase()).as_bytes());
+        hash[..16].to_owned()
+    }
+
+    fn email(&self, email: &str) -> String {
+        format!("{}@example.invalid", self.hash(email))
+    }
+
+    fn opt_email(&self, email: Option<&str>) -> Option<String> {
+        email.map(|e| self.email(e))
+    }
+
+    fn name(prefix: &str) -> String {
+        format!("{prefix} {}", crypto::get_random_string_alphanum(8))
+    }
+
+    /// Keeps the type and the number of parts of an encrypted string, only the first characters of each part are kept
+    fn enc(value: &str) -> String {
+        match value.split_once('.') {
+            Some((enc_type, data)) => {
+                let parts: Vec<String> = data.split('|').map(|p| p.chars().take(4).collect()).collect();
+                format!("{enc_type}.{}", parts.join("|"))
+            }
+            None => String::new(),
+        }
+    }
+
+    fn opt_enc(value: Option<&str>) -> Option<String> {
+        value.map(Self::enc)
+    }
+
+    /// Truncates all the encrypted strings nested in a json document, the dates are kept as they are
+    fn enc_json(value: &str) -> Value {
+        fn walk(value: Value) -> Value {
+            match value {
+                Value::String(s) => Value::String(Anonymizer::enc(&s)),
+                Value::Array(values) => Value::Array(values.into_iter().map(walk).collect()),
+                Value::Object(map) => Value::Object(
+                    map.into_iter()
+                        .map(|(key, v)| {
+                            let v = if key.ends_with("Date") {
+                                v
+                            } else {
+                                walk(v)
+                            };
+                            (key, v)
+                        })
+                        .collect(),
+                ),
+                v => v,
+            }
+        }
+        serde_json::from_str(value).map(walk).unwrap_or(Value::Null)
+    }
+}
+
+fn opt_date(dt: Option<&NaiveDateTime>) -> Option<String> {
+    dt.map(format_date)
+}
+
+/// Creates the anonymized dump of the database as a json document
+pub async fn create_dump(conn: &DbConn) -> ApiResult<Value> {
+    let schema = super::integrity::schema_report(conn).await;
+    let tables = conn.run(dump_tables).await.map_res("Error reading the database")?;
+
+    Ok(json!({
+        "createdAt": format_date(&Utc::now().naive_utc()),
+        "version": crate::VERSION,
+        "schema": schema,
+        "tables": tables,
+        "excludedTables": EXCLUDED_TABLES,
+    }))
+}
+
+fn dump_tables(conn: &mut DbConnInner) -> QueryResult<Map<String, Value>> {
+    let a = Anonymizer {
+        salt: crypto::encode_random_bytes::<16>(&data_encoding::HEXLOWER),
+    };
+    let mut tables = Map::new();
+
+    let rows = users::table
+        .select((
+            users::uuid,
+            users::enabled,
+            users::created_at,
+            users::updated_at,
+            users::verified_at,
+            users::email,
+            users::password_hint,
+            users::akey,
+            users::private_key,
+            users::client_kdf_type,
+            users::client_kdf_iter,
+            users::client_kdf_memory,
+            users::client_kdf_parallelism,
+            users::password_hash,
+            users::last_login_at,
+        ))
+        .load::<(
+            String,
+            bool,
+            NaiveDateTime,
+            NaiveDateTime,
+            Option<NaiveDateTime>,
+            String,
+            Option<String>,
+            String,
+            Option<String>,
+            i32,
+            i32,
+            Option<i32>,
+            Option<i32>,
+            Vec<u8>,
+            Option<NaiveDateTime>,
+        )>(conn)?;
+    tables.insert(
+        "users".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "uuid": r.0,
+                    "enabled": r.1,
+                    "createdAt": format_date(&r.2),
+                    "updatedAt": format_date(&r.3),
+                    "verifiedAt": opt_date(r.4.as_ref()),
+                    "email": a.email(&r.5),
+                    "name": Anonymizer::name("User"),
+                    "hasPasswordHint": r.6.is_some(),
+                    "akey": Anonymizer::enc(&r.7),
+                    "privateKey": Anonymizer::opt_enc(r.8.as_deref()),
+                    "kdfType": r.9,
+                    "kdfIterations": r.10,
+                    "kdfMemory": r.11,
+                    "kdfParallelism": r.12,
+                    "hasMasterPassword": !r.13.is_empty(),
+                    "lastLoginAt": opt_date(r.14.as_ref()),
+                })
+            })
+            .collect(),
+    );
+
+    let rows = organizations::table
+        .select((organizations::uuid, organizations::billing_email, organizations::private_key))
+        .load::<(String, String, Option<String>)>(conn)?;
+    tables.insert(
+        "organizations".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "uuid": r.0,
+                    "name": Anonymizer::name("Organization"),
+                    "billingEmail": a.email(&r.1),
+                    "privateKey": Anonymizer::opt_enc(r.2.as_deref()),
+                })
+            })
+            .collect(),
+    );
+
+    let rows = users_organizations::table
+        .select((
+            users_organizations::uuid,
+            users_organizations::user_uuid,
+            users_organizations::org_uuid,
+            users_organizations::invited_by_email,
+            users_organizations::access_all,
+            users_organizations::akey,
+            users_organizations::status,
+            users_organizations::atype,
+            users_organizations::reset_password_key,
+        ))
+        .load::<(String, String, String, Option<String>, bool, String, i32, i32, Option<String>)>(conn)?;
+    tables.insert(
+        "users_organizations".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "uuid": r.0,
+                    "userUuid": r.1,
+                    "orgUuid": r.2,
+                    "invitedByEmail": a.opt_email(r.3.as_deref()),
+                    "accessAll": r.4,
+                    "akey": Anonymizer::enc(&r.5),
+                    "status": r.6,
+                    "type": r.7,
+                    "hasResetPasswordKey": r.8.is_some(),
+                })
+            })
+            .collect(),
+    );
+
+    let rows = collections::table
+        .select((collections::uuid, collections::org_uuid, collections::name, collections::onboarding))
+        .load::<(String, String, String, bool)>(conn)?;
+    tables.insert(
+        "collections".into(),
+        rows.iter()
+            .map(|r| json!({ "uuid": r.0, "orgUuid": r.1, "name": Anonymizer::enc(&r.2), "onboarding": r.3 }))
+            .collect(),
+    );
+
+    let rows = users_collections::table
+        .select((
+            users_collections::user_uuid,
+            users_collections::collection_uuid,
+            users_collections::read_only,
+            users_collections::hide_passwords,
+            users_collections::manage,
+        ))
+        .load::<(String, String, bool, bool, bool)>(conn)?;
+    tables.insert(
+        "users_collections".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "userUuid": r.0,
+                    "collectionUuid": r.1,
+                    "readOnly": r.2,
+                    "hidePasswords": r.3,
+                    "manage": r.4,
+                })
+            })
+            .collect(),
+    );
+
+    let rows =
+        groups::table
+            .select((groups::uuid, groups::organizations_uuid, groups::access_all))
+            .load::<(String, String, bool)>(conn)?;
+    tables.insert(
+        "groups".into(),
+        rows.iter()
+            .map(|r| json!({ "uuid": r.0, "orgUuid": r.1, "name": Anonymizer::name("Group"), "accessAll": r.2 }))
+            .collect(),
+    );
+
+    let rows = groups_users::table
+        .select((groups_users::groups_uuid, groups_users::users_organizations_uuid))
+        .load::<(String, String)>(conn)?;
+    tables.insert(
+        "groups_users".into(),
+        rows.iter().map(|r| json!({ "groupUuid": r.0, "membershipUuid": r.1 })).collect(),
+    );
+
+    let rows = collections_groups::table
+        .select((
+            collections_groups::collections_uuid,
+            collections_groups::groups_uuid,
+            collections_groups::read_only,
+            collections_groups::hide_passwords,
+            collections_groups::manage,
+        ))
+        .load::<(String, String, bool, bool, bool)>(conn)?;
+    tables.insert(
+        "collections_groups".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "collectionUuid": r.0,
+                    "groupUuid": r.1,
+                    "readOnly": r.2,
+                    "hidePasswords": r.3,
+                    "manage": r.4,
+                })
+            })
+            .collect(),
+    );
+
+    let rows = ciphers::table
+        .select((
+            ciphers::uuid,
+            ciphers::created_at,
+            ciphers::updated_at,
+            ciphers::user_uuid,
+            ciphers::organization_uuid,
+            ciphers::key,
+            ciphers::atype,
+            ciphers::name,
+            ciphers::notes,
+            ciphers::fields,
+            ciphers::data,
+            ciphers::deleted_at,
+            ciphers::reprompt,
+        ))
+        .load::<(
+            String,
+            NaiveDateTime,
+            NaiveDateTime,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            i32,
+            String,
+            Option<String>,
+            Option<String>,
+            String,
+            Option<NaiveDateTime>,
+            Option<i32>,
+        )>(conn)?;
+    tables.insert(
+        "ciphers".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "uuid": r.0,
+                    "createdAt": format_date(&r.1),
+                    "updatedAt": format_date(&r.2),
+                    "userUuid": r.3,
+                    "organizationUuid": r.4,
+                    "key": Anonymizer::opt_enc(r.5.as_deref()),
+                    "type": r.6,
+                    "name": Anonymizer::enc(&r.7),
+                    "notes": Anonymizer::opt_enc(r.8.as_deref()),
+                    "fields": r.9.as_deref().map(Anonymizer::enc_json),
+                    "data": Anonymizer::enc_json(&r.10),
+                    "deletedAt": opt_date(r.11.as_ref()),
+                    "reprompt": r.12,
+                })
+            })
+            .collect(),
+    );
+
+    let rows = ciphers_collections::table
+        .select((ciphers_collections::cipher_uuid, ciphers_collections::collection_uuid))
+        .load::<(String, String)>(conn)?;
+    tables.insert(
+        "ciphers_collections".into(),
+        rows.iter().map(|r| json!({ "cipherUuid": r.0, "collectionUuid": r.1 })).collect(),
+    );
+
+    let rows = folders::table
+        .select((folders::uuid, folders::user_uuid, folders::name))
+        .load::<(String, String, String)>(conn)?;
+    tables.insert(
+        "folders".into(),
+        rows.iter().map(|r| json!({ "uuid": r.0, "userUuid": r.1, "name": Anonymizer::enc(&r.2) })).collect(),
+    );
+
+    let rows =
+        folders_ciphers::table
+            .select((folders_ciphers::cipher_uuid, folders_ciphers::folder_uuid))
+            .load::<(String, String)>(conn)?;
+    tables.insert(
+        "folders_ciphers".into(),
+        rows.iter().map(|r| json!({ "cipherUuid": r.0, "folderUuid": r.1 })).collect(),
+    );
+
+    let rows =
+        favorites::table.select((favorites::user_uuid, favorites::cipher_uuid)).load::<(String, String)>(conn)?;
+    tables.insert("favorites".into(), rows.iter().map(|r| json!({ "userUuid": r.0, "cipherUuid": r.1 })).collect());
+
+    let rows = attachments::table
+        .select((
+            attachments::id,
+            attachments::cipher_uuid,
+            attachments::file_name,
+            attachments::file_size,
+            attachments::created_at,
+        ))
+        .load::<(String, String, String, i64, Option<NaiveDateTime>)>(conn)?;
+    tables.insert(
+        "attachments".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "id": r.0,
+                    "cipherUuid": r.1,
+                    "fileName": Anonymizer::enc(&r.2),
+                    "fileSize": r.3,
+                    "createdAt": opt_date(r.4.as_ref()),
+                })
+            })
+            .collect(),
+    );
+
+    let rows = devices::table
+        .select((
+            devices::uuid,
+            devices::user_uuid,
+            devices::created_at,
+            devices::updated_at,
+            devices::atype,
+            devices::push_token,
+            devices::trusted,
+            devices::blocked,
+        ))
+        .load::<(String, String, NaiveDateTime, NaiveDateTime, i32, Option<String>, bool, bool)>(conn)?;
+    tables.insert(
+        "devices".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "uuid": r.0,
+                    "userUuid": r.1,
+                    "createdAt": format_date(&r.2),
+                    "updatedAt": format_date(&r.3),
+                    "name": Anonymizer::name("Device"),
+                    "type": r.4,
+                    "hasPushToken": r.5.is_some(),
+                    "trusted": r.6,
+                    "blocked": r.7,
+                })
+            })
+            .collect(),
+    );
+
+    let rows = sends::table
+        .select((
+            sends::uuid,
+            sends::user_uuid,
+            sends::organization_uuid,
+            sends::atype,
+            sends::name,
+            sends::data,
+            sends::password_hash,
+            sends::access_count,
+            sends::max_access_count,
+            sends::deletion_date,
+            sends::disabled,
+        ))
+        .load::<(
+            String,
+            Option<String>,
+            Option<String>,
+            i32,
+            String,
+            String,
+            Option<Vec<u8>>,
+            i32,
+            Option<i32>,
+            NaiveDateTime,
+            bool,
+        )>(conn)?;
+    tables.insert(
+        "sends".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "uuid": r.0,
+                    "userUuid": r.1,
+                    "organizationUuid": r.2,
+                    "type": r.3,
+                    "name": Anonymizer::enc(&r.4),
+                    "data": Anonymizer::enc_json(&r.5),
+                    "hasPassword": r.6.is_some(),
+                    "accessCount": r.7,
+                    "maxAccessCount": r.8,
+                    "deletionDate": format_date(&r.9),
+                    "disabled": r.10,
+                })
+            })
+            .collect(),
+    );
+
+    let rows = twofactor::table
+        .select((twofactor::uuid, twofactor::user_uuid, twofactor::atype, twofactor::enabled))
+        .load::<(String, String, i32, bool)>(conn)?;
+    tables.insert(
+        "twofactor".into(),
+        rows.iter().map(|r| json!({ "uuid": r.0, "userUuid": r.1, "type": r.2, "enabled": r.3 })).collect(),
+    );
+
+    // The policy settings don't contain anything personal, and often are what changes the behavior
+    let rows = org_policies::table
+        .select((
+            org_policies::uuid,
+            org_policies::org_uuid,
+            org_policies::atype,
+            org_policies::enabled,
+            org_policies::data,
+        ))
+        .load::<(String, String, i32, bool, String)>(conn)?;
+    tables.insert(
+        "org_policies".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "uuid": r.0,
+                    "orgUuid": r.1,
+                    "type": r.2,
+                    "enabled": r.3,
+                    "data": serde_json::from_str::<Value>(&r.4).unwrap_or(Value::Null),
+                })
+            })
+            .collect(),
+    );
+
+    let rows = emergency_access::table
+        .select((
+            emergency_access::uuid,
+            emergency_access::grantor_uuid,
+            emergency_access::grantee_uuid,
+            emergency_access::email,
+            emergency_access::key_encrypted,
+            emergency_access::atype,
+            emergency_access::status,
+            emergency_access::wait_time_days,
+            emergency_access::recovery_initiated_at,
+            emergency_access::last_notification_at,
+            emergency_access::updated_at,
+            emergency_access::created_at,
+        ))
+        .load::<(
+            String,
+            String,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            i32,
+            i32,
+            i32,
+            Option<NaiveDateTime>,
+            Option<NaiveDateTime>,
+            NaiveDateTime,
+            NaiveDateTime,
+        )>(conn)?;
+    tables.insert(
+        "emergency_access".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "uuid": r.0,
+                    "grantorUuid": r.1,
+                    "granteeUuid": r.2,
+                    "email": a.opt_email(r.3.as_deref()),
+                    "keyEncrypted": Anonymizer::opt_enc(r.4.as_deref()),
+                    "type": r.5,
+                    "status": r.6,
+                    "waitTimeDays": r.7,
+                    "recoveryInitiatedAt": opt_date(r.8.as_ref()),
+                    "lastNotificationAt": opt_date(r.9.as_ref()),
+                    "updatedAt": format_date(&r.10),
+                    "createdAt": format_date(&r.11),
+                })
+            })
+            .collect(),
+    );
+
+    let rows = sso_users::table.select((sso_users::user_uuid, sso_users::identifier)).load::<(String, String)>(conn)?;
+    tables.insert(
+        "sso_users".into(),
+        rows.iter().map(|r| json!({ "userUuid": r.0, "identifier": a.hash(&r.1) })).collect(),
+    );
+
+    let rows = organization_api_key::table
+        .select((
+            organization_api_key::uuid,
+            organization_api_key::org_uuid,
+            organization_api_key::atype,
+            organization_api_key::revision_date,
+        ))
+        .load::<(String, String, i32, NaiveDateTime)>(conn)?;
+    tables.insert(
+        "organization_api_key".into(),
+        rows.iter()
+            .map(|r| json!({ "uuid": r.0, "orgUuid": r.1, "type": r.2, "revisionDate": format_date(&r.3) }))
+            .collect(),
+    );
+
+    let rows = invitations::table.select(invitations::email).load::<String>(conn)?;
+    tables.insert("invitations".into(), rows.iter().map(|r| json!({ "email": a.email(r) })).collect());
+
+    let rows = auth_requests::table
+        .select((
+            auth_requests::uuid,
+            auth_requests::user_uuid,
+            auth_requests::organization_uuid,
+            auth_requests::device_type,
+            auth_requests::approved,
+            auth_requests::creation_date,
+            auth_requests::response_date,
+            auth_requests::authentication_date,
+        ))
+        .load::<(
+            String,
+            String,
+            Option<String>,
+            i32,
+            Option<bool>,
+            NaiveDateTime,
+            Option<NaiveDateTime>,
+            Option<NaiveDateTime>,
+        )>(conn)?;
+    tables.insert(
+        "auth_requests".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "uuid": r.0,
+                    "userUuid": r.1,
+                    "organizationUuid": r.2,
+                    "deviceType": r.3,
+                    "approved": r.4,
+                    "creationDate": format_date(&r.5),
+                    "responseDate": opt_date(r.6.as_ref()),
+                    "authenticationDate": opt_date(r.7.as_ref()),
+                })
+            })
+            .collect(),
+    );
+
+    let rows =
+        archives::table.select((archives::user_uuid, archives::cipher_uuid, archives::archived_at)).load::<(
+            String,
+            String,
+            NaiveDateTime,
+        )>(conn)?;
+    tables.insert(
+        "archives".into(),
+        rows.iter().map(|r| json!({ "userUuid": r.0, "cipherUuid": r.1, "archivedAt": format_date(&r.2) })).collect(),
+    );
+
+    let rows = audit_grants::table
+        .select((
+            audit_grants::user_uuid,
+            audit_grants::collection_uuid,
+            audit_grants::org_uuid,
+            audit_grants::granted_by_uuid,
+            audit_grants::created_at,
+            audit_grants::expires_at,
+        ))
+        .load::<(String, String, String, String, NaiveDateTime, NaiveDateTime)>(conn)?;
+    tables.insert(
+        "audit_grants".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "userUuid": r.0,
+                    "collectionUuid": r.1,
+                    "orgUuid": r.2,
+                    "grantedByUuid": r.3,
+                    "createdAt": format_date(&r.4),
+                    "expiresAt": format_date(&r.5),
+                })
+            })
+            .collect(),
+    );
+
+    let rows = org_domains::table
+        .select((
+            org_domains::uuid,
+            org_domains::org_uuid,
+            org_domains::domain_name,
+            org_domains::created_at,
+            org_domains::verified_at,
+            org_domains::last_checked_at,
+        ))
+        .load::<(String, String, String, NaiveDateTime, Option<NaiveDateTime>, Option<NaiveDateTime>)>(conn)?;
+    tables.insert(
+        "org_domains".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "uuid": r.0,
+                    "orgUuid": r.1,
+                    "domainName": format!("{}.invalid", a.hash(&r.2)),
+                    "createdAt": format_date(&r.3),
+                    "verifiedAt": opt_date(r.4.as_ref()),
+                    "lastCheckedAt": opt_date(r.5.as_ref()),
+                })
+            })
+            .collect(),
+    );
+
+    let rows = attachment_shares::table
+        .select((
+            attachment_shares::attachment_id,
+            attachment_shares::cipher_uuid,
+            attachment_shares::user_uuid,
+            attachment_shares::created_at,
+            attachment_shares::expires_at,
+            attachment_shares::used_at,
+        ))
+        .load::<(String, String, String, NaiveDateTime, NaiveDateTime, Option<NaiveDateTime>)>(conn)?;
+    tables.insert(
+        "attachment_shares".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "attachmentId": r.0,
+                    "cipherUuid": r.1,
+                    "userUuid": r.2,
+                    "createdAt": format_date(&r.3),
+                    "expiresAt": format_date(&r.4),
+                    "usedAt": opt_date(r.5.as_ref()),
+                })
+            })
+            .collect(),
+    );
+
+    let rows = attachment_rekeys::table
+        .select((
+            attachment_rekeys::attachment_id,
+            attachment_rekeys::cipher_uuid,
+            attachment_rekeys::user_uuid,
+            attachment_rekeys::legacy,
+            attachment_rekeys::replaced_by,
+            attachment_rekeys::created_at,
+            attachment_rekeys::verified_at,
+        ))
+        .load::<(String, String, String, bool, Option<String>, NaiveDateTime, Option<NaiveDateTime>)>(conn)?;
+    tables.insert(
+        "attachment_rekeys".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "attachmentId": r.0,
+                    "cipherUuid": r.1,
+                    "userUuid": r.2,
+                    "legacy": r.3,
+                    "replacedBy": r.4,
+                    "createdAt": format_date(&r.5),
+                    "verifiedAt": opt_date(r.6.as_ref()),
+                })
+            })
+            .collect(),
+    );
+
+    let rows = cipher_user_changes::table
+        .select((cipher_user_changes::user_uuid, cipher_user_changes::cipher_uuid, cipher_user_changes::changed_at))
+        .load::<(String, String, NaiveDateTime)>(conn)?;
+    tables.insert(
+        "cipher_user_changes".into(),
+        rows.iter().map(|r| json!({ "userUuid": r.0, "cipherUuid": r.1, "changedAt": format_date(&r.2) })).collect(),
+    );
+
+    let rows = cipher_templates::table
+        .select((
+            cipher_templates::uuid,
+            cipher_templates::org_uuid,
+            cipher_templates::atype,
+            cipher_templates::fields,
+            cipher_templates::default_collection_uuid,
+            cipher_templates::created_at,
+            cipher_templates::updated_at,
+        ))
+        .load::<(String, String, i32, String, Option<String>, NaiveDateTime, NaiveDateTime)>(conn)?;
+    tables.insert(
+        "cipher_templates".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "uuid": r.0,
+                    "orgUuid": r.1,
+                    "name": Anonymizer::name("Template"),
+                    "type": r.2,
+                    "fields": Anonymizer::enc_json(&r.3),
+                    "defaultCollectionUuid": r.4,
+                    "createdAt": format_date(&r.5),
+                    "updatedAt": format_date(&r.6),
+                })
+            })
+            .collect(),
+    );
+
+    // The domains are the public lists of equivalent domains, not those of the users
+    let rows = global_domain_overrides::table
+        .select((
+            global_domain_overrides::atype,
+            global_domain_overrides::domains,
+            global_domain_overrides::disabled,
+            global_domain_overrides::updated_at,
+        ))
+        .load::<(i32, String, bool, NaiveDateTime)>(conn)?;
+    tables.insert(
+        "global_domain_overrides".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "type": r.0,
+                    "domains": serde_json::from_str::<Value>(&r.1).unwrap_or(Value::Null),
+                    "disabled": r.2,
+                    "updatedAt": format_date(&r.3),
+                })
+            })
+            .collect(),
+    );
+
+    let rows = login_fingerprints::table
+        .select((
+            login_fingerprints::user_uuid,
+            login_fingerprints::device_uuid,
+            login_fingerprints::device_type,
+            login_fingerprints::country,
+            login_fingerprints::created_at,
+        ))
+        .load::<(String, String, i32, String, NaiveDateTime)>(conn)?;
+    tables.insert(
+        "login_fingerprints".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "userUuid": r.0,
+                    "deviceUuid": r.1,
+                    "deviceType": r.2,
+                    "country": r.3,
+                    "createdAt": format_date(&r.4),
+                })
+            })
+            .collect(),
+    );
+
+    let rows = quota_warnings::table
+        .select((quota_warnings::owner_uuid, quota_warnings::level, quota_warnings::updated_at))
+        .load::<(String, i32, NaiveDateTime)>(conn)?;
+    tables.insert(
+        "quota_warnings".into(),
+        rows.iter().map(|r| json!({ "ownerUuid": r.0, "level": r.1, "updatedAt": format_date(&r.2) })).collect(),
+    );
+
+    let rows = send_egress::table.select((send_egress::month, send_egress::bytes)).load::<(String, i64)>(conn)?;
+    tables.insert("send_egress".into(), rows.iter().map(|r| json!({ "month": r.0, "bytes": r.1 })).collect());
+
+    let rows = tombstones::table
+        .select((tombstones::uuid, tombstones::owner_uuid, tombstones::atype, tombstones::deleted_at))
+        .load::<(String, String, i32, NaiveDateTime)>(conn)?;
+    tables.insert(
+        "tombstones".into(),
+        rows.iter()
+            .map(|r| json!({ "uuid": r.0, "ownerUuid": r.1, "type": r.2, "deletedAt": format_date(&r.3) }))
+            .collect(),
+    );
+
+    let rows = usage_snapshots::table
+        .select((
+            usage_snapshots::day,
+            usage_snapshots::users,
+            usage_snapshots::active_devices,
+            usage_snapshots::ciphers,
+            usage_snapshots::attachments,
+            usage_snapshots::attachments_bytes,
+            usage_snapshots::sends,
+            usage_snapshots::sends_bytes,
+            usage_snapshots::events,
+        ))
+        .load::<(NaiveDate, i64, i64, i64, i64, i64, i64, i64, i64)>(conn)?;
+    tables.insert(
+        "usage_snapshots".into(),
+        rows.iter()
+            .map(|r| {
+                json!({
+                    "day": r.0.to_string(),
+                    "users": r.1,
+                    "activeDevices": r.2,
+                    "ciphers": r.3,
+                    "attachments": r.4,
+                    "attachmentsBytes": r.5,
+                    "sends": r.6,
+                    "sendsBytes": r.7,
+                    "events": r.8,
+                })
+            })
+            .collect(),
+    );
+
+    // Only what was done and by whom, the details and IP addresses can contain personal data
+    let rows = admin_audit_log::table
+        .select((
+            admin_audit_log::uuid,
+            admin_audit_log::created_at,
+            admin_audit_log::action,
+            admin_audit_log::user_uuid,
+        ))
+        .load::<(String, NaiveDateTime, String, Option<String>)>(conn)?;
+    tables.insert(
+        "admin_audit_log".into(),
+        rows.iter()
+            .map(|r| json!({ "uuid": r.0, "createdAt": format_date(&r.1), "action": r.2, "userUuid": r.3 }))
+            .collect(),
+    );
+
+    Ok(tables)
+}
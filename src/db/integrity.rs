@@ -0,0 +1,226 @@
+use std::sync::RwLock;
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel_migrations::{EmbeddedMigrations, MigrationHarness, MigrationSource};
+use futures::{StreamExt, stream};
+use serde_json::Value;
+
+use crate::{
+    CONFIG,
+    config::PathType,
+    db::{
+        DbConn, DbPool,
+        models::{Attachment, AttachmentId, Cipher, CipherId, JobLock},
+    },
+    util::format_naive_datetime_local,
+};
+
+// Startup preflight checks of the database schema and data.
+// These look for known inconsistent states which can be left behind by older versions, crashes or manual edits.
+// They run in the background, so a large number of attachments doesn't delay the startup.
+// The last report is kept in memory, so it can be shown on the admin diagnostics page.
+static LAST_REPORT: RwLock<Option<IntegrityReport>> = RwLock::new(None);
+
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaStatus {
+    latest_applied: Option<String>,
+    // Migrations known to this version, but not applied to the database
+    pending: Vec<String>,
+    // Migrations applied to the database, but unknown to this version, which indicates a downgrade
+    unknown: Vec<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MissingAttachment {
+    id: AttachmentId,
+    cipher_uuid: CipherId,
+    repaired: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct IntegrityReport {
+    #[serde(skip)]
+    checked_at: NaiveDateTime,
+    schema: SchemaStatus,
+    orphaned_ciphers: Vec<CipherId>,
+    missing_attachment_files: Vec<MissingAttachment>,
+}
+
+impl IntegrityReport {
+    fn issue_count(&self) -> usize {
+        self.schema.pending.len()
+            + self.schema.unknown.len()
+            + usize::from(self.schema.error.is_some())
+            + self.orphaned_ciphers.len()
+            + self.missing_attachment_files.iter().filter(|a| !a.repaired).count()
+    }
+}
+
+// Number of attachment files checked at the same time, the storage can be a remote object store
+const ATTACHMENT_CHECK_CONCURRENCY: usize = 16;
+
+/// Runs all the checks, logs what was found and stores the report for the admin interface.
+pub async fn run_preflight(pool: DbPool) {
+    let conn = match pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Unable to get a database connection for the integrity check: {e:?}");
+            return;
+        }
+    };
+
+    info!("Running startup integrity checks");
+    let report = IntegrityReport {
+        checked_at: Utc::now().naive_utc(),
+        schema: check_schema(&conn).await,
+        orphaned_ciphers: Cipher::find_orphaned_uuids(&conn).await,
+        missing_attachment_files: check_attachment_files(&conn).await,
+    };
+
+    if let Some(e) = &report.schema.error {
+        error!("Unable to verify the database schema: {e}");
+    }
+    if !report.schema.pending.is_empty() {
+        error!("The database is missing migrations: {}", report.schema.pending.join(", "));
+    }
+    if !report.schema.unknown.is_empty() {
+        warn!(
+            "The database contains migrations unknown to this version, this usually means Vaultwarden was downgraded: {}",
+            report.schema.unknown.join(", ")
+        );
+    }
+    if !report.orphaned_ciphers.is_empty() {
+        warn!("Found {} cipher(s) without an owner or organization", report.orphaned_ciphers.len());
+    }
+    for missing in &report.missing_attachment_files {
+        if missing.repaired {
+            info!("Removed attachment '{}' of cipher '{}' as its file was missing", missing.id, missing.cipher_uuid);
+        } else {
+            warn!("The file of attachment '{}' of cipher '{}' is missing", missing.id, missing.cipher_uuid);
+        }
+    }
+
+    match report.issue_count() {
+        0 => info!("Startup integrity checks completed, no issues found"),
+        n => warn!("Startup integrity checks completed, {n} issue(s) found, see the admin diagnostics for details"),
+    }
+
+    *LAST_REPORT.write().unwrap() = Some(report);
+}
+
+/// Returns the report of the last run integrity check, if any
+pub fn last_report() -> Option<Value> {
+    LAST_REPORT.read().unwrap().as_ref().map(|report| {
+        let mut json = serde_json::to_value(report).unwrap_or_default();
+        json["checkedAt"] = Value::String(format_naive_datetime_local(&report.checked_at, "%Y-%m-%d %H:%M:%S %Z"));
+        json["issueCount"] = Value::from(report.issue_count());
+        json
+    })
+}
+
+/// Returns the latest applied migration, and the migrations which differ between the database and this version
+pub async fn schema_report(conn: &DbConn) -> Value {
+    serde_json::to_value(check_schema(conn).await).unwrap_or_default()
+}
+
+async fn check_schema(conn: &DbConn) -> SchemaStatus {
+    db_run! { conn:
+        sqlite {
+            schema_status(conn, &super::sqlite_migrations::MIGRATIONS)
+        }
+        mysql {
+            schema_status(conn, &super::mysql_migrations::MIGRATIONS)
+        }
+        postgresql {
+            schema_status(conn, &super::postgresql_migrations::MIGRATIONS)
+        }
+    }
+}
+
+fn schema_status<DB, C>(conn: &mut C, migrations: &EmbeddedMigrations) -> SchemaStatus
+where
+    DB: diesel::backend::Backend,
+    C: MigrationHarness<DB>,
+    EmbeddedMigrations: MigrationSource<DB>,
+{
+    let known = match MigrationSource::<DB>::migrations(migrations) {
+        Ok(known) => known.iter().map(|m| m.name().version().to_string()).collect::<Vec<_>>(),
+        Err(e) => {
+            return SchemaStatus {
+                error: Some(e.to_string()),
+                ..Default::default()
+            };
+        }
+    };
+    let mut applied = match conn.applied_migrations() {
+        Ok(applied) => applied.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        Err(e) => {
+            return SchemaStatus {
+                error: Some(e.to_string()),
+                ..Default::default()
+            };
+        }
+    };
+    applied.sort();
+
+    SchemaStatus {
+        latest_applied: applied.last().cloned(),
+        pending: known.iter().filter(|v| !applied.contains(v)).cloned().collect(),
+        unknown: applied.iter().filter(|v| !known.contains(v)).cloned().collect(),
+        error: None,
+    }
+}
+
+async fn check_attachment_files(conn: &DbConn) -> Vec<MissingAttachment> {
+    let backend = match CONFIG.storage_backend(&PathType::Attachments) {
+        Ok(backend) => backend,
+        Err(e) => {
+            error!("Unable to check attachment files: {e:?}");
+            return Vec::new();
+        }
+    };
+
+    let backend = &backend;
+    let missing_files: Vec<Attachment> = stream::iter(Attachment::find_all(conn).await)
+        .map(|attachment| async move {
+            match backend.stat(&attachment.get_file_path()).await {
+                Ok(Some(_)) => None,
+                Ok(None) => Some(attachment),
+                Err(e) => {
+                    warn!("Unable to check the file of attachment '{}': {e:?}", attachment.id);
+                    None
+                }
+            }
+        })
+        .buffer_unordered(ATTACHMENT_CHECK_CONCURRENCY)
+        .filter_map(|attachment| async move { attachment })
+        .collect()
+        .await;
+
+    let mut missing = Vec::with_capacity(missing_files.len());
+    for attachment in missing_files {
+        if is_upload_pending(&attachment, conn).await {
+            continue;
+        }
+        // The file is already gone, so removing the record doesn't lose any data
+        let repaired = CONFIG.startup_integrity_repair() && attachment.delete(conn).await.is_ok();
+        missing.push(MissingAttachment {
+            id: attachment.id,
+            cipher_uuid: attachment.cipher_uuid,
+            repaired,
+        });
+    }
+    missing
+}
+
+/// The file of an attachment is only stored once its upload completes, which can take up to `TMP_CLEANUP_MAX_AGE`.
+/// Resumable uploads which are still going on hold a job lock, possibly on another instance sharing the database.
+async fn is_upload_pending(attachment: &Attachment, conn: &DbConn) -> bool {
+    let max_age = TimeDelta::try_seconds(CONFIG.tmp_cleanup_max_age().try_into().unwrap()).unwrap();
+    attachment.created_at.is_some_and(|created_at| Utc::now().naive_utc() - created_at < max_age)
+        || JobLock::find_active_by_name(&Attachment::upload_lock_name(&attachment.id), conn).await.is_some()
+}
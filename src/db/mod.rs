@@ -7,7 +7,7 @@ use std::{
 
 use diesel::{
     Connection, RunQueryDsl,
-    connection::SimpleConnection,
+    connection::{SimpleConnection, TransactionManager},
     r2d2::{CustomizeConnection, Pool, PooledConnection},
 };
 use rocket::{
@@ -52,6 +52,9 @@ pub enum DbConnInner {
     Sqlite(diesel::sqlite::SqliteConnection),
 }
 
+// Dispatches to the transaction manager of the backend in use
+type DbTransactionManager = <DbConnInner as Connection>::TransactionManager;
+
 /// Custom connection manager that implements manual connection establishment
 pub struct DbConnManager {
     database_url: String,
@@ -120,6 +123,28 @@ pub enum DbConnType {
 
 pub static ACTIVE_DB_TYPE: OnceLock<DbConnType> = OnceLock::new();
 
+/// Whether `DATABASE_URL` is a SQLite database, unlike `ACTIVE_DB_TYPE` this also works before the pool is created
+pub fn is_sqlite_url() -> bool {
+    match DbConnType::from_url(&CONFIG.database_url()) {
+        #[cfg(sqlite)]
+        Ok(DbConnType::Sqlite) => true,
+        _ => false,
+    }
+}
+
+/// The display name of the database type in use
+pub fn active_db_type_name() -> &'static str {
+    match ACTIVE_DB_TYPE.get() {
+        #[cfg(mysql)]
+        Some(DbConnType::Mysql) => "MySQL",
+        #[cfg(postgresql)]
+        Some(DbConnType::Postgresql) => "PostgreSQL",
+        #[cfg(sqlite)]
+        Some(DbConnType::Sqlite) => "SQLite",
+        _ => "Unknown",
+    }
+}
+
 pub struct DbConn {
     conn: Arc<Mutex<Option<PooledConnection<DbConnManager>>>>,
     permit: Option<OwnedSemaphorePermit>,
@@ -332,6 +357,27 @@ impl DbConn {
         // Run blocking can't be used due to the 'static limitation, use block_in_place instead
         tokio::task::block_in_place(move || f(conn))
     }
+
+    /// Runs `f` inside a single database transaction, which is rolled back when `f` returns an error.
+    /// Every query done through this connection in the meantime is part of it, transactions started inside become savepoints.
+    /// If the connection is dropped while the transaction is still open, the pool discards it instead of reusing it.
+    pub async fn transaction<T>(&self, f: impl AsyncFnOnce(&Self) -> Result<T, Error>) -> Result<T, Error> {
+        self.run(|conn| DbTransactionManager::begin_transaction(conn)).await.map_res("Error starting transaction")?;
+        match f(self).await {
+            Ok(value) => {
+                self.run(|conn| DbTransactionManager::commit_transaction(conn))
+                    .await
+                    .map_res("Error committing transaction")?;
+                Ok(value)
+            }
+            Err(e) => {
+                if let Err(rollback_err) = self.run(|conn| DbTransactionManager::rollback_transaction(conn)).await {
+                    error!("Error rolling back transaction: {rollback_err:?}");
+                }
+                Err(e)
+            }
+        }
+    }
 }
 
 #[macro_export]
@@ -398,6 +444,10 @@ pub mod schema;
 // Reexport the models, needs to be after the macros are defined so it can access them
 pub mod models;
 
+pub mod anonymize;
+pub mod integrity;
+pub mod orphans;
+
 /// Creates a back-up of the sqlite database
 /// MySQL/MariaDB and PostgreSQL are not supported.
 #[cfg(sqlite)]
@@ -405,7 +455,7 @@ pub fn backup_sqlite() -> Result<String, Error> {
     use diesel::Connection;
 
     let db_url = CONFIG.database_url();
-    if DbConnType::from_url(&CONFIG.database_url()).is_ok_and(|t| t == DbConnType::Sqlite) {
+    if is_sqlite_url() {
         // Strip the sqlite:// prefix if present to get the raw file path
         let file_path = db_url.strip_prefix("sqlite://").unwrap_or(&db_url);
         // Open a read-only connection for the backup
@@ -434,6 +484,17 @@ pub fn backup_sqlite() -> Result<String, Error> {
     err_silent!("The database type is not SQLite. Backups only works for SQLite databases")
 }
 
+/// Rebuilds the database to reclaim the space of deleted rows and to refresh the statistics of the query planner.
+/// MySQL/MariaDB is not supported, `mysqlcheck --optimize` can be used instead.
+pub async fn vacuum(conn: &DbConn) -> Result<(), Error> {
+    #[cfg(mysql)]
+    if ACTIVE_DB_TYPE.get().is_some_and(|t| *t == DbConnType::Mysql) {
+        err_silent!("The database type is MySQL/MariaDB. Use `mysqlcheck --optimize` to optimize the database")
+    }
+
+    conn.run(|conn| conn.batch_execute("VACUUM")).await.map_res("VACUUM failed")
+}
+
 /// Get the SQL Server version
 pub async fn get_sql_server_version(conn: &DbConn) -> String {
     db_run! { conn:
@@ -0,0 +1,77 @@
+use std::net::IpAddr;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::admin_audit_log},
+    error::MapResult,
+    util::{format_date, get_uuid},
+};
+
+use super::UserId;
+
+/// Actions done from the admin panel which need to be traceable afterwards
+#[derive(Identifiable, Queryable, Insertable)]
+#[diesel(table_name = admin_audit_log)]
+#[diesel(primary_key(uuid))]
+pub struct AdminAuditLog {
+    pub uuid: String,
+    pub created_at: NaiveDateTime,
+    pub ip: String,
+    pub action: String,
+    pub user_uuid: Option<UserId>,
+    pub details: Option<String>,
+}
+
+/// Local methods
+impl AdminAuditLog {
+    pub fn new(ip: &IpAddr, action: &str, user_uuid: Option<UserId>, details: Option<String>) -> Self {
+        Self {
+            uuid: get_uuid(),
+            created_at: Utc::now().naive_utc(),
+            ip: ip.to_string(),
+            action: action.to_owned(),
+            user_uuid,
+            details,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.uuid,
+            "date": format_date(&self.created_at),
+            "ip": self.ip,
+            "action": self.action,
+            "userId": self.user_uuid,
+            "details": self.details,
+        })
+    }
+}
+
+/// Database methods
+impl AdminAuditLog {
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::insert_into(admin_audit_log::table)
+                .values(self)
+                .execute(conn)
+                .map_res("Error saving admin audit log entry")
+        })
+        .await
+    }
+
+    /// Returns the most recent entries, newest first
+    pub async fn find_recent(limit: i64, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            admin_audit_log::table
+                .order(admin_audit_log::created_at.desc())
+                .limit(limit)
+                .load::<Self>(conn)
+                .expect("Error loading admin audit log")
+        })
+        .await
+    }
+}
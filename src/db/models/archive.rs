@@ -7,7 +7,7 @@ use crate::{
     error::MapResult,
 };
 
-use super::{CipherId, User, UserId};
+use super::{CipherId, CipherUserChange, User, UserId};
 
 #[derive(Identifiable, Queryable, Insertable)]
 #[diesel(table_name = archives)]
@@ -40,6 +40,7 @@ impl Archive {
         conn: &DbConn,
     ) -> EmptyResult {
         User::update_uuid_revision(user_uuid, conn).await;
+        CipherUserChange::record(cipher_uuid, vec![user_uuid.clone()], conn).await?;
         db_run! { conn:
             sqlite, mysql {
                 diesel::replace_into(archives::table)
@@ -70,6 +71,7 @@ impl Archive {
     // Deletes an archive record for a specific cipher
     pub async fn delete_by_cipher(user_uuid: &UserId, cipher_uuid: &CipherId, conn: &DbConn) -> EmptyResult {
         User::update_uuid_revision(user_uuid, conn).await;
+        CipherUserChange::record(cipher_uuid, vec![user_uuid.clone()], conn).await?;
         conn.run(move |conn| {
             diesel::delete(
                 archives::table.filter(archives::user_uuid.eq(user_uuid)).filter(archives::cipher_uuid.eq(cipher_uuid)),
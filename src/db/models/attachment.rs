@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 use bigdecimal::{BigDecimal, ToPrimitive};
+use chrono::{NaiveDateTime, Utc};
 use derive_more::{AsRef, Deref, Display};
 use diesel::prelude::*;
 use serde_json::Value;
@@ -18,7 +19,7 @@ use crate::{
 };
 use macros::IdFromParam;
 
-use super::{CipherId, OrganizationId, UserId};
+use super::{AttachmentShare, CipherId, OrganizationId, UserId};
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
 #[diesel(table_name = attachments)]
@@ -30,11 +31,13 @@ pub struct Attachment {
     pub file_name: String, // encrypted
     pub file_size: i64,
     pub akey: Option<String>,
+    // Unknown for the attachments created before it was recorded
+    pub created_at: Option<NaiveDateTime>,
 }
 
 /// Local methods
 impl Attachment {
-    pub const fn new(
+    pub fn new(
         id: AttachmentId,
         cipher_uuid: CipherId,
         file_name: String,
@@ -47,21 +50,27 @@ impl Attachment {
             file_name,
             file_size,
             akey,
+            created_at: Some(Utc::now().naive_utc()),
         }
     }
 
+    /// Name of the job lock which pins a resumable upload of the attachment to the instance holding its data
+    pub fn upload_lock_name(id: &AttachmentId) -> String {
+        format!("upload:{id}")
+    }
+
     pub fn get_file_path(&self) -> String {
         format!("{}/{}", self.cipher_uuid, self.id)
     }
 
     pub async fn get_url(&self, host: &str) -> Result<String, crate::Error> {
-        let operator = CONFIG.opendal_operator_for_path_type(&PathType::Attachments)?;
+        let backend = CONFIG.storage_backend(&PathType::Attachments)?;
 
-        if crate::storage::is_fs_operator(&operator) {
+        if let Some(url) = backend.presign(&self.get_file_path(), Duration::from_mins(5)).await? {
+            Ok(url)
+        } else {
             let token = encode_jwt(&generate_file_download_claims(self.cipher_uuid.clone(), self.id.clone()));
             Ok(format!("{host}/attachments/{}/{}?token={token}", self.cipher_uuid, self.id))
-        } else {
-            Ok(operator.presign_read(&self.get_file_path(), Duration::from_mins(5)).await?.uri().to_string())
         }
     }
 
@@ -121,19 +130,9 @@ impl Attachment {
             .map_res("Error deleting attachment")
         })
         .await?;
+        AttachmentShare::delete_all_by_attachment(&self.id, conn).await?;
 
-        let operator = CONFIG.opendal_operator_for_path_type(&PathType::Attachments)?;
-        let file_path = self.get_file_path();
-
-        if let Err(e) = operator.delete(&file_path).await {
-            if e.kind() == opendal::ErrorKind::NotFound {
-                debug!("File '{file_path}' already deleted.");
-            } else {
-                return Err(e.into());
-            }
-        }
-
-        Ok(())
+        CONFIG.storage_backend(&PathType::Attachments)?.delete(&self.get_file_path(), false).await
     }
 
     pub async fn delete_all_by_cipher(cipher_uuid: &CipherId, conn: &DbConn) -> EmptyResult {
@@ -237,6 +236,30 @@ impl Attachment {
         })
         .await
     }
+
+    pub async fn count_all(conn: &DbConn) -> i64 {
+        conn.run(move |conn| attachments::table.count().first::<i64>(conn).unwrap_or(0)).await
+    }
+
+    pub async fn size_all(conn: &DbConn) -> i64 {
+        conn.run(move |conn| {
+            let result: Option<BigDecimal> = attachments::table
+                .select(diesel::dsl::sum(attachments::file_size))
+                .first(conn)
+                .expect("Error loading attachment total size");
+
+            match result.map(|r| r.to_i64()) {
+                Some(Some(r)) => r,
+                Some(None) => i64::MAX,
+                None => 0,
+            }
+        })
+        .await
+    }
+
+    pub async fn find_all(conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| attachments::table.load::<Self>(conn).expect("Error loading attachments")).await
+    }
 }
 
 #[derive(
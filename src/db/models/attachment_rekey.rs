@@ -0,0 +1,133 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    api::EmptyResult,
+    db::{
+        DbConn,
+        models::{AttachmentId, CipherId, UserId},
+        schema::attachment_rekeys,
+    },
+    error::MapResult,
+    util::format_date,
+};
+
+/// An attachment whose file is still encrypted with a key from before the last key rotation of its owner.
+/// The client re-uploads these files encrypted with a new attachment key, the replacement is then verified in the background.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = attachment_rekeys)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(primary_key(attachment_id))]
+pub struct AttachmentRekey {
+    pub attachment_id: AttachmentId,
+    pub cipher_uuid: CipherId,
+    pub user_uuid: UserId,
+    pub legacy: bool, // The file has no attachment key and was encrypted with the user key itself
+    pub replaced_by: Option<AttachmentId>,
+    pub created_at: NaiveDateTime,
+    pub verified_at: Option<NaiveDateTime>,
+}
+
+impl AttachmentRekey {
+    pub fn new(attachment_id: AttachmentId, cipher_uuid: CipherId, user_uuid: UserId, legacy: bool) -> Self {
+        Self {
+            attachment_id,
+            cipher_uuid,
+            user_uuid,
+            legacy,
+            replaced_by: None,
+            created_at: Utc::now().naive_utc(),
+            verified_at: None,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "attachmentId": self.attachment_id,
+            "cipherId": self.cipher_uuid,
+            "legacy": self.legacy,
+            "replacedBy": self.replaced_by,
+            "verified": self.verified_at.is_some(),
+            "verificationDate": self.verified_at.as_ref().map(format_date),
+            "object": "attachmentRekey",
+        })
+    }
+}
+
+/// Database methods
+impl AttachmentRekey {
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        db_run! { conn:
+            sqlite, mysql {
+                diesel::replace_into(attachment_rekeys::table)
+                    .values(self)
+                    .execute(conn)
+                    .map_res("Error saving attachment re-key")
+            }
+            postgresql {
+                diesel::insert_into(attachment_rekeys::table)
+                    .values(self)
+                    .on_conflict(attachment_rekeys::attachment_id)
+                    .do_update()
+                    .set(self)
+                    .execute(conn)
+                    .map_res("Error saving attachment re-key")
+            }
+        }
+    }
+
+    pub async fn delete(self, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(attachment_rekeys::table.filter(attachment_rekeys::attachment_id.eq(self.attachment_id)))
+                .execute(conn)
+                .map_res("Error deleting attachment re-key")
+        })
+        .await
+    }
+
+    pub async fn find_by_attachment_and_user(
+        attachment_id: &AttachmentId,
+        user_uuid: &UserId,
+        conn: &DbConn,
+    ) -> Option<Self> {
+        conn.run(move |conn| {
+            attachment_rekeys::table
+                .filter(attachment_rekeys::attachment_id.eq(attachment_id))
+                .filter(attachment_rekeys::user_uuid.eq(user_uuid))
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
+    pub async fn find_by_user(user_uuid: &UserId, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            attachment_rekeys::table
+                .filter(attachment_rekeys::user_uuid.eq(user_uuid))
+                .order(attachment_rekeys::created_at.asc())
+                .load::<Self>(conn)
+                .expect("Error loading attachment re-keys")
+        })
+        .await
+    }
+
+    pub async fn find_unverified(conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            attachment_rekeys::table
+                .filter(attachment_rekeys::verified_at.is_null())
+                .load::<Self>(conn)
+                .expect("Error loading attachment re-keys")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(attachment_rekeys::table.filter(attachment_rekeys::user_uuid.eq(user_uuid)))
+                .execute(conn)
+                .map_res("Error deleting attachment re-keys")
+        })
+        .await
+    }
+}
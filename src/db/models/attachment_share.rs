@@ -0,0 +1,135 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    api::EmptyResult,
+    crypto,
+    db::{
+        DbConn,
+        models::{AttachmentId, CipherId, UserId},
+        schema::attachment_shares,
+    },
+    error::MapResult,
+    util::format_date,
+};
+
+/// A one-time link to download a single attachment without an account, created by the owner of the cipher.
+/// Only the hash of the token is stored, the token itself is only part of the link.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = attachment_shares)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(primary_key(token_hash))]
+pub struct AttachmentShare {
+    pub token_hash: String,
+    pub attachment_id: AttachmentId,
+    pub cipher_uuid: CipherId,
+    pub user_uuid: UserId,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub used_ip: Option<String>,
+}
+
+impl AttachmentShare {
+    /// Returns the share and the token of its link
+    pub fn new(
+        attachment_id: AttachmentId,
+        cipher_uuid: CipherId,
+        user_uuid: UserId,
+        valid_for: TimeDelta,
+    ) -> (Self, String) {
+        let token = crypto::encode_random_bytes::<32>(&data_encoding::BASE64URL_NOPAD);
+        let now = Utc::now().naive_utc();
+        let share = Self {
+            token_hash: Self::hash_token(&token),
+            attachment_id,
+            cipher_uuid,
+            user_uuid,
+            created_at: now,
+            expires_at: now + valid_for,
+            used_at: None,
+            used_ip: None,
+        };
+        (share, token)
+    }
+
+    fn hash_token(token: &str) -> String {
+        crypto::sha256_hex(token.as_bytes())
+    }
+
+    pub fn to_json(&self, url: &str) -> Value {
+        json!({
+            "attachmentId": self.attachment_id,
+            "cipherId": self.cipher_uuid,
+            "url": url,
+            "expirationDate": format_date(&self.expires_at),
+            "object": "attachmentShare",
+        })
+    }
+}
+
+/// Database methods
+impl AttachmentShare {
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::insert_into(attachment_shares::table)
+                .values(self)
+                .execute(conn)
+                .map_res("Error saving attachment share")
+        })
+        .await
+    }
+
+    /// Marks the share of the token as used and returns it, unless it was already used or is expired.
+    /// The update only succeeds once, so concurrent downloads with the same link can't both get the file.
+    pub async fn take(token: &str, ip: &str, conn: &DbConn) -> Option<Self> {
+        let token_hash = Self::hash_token(token);
+        let ip = ip.to_owned();
+        let now = Utc::now().naive_utc();
+        conn.run(move |conn| {
+            let updated = diesel::update(
+                attachment_shares::table
+                    .filter(attachment_shares::token_hash.eq(&token_hash))
+                    .filter(attachment_shares::used_at.is_null())
+                    .filter(attachment_shares::expires_at.gt(now)),
+            )
+            .set((attachment_shares::used_at.eq(now), attachment_shares::used_ip.eq(ip)))
+            .execute(conn)
+            .unwrap_or(0);
+            if updated != 1 {
+                return None;
+            }
+            attachment_shares::table.filter(attachment_shares::token_hash.eq(token_hash)).first::<Self>(conn).ok()
+        })
+        .await
+    }
+
+    pub async fn delete_expired(conn: &DbConn) -> EmptyResult {
+        let now = Utc::now().naive_utc();
+        conn.run(move |conn| {
+            diesel::delete(attachment_shares::table.filter(attachment_shares::expires_at.le(now)))
+                .execute(conn)
+                .map_res("Error deleting attachment shares")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_attachment(attachment_id: &AttachmentId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(attachment_shares::table.filter(attachment_shares::attachment_id.eq(attachment_id)))
+                .execute(conn)
+                .map_res("Error deleting attachment shares")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(attachment_shares::table.filter(attachment_shares::user_uuid.eq(user_uuid)))
+                .execute(conn)
+                .map_res("Error deleting attachment shares")
+        })
+        .await
+    }
+}
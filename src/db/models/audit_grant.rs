@@ -0,0 +1,178 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    api::EmptyResult,
+    db::{
+        DbConn,
+        models::{CollectionId, CollectionUser, OrganizationId, UserId},
+        schema::audit_grants,
+    },
+    error::MapResult,
+    util::format_date,
+};
+
+/// Read-only access to a collection granted to an auditor until `expires_at`.
+/// The access itself is a regular `CollectionUser` row, which is removed together with the grant once it expires.
+/// When that row is changed or removed some other way, like in the collection editor, the grant is dropped and the row is left alone.
+#[derive(Identifiable, Queryable, Insertable)]
+#[diesel(table_name = audit_grants)]
+#[diesel(primary_key(user_uuid, collection_uuid))]
+pub struct AuditGrant {
+    pub user_uuid: UserId,
+    pub collection_uuid: CollectionId,
+    pub org_uuid: OrganizationId,
+    pub granted_by_uuid: UserId,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+impl AuditGrant {
+    pub fn new(
+        user_uuid: UserId,
+        collection_uuid: CollectionId,
+        org_uuid: OrganizationId,
+        granted_by_uuid: UserId,
+        expires_at: NaiveDateTime,
+    ) -> Self {
+        Self {
+            user_uuid,
+            collection_uuid,
+            org_uuid,
+            granted_by_uuid,
+            created_at: Utc::now().naive_utc(),
+            expires_at,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "userId": self.user_uuid,
+            "collectionId": self.collection_uuid,
+            "grantedBy": self.granted_by_uuid,
+            "creationDate": format_date(&self.created_at),
+            "expirationDate": format_date(&self.expires_at),
+            "object": "auditGrant",
+        })
+    }
+}
+
+/// Database methods
+impl AuditGrant {
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        db_run! { conn:
+            sqlite, mysql {
+                diesel::replace_into(audit_grants::table)
+                    .values(self)
+                    .execute(conn)
+                    .map_res("Error saving audit grant")
+            }
+            postgresql {
+                diesel::insert_into(audit_grants::table)
+                    .values(self)
+                    .on_conflict((audit_grants::user_uuid, audit_grants::collection_uuid))
+                    .do_update()
+                    .set((
+                        audit_grants::granted_by_uuid.eq(&self.granted_by_uuid),
+                        audit_grants::created_at.eq(&self.created_at),
+                        audit_grants::expires_at.eq(&self.expires_at),
+                    ))
+                    .execute(conn)
+                    .map_res("Error saving audit grant")
+            }
+        }
+    }
+
+    pub async fn delete(self, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(
+                audit_grants::table
+                    .filter(audit_grants::user_uuid.eq(self.user_uuid))
+                    .filter(audit_grants::collection_uuid.eq(self.collection_uuid)),
+            )
+            .execute(conn)
+            .map_res("Error deleting audit grant")
+        })
+        .await
+    }
+
+    pub async fn find_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            audit_grants::table
+                .filter(audit_grants::org_uuid.eq(org_uuid))
+                .order(audit_grants::expires_at.asc())
+                .load::<Self>(conn)
+                .expect("Error loading audit grants")
+        })
+        .await
+    }
+
+    pub async fn find_by_org_and_user(org_uuid: &OrganizationId, user_uuid: &UserId, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            audit_grants::table
+                .filter(audit_grants::org_uuid.eq(org_uuid))
+                .filter(audit_grants::user_uuid.eq(user_uuid))
+                .load::<Self>(conn)
+                .expect("Error loading audit grants")
+        })
+        .await
+    }
+
+    pub async fn find_active_by_user(user_uuid: &UserId, now: &NaiveDateTime, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            audit_grants::table
+                .filter(audit_grants::user_uuid.eq(user_uuid))
+                .filter(audit_grants::expires_at.gt(now))
+                .load::<Self>(conn)
+                .expect("Error loading audit grants")
+        })
+        .await
+    }
+
+    pub async fn find_expired(now: &NaiveDateTime, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            audit_grants::table
+                .filter(audit_grants::expires_at.le(now))
+                .load::<Self>(conn)
+                .expect("Error loading expired audit grants")
+        })
+        .await
+    }
+
+    /// Whether the collection access of the auditor is still the read-only access given by the grant
+    pub async fn has_audit_access(&self, conn: &DbConn) -> bool {
+        CollectionUser::find_by_collection_and_user(&self.collection_uuid, &self.user_uuid, conn)
+            .await
+            .is_some_and(|col_user| col_user.read_only && !col_user.hide_passwords && !col_user.manage)
+    }
+
+    /// Drops the grants of the organization whose collection access was changed or removed since they were given.
+    /// The access then belongs to whoever changed it, and must not be removed once the grant expires.
+    pub async fn delete_stale_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
+        for grant in Self::find_by_org(org_uuid, conn).await {
+            if !grant.has_audit_access(conn).await {
+                grant.delete(conn).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(audit_grants::table.filter(audit_grants::user_uuid.eq(user_uuid)))
+                .execute(conn)
+                .map_res("Error deleting audit grants")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_collection(collection_uuid: &CollectionId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(audit_grants::table.filter(audit_grants::collection_uuid.eq(collection_uuid)))
+                .execute(conn)
+                .map_res("Error deleting audit grants")
+        })
+        .await
+    }
+}
@@ -1,4 +1,4 @@
-use chrono::{NaiveDateTime, Utc};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
 use derive_more::{AsRef, Deref, Display, From};
 use diesel::prelude::*;
 use serde_json::Value;
@@ -12,7 +12,14 @@ use crate::{
 };
 use macros::UuidFromParam;
 
-use super::{DeviceId, OrganizationId, UserId};
+use super::{DeviceId, DeviceType, OrganizationId, UserId};
+
+/// Unanswered requests can't be approved anymore after this, which is functionally equivalent to upstream:
+/// https://github.com/bitwarden/server/blob/f8ee2270409f7a13125cd414c450740af605a175/src/Sql/dbo/Auth/Stored%20Procedures/AuthRequest_DeleteIfExpired.sql
+pub const AUTH_REQUEST_EXPIRATION_MINUTES: i64 = 15;
+
+/// How long answered and expired requests are kept, so users can review who tried to log in to their account
+pub const AUTH_REQUEST_HISTORY_DAYS: i64 = 30;
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset, Deserialize, Serialize)]
 #[diesel(table_name = auth_requests)]
@@ -73,6 +80,37 @@ impl AuthRequest {
         }
     }
 
+    /// Whether the request was left unanswered for too long
+    pub fn is_expired(&self) -> bool {
+        self.approved.is_none()
+            && self.creation_date + TimeDelta::minutes(AUTH_REQUEST_EXPIRATION_MINUTES) <= Utc::now().naive_utc()
+    }
+
+    pub fn outcome(&self) -> &'static str {
+        match self.approved {
+            Some(true) => "approved",
+            Some(false) => "denied",
+            None if self.is_expired() => "expired",
+            None => "pending",
+        }
+    }
+
+    /// Without any of the keys or the access code, meant for reviewing past requests
+    pub fn to_json_history(&self) -> Value {
+        json!({
+            "id": self.uuid,
+            "requestDeviceIdentifier": self.request_device_identifier,
+            "requestDeviceType": DeviceType::from_i32(self.device_type).to_string(),
+            "requestIpAddress": self.request_ip,
+            "creationDate": format_date(&self.creation_date),
+            "outcome": self.outcome(),
+            "responseDeviceId": self.response_device_id,
+            "responseDate": self.response_date.as_ref().map(format_date),
+            "authenticationDate": self.authentication_date.as_ref().map(format_date),
+            "object": "auth-request-history",
+        })
+    }
+
     pub fn to_json_for_pending_device(&self) -> Value {
         json!({
             "id": self.uuid,
@@ -143,11 +181,13 @@ impl AuthRequest {
         device_uuid: &DeviceId,
         conn: &DbConn,
     ) -> Option<Self> {
+        let expiry_time = Utc::now().naive_utc() - TimeDelta::minutes(AUTH_REQUEST_EXPIRATION_MINUTES);
         conn.run(move |conn| {
             auth_requests::table
                 .filter(auth_requests::user_uuid.eq(user_uuid))
                 .filter(auth_requests::request_device_identifier.eq(device_uuid))
                 .filter(auth_requests::approved.is_null())
+                .filter(auth_requests::creation_date.gt(expiry_time))
                 .order_by(auth_requests::creation_date.desc())
                 .first::<Self>(conn)
                 .ok()
@@ -155,21 +195,25 @@ impl AuthRequest {
         .await
     }
 
-    pub async fn find_created_before(dt: &NaiveDateTime, conn: &DbConn) -> Vec<Self> {
+    /// Returns the requests of the last `AUTH_REQUEST_HISTORY_DAYS` days, the newest first
+    pub async fn find_recent_by_user(user_uuid: &UserId, conn: &DbConn) -> Vec<Self> {
+        let since = Utc::now().naive_utc() - TimeDelta::days(AUTH_REQUEST_HISTORY_DAYS);
         conn.run(move |conn| {
             auth_requests::table
-                .filter(auth_requests::creation_date.lt(dt))
+                .filter(auth_requests::user_uuid.eq(user_uuid))
+                .filter(auth_requests::creation_date.gt(since))
+                .order_by(auth_requests::creation_date.desc())
                 .load::<Self>(conn)
                 .expect("Error loading auth_requests")
         })
         .await
     }
 
-    pub async fn delete(&self, conn: &DbConn) -> EmptyResult {
+    pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
         conn.run(move |conn| {
-            diesel::delete(auth_requests::table.filter(auth_requests::uuid.eq(&self.uuid)))
+            diesel::delete(auth_requests::table.filter(auth_requests::user_uuid.eq(user_uuid)))
                 .execute(conn)
-                .map_res("Error deleting auth request")
+                .map_res("Error deleting auth requests")
         })
         .await
     }
@@ -178,12 +222,31 @@ impl AuthRequest {
         ct_eq(&self.access_code, access_code)
     }
 
+    /// Removes the keys and the access code of the requests which can't be used anymore,
+    /// and deletes the requests older than `AUTH_REQUEST_HISTORY_DAYS` days
     pub async fn purge_expired_auth_requests(conn: &DbConn) {
-        // delete auth requests older than 15 minutes which is functionally equivalent to upstream:
-        // https://github.com/bitwarden/server/blob/f8ee2270409f7a13125cd414c450740af605a175/src/Sql/dbo/Auth/Stored%20Procedures/AuthRequest_DeleteIfExpired.sql
-        let expiry_time = Utc::now().naive_utc() - chrono::TimeDelta::try_minutes(15).unwrap();
-        for auth_request in Self::find_created_before(&expiry_time, conn).await {
-            auth_request.delete(conn).await.ok();
+        let now = Utc::now().naive_utc();
+        let expiry_time = now - TimeDelta::minutes(AUTH_REQUEST_EXPIRATION_MINUTES);
+        let history_limit = now - TimeDelta::days(AUTH_REQUEST_HISTORY_DAYS);
+        let result = conn
+            .run(move |conn| {
+                diesel::update(
+                    auth_requests::table
+                        .filter(auth_requests::creation_date.le(expiry_time))
+                        .filter(auth_requests::access_code.ne("")),
+                )
+                .set((
+                    auth_requests::access_code.eq(""),
+                    auth_requests::enc_key.eq(None::<String>),
+                    auth_requests::master_password_hash.eq(None::<String>),
+                ))
+                .execute(conn)?;
+                diesel::delete(auth_requests::table.filter(auth_requests::creation_date.le(history_limit)))
+                    .execute(conn)
+            })
+            .await;
+        if let Err(e) = result {
+            error!("Error purging expired auth requests: {e:?}");
         }
     }
 }
@@ -1,4 +1,4 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashSet};
 
 use chrono::{NaiveDateTime, TimeDelta, Utc};
 use derive_more::{AsRef, Deref, Display, From};
@@ -15,7 +15,7 @@ use crate::{
         DbConn,
         schema::{
             ciphers, ciphers_collections, collections, collections_groups, folders, folders_ciphers, groups,
-            groups_users, users_collections, users_organizations,
+            groups_users, organizations, users_collections, users_organizations,
         },
     },
     error::MapResult,
@@ -24,10 +24,23 @@ use crate::{
 use macros::UuidFromParam;
 
 use super::{
-    Archive, Attachment, CollectionCipher, CollectionId, Favorite, FolderCipher, FolderId, Group, Membership,
-    MembershipStatus, MembershipType, OrganizationId, User, UserId,
+    Archive, Attachment, CipherUserChange, CollectionCipher, CollectionId, Favorite, FolderCipher, FolderId, Group,
+    Membership, MembershipStatus, MembershipType, OrgPolicy, Organization, OrganizationId, Tombstone, TombstoneType,
+    User, UserId,
 };
 
+// Number of rows inserted per statement by the bulk inserts.
+// Keeps the number of bind parameters well below the limits of all the supported databases.
+pub const BULK_INSERT_CHUNK_SIZE: usize = 500;
+
+/// The organizations in which admins can access all items, see `Organization::has_full_item_access`
+#[diesel::dsl::auto_type]
+fn admin_access_orgs() -> _ {
+    organizations::table
+        .filter(organizations::allow_admin_access_to_all_collection_items.eq(true))
+        .select(organizations::uuid)
+}
+
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
 #[diesel(table_name = ciphers)]
 #[diesel(treat_none_as_null = true)]
@@ -59,6 +72,11 @@ pub struct Cipher {
     pub password_history: Option<String>,
     pub deleted_at: Option<NaiveDateTime>,
     pub reprompt: Option<i32>,
+
+    // Set through the API by automation tools, unique per user or organization
+    pub external_id: Option<String>,
+    // The user who moved the cipher to the trash, shown in the trash of the organization
+    pub deleted_by: Option<UserId>,
 }
 
 pub enum RepromptType {
@@ -91,6 +109,8 @@ impl Cipher {
             password_history: None,
             deleted_at: None,
             reprompt: None,
+            external_id: None,
+            deleted_by: None,
         }
     }
 
@@ -174,18 +194,15 @@ impl Cipher {
             }
         }
 
-        // We don't need these values at all for Organizational syncs
-        // Skip any other database calls if this is the case and just return false.
-        let (read_only, hide_passwords, _) = if sync_type == CipherSyncType::User {
+        // Organizational syncs need these too, the admin console uses them to decide which actions are available per item
+        let (read_only, hide_passwords, manage) =
             if let Some((ro, hp, mn)) = self.get_access_restrictions(user_uuid, cipher_sync_data, conn).await {
                 (ro, hp, mn)
             } else {
                 error!("Cipher ownership assertion failure");
                 (true, true, false)
-            }
-        } else {
-            (false, false, false)
-        };
+            };
+        let deletable = self.is_deletable_by_user_with_sync_data(user_uuid, cipher_sync_data, conn).await;
 
         let fields_json: Vec<_> = self
             .fields
@@ -342,6 +359,7 @@ impl Cipher {
             "deletedDate": self.deleted_at.map_or(Value::Null, |d| Value::String(format_date(&d))),
             "reprompt": self.reprompt.filter(|r| *r == RepromptType::None as i32 || *r == RepromptType::Password as i32).unwrap_or(RepromptType::None as i32),
             "organizationId": self.organization_uuid,
+            "externalId": self.external_id,
             "key": self.key,
             "attachments": attachments_json,
             // We have UseTotp set to true by default within the Organization model.
@@ -386,18 +404,25 @@ impl Cipher {
             } else {
                 self.get_archived_at(user_uuid, conn).await.map_or(Value::Null, |d| Value::String(format_date(&d)))
             });
-            // These values are true by default, but can be false if the
-            // cipher belongs to a collection or group where the org owner has enabled
-            // the "Read Only" or "Hide Passwords" restrictions for the user.
-            json_object["edit"] = json!(!read_only);
-            json_object["viewPassword"] = json!(!hide_passwords);
-            // The new key used by clients since v2025.6.0
-            json_object["permissions"] = json!({
-                "delete": !read_only,
-                "restore": !read_only,
-            });
+        } else {
+            // Folders, favorites and archives are personal, the organization vault only has the defaults
+            json_object["folderId"] = Value::Null;
+            json_object["favorite"] = json!(false);
+            json_object["archivedDate"] = Value::Null;
         }
 
+        // These values are true by default, but can be false if the
+        // cipher belongs to a collection or group where the org owner has enabled
+        // the "Read Only" or "Hide Passwords" restrictions for the user.
+        json_object["edit"] = json!(!read_only);
+        json_object["viewPassword"] = json!(!hide_passwords);
+        json_object["manage"] = json!(manage);
+        // The new key used by clients since v2025.6.0
+        json_object["permissions"] = json!({
+            "delete": deletable,
+            "restore": deletable,
+        });
+
         let key = match self.atype {
             1 => "login",
             2 => "secureNote",
@@ -411,15 +436,12 @@ impl Cipher {
         Ok(json_object)
     }
 
-    pub async fn update_users_revision(&self, conn: &DbConn) -> Vec<UserId> {
-        let mut user_uuids = Vec::new();
+    /// The users who can see this cipher, either as owner or through the collections of the organization
+    pub async fn find_user_uuids_with_access(&self, conn: &DbConn) -> Vec<UserId> {
         match self.user_uuid {
-            Some(ref user_uuid) => {
-                User::update_uuid_revision(user_uuid, conn).await;
-                user_uuids.push(user_uuid.clone());
-            }
+            Some(ref user_uuid) => vec![user_uuid.clone()],
             None => {
-                // Belongs to Organization, need to update affected users
+                let mut user_uuids = Vec::new();
                 if let Some(ref org_uuid) = self.organization_uuid {
                     // users having access to the collection
                     let mut collection_users = Membership::find_by_cipher_and_org(&self.uuid, org_uuid, conn).await;
@@ -429,46 +451,76 @@ impl Cipher {
                             Membership::find_by_cipher_and_org_with_group(&self.uuid, org_uuid, conn).await;
                         collection_users.extend(group_users);
                     }
-                    for member in collection_users {
-                        User::update_uuid_revision(&member.user_uuid, conn).await;
-                        user_uuids.push(member.user_uuid.clone());
-                    }
+                    user_uuids.extend(collection_users.into_iter().map(|member| member.user_uuid));
                 }
+                user_uuids
             }
         }
+    }
+
+    pub async fn update_users_revision(&self, conn: &DbConn) -> Vec<UserId> {
+        let user_uuids = self.find_user_uuids_with_access(conn).await;
+        for user_uuid in &user_uuids {
+            User::update_uuid_revision(user_uuid, conn).await;
+        }
         user_uuids
     }
 
     pub async fn save(&mut self, conn: &DbConn) -> EmptyResult {
-        self.update_users_revision(conn).await;
+        // The revision of the users must not be older than the cipher, see `User::find_with_stale_revision`
         self.updated_at = Utc::now().naive_utc();
+        self.update_users_revision(conn).await;
 
-        db_run! { conn:
-            sqlite, mysql {
-                match diesel::replace_into(ciphers::table)
-                    .values(&*self)
+        // A real upsert, `replace_into` would delete any other cipher a concurrent save gave the same external id
+        let res: QueryResult<usize> = db_run! { conn:
+            mysql {
+                // `ON DUPLICATE KEY UPDATE` can't be limited to the primary key and would overwrite that other cipher
+                match diesel::update(ciphers::table)
+                    .filter(ciphers::uuid.eq(&self.uuid))
+                    .set(&*self)
                     .execute(conn)
                 {
-                    Ok(_) => Ok(()),
-                    // Record already exists and causes a Foreign Key Violation because replace_into() wants to delete the record first.
-                    Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::ForeignKeyViolation, _)) => {
-                        diesel::update(ciphers::table)
-                            .filter(ciphers::uuid.eq(&self.uuid))
-                            .set(&*self)
-                            .execute(conn)
-                            .map_res("Error saving cipher")
-                    }
-                    Err(e) => Err(e.into()),
-                }.map_res("Error saving cipher")
+                    Ok(0) => diesel::insert_into(ciphers::table).values(&*self).execute(conn),
+                    res => res,
+                }
             }
-            postgresql {
+            postgresql, sqlite {
                 diesel::insert_into(ciphers::table)
                     .values(&*self)
                     .on_conflict(ciphers::uuid)
                     .do_update()
                     .set(&*self)
                     .execute(conn)
-                    .map_res("Error saving cipher")
+            }
+        };
+        match res {
+            Ok(_) => Ok(()),
+            // The unique index on the external id of the owner is the only one left that can conflict
+            Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _)) => {
+                err!("An item with this external ID already exists")
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Inserts new ciphers in batches, used by imports.
+    /// Unlike `save`, this does not update the revision of the users, this needs to be done afterwards.
+    pub async fn insert_many(ciphers: &mut [Self], conn: &DbConn) -> EmptyResult {
+        let now = Utc::now().naive_utc();
+        for cipher in ciphers.iter_mut() {
+            cipher.updated_at = now;
+        }
+        let ciphers = &*ciphers;
+
+        db_run! { conn:
+            sqlite, mysql, postgresql {
+                conn.transaction(|conn| {
+                    for chunk in ciphers.chunks(BULK_INSERT_CHUNK_SIZE) {
+                        diesel::insert_into(ciphers::table).values(chunk).execute(conn)?;
+                    }
+                    Ok::<(), diesel::result::Error>(())
+                })
+                .map_res("Error saving ciphers")
             }
         }
     }
@@ -480,6 +532,10 @@ impl Cipher {
         CollectionCipher::delete_all_by_cipher(&self.uuid, conn).await?;
         Attachment::delete_all_by_cipher(&self.uuid, conn).await?;
         Favorite::delete_all_by_cipher(&self.uuid, conn).await?;
+        CipherUserChange::delete_all_by_cipher(&self.uuid, conn).await?;
+        if let Some(owner) = self.organization_uuid.as_deref().or(self.user_uuid.as_deref()) {
+            Tombstone::record(TombstoneType::Cipher, &self.uuid, owner, conn).await?;
+        }
 
         conn.run(move |conn| {
             diesel::delete(ciphers::table.filter(ciphers::uuid.eq(&self.uuid)))
@@ -505,11 +561,22 @@ impl Cipher {
     }
 
     /// Purge all ciphers that are old enough to be auto-deleted.
+    /// The ciphers of organizations with a TrashRetention policy use the days of the policy instead of the global setting.
     pub async fn purge_trash(conn: &DbConn) {
-        if let Some(auto_delete_days) = CONFIG.trash_auto_delete_days() {
-            let now = Utc::now().naive_utc();
-            let dt = now - TimeDelta::try_days(auto_delete_days).unwrap();
-            for cipher in Self::find_deleted_before(&dt, conn).await {
+        let org_days = OrgPolicy::find_trash_retention_days(conn).await;
+        let global_days = CONFIG.trash_auto_delete_days();
+        let Some(min_days) = global_days.into_iter().chain(org_days.values().copied()).min() else {
+            return;
+        };
+
+        let now = Utc::now().naive_utc();
+        let cutoff = |days: i64| now - TimeDelta::try_days(days).unwrap();
+        for cipher in Self::find_deleted_before(&cutoff(min_days), conn).await {
+            let days =
+                cipher.organization_uuid.as_ref().and_then(|org_id| org_days.get(org_id).copied()).or(global_days);
+            if let Some(days) = days
+                && cipher.deleted_at.is_some_and(|deleted_at| deleted_at < cutoff(days))
+            {
                 cipher.delete(conn).await.ok();
             }
         }
@@ -522,6 +589,8 @@ impl Cipher {
         conn: &DbConn,
     ) -> EmptyResult {
         User::update_uuid_revision(user_uuid, conn).await;
+        // For the clients the folder is part of the cipher, so a delta sync needs to return it again
+        CipherUserChange::record(&self.uuid, vec![user_uuid.clone()], conn).await?;
 
         match (self.get_folder_uuid(user_uuid, conn).await, folder_uuid) {
             // No changes
@@ -564,11 +633,16 @@ impl Cipher {
     ) -> bool {
         if let Some(ref org_uuid) = self.organization_uuid {
             if let Some(cipher_sync_data) = cipher_sync_data {
-                if let Some(cached_member) = cipher_sync_data.members.get(org_uuid) {
-                    return cached_member.has_full_access();
+                if let (Some(org), Some(cached_member)) =
+                    (cipher_sync_data.organizations.get(org_uuid), cipher_sync_data.members.get(org_uuid))
+                {
+                    return org.has_full_item_access(cached_member);
                 }
-            } else if let Some(member) = Membership::find_confirmed_by_user_and_org(user_uuid, org_uuid, conn).await {
-                return member.has_full_access();
+            } else if let (Some(org), Some(member)) = (
+                Organization::find_by_uuid(org_uuid, conn).await,
+                Membership::find_confirmed_by_user_and_org(user_uuid, org_uuid, conn).await,
+            ) {
+                return org.has_full_item_access(&member);
             }
         }
         false
@@ -607,13 +681,52 @@ impl Cipher {
         // Check whether this cipher is directly owned by the user, or is in
         // a collection that the user has full access to. If so, there are no
         // access restrictions.
-        if self.is_owned_by_user(user_uuid)
-            || self.is_in_full_access_org(user_uuid, cipher_sync_data, conn).await
-            || self.is_in_full_access_group(user_uuid, cipher_sync_data, conn).await
+        if self.is_owned_by_user(user_uuid) {
+            return Some((false, false, true));
+        }
+        if (self.is_in_full_access_org(user_uuid, cipher_sync_data, conn).await
+            || self.is_in_full_access_group(user_uuid, cipher_sync_data, conn).await)
+            && !self.is_confidential(cipher_sync_data, conn).await
         {
             return Some((false, false, true));
         }
 
+        self.get_collections_access_restrictions(user_uuid, cipher_sync_data, conn).await
+    }
+
+    /// Returns whether this cipher is in a confidential collection of an organization which has these enabled.
+    /// Full access to the organization doesn't include these ciphers, only an assignment to one of their collections does.
+    async fn is_confidential(&self, cipher_sync_data: Option<&CipherSyncData>, conn: &DbConn) -> bool {
+        if self.organization_uuid.is_none() {
+            return false;
+        }
+        if let Some(cipher_sync_data) = cipher_sync_data {
+            return cipher_sync_data.confidential_ciphers.contains(&self.uuid);
+        }
+        conn.run(move |conn| {
+            ciphers_collections::table
+                .inner_join(collections::table.on(collections::uuid.eq(ciphers_collections::collection_uuid)))
+                .inner_join(organizations::table.on(organizations::uuid.eq(collections::org_uuid)))
+                .filter(ciphers_collections::cipher_uuid.eq(&self.uuid))
+                .filter(collections::confidential.eq(true))
+                .filter(organizations::confidential_collections.eq(true))
+                .count()
+                .first::<i64>(conn)
+                .ok()
+                .unwrap_or(0)
+                != 0
+        })
+        .await
+    }
+
+    /// Returns the user's access restrictions to this cipher through the collections it is in,
+    /// without taking the ownership of the cipher or the full access of the user into account.
+    async fn get_collections_access_restrictions(
+        &self,
+        user_uuid: &UserId,
+        cipher_sync_data: Option<&CipherSyncData>,
+        conn: &DbConn,
+    ) -> Option<(bool, bool, bool)> {
         let rows = if let Some(cipher_sync_data) = cipher_sync_data {
             let mut rows: Vec<(bool, bool, bool)> = Vec::new();
             if let Some(collections) = cipher_sync_data.cipher_collections.get(&self.uuid) {
@@ -723,6 +836,57 @@ impl Cipher {
         }
     }
 
+    /// Returns whether the user can delete this cipher.
+    /// For organization ciphers this also depends on the collection management settings of the organization:
+    /// with `limitItemDeletion` only members which can manage the cipher can delete it,
+    /// and without `allowAdminAccessToAllCollectionItems` admins need access to the cipher through a collection.
+    pub async fn is_deletable_by_user(&self, user_uuid: &UserId, conn: &DbConn) -> bool {
+        self.is_deletable_by_user_with_sync_data(user_uuid, None, conn).await
+    }
+
+    async fn is_deletable_by_user_with_sync_data(
+        &self,
+        user_uuid: &UserId,
+        cipher_sync_data: Option<&CipherSyncData>,
+        conn: &DbConn,
+    ) -> bool {
+        let Some(ref org_uuid) = self.organization_uuid else {
+            return self.is_owned_by_user(user_uuid);
+        };
+        // Whether the member has access to all items, and whether deleting needs the manage permission
+        let full_access_and_limit =
+            |org: &Organization, member: &Membership| (org.has_full_item_access(member), org.limit_item_deletion);
+        let settings = if let Some(cipher_sync_data) = cipher_sync_data {
+            match (cipher_sync_data.organizations.get(org_uuid), cipher_sync_data.members.get(org_uuid)) {
+                (Some(org), Some(member)) => Some(full_access_and_limit(org, member)),
+                _ => None,
+            }
+        } else {
+            match (
+                Organization::find_by_uuid(org_uuid, conn).await,
+                Membership::find_confirmed_by_user_and_org(user_uuid, org_uuid, conn).await,
+            ) {
+                (Some(org), Some(member)) => Some(full_access_and_limit(&org, &member)),
+                _ => None,
+            }
+        };
+        let Some((full_access, limit_item_deletion)) = settings else {
+            return false;
+        };
+
+        if (full_access || self.is_in_full_access_group(user_uuid, cipher_sync_data, conn).await)
+            && !self.is_confidential(cipher_sync_data, conn).await
+        {
+            return true;
+        }
+
+        match self.get_collections_access_restrictions(user_uuid, cipher_sync_data, conn).await {
+            Some((_read_only, _hide_passwords, true)) => true,
+            Some((read_only, _hide_passwords, false)) => !read_only && !limit_item_deletion,
+            None => false,
+        }
+    }
+
     // used for checking if collection can be edited (only if user has access to a collection they
     // can write to and also passwords are not hidden to prevent privilege escalation)
     pub async fn is_in_editable_collection_by_user(&self, user_uuid: &UserId, conn: &DbConn) -> bool {
@@ -810,6 +974,20 @@ impl Cipher {
         visible_only: bool,
         cipher_uuids: &Vec<CipherId>,
         conn: &DbConn,
+    ) -> Vec<Self> {
+        let mut ciphers = Self::load_by_user(user_uuid, visible_only, cipher_uuids, None, conn).await;
+        Self::retain_not_hidden(&mut ciphers, user_uuid, conn).await;
+        ciphers
+    }
+
+    // The query of `find_by_user`, without hiding the items of confidential collections.
+    // With a page (the cipher to start after and the limit), the ciphers are ordered by uuid.
+    async fn load_by_user(
+        user_uuid: &UserId,
+        visible_only: bool,
+        cipher_uuids: &Vec<CipherId>,
+        page: Option<(Option<&CipherId>, i64)>,
+        conn: &DbConn,
     ) -> Vec<Self> {
         if CONFIG.org_groups_enabled() {
             conn.run(move |conn| {
@@ -850,7 +1028,9 @@ impl Cipher {
 
                 if !visible_only {
                     query = query.or_filter(
-                        users_organizations::atype.le(MembershipType::Admin as i32), // Org admin/owner
+                        users_organizations::atype.eq(MembershipType::Owner as i32).or(users_organizations::atype
+                            .eq(MembershipType::Admin as i32)
+                            .and(users_organizations::org_uuid.eq_any(admin_access_orgs()))), // Org admin/owner
                     );
                 }
 
@@ -859,6 +1039,13 @@ impl Cipher {
                     query = query.filter(ciphers::uuid.eq_any(cipher_uuids));
                 }
 
+                if let Some((after, limit)) = page {
+                    if let Some(after) = after {
+                        query = query.filter(ciphers::uuid.gt(after));
+                    }
+                    query = query.order(ciphers::uuid).limit(limit);
+                }
+
                 query.select(ciphers::all_columns).distinct().load::<Self>(conn).expect("Error loading ciphers")
             })
             .await
@@ -885,7 +1072,9 @@ impl Cipher {
 
                 if !visible_only {
                     query = query.or_filter(
-                        users_organizations::atype.le(MembershipType::Admin as i32), // Org admin/owner
+                        users_organizations::atype.eq(MembershipType::Owner as i32).or(users_organizations::atype
+                            .eq(MembershipType::Admin as i32)
+                            .and(users_organizations::org_uuid.eq_any(admin_access_orgs()))), // Org admin/owner
                     );
                 }
 
@@ -894,17 +1083,49 @@ impl Cipher {
                     query = query.filter(ciphers::uuid.eq_any(cipher_uuids));
                 }
 
+                if let Some((after, limit)) = page {
+                    if let Some(after) = after {
+                        query = query.filter(ciphers::uuid.gt(after));
+                    }
+                    query = query.order(ciphers::uuid).limit(limit);
+                }
+
                 query.select(ciphers::all_columns).distinct().load::<Self>(conn).expect("Error loading ciphers")
             })
             .await
         }
     }
 
+    // Full access to an organization doesn't include the items of its confidential collections
+    async fn retain_not_hidden(ciphers: &mut Vec<Self>, user_uuid: &UserId, conn: &DbConn) {
+        let hidden = Self::find_hidden_confidential_uuids(user_uuid, conn).await;
+        if !hidden.is_empty() {
+            ciphers.retain(|c| !hidden.contains(&c.uuid));
+        }
+    }
+
     // Find all ciphers visible to the specified user.
     pub async fn find_by_user_visible(user_uuid: &UserId, conn: &DbConn) -> Vec<Self> {
         Self::find_by_user(user_uuid, true, &vec![], conn).await
     }
 
+    /// Same as `find_by_user_visible`, but ordered by uuid and limited to `limit` ciphers after the given cipher.
+    /// Also returns the cipher to continue after when the page was full, hidden ciphers can make a page shorter.
+    pub async fn find_by_user_visible_page(
+        user_uuid: &UserId,
+        after: Option<&CipherId>,
+        limit: i64,
+        conn: &DbConn,
+    ) -> (Vec<Self>, Option<CipherId>) {
+        let mut ciphers = Self::load_by_user(user_uuid, true, &vec![], Some((after, limit)), conn).await;
+        let continue_after = match ciphers.last() {
+            Some(last) if i64::try_from(ciphers.len()) == Ok(limit) => Some(last.uuid.clone()),
+            _ => None,
+        };
+        Self::retain_not_hidden(&mut ciphers, user_uuid, conn).await;
+        (ciphers, continue_after)
+    }
+
     pub async fn find_by_user_and_ciphers(
         user_uuid: &UserId,
         cipher_uuids: &Vec<CipherId>,
@@ -928,6 +1149,27 @@ impl Cipher {
         .await
     }
 
+    /// Same as `find_owned_by_user`, but ordered by uuid and limited to `limit` ciphers after the given cipher
+    pub async fn find_owned_by_user_page(
+        user_uuid: &UserId,
+        after: Option<&CipherId>,
+        limit: i64,
+        conn: &DbConn,
+    ) -> Vec<Self> {
+        conn.run(move |conn| {
+            let mut query = ciphers::table
+                .filter(ciphers::user_uuid.eq(user_uuid).and(ciphers::organization_uuid.is_null()))
+                .order(ciphers::uuid)
+                .limit(limit)
+                .into_boxed();
+            if let Some(after) = after {
+                query = query.filter(ciphers::uuid.gt(after));
+            }
+            query.load::<Self>(conn).expect("Error loading ciphers")
+        })
+        .await
+    }
+
     pub async fn count_owned_by_user(user_uuid: &UserId, conn: &DbConn) -> i64 {
         conn.run(move |conn| {
             ciphers::table.filter(ciphers::user_uuid.eq(user_uuid)).count().first::<i64>(conn).ok().unwrap_or(0)
@@ -935,6 +1177,82 @@ impl Cipher {
         .await
     }
 
+    /// The ciphers in the confidential collections of the organizations of the user, if the organization has these enabled
+    pub async fn find_confidential_uuids_by_user(user_uuid: &UserId, conn: &DbConn) -> HashSet<CipherId> {
+        conn.run(move |conn| {
+            ciphers_collections::table
+                .inner_join(collections::table.on(collections::uuid.eq(ciphers_collections::collection_uuid)))
+                .inner_join(organizations::table.on(organizations::uuid.eq(collections::org_uuid)))
+                .inner_join(
+                    users_organizations::table.on(users_organizations::org_uuid
+                        .eq(organizations::uuid)
+                        .and(users_organizations::user_uuid.eq(user_uuid))),
+                )
+                .filter(collections::confidential.eq(true))
+                .filter(organizations::confidential_collections.eq(true))
+                .select(ciphers_collections::cipher_uuid)
+                .distinct()
+                .load::<CipherId>(conn)
+                .expect("Error loading confidential ciphers")
+                .into_iter()
+                .collect()
+        })
+        .await
+    }
+
+    /// The confidential ciphers the user can't access, because they aren't assigned to any collection of the cipher,
+    /// neither directly nor through one of their groups
+    pub async fn find_hidden_confidential_uuids(user_uuid: &UserId, conn: &DbConn) -> HashSet<CipherId> {
+        let mut hidden = Self::find_confidential_uuids_by_user(user_uuid, conn).await;
+        if hidden.is_empty() {
+            return hidden;
+        }
+
+        let assigned: HashSet<CipherId> = conn
+            .run(move |conn| {
+                ciphers_collections::table
+                    .inner_join(
+                        users_collections::table.on(users_collections::collection_uuid
+                            .eq(ciphers_collections::collection_uuid)
+                            .and(users_collections::user_uuid.eq(user_uuid))),
+                    )
+                    .select(ciphers_collections::cipher_uuid)
+                    .load::<CipherId>(conn)
+                    .expect("Error loading assigned ciphers")
+                    .into_iter()
+                    .collect()
+            })
+            .await;
+        hidden.retain(|c| !assigned.contains(c));
+
+        if CONFIG.org_groups_enabled() && !hidden.is_empty() {
+            let group_assigned: HashSet<CipherId> = conn
+                .run(move |conn| {
+                    ciphers_collections::table
+                        .inner_join(
+                            collections_groups::table
+                                .on(collections_groups::collections_uuid.eq(ciphers_collections::collection_uuid)),
+                        )
+                        .inner_join(
+                            groups_users::table.on(groups_users::groups_uuid.eq(collections_groups::groups_uuid)),
+                        )
+                        .inner_join(
+                            users_organizations::table
+                                .on(users_organizations::uuid.eq(groups_users::users_organizations_uuid)),
+                        )
+                        .filter(users_organizations::user_uuid.eq(user_uuid))
+                        .select(ciphers_collections::cipher_uuid)
+                        .load::<CipherId>(conn)
+                        .expect("Error loading assigned ciphers")
+                        .into_iter()
+                        .collect()
+                })
+                .await;
+            hidden.retain(|c| !group_assigned.contains(c));
+        }
+        hidden
+    }
+
     pub async fn find_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
             ciphers::table
@@ -945,6 +1263,119 @@ impl Cipher {
         .await
     }
 
+    /// Requires the master password for the ciphers of the organization, or only for those in one of the given collections
+    pub async fn force_reprompt_by_org(
+        org_uuid: &OrganizationId,
+        collection_uuids: Vec<CollectionId>,
+        conn: &DbConn,
+    ) -> EmptyResult {
+        let now = Utc::now().naive_utc();
+        let changes = (ciphers::reprompt.eq(RepromptType::Password as i32), ciphers::updated_at.eq(now));
+        conn.run(move |conn| {
+            let org_ciphers = ciphers::organization_uuid
+                .eq(org_uuid)
+                .and(ciphers::reprompt.is_null().or(ciphers::reprompt.ne(RepromptType::Password as i32)));
+            if collection_uuids.is_empty() {
+                diesel::update(ciphers::table.filter(org_ciphers)).set(changes).execute(conn)
+            } else {
+                let collection_ciphers = ciphers_collections::table
+                    .filter(ciphers_collections::collection_uuid.eq_any(collection_uuids))
+                    .select(ciphers_collections::cipher_uuid);
+                diesel::update(ciphers::table.filter(org_ciphers).filter(ciphers::uuid.eq_any(collection_ciphers)))
+                    .set(changes)
+                    .execute(conn)
+            }
+            .map_res("Error updating the reprompt of the ciphers")
+        })
+        .await
+    }
+
+    /// Finds the ciphers of the collection which are not in the trash
+    pub async fn find_by_collection(collection_uuid: &CollectionId, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            ciphers::table
+                .inner_join(ciphers_collections::table.on(ciphers::uuid.eq(ciphers_collections::cipher_uuid)))
+                .filter(ciphers_collections::collection_uuid.eq(collection_uuid))
+                .filter(ciphers::deleted_at.is_null())
+                .select(ciphers::all_columns)
+                .load::<Self>(conn)
+                .expect("Error loading ciphers")
+        })
+        .await
+    }
+
+    /// Finds the ciphers of the organization which are in a collection the user can manage,
+    /// either directly or through one of their groups
+    pub async fn find_managed_by_user_and_org(
+        user_uuid: &UserId,
+        org_uuid: &OrganizationId,
+        conn: &DbConn,
+    ) -> Vec<Self> {
+        if CONFIG.org_groups_enabled() {
+            conn.run(move |conn| {
+                ciphers::table
+                    .inner_join(ciphers_collections::table.on(ciphers::uuid.eq(ciphers_collections::cipher_uuid)))
+                    .inner_join(
+                        users_organizations::table.on(users_organizations::org_uuid
+                            .nullable()
+                            .eq(ciphers::organization_uuid)
+                            .and(users_organizations::user_uuid.eq(user_uuid))
+                            .and(users_organizations::status.eq(MembershipStatus::Confirmed as i32))),
+                    )
+                    .left_join(
+                        users_collections::table.on(users_collections::collection_uuid
+                            .eq(ciphers_collections::collection_uuid)
+                            .and(users_collections::user_uuid.eq(user_uuid))
+                            .and(users_collections::manage.eq(true))),
+                    )
+                    .left_join(
+                        groups_users::table.on(groups_users::users_organizations_uuid.eq(users_organizations::uuid)),
+                    )
+                    .left_join(
+                        collections_groups::table.on(collections_groups::collections_uuid
+                            .eq(ciphers_collections::collection_uuid)
+                            .and(collections_groups::groups_uuid.eq(groups_users::groups_uuid))
+                            .and(collections_groups::manage.eq(true))),
+                    )
+                    .filter(ciphers::organization_uuid.eq(org_uuid))
+                    .filter(
+                        users_collections::user_uuid
+                            .is_not_null()
+                            .or(collections_groups::collections_uuid.is_not_null()),
+                    )
+                    .select(ciphers::all_columns)
+                    .distinct()
+                    .load::<Self>(conn)
+                    .expect("Error loading managed ciphers")
+            })
+            .await
+        } else {
+            conn.run(move |conn| {
+                ciphers::table
+                    .inner_join(ciphers_collections::table.on(ciphers::uuid.eq(ciphers_collections::cipher_uuid)))
+                    .inner_join(
+                        users_organizations::table.on(users_organizations::org_uuid
+                            .nullable()
+                            .eq(ciphers::organization_uuid)
+                            .and(users_organizations::user_uuid.eq(user_uuid))
+                            .and(users_organizations::status.eq(MembershipStatus::Confirmed as i32))),
+                    )
+                    .inner_join(
+                        users_collections::table.on(users_collections::collection_uuid
+                            .eq(ciphers_collections::collection_uuid)
+                            .and(users_collections::user_uuid.eq(user_uuid))
+                            .and(users_collections::manage.eq(true))),
+                    )
+                    .filter(ciphers::organization_uuid.eq(org_uuid))
+                    .select(ciphers::all_columns)
+                    .distinct()
+                    .load::<Self>(conn)
+                    .expect("Error loading managed ciphers")
+            })
+            .await
+        }
+    }
+
     pub async fn count_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> i64 {
         conn.run(move |conn| {
             ciphers::table.filter(ciphers::organization_uuid.eq(org_uuid)).count().first::<i64>(conn).ok().unwrap_or(0)
@@ -952,6 +1383,49 @@ impl Cipher {
         .await
     }
 
+    pub async fn count_all(conn: &DbConn) -> i64 {
+        conn.run(move |conn| ciphers::table.count().first::<i64>(conn).ok().unwrap_or(0)).await
+    }
+
+    /// Find all ciphers which neither belong to a user nor to an organization, and thus can't be accessed by anyone.
+    pub async fn find_orphaned_uuids(conn: &DbConn) -> Vec<CipherId> {
+        conn.run(move |conn| {
+            ciphers::table
+                .filter(ciphers::user_uuid.is_null())
+                .filter(ciphers::organization_uuid.is_null())
+                .select(ciphers::uuid)
+                .load::<CipherId>(conn)
+                .expect("Error loading ciphers")
+        })
+        .await
+    }
+
+    pub async fn find_by_user_and_external_id(user_uuid: &UserId, external_id: &str, conn: &DbConn) -> Option<Self> {
+        conn.run(move |conn| {
+            ciphers::table
+                .filter(ciphers::user_uuid.eq(user_uuid))
+                .filter(ciphers::external_id.eq(external_id))
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
+    pub async fn find_by_org_and_external_id(
+        org_uuid: &OrganizationId,
+        external_id: &str,
+        conn: &DbConn,
+    ) -> Option<Self> {
+        conn.run(move |conn| {
+            ciphers::table
+                .filter(ciphers::organization_uuid.eq(org_uuid))
+                .filter(ciphers::external_id.eq(external_id))
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
     pub async fn find_by_folder(folder_uuid: &FolderId, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
             folders_ciphers::table
@@ -972,6 +1446,19 @@ impl Cipher {
         .await
     }
 
+    /// Find all ciphers of the organization in the trash, the most recently deleted first
+    pub async fn find_deleted_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            ciphers::table
+                .filter(ciphers::organization_uuid.eq(org_uuid))
+                .filter(ciphers::deleted_at.is_not_null())
+                .order(ciphers::deleted_at.desc())
+                .load::<Self>(conn)
+                .expect("Error loading ciphers")
+        })
+        .await
+    }
+
     pub async fn get_collections(&self, user_uuid: UserId, conn: &DbConn) -> Vec<CollectionId> {
         if CONFIG.org_groups_enabled() {
             conn.run(move |conn| {
@@ -1086,7 +1573,10 @@ impl Cipher {
                             .or(collections_groups::collections_uuid
                                 .is_not_null() // Access via groups
                                 .and(collections_groups::read_only.eq(false)))
-                            .or(users_organizations::atype.le(MembershipType::Admin as i32)), // User is admin or owner
+                            .or(users_organizations::atype.eq(MembershipType::Owner as i32))
+                            .or(users_organizations::atype
+                                .eq(MembershipType::Admin as i32)
+                                .and(users_organizations::org_uuid.eq_any(admin_access_orgs()))), // User is admin or owner
                     )
                     .select(ciphers_collections::collection_uuid)
                     .load::<CollectionId>(conn)
@@ -1114,7 +1604,10 @@ impl Cipher {
                             .or(users_collections::user_uuid
                                 .eq(user_uuid) // User has access to collection
                                 .and(users_collections::read_only.eq(false)))
-                            .or(users_organizations::atype.le(MembershipType::Admin as i32)), // User is admin or owner
+                            .or(users_organizations::atype.eq(MembershipType::Owner as i32))
+                            .or(users_organizations::atype
+                                .eq(MembershipType::Admin as i32)
+                                .and(users_organizations::org_uuid.eq_any(admin_access_orgs()))), // User is admin or owner
                     )
                     .select(ciphers_collections::collection_uuid)
                     .load::<CollectionId>(conn)
@@ -0,0 +1,218 @@
+use chrono::{NaiveDateTime, Utc};
+use derive_more::{AsRef, Deref, Display, From};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::cipher_templates},
+    error::MapResult,
+    util::format_date,
+};
+use macros::UuidFromParam;
+
+use super::{CollectionId, OrganizationId};
+
+/// A template of an organization for creating consistent items, with pre-filled custom fields and a default collection.
+/// The field names are stored as sent by the client, which should encrypt them with the organization key like the items themselves.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = cipher_templates)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(primary_key(uuid))]
+pub struct CipherTemplate {
+    pub uuid: CipherTemplateId,
+    pub org_uuid: OrganizationId,
+    pub name: String,
+    pub atype: i32,
+    /// JSON array of `TemplateField`
+    pub fields: String,
+    pub default_collection_uuid: Option<CollectionId>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateField {
+    pub name: String,
+    pub r#type: i32,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Local methods
+impl CipherTemplate {
+    pub fn new(org_uuid: OrganizationId, name: String, atype: i32) -> Self {
+        let now = Utc::now().naive_utc();
+        Self {
+            uuid: CipherTemplateId(crate::util::get_uuid()),
+            org_uuid,
+            name,
+            atype,
+            fields: String::from("[]"),
+            default_collection_uuid: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn get_fields(&self) -> Vec<TemplateField> {
+        serde_json::from_str(&self.fields).unwrap_or_default()
+    }
+
+    pub fn set_fields(&mut self, fields: &[TemplateField]) {
+        self.fields = serde_json::to_string(fields).unwrap_or_else(|_| String::from("[]"));
+    }
+
+    /// Checks the custom fields of an item against the required fields of the template.
+    /// The names of both are encrypted by the clients, with a different IV each time, so they can't be compared here.
+    /// Instead the item needs at least as many custom fields of every type as the template requires.
+    pub fn is_satisfied_by(&self, item_fields: &[Value]) -> bool {
+        let fields = self.get_fields();
+        fields.iter().filter(|f| f.required).all(|required| {
+            let needed = fields.iter().filter(|f| f.required && f.r#type == required.r#type).count();
+            let present = item_fields
+                .iter()
+                .filter(|f| f["type"].as_i64() == Some(i64::from(required.r#type)))
+                .filter(|f| f["value"].as_str().is_some_and(|v| !v.is_empty()))
+                .count();
+            present >= needed
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.uuid,
+            "organizationId": self.org_uuid,
+            "name": self.name,
+            "type": self.atype,
+            "fields": self.get_fields(),
+            "defaultCollectionId": self.default_collection_uuid,
+            "creationDate": format_date(&self.created_at),
+            "revisionDate": format_date(&self.updated_at),
+            "object": "cipherTemplate",
+        })
+    }
+}
+
+/// Database methods
+impl CipherTemplate {
+    pub async fn save(&mut self, conn: &DbConn) -> EmptyResult {
+        self.updated_at = Utc::now().naive_utc();
+
+        db_run! { conn:
+            sqlite, mysql {
+                match diesel::replace_into(cipher_templates::table)
+                    .values(&*self)
+                    .execute(conn)
+                {
+                    Ok(_) => Ok(()),
+                    // Record already exists and causes a Foreign Key Violation because replace_into() wants to delete the record first.
+                    Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::ForeignKeyViolation, _)) => {
+                        diesel::update(cipher_templates::table)
+                            .filter(cipher_templates::uuid.eq(&self.uuid))
+                            .set(&*self)
+                            .execute(conn)
+                            .map_res("Error saving cipher template")
+                    }
+                    Err(e) => Err(e.into()),
+                }.map_res("Error saving cipher template")
+            }
+            postgresql {
+                diesel::insert_into(cipher_templates::table)
+                    .values(&*self)
+                    .on_conflict(cipher_templates::uuid)
+                    .do_update()
+                    .set(&*self)
+                    .execute(conn)
+                    .map_res("Error saving cipher template")
+            }
+        }
+    }
+
+    pub async fn delete(self, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(cipher_templates::table.filter(cipher_templates::uuid.eq(self.uuid)))
+                .execute(conn)
+                .map_res("Error deleting cipher template")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_organization(org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(cipher_templates::table.filter(cipher_templates::org_uuid.eq(org_uuid)))
+                .execute(conn)
+                .map_res("Error deleting cipher templates")
+        })
+        .await
+    }
+
+    /// Called when a collection is deleted, the templates using it as default collection keep working without one
+    pub async fn clear_default_collection(collection_uuid: &CollectionId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::update(
+                cipher_templates::table.filter(cipher_templates::default_collection_uuid.eq(collection_uuid)),
+            )
+            .set(cipher_templates::default_collection_uuid.eq(None::<CollectionId>))
+            .execute(conn)
+            .map_res("Error clearing the default collection of cipher templates")
+        })
+        .await
+    }
+
+    pub async fn find_by_uuid_and_org(
+        uuid: &CipherTemplateId,
+        org_uuid: &OrganizationId,
+        conn: &DbConn,
+    ) -> Option<Self> {
+        conn.run(move |conn| {
+            cipher_templates::table
+                .filter(cipher_templates::uuid.eq(uuid))
+                .filter(cipher_templates::org_uuid.eq(org_uuid))
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
+    pub async fn find_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            cipher_templates::table
+                .filter(cipher_templates::org_uuid.eq(org_uuid))
+                .order(cipher_templates::created_at)
+                .load::<Self>(conn)
+                .expect("Error loading cipher templates")
+        })
+        .await
+    }
+
+    pub async fn find_by_org_and_type(org_uuid: &OrganizationId, atype: i32, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            cipher_templates::table
+                .filter(cipher_templates::org_uuid.eq(org_uuid))
+                .filter(cipher_templates::atype.eq(atype))
+                .load::<Self>(conn)
+                .expect("Error loading cipher templates")
+        })
+        .await
+    }
+}
+
+#[derive(
+    Clone,
+    Debug,
+    AsRef,
+    Deref,
+    DieselNewType,
+    Display,
+    From,
+    FromForm,
+    Hash,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    UuidFromParam,
+)]
+pub struct CipherTemplateId(String);
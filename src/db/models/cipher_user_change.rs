@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::cipher_user_changes},
+    error::MapResult,
+};
+
+use super::{CipherId, UserId, tombstone::TOMBSTONE_RETENTION_DAYS};
+
+/// Records that a cipher changed for a single user, without a change of the cipher itself:
+/// its folder, favorite or archive status changed, or the user gained access to it through a collection.
+/// Touching the cipher instead would make the copy of every other user look out of date.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = cipher_user_changes)]
+#[diesel(primary_key(user_uuid, cipher_uuid))]
+pub struct CipherUserChange {
+    pub user_uuid: UserId,
+    pub cipher_uuid: CipherId,
+    pub changed_at: NaiveDateTime,
+}
+
+/// Database methods
+impl CipherUserChange {
+    pub async fn record(cipher_uuid: &CipherId, user_uuids: Vec<UserId>, conn: &DbConn) -> EmptyResult {
+        let now = Utc::now().naive_utc();
+        let changes: Vec<Self> = user_uuids
+            .into_iter()
+            .map(|user_uuid| Self {
+                user_uuid,
+                cipher_uuid: cipher_uuid.clone(),
+                changed_at: now,
+            })
+            .collect();
+
+        db_run! { conn:
+            sqlite, mysql {
+                for change in &changes {
+                    diesel::replace_into(cipher_user_changes::table)
+                        .values(change)
+                        .execute(conn)
+                        .map_res("Error saving cipher change")?;
+                }
+                Ok(())
+            }
+            postgresql {
+                for change in &changes {
+                    diesel::insert_into(cipher_user_changes::table)
+                        .values(change)
+                        .on_conflict((cipher_user_changes::user_uuid, cipher_user_changes::cipher_uuid))
+                        .do_update()
+                        .set(cipher_user_changes::changed_at.eq(change.changed_at))
+                        .execute(conn)
+                        .map_res("Error saving cipher change")?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn find_cipher_uuids_by_user_since(
+        user_uuid: &UserId,
+        since: NaiveDateTime,
+        conn: &DbConn,
+    ) -> HashSet<CipherId> {
+        conn.run(move |conn| {
+            cipher_user_changes::table
+                .filter(cipher_user_changes::user_uuid.eq(user_uuid))
+                .filter(cipher_user_changes::changed_at.ge(since))
+                .select(cipher_user_changes::cipher_uuid)
+                .load::<CipherId>(conn)
+                .unwrap_or_default()
+                .into_iter()
+                .collect()
+        })
+        .await
+    }
+
+    /// The changes are kept as long as the tombstones, older delta syncs return the whole vault anyway
+    pub async fn purge(conn: &DbConn) -> EmptyResult {
+        let limit = Utc::now().naive_utc() - TimeDelta::days(TOMBSTONE_RETENTION_DAYS);
+        conn.run(move |conn| {
+            diesel::delete(cipher_user_changes::table.filter(cipher_user_changes::changed_at.le(limit)))
+                .execute(conn)
+                .map_res("Error purging cipher changes")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_cipher(cipher_uuid: &CipherId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(cipher_user_changes::table.filter(cipher_user_changes::cipher_uuid.eq(cipher_uuid)))
+                .execute(conn)
+                .map_res("Error deleting cipher changes")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(cipher_user_changes::table.filter(cipher_user_changes::user_uuid.eq(user_uuid)))
+                .execute(conn)
+                .map_res("Error deleting cipher changes")
+        })
+        .await
+    }
+}
@@ -17,8 +17,8 @@ use crate::{
 use macros::UuidFromParam;
 
 use super::{
-    CipherId, CollectionGroup, GroupUser, Membership, MembershipId, MembershipStatus, MembershipType, OrganizationId,
-    User, UserId,
+    AuditGrant, Cipher, CipherId, CipherTemplate, CipherUserChange, CollectionGroup, GroupUser, Membership,
+    MembershipId, MembershipStatus, MembershipType, OrganizationId, Tombstone, TombstoneType, User, UserId,
 };
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
@@ -30,6 +30,10 @@ pub struct Collection {
     pub org_uuid: OrganizationId,
     pub name: String,
     pub external_id: Option<String>,
+    /// New members get read-only access to this collection when they are confirmed
+    pub onboarding: bool,
+    /// Only the members assigned to this collection see its items, if the organization has confidential collections enabled
+    pub confidential: bool,
 }
 
 #[derive(Identifiable, Queryable, Insertable)]
@@ -59,6 +63,8 @@ impl Collection {
             org_uuid,
             name,
             external_id: None,
+            onboarding: false,
+            confidential: false,
         };
 
         new_model.set_external_id(external_id);
@@ -192,10 +198,15 @@ impl Collection {
     }
 
     pub async fn delete(self, conn: &DbConn) -> EmptyResult {
-        self.update_users_revision(conn).await;
+        for member in &Membership::find_by_collection_and_org(&self.uuid, &self.org_uuid, conn).await {
+            User::update_uuid_access_revision(&member.user_uuid, conn).await;
+        }
         CollectionCipher::delete_all_by_collection(&self.uuid, conn).await?;
         CollectionUser::delete_all_by_collection(&self.uuid, conn).await?;
         CollectionGroup::delete_all_by_collection(&self.uuid, &self.org_uuid, conn).await?;
+        AuditGrant::delete_all_by_collection(&self.uuid, conn).await?;
+        CipherTemplate::clear_default_collection(&self.uuid, conn).await?;
+        Tombstone::record(TombstoneType::Collection, &self.uuid, &self.org_uuid, conn).await?;
 
         conn.run(move |conn| {
             diesel::delete(collections::table.filter(collections::uuid.eq(self.uuid)))
@@ -312,6 +323,80 @@ impl Collection {
             .collect()
     }
 
+    pub async fn find_onboarding_by_organization(org_uuid: &OrganizationId, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            collections::table
+                .filter(collections::org_uuid.eq(org_uuid))
+                .filter(collections::onboarding.eq(true))
+                .load::<Self>(conn)
+                .expect("Error loading collections")
+        })
+        .await
+    }
+
+    /// Marks the given collections of the organization as onboarding collections, and unmarks all the others
+    pub async fn set_onboarding_by_organization(
+        org_uuid: &OrganizationId,
+        collection_uuids: &[CollectionId],
+        conn: &DbConn,
+    ) -> EmptyResult {
+        db_run! { conn:
+            sqlite, mysql, postgresql {
+                conn.transaction(|conn| {
+                    diesel::update(collections::table.filter(collections::org_uuid.eq(org_uuid)))
+                        .set(collections::onboarding.eq(false))
+                        .execute(conn)?;
+                    diesel::update(
+                        collections::table
+                            .filter(collections::org_uuid.eq(org_uuid))
+                            .filter(collections::uuid.eq_any(collection_uuids)),
+                    )
+                    .set(collections::onboarding.eq(true))
+                    .execute(conn)?;
+                    Ok::<(), diesel::result::Error>(())
+                })
+                .map_res("Error saving onboarding collections")
+            }
+        }
+    }
+
+    pub async fn find_confidential_by_organization(org_uuid: &OrganizationId, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            collections::table
+                .filter(collections::org_uuid.eq(org_uuid))
+                .filter(collections::confidential.eq(true))
+                .load::<Self>(conn)
+                .expect("Error loading collections")
+        })
+        .await
+    }
+
+    /// Marks the given collections of the organization as confidential collections, and unmarks all the others
+    pub async fn set_confidential_by_organization(
+        org_uuid: &OrganizationId,
+        collection_uuids: &[CollectionId],
+        conn: &DbConn,
+    ) -> EmptyResult {
+        db_run! { conn:
+            sqlite, mysql, postgresql {
+                conn.transaction(|conn| {
+                    diesel::update(collections::table.filter(collections::org_uuid.eq(org_uuid)))
+                        .set(collections::confidential.eq(false))
+                        .execute(conn)?;
+                    diesel::update(
+                        collections::table
+                            .filter(collections::org_uuid.eq(org_uuid))
+                            .filter(collections::uuid.eq_any(collection_uuids)),
+                    )
+                    .set(collections::confidential.eq(true))
+                    .execute(conn)?;
+                    Ok::<(), diesel::result::Error>(())
+                })
+                .map_res("Error saving confidential collections")
+            }
+        }
+    }
+
     pub async fn find_by_organization(org_uuid: &OrganizationId, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
             collections::table
@@ -679,7 +764,7 @@ impl CollectionUser {
         manage: bool,
         conn: &DbConn,
     ) -> EmptyResult {
-        User::update_uuid_revision(user_uuid, conn).await;
+        User::update_uuid_access_revision(user_uuid, conn).await;
 
         db_run! { conn:
             sqlite, mysql {
@@ -735,7 +820,7 @@ impl CollectionUser {
     }
 
     pub async fn delete(self, conn: &DbConn) -> EmptyResult {
-        User::update_uuid_revision(&self.user_uuid, conn).await;
+        User::update_uuid_access_revision(&self.user_uuid, conn).await;
 
         conn.run(move |conn| {
             diesel::delete(
@@ -816,7 +901,7 @@ impl CollectionUser {
 
     pub async fn delete_all_by_collection(collection_uuid: &CollectionId, conn: &DbConn) -> EmptyResult {
         for collection in &CollectionUser::find_by_collection(collection_uuid, conn).await {
-            User::update_uuid_revision(&collection.user_uuid, conn).await;
+            User::update_uuid_access_revision(&collection.user_uuid, conn).await;
         }
 
         conn.run(move |conn| {
@@ -859,9 +944,7 @@ impl CollectionUser {
 /// Database methods
 impl CollectionCipher {
     pub async fn save(cipher_uuid: &CipherId, collection_uuid: &CollectionId, conn: &DbConn) -> EmptyResult {
-        Self::update_users_revision(collection_uuid, conn).await;
-
-        db_run! { conn:
+        let res: EmptyResult = db_run! { conn:
             sqlite, mysql {
                 // Not checking for ForeignKey Constraints here.
                 // Table ciphers_collections does not have ForeignKey Constraints which would cause conflicts.
@@ -885,11 +968,24 @@ impl CollectionCipher {
                     .execute(conn)
                     .map_res("Error adding cipher to collection")
             }
+        };
+        res?;
+
+        // The users of the collection may see the cipher for the first time, without the cipher itself having changed
+        if let Some(cipher) = Cipher::find_by_uuid(cipher_uuid, conn).await {
+            let user_uuids = cipher.update_users_revision(conn).await;
+            CipherUserChange::record(cipher_uuid, user_uuids, conn).await?;
         }
+        Ok(())
     }
 
     pub async fn delete(cipher_uuid: &CipherId, collection_uuid: &CollectionId, conn: &DbConn) -> EmptyResult {
-        Self::update_users_revision(collection_uuid, conn).await;
+        // Users of the collection may lose access to the cipher, which a delta sync can't tell them
+        if let Some(cipher) = Cipher::find_by_uuid(cipher_uuid, conn).await {
+            for user_uuid in cipher.find_user_uuids_with_access(conn).await {
+                User::update_uuid_access_revision(&user_uuid, conn).await;
+            }
+        }
 
         conn.run(move |conn| {
             diesel::delete(
@@ -903,6 +999,17 @@ impl CollectionCipher {
         .await
     }
 
+    pub async fn find_collection_uuids_by_cipher(cipher_uuid: &CipherId, conn: &DbConn) -> Vec<CollectionId> {
+        conn.run(move |conn| {
+            ciphers_collections::table
+                .filter(ciphers_collections::cipher_uuid.eq(cipher_uuid))
+                .select(ciphers_collections::collection_uuid)
+                .load::<CollectionId>(conn)
+                .unwrap_or_default()
+        })
+        .await
+    }
+
     pub async fn delete_all_by_cipher(cipher_uuid: &CipherId, conn: &DbConn) -> EmptyResult {
         conn.run(move |conn| {
             diesel::delete(ciphers_collections::table.filter(ciphers_collections::cipher_uuid.eq(cipher_uuid)))
@@ -920,12 +1027,6 @@ impl CollectionCipher {
         })
         .await
     }
-
-    pub async fn update_users_revision(collection_uuid: &CollectionId, conn: &DbConn) {
-        if let Some(collection) = Collection::find_by_uuid(collection_uuid, conn).await {
-            collection.update_users_revision(conn).await;
-        }
-    }
 }
 
 // Added in case we need the membership_uuid instead of the user_uuid
@@ -5,9 +5,13 @@ use diesel::prelude::*;
 use serde_json::Value;
 
 use crate::{
+    CONFIG,
     api::EmptyResult,
     crypto,
-    db::{DbConn, schema::devices},
+    db::{
+        DbConn,
+        schema::{devices, users},
+    },
     error::MapResult,
     util::{format_date, get_uuid},
 };
@@ -15,6 +19,9 @@ use macros::{IdFromParam, UuidFromParam};
 
 use super::{AuthRequest, UserId};
 
+/// A device is identified by the id the app generated for itself together with the user.
+/// An app used with several accounts has a record per account, each with its own `push_uuid`,
+/// so it is registered at the push relay once per account and a device id alone never identifies a record.
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
 #[diesel(table_name = devices)]
 #[diesel(treat_none_as_null = true)]
@@ -33,6 +40,13 @@ pub struct Device {
 
     pub refresh_token: String,
     pub twofactor_remember: Option<String>,
+
+    // Set by the user from their list of devices, blocked devices can't log in or refresh their tokens anymore
+    pub trusted: bool,
+    pub blocked: bool,
+
+    // Set when the push relay refused the push token, the token is removed by the device cleanup job
+    pub push_rejected_at: Option<NaiveDateTime>,
 }
 
 /// Local methods
@@ -53,6 +67,11 @@ impl Device {
             push_token: None,
             refresh_token: Device::generate_refresh_token(),
             twofactor_remember: None,
+
+            trusted: false,
+            blocked: false,
+
+            push_rejected_at: None,
         }
     }
 
@@ -68,7 +87,10 @@ impl Device {
             "type": self.atype,
             "identifier": self.uuid,
             "creationDate": format_date(&self.created_at),
-            "isTrusted": false,
+            "revisionDate": format_date(&self.updated_at),
+            "isTrusted": self.trusted,
+            "isBlocked": self.blocked,
+            "pushRegistered": self.is_push_registered(),
             "object":"device"
         })
     }
@@ -92,6 +114,11 @@ impl Device {
         self.created_at == self.updated_at
     }
 
+    /// Whether push notifications are delivered to this device
+    pub fn is_push_registered(&self) -> bool {
+        CONFIG.push_enabled() && self.push_token.is_some() && self.push_rejected_at.is_none()
+    }
+
     pub fn is_push_device(&self) -> bool {
         matches!(DeviceType::from_i32(self.atype), DeviceType::Android | DeviceType::Ios)
     }
@@ -122,8 +149,11 @@ impl DeviceWithAuthRequest {
             "type": self.device.atype,
             "identifier": self.device.uuid,
             "creationDate": format_date(&self.device.created_at),
+            "revisionDate": format_date(&self.device.updated_at),
             "devicePendingAuthRequest": auth_request,
-            "isTrusted": false,
+            "isTrusted": self.device.trusted,
+            "isBlocked": self.device.blocked,
+            "pushRegistered": self.device.is_push_registered(),
             "encryptedPublicKey": null,
             "encryptedUserKey": null,
             "object": "device",
@@ -205,20 +235,61 @@ impl Device {
         .await
     }
 
-    pub async fn find_by_uuid(uuid: &DeviceId, conn: &DbConn) -> Option<Self> {
-        conn.run(move |conn| devices::table.filter(devices::uuid.eq(uuid)).first::<Self>(conn).ok()).await
+    pub async fn find_trusted_by_user(user_uuid: &UserId, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            devices::table
+                .filter(devices::user_uuid.eq(user_uuid))
+                .filter(devices::trusted.eq(true))
+                .load::<Self>(conn)
+                .expect("Error loading devices")
+        })
+        .await
     }
 
-    pub async fn clear_push_token_by_uuid(uuid: &DeviceId, conn: &DbConn) -> EmptyResult {
+    /// Returns the devices of the user, the most recently used one first
+    pub async fn find_by_user_latest_first(user_uuid: &UserId, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
-            diesel::update(devices::table)
-                .filter(devices::uuid.eq(uuid))
-                .set(devices::push_token.eq::<Option<String>>(None))
-                .execute(conn)
-                .map_res("Error removing push token")
+            devices::table
+                .filter(devices::user_uuid.eq(user_uuid))
+                .order(devices::updated_at.desc())
+                .load::<Self>(conn)
+                .expect("Error loading devices")
+        })
+        .await
+    }
+
+    /// Returns the devices of the user which are also used with other accounts, once per other account with its email
+    pub async fn find_shared_by_user(user_uuid: &UserId, conn: &DbConn) -> Vec<(DeviceId, String)> {
+        conn.run(move |conn| {
+            let device_uuids = devices::table
+                .filter(devices::user_uuid.eq(user_uuid))
+                .select(devices::uuid)
+                .load::<DeviceId>(conn)
+                .expect("Error loading devices");
+            devices::table
+                .inner_join(users::table)
+                .filter(devices::uuid.eq_any(device_uuids))
+                .filter(devices::user_uuid.ne(user_uuid))
+                .select((devices::uuid, users::email))
+                .load::<(DeviceId, String)>(conn)
+                .expect("Error loading shared devices")
+        })
+        .await
+    }
+
+    /// Returns the records of other devices which still have the push token, for example of a reinstalled app.
+    /// Records of other accounts on the same device are not included, they share the token on purpose.
+    pub async fn find_by_push_token_of_other_devices(push_token: &str, uuid: &DeviceId, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            devices::table
+                .filter(devices::push_token.eq(push_token))
+                .filter(devices::uuid.ne(uuid))
+                .load::<Self>(conn)
+                .expect("Error loading devices")
         })
         .await
     }
+
     pub async fn find_by_refresh_token(refresh_token: &str, conn: &DbConn) -> Option<Self> {
         conn.run(move |conn| devices::table.filter(devices::refresh_token.eq(refresh_token)).first::<Self>(conn).ok())
             .await
@@ -240,6 +311,7 @@ impl Device {
             devices::table
                 .filter(devices::user_uuid.eq(user_uuid))
                 .filter(devices::push_token.is_not_null())
+                .filter(devices::push_rejected_at.is_null())
                 .load::<Self>(conn)
                 .expect("Error loading push devices")
         })
@@ -251,6 +323,7 @@ impl Device {
             devices::table
                 .filter(devices::user_uuid.eq(user_uuid))
                 .filter(devices::push_token.is_not_null())
+                .filter(devices::push_rejected_at.is_null())
                 .count()
                 .first::<i64>(conn)
                 .ok()
@@ -260,6 +333,67 @@ impl Device {
         .await
     }
 
+    pub async fn count_active_since(since: NaiveDateTime, conn: &DbConn) -> i64 {
+        conn.run(move |conn| {
+            devices::table.filter(devices::updated_at.ge(since)).count().first::<i64>(conn).ok().unwrap_or(0)
+        })
+        .await
+    }
+
+    pub async fn find_push_rejected(conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            devices::table
+                .filter(devices::push_token.is_not_null())
+                .filter(devices::push_rejected_at.is_not_null())
+                .load::<Self>(conn)
+                .expect("Error loading push rejected devices")
+        })
+        .await
+    }
+
+    pub async fn find_push_registered(conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            devices::table
+                .filter(devices::push_token.is_not_null())
+                .filter(devices::push_rejected_at.is_null())
+                .load::<Self>(conn)
+                .expect("Error loading push devices")
+        })
+        .await
+    }
+
+    pub async fn count_push_registered(conn: &DbConn) -> i64 {
+        conn.run(move |conn| {
+            devices::table
+                .filter(devices::push_token.is_not_null())
+                .filter(devices::push_rejected_at.is_null())
+                .count()
+                .first::<i64>(conn)
+                .ok()
+                .unwrap_or(0)
+        })
+        .await
+    }
+
+    /// Returns the devices which weren't used since the given date
+    pub async fn find_unused_since(since: NaiveDateTime, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            devices::table.filter(devices::updated_at.lt(since)).load::<Self>(conn).expect("Error loading devices")
+        })
+        .await
+    }
+
+    pub async fn delete(self, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(
+                devices::table.filter(devices::uuid.eq(self.uuid)).filter(devices::user_uuid.eq(self.user_uuid)),
+            )
+            .execute(conn)
+            .map_res("Error removing device")
+        })
+        .await
+    }
+
     pub async fn rotate_refresh_tokens_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
         // Generate a new token per device.
         // We cannot do a single UPDATE with one value because each device needs a unique token.
@@ -364,6 +498,33 @@ impl DeviceType {
             _ => DeviceType::UnknownBrowser,
         }
     }
+
+    /// The client type as used by `MIN_CLIENT_VERSIONS`, `None` for the SDK and server integrations
+    pub fn client_type(&self) -> Option<&'static str> {
+        match self {
+            DeviceType::Android | DeviceType::Ios | DeviceType::AndroidAmazon => Some("mobile"),
+            DeviceType::ChromeExtension
+            | DeviceType::FirefoxExtension
+            | DeviceType::OperaExtension
+            | DeviceType::EdgeExtension
+            | DeviceType::VivaldiExtension
+            | DeviceType::SafariExtension => Some("browser"),
+            DeviceType::WindowsDesktop | DeviceType::MacOsDesktop | DeviceType::LinuxDesktop | DeviceType::Uwp => {
+                Some("desktop")
+            }
+            DeviceType::ChromeBrowser
+            | DeviceType::FirefoxBrowser
+            | DeviceType::OperaBrowser
+            | DeviceType::EdgeBrowser
+            | DeviceType::IEBrowser
+            | DeviceType::UnknownBrowser
+            | DeviceType::SafariBrowser
+            | DeviceType::VivaldiBrowser
+            | DeviceType::DuckDuckGoBrowser => Some("web"),
+            DeviceType::WindowsCLI | DeviceType::MacOsCLI | DeviceType::LinuxCLI => Some("cli"),
+            DeviceType::Sdk | DeviceType::Server => None,
+        }
+    }
 }
 
 #[derive(
@@ -12,7 +12,7 @@ use crate::{
     error::MapResult,
 };
 
-use super::{CipherId, CollectionId, GroupId, MembershipId, OrgPolicyId, OrganizationId, UserId};
+use super::{CipherId, CollectionId, GroupId, MembershipId, OrgPolicyId, OrganizationId, SendId, UserId};
 
 // https://bitwarden.com/help/event-logs/
 
@@ -41,6 +41,29 @@ pub struct Event {
     pub provider_uuid: Option<String>,
     pub provider_user_uuid: Option<String>,
     pub provider_org_uuid: Option<String>,
+    // Vaultwarden specific, the Send of the organization Send events
+    pub send_uuid: Option<SendId>,
+}
+
+/// The number of events of one type on one day, see `Event::count_by_org_per_day`
+#[derive(QueryableByName)]
+pub struct EventActivity {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub day: String,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub event_type: i32,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub total: i64,
+}
+
+impl EventActivity {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "date": self.day,
+            "type": self.event_type,
+            "count": self.total,
+        })
+    }
 }
 
 // Upstream enum: https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Core/AdminConsole/Enums/EventType.cs
@@ -133,12 +156,22 @@ pub enum EventType {
     // ProviderOrganizationRemoved = 1902, // Not supported
     // ProviderOrganizationVaultAccessed = 1903, // Not supported
 
-    // OrganizationDomainAdded = 2000, // Not supported
-    // OrganizationDomainRemoved = 2001, // Not supported
-    // OrganizationDomainVerified = 2002, // Not supported
-    // OrganizationDomainNotVerified = 2003, // Not supported
-
+    // Organization domains
+    OrganizationDomainAdded = 2000,
+    OrganizationDomainRemoved = 2001,
+    OrganizationDomainVerified = 2002,
+    OrganizationDomainNotVerified = 2003,
     // SecretRetrieved = 2100, // Not supported
+
+    // Organization Sends (Vaultwarden specific)
+    OrganizationSendCreated = 2200,
+    OrganizationSendDeleted = 2201,
+
+    // Organization policies (Vaultwarden specific)
+    CipherAttachmentBlocked = 2300,
+
+    // Audit grants (Vaultwarden specific)
+    CollectionAuditGrantRead = 2400,
 }
 
 /// Local methods
@@ -166,6 +199,7 @@ impl Event {
             provider_uuid: None,
             provider_user_uuid: None,
             provider_org_uuid: None,
+            send_uuid: None,
         }
     }
 
@@ -188,15 +222,50 @@ impl Event {
             "providerId": self.provider_uuid,
             "providerUserId": self.provider_user_uuid,
             "providerOrganizationId": self.provider_org_uuid,
+            "sendId": self.send_uuid, // Vaultwarden specific
             // "installationId": null, // Not supported
         })
     }
+
+    /// The representation used by the public API, which SIEM collectors consume
+    /// Upstream: https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Api/AdminConsole/Public/Models/Response/EventResponseModel.cs
+    pub fn to_public_json(&self) -> Value {
+        use crate::util::format_date;
+
+        json!({
+            "object": "event",
+            "type": self.event_type,
+            "itemId": self.cipher_uuid,
+            "collectionId": self.collection_uuid,
+            "groupId": self.group_uuid,
+            "policyId": self.policy_uuid,
+            "memberId": self.org_user_uuid,
+            "actingUserId": self.act_user_uuid,
+            "installationId": null, // Not supported
+            "date": format_date(&self.event_date),
+            "device": self.device_type,
+            "ipAddress": self.ip_address,
+        })
+    }
+
+    /// The continuation token pointing after this event.
+    /// Both the date and the uuid are needed, multiple events can share the same date.
+    pub fn public_continuation_token(&self) -> String {
+        format!("{}_{}", crate::util::format_date(&self.event_date), self.uuid.0)
+    }
+
+    fn parse_public_continuation_token(token: &str) -> Option<(NaiveDateTime, EventId)> {
+        let (date, uuid) = token.split_once('_')?;
+        let date = chrono::DateTime::parse_from_rfc3339(date).ok()?.naive_utc();
+        Some((date, EventId(uuid.to_owned())))
+    }
 }
 
 /// Database methods
 /// https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Core/AdminConsole/Services/Implementations/EventService.cs
 impl Event {
     pub const PAGE_SIZE: i64 = 30;
+    pub const PUBLIC_PAGE_SIZE: i64 = 100;
 
     /// #############
     /// Basic Queries
@@ -282,6 +351,101 @@ impl Event {
         .await
     }
 
+    /// Counts the events of the organization per day (in UTC) and event type.
+    /// The grouping is done by the database, only the function to get the day differs between the backends.
+    pub async fn count_by_org_per_day(
+        org_uuid: &OrganizationId,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        conn: &DbConn,
+    ) -> Result<Vec<EventActivity>, crate::Error> {
+        use diesel::sql_types::{Text, Timestamp};
+
+        let org_uuid = org_uuid.to_string();
+        db_run! { conn:
+            sqlite {
+                diesel::sql_query(
+                    "SELECT date(event_date) AS day, event_type, COUNT(*) AS total FROM event \
+                     WHERE org_uuid = ? AND event_date BETWEEN ? AND ? \
+                     GROUP BY day, event_type ORDER BY day, event_type",
+                )
+                .bind::<Text, _>(org_uuid)
+                .bind::<Timestamp, _>(start)
+                .bind::<Timestamp, _>(end)
+                .load::<EventActivity>(conn)
+                .map_res("Error counting events")
+            }
+            mysql {
+                diesel::sql_query(
+                    "SELECT DATE_FORMAT(event_date, '%Y-%m-%d') AS day, event_type, COUNT(*) AS total FROM event \
+                     WHERE org_uuid = ? AND event_date BETWEEN ? AND ? \
+                     GROUP BY day, event_type ORDER BY day, event_type",
+                )
+                .bind::<Text, _>(org_uuid)
+                .bind::<Timestamp, _>(start)
+                .bind::<Timestamp, _>(end)
+                .load::<EventActivity>(conn)
+                .map_res("Error counting events")
+            }
+            postgresql {
+                diesel::sql_query(
+                    "SELECT to_char(event_date, 'YYYY-MM-DD') AS day, event_type, COUNT(*) AS total FROM event \
+                     WHERE org_uuid = $1 AND event_date BETWEEN $2 AND $3 \
+                     GROUP BY day, event_type ORDER BY day, event_type",
+                )
+                .bind::<Text, _>(org_uuid)
+                .bind::<Timestamp, _>(start)
+                .bind::<Timestamp, _>(end)
+                .load::<EventActivity>(conn)
+                .map_res("Error counting events")
+            }
+        }
+    }
+
+    /// Returns one page of organization events for the public API, newest first.
+    /// Returns `None` when the continuation token is invalid.
+    pub async fn find_by_org_public(
+        org_uuid: &OrganizationId,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+        acting_user_uuid: Option<&UserId>,
+        cipher_uuid: Option<&CipherId>,
+        continuation_token: Option<&str>,
+        conn: &DbConn,
+    ) -> Option<Vec<Self>> {
+        let after = match continuation_token {
+            Some(token) => Some(Self::parse_public_continuation_token(token)?),
+            None => None,
+        };
+
+        Some(
+            conn.run(move |conn| {
+                let mut query = event::table
+                    .filter(event::org_uuid.eq(org_uuid))
+                    .filter(event::event_date.between(start, end))
+                    .into_boxed();
+
+                if let Some(acting_user_uuid) = acting_user_uuid {
+                    query = query.filter(event::act_user_uuid.eq(acting_user_uuid));
+                }
+                if let Some(cipher_uuid) = cipher_uuid {
+                    query = query.filter(event::cipher_uuid.eq(cipher_uuid));
+                }
+                if let Some((date, uuid)) = after {
+                    query = query
+                        .filter(event::event_date.lt(date).or(event::event_date.eq(date).and(event::uuid.lt(uuid))));
+                }
+
+                query
+                    .order_by((event::event_date.desc(), event::uuid.desc()))
+                    .limit(Self::PUBLIC_PAGE_SIZE)
+                    .load::<Self>(conn)
+                    .expect("Error filtering events")
+            })
+            .await,
+        )
+    }
+
     pub async fn count_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> i64 {
         conn.run(move |conn| {
             event::table.filter(event::org_uuid.eq(org_uuid)).count().first::<i64>(conn).ok().unwrap_or(0)
@@ -333,6 +497,13 @@ impl Event {
         .await
     }
 
+    pub async fn count_since(since: NaiveDateTime, conn: &DbConn) -> i64 {
+        conn.run(move |conn| {
+            event::table.filter(event::event_date.ge(since)).count().first::<i64>(conn).ok().unwrap_or(0)
+        })
+        .await
+    }
+
     pub async fn clean_events(conn: &DbConn) -> EmptyResult {
         if let Some(days_to_retain) = CONFIG.events_days_retain() {
             let dt = Utc::now().naive_utc() - TimeDelta::try_days(days_to_retain).unwrap();
@@ -6,7 +6,7 @@ use crate::{
     error::MapResult,
 };
 
-use super::{CipherId, User, UserId};
+use super::{CipherId, CipherUserChange, User, UserId};
 
 #[derive(Identifiable, Queryable, Insertable)]
 #[diesel(table_name = favorites)]
@@ -41,6 +41,7 @@ impl Favorite {
         match (old, new) {
             (false, true) => {
                 User::update_uuid_revision(user_uuid, conn).await;
+                CipherUserChange::record(cipher_uuid, vec![user_uuid.clone()], conn).await?;
                 conn.run(move |conn| {
                     diesel::insert_into(favorites::table)
                         .values((favorites::user_uuid.eq(user_uuid), favorites::cipher_uuid.eq(cipher_uuid)))
@@ -51,6 +52,7 @@ impl Favorite {
             }
             (true, false) => {
                 User::update_uuid_revision(user_uuid, conn).await;
+                CipherUserChange::record(cipher_uuid, vec![user_uuid.clone()], conn).await?;
                 conn.run(move |conn| {
                     diesel::delete(
                         favorites::table
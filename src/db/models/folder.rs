@@ -13,7 +13,7 @@ use crate::{
 };
 use macros::UuidFromParam;
 
-use super::{CipherId, User, UserId};
+use super::{CipherId, Tombstone, TombstoneType, User, UserId, cipher::BULK_INSERT_CHUNK_SIZE};
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
 #[diesel(table_name = folders)]
@@ -109,6 +109,7 @@ impl Folder {
     pub async fn delete(&self, conn: &DbConn) -> EmptyResult {
         User::update_uuid_revision(&self.user_uuid, conn).await;
         FolderCipher::delete_all_by_folder(&self.uuid, conn).await?;
+        Tombstone::record(TombstoneType::Folder, &self.uuid, &self.user_uuid, conn).await?;
 
         conn.run(move |conn| {
             diesel::delete(folders::table.filter(folders::uuid.eq(&self.uuid)))
@@ -167,6 +168,21 @@ impl FolderCipher {
         }
     }
 
+    /// Inserts new folder relations in batches, used by imports
+    pub async fn insert_many(folder_ciphers: &[Self], conn: &DbConn) -> EmptyResult {
+        db_run! { conn:
+            sqlite, mysql, postgresql {
+                conn.transaction(|conn| {
+                    for chunk in folder_ciphers.chunks(BULK_INSERT_CHUNK_SIZE) {
+                        diesel::insert_into(folders_ciphers::table).values(chunk).execute(conn)?;
+                    }
+                    Ok::<(), diesel::result::Error>(())
+                })
+                .map_res("Error adding ciphers to folders")
+            }
+        }
+    }
+
     pub async fn delete(self, conn: &DbConn) -> EmptyResult {
         conn.run(move |conn| {
             diesel::delete(
@@ -0,0 +1,89 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+
+use crate::{
+    api::{EmptyResult, core::response_cache},
+    db::{DbConn, schema::global_domain_overrides},
+    error::MapResult,
+};
+
+/// A change of the admin to one of the built-in global equivalent domains, or a custom set of the instance.
+/// The domains are stored as a json array, a disabled entry removes the built-in set of the same type.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = global_domain_overrides)]
+#[diesel(primary_key(atype))]
+pub struct GlobalDomainOverride {
+    pub atype: i32,
+    pub domains: String,
+    pub disabled: bool,
+    pub updated_at: NaiveDateTime,
+}
+
+impl GlobalDomainOverride {
+    pub fn new(atype: i32, domains: &[String], disabled: bool) -> Self {
+        Self {
+            atype,
+            domains: serde_json::to_string(domains).unwrap_or_else(|_| "[]".to_owned()),
+            disabled,
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+
+    pub fn domain_list(&self) -> Vec<String> {
+        serde_json::from_str(&self.domains).unwrap_or_default()
+    }
+}
+
+/// Database methods
+impl GlobalDomainOverride {
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        let res = db_run! { conn:
+            sqlite, mysql {
+                diesel::replace_into(global_domain_overrides::table)
+                    .values(self)
+                    .execute(conn)
+                    .map_res("Error saving global domain override")
+            }
+            postgresql {
+                diesel::insert_into(global_domain_overrides::table)
+                    .values(self)
+                    .on_conflict(global_domain_overrides::atype)
+                    .do_update()
+                    .set(self)
+                    .execute(conn)
+                    .map_res("Error saving global domain override")
+            }
+        };
+        response_cache::invalidate_all();
+        res
+    }
+
+    pub async fn delete(self, conn: &DbConn) -> EmptyResult {
+        let res = conn
+            .run(move |conn| {
+                diesel::delete(global_domain_overrides::table.filter(global_domain_overrides::atype.eq(self.atype)))
+                    .execute(conn)
+                    .map_res("Error deleting global domain override")
+            })
+            .await;
+        response_cache::invalidate_all();
+        res
+    }
+
+    pub async fn find_all(conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            global_domain_overrides::table
+                .order(global_domain_overrides::atype.asc())
+                .load::<Self>(conn)
+                .expect("Error loading global domain overrides")
+        })
+        .await
+    }
+
+    pub async fn find_by_type(atype: i32, conn: &DbConn) -> Option<Self> {
+        conn.run(move |conn| {
+            global_domain_overrides::table.filter(global_domain_overrides::atype.eq(atype)).first::<Self>(conn).ok()
+        })
+        .await
+    }
+}
@@ -611,7 +611,7 @@ impl GroupUser {
 
     pub async fn update_user_revision(&self, conn: &DbConn) {
         match Membership::find_by_uuid(&self.users_organizations_uuid, conn).await {
-            Some(member) => User::update_uuid_revision(&member.user_uuid, conn).await,
+            Some(member) => User::update_uuid_access_revision(&member.user_uuid, conn).await,
             None => warn!("Member could not be found!"),
         }
     }
@@ -622,7 +622,7 @@ impl GroupUser {
         conn: &DbConn,
     ) -> EmptyResult {
         match Membership::find_by_uuid(member_uuid, conn).await {
-            Some(member) => User::update_uuid_revision(&member.user_uuid, conn).await,
+            Some(member) => User::update_uuid_access_revision(&member.user_uuid, conn).await,
             None => warn!("Member could not be found!"),
         }
 
@@ -653,7 +653,7 @@ impl GroupUser {
 
     pub async fn delete_all_by_member(member_uuid: &MembershipId, conn: &DbConn) -> EmptyResult {
         match Membership::find_by_uuid(member_uuid, conn).await {
-            Some(member) => User::update_uuid_revision(&member.user_uuid, conn).await,
+            Some(member) => User::update_uuid_access_revision(&member.user_uuid, conn).await,
             None => warn!("Member could not be found!"),
         }
 
@@ -0,0 +1,124 @@
+use std::sync::LazyLock;
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::job_locks},
+    error::MapResult,
+    util::get_uuid,
+};
+
+// Identifies this instance as the holder of a job lock
+static HOLDER_ID: LazyLock<String> = LazyLock::new(get_uuid);
+
+/// A lease on a scheduled job, used to prevent multiple instances sharing the same database
+/// from running the same job at the same time.
+/// Resumable attachment uploads use it as well, to pin each upload to the instance holding its partial data.
+#[derive(Identifiable, Queryable, Insertable)]
+#[diesel(table_name = job_locks)]
+#[diesel(primary_key(name))]
+pub struct JobLock {
+    pub name: String,
+    pub holder: String,
+    pub locked_until: NaiveDateTime,
+}
+
+/// Local methods
+impl JobLock {
+    pub fn holder_id() -> &'static str {
+        &HOLDER_ID
+    }
+
+    pub fn is_held_by_us(&self) -> bool {
+        self.holder == *HOLDER_ID
+    }
+
+    fn expiration(ttl: u64) -> NaiveDateTime {
+        Utc::now().naive_utc() + TimeDelta::try_seconds(ttl.try_into().unwrap()).unwrap()
+    }
+}
+
+/// Database methods
+impl JobLock {
+    /// Tries to take the lock of the given job for `ttl` seconds.
+    /// Returns `true` if the lock was free, or held by another instance but expired.
+    pub async fn try_acquire(name: &str, ttl: u64, conn: &DbConn) -> bool {
+        let now = Utc::now().naive_utc();
+        let locked_until = Self::expiration(ttl);
+
+        conn.run(move |conn| {
+            // Take over an expired lock
+            let updated = diesel::update(job_locks::table)
+                .filter(job_locks::name.eq(name))
+                .filter(job_locks::locked_until.lt(now))
+                .set((job_locks::holder.eq(&*HOLDER_ID), job_locks::locked_until.eq(locked_until)))
+                .execute(conn)
+                .unwrap_or_default();
+            if updated == 1 {
+                return true;
+            }
+
+            // No lock exists yet, this fails on the primary key if another instance holds the lock
+            diesel::insert_into(job_locks::table)
+                .values(Self {
+                    name: name.to_owned(),
+                    holder: HOLDER_ID.clone(),
+                    locked_until,
+                })
+                .execute(conn)
+                .is_ok()
+        })
+        .await
+    }
+
+    /// Extends a lock held by this instance for another `ttl` seconds.
+    /// Returns `false` if the lock was lost in the meantime.
+    pub async fn renew(name: &str, ttl: u64, conn: &DbConn) -> bool {
+        let locked_until = Self::expiration(ttl);
+
+        conn.run(move |conn| {
+            diesel::update(job_locks::table)
+                .filter(job_locks::name.eq(name))
+                .filter(job_locks::holder.eq(&*HOLDER_ID))
+                .set(job_locks::locked_until.eq(locked_until))
+                .execute(conn)
+                .is_ok_and(|updated| updated == 1)
+        })
+        .await
+    }
+
+    pub async fn release(name: &str, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(job_locks::table.filter(job_locks::name.eq(name)).filter(job_locks::holder.eq(&*HOLDER_ID)))
+                .execute(conn)
+                .map_res("Error releasing job lock")
+        })
+        .await
+    }
+
+    pub async fn find_active_by_name(name: &str, conn: &DbConn) -> Option<Self> {
+        let now = Utc::now().naive_utc();
+        conn.run(move |conn| {
+            job_locks::table
+                .filter(job_locks::name.eq(name))
+                .filter(job_locks::locked_until.ge(now))
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
+    pub async fn find_active(conn: &DbConn) -> Vec<Self> {
+        let now = Utc::now().naive_utc();
+        conn.run(move |conn| {
+            job_locks::table
+                .filter(job_locks::locked_until.ge(now))
+                .order(job_locks::name)
+                .load::<Self>(conn)
+                .unwrap_or_default()
+        })
+        .await
+    }
+}
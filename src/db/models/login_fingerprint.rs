@@ -0,0 +1,107 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+
+use crate::{
+    api::EmptyResult,
+    db::{
+        DbConn, DbConnInner,
+        models::{Device, DeviceId, UserId},
+        schema::login_fingerprints,
+    },
+    error::MapResult,
+};
+
+/// The combinations of device, device type and country a user logged in from.
+/// A login with a combination which wasn't seen before is notified by email.
+/// An empty country means it is unknown, either because `COUNTRY_HEADER` isn't set, or for the devices known before this was tracked.
+#[derive(Identifiable, Queryable, Insertable)]
+#[diesel(table_name = login_fingerprints)]
+#[diesel(primary_key(user_uuid, device_uuid, device_type, country))]
+pub struct LoginFingerprint {
+    pub user_uuid: UserId,
+    pub device_uuid: DeviceId,
+    pub device_type: i32,
+    pub country: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(PartialEq)]
+pub enum FingerprintStatus {
+    Known,
+    /// Known without a country, the country only needs to be stored
+    KnownWithoutCountry,
+    New,
+}
+
+/// Database methods
+impl LoginFingerprint {
+    /// Checks whether the login was seen before for the user, without storing it.
+    /// A device known without a country isn't new, so enabling `COUNTRY_HEADER` doesn't notify all users.
+    pub async fn find_status(device: &Device, country: Option<&str>, conn: &DbConn) -> FingerprintStatus {
+        let user_uuid = device.user_uuid.clone();
+        let device_uuid = device.uuid.clone();
+        let device_type = device.atype;
+        let country = country.unwrap_or_default().to_owned();
+
+        conn.run(move |conn| {
+            let known = |conn: &mut DbConnInner, country: &str| {
+                login_fingerprints::table
+                    .filter(login_fingerprints::user_uuid.eq(&user_uuid))
+                    .filter(login_fingerprints::device_uuid.eq(&device_uuid))
+                    .filter(login_fingerprints::device_type.eq(device_type))
+                    .filter(login_fingerprints::country.eq(country))
+                    .count()
+                    .first::<i64>(conn)
+                    .unwrap_or(0)
+                    > 0
+            };
+            if known(conn, &country) {
+                FingerprintStatus::Known
+            } else if !country.is_empty() && known(conn, "") {
+                FingerprintStatus::KnownWithoutCountry
+            } else {
+                FingerprintStatus::New
+            }
+        })
+        .await
+    }
+
+    /// Stores the fingerprint of the login, replacing the one of the same device without a country.
+    /// Only called once the user was notified, so a failed notification is tried again on the next login.
+    pub async fn save(device: &Device, country: Option<&str>, conn: &DbConn) -> EmptyResult {
+        let fingerprint = Self {
+            user_uuid: device.user_uuid.clone(),
+            device_uuid: device.uuid.clone(),
+            device_type: device.atype,
+            country: country.unwrap_or_default().to_owned(),
+            created_at: Utc::now().naive_utc(),
+        };
+
+        conn.run(move |conn| {
+            conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                if !fingerprint.country.is_empty() {
+                    diesel::delete(
+                        login_fingerprints::table
+                            .filter(login_fingerprints::user_uuid.eq(&fingerprint.user_uuid))
+                            .filter(login_fingerprints::device_uuid.eq(&fingerprint.device_uuid))
+                            .filter(login_fingerprints::device_type.eq(fingerprint.device_type))
+                            .filter(login_fingerprints::country.eq("")),
+                    )
+                    .execute(conn)?;
+                }
+                diesel::insert_into(login_fingerprints::table).values(&fingerprint).execute(conn)
+            })
+            .map_res("Error saving login fingerprint")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(login_fingerprints::table.filter(login_fingerprints::user_uuid.eq(user_uuid)))
+                .execute(conn)
+                .map_res("Error deleting login fingerprints")
+        })
+        .await
+    }
+}
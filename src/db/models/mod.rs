@@ -1,45 +1,79 @@
+mod admin_audit_log;
 mod archive;
 mod attachment;
+mod attachment_rekey;
+mod attachment_share;
+mod audit_grant;
 mod auth_request;
 mod cipher;
+mod cipher_template;
+mod cipher_user_change;
 mod collection;
 mod device;
 mod emergency_access;
 mod event;
 mod favorite;
 mod folder;
+mod global_domain_override;
 mod group;
+mod job_lock;
+mod login_fingerprint;
+mod org_cache;
+mod org_domain;
 mod org_policy;
 mod organization;
+mod quota_warning;
 mod send;
+mod send_download_token;
+mod send_egress;
 mod sso_auth;
+mod tombstone;
 mod two_factor;
 mod two_factor_duo_context;
 mod two_factor_incomplete;
+mod usage_snapshot;
 mod user;
 
+pub use self::admin_audit_log::AdminAuditLog;
 pub use self::archive::Archive;
 pub use self::attachment::{Attachment, AttachmentId};
+pub use self::attachment_rekey::AttachmentRekey;
+pub use self::attachment_share::AttachmentShare;
+pub use self::audit_grant::AuditGrant;
 pub use self::auth_request::{AuthRequest, AuthRequestId};
 pub use self::cipher::{Cipher, CipherId, RepromptType};
+pub use self::cipher_template::{CipherTemplate, CipherTemplateId, TemplateField};
+pub use self::cipher_user_change::CipherUserChange;
 pub use self::collection::{Collection, CollectionCipher, CollectionId, CollectionUser};
 pub use self::device::{Device, DeviceId, DeviceType, DeviceWithAuthRequest, PushId};
 pub use self::emergency_access::{EmergencyAccess, EmergencyAccessId, EmergencyAccessStatus, EmergencyAccessType};
-pub use self::event::{Event, EventType};
+pub use self::event::{Event, EventActivity, EventType};
 pub use self::favorite::Favorite;
 pub use self::folder::{Folder, FolderCipher, FolderId};
+pub use self::global_domain_override::GlobalDomainOverride;
 pub use self::group::{CollectionGroup, Group, GroupId, GroupUser};
-pub use self::org_policy::{OrgPolicy, OrgPolicyId, OrgPolicyType};
+pub use self::job_lock::JobLock;
+pub use self::login_fingerprint::{FingerprintStatus, LoginFingerprint};
+pub use self::org_domain::{OrgDomain, OrgDomainId};
+pub use self::org_policy::{
+    AttachmentPolicyData, OrgPolicy, OrgPolicyId, OrgPolicyType, PasswordHintPolicyData, RepromptPolicyData,
+    TrashRetentionPolicyData, TwoFactorPolicyData,
+};
 pub use self::organization::{
-    Membership, MembershipId, MembershipStatus, MembershipType, OrgApiKeyId, Organization, OrganizationApiKey,
-    OrganizationId,
+    Membership, MembershipId, MembershipStatus, MembershipType, OrgApiKeyId, OrgApiKeyType, Organization,
+    OrganizationApiKey, OrganizationId,
 };
+pub use self::quota_warning::QuotaWarning;
 pub use self::send::{
     Send, SendType,
     id::{SendFileId, SendId},
 };
+pub use self::send_download_token::SendDownloadToken;
+pub use self::send_egress::SendEgress;
 pub use self::sso_auth::{OIDCAuthenticatedUser, OIDCCodeResponseError, SsoAuth};
+pub use self::tombstone::{Tombstone, TombstoneType};
 pub use self::two_factor::{TwoFactor, TwoFactorType};
 pub use self::two_factor_duo_context::TwoFactorDuoContext;
 pub use self::two_factor_incomplete::TwoFactorIncomplete;
+pub use self::usage_snapshot::UsageSnapshot;
 pub use self::user::{Invitation, SsoUser, User, UserId, UserKdfType, UserStampException};
@@ -0,0 +1,65 @@
+use std::{sync::LazyLock, time::Duration};
+
+use moka::sync::Cache;
+
+use crate::CONFIG;
+
+use super::{Membership, OrganizationId, UserId};
+
+// Short lived in-process caches of the policy and membership lookups done on every cipher write.
+// Imports and bulk edits would otherwise run the same queries for every single item.
+// Every change to a policy or membership clears both caches, the TTL limits how long changes
+// done by other instances sharing the same database can go unnoticed.
+type PolicyKey = (UserId, i32, Option<OrganizationId>);
+type MembershipKey = (UserId, OrganizationId);
+
+static POLICIES: LazyLock<Cache<PolicyKey, bool>> = LazyLock::new(build_cache);
+static CONFIRMED_MEMBERSHIPS: LazyLock<Cache<MembershipKey, Option<Membership>>> = LazyLock::new(build_cache);
+
+fn build_cache<K, V>() -> Cache<K, V>
+where
+    K: std::hash::Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    Cache::builder().max_capacity(10_000).time_to_live(Duration::from_secs(CONFIG.org_cache_ttl().max(1))).build()
+}
+
+fn is_enabled() -> bool {
+    CONFIG.org_cache_ttl() > 0
+}
+
+pub(super) fn get_policy(key: &PolicyKey) -> Option<bool> {
+    if is_enabled() {
+        POLICIES.get(key)
+    } else {
+        None
+    }
+}
+
+pub(super) fn set_policy(key: PolicyKey, applicable: bool) {
+    if is_enabled() {
+        POLICIES.insert(key, applicable);
+    }
+}
+
+pub(super) fn get_confirmed_membership(key: &MembershipKey) -> Option<Option<Membership>> {
+    if is_enabled() {
+        CONFIRMED_MEMBERSHIPS.get(key)
+    } else {
+        None
+    }
+}
+
+pub(super) fn set_confirmed_membership(key: MembershipKey, membership: Option<&Membership>) {
+    if is_enabled() {
+        CONFIRMED_MEMBERSHIPS.insert(key, membership.cloned());
+    }
+}
+
+/// Clears all cached lookups, needs to be called whenever a policy or membership changes
+pub(super) fn invalidate() {
+    if is_enabled() {
+        POLICIES.invalidate_all();
+        CONFIRMED_MEMBERSHIPS.invalidate_all();
+    }
+}
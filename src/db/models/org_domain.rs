@@ -0,0 +1,188 @@
+use chrono::{NaiveDateTime, Utc};
+use derive_more::{AsRef, Deref, Display, From};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::org_domains},
+    error::MapResult,
+    util::format_date,
+};
+use macros::UuidFromParam;
+
+use super::OrganizationId;
+
+/// A domain claimed by an organization.
+/// The claim only becomes active once the `txt` value was found in a DNS TXT record of the domain.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = org_domains)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(primary_key(uuid))]
+pub struct OrgDomain {
+    pub uuid: OrgDomainId,
+    pub org_uuid: OrganizationId,
+    pub domain_name: String,
+    pub txt: String,
+    pub created_at: NaiveDateTime,
+    pub verified_at: Option<NaiveDateTime>,
+    pub last_checked_at: Option<NaiveDateTime>,
+}
+
+/// Local methods
+impl OrgDomain {
+    pub fn new(org_uuid: OrganizationId, domain_name: &str) -> Self {
+        Self {
+            uuid: OrgDomainId(crate::util::get_uuid()),
+            org_uuid,
+            domain_name: domain_name.to_lowercase(),
+            txt: format!("bw={}", crate::crypto::get_random_string_alphanum(44)),
+            created_at: Utc::now().naive_utc(),
+            verified_at: None,
+            last_checked_at: None,
+        }
+    }
+
+    pub fn is_verified(&self) -> bool {
+        self.verified_at.is_some()
+    }
+
+    // https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Api/AdminConsole/Models/Response/Organizations/OrganizationDomainResponseModel.cs
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.uuid,
+            "organizationId": self.org_uuid,
+            "txt": self.txt,
+            "domainName": self.domain_name,
+            "creationDate": format_date(&self.created_at),
+            "nextRunDate": null,
+            "jobRunCount": 0,
+            "verifiedDate": self.verified_at.as_ref().map(format_date),
+            "lastCheckedDate": self.last_checked_at.as_ref().map(format_date),
+            "object": "organizationDomain",
+        })
+    }
+}
+
+/// Database methods
+impl OrgDomain {
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        db_run! { conn:
+            sqlite, mysql {
+                match diesel::replace_into(org_domains::table)
+                    .values(self)
+                    .execute(conn)
+                {
+                    Ok(_) => Ok(()),
+                    // Record already exists and causes a Foreign Key Violation because replace_into() wants to delete the record first.
+                    Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::ForeignKeyViolation, _)) => {
+                        diesel::update(org_domains::table)
+                            .filter(org_domains::uuid.eq(&self.uuid))
+                            .set(self)
+                            .execute(conn)
+                            .map_res("Error saving organization domain")
+                    }
+                    Err(e) => Err(e.into()),
+                }.map_res("Error saving organization domain")
+            }
+            postgresql {
+                diesel::insert_into(org_domains::table)
+                    .values(self)
+                    .on_conflict(org_domains::uuid)
+                    .do_update()
+                    .set(self)
+                    .execute(conn)
+                    .map_res("Error saving organization domain")
+            }
+        }
+    }
+
+    pub async fn delete(self, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(org_domains::table.filter(org_domains::uuid.eq(self.uuid)))
+                .execute(conn)
+                .map_res("Error deleting organization domain")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_organization(org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(org_domains::table.filter(org_domains::org_uuid.eq(org_uuid)))
+                .execute(conn)
+                .map_res("Error deleting organization domains")
+        })
+        .await
+    }
+
+    pub async fn find_by_uuid_and_org(uuid: &OrgDomainId, org_uuid: &OrganizationId, conn: &DbConn) -> Option<Self> {
+        conn.run(move |conn| {
+            org_domains::table
+                .filter(org_domains::uuid.eq(uuid))
+                .filter(org_domains::org_uuid.eq(org_uuid))
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
+    pub async fn find_by_org_and_name(org_uuid: &OrganizationId, domain_name: &str, conn: &DbConn) -> Option<Self> {
+        let domain_name = domain_name.to_lowercase();
+        conn.run(move |conn| {
+            org_domains::table
+                .filter(org_domains::org_uuid.eq(org_uuid))
+                .filter(org_domains::domain_name.eq(domain_name))
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
+    pub async fn find_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            org_domains::table
+                .filter(org_domains::org_uuid.eq(org_uuid))
+                .order(org_domains::domain_name)
+                .load::<Self>(conn)
+                .expect("Error loading organization domains")
+        })
+        .await
+    }
+
+    /// Returns the verified claim of a domain, a domain can only be verified by one organization at a time
+    pub async fn find_verified_by_name(domain_name: &str, conn: &DbConn) -> Option<Self> {
+        let domain_name = domain_name.to_lowercase();
+        conn.run(move |conn| {
+            org_domains::table
+                .filter(org_domains::domain_name.eq(domain_name))
+                .filter(org_domains::verified_at.is_not_null())
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
+    /// Returns the verified claim matching the domain part of an email address
+    pub async fn find_verified_by_email(email: &str, conn: &DbConn) -> Option<Self> {
+        let (_, domain_name) = email.rsplit_once('@')?;
+        Self::find_verified_by_name(domain_name, conn).await
+    }
+}
+
+#[derive(
+    Clone,
+    Debug,
+    AsRef,
+    Deref,
+    DieselNewType,
+    Display,
+    From,
+    FromForm,
+    Hash,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    UuidFromParam,
+)]
+pub struct OrgDomainId(String);
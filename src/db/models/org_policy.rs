@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+
+use chrono::{TimeDelta, Utc};
 use derive_more::{AsRef, From};
 use diesel::prelude::*;
 use serde::Deserialize;
@@ -5,7 +8,10 @@ use serde_json::Value;
 
 use crate::{
     CONFIG,
-    api::{EmptyResult, core::two_factor},
+    api::{
+        EmptyResult,
+        core::{response_cache, two_factor},
+    },
     db::{
         DbConn,
         schema::{org_policies, users_organizations},
@@ -13,7 +19,10 @@ use crate::{
     error::MapResult,
 };
 
-use super::{Membership, MembershipId, MembershipStatus, MembershipType, OrganizationId, TwoFactor, UserId};
+use super::{
+    CollectionId, Membership, MembershipId, MembershipStatus, MembershipType, OrganizationId, TwoFactor, UserId,
+    org_cache,
+};
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
 #[diesel(table_name = org_policies)]
@@ -33,7 +42,7 @@ pub enum OrgPolicyType {
     MasterPassword = 1,
     PasswordGenerator = 2,
     SingleOrg = 3,
-    // RequireSso = 4, // Not supported
+    RequireSso = 4,
     PersonalOwnership = 5,
     DisableSend = 6,
     SendOptions = 7,
@@ -49,6 +58,11 @@ pub enum OrgPolicyType {
     // AutotypeDefaultSetting = 17, // Not supported yet
     // AutoConfirm = 18, // Not supported (not implemented yet)
     // BlockClaimedDomainAccountCreation = 19, // Not supported (Not AGPLv3 Licensed)
+
+    // Vaultwarden specific
+    BlockAttachments = 100,
+    TrashRetention = 101,
+    CipherReprompt = 102,
 }
 
 // https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Core/AdminConsole/Models/Data/Organizations/Policies/SendOptionsPolicyData.cs#L5
@@ -67,6 +81,94 @@ pub struct ResetPasswordDataModel {
     pub auto_enroll_enabled: bool,
 }
 
+// Vaultwarden specific, the official clients don't send any data for the TwoFactorAuthentication policy
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFactorPolicyData {
+    // Number of days members without 2FA keep their access, instead of being revoked directly
+    #[serde(default, alias = "GracePeriodDays")]
+    pub grace_period_days: Option<i64>,
+}
+
+impl TwoFactorPolicyData {
+    pub fn grace_period(&self) -> Option<TimeDelta> {
+        self.grace_period_days.filter(|days| *days > 0).and_then(TimeDelta::try_days)
+    }
+}
+
+// Vaultwarden specific, data of the BlockAttachments policy
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentPolicyData {
+    // The cipher types which can't have attachments, all types when empty
+    #[serde(default, alias = "CipherTypes")]
+    pub cipher_types: Vec<i32>,
+}
+
+impl AttachmentPolicyData {
+    pub fn blocks(&self, cipher_type: i32) -> bool {
+        self.cipher_types.is_empty() || self.cipher_types.contains(&cipher_type)
+    }
+}
+
+// Vaultwarden specific, data of the TrashRetention policy
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashRetentionPolicyData {
+    // Number of days the trashed items of the organization are kept, instead of `TRASH_AUTO_DELETE_DAYS`
+    #[serde(alias = "Days")]
+    pub days: i64,
+}
+
+// Vaultwarden specific, data of the CipherReprompt policy
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepromptPolicyData {
+    // The collections of which the items always require the master password, the whole organization when empty
+    #[serde(default, alias = "CollectionIds")]
+    pub collection_ids: Vec<CollectionId>,
+}
+
+impl RepromptPolicyData {
+    pub fn applies_to(&self, collections: &[CollectionId]) -> bool {
+        self.collection_ids.is_empty() || collections.iter().any(|c| self.collection_ids.contains(c))
+    }
+}
+
+// Vaultwarden specific, sent along with the data of the MasterPassword policy
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordHintPolicyData {
+    // Members can't set a password hint, and the hint they set before isn't sent to them anymore
+    #[serde(default, alias = "DisablePasswordHint")]
+    pub disable_password_hint: bool,
+    #[serde(default, alias = "MaxPasswordHintLength")]
+    pub max_password_hint_length: Option<usize>,
+    // Members need to set a password hint, which the admins of the organization can read
+    #[serde(default, alias = "RequirePasswordHint")]
+    pub require_password_hint: bool,
+}
+
+impl PasswordHintPolicyData {
+    pub fn check(&self, password_hint: Option<&String>) -> EmptyResult {
+        match password_hint {
+            Some(_) if self.disable_password_hint => {
+                err!("Password hints are disabled by the policy of an organization. Remove the hint and try again.")
+            }
+            Some(hint) if self.max_password_hint_length.is_some_and(|max| hint.chars().count() > max) => {
+                err!(format!(
+                    "The password hint can't be longer than {} characters, as required by the policy of an organization.",
+                    self.max_password_hint_length.unwrap_or_default()
+                ))
+            }
+            None if self.require_password_hint => {
+                err!("A password hint is required by the policy of an organization. Its administrators can read it.")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 /// Local methods
 impl OrgPolicy {
     pub fn new(org_uuid: OrganizationId, atype: OrgPolicyType, enabled: bool, data: String) -> Self {
@@ -83,6 +185,13 @@ impl OrgPolicy {
         self.atype == policy_type as i32
     }
 
+    /// The Vaultwarden specific policies are left out of the responses, the clients can fail to decode unknown policy types
+    pub fn is_known_to_clients(&self) -> bool {
+        !self.has_type(OrgPolicyType::BlockAttachments)
+            && !self.has_type(OrgPolicyType::TrashRetention)
+            && !self.has_type(OrgPolicyType::CipherReprompt)
+    }
+
     pub fn to_json(&self) -> Value {
         let data_json: Value = serde_json::from_str(&self.data).unwrap_or(Value::Null);
         let mut policy = json!({
@@ -109,7 +218,7 @@ impl OrgPolicy {
 /// Database methods
 impl OrgPolicy {
     pub async fn save(&self, conn: &DbConn) -> EmptyResult {
-        db_run! { conn:
+        let res = db_run! { conn:
             sqlite, mysql {
                 match diesel::replace_into(org_policies::table)
                     .values(self)
@@ -147,16 +256,24 @@ impl OrgPolicy {
                     .execute(conn)
                     .map_res("Error saving org_policy")
             }
-        }
+        };
+        org_cache::invalidate();
+        response_cache::invalidate_org(&self.org_uuid);
+        res
     }
 
     pub async fn delete(self, conn: &DbConn) -> EmptyResult {
-        conn.run(move |conn| {
-            diesel::delete(org_policies::table.filter(org_policies::uuid.eq(self.uuid)))
-                .execute(conn)
-                .map_res("Error deleting org_policy")
-        })
-        .await
+        let org_uuid = self.org_uuid.clone();
+        let res = conn
+            .run(move |conn| {
+                diesel::delete(org_policies::table.filter(org_policies::uuid.eq(self.uuid)))
+                    .execute(conn)
+                    .map_res("Error deleting org_policy")
+            })
+            .await;
+        org_cache::invalidate();
+        response_cache::invalidate_org(&org_uuid);
+        res
     }
 
     pub async fn find_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> Vec<Self> {
@@ -200,15 +317,30 @@ impl OrgPolicy {
         .await
     }
 
-    pub async fn delete_all_by_organization(org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
+    pub async fn find_enabled_by_type(policy_type: OrgPolicyType, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
-            diesel::delete(org_policies::table.filter(org_policies::org_uuid.eq(org_uuid)))
-                .execute(conn)
-                .map_res("Error deleting org_policy")
+            org_policies::table
+                .filter(org_policies::atype.eq(policy_type as i32))
+                .filter(org_policies::enabled.eq(true))
+                .load::<Self>(conn)
+                .expect("Error loading org_policy")
         })
         .await
     }
 
+    pub async fn delete_all_by_organization(org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
+        let res = conn
+            .run(move |conn| {
+                diesel::delete(org_policies::table.filter(org_policies::org_uuid.eq(org_uuid)))
+                    .execute(conn)
+                    .map_res("Error deleting org_policy")
+            })
+            .await;
+        org_cache::invalidate();
+        response_cache::invalidate_org(org_uuid);
+        res
+    }
+
     pub async fn find_accepted_and_confirmed_by_user_and_active_policy(
         user_uuid: &UserId,
         policy_type: OrgPolicyType,
@@ -232,6 +364,22 @@ impl OrgPolicy {
         .await
     }
 
+    /// Merges the password hint rules of the MasterPassword policies of the organizations of the user
+    pub async fn password_hint_policy(user_uuid: &UserId, conn: &DbConn) -> PasswordHintPolicyData {
+        Self::find_accepted_and_confirmed_by_user_and_active_policy(user_uuid, OrgPolicyType::MasterPassword, conn)
+            .await
+            .iter()
+            .filter_map(|p| serde_json::from_str::<PasswordHintPolicyData>(&p.data).ok())
+            .fold(PasswordHintPolicyData::default(), |acc, policy| PasswordHintPolicyData {
+                disable_password_hint: acc.disable_password_hint || policy.disable_password_hint,
+                max_password_hint_length: match (acc.max_password_hint_length, policy.max_password_hint_length) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                },
+                require_password_hint: acc.require_password_hint || policy.require_password_hint,
+            })
+    }
+
     pub async fn find_confirmed_by_user_and_active_policy(
         user_uuid: &UserId,
         policy_type: OrgPolicyType,
@@ -263,6 +411,12 @@ impl OrgPolicy {
         exclude_org_uuid: Option<&OrganizationId>,
         conn: &DbConn,
     ) -> bool {
+        let key = (user_uuid.clone(), policy_type as i32, exclude_org_uuid.cloned());
+        if let Some(applicable) = org_cache::get_policy(&key) {
+            return applicable;
+        }
+
+        let mut applicable = false;
         for policy in
             OrgPolicy::find_accepted_and_confirmed_by_user_and_active_policy(user_uuid, policy_type, conn).await
         {
@@ -274,10 +428,12 @@ impl OrgPolicy {
             if let Some(user) = Membership::find_confirmed_by_user_and_org(user_uuid, &policy.org_uuid, conn).await
                 && user.atype < MembershipType::Admin
             {
-                return true;
+                applicable = true;
+                break;
             }
         }
-        false
+        org_cache::set_policy(key, applicable);
+        applicable
     }
 
     pub async fn check_user_allowed(m: &Membership, action: &str, conn: &DbConn) -> EmptyResult {
@@ -289,6 +445,10 @@ impl OrgPolicy {
             {
                 if CONFIG.email_2fa_auto_fallback() {
                     two_factor::email::find_and_activate_email_2fa(&m.user_uuid, conn).await?;
+                } else if Self::two_factor_grace_period(&m.org_uuid, conn).await.is_some()
+                    && m.two_factor_grace_until.is_none_or(|until| until > Utc::now().naive_utc())
+                {
+                    // The member is still within the grace period, or it starts with the next run of the job
                 } else {
                     err!(format!("Cannot {} because 2FA is required (membership {})", action, m.uuid));
                 }
@@ -316,6 +476,47 @@ impl OrgPolicy {
         Ok(())
     }
 
+    /// The grace period of the enabled TwoFactorAuthentication policy of the organization, if there is one
+    pub async fn two_factor_grace_period(org_uuid: &OrganizationId, conn: &DbConn) -> Option<TimeDelta> {
+        let policy = Self::find_by_org_and_type(org_uuid, OrgPolicyType::TwoFactorAuthentication, conn).await?;
+        if !policy.enabled {
+            return None;
+        }
+        serde_json::from_str::<TwoFactorPolicyData>(&policy.data).unwrap_or_default().grace_period()
+    }
+
+    /// Whether the enabled BlockAttachments policy of the organization forbids attachments on the cipher type
+    pub async fn is_attachment_blocked(org_uuid: &OrganizationId, cipher_type: i32, conn: &DbConn) -> bool {
+        match Self::find_by_org_and_type(org_uuid, OrgPolicyType::BlockAttachments, conn).await {
+            Some(policy) if policy.enabled => {
+                serde_json::from_str::<AttachmentPolicyData>(&policy.data).unwrap_or_default().blocks(cipher_type)
+            }
+            _ => false,
+        }
+    }
+
+    /// The data of the CipherReprompt policy of the organization, if it is enabled
+    pub async fn reprompt_policy(org_uuid: &OrganizationId, conn: &DbConn) -> Option<RepromptPolicyData> {
+        match Self::find_by_org_and_type(org_uuid, OrgPolicyType::CipherReprompt, conn).await {
+            Some(policy) if policy.enabled => {
+                Some(serde_json::from_str::<RepromptPolicyData>(&policy.data).unwrap_or_default())
+            }
+            _ => None,
+        }
+    }
+
+    /// The trash retention days of the organizations with an enabled TrashRetention policy
+    pub async fn find_trash_retention_days(conn: &DbConn) -> HashMap<OrganizationId, i64> {
+        Self::find_enabled_by_type(OrgPolicyType::TrashRetention, conn)
+            .await
+            .into_iter()
+            .filter_map(|policy| {
+                let data = serde_json::from_str::<TrashRetentionPolicyData>(&policy.data).ok()?;
+                Some((policy.org_uuid, data.days))
+            })
+            .collect()
+    }
+
     pub async fn org_is_reset_password_auto_enroll(org_uuid: &OrganizationId, conn: &DbConn) -> bool {
         match OrgPolicy::find_by_org_and_type(org_uuid, OrgPolicyType::ResetPassword, conn).await {
             Some(policy) => match serde_json::from_str::<ResetPasswordDataModel>(&policy.data) {
@@ -11,7 +11,7 @@ use serde_json::Value;
 
 use crate::{
     CONFIG,
-    api::EmptyResult,
+    api::{EmptyResult, core::response_cache},
     db::{
         DbConn,
         schema::{
@@ -24,8 +24,9 @@ use crate::{
 use macros::UuidFromParam;
 
 use super::{
-    Cipher, CipherId, Collection, CollectionGroup, CollectionId, CollectionUser, Group, GroupId, GroupUser, OrgPolicy,
-    OrgPolicyType, TwoFactor, User, UserId,
+    Attachment, AuditGrant, Cipher, CipherId, CipherTemplate, Collection, CollectionGroup, CollectionId,
+    CollectionUser, Group, GroupId, GroupUser, OrgDomain, OrgPolicy, OrgPolicyType, QuotaWarning, Send, Tombstone,
+    TombstoneType, TwoFactor, User, UserId, org_cache,
 };
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
@@ -38,9 +39,27 @@ pub struct Organization {
     pub billing_email: String,
     pub private_key: Option<String>,
     pub public_key: Option<String>,
+    pub tenant_id: Option<String>,
+    pub limit_collection_creation: bool,
+    pub limit_collection_deletion: bool,
+    pub limit_item_deletion: bool,
+    pub allow_admin_access_to_all_collection_items: bool,
+    /// Added to the email members get when they are confirmed
+    pub welcome_message: Option<String>,
+    /// The items of confidential collections are hidden from owners, admins and members with access to all items,
+    /// unless they are assigned to a collection of the item
+    pub confidential_collections: bool,
+    /// Overrides `ICON_SERVICE` for the icon requests which name this organization, "none" disables the icons
+    pub icon_service: Option<String>,
+    /// Receives the events of this organization when `ORG_WEBHOOKS_ALLOWED` is enabled, see webhook.rs
+    pub webhook_url: Option<String>,
+    /// Encrypted with `crypto::encrypt_secret`
+    pub webhook_secret: Option<String>,
+    /// New items of this organization need the required custom fields of one of its templates of the same type
+    pub enforce_cipher_templates: bool,
 }
 
-#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[derive(Clone, Identifiable, Queryable, Insertable, AsChangeset)]
 #[diesel(table_name = users_organizations)]
 #[diesel(treat_none_as_null = true)]
 #[diesel(primary_key(uuid))]
@@ -57,6 +76,11 @@ pub struct Membership {
     pub atype: i32,
     pub reset_password_key: Option<String>,
     pub external_id: Option<String>,
+    pub invited_at: Option<NaiveDateTime>,
+    // Until when the member may stay without 2FA, while the organization requires it
+    pub two_factor_grace_until: Option<NaiveDateTime>,
+    // Vaultwarden specific, allows the member to create and list the Sends of the organization
+    pub manage_sends: bool,
 }
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
@@ -70,6 +94,16 @@ pub struct OrganizationApiKey {
     pub revision_date: NaiveDateTime,
 }
 
+// https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Core/Enums/OrganizationApiKeyType.cs
+#[derive(Copy, Clone, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum OrgApiKeyType {
+    // The key of the public API and the directory connector
+    Default = 0,
+    // BillingSync = 1, // Not supported
+    // The key SCIM clients authenticate with
+    Scim = 2,
+}
+
 // https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Core/AdminConsole/Enums/OrganizationUserStatusType.cs
 #[derive(PartialEq)]
 pub enum MembershipStatus {
@@ -193,16 +227,63 @@ impl Organization {
             billing_email,
             private_key,
             public_key,
+            tenant_id: None,
+            limit_collection_creation: true,
+            limit_collection_deletion: true,
+            limit_item_deletion: false,
+            allow_admin_access_to_all_collection_items: true,
+            welcome_message: None,
+            confidential_collections: false,
+            icon_service: None,
+            webhook_url: None,
+            webhook_secret: None,
+            enforce_cipher_templates: false,
         }
     }
+
+    /// Owners and admins can always create collections, managers only when they can manage all collections
+    /// or when the creation isn't limited. Users can't manage collections at all.
+    pub fn can_create_collections(&self, member: &Membership) -> bool {
+        member.atype >= MembershipType::Admin
+            || (member.atype == MembershipType::Manager && (member.access_all || !self.limit_collection_creation))
+    }
+
+    /// Same as `can_create_collections`, but for the `limitCollectionDeletion` setting
+    pub fn can_delete_collections(&self, member: &Membership) -> bool {
+        member.atype >= MembershipType::Admin
+            || (member.atype == MembershipType::Manager && (member.access_all || !self.limit_collection_deletion))
+    }
+
+    /// Whether the member can access all items of the organization without being assigned to their collections:
+    /// with access to all collections, as owner, or as admin when `allowAdminAccessToAllCollectionItems` is enabled.
+    /// Groups with access to all collections and confidential collections are checked separately.
+    pub fn has_full_item_access(&self, member: &Membership) -> bool {
+        member.has_status(MembershipStatus::Confirmed)
+            && (member.access_all
+                || member.atype == MembershipType::Owner
+                || (member.atype == MembershipType::Admin && self.allow_admin_access_to_all_collection_items))
+    }
+
+    /// The seats reported to the clients, `None` means unlimited
+    pub fn seats() -> Option<i32> {
+        CONFIG.org_seats()
+    }
+
+    /// The storage reported to the clients, based on `ORG_ATTACHMENT_LIMIT`
+    pub fn max_storage_gb() -> i16 {
+        CONFIG.org_attachment_limit().map_or(i16::MAX, |limit_kb| {
+            i16::try_from(u64::try_from(limit_kb).unwrap_or_default().div_ceil(1024 * 1024)).unwrap_or(i16::MAX)
+        })
+    }
+
     // https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Api/AdminConsole/Models/Response/Organizations/OrganizationResponseModel.cs
     pub fn to_json(&self) -> Value {
         json!({
             "id": self.uuid,
             "name": self.name,
-            "seats": null,
+            "seats": Self::seats(),
             "maxCollections": null,
-            "maxStorageGb": i16::MAX, // The value doesn't matter, we don't check server-side
+            "maxStorageGb": Self::max_storage_gb(),
             "use2fa": true,
             "useCustomPermissions": true,
             "useDirectory": false, // Is supported, but this value isn't checked anywhere (yet)
@@ -219,9 +300,10 @@ impl Organization {
             "useApi": true,
             "hasPublicAndPrivateKeys": self.private_key.is_some() && self.public_key.is_some(),
             "useResetPassword": CONFIG.mail_enabled(),
-            "allowAdminAccessToAllCollectionItems": true,
-            "limitCollectionCreation": true,
-            "limitCollectionDeletion": true,
+            "allowAdminAccessToAllCollectionItems": self.allow_admin_access_to_all_collection_items,
+            "limitCollectionCreation": self.limit_collection_creation,
+            "limitCollectionDeletion": self.limit_collection_deletion,
+            "limitItemDeletion": self.limit_item_deletion,
 
             "businessName": self.name,
             "businessAddress1": null,
@@ -267,6 +349,9 @@ impl Membership {
             atype: MembershipType::User as i32,
             reset_password_key: None,
             external_id: None,
+            invited_at: Some(Utc::now().naive_utc()),
+            two_factor_grace_until: None,
+            manage_sends: false,
         }
     }
 
@@ -281,6 +366,7 @@ impl Membership {
     pub fn revoke(&mut self) -> bool {
         if self.status > MembershipStatus::Revoked as i32 {
             self.status -= ACTIVATE_REVOKE_DIFF;
+            self.two_factor_grace_until = None;
             return true;
         }
         false
@@ -318,12 +404,12 @@ impl Membership {
 }
 
 impl OrganizationApiKey {
-    pub fn new(org_uuid: OrganizationId, api_key: String) -> Self {
+    pub fn new(org_uuid: OrganizationId, atype: OrgApiKeyType, api_key: String) -> Self {
         Self {
             uuid: OrgApiKeyId(crate::util::get_uuid()),
 
             org_uuid,
-            atype: 0, // Type 0 is the default and only type we support currently
+            atype: atype as i32,
             api_key,
             revision_date: Utc::now().naive_utc(),
         }
@@ -342,10 +428,10 @@ impl Organization {
         }
 
         for member in &Membership::find_by_org(&self.uuid, conn).await {
-            User::update_uuid_revision(&member.user_uuid, conn).await;
+            User::update_uuid_access_revision(&member.user_uuid, conn).await;
         }
 
-        db_run! { conn:
+        let res = db_run! { conn:
             sqlite, mysql {
                 match diesel::replace_into(organizations::table)
                     .values(self)
@@ -373,7 +459,9 @@ impl Organization {
                     .execute(conn)
                     .map_res("Error saving organization")
             }
-        }
+        };
+        response_cache::invalidate_org(&self.uuid);
+        res
     }
 
     pub async fn delete(self, conn: &DbConn) -> EmptyResult {
@@ -381,15 +469,24 @@ impl Organization {
         Collection::delete_all_by_organization(&self.uuid, conn).await?;
         Membership::delete_all_by_organization(&self.uuid, conn).await?;
         OrgPolicy::delete_all_by_organization(&self.uuid, conn).await?;
+        OrgDomain::delete_all_by_organization(&self.uuid, conn).await?;
+        CipherTemplate::delete_all_by_organization(&self.uuid, conn).await?;
         Group::delete_all_by_organization(&self.uuid, conn).await?;
         OrganizationApiKey::delete_all_by_organization(&self.uuid, conn).await?;
-
-        conn.run(move |conn| {
-            diesel::delete(organizations::table.filter(organizations::uuid.eq(self.uuid)))
-                .execute(conn)
-                .map_res("Error saving organization")
-        })
-        .await
+        Send::delete_all_by_organization(&self.uuid, conn).await?;
+        QuotaWarning::delete_by_owner(self.uuid.as_ref(), conn).await?;
+        Tombstone::delete_all_by_owner(self.uuid.as_ref(), conn).await?;
+
+        let org_uuid = self.uuid.clone();
+        let res = conn
+            .run(move |conn| {
+                diesel::delete(organizations::table.filter(organizations::uuid.eq(self.uuid)))
+                    .execute(conn)
+                    .map_res("Error saving organization")
+            })
+            .await;
+        response_cache::invalidate_org(&org_uuid);
+        res
     }
 
     pub async fn find_by_uuid(uuid: &OrganizationId, conn: &DbConn) -> Option<Self> {
@@ -447,6 +544,27 @@ impl Membership {
         // It will be converted back on other locations
         let membership_type = self.type_manager_as_custom();
 
+        // Owners and admins get the settings of the organization, which they can change.
+        // For the other members these tell the clients whether they are allowed to create or delete collections,
+        // otherwise the clients would also offer this to users, which isn't supported.
+        let (limit_collection_creation, limit_collection_deletion) = if self.atype >= MembershipType::Admin {
+            (org.limit_collection_creation, org.limit_collection_deletion)
+        } else {
+            (!org.can_create_collections(self), !org.can_delete_collections(self))
+        };
+
+        // Vaultwarden specific, lets the web-vault show the usage of the organization to the owners and admins
+        let usage = if self.atype >= MembershipType::Admin && self.status == MembershipStatus::Confirmed as i32 {
+            json!({
+                "seatsUsed": Membership::count_seats_by_org(&org.uuid, conn).await,
+                "collectionCount": Collection::count_by_org(&org.uuid, conn).await,
+                "storageUsed": Attachment::size_by_org(&org.uuid, conn).await,
+                "storageQuota": CONFIG.org_attachment_limit().map(|limit_kb| limit_kb.saturating_mul(1024)),
+            })
+        } else {
+            Value::Null
+        };
+
         let permissions = json!({
                 // TODO: Add full support for Custom User Roles
                 // See: https://bitwarden.com/help/article/user-types-access-control/#custom-role
@@ -509,17 +627,19 @@ impl Membership {
             "familySponsorshipValidUntil": null,
             "familySponsorshipToDelete": null,
             "accessSecretsManager": false,
-            // limit collection creation to managers with access_all permission to prevent issues
-            "limitCollectionCreation": self.atype < MembershipType::Manager || !self.access_all,
-            "limitCollectionDeletion": true,
-            "limitItemDeletion": false,
-            "allowAdminAccessToAllCollectionItems": true,
+            "limitCollectionCreation": limit_collection_creation,
+            "limitCollectionDeletion": limit_collection_deletion,
+            "limitItemDeletion": org.limit_item_deletion,
+            "allowAdminAccessToAllCollectionItems": org.allow_admin_access_to_all_collection_items,
             "userIsManagedByOrganization": false, // Means not managed via the Members UI, like SSO
             "userIsClaimedByOrganization": false, // The new key instead of the obsolete userIsManagedByOrganization
+            // Vaultwarden specific, set while the member still needs to enable 2FA before access is revoked
+            "twoFactorGracePeriodEndDate": self.two_factor_grace_until.as_ref().map(crate::util::format_date),
 
             "permissions": permissions,
 
-            "maxStorageGb": i16::MAX, // The value doesn't matter, we don't check server-side
+            "maxStorageGb": Organization::max_storage_gb(),
+            "usage": usage,
 
             // These are per user
             "userId": self.user_uuid,
@@ -654,6 +774,8 @@ impl Membership {
             "hasMasterPassword": !user.password_hash.is_empty(),
 
             "permissions": permissions,
+            // Vaultwarden specific
+            "manageSends": self.manage_sends,
 
             "ssoBound": false, // Not supported
             "managedByOrganization": false, // This key is obsolete replaced by claimedByOrganization
@@ -737,9 +859,9 @@ impl Membership {
     }
 
     pub async fn save(&self, conn: &DbConn) -> EmptyResult {
-        User::update_uuid_revision(&self.user_uuid, conn).await;
+        User::update_uuid_access_revision(&self.user_uuid, conn).await;
 
-        db_run! { conn:
+        let res = db_run! { conn:
             sqlite, mysql {
                 match diesel::replace_into(users_organizations::table)
                     .values(self)
@@ -766,21 +888,77 @@ impl Membership {
                     .execute(conn)
                     .map_res("Error adding user to organization")
             }
-        }
+        };
+        org_cache::invalidate();
+        res
+    }
+
+    /// Saves the confirmed member together with read-only access to the onboarding collections of the organization,
+    /// in one transaction so the member is never confirmed without them. Existing access to these collections is kept.
+    pub async fn confirm_with_collections(&self, collection_uuids: &[CollectionId], conn: &DbConn) -> EmptyResult {
+        User::update_uuid_access_revision(&self.user_uuid, conn).await;
+
+        let access: Vec<CollectionUser> = collection_uuids
+            .iter()
+            .map(|collection_uuid| CollectionUser {
+                user_uuid: self.user_uuid.clone(),
+                collection_uuid: collection_uuid.clone(),
+                read_only: true,
+                hide_passwords: false,
+                manage: false,
+            })
+            .collect();
+
+        let res = db_run! { conn:
+            sqlite, mysql {
+                conn.transaction(|conn| {
+                    diesel::update(users_organizations::table)
+                        .filter(users_organizations::uuid.eq(&self.uuid))
+                        .set(self)
+                        .execute(conn)?;
+                    for collection_user in &access {
+                        diesel::insert_or_ignore_into(users_collections::table).values(collection_user).execute(conn)?;
+                    }
+                    Ok::<(), diesel::result::Error>(())
+                })
+                .map_res("Error confirming user")
+            }
+            postgresql {
+                conn.transaction(|conn| {
+                    diesel::update(users_organizations::table)
+                        .filter(users_organizations::uuid.eq(&self.uuid))
+                        .set(self)
+                        .execute(conn)?;
+                    diesel::insert_into(users_collections::table)
+                        .values(&access)
+                        .on_conflict_do_nothing()
+                        .execute(conn)?;
+                    Ok::<(), diesel::result::Error>(())
+                })
+                .map_res("Error confirming user")
+            }
+        };
+        org_cache::invalidate();
+        res
     }
 
     pub async fn delete(self, conn: &DbConn) -> EmptyResult {
-        User::update_uuid_revision(&self.user_uuid, conn).await;
+        User::update_uuid_access_revision(&self.user_uuid, conn).await;
 
         CollectionUser::delete_all_by_user_and_org(&self.user_uuid, &self.org_uuid, conn).await?;
+        AuditGrant::delete_stale_by_org(&self.org_uuid, conn).await?;
         GroupUser::delete_all_by_member(&self.uuid, conn).await?;
+        Tombstone::record(TombstoneType::Organization, &self.org_uuid, &self.user_uuid, conn).await?;
 
-        conn.run(move |conn| {
-            diesel::delete(users_organizations::table.filter(users_organizations::uuid.eq(self.uuid)))
-                .execute(conn)
-                .map_res("Error removing user from organization")
-        })
-        .await
+        let res = conn
+            .run(move |conn| {
+                diesel::delete(users_organizations::table.filter(users_organizations::uuid.eq(self.uuid)))
+                    .execute(conn)
+                    .map_res("Error removing user from organization")
+            })
+            .await;
+        org_cache::invalidate();
+        res
     }
 
     pub async fn delete_all_by_organization(org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
@@ -807,6 +985,19 @@ impl Membership {
         None
     }
 
+    /// When the invitation of this member expires, `None` for members which aren't invited (anymore),
+    /// or which were invited before the invitation date was stored
+    pub fn invite_expiration(&self) -> Option<NaiveDateTime> {
+        if self.status != MembershipStatus::Invited as i32 {
+            return None;
+        }
+        self.invited_at.as_ref().map(crate::auth::invite_expiration)
+    }
+
+    pub fn is_invite_expired(&self) -> bool {
+        self.invite_expiration().is_some_and(|exp| exp < Utc::now().naive_utc())
+    }
+
     pub fn has_status(&self, status: MembershipStatus) -> bool {
         self.status == status as i32
     }
@@ -819,6 +1010,10 @@ impl Membership {
         (self.access_all || self.atype >= MembershipType::Admin) && self.has_status(MembershipStatus::Confirmed)
     }
 
+    pub fn can_manage_sends(&self) -> bool {
+        (self.manage_sends || self.atype >= MembershipType::Admin) && self.has_status(MembershipStatus::Confirmed)
+    }
+
     pub async fn find_by_uuid(uuid: &MembershipId, conn: &DbConn) -> Option<Self> {
         conn.run(move |conn| {
             users_organizations::table.filter(users_organizations::uuid.eq(uuid)).first::<Self>(conn).ok()
@@ -859,20 +1054,58 @@ impl Membership {
         .await
     }
 
-    // Should be used only when email are disabled.
-    // In Organizations::send_invite status is set to Accepted only if the user has a password.
-    pub async fn accept_user_invitations(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+    pub async fn find_invited_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
-            diesel::update(users_organizations::table)
-                .filter(users_organizations::user_uuid.eq(user_uuid))
+            users_organizations::table
+                .filter(users_organizations::org_uuid.eq(org_uuid))
                 .filter(users_organizations::status.eq(MembershipStatus::Invited as i32))
-                .set(users_organizations::status.eq(MembershipStatus::Accepted as i32))
-                .execute(conn)
-                .map_res("Error confirming invitations")
+                .order(users_organizations::invited_at.asc())
+                .load::<Self>(conn)
+                .unwrap_or_default()
+        })
+        .await
+    }
+
+    pub async fn find_in_two_factor_grace_period(conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            users_organizations::table
+                .filter(users_organizations::two_factor_grace_until.is_not_null())
+                .load::<Self>(conn)
+                .unwrap_or_default()
+        })
+        .await
+    }
+
+    /// Counts the invitations of all organizations which were sent before the given date and weren't accepted
+    pub async fn count_invited_before(dt: &NaiveDateTime, conn: &DbConn) -> i64 {
+        conn.run(move |conn| {
+            users_organizations::table
+                .filter(users_organizations::status.eq(MembershipStatus::Invited as i32))
+                .filter(users_organizations::invited_at.lt(dt))
+                .count()
+                .first::<i64>(conn)
+                .unwrap_or(0)
         })
         .await
     }
 
+    // Should be used only when email are disabled.
+    // In Organizations::send_invite status is set to Accepted only if the user has a password.
+    pub async fn accept_user_invitations(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        let res = conn
+            .run(move |conn| {
+                diesel::update(users_organizations::table)
+                    .filter(users_organizations::user_uuid.eq(user_uuid))
+                    .filter(users_organizations::status.eq(MembershipStatus::Invited as i32))
+                    .set(users_organizations::status.eq(MembershipStatus::Accepted as i32))
+                    .execute(conn)
+                    .map_res("Error confirming invitations")
+            })
+            .await;
+        org_cache::invalidate();
+        res
+    }
+
     pub async fn find_any_state_by_user(user_uuid: &UserId, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
             users_organizations::table
@@ -956,6 +1189,20 @@ impl Membership {
         .await
     }
 
+    // Revoked members don't occupy a seat
+    pub async fn count_seats_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> i64 {
+        conn.run(move |conn| {
+            users_organizations::table
+                .filter(users_organizations::org_uuid.eq(org_uuid))
+                .filter(users_organizations::status.ne(MembershipStatus::Revoked as i32))
+                .count()
+                .first::<i64>(conn)
+                .ok()
+                .unwrap_or(0)
+        })
+        .await
+    }
+
     pub async fn find_by_org_and_type(org_uuid: &OrganizationId, atype: MembershipType, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
             users_organizations::table
@@ -1000,15 +1247,23 @@ impl Membership {
         org_uuid: &OrganizationId,
         conn: &DbConn,
     ) -> Option<Self> {
-        conn.run(move |conn| {
-            users_organizations::table
-                .filter(users_organizations::user_uuid.eq(user_uuid))
-                .filter(users_organizations::org_uuid.eq(org_uuid))
-                .filter(users_organizations::status.eq(MembershipStatus::Confirmed as i32))
-                .first::<Self>(conn)
-                .ok()
-        })
-        .await
+        let key = (user_uuid.clone(), org_uuid.clone());
+        if let Some(member) = org_cache::get_confirmed_membership(&key) {
+            return member;
+        }
+
+        let member = conn
+            .run(move |conn| {
+                users_organizations::table
+                    .filter(users_organizations::user_uuid.eq(user_uuid))
+                    .filter(users_organizations::org_uuid.eq(org_uuid))
+                    .filter(users_organizations::status.eq(MembershipStatus::Confirmed as i32))
+                    .first::<Self>(conn)
+                    .ok()
+            })
+            .await;
+        org_cache::set_confirmed_membership(key, member.as_ref());
+        member
     }
 
     pub async fn find_by_user(user_uuid: &UserId, conn: &DbConn) -> Vec<Self> {
@@ -1203,9 +1458,22 @@ impl OrganizationApiKey {
         }
     }
 
+    /// Returns the key of the public API
     pub async fn find_by_org_uuid(org_uuid: &OrganizationId, conn: &DbConn) -> Option<Self> {
+        Self::find_by_org_uuid_and_type(org_uuid, OrgApiKeyType::Default, conn).await
+    }
+
+    pub async fn find_by_org_uuid_and_type(
+        org_uuid: &OrganizationId,
+        atype: OrgApiKeyType,
+        conn: &DbConn,
+    ) -> Option<Self> {
         conn.run(move |conn| {
-            organization_api_key::table.filter(organization_api_key::org_uuid.eq(org_uuid)).first::<Self>(conn).ok()
+            organization_api_key::table
+                .filter(organization_api_key::org_uuid.eq(org_uuid))
+                .filter(organization_api_key::atype.eq(atype as i32))
+                .first::<Self>(conn)
+                .ok()
         })
         .await
     }
@@ -0,0 +1,73 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::quota_warnings},
+    error::MapResult,
+};
+
+/// The highest attachment storage warning level (percentage of the limit) a user or an organization was warned about.
+/// The owner is either a user or an organization, the level is lowered again once the usage drops.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = quota_warnings)]
+#[diesel(primary_key(owner_uuid))]
+pub struct QuotaWarning {
+    pub owner_uuid: String,
+    pub level: i32,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Database methods
+impl QuotaWarning {
+    pub async fn find_level(owner_uuid: &str, conn: &DbConn) -> i32 {
+        let owner_uuid = owner_uuid.to_owned();
+        conn.run(move |conn| {
+            quota_warnings::table
+                .filter(quota_warnings::owner_uuid.eq(owner_uuid))
+                .select(quota_warnings::level)
+                .first::<i32>(conn)
+                .unwrap_or(0)
+        })
+        .await
+    }
+
+    pub async fn set_level(owner_uuid: &str, level: i32, conn: &DbConn) -> EmptyResult {
+        if level == 0 {
+            return Self::delete_by_owner(owner_uuid, conn).await;
+        }
+
+        let warning = Self {
+            owner_uuid: owner_uuid.to_owned(),
+            level,
+            updated_at: Utc::now().naive_utc(),
+        };
+        db_run! { conn:
+            sqlite, mysql {
+                diesel::replace_into(quota_warnings::table)
+                    .values(&warning)
+                    .execute(conn)
+                    .map_res("Error saving quota warning")
+            }
+            postgresql {
+                diesel::insert_into(quota_warnings::table)
+                    .values(&warning)
+                    .on_conflict(quota_warnings::owner_uuid)
+                    .do_update()
+                    .set(&warning)
+                    .execute(conn)
+                    .map_res("Error saving quota warning")
+            }
+        }
+    }
+
+    pub async fn delete_by_owner(owner_uuid: &str, conn: &DbConn) -> EmptyResult {
+        let owner_uuid = owner_uuid.to_owned();
+        conn.run(move |conn| {
+            diesel::delete(quota_warnings::table.filter(quota_warnings::owner_uuid.eq(owner_uuid)))
+                .execute(conn)
+                .map_res("Error deleting quota warning")
+        })
+        .await
+    }
+}
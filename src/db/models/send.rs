@@ -13,7 +13,7 @@ use crate::{
     util::{LowerCase, NumberOrString, format_date},
 };
 
-use super::{OrganizationId, User, UserId};
+use super::{Organization, OrganizationId, Tombstone, TombstoneType, User, UserId};
 use id::SendId;
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
@@ -46,6 +46,12 @@ pub struct Send {
 
     pub disabled: bool,
     pub hide_email: Option<bool>,
+
+    // Image metadata provided by the creator, so the access page can show a preview of a file Send
+    pub preview: Option<String>,
+
+    // Bytes served by the anonymous downloads of the file
+    pub download_bytes: i64,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, num_derive::FromPrimitive)]
@@ -93,6 +99,10 @@ impl Send {
 
             disabled: false,
             hide_email: None,
+
+            preview: None,
+
+            download_bytes: 0,
         }
     }
 
@@ -121,6 +131,32 @@ impl Send {
         }
     }
 
+    /// Returns the size of the file of a file Send, `None` for text Sends
+    pub fn file_size(&self) -> Option<i64> {
+        if self.atype != SendType::File as i32 {
+            return None;
+        }
+        Self::file_size_of(&self.data)
+    }
+
+    // The size is part of the data of a file Send
+    fn file_size_of(data: &str) -> Option<i64> {
+        #[derive(serde::Deserialize)]
+        struct FileData {
+            #[serde(rename = "size", alias = "Size")]
+            size: NumberOrString,
+        }
+
+        serde_json::from_str::<FileData>(data).map_err(Into::into).and_then(|d| d.size.into_i64()).ok()
+    }
+
+    fn preview_json(&self) -> Option<Value> {
+        if self.atype != SendType::File as i32 {
+            return None;
+        }
+        self.preview.as_deref().and_then(|p| serde_json::from_str(p).ok())
+    }
+
     pub async fn creator_identifier(&self, conn: &DbConn) -> Option<String> {
         if let Some(hide_email) = self.hide_email
             && hide_email
@@ -134,6 +170,13 @@ impl Send {
             return Some(user.email);
         }
 
+        // Sends of an organization show the name of the organization instead of the member who created it
+        if let Some(org_uuid) = &self.organization_uuid
+            && let Some(org) = Organization::find_by_uuid(org_uuid, conn).await
+        {
+            return Some(org.name);
+        }
+
         None
     }
 
@@ -162,6 +205,8 @@ impl Send {
             "authType": if self.password_hash.is_some() { SendAuthType::Password as i32 } else { SendAuthType::None as i32 },
             "disabled": self.disabled,
             "hideEmail": self.hide_email,
+            // Vaultwarden specific
+            "preview": self.preview_json(),
 
             "revisionDate": format_date(&self.revision_date),
             "expirationDate": self.expiration_date.as_ref().map(format_date),
@@ -186,6 +231,8 @@ impl Send {
             "text": if self.atype == SendType::Text as i32 { Some(&data) } else { None },
             "file": if self.atype == SendType::File as i32 { Some(&data) } else { None },
 
+            "preview": self.preview_json(),
+
             "expirationDate": self.expiration_date.as_ref().map(format_date),
             "creatorIdentifier": self.creator_identifier(conn).await,
             "object": "send-access",
@@ -232,8 +279,11 @@ impl Send {
         self.update_users_revision(conn).await;
 
         if self.atype == SendType::File as i32 {
-            let operator = CONFIG.opendal_operator_for_path_type(&PathType::Sends)?;
-            operator.delete_with(&self.uuid).recursive(true).await.ok();
+            let backend = CONFIG.storage_backend(&PathType::Sends)?;
+            backend.delete(&self.uuid, true).await.ok();
+        }
+        if let Some(owner) = self.organization_uuid.as_deref().or(self.user_uuid.as_deref()) {
+            Tombstone::record(TombstoneType::Send, &self.uuid, owner, conn).await?;
         }
 
         conn.run(move |conn| {
@@ -243,10 +293,15 @@ impl Send {
     }
 
     /// Purge all sends that are past their deletion date.
-    pub async fn purge(conn: &DbConn) {
+    /// Returns the sends which were deleted, so the owners can be notified.
+    pub async fn purge(conn: &DbConn) -> Vec<Self> {
+        let mut purged = Vec::new();
         for send in Self::find_by_past_deletion_date(conn).await {
-            send.delete(conn).await.ok();
+            if send.delete(conn).await.is_ok() {
+                purged.push(send);
+            }
         }
+        purged
     }
 
     pub async fn update_users_revision(&self, conn: &DbConn) -> Vec<UserId> {
@@ -255,7 +310,7 @@ impl Send {
             User::update_uuid_revision(user_uuid, conn).await;
             user_uuids.push(user_uuid.clone());
         } else {
-            // Belongs to Organization, not implemented
+            // Sends of an organization aren't synced to the clients, they are managed with the organization routes
         }
         user_uuids
     }
@@ -267,6 +322,13 @@ impl Send {
         Ok(())
     }
 
+    pub async fn delete_all_by_organization(org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
+        for send in Self::find_by_org(org_uuid, conn).await {
+            send.delete(conn).await?;
+        }
+        Ok(())
+    }
+
     pub async fn find_by_access_id(access_id: &str, conn: &DbConn) -> Option<Self> {
         let Ok(uuid_vec) = BASE64URL_NOPAD.decode(access_id.as_bytes()) else {
             return None;
@@ -284,6 +346,17 @@ impl Send {
         conn.run(move |conn| sends::table.filter(sends::uuid.eq(uuid)).first::<Self>(conn).ok()).await
     }
 
+    pub async fn find_by_uuid_and_org(uuid: &SendId, org_uuid: &OrganizationId, conn: &DbConn) -> Option<Self> {
+        conn.run(move |conn| {
+            sends::table
+                .filter(sends::uuid.eq(uuid))
+                .filter(sends::organization_uuid.eq(org_uuid))
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
     pub async fn find_by_uuid_and_user(uuid: &SendId, user_uuid: &UserId, conn: &DbConn) -> Option<Self> {
         conn.run(move |conn| {
             sends::table.filter(sends::uuid.eq(uuid)).filter(sends::user_uuid.eq(user_uuid)).first::<Self>(conn).ok()
@@ -299,26 +372,76 @@ impl Send {
     }
 
     pub async fn size_by_user(user_uuid: &UserId, conn: &DbConn) -> Option<i64> {
-        #[derive(serde::Deserialize)]
-        struct FileData {
-            #[serde(rename = "size", alias = "Size")]
-            size: NumberOrString,
-        }
-
         let sends = Self::find_by_user(user_uuid, conn).await;
         let mut total: i64 = 0;
-        for send in sends {
-            if send.atype == SendType::File as i32
-                && let Ok(size) =
-                    serde_json::from_str::<FileData>(&send.data).map_err(Into::into).and_then(|d| d.size.into_i64())
-            {
-                total = total.checked_add(size)?;
-            }
+        for size in sends.iter().filter_map(Self::file_size) {
+            total = total.checked_add(size)?;
         }
 
         Some(total)
     }
 
+    pub async fn count_by_user(user_uuid: &UserId, conn: &DbConn) -> i64 {
+        conn.run(move |conn| {
+            sends::table.filter(sends::user_uuid.eq(user_uuid)).count().first::<i64>(conn).ok().unwrap_or(0)
+        })
+        .await
+    }
+
+    /// Counts an access to the file of the Send and adds the downloaded bytes in a single statement,
+    /// so concurrent downloads can neither lose an update nor exceed the max access count.
+    /// Returns `false` if the max access count has already been reached.
+    pub async fn register_file_access(
+        uuid: &SendId,
+        bytes: i64,
+        revision_date: NaiveDateTime,
+        conn: &DbConn,
+    ) -> Result<bool, crate::Error> {
+        conn.run(move |conn| {
+            diesel::update(sends::table.filter(sends::uuid.eq(uuid)).filter(
+                sends::max_access_count.is_null().or(sends::access_count.lt(sends::max_access_count.assume_not_null())),
+            ))
+            .set((
+                sends::access_count.eq(sends::access_count + 1),
+                sends::download_bytes.eq(sends::download_bytes + bytes),
+                sends::revision_date.eq(revision_date),
+            ))
+            .execute(conn)
+            .map(|updated| updated == 1)
+            .map_res("Error updating the access count of the send")
+        })
+        .await
+    }
+
+    pub async fn find_most_downloaded(limit: i64, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            sends::table
+                .filter(sends::download_bytes.gt(0))
+                .order(sends::download_bytes.desc())
+                .limit(limit)
+                .load::<Self>(conn)
+                .unwrap_or_default()
+        })
+        .await
+    }
+
+    /// Returns the number of Sends, and the total size of all the files of file Sends
+    pub async fn count_and_size_all(conn: &DbConn) -> (i64, i64) {
+        conn.run(move |conn| {
+            let count = sends::table.count().first::<i64>(conn).unwrap_or(0);
+            // Only the data of the file Sends is needed for the size, text Sends can be large and are skipped
+            let file_data = sends::table
+                .filter(sends::atype.eq(SendType::File as i32))
+                .select(sends::data)
+                .load::<String>(conn)
+                .unwrap_or_default();
+            let size =
+                file_data.iter().map(String::as_str).filter_map(Self::file_size_of).fold(0i64, i64::saturating_add);
+            (count, size)
+        })
+        .await
+    }
+
     pub async fn find_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
             sends::table.filter(sends::organization_uuid.eq(org_uuid)).load::<Self>(conn).expect("Error loading sends")
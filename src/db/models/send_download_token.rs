@@ -0,0 +1,58 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+
+use crate::{
+    db::{DbConn, schema::send_download_tokens},
+    error::MapResult,
+    util::get_uuid,
+};
+
+/// A download of a Send file granted by the access endpoint, which is consumed by the first download,
+/// so the link handed out to a recipient can't be used again to bypass the access count.
+/// Presigned URLs of an object store are served by the store itself and aren't covered by this.
+#[derive(Identifiable, Queryable, Insertable)]
+#[diesel(table_name = send_download_tokens)]
+#[diesel(primary_key(uuid))]
+pub struct SendDownloadToken {
+    pub uuid: String,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Database methods
+impl SendDownloadToken {
+    /// Creates a token valid for `ttl`, the expired tokens which were never used are removed on the way
+    pub async fn create(ttl: TimeDelta, conn: &DbConn) -> Result<String, crate::Error> {
+        let now = Utc::now().naive_utc();
+        let token = Self {
+            uuid: get_uuid(),
+            expires_at: now + ttl,
+        };
+
+        conn.run(move |conn| {
+            diesel::delete(send_download_tokens::table.filter(send_download_tokens::expires_at.lt(now)))
+                .execute(conn)
+                .map_res("Error removing expired Send download tokens")?;
+            diesel::insert_into(send_download_tokens::table)
+                .values(&token)
+                .execute(conn)
+                .map_res("Error saving Send download token")?;
+            Ok(token.uuid)
+        })
+        .await
+    }
+
+    /// Consumes the token, returns `false` if it doesn't exist, has expired or was already used
+    pub async fn consume(uuid: &str, conn: &DbConn) -> bool {
+        let now = Utc::now().naive_utc();
+        conn.run(move |conn| {
+            diesel::delete(
+                send_download_tokens::table
+                    .filter(send_download_tokens::uuid.eq(uuid))
+                    .filter(send_download_tokens::expires_at.ge(now)),
+            )
+            .execute(conn)
+            .is_ok_and(|deleted| deleted == 1)
+        })
+        .await
+    }
+}
@@ -0,0 +1,66 @@
+use chrono::Utc;
+use diesel::prelude::*;
+
+use crate::db::{DbConn, DbConnInner, schema::send_egress};
+
+/// Bytes served by the anonymous Send downloads per month, used to enforce `SEND_MONTHLY_EGRESS_LIMIT`.
+/// Months are stored as `YYYY-MM` in UTC.
+#[derive(Identifiable, Queryable, Insertable)]
+#[diesel(table_name = send_egress)]
+#[diesel(primary_key(month))]
+pub struct SendEgress {
+    pub month: String,
+    pub bytes: i64,
+}
+
+/// Local methods
+impl SendEgress {
+    fn current_month() -> String {
+        Utc::now().format("%Y-%m").to_string()
+    }
+}
+
+/// Database methods
+impl SendEgress {
+    /// Adds the bytes to the total of the current month
+    pub async fn add(bytes: i64, conn: &DbConn) {
+        let month = Self::current_month();
+        conn.run(move |conn| {
+            let add = |conn: &mut DbConnInner| {
+                diesel::update(send_egress::table)
+                    .filter(send_egress::month.eq(&month))
+                    .set(send_egress::bytes.eq(send_egress::bytes + bytes))
+                    .execute(conn)
+                    .unwrap_or_default()
+            };
+            if add(conn) == 1 {
+                return;
+            }
+
+            // First download of the month, this fails on the primary key if another download inserted it first
+            let inserted = diesel::insert_into(send_egress::table)
+                .values(Self {
+                    month: month.clone(),
+                    bytes,
+                })
+                .execute(conn)
+                .is_ok();
+            if !inserted && add(conn) != 1 {
+                error!("Error adding the Send egress of {month}");
+            }
+        })
+        .await;
+    }
+
+    pub async fn current_month_bytes(conn: &DbConn) -> i64 {
+        let month = Self::current_month();
+        conn.run(move |conn| {
+            send_egress::table
+                .filter(send_egress::month.eq(month))
+                .select(send_egress::bytes)
+                .first::<i64>(conn)
+                .unwrap_or(0)
+        })
+        .await
+    }
+}
@@ -0,0 +1,163 @@
+use std::sync::OnceLock;
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::tombstones},
+    error::MapResult,
+    util::format_date,
+};
+
+/// How long deletions are remembered, a delta sync from before that returns the whole vault again
+pub const TOMBSTONE_RETENTION_DAYS: i64 = 90;
+
+/// The migration which started tracking access and per-user changes, see `Tombstone::tracked_since`
+const TRACKING_MIGRATION: &str = "20261018100000";
+static TRACKING_START: OnceLock<NaiveDateTime> = OnceLock::new();
+
+#[derive(QueryableByName)]
+struct MigrationRun {
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    run_on: NaiveDateTime,
+}
+
+/// Records that an item was deleted, so a delta sync can tell the clients to remove it.
+/// The owner is the user or the organization the item belonged to.
+/// When a user leaves an organization, an `Organization` tombstone owned by the user is recorded instead of one per item.
+/// When a user loses access to some items of an organization, an `Access` tombstone with the user as item and owner is
+/// recorded, the next delta sync of that user then returns the whole vault.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = tombstones)]
+#[diesel(primary_key(uuid, owner_uuid))]
+pub struct Tombstone {
+    pub uuid: String,
+    pub owner_uuid: String,
+    pub atype: i32,
+    pub deleted_at: NaiveDateTime,
+}
+
+#[derive(Copy, Clone)]
+pub enum TombstoneType {
+    Cipher = 0,
+    Folder = 1,
+    Send = 2,
+    Collection = 3,
+    Organization = 4,
+    Access = 5,
+}
+
+impl Tombstone {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.uuid,
+            "type": self.atype,
+            "deletedDate": format_date(&self.deleted_at),
+            "object": "tombstone",
+        })
+    }
+}
+
+/// Database methods
+impl Tombstone {
+    pub async fn record(atype: TombstoneType, uuid: &str, owner_uuid: &str, conn: &DbConn) -> EmptyResult {
+        let tombstone = Self {
+            uuid: uuid.to_owned(),
+            owner_uuid: owner_uuid.to_owned(),
+            atype: atype as i32,
+            deleted_at: Utc::now().naive_utc(),
+        };
+        db_run! { conn:
+            sqlite, mysql {
+                diesel::replace_into(tombstones::table)
+                    .values(&tombstone)
+                    .execute(conn)
+                    .map_res("Error saving tombstone")
+            }
+            postgresql {
+                diesel::insert_into(tombstones::table)
+                    .values(&tombstone)
+                    .on_conflict((tombstones::uuid, tombstones::owner_uuid))
+                    .do_update()
+                    .set(&tombstone)
+                    .execute(conn)
+                    .map_res("Error saving tombstone")
+            }
+        }
+    }
+
+    pub async fn find_by_owners_since(owner_uuids: Vec<String>, since: NaiveDateTime, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            tombstones::table
+                .filter(tombstones::owner_uuid.eq_any(owner_uuids))
+                .filter(tombstones::deleted_at.gt(since))
+                .order(tombstones::deleted_at)
+                .load::<Self>(conn)
+                .unwrap_or_default()
+        })
+        .await
+    }
+
+    /// Whether the access changes of the user since the given date would need a full sync
+    pub async fn has_access_change_since(user_uuid: &str, since: NaiveDateTime, conn: &DbConn) -> bool {
+        let user_uuid = user_uuid.to_owned();
+        conn.run(move |conn| {
+            tombstones::table
+                .filter(tombstones::uuid.eq(&user_uuid))
+                .filter(tombstones::owner_uuid.eq(&user_uuid))
+                .filter(tombstones::atype.eq(TombstoneType::Access as i32))
+                .filter(tombstones::deleted_at.gt(since))
+                .count()
+                .first::<i64>(conn)
+                .map_or(true, |count| count > 0)
+        })
+        .await
+    }
+
+    /// Whether deletions and access changes since the given date are still all known
+    pub async fn covers(since: NaiveDateTime, conn: &DbConn) -> bool {
+        since > Utc::now().naive_utc() - TimeDelta::days(TOMBSTONE_RETENTION_DAYS)
+            && Self::tracked_since(conn).await.is_some_and(|start| since >= start)
+    }
+
+    /// When the migration which started tracking access and per-user changes ran,
+    /// changes from before that aren't known and need a full sync
+    async fn tracked_since(conn: &DbConn) -> Option<NaiveDateTime> {
+        if let Some(start) = TRACKING_START.get() {
+            return Some(*start);
+        }
+        let start = conn
+            .run(|conn| {
+                diesel::sql_query(format!(
+                    "SELECT run_on FROM __diesel_schema_migrations WHERE version = '{TRACKING_MIGRATION}'"
+                ))
+                .get_result::<MigrationRun>(conn)
+                .ok()
+            })
+            .await?
+            .run_on;
+        Some(*TRACKING_START.get_or_init(|| start))
+    }
+
+    pub async fn purge(conn: &DbConn) -> EmptyResult {
+        let limit = Utc::now().naive_utc() - TimeDelta::days(TOMBSTONE_RETENTION_DAYS);
+        conn.run(move |conn| {
+            diesel::delete(tombstones::table.filter(tombstones::deleted_at.le(limit)))
+                .execute(conn)
+                .map_res("Error purging tombstones")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_owner(owner_uuid: &str, conn: &DbConn) -> EmptyResult {
+        let owner_uuid = owner_uuid.to_owned();
+        conn.run(move |conn| {
+            diesel::delete(tombstones::table.filter(tombstones::owner_uuid.eq(owner_uuid)))
+                .execute(conn)
+                .map_res("Error deleting tombstones")
+        })
+        .await
+    }
+}
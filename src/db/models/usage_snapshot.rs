@@ -0,0 +1,111 @@
+use chrono::{NaiveDate, TimeDelta, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::usage_snapshots},
+    error::MapResult,
+};
+
+use super::{Attachment, Cipher, Device, Event, Send, User};
+
+/// Daily totals of the instance, used by operators to follow the growth of the instance over time.
+/// Only one snapshot is kept per day, taking a new one on the same day replaces it.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = usage_snapshots)]
+#[diesel(primary_key(day))]
+pub struct UsageSnapshot {
+    pub day: NaiveDate,
+    pub users: i64,
+    // Devices which were used in the last 30 days
+    pub active_devices: i64,
+    pub ciphers: i64,
+    pub attachments: i64,
+    pub attachments_bytes: i64,
+    pub sends: i64,
+    pub sends_bytes: i64,
+    // Events logged in the last 24 hours
+    pub events: i64,
+}
+
+/// Local methods
+impl UsageSnapshot {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "day": self.day.format("%Y-%m-%d").to_string(),
+            "users": self.users,
+            "activeDevices": self.active_devices,
+            "ciphers": self.ciphers,
+            "attachments": self.attachments,
+            "attachmentsBytes": self.attachments_bytes,
+            "sends": self.sends,
+            "sendsBytes": self.sends_bytes,
+            "events": self.events,
+        })
+    }
+}
+
+/// Database methods
+impl UsageSnapshot {
+    /// Collects the current totals, without saving them
+    pub async fn capture(conn: &DbConn) -> Self {
+        let now = Utc::now().naive_utc();
+        let (sends, sends_bytes) = Send::count_and_size_all(conn).await;
+
+        Self {
+            day: now.date(),
+            users: User::count_all(conn).await,
+            active_devices: Device::count_active_since(now - TimeDelta::try_days(30).unwrap(), conn).await,
+            ciphers: Cipher::count_all(conn).await,
+            attachments: Attachment::count_all(conn).await,
+            attachments_bytes: Attachment::size_all(conn).await,
+            sends,
+            sends_bytes,
+            events: Event::count_since(now - TimeDelta::try_days(1).unwrap(), conn).await,
+        }
+    }
+
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        db_run! { conn:
+            sqlite, mysql {
+                diesel::replace_into(usage_snapshots::table)
+                    .values(self)
+                    .execute(conn)
+                    .map_res("Error saving usage snapshot")
+            }
+            postgresql {
+                diesel::insert_into(usage_snapshots::table)
+                    .values(self)
+                    .on_conflict(usage_snapshots::day)
+                    .do_update()
+                    .set(self)
+                    .execute(conn)
+                    .map_res("Error saving usage snapshot")
+            }
+        }
+    }
+
+    pub async fn delete_older_than(days: u32, conn: &DbConn) -> EmptyResult {
+        let before = Utc::now().date_naive() - TimeDelta::try_days(i64::from(days)).unwrap();
+        conn.run(move |conn| {
+            diesel::delete(usage_snapshots::table.filter(usage_snapshots::day.lt(before)))
+                .execute(conn)
+                .map_res("Error deleting old usage snapshots")
+        })
+        .await
+    }
+
+    /// Returns the snapshots of the last `days` days, oldest first
+    pub async fn find_since_days(days: i64, conn: &DbConn) -> Vec<Self> {
+        let since = Utc::now().date_naive() - TimeDelta::try_days(days).unwrap_or_default();
+        conn.run(move |conn| {
+            usage_snapshots::table
+                .filter(usage_snapshots::day.gt(since))
+                .order(usage_snapshots::day)
+                .load::<Self>(conn)
+                .expect("Error loading usage snapshots")
+        })
+        .await
+    }
+}
@@ -10,7 +10,7 @@ use crate::{
     db::{
         DbConn,
         models::DeviceId,
-        schema::{invitations, sso_users, twofactor_incomplete, users},
+        schema::{ciphers, invitations, sso_users, twofactor_incomplete, users, users_organizations},
     },
     error::MapResult,
     sso::OIDCIdentifier,
@@ -19,7 +19,9 @@ use crate::{
 use macros::UuidFromParam;
 
 use super::{
-    Cipher, Device, EmergencyAccess, Favorite, Folder, Membership, MembershipType, TwoFactor, TwoFactorIncomplete,
+    AttachmentRekey, AttachmentShare, AuditGrant, AuthRequest, Cipher, CipherUserChange, Device, EmergencyAccess,
+    Favorite, Folder, LoginFingerprint, Membership, MembershipStatus, MembershipType, QuotaWarning, Tombstone,
+    TombstoneType, TwoFactor, TwoFactorIncomplete,
 };
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset, Selectable)]
@@ -69,6 +71,29 @@ pub struct User {
     pub avatar_color: Option<String>,
 
     pub external_id: Option<String>, // Todo: Needs to be removed in the future, this is not used anymore.
+
+    pub tenant_id: Option<String>,
+
+    // Until when the user allows the server admin to open support sessions for their account
+    pub support_access_until: Option<NaiveDateTime>,
+
+    // When the last invitation to this instance was sent by the admin
+    pub invited_at: Option<NaiveDateTime>,
+
+    pub last_login_at: Option<NaiveDateTime>,
+    // When the user was warned about the inactivity of the account, reset by the next login
+    pub inactivity_warned_at: Option<NaiveDateTime>,
+    // Excluded from the inactive users job by the admin
+    pub inactivity_exempt: bool,
+
+    // Whether the user wants to be notified by email of logins from new devices or countries
+    pub login_notifications: bool,
+
+    // When an email or two-step login change was started which isn't confirmed yet
+    pub security_change_pending_at: Option<NaiveDateTime>,
+
+    // Excluded from the per-user item and Send quotas by the admin
+    pub quota_exempt: bool,
 }
 
 #[derive(Identifiable, Queryable, Insertable)]
@@ -154,9 +179,29 @@ impl User {
             avatar_color: None,
 
             external_id: None, // Todo: Needs to be removed in the future, this is not used anymore.
+
+            tenant_id: None,
+
+            support_access_until: None,
+
+            invited_at: None,
+
+            last_login_at: None,
+            inactivity_warned_at: None,
+            inactivity_exempt: false,
+
+            login_notifications: true,
+
+            security_change_pending_at: None,
+
+            quota_exempt: false,
         }
     }
 
+    pub fn has_support_access(&self) -> bool {
+        self.support_access_until.is_some_and(|until| until > Utc::now().naive_utc())
+    }
+
     pub fn check_valid_password(&self, password: &str) -> bool {
         crypto::verify_password_hash(
             password.as_bytes(),
@@ -340,6 +385,14 @@ impl User {
         Device::delete_all_by_user(&self.uuid, conn).await?;
         TwoFactor::delete_all_by_user(&self.uuid, conn).await?;
         TwoFactorIncomplete::delete_all_by_user(&self.uuid, conn).await?;
+        LoginFingerprint::delete_all_by_user(&self.uuid, conn).await?;
+        AuditGrant::delete_all_by_user(&self.uuid, conn).await?;
+        AttachmentRekey::delete_all_by_user(&self.uuid, conn).await?;
+        AttachmentShare::delete_all_by_user(&self.uuid, conn).await?;
+        AuthRequest::delete_all_by_user(&self.uuid, conn).await?;
+        QuotaWarning::delete_by_owner(self.uuid.as_ref(), conn).await?;
+        Tombstone::delete_all_by_owner(self.uuid.as_ref(), conn).await?;
+        CipherUserChange::delete_all_by_user(&self.uuid, conn).await?;
         Invitation::take(&self.email, conn).await; // Delete invitation if any
 
         conn.run(move |conn| {
@@ -354,6 +407,15 @@ impl User {
         }
     }
 
+    /// Like `update_uuid_revision`, for changes which can take away access to organization items,
+    /// so the next delta sync of the user returns the whole vault
+    pub async fn update_uuid_access_revision(uuid: &UserId, conn: &DbConn) {
+        Self::update_uuid_revision(uuid, conn).await;
+        if let Err(e) = Tombstone::record(TombstoneType::Access, uuid.as_ref(), uuid.as_ref(), conn).await {
+            warn!("Failed to record access change for {uuid}: {e:#?}");
+        }
+    }
+
     pub async fn update_all_revisions(conn: &DbConn) -> EmptyResult {
         let updated_at = Utc::now().naive_utc();
 
@@ -390,6 +452,26 @@ impl User {
         conn.run(move |conn| users::table.filter(users::email.eq(lower_mail)).first::<Self>(conn).ok()).await
     }
 
+    /// Returns the user with the email, or with another subaddress of it when `EMAIL_SUBADDRESS_NORMALIZATION` applies to its domain.
+    /// So `user+tag@example.com` finds `user@example.com` and the other way around, the oldest account first.
+    pub async fn find_by_mail_or_subaddress(mail: &str, conn: &DbConn) -> Option<Self> {
+        if let Some(user) = Self::find_by_mail(mail, conn).await {
+            return Some(user);
+        }
+        let (local, domain) = CONFIG.email_subaddress_base(mail)?;
+        let base = format!("{local}@{domain}");
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        let pattern = format!("{}+%@{}", escape(&local), escape(&domain));
+        conn.run(move |conn| {
+            users::table
+                .filter(users::email.eq(base).or(users::email.like(pattern).escape('\\')))
+                .order(users::created_at)
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
     pub async fn find_by_uuid(uuid: &UserId, conn: &DbConn) -> Option<Self> {
         conn.run(move |conn| users::table.filter(users::uuid.eq(uuid)).first::<Self>(conn).ok()).await
     }
@@ -411,6 +493,98 @@ impl User {
         None
     }
 
+    /// Users which registered with a master password before the given date, but never verified their email address nor logged in.
+    /// Accounts which logged in before the last login was stored got it from their devices by the migration.
+    pub async fn find_unverified_registered_before(dt: &NaiveDateTime, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            users::table
+                .filter(users::verified_at.is_null())
+                .filter(users::password_hash.ne(Vec::<u8>::new()))
+                .filter(users::created_at.lt(dt))
+                // Not the devices, they are removed after a while of inactivity, see `DEVICES_DAYS_RETAIN`
+                .filter(users::last_login_at.is_null())
+                .load::<Self>(conn)
+                .expect("Error loading unverified users")
+        })
+        .await
+    }
+
+    /// Users whose revision date is older than the newest change of a cipher they can see.
+    /// Clients only sync when the revision date changes, so these users don't receive those changes.
+    pub async fn find_with_stale_revision(conn: &DbConn) -> Vec<UserId> {
+        conn.run(move |conn| {
+            users::table
+                .filter(
+                    diesel::dsl::exists(
+                        ciphers::table
+                            .filter(ciphers::user_uuid.eq(users::uuid.nullable()))
+                            .filter(ciphers::updated_at.gt(users::updated_at)),
+                    )
+                    .or(diesel::dsl::exists(
+                        ciphers::table
+                            .inner_join(
+                                users_organizations::table
+                                    .on(ciphers::organization_uuid.eq(users_organizations::org_uuid.nullable())),
+                            )
+                            .filter(users_organizations::user_uuid.eq(users::uuid))
+                            .filter(users_organizations::status.eq(MembershipStatus::Confirmed as i32))
+                            .filter(ciphers::updated_at.gt(users::updated_at)),
+                    )),
+                )
+                .select(users::uuid)
+                .load::<UserId>(conn)
+                .expect("Error loading users with a stale revision date")
+        })
+        .await
+    }
+
+    /// Registered and enabled users without a login since `dt`, including the ones exempted by the admin
+    pub async fn find_inactive_since(dt: &NaiveDateTime, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            users::table
+                .filter(users::enabled.eq(true))
+                .filter(users::password_hash.ne(Vec::<u8>::new()))
+                .filter(users::last_login_at.lt(dt).or(users::last_login_at.is_null().and(users::created_at.lt(dt))))
+                .order(users::last_login_at.asc())
+                .load::<Self>(conn)
+                .expect("Error loading inactive users")
+        })
+        .await
+    }
+
+    /// Stores a successful login, which also ends the inactivity of the account
+    pub async fn update_last_login(uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        let now = Utc::now().naive_utc();
+        conn.run(move |conn| {
+            diesel::update(users::table.filter(users::uuid.eq(uuid)))
+                .set((users::last_login_at.eq(now), users::inactivity_warned_at.eq::<Option<NaiveDateTime>>(None)))
+                .execute(conn)
+                .map_res("Error updating the last login of the user")
+        })
+        .await
+    }
+
+    /// Users invited by the admin which didn't register yet
+    pub async fn find_invited(conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            users::table
+                .filter(users::invited_at.is_not_null())
+                .filter(users::password_hash.eq(Vec::<u8>::new()))
+                .order(users::invited_at.asc())
+                .load::<Self>(conn)
+                .expect("Error loading invited users")
+        })
+        .await
+    }
+
+    /// When the invitation of this user expires, `None` if the user isn't invited by the admin (anymore)
+    pub fn invite_expiration(&self) -> Option<NaiveDateTime> {
+        if !self.password_hash.is_empty() {
+            return None;
+        }
+        self.invited_at.as_ref().map(crate::auth::invite_expiration)
+    }
+
     pub async fn get_all(conn: &DbConn) -> Vec<(Self, Option<SsoUser>)> {
         conn.run(move |conn| {
             users::table
@@ -424,6 +598,21 @@ impl User {
         .await
     }
 
+    pub async fn count_all(conn: &DbConn) -> i64 {
+        conn.run(move |conn| users::table.count().first::<i64>(conn).ok().unwrap_or(0)).await
+    }
+
+    /// Whether an email or two-step login change is pending, during `SECURITY_CHANGE_LOCKOUT_HOURS` after it was started
+    pub fn has_pending_security_change(&self) -> bool {
+        let lockout = TimeDelta::try_hours(i64::from(CONFIG.security_change_lockout_hours())).unwrap_or_default();
+        self.security_change_pending_at.is_some_and(|dt| dt + lockout > Utc::now().naive_utc())
+    }
+
+    /// Accounts created before login tracking was added, and never used since, count from their creation
+    pub fn inactive_since(&self) -> NaiveDateTime {
+        self.last_login_at.unwrap_or(self.created_at)
+    }
+
     pub async fn last_active(&self, conn: &DbConn) -> Option<NaiveDateTime> {
         match Device::find_latest_active_by_user(&self.uuid, conn).await {
             Some(device) => Some(device.updated_at),
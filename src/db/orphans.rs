@@ -0,0 +1,104 @@
+use diesel::{
+    dsl::{exists, not},
+    prelude::*,
+};
+use serde_json::Value;
+
+use crate::{
+    api::ApiResult,
+    db::{
+        DbConn, DbConnInner,
+        schema::{
+            ciphers, ciphers_collections, collections, devices, favorites, groups_users, organizations, users,
+            users_organizations,
+        },
+    },
+    error::MapResult,
+};
+
+// Rows pointing at records which don't exist anymore.
+// The foreign keys prevent most of these, but they can be left behind by crashes, older versions or manual edits of the database.
+// They are listed in the admin diagnostics, and each category can be removed there.
+const CATEGORIES: &[(&str, &str)] = &[
+    ("memberships", "Organization memberships of deleted users or organizations"),
+    ("collection_ciphers", "Collection items of deleted ciphers or collections"),
+    ("favorites", "Favorites of deleted ciphers or users"),
+    ("devices", "Devices of deleted users"),
+];
+
+/// Counts the orphaned rows of every category
+pub async fn report(conn: &DbConn) -> Vec<Value> {
+    let mut report = Vec::with_capacity(CATEGORIES.len());
+    for (category, description) in CATEGORIES {
+        let count = conn.run(move |conn| process(category, false, conn)).await.unwrap_or_else(|e| {
+            error!("Error counting the orphaned {category}: {e:?}");
+            0
+        });
+        report.push(json!({
+            "category": category,
+            "description": description,
+            "count": count,
+        }));
+    }
+    report
+}
+
+/// Removes the orphaned rows of one category, returns the number of removed rows
+pub async fn cleanup(category: &str, conn: &DbConn) -> ApiResult<usize> {
+    if !CATEGORIES.iter().any(|(c, _)| *c == category) {
+        err!(format!("Unknown orphaned data category '{category}'"))
+    }
+    let category = category.to_owned();
+    conn.run(move |conn| process(&category, true, conn)).await.map_res("Error removing the orphaned data")
+}
+
+fn process(category: &str, delete: bool, conn: &mut DbConnInner) -> QueryResult<usize> {
+    let count = |c: i64| usize::try_from(c).unwrap_or_default();
+    match category {
+        "memberships" => {
+            let orphaned = not(exists(users::table.filter(users::uuid.eq(users_organizations::user_uuid))))
+                .or(not(exists(organizations::table.filter(organizations::uuid.eq(users_organizations::org_uuid)))));
+            if delete {
+                let removed = diesel::delete(users_organizations::table.filter(orphaned)).execute(conn)?;
+                // The group memberships can't be used anymore without the organization membership
+                diesel::delete(
+                    groups_users::table.filter(not(exists(
+                        users_organizations::table
+                            .filter(users_organizations::uuid.eq(groups_users::users_organizations_uuid)),
+                    ))),
+                )
+                .execute(conn)?;
+                Ok(removed)
+            } else {
+                users_organizations::table.filter(orphaned).count().get_result(conn).map(count)
+            }
+        }
+        "collection_ciphers" => {
+            let orphaned = not(exists(ciphers::table.filter(ciphers::uuid.eq(ciphers_collections::cipher_uuid))))
+                .or(not(exists(collections::table.filter(collections::uuid.eq(ciphers_collections::collection_uuid)))));
+            if delete {
+                diesel::delete(ciphers_collections::table.filter(orphaned)).execute(conn)
+            } else {
+                ciphers_collections::table.filter(orphaned).count().get_result(conn).map(count)
+            }
+        }
+        "favorites" => {
+            let orphaned = not(exists(ciphers::table.filter(ciphers::uuid.eq(favorites::cipher_uuid))))
+                .or(not(exists(users::table.filter(users::uuid.eq(favorites::user_uuid)))));
+            if delete {
+                diesel::delete(favorites::table.filter(orphaned)).execute(conn)
+            } else {
+                favorites::table.filter(orphaned).count().get_result(conn).map(count)
+            }
+        }
+        "devices" => {
+            let orphaned = not(exists(users::table.filter(users::uuid.eq(devices::user_uuid))));
+            if delete {
+                diesel::delete(devices::table.filter(orphaned)).execute(conn)
+            } else {
+                devices::table.filter(orphaned).count().get_result(conn).map(count)
+            }
+        }
+        _ => Ok(0),
+    }
+}
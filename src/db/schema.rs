@@ -1,3 +1,28 @@
+table! {
+    attachment_rekeys (attachment_id) {
+        attachment_id -> Text,
+        cipher_uuid -> Text,
+        user_uuid -> Text,
+        legacy -> Bool,
+        replaced_by -> Nullable<Text>,
+        created_at -> Timestamp,
+        verified_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    attachment_shares (token_hash) {
+        token_hash -> Text,
+        attachment_id -> Text,
+        cipher_uuid -> Text,
+        user_uuid -> Text,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        used_ip -> Nullable<Text>,
+    }
+}
+
 table! {
     attachments (id) {
         id -> Text,
@@ -5,6 +30,18 @@ table! {
         file_name -> Text,
         file_size -> BigInt,
         akey -> Nullable<Text>,
+        created_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    audit_grants (user_uuid, collection_uuid) {
+        user_uuid -> Text,
+        collection_uuid -> Text,
+        org_uuid -> Text,
+        granted_by_uuid -> Text,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
     }
 }
 
@@ -24,6 +61,8 @@ table! {
         password_history -> Nullable<Text>,
         deleted_at -> Nullable<Timestamp>,
         reprompt -> Nullable<Integer>,
+        external_id -> Nullable<Text>,
+        deleted_by -> Nullable<Text>,
     }
 }
 
@@ -34,12 +73,35 @@ table! {
     }
 }
 
+table! {
+    cipher_user_changes (user_uuid, cipher_uuid) {
+        user_uuid -> Text,
+        cipher_uuid -> Text,
+        changed_at -> Timestamp,
+    }
+}
+
+table! {
+    cipher_templates (uuid) {
+        uuid -> Text,
+        org_uuid -> Text,
+        name -> Text,
+        atype -> Integer,
+        fields -> Text,
+        default_collection_uuid -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 table! {
     collections (uuid) {
         uuid -> Text,
         org_uuid -> Text,
         name -> Text,
         external_id -> Nullable<Text>,
+        onboarding -> Bool,
+        confidential -> Bool,
     }
 }
 
@@ -55,6 +117,9 @@ table! {
         push_token -> Nullable<Text>,
         refresh_token -> Text,
         twofactor_remember -> Nullable<Text>,
+        trusted -> Bool,
+        blocked -> Bool,
+        push_rejected_at -> Nullable<Timestamp>,
     }
 }
 
@@ -76,6 +141,7 @@ table! {
         provider_uuid -> Nullable<Text>,
         provider_user_uuid -> Nullable<Text>,
         provider_org_uuid -> Nullable<Text>,
+        send_uuid -> Nullable<Text>,
     }
 }
 
@@ -103,12 +169,51 @@ table! {
     }
 }
 
+table! {
+    global_domain_overrides (atype) {
+        atype -> Integer,
+        domains -> Text,
+        disabled -> Bool,
+        updated_at -> Timestamp,
+    }
+}
+
 table! {
     invitations (email) {
         email -> Text,
     }
 }
 
+table! {
+    job_locks (name) {
+        name -> Text,
+        holder -> Text,
+        locked_until -> Timestamp,
+    }
+}
+
+table! {
+    login_fingerprints (user_uuid, device_uuid, device_type, country) {
+        user_uuid -> Text,
+        device_uuid -> Text,
+        device_type -> Integer,
+        country -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    org_domains (uuid) {
+        uuid -> Text,
+        org_uuid -> Text,
+        domain_name -> Text,
+        txt -> Text,
+        created_at -> Timestamp,
+        verified_at -> Nullable<Timestamp>,
+        last_checked_at -> Nullable<Timestamp>,
+    }
+}
+
 table! {
     org_policies (uuid) {
         uuid -> Text,
@@ -126,6 +231,25 @@ table! {
         billing_email -> Text,
         private_key -> Nullable<Text>,
         public_key -> Nullable<Text>,
+        tenant_id -> Nullable<Text>,
+        limit_collection_creation -> Bool,
+        limit_collection_deletion -> Bool,
+        limit_item_deletion -> Bool,
+        allow_admin_access_to_all_collection_items -> Bool,
+        welcome_message -> Nullable<Text>,
+        confidential_collections -> Bool,
+        icon_service -> Nullable<Text>,
+        webhook_url -> Nullable<Text>,
+        webhook_secret -> Nullable<Text>,
+        enforce_cipher_templates -> Bool,
+    }
+}
+
+table! {
+    quota_warnings (owner_uuid) {
+        owner_uuid -> Text,
+        level -> Integer,
+        updated_at -> Timestamp,
     }
 }
 
@@ -150,6 +274,31 @@ table! {
         deletion_date -> Timestamp,
         disabled -> Bool,
         hide_email -> Nullable<Bool>,
+        preview -> Nullable<Text>,
+        download_bytes -> BigInt,
+    }
+}
+
+table! {
+    send_download_tokens (uuid) {
+        uuid -> Text,
+        expires_at -> Timestamp,
+    }
+}
+
+table! {
+    send_egress (month) {
+        month -> Text,
+        bytes -> BigInt,
+    }
+}
+
+table! {
+    tombstones (uuid, owner_uuid) {
+        uuid -> Text,
+        owner_uuid -> Text,
+        atype -> Integer,
+        deleted_at -> Timestamp,
     }
 }
 
@@ -184,6 +333,20 @@ table! {
     }
 }
 
+table! {
+    usage_snapshots (day) {
+        day -> Date,
+        users -> BigInt,
+        active_devices -> BigInt,
+        ciphers -> BigInt,
+        attachments -> BigInt,
+        attachments_bytes -> BigInt,
+        sends -> BigInt,
+        sends_bytes -> BigInt,
+        events -> BigInt,
+    }
+}
+
 table! {
     users (uuid) {
         uuid -> Text,
@@ -217,6 +380,15 @@ table! {
         api_key -> Nullable<Text>,
         avatar_color -> Nullable<Text>,
         external_id -> Nullable<Text>,
+        tenant_id -> Nullable<Text>,
+        support_access_until -> Nullable<Timestamp>,
+        invited_at -> Nullable<Timestamp>,
+        last_login_at -> Nullable<Timestamp>,
+        inactivity_warned_at -> Nullable<Timestamp>,
+        inactivity_exempt -> Bool,
+        login_notifications -> Bool,
+        security_change_pending_at -> Nullable<Timestamp>,
+        quota_exempt -> Bool,
     }
 }
 
@@ -242,6 +414,9 @@ table! {
         atype -> Integer,
         reset_password_key -> Nullable<Text>,
         external_id -> Nullable<Text>,
+        invited_at -> Nullable<Timestamp>,
+        two_factor_grace_until -> Nullable<Timestamp>,
+        manage_sends -> Bool,
     }
 }
 
@@ -343,6 +518,17 @@ table! {
     }
 }
 
+table! {
+    admin_audit_log (uuid) {
+        uuid -> Text,
+        created_at -> Timestamp,
+        ip -> Text,
+        action -> Text,
+        user_uuid -> Nullable<Text>,
+        details -> Nullable<Text>,
+    }
+}
+
 table! {
     archives (user_uuid, cipher_uuid) {
         user_uuid -> Text,
@@ -363,6 +549,8 @@ joinable!(devices -> users (user_uuid));
 joinable!(folders -> users (user_uuid));
 joinable!(folders_ciphers -> ciphers (cipher_uuid));
 joinable!(folders_ciphers -> folders (folder_uuid));
+joinable!(org_domains -> organizations (org_uuid));
+joinable!(cipher_templates -> organizations (org_uuid));
 joinable!(org_policies -> organizations (org_uuid));
 joinable!(sends -> organizations (organization_uuid));
 joinable!(sends -> users (user_uuid));
@@ -382,17 +570,22 @@ joinable!(collections_groups -> groups (groups_uuid));
 joinable!(event -> users_organizations (uuid));
 joinable!(auth_requests -> users (user_uuid));
 joinable!(sso_users -> users (user_uuid));
+joinable!(login_fingerprints -> users (user_uuid));
 
 allow_tables_to_appear_in_same_query!(
+    admin_audit_log,
     archives,
+    attachment_shares,
     attachments,
     ciphers,
     ciphers_collections,
     collections,
     devices,
+    favorites,
     folders,
     folders_ciphers,
     invitations,
+    org_domains,
     org_policies,
     organizations,
     sends,
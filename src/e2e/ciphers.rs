@@ -0,0 +1,234 @@
+use diesel::prelude::*;
+use rocket::http::Status;
+
+use super::TestClient;
+use crate::{crypto, db::schema::job_locks};
+
+#[tokio::test]
+async fn cipher_lifecycle() {
+    let client = TestClient::new().await;
+    let user = client.register_user().await;
+    let other = client.register_user().await;
+
+    let cipher = client.create_cipher(&user, "2.e2e|cipher|name").await;
+    let cipher_id = cipher["id"].as_str().unwrap();
+    let sync = client.sync(&user).await;
+    assert!(sync["ciphers"].as_array().unwrap().iter().any(|c| c["id"] == cipher_id));
+
+    let (status, updated) = client
+        .put(
+            &user,
+            &format!("/api/ciphers/{cipher_id}"),
+            &json!({
+                "type": 1,
+                "name": "2.e2e|renamed|name",
+                "login": {},
+                "lastKnownRevisionDate": cipher["revisionDate"],
+            }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "{updated}");
+    assert_eq!(updated["name"], "2.e2e|renamed|name");
+
+    // Other users can't read or delete the cipher
+    let (status, _) = client.get(&other, &format!("/api/ciphers/{cipher_id}")).await;
+    assert_ne!(status, Status::Ok);
+    assert_ne!(client.delete(&other, &format!("/api/ciphers/{cipher_id}")).await, Status::Ok);
+
+    assert_eq!(client.delete(&user, &format!("/api/ciphers/{cipher_id}")).await, Status::Ok);
+    let sync = client.sync(&user).await;
+    assert!(sync["ciphers"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn import_with_folders() {
+    let client = TestClient::new().await;
+    let user = client.register_user().await;
+
+    let (status, response) = client
+        .post(
+            &user,
+            "/api/ciphers/import",
+            &json!({
+                "ciphers": [
+                    { "type": 1, "name": "2.e2e|first|name", "login": {} },
+                    { "type": 2, "name": "2.e2e|second|name", "secureNote": { "type": 0 } },
+                ],
+                "folders": [{ "name": "2.e2e|folder|name" }],
+                "folderRelationships": [{ "key": 1, "value": 0 }],
+            }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "{response}");
+
+    let sync = client.sync(&user).await;
+    let folders = sync["folders"].as_array().unwrap();
+    assert_eq!(folders.len(), 1);
+    let ciphers = sync["ciphers"].as_array().unwrap();
+    assert_eq!(ciphers.len(), 2);
+    for cipher in ciphers {
+        if cipher["type"] == 2 {
+            assert_eq!(cipher["folderId"], folders[0]["id"]);
+        } else {
+            assert!(cipher["folderId"].is_null());
+        }
+    }
+}
+
+#[tokio::test]
+async fn attachment_roundtrip() {
+    let client = TestClient::new().await;
+    let user = client.register_user().await;
+    let other = client.register_user().await;
+
+    let cipher = client.create_cipher(&user, "2.e2e|cipher|name").await;
+    let cipher_id = cipher["id"].as_str().unwrap();
+    let content = b"encrypted attachment content";
+    let attachment_id = client.upload_attachment(&user, cipher_id, "2.e2e|file|name", content).await;
+
+    let (status, _) = client.get(&other, &format!("/api/ciphers/{cipher_id}/attachment/{attachment_id}")).await;
+    assert_ne!(status, Status::Ok);
+
+    let (status, attachment) = client.get(&user, &format!("/api/ciphers/{cipher_id}/attachment/{attachment_id}")).await;
+    assert_eq!(status, Status::Ok, "{attachment}");
+    assert_eq!(attachment["size"], content.len().to_string());
+    let (status, downloaded) = client.download(attachment["url"].as_str().unwrap()).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(downloaded, content);
+
+    assert_eq!(client.delete(&user, &format!("/api/ciphers/{cipher_id}/attachment/{attachment_id}")).await, Status::Ok);
+    let (status, _) = client.get(&user, &format!("/api/ciphers/{cipher_id}/attachment/{attachment_id}")).await;
+    assert_ne!(status, Status::Ok);
+}
+
+#[tokio::test]
+async fn resumable_attachment_upload() {
+    let client = TestClient::new().await;
+    let user = client.register_user().await;
+
+    let cipher = client.create_cipher(&user, "2.e2e|cipher|name").await;
+    let cipher_id = cipher["id"].as_str().unwrap();
+    let content = b"encrypted attachment content, sent in two chunks";
+    let (first, second) = content.split_at(20);
+    let attachment_id = client.create_attachment(&user, cipher_id, "2.e2e|file|name", content.len()).await;
+    let upload_path = format!("/api/ciphers/{cipher_id}/attachment/{attachment_id}/upload");
+
+    let (status, upload) = client.get(&user, &upload_path).await;
+    assert_eq!(status, Status::Ok, "{upload}");
+    assert_eq!(upload["offset"], 0);
+    let (status, upload) = client.upload_chunk(&user, &upload_path, 0, first).await;
+    assert_eq!(status, Status::Ok, "{upload}");
+    assert_eq!(upload["offset"], first.len());
+
+    // A chunk sent again, for example after losing the response, isn't appended twice
+    let (status, _) = client.upload_chunk(&user, &upload_path, 0, first).await;
+    assert_eq!(status, Status::Conflict);
+    let (_, upload) = client.get(&user, &upload_path).await;
+    assert_eq!(upload["offset"], first.len());
+
+    // Data not matching its hash has to be uploaded again
+    let (status, upload) = client.upload_chunk(&user, &upload_path, first.len(), second).await;
+    assert_eq!(status, Status::Ok, "{upload}");
+    assert_eq!(upload["offset"], content.len());
+    let (status, _) = client.post(&user, &upload_path, &json!({ "sha256": crypto::sha256_hex(first) })).await;
+    assert_ne!(status, Status::Ok);
+    let (_, upload) = client.get(&user, &upload_path).await;
+    assert_eq!(upload["offset"], 0);
+
+    let (status, upload) = client.upload_chunk(&user, &upload_path, 0, content).await;
+    assert_eq!(status, Status::Ok, "{upload}");
+    let (status, completed) = client.post(&user, &upload_path, &json!({ "sha256": crypto::sha256_hex(content) })).await;
+    assert_eq!(status, Status::Ok, "{completed}");
+
+    // Once completed the data can be downloaded, but not uploaded anymore
+    let (status, attachment) = client.get(&user, &format!("/api/ciphers/{cipher_id}/attachment/{attachment_id}")).await;
+    assert_eq!(status, Status::Ok, "{attachment}");
+    let (status, downloaded) = client.download(attachment["url"].as_str().unwrap()).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(downloaded, content);
+    let (status, _) = client.get(&user, &upload_path).await;
+    assert_ne!(status, Status::Ok);
+}
+
+#[tokio::test]
+async fn resumable_upload_stays_on_its_instance() {
+    let client = TestClient::new().await;
+    let user = client.register_user().await;
+
+    let cipher = client.create_cipher(&user, "2.e2e|cipher|name").await;
+    let cipher_id = cipher["id"].as_str().unwrap();
+    let content = b"encrypted attachment content";
+    let attachment_id = client.create_attachment(&user, cipher_id, "2.e2e|file|name", content.len()).await;
+    let upload_path = format!("/api/ciphers/{cipher_id}/attachment/{attachment_id}/upload");
+    let (status, upload) = client.upload_chunk(&user, &upload_path, 0, &content[..10]).await;
+    assert_eq!(status, Status::Ok, "{upload}");
+
+    // Another instance sharing the database has received the data instead, which isn't available here
+    let conn = super::test_pool().await.get().await.unwrap();
+    let lock_name = format!("upload:{attachment_id}");
+    let updated = conn
+        .run(move |conn| {
+            diesel::update(job_locks::table.filter(job_locks::name.eq(lock_name)))
+                .set(job_locks::holder.eq("e2e-other-instance"))
+                .execute(conn)
+        })
+        .await;
+    assert_eq!(updated.unwrap(), 1);
+
+    let (status, _) = client.get(&user, &upload_path).await;
+    assert_eq!(status, Status::Conflict);
+    let (status, _) = client.upload_chunk(&user, &upload_path, 10, &content[10..]).await;
+    assert_eq!(status, Status::Conflict);
+    let (status, _) = client.post(&user, &upload_path, &json!({ "sha256": crypto::sha256_hex(content) })).await;
+    assert_eq!(status, Status::Conflict);
+}
+
+#[tokio::test]
+async fn unique_external_ids() {
+    let client = TestClient::new().await;
+    let org = client.create_organization().await;
+    let user = &org.owner;
+    let body = json!({ "type": 1, "name": "2.e2e|cipher|name", "login": {}, "externalId": "terraform/db-password" });
+
+    let (status, cipher) = client.post(user, "/api/ciphers", &body).await;
+    assert_eq!(status, Status::Ok, "{cipher}");
+    assert_eq!(cipher["externalId"], "terraform/db-password");
+    let (status, _) = client.post(user, "/api/ciphers", &body).await;
+    assert_ne!(status, Status::Ok);
+
+    // Saving another item with a taken id fails and leaves the first one in place
+    let other = client.create_cipher(user, "2.e2e|other|name").await;
+    let other_id = other["id"].as_str().unwrap();
+    let mut update = body.clone();
+    update["lastKnownRevisionDate"] = other["revisionDate"].clone();
+    let (status, _) = client.put(user, &format!("/api/ciphers/{other_id}"), &update).await;
+    assert_ne!(status, Status::Ok);
+
+    // The id is unique per owner, the organization can use the same one
+    let shared = client.create_org_cipher(&org, "2.e2e|shared|name").await;
+    let shared_id = shared["id"].as_str().unwrap();
+    let (status, shared) = client
+        .put(
+            user,
+            &format!("/api/ciphers/{shared_id}"),
+            &json!({
+                "type": 1,
+                "name": "2.e2e|shared|name",
+                "login": {},
+                "organizationId": org.id,
+                "externalId": "terraform/db-password",
+                "lastKnownRevisionDate": shared["revisionDate"],
+            }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "{shared}");
+
+    let (status, found) = client.get(user, "/api/ciphers/by-external-id/terraform%2Fdb-password").await;
+    assert_eq!(status, Status::Ok, "{found}");
+    assert_eq!(found["id"], cipher["id"]);
+    let (status, found) = client
+        .get(user, &format!("/api/ciphers/by-external-id/terraform%2Fdb-password?organizationId={}", org.id))
+        .await;
+    assert_eq!(status, Status::Ok, "{found}");
+    assert_eq!(found["id"], shared_id);
+}
@@ -0,0 +1,288 @@
+use rocket::{
+    http::{ContentType, Header, Status},
+    local::asynchronous::{Client, LocalRequest, LocalResponse},
+};
+use serde_json::Value;
+
+use crate::{CONFIG, build_rocket, util::get_uuid};
+
+// The server doesn't check the hash, it only stores its own hash of it
+const MASTER_PASSWORD_HASH: &str = "e2e-master-password-hash";
+// Recent enough to receive all the fields of the responses
+const CLIENT_VERSION: &str = "2025.10.0";
+
+/// A registered and logged in user
+pub struct TestUser {
+    pub email: String,
+    access_token: String,
+}
+
+/// An organization with its default collection, owned by a user registered for it
+pub struct TestOrganization {
+    pub id: String,
+    pub collection_id: String,
+    pub owner: TestUser,
+}
+
+/// Client for the in-process server, with typed helpers for the most used routes
+pub struct TestClient {
+    client: Client,
+}
+
+impl TestClient {
+    pub async fn new() -> Self {
+        let rocket = build_rocket(super::test_pool().await, false).await.expect("Error building the server");
+        Self {
+            client: Client::untracked(rocket).await.expect("Error creating the local client"),
+        }
+    }
+
+    /// Registers a new user with a random email address and logs in with a new device
+    pub async fn register_user(&self) -> TestUser {
+        self.register_user_on(None).await
+    }
+
+    /// Same as `register_user`, but on the given host, which selects the tenant of the user
+    pub async fn register_user_on(&self, host: Option<&str>) -> TestUser {
+        let email = format!("{}@example.com", get_uuid());
+        let response = Self::on_host(self.client.post("/identity/accounts/register"), host)
+            .json(&json!({
+                "email": email,
+                "kdf": 0,
+                "kdfIterations": 600_000,
+                "key": "2.e2e|user|key",
+                "keys": {
+                    "publicKey": "e2e-public-key",
+                    "encryptedPrivateKey": "2.e2e|private|key",
+                },
+                "masterPasswordHash": MASTER_PASSWORD_HASH,
+            }))
+            .dispatch()
+            .await;
+        let status = response.status();
+        assert_eq!(status, Status::Ok, "Registration failed: {:?}", response.into_string().await);
+
+        let response = Self::on_host(self.client.post("/identity/connect/token"), host)
+            .header(ContentType::Form)
+            .header(Header::new("Bitwarden-Client-Version", CLIENT_VERSION))
+            .body(format!(
+                "grant_type=password&username={}&password={MASTER_PASSWORD_HASH}&scope=api%20offline_access\
+                 &client_id=web&deviceIdentifier={}&deviceName=e2e&deviceType=9",
+                email.replace('@', "%40"),
+                get_uuid(),
+            ))
+            .dispatch()
+            .await;
+        let (status, login) = json_response(response).await;
+        assert_eq!(status, Status::Ok, "Login failed: {login}");
+
+        TestUser {
+            email,
+            access_token: login["access_token"].as_str().expect("Login without access token").to_owned(),
+        }
+    }
+
+    fn on_host<'c>(request: LocalRequest<'c>, host: Option<&str>) -> LocalRequest<'c> {
+        match host {
+            Some(host) => request.header(Header::new("Host", host.to_owned())),
+            None => request,
+        }
+    }
+
+    fn authorized<'c>(request: LocalRequest<'c>, user: &TestUser) -> LocalRequest<'c> {
+        request
+            .header(Header::new("Authorization", format!("Bearer {}", user.access_token)))
+            .header(Header::new("Bitwarden-Client-Version", CLIENT_VERSION))
+    }
+
+    pub async fn get(&self, user: &TestUser, path: &str) -> (Status, Value) {
+        json_response(Self::authorized(self.client.get(path.to_owned()), user).dispatch().await).await
+    }
+
+    pub async fn post(&self, user: &TestUser, path: &str, body: &Value) -> (Status, Value) {
+        json_response(Self::authorized(self.client.post(path.to_owned()), user).json(body).dispatch().await).await
+    }
+
+    pub async fn put(&self, user: &TestUser, path: &str, body: &Value) -> (Status, Value) {
+        json_response(Self::authorized(self.client.put(path.to_owned()), user).json(body).dispatch().await).await
+    }
+
+    pub async fn delete(&self, user: &TestUser, path: &str) -> Status {
+        Self::authorized(self.client.delete(path.to_owned()), user).dispatch().await.status()
+    }
+
+    /// Downloads a file from a url returned by the server, like the one of an attachment
+    pub async fn download(&self, url: &str) -> (Status, Vec<u8>) {
+        let path = url.strip_prefix(&CONFIG.domain()).unwrap_or(url).to_owned();
+        let response = self.client.get(path).dispatch().await;
+        (response.status(), response.into_bytes().await.unwrap_or_default())
+    }
+
+    pub async fn sync(&self, user: &TestUser) -> Value {
+        let (status, sync) = self.get(user, "/api/sync").await;
+        assert_eq!(status, Status::Ok, "Sync failed: {sync}");
+        sync
+    }
+
+    /// Creates a login item in the personal vault of the user
+    pub async fn create_cipher(&self, user: &TestUser, name: &str) -> Value {
+        let (status, cipher) = self
+            .post(
+                user,
+                "/api/ciphers",
+                &json!({
+                    "type": 1,
+                    "name": name,
+                    "login": {
+                        "username": "2.e2e|username|value",
+                        "password": "2.e2e|password|value",
+                    },
+                }),
+            )
+            .await;
+        assert_eq!(status, Status::Ok, "Creating the cipher failed: {cipher}");
+        cipher
+    }
+
+    /// Registers a new owner and creates an organization for it with a default collection
+    pub async fn create_organization(&self) -> TestOrganization {
+        let owner = self.register_user().await;
+        let (status, org) = self
+            .post(
+                &owner,
+                "/api/organizations",
+                &json!({
+                    "name": "e2e organization",
+                    "billingEmail": owner.email,
+                    "collectionName": "2.e2e|collection|name",
+                    "key": "4.e2e-org-key",
+                    "planType": 0,
+                }),
+            )
+            .await;
+        assert_eq!(status, Status::Ok, "Creating the organization failed: {org}");
+        let id = org["id"].as_str().expect("Organization without id").to_owned();
+
+        let (status, collections) = self.get(&owner, &format!("/api/organizations/{id}/collections")).await;
+        assert_eq!(status, Status::Ok, "Listing the collections failed: {collections}");
+        let collection_id = collections["data"][0]["id"].as_str().expect("Organization without collection").to_owned();
+        TestOrganization {
+            id,
+            collection_id,
+            owner,
+        }
+    }
+
+    /// Invites an existing user into the organization and confirms it, returns the id of the membership
+    pub async fn add_member(&self, org: &TestOrganization, user: &TestUser, atype: i32) -> String {
+        let org_id = &org.id;
+        // Existing users are accepted directly when email is disabled
+        let (status, invite) = self
+            .post(
+                &org.owner,
+                &format!("/api/organizations/{org_id}/users/invite"),
+                &json!({ "emails": [user.email], "groups": [], "type": atype }),
+            )
+            .await;
+        assert_eq!(status, Status::Ok, "Inviting the member failed: {invite}");
+
+        let (status, members) = self.get(&org.owner, &format!("/api/organizations/{org_id}/users")).await;
+        assert_eq!(status, Status::Ok, "Listing the members failed: {members}");
+        let member_id = members["data"]
+            .as_array()
+            .and_then(|m| m.iter().find(|m| m["email"] == user.email))
+            .and_then(|m| m["id"].as_str())
+            .expect("Invited member missing")
+            .to_owned();
+
+        let (status, confirm) = self
+            .post(
+                &org.owner,
+                &format!("/api/organizations/{org_id}/users/{member_id}/confirm"),
+                &json!({ "key": "4.e2e-member-org-key" }),
+            )
+            .await;
+        assert_eq!(status, Status::Ok, "Confirming the member failed: {confirm}");
+        member_id
+    }
+
+    /// Creates an item owned by the organization in its default collection
+    pub async fn create_org_cipher(&self, org: &TestOrganization, name: &str) -> Value {
+        let cipher = self.create_cipher(&org.owner, "2.e2e|cipher|name").await;
+        let cipher_id = cipher["id"].as_str().expect("Cipher without id");
+        let (status, shared) = self
+            .put(
+                &org.owner,
+                &format!("/api/ciphers/{cipher_id}/share"),
+                &json!({
+                    "cipher": {
+                        "type": 1,
+                        "name": name,
+                        "login": {},
+                        "organizationId": org.id,
+                        "lastKnownRevisionDate": cipher["revisionDate"],
+                    },
+                    "collectionIds": [org.collection_id],
+                }),
+            )
+            .await;
+        assert_eq!(status, Status::Ok, "Sharing the cipher failed: {shared}");
+        shared
+    }
+
+    /// Creates an attachment through the v2 api without uploading its data, returns the id of the attachment
+    pub async fn create_attachment(&self, user: &TestUser, cipher_id: &str, file_name: &str, size: usize) -> String {
+        let (status, upload) = self
+            .post(
+                user,
+                &format!("/api/ciphers/{cipher_id}/attachment/v2"),
+                &json!({
+                    "key": "2.e2e|attachment|key",
+                    "fileName": file_name,
+                    "fileSize": size,
+                }),
+            )
+            .await;
+        assert_eq!(status, Status::Ok, "Requesting the attachment upload failed: {upload}");
+        upload["attachmentId"].as_str().expect("Upload without attachment id").to_owned()
+    }
+
+    /// Sends a chunk of the data of a resumable attachment upload
+    pub async fn upload_chunk(&self, user: &TestUser, path: &str, offset: usize, data: &[u8]) -> (Status, Value) {
+        let request = Self::authorized(self.client.patch(path.to_owned()), user)
+            .header(Header::new("Upload-Offset", offset.to_string()))
+            .header(ContentType::Binary)
+            .body(data);
+        json_response(request.dispatch().await).await
+    }
+
+    /// Uploads an attachment through the v2 api, returns the id of the attachment
+    pub async fn upload_attachment(&self, user: &TestUser, cipher_id: &str, file_name: &str, data: &[u8]) -> String {
+        let attachment_id = self.create_attachment(user, cipher_id, file_name, data.len()).await;
+
+        let boundary = get_uuid();
+        let mut body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"data\"; filename=\"{file_name}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+        let response =
+            Self::authorized(self.client.post(format!("/api/ciphers/{cipher_id}/attachment/{attachment_id}")), user)
+                .header(ContentType::new("multipart", "form-data").with_params(("boundary", boundary)))
+                .body(body)
+                .dispatch()
+                .await;
+        let status = response.status();
+        assert_eq!(status, Status::Ok, "Uploading the attachment failed: {:?}", response.into_string().await);
+        attachment_id
+    }
+}
+
+async fn json_response(response: LocalResponse<'_>) -> (Status, Value) {
+    let status = response.status();
+    let body = response.into_string().await.unwrap_or_default();
+    (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+}
@@ -0,0 +1,33 @@
+use super::TestClient;
+use crate::db::anonymize::{EXCLUDED_TABLES, create_dump};
+
+/// Names of all the tables of the schema
+fn schema_tables() -> Vec<&'static str> {
+    include_str!("../db/schema.rs")
+        .lines()
+        .filter_map(|line| line.strip_prefix("    ").filter(|l| !l.starts_with(' ') && l.ends_with('{')))
+        .filter_map(|line| line.split_whitespace().next())
+        .collect()
+}
+
+#[tokio::test]
+async fn anonymized_dump_covers_schema() {
+    let client = TestClient::new().await;
+    let org = client.create_organization().await;
+    client.create_org_cipher(&org, "2.e2e|shared|name").await;
+
+    let conn = super::test_pool().await.get().await.unwrap();
+    let dump = create_dump(&conn).await.expect("Creating the dump failed");
+    let dumped = dump["tables"].as_object().unwrap();
+
+    // A table added to the schema needs to be dumped, or excluded on purpose
+    let tables = schema_tables();
+    assert!(tables.len() > 30, "{tables:?}");
+    for table in tables {
+        assert!(
+            dumped.contains_key(table) != EXCLUDED_TABLES.contains(&table),
+            "The table '{table}' needs to be either dumped or excluded"
+        );
+    }
+    assert!(!dumped["users"].to_string().contains(&org.owner.email));
+}
@@ -0,0 +1,96 @@
+//
+// End-to-end tests
+//
+// These tests run the real routes of the server in-process through Rocket's local client,
+// against a SQLite database in a fresh temporary data folder. The server doesn't bind a port.
+// All tests share the same server configuration and database,
+// so every test registers its own users and creates its own organizations.
+//
+// The environment is loaded before the global config is first used, so these tests are behind the `e2e` feature
+// and need to run in their own test process: `cargo test --features sqlite,e2e e2e::`
+//
+use std::{net::TcpListener, path::PathBuf, sync::LazyLock};
+
+use tokio::sync::OnceCell;
+
+use crate::{CONFIG, auth, db::DbPool, util::get_uuid};
+
+mod ciphers;
+mod client;
+mod diagnostics;
+mod organizations;
+mod tenants;
+
+use client::TestClient;
+
+static DATA_FOLDER: LazyLock<PathBuf> =
+    LazyLock::new(|| std::env::temp_dir().join(format!("vaultwarden-e2e-{}", get_uuid())));
+
+static POOL: OnceCell<DbPool> = OnceCell::const_new();
+
+// The tenant of the tenants file, its mails are sent to `TENANT_SMTP`
+const TENANT_ID: &str = "e2e-tenant";
+const TENANT_HOST: &str = "tenant.e2e.test";
+
+// Plain SMTP server of the tenant, see `tenants::receive_mail`
+static TENANT_SMTP: LazyLock<TcpListener> = LazyLock::new(|| {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Error binding the tenant SMTP server");
+    listener.set_nonblocking(true).expect("Error configuring the tenant SMTP server");
+    listener
+});
+
+/// Prepares the environment on first use and returns the pool of the test database
+async fn test_pool() -> DbPool {
+    POOL.get_or_init(|| async {
+        let data_folder = DATA_FOLDER.to_str().expect("Temporary folder is not valid UTF-8");
+        std::fs::create_dir_all(data_folder).expect("Error creating the test data folder");
+
+        let tenants_file = format!("{data_folder}/tenants.json");
+        let smtp_port = TENANT_SMTP.local_addr().expect("Tenant SMTP server without address").port();
+        std::fs::write(
+            &tenants_file,
+            json!([{
+                "id": TENANT_ID,
+                "hosts": [TENANT_HOST],
+                "name": "E2E Tenant",
+                "smtpFrom": format!("vault@{TENANT_HOST}"),
+                "domain": format!("https://{TENANT_HOST}"),
+                "smtp": { "host": "127.0.0.1", "port": smtp_port, "security": "off" },
+            }])
+            .to_string(),
+        )
+        .expect("Error writing the tenants file");
+
+        let env_file = DATA_FOLDER.join("e2e.env");
+        std::fs::write(
+            &env_file,
+            format!(
+                "DATA_FOLDER={data_folder}\n\
+                 DATABASE_URL={data_folder}/db.sqlite3\n\
+                 DOMAIN=http://localhost:8000\n\
+                 WEB_VAULT_ENABLED=false\n\
+                 SIGNUPS_ALLOWED=true\n\
+                 ORG_EVENTS_ENABLED=true\n\
+                 PASSWORD_ITERATIONS=100000\n\
+                 LOGIN_RATELIMIT_MAX_BURST=1000\n\
+                 TENANTS_FILE={tenants_file}\n"
+            ),
+        )
+        .expect("Error writing the test environment file");
+        // The values need to be in the environment before the config is loaded for the first time
+        dotenvy::from_path_override(&env_file).expect("Error loading the test environment file");
+        assert_eq!(
+            CONFIG.data_folder(),
+            data_folder,
+            "The config was loaded before the end-to-end environment, run these tests on their own with `cargo test --features sqlite,e2e e2e::`"
+        );
+
+        auth::initialize_keys().await.expect("Error creating the private key");
+        std::fs::create_dir_all(CONFIG.tmp_folder()).expect("Error creating the tmp folder");
+        let pool = DbPool::from_config().expect("Error creating the test database");
+        crate::tenant::init(&pool);
+        pool
+    })
+    .await
+    .clone()
+}
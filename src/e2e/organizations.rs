@@ -0,0 +1,255 @@
+use rocket::http::Status;
+
+use super::TestClient;
+
+#[tokio::test]
+async fn share_cipher_into_collection() {
+    let client = TestClient::new().await;
+    let org = client.create_organization().await;
+    let outsider = client.register_user().await;
+    let (org_id, collection_id) = (&org.id, &org.collection_id);
+
+    let shared = client.create_org_cipher(&org, "2.e2e|shared|name").await;
+    let cipher_id = shared["id"].as_str().unwrap();
+    assert_eq!(shared["organizationId"], org_id.as_str());
+    assert_eq!(shared["collectionIds"], json!([collection_id]));
+
+    let (status, org_ciphers) =
+        client.get(&org.owner, &format!("/api/ciphers/organization-details?organizationId={org_id}")).await;
+    assert_eq!(status, Status::Ok, "{org_ciphers}");
+    assert!(org_ciphers["data"].as_array().unwrap().iter().any(|c| c["id"] == cipher_id));
+
+    // Users outside of the organization see nothing of it
+    let (status, _) = client.get(&outsider, &format!("/api/organizations/{org_id}/collections")).await;
+    assert_ne!(status, Status::Ok);
+    let (status, _) = client.get(&outsider, &format!("/api/ciphers/{cipher_id}")).await;
+    assert_ne!(status, Status::Ok);
+    let sync = client.sync(&outsider).await;
+    assert!(sync["ciphers"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn collection_management_settings() {
+    let client = TestClient::new().await;
+    let org = client.create_organization().await;
+    let (org_id, collection_id) = (&org.id, &org.collection_id);
+
+    let (status, settings) = client
+        .put(
+            &org.owner,
+            &format!("/api/organizations/{org_id}/collection-management"),
+            &json!({
+                "limitCollectionCreation": false,
+                "limitCollectionDeletion": true,
+                "limitItemDeletion": true,
+                "allowAdminAccessToAllCollectionItems": false,
+            }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "{settings}");
+    assert_eq!(settings["limitCollectionCreation"], false);
+    assert_eq!(settings["limitItemDeletion"], true);
+
+    let sync = client.sync(&org.owner).await;
+    let profile_org = &sync["profile"]["organizations"][0];
+    assert_eq!(profile_org["limitCollectionCreation"], false);
+    assert_eq!(profile_org["limitCollectionDeletion"], true);
+    assert_eq!(profile_org["allowAdminAccessToAllCollectionItems"], false);
+
+    // The owner has access to all collections, so the limits don't apply
+    assert_eq!(
+        client.delete(&org.owner, &format!("/api/organizations/{org_id}/collections/{collection_id}")).await,
+        Status::Ok
+    );
+}
+
+#[tokio::test]
+async fn onboarding_collections_on_confirm() {
+    let client = TestClient::new().await;
+    let org = client.create_organization().await;
+    let member = client.register_user().await;
+    let (org_id, collection_id) = (&org.id, &org.collection_id);
+
+    let (status, onboarding) = client
+        .put(
+            &org.owner,
+            &format!("/api/organizations/{org_id}/onboarding"),
+            &json!({ "welcomeMessage": "Welcome to the team!", "collectionIds": [collection_id] }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "{onboarding}");
+    assert_eq!(onboarding["collectionIds"], json!([collection_id]));
+
+    client.add_member(&org, &member, 2).await;
+
+    let sync = client.sync(&member).await;
+    let collection = sync["collections"].as_array().unwrap().iter().find(|c| c["id"] == collection_id.as_str());
+    assert_eq!(collection.expect("Onboarding collection missing")["readOnly"], true);
+}
+
+#[tokio::test]
+async fn pending_invites() {
+    let client = TestClient::new().await;
+    let org = client.create_organization().await;
+    let org_id = &org.id;
+
+    let email = format!("{}@example.com", crate::util::get_uuid());
+    let (status, invite) = client
+        .post(
+            &org.owner,
+            &format!("/api/organizations/{org_id}/users/invite"),
+            &json!({ "emails": [email], "groups": [], "type": 2 }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "{invite}");
+
+    let (status, invites) = client.get(&org.owner, &format!("/api/organizations/{org_id}/users/invites")).await;
+    assert_eq!(status, Status::Ok, "{invites}");
+    assert_eq!(invites["data"][0]["email"], email);
+    assert_eq!(invites["data"][0]["expired"], false);
+    assert_eq!(invites["expiredCount"], 0);
+
+    // Nothing has expired yet, so nothing is sent again
+    let (status, reinvited) =
+        client.post(&org.owner, &format!("/api/organizations/{org_id}/users/reinvite-expired"), &json!({})).await;
+    assert_eq!(status, Status::Ok, "{reinvited}");
+    assert!(reinvited["data"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn confidential_collections() {
+    let client = TestClient::new().await;
+    let org = client.create_organization().await;
+    let admin = client.register_user().await;
+    let (org_id, collection_id) = (&org.id, &org.collection_id);
+
+    let shared = client.create_org_cipher(&org, "2.e2e|shared|name").await;
+    let cipher_id = shared["id"].as_str().unwrap();
+    let attachment_id = client.upload_attachment(&org.owner, cipher_id, "2.e2e|file|name", b"e2e").await;
+    client.add_member(&org, &admin, 1).await;
+    let (status, _) = client.get(&admin, &format!("/api/ciphers/{cipher_id}")).await;
+    assert_eq!(status, Status::Ok);
+    let download_link = format!("/api/ciphers/{cipher_id}/attachment/{attachment_id}/download-link");
+    let (status, link) = client.post(&admin, &download_link, &json!({})).await;
+    assert_eq!(status, Status::Ok, "{link}");
+
+    let (status, confidential) = client
+        .put(
+            &org.owner,
+            &format!("/api/organizations/{org_id}/confidential-collections"),
+            &json!({ "enabled": true, "collectionIds": [collection_id] }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "{confidential}");
+    assert_eq!(confidential["collectionIds"], json!([collection_id]));
+
+    // Neither of them is assigned to the collection, they only had access through their full access to the organization
+    for user in [&org.owner, &admin] {
+        let (status, _) = client.get(user, &format!("/api/ciphers/{cipher_id}")).await;
+        assert_ne!(status, Status::Ok);
+        let (status, org_ciphers) =
+            client.get(user, &format!("/api/ciphers/organization-details?organizationId={org_id}")).await;
+        assert_eq!(status, Status::Ok, "{org_ciphers}");
+        assert!(org_ciphers["data"].as_array().unwrap().is_empty());
+        let (status, _) = client.post(user, &download_link, &json!({})).await;
+        assert_ne!(status, Status::Ok);
+    }
+}
+
+#[tokio::test]
+async fn audit_grant_follows_collection_edits() {
+    let client = TestClient::new().await;
+    let org = client.create_organization().await;
+    let auditor = client.register_user().await;
+    let (org_id, collection_id) = (&org.id, &org.collection_id);
+    let member_id = client.add_member(&org, &auditor, 2).await;
+
+    let (status, grants) = client
+        .post(
+            &org.owner,
+            &format!("/api/organizations/{org_id}/audit-grants"),
+            &json!({ "memberId": member_id, "collectionIds": [collection_id], "hours": 1 }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "{grants}");
+
+    let edit_collection = async |read_only: bool| {
+        let (status, collection) = client
+            .put(
+                &org.owner,
+                &format!("/api/organizations/{org_id}/collections/{collection_id}"),
+                &json!({
+                    "name": "2.e2e|collection|name",
+                    "groups": [],
+                    "users": [{ "id": member_id, "readOnly": read_only, "hidePasswords": false, "manage": false }],
+                }),
+            )
+            .await;
+        assert_eq!(status, Status::Ok, "{collection}");
+        let (status, grants) = client.get(&org.owner, &format!("/api/organizations/{org_id}/audit-grants")).await;
+        assert_eq!(status, Status::Ok, "{grants}");
+        grants["data"].as_array().unwrap().len()
+    };
+
+    // Saving the collection with the auditor's access unchanged keeps the grant
+    assert_eq!(edit_collection(true).await, 1);
+    // A regular access given in the collection editor isn't the grant's anymore, so it doesn't expire with it
+    assert_eq!(edit_collection(false).await, 0);
+    let sync = client.sync(&auditor).await;
+    let collection = sync["collections"].as_array().unwrap().iter().find(|c| c["id"] == collection_id.as_str());
+    assert_eq!(collection.expect("Collection access missing")["readOnly"], false);
+}
+
+#[tokio::test]
+async fn read_only_member_saves_item() {
+    let client = TestClient::new().await;
+    let org = client.create_organization().await;
+    let member = client.register_user().await;
+    let (org_id, collection_id) = (&org.id, &org.collection_id);
+
+    let shared = client.create_org_cipher(&org, "2.e2e|shared|name").await;
+    let cipher_id = shared["id"].as_str().unwrap();
+    let member_id = client.add_member(&org, &member, 2).await;
+    let (status, collection) = client
+        .put(
+            &org.owner,
+            &format!("/api/organizations/{org_id}/collections/{collection_id}"),
+            &json!({
+                "name": "2.e2e|collection|name",
+                "groups": [],
+                "users": [{ "id": member_id, "readOnly": true, "hidePasswords": false, "manage": false }],
+            }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "{collection}");
+
+    let save = async |name: &str, favorite: bool| {
+        client
+            .put(
+                &member,
+                &format!("/api/ciphers/{cipher_id}"),
+                &json!({
+                    "type": 1,
+                    "name": name,
+                    "login": {},
+                    "organizationId": org_id,
+                    "favorite": favorite,
+                    "lastKnownRevisionDate": shared["revisionDate"],
+                }),
+            )
+            .await
+    };
+
+    // Marking the item as favorite only changes the member's own properties
+    let (status, saved) = save("2.e2e|shared|name", true).await;
+    assert_eq!(status, Status::Ok, "{saved}");
+    assert_eq!(saved["favorite"], true);
+    assert_eq!(saved["name"], "2.e2e|shared|name");
+
+    // Without a change of the folder or favorite status, the save can only be an edit of the item
+    let (status, _) = save("2.e2e|renamed|name", true).await;
+    assert_ne!(status, Status::Ok);
+    let (status, cipher) = client.get(&org.owner, &format!("/api/ciphers/{cipher_id}")).await;
+    assert_eq!(status, Status::Ok, "{cipher}");
+    assert_eq!(cipher["name"], "2.e2e|shared|name");
+}
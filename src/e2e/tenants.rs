@@ -0,0 +1,75 @@
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+use super::{TENANT_HOST, TENANT_ID, TENANT_SMTP, TestClient};
+use crate::{
+    CONFIG,
+    db::models::{MembershipId, OrganizationId, User},
+    mail,
+    util::get_uuid,
+};
+
+/// Receives a single mail over plain SMTP and returns its data, with the quoted-printable soft line breaks removed
+async fn receive_mail(listener: TcpListener) -> String {
+    let (stream, _) = listener.accept().await.expect("No connection to the tenant SMTP server");
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    writer.write_all(b"220 e2e ESMTP\r\n").await.unwrap();
+
+    let mut data = String::new();
+    let mut in_data = false;
+    while let Some(line) = lines.next_line().await.unwrap() {
+        if in_data {
+            if line == "." {
+                in_data = false;
+                writer.write_all(b"250 OK\r\n").await.unwrap();
+            } else {
+                data.push_str(&line);
+                data.push('\n');
+            }
+            continue;
+        }
+        match line.get(..4).map(str::to_ascii_uppercase).as_deref() {
+            Some("DATA") => {
+                in_data = true;
+                writer.write_all(b"354 Send the data\r\n").await.unwrap();
+            }
+            Some("QUIT") => {
+                writer.write_all(b"221 Bye\r\n").await.unwrap();
+                break;
+            }
+            _ => writer.write_all(b"250 OK\r\n").await.unwrap(),
+        }
+    }
+    data.replace("=\n", "").replace("=3D", "=")
+}
+
+#[tokio::test]
+async fn invite_mail_of_tenant() {
+    let client = TestClient::new().await;
+    let invited = client.register_user_on(Some(TENANT_HOST)).await;
+
+    let conn = super::test_pool().await.get().await.unwrap();
+    let user = User::find_by_mail(&invited.email, &conn).await.expect("Registered user missing");
+    assert_eq!(user.tenant_id.as_deref(), Some(TENANT_ID));
+
+    // The mail is sent through the SMTP server of the tenant, with links to its own domain
+    let listener = TcpListener::from_std(TENANT_SMTP.try_clone().unwrap()).unwrap();
+    let (sent, received) = tokio::join!(
+        mail::send_invite(
+            &user,
+            OrganizationId::from(get_uuid()),
+            MembershipId::from(get_uuid()),
+            "E2E Tenant Organization",
+            None
+        ),
+        receive_mail(listener)
+    );
+    sent.expect("Sending the invite failed");
+
+    assert!(received.contains(&format!("<vault@{TENANT_HOST}>")), "{received}");
+    assert!(received.contains(&format!("https://{TENANT_HOST}/#/accept-organization/?")), "{received}");
+    assert!(!received.contains(&CONFIG.domain()), "{received}");
+}
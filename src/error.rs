@@ -15,14 +15,14 @@ macro_rules! make_error {
 
         #[derive(Debug)]
         pub struct ErrorEvent { pub event: EventType }
-        pub struct Error { message: String, kind: ErrorKind, code: u16, event: Option<ErrorEvent> }
+        pub struct Error { message: String, kind: ErrorKind, code: u16, event: Option<ErrorEvent>, error_code: Option<ErrorCode> }
 
         $(impl From<$ty> for Error {
             fn from(err: $ty) -> Self { Error::from((stringify!($name), err)) }
         })+
         $(impl<S: Into<String>> From<(S, $ty)> for Error {
             fn from(val: (S, $ty)) -> Self {
-                Error { message: val.0.into(), kind: ErrorKind::$name(val.1), code: BAD_REQUEST, event: None }
+                Error { message: val.0.into(), kind: ErrorKind::$name(val.1), code: BAD_REQUEST, event: None, error_code: None }
             }
         })+
         impl StdError for Error {
@@ -33,7 +33,7 @@ macro_rules! make_error {
         impl std::fmt::Display for Error {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 match &self.kind {$(
-                   ErrorKind::$name(e) => f.write_str(&$usr_msg_fun(e, &self.message)),
+                   ErrorKind::$name(e) => f.write_str(&$usr_msg_fun(e, &self.message, self.error_code)),
                 )+}
             }
         }
@@ -65,6 +65,55 @@ pub struct Empty {}
 
 pub struct Compact {}
 
+/// Stable machine-readable error codes, sent as `errorCode` next to the message.
+/// Scripts and third-party clients can branch on these, the messages themselves may change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    // The item was changed by another client since it was synced
+    CipherStale,
+    // The item is being saved by another client at the same time, the request can be retried
+    CipherWriteConflict,
+    // An attachment or Send storage limit would be exceeded
+    QuotaExceeded,
+    // The `Remove individual vault` policy applies to the user
+    PolicyPersonalOwnership,
+    // The `Remove Send` policy applies to the user, or Sends are disabled
+    PolicyDisableSend,
+    // The `Send options` policy doesn't allow hiding the email address
+    PolicySendOptions,
+    // Rejected by the strict request parsing
+    MalformedRequest,
+    // A token, like the one of an email link, isn't valid anymore
+    TokenExpired,
+    // The invitation isn't valid anymore, a new one needs to be sent
+    InvitationExpired,
+    // The file is on the SEND_FILE_HASH_BLOCKLIST
+    SendFileBlocked,
+    // The `Block attachments` policy of the organization doesn't allow attachments on the type of the item
+    PolicyBlockAttachments,
+    // The organization enforces its item templates and the item lacks the required custom fields
+    CipherTemplateFields,
+}
+
+impl ErrorCode {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::CipherStale => "cipher_stale",
+            Self::CipherWriteConflict => "cipher_write_conflict",
+            Self::QuotaExceeded => "quota_exceeded",
+            Self::PolicyPersonalOwnership => "policy_personal_ownership",
+            Self::PolicyDisableSend => "policy_disable_send",
+            Self::PolicySendOptions => "policy_send_options",
+            Self::MalformedRequest => "malformed_request",
+            Self::TokenExpired => "token_expired",
+            Self::InvitationExpired => "invitation_expired",
+            Self::SendFileBlocked => "send_file_blocked",
+            Self::PolicyBlockAttachments => "policy_block_attachments",
+            Self::CipherTemplateFields => "cipher_template_fields",
+        }
+    }
+}
+
 // Error struct
 // Contains a String error message, meant for the user and an enum variant, with an error of different types.
 //
@@ -159,6 +208,12 @@ impl Error {
         self
     }
 
+    #[must_use]
+    pub const fn with_error_code(mut self, error_code: ErrorCode) -> Self {
+        self.error_code = Some(error_code);
+        self
+    }
+
     #[must_use]
     pub fn with_event(mut self, event: ErrorEvent) -> Self {
         self.event = Some(event);
@@ -172,6 +227,10 @@ impl Error {
     pub fn message(&self) -> &str {
         &self.message
     }
+
+    pub const fn error_code(&self) -> Option<ErrorCode> {
+        self.error_code
+    }
 }
 
 pub trait MapResult<S> {
@@ -203,7 +262,7 @@ fn no_source<T, S>(_: T) -> Option<S> {
     None
 }
 
-fn serialize(e: &impl Serialize, _msg: &str) -> String {
+fn serialize(e: &impl Serialize, _msg: &str, _error_code: Option<ErrorCode>) -> String {
     serde_json::to_string(e).unwrap()
 }
 
@@ -222,9 +281,10 @@ impl Serialize for ApiErrorResponse<'_> {
             object: &'static str,
         }
 
-        let mut state = serializer.serialize_struct("ApiErrorResponse", 9)?;
+        let mut state = serializer.serialize_struct("ApiErrorResponse", 10)?;
 
         state.serialize_field("message", self.0.message)?;
+        state.serialize_field("errorCode", &self.0.error_code.map(ErrorCode::as_str))?;
 
         let mut validation_errors = std::collections::HashMap::with_capacity(1);
         validation_errors.insert("", vec![self.0.message]);
@@ -256,9 +316,10 @@ impl Serialize for CompactApiErrorResponse<'_> {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("CompactApiErrorResponse", 6)?;
+        let mut state = serializer.serialize_struct("CompactApiErrorResponse", 7)?;
 
         state.serialize_field("message", self.0.message)?;
+        state.serialize_field("errorCode", &self.0.error_code.map(ErrorCode::as_str))?;
         state.serialize_field("validationErrors", &None::<()>)?;
         state.serialize_field("exceptionMessage", &None::<()>)?;
         state.serialize_field("exceptionStackTrace", &None::<()>)?;
@@ -274,6 +335,7 @@ impl Serialize for CompactApiErrorResponse<'_> {
 /// is small and doesn't contain unneeded empty fields. This is more memory efficient, but also less code to compile
 struct ApiErrorMsg<'a> {
     message: &'a str,
+    error_code: Option<ErrorCode>,
 }
 /// Default API Error response struct
 /// The custom serialization adds all other needed fields
@@ -282,16 +344,18 @@ struct ApiErrorResponse<'a>(ApiErrorMsg<'a>);
 /// The custom serialization adds all other needed fields
 struct CompactApiErrorResponse<'a>(ApiErrorMsg<'a>);
 
-fn api_error(_: &impl std::any::Any, msg: &str) -> String {
+fn api_error(_: &impl std::any::Any, msg: &str, error_code: Option<ErrorCode>) -> String {
     let response = ApiErrorMsg {
         message: msg,
+        error_code,
     };
     serde_json::to_string(&ApiErrorResponse(response)).unwrap()
 }
 
-fn compact_api_error(_: &impl std::any::Any, msg: &str) -> String {
+fn compact_api_error(_: &impl std::any::Any, msg: &str, error_code: Option<ErrorCode>) -> String {
     let response = ApiErrorMsg {
         message: msg,
+        error_code,
     };
     serde_json::to_string(&CompactApiErrorResponse(response)).unwrap()
 }
@@ -340,6 +404,11 @@ macro_rules! err {
         error!("{msg}");
         return Err($crate::error::Error::new_msg(msg).with_event($crate::error::ErrorEvent $err_event));
     }};
+    ($msg:expr, ErrorCode::$err_code:ident) => {{
+        let msg = $msg;
+        error!("{msg}");
+        return Err($crate::error::Error::new_msg(msg).with_error_code($crate::error::ErrorCode::$err_code));
+    }};
     ($usr_msg:expr, $log_value:expr) => {{
         let usr_msg = $usr_msg;
         let log_value = $log_value;
@@ -352,6 +421,12 @@ macro_rules! err {
         error!("{usr_msg}. {log_value}");
         return Err($crate::error::Error::new(usr_msg, log_value).with_event($crate::error::ErrorEvent $err_event));
     }};
+    ($usr_msg:expr, $log_value:expr, ErrorCode::$err_code:ident) => {{
+        let usr_msg = $usr_msg;
+        let log_value = $log_value;
+        error!("{usr_msg}. {log_value}");
+        return Err($crate::error::Error::new(usr_msg, log_value).with_error_code($crate::error::ErrorCode::$err_code));
+    }};
 }
 
 #[macro_export]
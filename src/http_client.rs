@@ -59,12 +59,17 @@ pub fn get_reqwest_client_builder() -> ClientBuilder {
         .timeout(Duration::from_secs(10))
 }
 
-fn should_block_ip(ip: IpAddr) -> bool {
+fn should_block_ip(ip: IpAddr, host: &str) -> bool {
     if !CONFIG.http_request_block_non_global_ips() {
         return false;
     }
 
-    !is_global(ip)
+    !is_global(ip) && !is_allowed_non_global_host(&CONFIG.http_request_allow_non_global_hosts(), host)
+}
+
+fn is_allowed_non_global_host(allowed_hosts: &str, host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    allowed_hosts.split(',').map(str::trim).any(|h| !h.is_empty() && h.eq_ignore_ascii_case(host))
 }
 
 fn should_block_address_regex(domain_or_ip: &str) -> bool {
@@ -142,7 +147,7 @@ pub fn should_block_host<S: AsRef<str>>(host: &Host<S>) -> Result<(), CustomHttp
     };
 
     if let Some(ip) = ip
-        && should_block_ip(ip)
+        && should_block_ip(ip, &host_str)
     {
         return Err(CustomHttpClientError::NonGlobalIp {
             domain: None,
@@ -253,6 +258,31 @@ impl CustomDnsResolver {
 
         Ok(results)
     }
+
+    async fn lookup_txt(&self, name: &str) -> Result<Vec<String>, BoxError> {
+        let Self::Hickory(r) = self else {
+            return Err("TXT lookups are not supported by the system resolver".into());
+        };
+
+        Ok(r.txt_lookup(name)
+            .await?
+            .iter()
+            .map(|txt| txt.txt_data().iter().map(|d| String::from_utf8_lossy(d)).collect::<String>())
+            .collect())
+    }
+}
+
+/// TXT lookups need the Hickory resolver, the system resolver used when it can't be created only resolves addresses
+pub fn supports_txt_lookups() -> bool {
+    matches!(*CustomDnsResolver::instance(), CustomDnsResolver::Hickory(_))
+}
+
+/// Returns the values of all the DNS TXT records of a domain
+pub async fn lookup_txt(name: &str) -> Result<Vec<String>, crate::Error> {
+    CustomDnsResolver::instance()
+        .lookup_txt(name)
+        .await
+        .map_err(|e| crate::Error::new("Unable to look up the DNS TXT records", format!("{name}: {e}")))
 }
 
 fn pre_resolve(name: &str) -> Result<(), CustomHttpClientError> {
@@ -272,7 +302,7 @@ fn pre_resolve(name: &str) -> Result<(), CustomHttpClientError> {
 }
 
 fn post_resolve(name: &str, ip: IpAddr) -> Result<(), CustomHttpClientError> {
-    if should_block_ip(ip) {
+    if should_block_ip(ip, name) {
         Err(CustomHttpClientError::NonGlobalIp {
             domain: Some(name.to_owned()),
             ip,
@@ -558,4 +588,17 @@ mod tests {
         let big = "a.".repeat(130) + "test"; // > 253
         assert!(get_valid_host(&big).is_err());
     }
+
+    #[test]
+    fn allowed_non_global_hosts() {
+        let allowed = "siem.internal.example.com, 10.0.0.5,,fd00::1";
+        assert!(is_allowed_non_global_host(allowed, "siem.internal.example.com"));
+        assert!(is_allowed_non_global_host(allowed, "SIEM.Internal.Example.com"));
+        assert!(is_allowed_non_global_host(allowed, "10.0.0.5"));
+        assert!(is_allowed_non_global_host(allowed, "[fd00::1]"));
+        assert!(!is_allowed_non_global_host(allowed, "internal.example.com"));
+        assert!(!is_allowed_non_global_host(allowed, "10.0.0.50"));
+        assert!(!is_allowed_non_global_host(allowed, ""));
+        assert!(!is_allowed_non_global_host("", "10.0.0.5"));
+    }
 }
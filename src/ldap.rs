@@ -0,0 +1,739 @@
+//
+// Minimal LDAPv3 (RFC 4511) client used by the directory sync
+//
+// Only what the sync needs is implemented: a simple bind, StartTLS and a paged subtree search.
+// The messages are BER encoded by hand, the connection is blocking and runs on a blocking thread.
+// `ldaps://` and StartTLS verify the certificate of the server against the system trust store.
+//
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use openssl::ssl::{SslConnector, SslMethod};
+use url::Url;
+
+use crate::{
+    CONFIG,
+    api::{ApiResult, EmptyResult},
+    error::Error,
+};
+
+// Application tags of the protocol operations
+const BIND_REQUEST: u8 = 0x60;
+const BIND_RESPONSE: u8 = 0x61;
+const UNBIND_REQUEST: u8 = 0x42;
+const SEARCH_REQUEST: u8 = 0x63;
+const SEARCH_RESULT_ENTRY: u8 = 0x64;
+const SEARCH_RESULT_DONE: u8 = 0x65;
+const SEARCH_RESULT_REFERENCE: u8 = 0x73;
+const EXTENDED_REQUEST: u8 = 0x77;
+const EXTENDED_RESPONSE: u8 = 0x78;
+
+// Universal tags
+const BOOLEAN: u8 = 0x01;
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const ENUMERATED: u8 = 0x0a;
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+
+const CONTROLS: u8 = 0xa0;
+const STARTTLS_OID: &str = "1.3.6.1.4.1.1466.20037";
+const PAGED_RESULTS_OID: &str = "1.2.840.113556.1.4.319";
+const PAGE_SIZE: i64 = 500;
+// Largest message accepted from the server, a search result entry only holds the DN and the email address
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// An entry returned by the user search
+pub struct LdapUser {
+    pub dn: String,
+    pub email: String,
+}
+
+/// Returns the users matching `LDAP_USER_FILTER` below `LDAP_BASE_DN` which have an email address
+pub async fn search_users() -> ApiResult<Vec<LdapUser>> {
+    match tokio::task::spawn_blocking(search_users_blocking).await {
+        Ok(res) => res,
+        Err(e) => err!(format!("LDAP search task failed: {e:?}")),
+    }
+}
+
+fn search_users_blocking() -> ApiResult<Vec<LdapUser>> {
+    let filter = parse_filter(&CONFIG.ldap_user_filter())?;
+    let email_attr = CONFIG.ldap_email_attribute();
+
+    let mut conn = Connection::open(&CONFIG.ldap_url(), CONFIG.ldap_starttls(), CONFIG.ldap_timeout())?;
+    conn.bind(&CONFIG.ldap_bind_dn(), &CONFIG.ldap_bind_password())?;
+    let entries = conn.search(&CONFIG.ldap_base_dn(), &filter, &[email_attr.as_str()]);
+    conn.unbind();
+
+    Ok(entries?
+        .into_iter()
+        .filter_map(|(dn, attributes)| {
+            let email = attributes
+                .into_iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(&email_attr))
+                .and_then(|(_, values)| values.into_iter().next())?;
+            Some(LdapUser {
+                dn,
+                email: email.trim().to_lowercase(),
+            })
+        })
+        .filter(|user| !user.email.is_empty())
+        .collect())
+}
+
+trait Transport: Read + Write + Send {}
+impl<T: Read + Write + Send> Transport for T {}
+
+type Attributes = Vec<(String, Vec<String>)>;
+
+struct Message {
+    op: u8,
+    body: Vec<u8>,
+    controls: Option<Vec<u8>>,
+}
+
+struct Connection {
+    stream: Box<dyn Transport>,
+    next_id: i64,
+}
+
+impl Connection {
+    fn open(url: &str, starttls: bool, timeout: u64) -> ApiResult<Self> {
+        let url = Url::parse(url).map_err(|e| Error::new("Invalid LDAP URL", format!("Invalid LDAP URL: {e}")))?;
+        let secure = match url.scheme() {
+            "ldaps" => true,
+            "ldap" => false,
+            scheme => err!(format!("Unsupported LDAP URL scheme `{scheme}`")),
+        };
+        let Some(host) = url.host_str() else {
+            err!("The LDAP URL has no host")
+        };
+        let port = url.port().unwrap_or(if secure {
+            636
+        } else {
+            389
+        });
+
+        let timeout = Duration::from_secs(timeout.max(1));
+        let Some(addr) = (host, port).to_socket_addrs()?.next() else {
+            err!(format!("Unable to resolve the LDAP server `{host}`"))
+        };
+        let tcp = TcpStream::connect_timeout(&addr, timeout)?;
+        tcp.set_read_timeout(Some(timeout))?;
+        tcp.set_write_timeout(Some(timeout))?;
+
+        if secure {
+            return Ok(Self {
+                stream: Box::new(Self::tls(host, tcp)?),
+                next_id: 1,
+            });
+        }
+        if !starttls {
+            return Ok(Self {
+                stream: Box::new(tcp),
+                next_id: 1,
+            });
+        }
+
+        let mut conn = Self {
+            stream: Box::new(tcp.try_clone()?),
+            next_id: 1,
+        };
+        let id = conn.send(EXTENDED_REQUEST, &tlv(0x80, STARTTLS_OID.as_bytes()), None)?;
+        let response = conn.receive(id)?;
+        if response.op != EXTENDED_RESPONSE {
+            err!("Unexpected response to the LDAP StartTLS request")
+        }
+        check_result(&response.body, "StartTLS")?;
+        Ok(Self {
+            stream: Box::new(Self::tls(host, tcp)?),
+            next_id: conn.next_id,
+        })
+    }
+
+    fn tls(host: &str, tcp: TcpStream) -> ApiResult<openssl::ssl::SslStream<TcpStream>> {
+        let connector = SslConnector::builder(SslMethod::tls_client())?.build();
+        connector.connect(host, tcp).map_err(|e| {
+            Error::new("Unable to establish a TLS connection to the LDAP server", format!("LDAP TLS error: {e}"))
+        })
+    }
+
+    fn bind(&mut self, dn: &str, password: &str) -> EmptyResult {
+        // A bind with a name but no password is an unauthenticated bind (RFC 4513 5.1.2), which most servers accept
+        // without checking anything. Only an anonymous bind without both is allowed.
+        if !dn.is_empty() && password.is_empty() {
+            err!("LDAP_BIND_PASSWORD is required when LDAP_BIND_DN is set")
+        }
+        let mut body = tlv(INTEGER, &encode_integer(3));
+        body.extend(tlv(OCTET_STRING, dn.as_bytes()));
+        body.extend(tlv(0x80, password.as_bytes()));
+        let id = self.send(BIND_REQUEST, &body, None)?;
+        let response = self.receive(id)?;
+        if response.op != BIND_RESPONSE {
+            err!("Unexpected response to the LDAP bind request")
+        }
+        check_result(&response.body, "Bind")
+    }
+
+    /// Runs a paged subtree search and returns the DN and the requested attributes of every entry
+    fn search(&mut self, base: &str, filter: &[u8], attributes: &[&str]) -> ApiResult<Vec<(String, Attributes)>> {
+        let mut entries = Vec::new();
+        let mut cookie = Vec::new();
+        loop {
+            let mut body = tlv(OCTET_STRING, base.as_bytes());
+            body.extend(tlv(ENUMERATED, &[2])); // wholeSubtree
+            body.extend(tlv(ENUMERATED, &[0])); // neverDerefAliases
+            body.extend(tlv(INTEGER, &encode_integer(0)));
+            body.extend(tlv(INTEGER, &encode_integer(0)));
+            body.extend(tlv(BOOLEAN, &[0]));
+            body.extend_from_slice(filter);
+            body.extend(tlv(
+                SEQUENCE,
+                &attributes.iter().flat_map(|a| tlv(OCTET_STRING, a.as_bytes())).collect::<Vec<u8>>(),
+            ));
+
+            let mut paging = tlv(INTEGER, &encode_integer(PAGE_SIZE));
+            paging.extend(tlv(OCTET_STRING, &cookie));
+            let mut control = tlv(OCTET_STRING, PAGED_RESULTS_OID.as_bytes());
+            control.extend(tlv(OCTET_STRING, &tlv(SEQUENCE, &paging)));
+            let controls = tlv(SEQUENCE, &control);
+
+            let id = self.send(SEARCH_REQUEST, &body, Some(&controls))?;
+            loop {
+                let response = self.receive(id)?;
+                match response.op {
+                    SEARCH_RESULT_ENTRY => entries.push(parse_entry(&response.body)?),
+                    SEARCH_RESULT_REFERENCE => {}
+                    SEARCH_RESULT_DONE => {
+                        check_result(&response.body, "Search")?;
+                        cookie = response.controls.as_deref().map(paged_cookie).transpose()?.unwrap_or_default();
+                        break;
+                    }
+                    op => err!(format!("Unexpected LDAP response 0x{op:02x} to the search request")),
+                }
+            }
+            if cookie.is_empty() {
+                return Ok(entries);
+            }
+        }
+    }
+
+    fn unbind(mut self) {
+        // The server closes the connection, there is no response
+        if let Err(e) = self.send(UNBIND_REQUEST, &[], None) {
+            debug!("Unable to unbind from the LDAP server: {e:?}");
+        }
+    }
+
+    fn send(&mut self, op: u8, body: &[u8], controls: Option<&[u8]>) -> ApiResult<i64> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut message = tlv(INTEGER, &encode_integer(id));
+        message.extend(tlv(op, body));
+        if let Some(controls) = controls {
+            message.extend(tlv(CONTROLS, controls));
+        }
+        self.stream.write_all(&tlv(SEQUENCE, &message))?;
+        self.stream.flush()?;
+        Ok(id)
+    }
+
+    fn receive(&mut self, expected_id: i64) -> ApiResult<Message> {
+        let mut header = [0u8; 2];
+        self.stream.read_exact(&mut header)?;
+        if header[0] != SEQUENCE {
+            err!("Invalid LDAP message received")
+        }
+        let mut len = usize::from(header[1]);
+        if header[1] & 0x80 != 0 {
+            let octets = usize::from(header[1] & 0x7f);
+            if octets == 0 || octets > 4 {
+                err!("Invalid LDAP message length")
+            }
+            let mut buf = [0u8; 4];
+            self.stream.read_exact(&mut buf[..octets])?;
+            len = buf[..octets].iter().fold(0, |len, b| (len << 8) | usize::from(*b));
+        }
+        if len > MAX_MESSAGE_SIZE {
+            err!(format!("The LDAP server sent a message of {len} bytes, more than the {MAX_MESSAGE_SIZE} allowed"))
+        }
+        let mut content = vec![0u8; len];
+        self.stream.read_exact(&mut content)?;
+
+        let mut reader = BerReader::new(&content);
+        let id = decode_integer(reader.expect(INTEGER)?);
+        let (op, body) = reader.next()?;
+        let controls = if reader.is_empty() {
+            None
+        } else {
+            Some(reader.expect(CONTROLS)?.to_vec())
+        };
+
+        if id == 0 {
+            // Notice of disconnection
+            err!("The LDAP server closed the connection")
+        }
+        if id != expected_id {
+            err!(format!("Unexpected LDAP message id {id}, expected {expected_id}"))
+        }
+        Ok(Message {
+            op,
+            body: body.to_vec(),
+            controls,
+        })
+    }
+}
+
+/// Returns an error when the LDAPResult isn't a success
+fn check_result(body: &[u8], operation: &str) -> EmptyResult {
+    let mut reader = BerReader::new(body);
+    let code = decode_integer(reader.expect(ENUMERATED)?);
+    let _matched_dn = reader.expect(OCTET_STRING)?;
+    let message = String::from_utf8_lossy(reader.expect(OCTET_STRING)?);
+    if code != 0 {
+        err!(format!("LDAP {operation} failed with result code {code}: {message}"))
+    }
+    Ok(())
+}
+
+fn parse_entry(body: &[u8]) -> ApiResult<(String, Attributes)> {
+    let mut reader = BerReader::new(body);
+    let dn = String::from_utf8_lossy(reader.expect(OCTET_STRING)?).into_owned();
+    let mut attributes = Vec::new();
+    let mut list = BerReader::new(reader.expect(SEQUENCE)?);
+    while !list.is_empty() {
+        let mut attribute = BerReader::new(list.expect(SEQUENCE)?);
+        let name = String::from_utf8_lossy(attribute.expect(OCTET_STRING)?).into_owned();
+        let mut values = Vec::new();
+        let mut set = BerReader::new(attribute.expect(SET)?);
+        while !set.is_empty() {
+            values.push(String::from_utf8_lossy(set.expect(OCTET_STRING)?).into_owned());
+        }
+        attributes.push((name, values));
+    }
+    Ok((dn, attributes))
+}
+
+/// Returns the cookie of the paged results control, empty when there are no more pages
+fn paged_cookie(controls: &[u8]) -> ApiResult<Vec<u8>> {
+    let mut reader = BerReader::new(controls);
+    while !reader.is_empty() {
+        let mut control = BerReader::new(reader.expect(SEQUENCE)?);
+        if control.expect(OCTET_STRING)? != PAGED_RESULTS_OID.as_bytes() {
+            continue;
+        }
+        let (mut tag, mut value) = control.next()?;
+        if tag == BOOLEAN {
+            (tag, value) = control.next()?;
+        }
+        if tag != OCTET_STRING {
+            err!("Invalid LDAP paged results control")
+        }
+        let mut paging = BerReader::new(BerReader::new(value).expect(SEQUENCE)?);
+        let _size = paging.expect(INTEGER)?;
+        return Ok(paging.expect(OCTET_STRING)?.to_vec());
+    }
+    Ok(Vec::new())
+}
+
+struct BerReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BerReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn byte(&mut self) -> ApiResult<u8> {
+        let Some(b) = self.data.get(self.pos) else {
+            err!("Truncated LDAP message")
+        };
+        self.pos += 1;
+        Ok(*b)
+    }
+
+    fn next(&mut self) -> ApiResult<(u8, &'a [u8])> {
+        let tag = self.byte()?;
+        let first = self.byte()?;
+        let len = if first & 0x80 == 0 {
+            usize::from(first)
+        } else {
+            let mut len = 0usize;
+            for _ in 0..(first & 0x7f) {
+                len = (len << 8) | usize::from(self.byte()?);
+            }
+            len
+        };
+        let Some(value) = self.data.get(self.pos..self.pos + len) else {
+            err!("Truncated LDAP message")
+        };
+        self.pos += len;
+        Ok((tag, value))
+    }
+
+    fn expect(&mut self, expected: u8) -> ApiResult<&'a [u8]> {
+        let (tag, value) = self.next()?;
+        if tag != expected {
+            err!(format!("Unexpected LDAP element 0x{tag:02x}, expected 0x{expected:02x}"))
+        }
+        Ok(value)
+    }
+}
+
+fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match u8::try_from(value.len()) {
+        Ok(len) if len < 0x80 => out.push(len),
+        _ => {
+            let bytes = value.len().to_be_bytes();
+            let len = &bytes[bytes.iter().take_while(|b| **b == 0).count()..];
+            out.push(0x80 + u8::try_from(len.len()).expect("A length has at most 8 bytes"));
+            out.extend_from_slice(len);
+        }
+    }
+    out.extend_from_slice(value);
+    out
+}
+
+fn encode_integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    // Drop the leading bytes which only repeat the sign
+    while start < 7
+        && ((bytes[start] == 0 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    bytes[start..].to_vec()
+}
+
+fn decode_integer(bytes: &[u8]) -> i64 {
+    let negative = bytes.first().is_some_and(|b| b & 0x80 != 0);
+    bytes.iter().take(8).fold(
+        if negative {
+            -1
+        } else {
+            0
+        },
+        |acc, b| (acc << 8) | i64::from(*b),
+    )
+}
+
+/// Whether the external id of a member is the DN of an entry below `base_dn`, which is how the sync marks its members
+pub fn is_dn_below(external_id: &str, base_dn: &str) -> bool {
+    let normalize = |dn: &str| dn.split(',').map(|rdn| rdn.trim().to_lowercase()).collect::<Vec<String>>();
+    let (dn, base) = (normalize(external_id), normalize(base_dn));
+    !base_dn.trim().is_empty() && dn.len() > base.len() && dn.ends_with(&base)
+}
+
+/// Encodes a search filter in the string representation of RFC 4515, like `(&(objectClass=person)(mail=*))`
+pub fn parse_filter(filter: &str) -> ApiResult<Vec<u8>> {
+    let filter = filter.trim();
+    // The outer parentheses are optional
+    let filter = if filter.starts_with('(') {
+        filter.to_owned()
+    } else {
+        format!("({filter})")
+    };
+    let mut parser = FilterParser {
+        input: filter.as_bytes(),
+        pos: 0,
+    };
+    let encoded = parser.filter()?;
+    if parser.pos != parser.input.len() {
+        err!("Invalid LDAP filter, unexpected characters after the end of the filter")
+    }
+    Ok(encoded)
+}
+
+struct FilterParser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl FilterParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> EmptyResult {
+        if self.peek() != Some(c) {
+            err!(format!("Invalid LDAP filter, expected `{}` at position {}", c as char, self.pos))
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn filter(&mut self) -> ApiResult<Vec<u8>> {
+        self.expect(b'(')?;
+        let encoded = match self.peek() {
+            Some(b'&') => {
+                self.pos += 1;
+                tlv(0xa0, &self.list()?)
+            }
+            Some(b'|') => {
+                self.pos += 1;
+                tlv(0xa1, &self.list()?)
+            }
+            Some(b'!') => {
+                self.pos += 1;
+                tlv(0xa2, &self.filter()?)
+            }
+            _ => self.item()?,
+        };
+        self.expect(b')')?;
+        Ok(encoded)
+    }
+
+    fn list(&mut self) -> ApiResult<Vec<u8>> {
+        let mut filters = Vec::new();
+        while self.peek() == Some(b'(') {
+            filters.extend(self.filter()?);
+        }
+        if filters.is_empty() {
+            err!("Invalid LDAP filter, `&` and `|` need at least one filter")
+        }
+        Ok(filters)
+    }
+
+    fn item(&mut self) -> ApiResult<Vec<u8>> {
+        let start = self.pos;
+        while let Some(c) = self.peek()
+            && !matches!(c, b'=' | b'~' | b'>' | b'<' | b'(' | b')')
+        {
+            self.pos += 1;
+        }
+        let attribute = &self.input[start..self.pos];
+        if attribute.is_empty() {
+            err!("Invalid LDAP filter, missing attribute name")
+        }
+
+        let tag = match self.peek() {
+            Some(b'=') => 0xa3,
+            Some(b'~') => 0xa8,
+            Some(b'>') => 0xa5,
+            Some(b'<') => 0xa6,
+            _ => err!("Invalid LDAP filter, missing comparison operator"),
+        };
+        self.pos += 1;
+        if tag != 0xa3 {
+            self.expect(b'=')?;
+        }
+
+        let start = self.pos;
+        while let Some(c) = self.peek()
+            && !matches!(c, b'(' | b')')
+        {
+            self.pos += 1;
+        }
+        let value = &self.input[start..self.pos];
+
+        let mut encoded = tlv(OCTET_STRING, attribute);
+        if tag != 0xa3 || !value.contains(&b'*') {
+            encoded.extend(tlv(OCTET_STRING, &unescape(value)?));
+            return Ok(tlv(tag, &encoded));
+        }
+        if value == b"*" {
+            return Ok(tlv(0x87, attribute));
+        }
+
+        // Substrings, a literal `*` in a value is escaped as `\2a`
+        let parts: Vec<&[u8]> = value.split(|c| *c == b'*').collect();
+        let last = parts.len() - 1;
+        let mut substrings = Vec::new();
+        for (i, part) in parts.iter().enumerate() {
+            if part.is_empty() {
+                continue;
+            }
+            let kind = match i {
+                0 => 0x80,
+                i if i == last => 0x82,
+                _ => 0x81,
+            };
+            substrings.extend(tlv(kind, &unescape(part)?));
+        }
+        encoded.extend(tlv(SEQUENCE, &substrings));
+        Ok(tlv(0xa4, &encoded))
+    }
+}
+
+/// Decodes the `\XX` escapes of a filter value
+fn unescape(value: &[u8]) -> ApiResult<Vec<u8>> {
+    let mut out = Vec::with_capacity(value.len());
+    let mut i = 0;
+    while i < value.len() {
+        if value[i] == b'\\' {
+            let Some(byte) = value
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            else {
+                err!("Invalid LDAP filter, `\\` must be followed by two hex digits")
+            };
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(value[i]);
+            i += 1;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn connection(response: Vec<u8>) -> Connection {
+        Connection {
+            stream: Box::new(Cursor::new(response)),
+            next_id: 1,
+        }
+    }
+
+    fn ldap_result(code: u8, message: &str) -> Vec<u8> {
+        let mut result = tlv(ENUMERATED, &[code]);
+        result.extend(tlv(OCTET_STRING, b""));
+        result.extend(tlv(OCTET_STRING, message.as_bytes()));
+        result
+    }
+
+    #[test]
+    fn test_tlv_lengths() {
+        assert_eq!(tlv(OCTET_STRING, b"ab"), [0x04, 0x02, b'a', b'b']);
+        let long = tlv(OCTET_STRING, &[0; 200]);
+        assert_eq!(long[..3], [0x04, 0x81, 200]);
+        assert_eq!(long.len(), 203);
+        let longer = tlv(OCTET_STRING, &[0; 300]);
+        assert_eq!(longer[..4], [0x04, 0x82, 0x01, 0x2c]);
+    }
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(encode_integer(0), [0x00]);
+        assert_eq!(encode_integer(127), [0x7f]);
+        assert_eq!(encode_integer(128), [0x00, 0x80]);
+        assert_eq!(encode_integer(500), [0x01, 0xf4]);
+        assert_eq!(encode_integer(-1), [0xff]);
+        assert_eq!(encode_integer(-129), [0xff, 0x7f]);
+        assert_eq!(decode_integer(&[0x01, 0xf4]), 500);
+        assert_eq!(decode_integer(&[0xff, 0x7f]), -129);
+        assert_eq!(decode_integer(&[0x00, 0x80]), 128);
+    }
+
+    #[test]
+    fn test_reader_rejects_truncated_elements() {
+        assert!(BerReader::new(&[0x04, 0x05, b'a']).next().is_err());
+        assert!(BerReader::new(&[0x04]).next().is_err());
+        assert!(BerReader::new(&[0x02, 0x01, 0x00]).expect(OCTET_STRING).is_err());
+    }
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(parse_filter("mail=*").unwrap(), tlv(0x87, b"mail"));
+        let mut equality = tlv(OCTET_STRING, b"cn");
+        equality.extend(tlv(OCTET_STRING, b"a(b"));
+        assert_eq!(parse_filter("(cn=a\\28b)").unwrap(), tlv(0xa3, &equality));
+
+        let mut substrings = tlv(OCTET_STRING, b"cn");
+        let mut parts = tlv(0x80, b"jo");
+        parts.extend(tlv(0x82, b"n"));
+        substrings.extend(tlv(SEQUENCE, &parts));
+        assert_eq!(parse_filter("(cn=jo*n)").unwrap(), tlv(0xa4, &substrings));
+
+        let mut and = tlv(0x87, b"mail");
+        and.extend(tlv(0xa2, &tlv(0x87, b"nsAccountLock")));
+        assert_eq!(parse_filter("(&(mail=*)(!(nsAccountLock=*)))").unwrap(), tlv(0xa0, &and));
+
+        assert!(parse_filter("(&)").is_err());
+        assert!(parse_filter("(cn=a").is_err());
+        assert!(parse_filter("(cn=a)x").is_err());
+        assert!(parse_filter("(=a)").is_err());
+        assert!(parse_filter("(cn=\\zz)").is_err());
+    }
+
+    #[test]
+    fn test_entry_and_result() {
+        let mut values = tlv(OCTET_STRING, b"alice@example.com");
+        values.extend(tlv(OCTET_STRING, b"a@example.com"));
+        let mut attribute = tlv(OCTET_STRING, b"mail");
+        attribute.extend(tlv(SET, &values));
+        let mut entry = tlv(OCTET_STRING, b"uid=alice,dc=example,dc=com");
+        entry.extend(tlv(SEQUENCE, &tlv(SEQUENCE, &attribute)));
+
+        let (dn, attributes) = parse_entry(&entry).unwrap();
+        assert_eq!(dn, "uid=alice,dc=example,dc=com");
+        assert_eq!(attributes, [("mail".to_owned(), vec!["alice@example.com".to_owned(), "a@example.com".to_owned()])]);
+
+        assert!(check_result(&ldap_result(0, ""), "Bind").is_ok());
+        assert!(check_result(&ldap_result(49, "Invalid credentials"), "Bind").is_err());
+    }
+
+    #[test]
+    fn test_paged_cookie() {
+        let mut paging = tlv(INTEGER, &encode_integer(0));
+        paging.extend(tlv(OCTET_STRING, b"next"));
+        let mut control = tlv(OCTET_STRING, PAGED_RESULTS_OID.as_bytes());
+        control.extend(tlv(BOOLEAN, &[0]));
+        control.extend(tlv(OCTET_STRING, &tlv(SEQUENCE, &paging)));
+        assert_eq!(paged_cookie(&tlv(SEQUENCE, &control)).unwrap(), b"next");
+
+        let other = tlv(SEQUENCE, &tlv(OCTET_STRING, b"1.2.3"));
+        assert!(paged_cookie(&other).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_receive() {
+        let mut message = tlv(INTEGER, &encode_integer(1));
+        message.extend(tlv(BIND_RESPONSE, &ldap_result(0, "")));
+        let mut conn = connection(tlv(SEQUENCE, &message));
+        let response = conn.receive(1).unwrap();
+        assert_eq!(response.op, BIND_RESPONSE);
+        assert!(response.controls.is_none());
+
+        // Another message id
+        assert!(connection(tlv(SEQUENCE, &message)).receive(2).is_err());
+
+        // Notice of disconnection
+        let mut notice = tlv(INTEGER, &encode_integer(0));
+        notice.extend(tlv(EXTENDED_RESPONSE, &ldap_result(52, "")));
+        assert!(connection(tlv(SEQUENCE, &notice)).receive(1).is_err());
+
+        // The announced length is checked before anything is allocated
+        assert!(connection(vec![SEQUENCE, 0x84, 0xff, 0xff, 0xff, 0xff]).receive(1).is_err());
+        assert!(connection(vec![SEQUENCE, 0x85, 0, 0, 0, 0, 1]).receive(1).is_err());
+    }
+
+    #[test]
+    fn test_bind_requires_a_password_with_a_dn() {
+        assert!(connection(Vec::new()).bind("cn=vaultwarden,dc=example,dc=com", "").is_err());
+    }
+
+    #[test]
+    fn test_is_dn_below() {
+        let base = "ou=people, dc=example,dc=com";
+        assert!(is_dn_below("uid=alice,ou=People,dc=example,dc=com", base));
+        assert!(is_dn_below("uid=bob, ou=sub,ou=people,dc=example,dc=com", base));
+        assert!(!is_dn_below("ou=people,dc=example,dc=com", base));
+        assert!(!is_dn_below("uid=alice,ou=staff,dc=example,dc=com", base));
+        assert!(!is_dn_below("00u1abcd", base));
+        assert!(!is_dn_below("uid=alice", ""));
+    }
+}
@@ -1,6 +1,6 @@
 use std::{env::consts::EXE_SUFFIX, str::FromStr};
 
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use lettre::{
     Address, AsyncSendmailTransport, AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
     message::{Attachment, Body, Mailbox, Message, MultiPart, SinglePart},
@@ -19,6 +19,7 @@ use crate::{
     },
     db::models::{Device, DeviceType, EmergencyAccessId, MembershipId, OrganizationId, User, UserId},
     error::Error,
+    tenant::MailTenant,
     util::upcase_first,
 };
 
@@ -30,16 +31,27 @@ fn sendmail_transport() -> AsyncSendmailTransport<Tokio1Executor> {
     }
 }
 
-fn smtp_transport() -> AsyncSmtpTransport<Tokio1Executor> {
+fn smtp_transport(tenant: &MailTenant) -> AsyncSmtpTransport<Tokio1Executor> {
     use std::time::Duration;
-    let host = CONFIG.smtp_host().unwrap();
+    // A tenant with its own SMTP server only uses the global settings which aren't about the server
+    let (host, port, security, credentials) = match tenant.smtp() {
+        Some(smtp) => {
+            (smtp.host.clone(), smtp.port(), smtp.security(), smtp.username.clone().zip(smtp.password.clone()))
+        }
+        None => (
+            CONFIG.smtp_host().unwrap(),
+            CONFIG.smtp_port(),
+            CONFIG.smtp_security(),
+            CONFIG.smtp_username().zip(CONFIG.smtp_password()),
+        ),
+    };
 
     let smtp_client = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host.as_str())
-        .port(CONFIG.smtp_port())
+        .port(port)
         .timeout(Some(Duration::from_secs(CONFIG.smtp_timeout())));
 
     // Determine security
-    let smtp_client = if CONFIG.smtp_security() == *"off" {
+    let smtp_client = if security == *"off" {
         smtp_client
     } else {
         let mut tls_parameters = TlsParameters::builder(host);
@@ -51,16 +63,16 @@ fn smtp_transport() -> AsyncSmtpTransport<Tokio1Executor> {
         }
         let tls_parameters = tls_parameters.build().unwrap();
 
-        if CONFIG.smtp_security() == *"force_tls" {
+        if security == *"force_tls" {
             smtp_client.tls(Tls::Wrapper(tls_parameters))
         } else {
             smtp_client.tls(Tls::Required(tls_parameters))
         }
     };
 
-    let smtp_client = match (CONFIG.smtp_username(), CONFIG.smtp_password()) {
-        (Some(user), Some(pass)) => smtp_client.credentials(Credentials::new(user, pass)),
-        _ => smtp_client,
+    let smtp_client = match credentials {
+        Some((user, pass)) => smtp_client.credentials(Credentials::new(user, pass)),
+        None => smtp_client,
     };
 
     let smtp_client = match CONFIG.helo_name() {
@@ -118,8 +130,14 @@ fn sanitize_data(data: &mut serde_json::Value) {
     }
 }
 
-fn get_text(template_name: &'static str, data: serde_json::Value) -> Result<(String, String, String), Error> {
+fn get_text(
+    tenant: &MailTenant,
+    template_name: &'static str,
+    data: serde_json::Value,
+) -> Result<(String, String, String), Error> {
     let mut data = data;
+    data["img_src"] = json!(tenant.img_src());
+    data["logo_url"] = json!(tenant.logo_url());
     sanitize_data(&mut data);
     let (subject_html, body_html) = get_template(&format!("{template_name}.html"), &data)?;
     let (_subject_text, body_text) = get_template(template_name, &data)?;
@@ -150,6 +168,7 @@ fn get_template(template_name: &str, data: &serde_json::Value) -> Result<(String
 }
 
 pub async fn send_password_hint(address: &str, hint: Option<String>) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let template_name = if hint.is_some() {
         "email/pw_hint_some"
     } else {
@@ -157,54 +176,57 @@ pub async fn send_password_hint(address: &str, hint: Option<String>) -> EmptyRes
     };
 
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         template_name,
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "hint": hint,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_delete_account(address: &str, user_id: &UserId) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let claims = generate_delete_claims(user_id.to_string());
     let delete_token = encode_jwt(&claims);
 
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/delete_account",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "user_id": user_id,
             "email": percent_encode(address.as_bytes(), NON_ALPHANUMERIC).to_string(),
             "token": delete_token,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_verify_email(address: &str, user_id: &UserId) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let claims = generate_verify_email_claims(user_id);
     let verify_email_token = encode_jwt(&claims);
 
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/verify_email",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "user_id": user_id,
             "email": percent_encode(address.as_bytes(), NON_ALPHANUMERIC).to_string(),
             "token": verify_email_token,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
-pub async fn send_register_verify_email(email: &str, token: &str) -> EmptyResult {
+// The account doesn't exist yet, so the tenant is the one the registration was started on
+pub async fn send_register_verify_email(email: &str, token: &str, tenant: MailTenant) -> EmptyResult {
     let mut query = url::Url::parse("https://query.builder").unwrap();
     query.query_pairs_mut().append_pair("email", email).append_pair("token", token);
     let Some(query_string) = query.query() else {
@@ -212,71 +234,114 @@ pub async fn send_register_verify_email(email: &str, token: &str) -> EmptyResult
     };
 
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/register_verify_email",
         json!({
             // `url.Url` would place the anchor `#` after the query parameters
-            "url": format!("{}/#/finish-signup/?{query_string}", CONFIG.domain()),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": format!("{}/#/finish-signup/?{query_string}", tenant.domain()),
             "email": email,
         }),
     )?;
 
-    send_email(email, &subject, body_html, body_text).await
+    send_email(&tenant, email, &subject, body_html, body_text).await
 }
 
 pub async fn send_welcome(address: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/welcome",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_welcome_must_verify(address: &str, user_id: &UserId) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let claims = generate_verify_email_claims(user_id);
     let verify_email_token = encode_jwt(&claims);
 
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/welcome_must_verify",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "user_id": user_id,
             "token": verify_email_token,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_2fa_removed_from_org(address: &str, org_name: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/send_2fa_removed_from_org",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "org_name": org_name,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
+}
+
+pub async fn send_2fa_grace_period(address: &str, org_name: &str, grace_until: &NaiveDateTime) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
+    let fmt = "%A, %B %_d, %Y at %r %Z";
+    let (subject, body_html, body_text) = get_text(
+        &tenant,
+        "email/send_2fa_grace_period",
+        json!({
+            "url": tenant.domain(),
+            "org_name": org_name,
+            "grace_until": crate::util::format_naive_datetime_local(grace_until, fmt),
+        }),
+    )?;
+
+    send_email(&tenant, address, &subject, body_html, body_text).await
+}
+
+pub async fn send_inactive_account_warning(
+    address: &str,
+    last_login: &NaiveDateTime,
+    action: &str,
+    action_date: &NaiveDateTime,
+) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
+    let fmt = "%A, %B %_d, %Y at %r %Z";
+    let (subject, body_html, body_text) = get_text(
+        &tenant,
+        "email/inactive_account_warning",
+        json!({
+            "url": tenant.domain(),
+            "last_login": crate::util::format_naive_datetime_local(last_login, fmt),
+            "disable": action == "disable",
+            "delete": action == "delete",
+            "action_date": crate::util::format_naive_datetime_local(action_date, fmt),
+        }),
+    )?;
+
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_single_org_removed_from_org(address: &str, org_name: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/send_single_org_removed_from_org",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "org_name": org_name,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_invite(
@@ -286,6 +351,7 @@ pub async fn send_invite(
     org_name: &str,
     invited_by_email: Option<String>,
 ) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(&user.email).await;
     let claims = generate_invite_claims(
         user.uuid.clone(),
         user.email.clone(),
@@ -317,16 +383,16 @@ pub async fn send_invite(
     };
 
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/send_org_invite",
         json!({
             // `url.Url` would place the anchor `#` after the query parameters
-            "url": format!("{}/#/accept-organization/?{query_string}", CONFIG.domain()),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": format!("{}/#/accept-organization/?{query_string}", tenant.domain()),
             "org_name": org_name,
         }),
     )?;
 
-    send_email(&user.email, &subject, body_html, body_text).await
+    send_email(&tenant, &user.email, &subject, body_html, body_text).await
 }
 
 pub async fn send_emergency_access_invite(
@@ -336,6 +402,7 @@ pub async fn send_emergency_access_invite(
     grantor_name: &str,
     grantor_email: &str,
 ) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let claims = generate_emergency_access_invite_claims(
         user_id,
         String::from(address),
@@ -360,55 +427,58 @@ pub async fn send_emergency_access_invite(
     };
 
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/send_emergency_access_invite",
         json!({
             // `url.Url` would place the anchor `#` after the query parameters
-            "url": format!("{}/#/accept-emergency/?{query_string}", CONFIG.domain()),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": format!("{}/#/accept-emergency/?{query_string}", tenant.domain()),
             "grantor_name": grantor_name,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_emergency_access_invite_accepted(address: &str, grantee_email: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/emergency_access_invite_accepted",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "grantee_email": grantee_email,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_emergency_access_invite_confirmed(address: &str, grantor_name: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/emergency_access_invite_confirmed",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "grantor_name": grantor_name,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_emergency_access_recovery_approved(address: &str, grantor_name: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/emergency_access_recovery_approved",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "grantor_name": grantor_name,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_emergency_access_recovery_initiated(
@@ -417,18 +487,19 @@ pub async fn send_emergency_access_recovery_initiated(
     atype: &str,
     wait_time_days: &i32,
 ) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/emergency_access_recovery_initiated",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "grantee_name": grantee_name,
             "atype": atype,
             "wait_time_days": wait_time_days,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_emergency_access_recovery_reminder(
@@ -437,89 +508,103 @@ pub async fn send_emergency_access_recovery_reminder(
     atype: &str,
     days_left: &str,
 ) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/emergency_access_recovery_reminder",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "grantee_name": grantee_name,
             "atype": atype,
             "days_left": days_left,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_emergency_access_recovery_rejected(address: &str, grantor_name: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/emergency_access_recovery_rejected",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "grantor_name": grantor_name,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_emergency_access_recovery_timed_out(address: &str, grantee_name: &str, atype: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/emergency_access_recovery_timed_out",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "grantee_name": grantee_name,
             "atype": atype,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_invite_accepted(new_user_email: &str, address: &str, org_name: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/invite_accepted",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "email": new_user_email,
             "org_name": org_name,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
-pub async fn send_invite_confirmed(address: &str, org_name: &str) -> EmptyResult {
+pub async fn send_invite_confirmed(address: &str, org_name: &str, welcome_message: Option<&str>) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/invite_confirmed",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "org_name": org_name,
+            "welcome_message": welcome_message,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
-pub async fn send_new_device_logged_in(address: &str, ip: &str, dt: &NaiveDateTime, device: &Device) -> EmptyResult {
+pub async fn send_new_device_logged_in(
+    address: &str,
+    ip: &str,
+    country: Option<&str>,
+    dt: &NaiveDateTime,
+    device: &Device,
+) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let fmt = "%A, %B %_d, %Y at %r %Z";
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/new_device_logged_in",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "ip": ip,
             "device_name": upcase_first(&device.name),
             "device_type": DeviceType::from_i32(device.atype).to_string(),
+            "country": country,
             "datetime": crate::util::format_naive_datetime_local(dt, fmt),
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_incomplete_2fa_login(
@@ -529,12 +614,13 @@ pub async fn send_incomplete_2fa_login(
     device_name: &str,
     device_type: &str,
 ) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let fmt = "%A, %B %_d, %Y at %r %Z";
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/incomplete_2fa_login",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "ip": ip,
             "device_name": upcase_first(device_name),
             "device_type": device_type,
@@ -543,115 +629,206 @@ pub async fn send_incomplete_2fa_login(
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_token(address: &str, token: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/twofactor_email",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "token": token,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_change_email(address: &str, token: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/change_email",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "token": token,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
+}
+
+pub async fn send_security_change_pending(address: &str, change: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
+    let (subject, body_html, body_text) = get_text(
+        &tenant,
+        "email/security_change_pending",
+        json!({
+            "url": tenant.domain(),
+            "change": change,
+            "lockout_hours": CONFIG.security_change_lockout_hours(),
+        }),
+    )?;
+
+    send_email(&tenant, address, &subject, body_html, body_text).await
+}
+
+pub async fn send_attachment_quota_warning(
+    address: &str,
+    owner: &str,
+    percent: i32,
+    used: &str,
+    limit: &str,
+) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
+    let (subject, body_html, body_text) = get_text(
+        &tenant,
+        "email/attachment_quota_warning",
+        json!({
+            "url": tenant.domain(),
+            "owner": owner,
+            "percent": percent,
+            "used": used,
+            "limit": limit,
+        }),
+    )?;
+
+    send_email(&tenant, address, &subject, body_html, body_text).await
+}
+
+pub async fn send_member_offboarded(
+    address: &str,
+    org_name: &str,
+    member_email: &str,
+    revoked: bool,
+    groups: usize,
+    items: usize,
+    grants: usize,
+) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
+    let (subject, body_html, body_text) = get_text(
+        &tenant,
+        "email/member_offboarded",
+        json!({
+            "url": tenant.domain(),
+            "org_name": org_name,
+            "member_email": member_email,
+            "revoked": revoked,
+            "groups": groups,
+            "items": items,
+            "grants": grants,
+        }),
+    )?;
+
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_change_email_existing(address: &str, acting_address: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/change_email_existing",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "existing_address": address,
             "acting_address": acting_address,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_change_email_invited(address: &str, acting_address: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/change_email_invited",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "existing_address": address,
             "acting_address": acting_address,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_sso_change_email(address: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/sso_change_email",
         json!({
-            "url": format!("{}/#/settings/account", CONFIG.domain()),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": format!("{}/#/settings/account", tenant.domain()),
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_test(address: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/smtp_test",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
+        }),
+    )?;
+
+    send_email(&tenant, address, &subject, body_html, body_text).await
+}
+
+pub async fn send_admin_lockout(address: &str, ip: &str, attempts: u32, minutes: u64) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
+    let fmt = "%A, %B %_d, %Y at %r %Z";
+    let (subject, body_html, body_text) = get_text(
+        &tenant,
+        "email/admin_lockout",
+        json!({
+            "url": tenant.domain(),
+            "ip": ip,
+            "attempts": attempts,
+            "minutes": minutes,
+            "datetime": crate::util::format_naive_datetime_local(&Utc::now().naive_utc(), fmt),
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_admin_reset_password(address: &str, user_name: &str, org_name: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/admin_reset_password",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "user_name": user_name,
             "org_name": org_name,
         }),
     )?;
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
 pub async fn send_protected_action_token(address: &str, token: &str) -> EmptyResult {
+    let tenant = MailTenant::for_recipient(address).await;
     let (subject, body_html, body_text) = get_text(
+        &tenant,
         "email/protected_action",
         json!({
-            "url": CONFIG.domain(),
-            "img_src": CONFIG._smtp_img_src(),
+            "url": tenant.domain(),
             "token": token,
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(&tenant, address, &subject, body_html, body_text).await
 }
 
-async fn send_with_selected_transport(email: Message) -> EmptyResult {
-    if CONFIG.use_sendmail() {
+async fn send_with_selected_transport(tenant: &MailTenant, email: Message) -> EmptyResult {
+    if CONFIG.use_sendmail() && tenant.smtp().is_none() {
         match sendmail_transport().send(email).await {
             Ok(()) => Ok(()),
             // Match some common errors and make them more user friendly
@@ -668,7 +845,7 @@ async fn send_with_selected_transport(email: Message) -> EmptyResult {
             }
         }
     } else {
-        match smtp_transport().send(email).await {
+        match smtp_transport(tenant).send(email).await {
             Ok(_) => Ok(()),
             // Match some common errors and make them more user friendly
             Err(e) => {
@@ -700,8 +877,15 @@ async fn send_with_selected_transport(email: Message) -> EmptyResult {
     }
 }
 
-async fn send_email(address: &str, subject: &str, body_html: String, body_text: String) -> EmptyResult {
-    let smtp_from = Address::from_str(&CONFIG.smtp_from())?;
+async fn send_email(
+    tenant: &MailTenant,
+    address: &str,
+    subject: &str,
+    body_html: String,
+    body_text: String,
+) -> EmptyResult {
+    let (smtp_from, smtp_from_name) = tenant.smtp_sender();
+    let smtp_from = Address::from_str(&smtp_from)?;
 
     let body = if CONFIG.smtp_embed_images() {
         let logo_gray_body = Body::new(crate::api::static_files("logo-gray.png").unwrap().1.to_vec());
@@ -725,9 +909,9 @@ async fn send_email(address: &str, subject: &str, body_html: String, body_text:
     let email = Message::builder()
         .message_id(Some(format!("<{}@{}>", crate::util::get_uuid(), smtp_from.domain())))
         .to(Mailbox::new(None, Address::from_str(address)?))
-        .from(Mailbox::new(Some(CONFIG.smtp_from_name()), smtp_from))
+        .from(Mailbox::new(Some(smtp_from_name), smtp_from))
         .subject(subject)
         .multipart(body)?;
 
-    send_with_selected_transport(email).await
+    send_with_selected_transport(tenant, email).await
 }
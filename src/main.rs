@@ -29,11 +29,15 @@ extern crate diesel_derive_newtype;
 use std::{
     collections::HashMap,
     fs::{canonicalize, create_dir_all},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     panic,
     path::Path,
     process::exit,
     str::FromStr,
-    sync::{Arc, atomic::Ordering},
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
     thread,
 };
 
@@ -49,19 +53,29 @@ use rocket::data::{Limits, ToByteUnit};
 
 #[macro_use]
 mod error;
+mod acme;
 mod api;
 mod auth;
+mod cli;
 mod config;
 mod crypto;
 #[macro_use]
 mod db;
+#[cfg(all(test, sqlite, feature = "e2e"))]
+mod e2e;
 mod http_client;
+mod ldap;
 mod mail;
+mod proxy;
 mod ratelimit;
+mod recovery;
 mod sso;
 mod sso_client;
 mod storage;
+mod telemetry;
+mod tenant;
 mod util;
+mod webhook;
 
 use crate::api::{
     WS_ANONYMOUS_SUBSCRIPTIONS, WS_USERS, core::two_factor::duo_oidc::purge_duo_contexts, purge_auth_requests,
@@ -73,21 +87,34 @@ pub use util::is_running_in_container;
 #[rocket::main]
 async fn main() -> Result<(), Error> {
     install_rustls_crypto_provider();
-    parse_args();
+    parse_args().await;
     launch_info();
 
     let level = init_logging()?;
 
     check_data_folder().await;
+    check_file_storage().await;
     auth::initialize_keys().await.unwrap_or_else(|e| {
         error!("Error creating private key '{}'\n{e:?}\nExiting Vaultwarden!", CONFIG.private_rsa_key());
         exit(1);
     });
     check_web_vault();
+    check_config_audit();
+    if !http_client::supports_txt_lookups() {
+        warn!("The DNS resolver can't look up TXT records, organization domains can't be verified");
+    }
 
     create_dir(&CONFIG.tmp_folder(), "tmp folder");
+    create_dir(&CONFIG.tmp_quarantine_folder(), "tmp quarantine folder");
 
     let pool = create_db_pool().await;
+    if CONFIG.startup_integrity_check() {
+        tokio::spawn(db::integrity::run_preflight(pool.clone()));
+    }
+    tenant::init(&pool);
+    if CONFIG.acme_enabled() {
+        acme::init();
+    }
     schedule_jobs(pool.clone());
     db::models::TwoFactor::migrate_u2f_to_webauthn(&pool.get().await.unwrap()).await.unwrap();
     db::models::TwoFactor::migrate_credential_to_passkey(&pool.get().await.unwrap()).await.unwrap();
@@ -110,6 +137,19 @@ COMMAND:
     hash [--preset {bitwarden|owasp}]  Generate an Argon2id PHC ADMIN_TOKEN
     backup                             Create a backup of the SQLite database
                                        You can also send the USR1 signal to trigger a backup
+    restore <bundle> [--force]         Restore a disaster recovery bundle created from the admin page
+                                       Use --force to overwrite an existing SQLite database
+    admin <TASK>                       Run an administrative task directly against the database
+                                       Meant for recovery when the server or the admin page is unreachable
+
+TASKS:
+    create-invite <email>              Invite a new user
+    disable-user <email>               Disable a user and log out all of its devices
+    remove-2fa <email>                 Remove all two-factor authentication methods of a user
+    rotate-admin-token                 Generate a new ADMIN_TOKEN and save its Argon2id hash in the config file
+    vacuum                             Reclaim the unused space of the database (SQLite and PostgreSQL)
+    backup                             Create a backup of the SQLite database
+    verify-attachments                 Check that the file of every attachment exists and has the expected size
 
 PRESETS:                  m=         t=          p=
     bitwarden (default) 64MiB, 3 Iterations, 4 Threads
@@ -119,7 +159,7 @@ PRESETS:                  m=         t=          p=
 
 pub const VERSION: Option<&str> = option_env!("VW_VERSION");
 
-fn parse_args() {
+async fn parse_args() {
     let mut pargs = pico_args::Arguments::from_env();
     let version = VERSION.unwrap_or("(Version info from Git not present)");
 
@@ -137,25 +177,14 @@ fn parse_args() {
 
     if let Some(command) = pargs.subcommand().unwrap_or_default() {
         if command == "hash" {
-            use argon2::{
-                Algorithm::Argon2id, Argon2, ParamsBuilder, PasswordHasher, Version::V0x13, password_hash::SaltString,
-            };
-
-            let mut argon2_params = ParamsBuilder::new();
             let preset: Option<String> = pargs.opt_value_from_str(["-p", "--preset"]).unwrap_or_default();
-            let selected_preset;
-            if preset.as_deref() == Some("owasp") {
-                selected_preset = "owasp";
-                argon2_params.m_cost(19456);
-                argon2_params.t_cost(2);
-                argon2_params.p_cost(1);
+            // Bitwarden preset is the default
+            let owasp = preset.as_deref() == Some("owasp");
+            let selected_preset = if owasp {
+                "owasp"
             } else {
-                // Bitwarden preset is the default
-                selected_preset = "bitwarden";
-                argon2_params.m_cost(65540);
-                argon2_params.t_cost(3);
-                argon2_params.p_cost(4);
-            }
+                "bitwarden"
+            };
 
             println!("Generate an Argon2id PHC string using the '{selected_preset}' preset:\n");
 
@@ -171,11 +200,8 @@ fn parse_args() {
                 exit(1);
             }
 
-            let argon2 = Argon2::new(Argon2id, V0x13, argon2_params.build().unwrap());
-            let salt = SaltString::encode_b64(&crypto::get_random_bytes::<32>()).unwrap();
-
             let argon2_timer = tokio::time::Instant::now();
-            if let Ok(password_hash) = argon2.hash_password(password.as_bytes(), &salt) {
+            if let Some(password_hash) = cli::hash_admin_token(&password, owasp) {
                 println!(
                     "\n\
                     ADMIN_TOKEN='{password_hash}'\n\n\
@@ -197,6 +223,33 @@ fn parse_args() {
                     exit(1);
                 }
             }
+        } else if command == "restore" {
+            let force = pargs.contains("--force");
+            let Ok(bundle) = pargs.free_from_str::<String>() else {
+                println!("Missing the path of the bundle to restore");
+                exit(1);
+            };
+            match recovery::restore_bundle(&bundle, force).await {
+                Ok(()) => {
+                    println!("Restore of '{bundle}' was successful");
+                    exit(0);
+                }
+                Err(e) => {
+                    println!("Restore failed. {e:?}");
+                    exit(1);
+                }
+            }
+        } else if command == "admin" {
+            match cli::run_admin_task(&mut pargs).await {
+                Ok(msg) => {
+                    println!("{msg}");
+                    exit(0);
+                }
+                Err(e) => {
+                    println!("Admin task failed. {e:?}");
+                    exit(1);
+                }
+            }
         }
         exit(0);
     }
@@ -454,17 +507,17 @@ fn create_dir(path: &str, description: &str) {
 async fn check_data_folder() {
     let data_folder = &CONFIG.data_folder();
 
-    if data_folder.starts_with("s3://") {
+    if storage::is_object_store(data_folder) {
         if let Err(e) = CONFIG
-            .opendal_operator_for_path_type(&PathType::Data)
+            .storage_backend(&PathType::Data)
             .unwrap_or_else(|e| {
-                error!("Failed to create S3 operator for data folder '{data_folder}': {e:?}");
+                error!("Failed to create the storage backend for data folder '{data_folder}': {e:?}");
                 exit(1);
             })
-            .check()
+            .health_check()
             .await
         {
-            error!("Could not access S3 data folder '{data_folder}': {e:?}");
+            error!("Could not access the data folder '{data_folder}': {e:?}");
             exit(1);
         }
 
@@ -502,6 +555,23 @@ async fn check_data_folder() {
     }
 }
 
+// Attachments and Sends can be stored in a bucket or container while the rest of the data folder stays local,
+// make sure the bucket is reachable before files are uploaded to it
+async fn check_file_storage() {
+    for path_type in [PathType::Attachments, PathType::Sends] {
+        let backend = CONFIG.storage_backend(&path_type).unwrap_or_else(|e| {
+            error!("Failed to create the storage backend for the {} folder: {e:?}", path_type.as_str());
+            exit(1);
+        });
+        if backend.kind() != "local"
+            && let Err(e) = backend.health_check().await
+        {
+            error!("Could not access the {} {} folder: {e:?}", backend.kind(), path_type.as_str());
+            exit(1);
+        }
+    }
+}
+
 /// Detect when using Docker or Podman the DATA_FOLDER is either a bind-mount or a volume created manually.
 /// If not created manually, then the data will not be persistent.
 /// A none persistent volume in either Docker or Podman is represented by a 64 alphanumerical string.
@@ -552,6 +622,13 @@ fn check_web_vault() {
     }
 }
 
+// The same findings are shown on the admin diagnostics page
+fn check_config_audit() {
+    for finding in CONFIG.audit() {
+        warn!("{} {}", finding.message, finding.remediation);
+    }
+}
+
 async fn create_db_pool() -> db::DbPool {
     match util::retry_db(db::DbPool::from_config, CONFIG.db_connection_retries()).await {
         Ok(p) => p,
@@ -563,25 +640,116 @@ async fn create_db_pool() -> db::DbPool {
 }
 
 async fn launch_rocket(pool: db::DbPool, extra_debug: bool) -> Result<(), Error> {
+    spawn_shutdown_signal_handler();
+
+    #[cfg(all(unix, sqlite))]
+    {
+        if db::ACTIVE_DB_TYPE.get() == Some(&db::DbConnType::Sqlite) {
+            tokio::spawn(async move {
+                let mut signal_user1 = tokio::signal::unix::signal(SignalKind::user_defined1()).unwrap();
+                loop {
+                    // If we need more signals to act upon, we might want to use select! here.
+                    // With only one item to listen for this is enough.
+                    let _ = signal_user1.recv().await;
+                    match db::backup_sqlite() {
+                        Ok(f) => info!("Backup to '{f}' was successful"),
+                        Err(e) => error!("Backup failed. {e:?}"),
+                    }
+                }
+            });
+        } else {
+            debug!("PostgreSQL and MySQL/MariaDB do not support this backup feature, skip adding USR1 signal.");
+        }
+    }
+
+    if let Some(listen) = CONFIG.proxy_protocol_listen() {
+        tokio::spawn(proxy::start_proxy_protocol_listener(listen));
+    }
+    #[cfg(unix)]
+    {
+        if let Some(path) = CONFIG.unix_socket_path() {
+            tokio::spawn(proxy::start_unix_listener(path));
+        }
+        if CONFIG.socket_activation() {
+            tokio::spawn(proxy::start_activated_listener());
+        }
+    }
+
+    // Rocket can't reload its TLS configuration, it is launched again when the ACME certificate was renewed
+    loop {
+        let instance = build_rocket(pool.clone(), extra_debug).await?;
+
+        CONFIG.set_rocket_shutdown_handle(instance.shutdown());
+        // A shutdown could have been requested while Rocket was being relaunched
+        if SHUTTING_DOWN.load(Ordering::Relaxed) {
+            CONFIG.shutdown();
+        }
+
+        instance.launch().await?;
+
+        if SHUTTING_DOWN.load(Ordering::Relaxed) || !acme::take_reload_request() {
+            break;
+        }
+        info!("Launching the web server again");
+    }
+
+    drain_running_jobs().await;
+
+    info!("Vaultwarden process exited!");
+    Ok(())
+}
+
+async fn build_rocket(pool: db::DbPool, extra_debug: bool) -> Result<rocket::Rocket<rocket::Ignite>, Error> {
     let basepath = &CONFIG.domain_path();
 
     let mut config = rocket::Config::from(rocket::Config::figment());
 
-    // We install our own signal handlers below; disable Rocket's built-in handlers
+    // We install our own signal handlers in `launch_rocket`; disable Rocket's built-in handlers
     config.shutdown.ctrlc = false;
     #[cfg(unix)]
     config.shutdown.signals.clear();
+    // Give in-flight requests, like uploads, the time to finish before the connections are closed
+    config.shutdown.grace = u32::try_from(CONFIG.shutdown_drain_timeout()).unwrap_or(u32::MAX);
 
     config.temp_dir = canonicalize(CONFIG.tmp_folder()).unwrap().into();
     config.cli_colors = false; // Make sure Rocket does not color any values for logging.
+    if proxy::forwards_all_connections() {
+        config.address = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        config.port = 0;
+    }
+    if let Some(keep_alive) = CONFIG.http_keep_alive() {
+        config.keep_alive = keep_alive;
+    }
     config.limits = Limits::new()
         .limit("json", 20.megabytes()) // 20MB should be enough for very large imports, something like 5000+ vault entries
         .limit("data-form", 525.megabytes()) // This needs to match the maximum allowed file size for Send
         .limit("file", 525.megabytes()); // This needs to match the maximum allowed file size for attachments
 
+    if CONFIG.acme_enabled() {
+        if let Err(e) = acme::ensure_certificate(SocketAddr::new(config.address, config.port)).await {
+            // An existing certificate is still used until it can be renewed
+            if !acme::has_certificate() {
+                return Err(e);
+            }
+            error!("Unable to renew the ACME certificate, using the current one: {e:?}");
+        }
+        config.tls = Some(acme::tls_config());
+    }
+    // Clients connecting directly get neither HTTP/2 nor a limit on the number of connections
+    if config.tls.is_some() {
+        warn!(
+            "The embedded server only serves HTTP/1.1 and only limits the number of websocket connections, use a reverse proxy for HTTP/2 or to limit all connections"
+        );
+    }
+
     // If adding more paths here, consider also adding them to
     // crate::utils::LOGGED_ROUTES to make sure they appear in the log
-    let instance = rocket::custom(config)
+    let mut instance = rocket::custom(config);
+    // Refuse everyone connecting to the loopback port directly instead of through the socket
+    if proxy::forwards_all_connections() {
+        instance = instance.attach(proxy::ForwardedOnly).mount("/", proxy::forwarded_only_routes());
+    }
+    let instance = instance
         .mount([basepath, "/"].concat(), api::web_routes())
         .mount([basepath, "/api"].concat(), api::core_routes())
         .mount([basepath, "/admin"].concat(), api::admin_routes())
@@ -589,6 +757,7 @@ async fn launch_rocket(pool: db::DbPool, extra_debug: bool) -> Result<(), Error>
         .mount([basepath, "/identity"].concat(), api::identity_routes())
         .mount([basepath, "/icons"].concat(), api::icons_routes())
         .mount([basepath, "/notifications"].concat(), api::notifications_routes())
+        .mount([basepath, "/scim"].concat(), api::scim_routes())
         .register([basepath, "/"].concat(), api::web_catchers())
         .register([basepath, "/api"].concat(), api::core_catchers())
         .register([basepath, "/admin"].concat(), api::admin_catchers())
@@ -598,37 +767,14 @@ async fn launch_rocket(pool: db::DbPool, extra_debug: bool) -> Result<(), Error>
         .attach(util::AppHeaders())
         .attach(util::Cors())
         .attach(util::BetterLogging(extra_debug))
+        .attach(rocket::fairing::AdHoc::on_liftoff("Forwarding address", |rocket| {
+            Box::pin(async move {
+                proxy::set_rocket_address(SocketAddr::new(rocket.config().address, rocket.config().port));
+            })
+        }))
         .ignite()
         .await?;
-
-    CONFIG.set_rocket_shutdown_handle(instance.shutdown());
-
-    spawn_shutdown_signal_handler();
-
-    #[cfg(all(unix, sqlite))]
-    {
-        if db::ACTIVE_DB_TYPE.get() == Some(&db::DbConnType::Sqlite) {
-            tokio::spawn(async move {
-                let mut signal_user1 = tokio::signal::unix::signal(SignalKind::user_defined1()).unwrap();
-                loop {
-                    // If we need more signals to act upon, we might want to use select! here.
-                    // With only one item to listen for this is enough.
-                    let _ = signal_user1.recv().await;
-                    match db::backup_sqlite() {
-                        Ok(f) => info!("Backup to '{f}' was successful"),
-                        Err(e) => error!("Backup failed. {e:?}"),
-                    }
-                }
-            });
-        } else {
-            debug!("PostgreSQL and MySQL/MariaDB do not support this backup feature, skip adding USR1 signal.");
-        }
-    }
-
-    instance.launch().await?;
-
-    info!("Vaultwarden process exited!");
-    Ok(())
+    Ok(instance)
 }
 
 #[cfg(unix)]
@@ -647,6 +793,7 @@ fn spawn_shutdown_signal_handler() {
         };
 
         info!("Received {signal_name}, initiating graceful shutdown");
+        SHUTTING_DOWN.store(true, Ordering::Relaxed);
         CONFIG.shutdown();
     });
 }
@@ -656,10 +803,131 @@ fn spawn_shutdown_signal_handler() {
     tokio::spawn(async move {
         tokio::signal::ctrl_c().await.expect("Error setting Ctrl-C handler");
         info!("Received Ctrl-C, initiating graceful shutdown");
+        SHUTTING_DOWN.store(true, Ordering::Relaxed);
         CONFIG.shutdown();
     });
 }
 
+// Set once a shutdown has been requested, no new jobs will be started after this
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+// Number of scheduled jobs currently running
+static RUNNING_JOBS: AtomicUsize = AtomicUsize::new(0);
+
+struct RunningJobGuard;
+
+impl RunningJobGuard {
+    fn new() -> Self {
+        RUNNING_JOBS.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for RunningJobGuard {
+    fn drop(&mut self) {
+        RUNNING_JOBS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Waits for the running background jobs to finish, for at most `SHUTDOWN_DRAIN_TIMEOUT` seconds.
+async fn drain_running_jobs() {
+    let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_secs(CONFIG.shutdown_drain_timeout());
+    let mut logged = false;
+    loop {
+        let running = RUNNING_JOBS.load(Ordering::Relaxed);
+        if running == 0 {
+            return;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            warn!("Shutting down with {running} background job(s) still running");
+            return;
+        }
+        // Only the first wait is worth an info message, the progress is logged every 500 ms
+        if logged {
+            debug!("Waiting for {running} background job(s) to finish");
+        } else {
+            info!("Waiting for {running} background job(s) to finish");
+            logged = true;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    }
+}
+
+// Jobs working on the local files of an instance, every instance sharing the database needs to run them itself
+static ACME_RENEW_JOB: LazyLock<String> = LazyLock::new(|| instance_job_name("acme_renew"));
+static TMP_CLEANUP_JOB: LazyLock<String> = LazyLock::new(|| instance_job_name("tmp_cleanup"));
+
+fn instance_job_name(job: &str) -> String {
+    format!("{job}@{}", db::models::JobLock::holder_id())
+}
+
+/// Runs a scheduled job after waiting a random delay of up to `JOB_START_JITTER` seconds.
+/// The job is skipped if it is still running, here or on another instance sharing the same database.
+/// While the job runs its lock is renewed, so long running jobs don't lose it to another instance.
+async fn run_job<F: Future>(name: &'static str, pool: db::DbPool, job: F) {
+    use db::models::JobLock;
+
+    let jitter = CONFIG.job_start_jitter();
+    if jitter > 0 {
+        use rand::{RngExt, rngs::SmallRng};
+        let mut rng: SmallRng = rand::make_rng();
+        let delay: u64 = rng.random_range(0..=jitter);
+        tokio::time::sleep(tokio::time::Duration::from_secs(delay)).await;
+    }
+
+    // Don't start anything new once a shutdown was requested
+    if SHUTTING_DOWN.load(Ordering::Relaxed) {
+        return;
+    }
+    let _running = RunningJobGuard::new();
+
+    let lock_timeout = CONFIG.job_lock_timeout();
+    match pool.get().await {
+        Ok(conn) => {
+            if !JobLock::try_acquire(name, lock_timeout, &conn).await {
+                debug!("Job `{name}` is already running, skipping this run");
+                return;
+            }
+        }
+        Err(e) => {
+            warn!("Unable to get a database connection to lock job `{name}`: {e:?}");
+            return;
+        }
+    }
+
+    let mut job = std::pin::pin!(job);
+    let mut renew_interval = tokio::time::interval(tokio::time::Duration::from_secs(lock_timeout / 3));
+    renew_interval.tick().await; // The first tick completes immediately
+    loop {
+        tokio::select! {
+            _ = &mut job => break,
+            _ = renew_interval.tick() => {
+                if let Ok(conn) = pool.get().await
+                    && !JobLock::renew(name, lock_timeout, &conn).await
+                {
+                    warn!("Lost the lock of job `{name}`, another instance might run it concurrently");
+                }
+            }
+        }
+    }
+
+    match pool.get().await {
+        Ok(conn) => {
+            if let Err(e) = JobLock::release(name, &conn).await {
+                warn!("Unable to release the lock of job `{name}`: {e:?}");
+            }
+        }
+        Err(e) => warn!("Unable to get a database connection to release job `{name}`: {e:?}"),
+    }
+}
+
+async fn db_backup_job() {
+    match tokio::task::spawn_blocking(db::backup_sqlite).await {
+        Ok(Ok(f)) => info!("Scheduled backup to '{f}' was successful"),
+        Ok(Err(e)) => error!("Scheduled backup failed. {e:?}"),
+        Err(e) => error!("Scheduled backup task failed. {e:?}"),
+    }
+}
+
 fn schedule_jobs(pool: db::DbPool) {
     if CONFIG.job_poll_interval_ms() == 0 {
         info!("Job scheduler disabled.");
@@ -679,14 +947,21 @@ fn schedule_jobs(pool: db::DbPool) {
             // Purge sends that are past their deletion date.
             if !CONFIG.send_purge_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.send_purge_schedule().parse().unwrap(), || {
-                    runtime.spawn(api::purge_sends(pool.clone()));
+                    runtime.spawn(run_job("send_purge", pool.clone(), api::purge_sends(pool.clone())));
                 }));
             }
 
             // Purge trashed items that are old enough to be auto-deleted.
             if !CONFIG.trash_purge_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.trash_purge_schedule().parse().unwrap(), || {
-                    runtime.spawn(api::purge_trashed_ciphers(pool.clone()));
+                    runtime.spawn(run_job("trash_purge", pool.clone(), api::purge_trashed_ciphers(pool.clone())));
+                }));
+            }
+
+            // Invite the users of the LDAP directory and revoke the removed ones.
+            if CONFIG.ldap_sync_enabled() && !CONFIG.ldap_sync_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.ldap_sync_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job("ldap_sync", pool.clone(), api::ldap_sync_job(pool.clone())));
                 }));
             }
 
@@ -694,7 +969,11 @@ fn schedule_jobs(pool: db::DbPool) {
             // indicates that a user's master password has been compromised.
             if !CONFIG.incomplete_2fa_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.incomplete_2fa_schedule().parse().unwrap(), || {
-                    runtime.spawn(api::send_incomplete_2fa_notifications(pool.clone()));
+                    runtime.spawn(run_job(
+                        "incomplete_2fa",
+                        pool.clone(),
+                        api::send_incomplete_2fa_notifications(pool.clone()),
+                    ));
                 }));
             }
 
@@ -703,7 +982,11 @@ fn schedule_jobs(pool: db::DbPool) {
             // sending reminders for requests that are about to be granted anyway.
             if !CONFIG.emergency_request_timeout_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.emergency_request_timeout_schedule().parse().unwrap(), || {
-                    runtime.spawn(api::emergency_request_timeout_job(pool.clone()));
+                    runtime.spawn(run_job(
+                        "emergency_request_timeout",
+                        pool.clone(),
+                        api::emergency_request_timeout_job(pool.clone()),
+                    ));
                 }));
             }
 
@@ -711,20 +994,100 @@ fn schedule_jobs(pool: db::DbPool) {
             // emergency access requests.
             if !CONFIG.emergency_notification_reminder_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.emergency_notification_reminder_schedule().parse().unwrap(), || {
-                    runtime.spawn(api::emergency_notification_reminder_job(pool.clone()));
+                    runtime.spawn(run_job(
+                        "emergency_notification_reminder",
+                        pool.clone(),
+                        api::emergency_notification_reminder_job(pool.clone()),
+                    ));
                 }));
             }
 
             if !CONFIG.auth_request_purge_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.auth_request_purge_schedule().parse().unwrap(), || {
-                    runtime.spawn(purge_auth_requests(pool.clone()));
+                    runtime.spawn(run_job("auth_request_purge", pool.clone(), purge_auth_requests(pool.clone())));
+                }));
+            }
+
+            // Remove rejected push tokens and devices which weren't used for x days.
+            if !CONFIG.device_cleanup_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.device_cleanup_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job("device_cleanup", pool.clone(), api::device_cleanup_job(pool.clone())));
+                }));
+            }
+
+            // Remove the accounts which didn't verify their email address in time.
+            if !CONFIG.unverified_users_purge_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.unverified_users_purge_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job(
+                        "unverified_users_purge",
+                        pool.clone(),
+                        api::purge_unverified_users(pool.clone()),
+                    ));
+                }));
+            }
+
+            // Remind members without 2FA and revoke them when the grace period of the 2FA policy has passed.
+            if !CONFIG.two_factor_grace_period_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.two_factor_grace_period_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job(
+                        "two_factor_grace_period",
+                        pool.clone(),
+                        api::enforce_2fa_grace_periods(pool.clone()),
+                    ));
+                }));
+            }
+
+            // Bump the revision date of users which is older than the newest change of their items.
+            if !CONFIG.revision_reconcile_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.revision_reconcile_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job(
+                        "revision_reconcile",
+                        pool.clone(),
+                        api::reconcile_revision_dates(pool.clone()),
+                    ));
+                }));
+            }
+
+            // Warn, disable or delete the accounts without a login for a number of months.
+            if !CONFIG.inactive_users_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.inactive_users_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job("inactive_users", pool.clone(), api::inactive_users_job(pool.clone())));
+                }));
+            }
+
+            // Remove the collection access of auditors once their grant expires.
+            if !CONFIG.audit_grant_expiry_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.audit_grant_expiry_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job("audit_grant_expiry", pool.clone(), api::expire_audit_grants(pool.clone())));
+                }));
+            }
+
+            // Warn the users and organizations which almost used up their attachment storage.
+            if !CONFIG.attachment_quota_warning_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.attachment_quota_warning_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job(
+                        "attachment_quota_warning",
+                        pool.clone(),
+                        api::attachment_quota_warning_job(pool.clone()),
+                    ));
+                }));
+            }
+
+            // Confirm the uploads of the attachments which were re-encrypted after a key rotation.
+            if !CONFIG.attachment_rekey_verification_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.attachment_rekey_verification_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job(
+                        "attachment_rekey_verification",
+                        pool.clone(),
+                        api::verify_attachment_rekeys(pool.clone()),
+                    ));
                 }));
             }
 
             // Clean unused, expired Duo authentication contexts.
             if !CONFIG.duo_context_purge_schedule().is_empty() && CONFIG._enable_duo() && !CONFIG.duo_use_iframe() {
                 sched.add(Job::new(CONFIG.duo_context_purge_schedule().parse().unwrap(), || {
-                    runtime.spawn(purge_duo_contexts(pool.clone()));
+                    runtime.spawn(run_job("duo_context_purge", pool.clone(), purge_duo_contexts(pool.clone())));
                 }));
             }
 
@@ -734,17 +1097,69 @@ fn schedule_jobs(pool: db::DbPool) {
                 && CONFIG.events_days_retain().is_some()
             {
                 sched.add(Job::new(CONFIG.event_cleanup_schedule().parse().unwrap(), || {
-                    runtime.spawn(api::event_cleanup_job(pool.clone()));
+                    runtime.spawn(run_job("event_cleanup", pool.clone(), api::event_cleanup_job(pool.clone())));
                 }));
             }
 
             // Purge sso auth from incomplete flow (default to daily at 00h20).
             if !CONFIG.purge_incomplete_sso_auth().is_empty() {
                 sched.add(Job::new(CONFIG.purge_incomplete_sso_auth().parse().unwrap(), || {
-                    runtime.spawn(db::models::SsoAuth::delete_expired(pool.clone()));
+                    runtime.spawn(run_job(
+                        "purge_incomplete_sso_auth",
+                        pool.clone(),
+                        db::models::SsoAuth::delete_expired(pool.clone()),
+                    ));
+                }));
+            }
+
+            // Remove stale partial uploads from the temp and quarantine folders.
+            if !CONFIG.tmp_cleanup_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.tmp_cleanup_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job(&TMP_CLEANUP_JOB, pool.clone(), storage::purge_stale_tmp_files()));
                 }));
             }
 
+            // Store the daily usage totals.
+            if !CONFIG.usage_snapshot_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.usage_snapshot_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job("usage_snapshot", pool.clone(), api::usage_snapshot_job(pool.clone())));
+                }));
+            }
+
+            // Post the opt-in usage statistics, the job checks the URL itself as it can be set from the admin page.
+            if !CONFIG.usage_statistics_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.usage_statistics_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job(
+                        "usage_statistics",
+                        pool.clone(),
+                        telemetry::usage_statistics_job(pool.clone()),
+                    ));
+                }));
+            }
+
+            // Renew the ACME certificate when it expires soon.
+            if CONFIG.acme_enabled() && !CONFIG.acme_renew_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.acme_renew_schedule().parse().unwrap(), || {
+                    runtime.spawn(run_job(&ACME_RENEW_JOB, pool.clone(), acme::renew_job()));
+                }));
+            }
+
+            // Create a backup of the SQLite database.
+            if !CONFIG.db_backup_schedule().is_empty() {
+                #[cfg(sqlite)]
+                let is_sqlite = db::ACTIVE_DB_TYPE.get() == Some(&db::DbConnType::Sqlite);
+                #[cfg(not(sqlite))]
+                let is_sqlite = false;
+
+                if is_sqlite {
+                    sched.add(Job::new(CONFIG.db_backup_schedule().parse().unwrap(), || {
+                        runtime.spawn(run_job("db_backup", pool.clone(), db_backup_job()));
+                    }));
+                } else {
+                    warn!("DB_BACKUP_SCHEDULE is set, but only SQLite databases can be backed up.");
+                }
+            }
+
             // Periodically check for jobs to run. We probably won't need any
             // jobs that run more often than once a minute, so a default poll
             // interval of 30 seconds should be sufficient. Users who want to
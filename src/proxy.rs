@@ -0,0 +1,489 @@
+//
+// Reverse proxy support
+//
+// The IP of the client is taken from `IP_HEADER` when the request comes from one of the `TRUSTED_PROXIES`.
+// Every proxy appends the address it received the request from, so the header is read from right to left,
+// and the first address which isn't a trusted proxy is the client. Without `TRUSTED_PROXIES` the first address
+// in the header is used, which trusts whatever the client sends if the proxy doesn't overwrite the header.
+//
+// Load balancers which can't add headers can use the PROXY protocol instead.
+// Connections to `PROXY_PROTOCOL_LISTEN` need to start with a PROXY header (v1 or v2) and are forwarded to Rocket,
+// the address of the client is remembered for the forwarded connection until it is closed.
+//
+// Rocket can only listen on a TCP port. To listen on `UNIX_SOCKET_PATH`, or on the socket received from systemd with
+// `SOCKET_ACTIVATION`, Rocket listens on a random port of the loopback interface and the connections are forwarded to it.
+// Requests which reach that port without going through the socket are refused by the `ForwardedOnly` fairing,
+// otherwise every local user could bypass the permissions of the socket and choose the IP of the client.
+//
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{LazyLock, Mutex, RwLock},
+    time::Duration,
+};
+
+use rocket::{
+    Data, Request, Route,
+    fairing::{Fairing, Info, Kind},
+    http::{Method, Status, uri::Origin},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, copy_bidirectional},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::CONFIG;
+
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl IpNet {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse::<u32>().ok()?)),
+            None => (value.parse::<IpAddr>().ok()?, None),
+        };
+        let max_prefix = if addr.is_ipv4() {
+            32
+        } else {
+            128
+        };
+        let prefix = prefix.unwrap_or(max_prefix);
+        (prefix <= max_prefix).then_some(Self {
+            addr,
+            prefix,
+        })
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(u128::from(u32::from(net)), u128::from(u32::from(ip)), 32 - self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => prefix_matches(u128::from(net), u128::from(ip), 128 - self.prefix),
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(net: u128, ip: u128, host_bits: u32) -> bool {
+    net.checked_shr(host_bits).unwrap_or(0) == ip.checked_shr(host_bits).unwrap_or(0)
+}
+
+static TRUSTED_PROXIES: LazyLock<Vec<IpNet>> = LazyLock::new(|| {
+    CONFIG.trusted_proxies().split(',').map(str::trim).filter(|p| !p.is_empty()).filter_map(IpNet::parse).collect()
+});
+
+/// Used by the config validation of `TRUSTED_PROXIES` and `TRUSTED_NETWORKS`, accepts an IP or a CIDR range
+pub fn is_valid_proxy(value: &str) -> bool {
+    IpNet::parse(value).is_some()
+}
+
+/// Whether `ip` is one of the `TRUSTED_PROXIES`, nobody is trusted when those aren't set
+pub fn is_trusted_proxy(ip: &IpAddr) -> bool {
+    TRUSTED_PROXIES.iter().any(|net| net.contains(ip))
+}
+
+// Proxies sometimes add the port as well
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    hop.parse::<IpAddr>().ok().or_else(|| hop.parse::<SocketAddr>().ok().map(|s| s.ip()))
+}
+
+/// Resolves the IP of the client, based on the peer of the connection and the value of `IP_HEADER`
+pub fn resolve_client_ip(peer: IpAddr, header: Option<&str>) -> IpAddr {
+    let Some(header) = header else {
+        return peer;
+    };
+
+    if TRUSTED_PROXIES.is_empty() {
+        let first = header.split(',').next().unwrap_or_default().trim();
+        return parse_hop(first).unwrap_or_else(|| {
+            warn!("'{}' header is malformed: {header}", CONFIG.ip_header());
+            peer
+        });
+    }
+
+    // Anyone else could send any value in the header
+    if !is_trusted_proxy(&peer) {
+        return peer;
+    }
+
+    let mut client = peer;
+    for hop in header.rsplit(',').map(str::trim) {
+        let Some(ip) = parse_hop(hop) else {
+            warn!("'{}' header is malformed: {header}", CONFIG.ip_header());
+            break;
+        };
+        client = ip;
+        if !is_trusted_proxy(&ip) {
+            break;
+        }
+    }
+    client
+}
+
+// Local address of every forwarded connection, mapped to the client IP of the PROXY header
+static PROXIED_PEERS: LazyLock<Mutex<HashMap<SocketAddr, IpAddr>>> = LazyLock::new(Default::default);
+
+/// Returns the client IP of the PROXY header, if the request came in through the PROXY protocol listener
+pub fn proxied_peer(remote: &SocketAddr) -> Option<IpAddr> {
+    PROXIED_PEERS.lock().ok()?.get(remote).copied()
+}
+
+// Address Rocket listens on, the forwarded connections are sent there
+static ROCKET_ADDRESS: RwLock<Option<SocketAddr>> = RwLock::new(None);
+
+/// Called once Rocket is listening, the port is only known then when it was chosen by the OS
+pub fn set_rocket_address(address: SocketAddr) {
+    let ip = match address.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    if let Ok(mut rocket_address) = ROCKET_ADDRESS.write() {
+        *rocket_address = Some(SocketAddr::new(ip, address.port()));
+    }
+}
+
+fn rocket_address() -> io::Result<SocketAddr> {
+    ROCKET_ADDRESS
+        .read()
+        .ok()
+        .and_then(|address| *address)
+        .ok_or_else(|| io::Error::other("Rocket isn't listening yet"))
+}
+
+/// Returns true when Rocket should only listen on the loopback interface, because the clients use another socket
+pub fn forwards_all_connections() -> bool {
+    CONFIG.unix_socket_path().is_some() || CONFIG.socket_activation()
+}
+
+pub async fn start_proxy_protocol_listener(listen: String) {
+    let listener = match TcpListener::bind(&listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Unable to listen for PROXY protocol connections on {listen}: {e}");
+            return;
+        }
+    };
+    info!("Accepting PROXY protocol connections on {listen}");
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Error accepting PROXY protocol connection: {e}");
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            if let Err(e) = forward_connection(stream, peer).await {
+                debug!("PROXY protocol connection from {peer} closed: {e}");
+            }
+        });
+    }
+}
+
+async fn forward_connection(mut inbound: TcpStream, peer: SocketAddr) -> io::Result<()> {
+    // Otherwise every client which can reach the listener could choose its own IP
+    if !TRUSTED_PROXIES.is_empty() && !is_trusted_proxy(&peer.ip()) {
+        return Err(io::Error::other("peer is not a trusted proxy"));
+    }
+
+    let client = tokio::time::timeout(Duration::from_secs(5), read_proxy_header(&mut inbound))
+        .await
+        .map_err(|_| io::Error::other("timeout reading the PROXY header"))??;
+
+    let mut outbound = TcpStream::connect(rocket_address()?).await?;
+    let local = outbound.local_addr()?;
+    if let Some(client) = client
+        && let Ok(mut peers) = PROXIED_PEERS.lock()
+    {
+        peers.insert(local, client);
+    }
+
+    let result = copy_bidirectional(&mut inbound, &mut outbound).await;
+
+    if let Ok(mut peers) = PROXIED_PEERS.lock() {
+        peers.remove(&local);
+    }
+    result.map(|_| ())
+}
+
+// Local address of every connection forwarded from the Unix socket or the activated socket
+static FORWARDED_CONNECTIONS: LazyLock<Mutex<HashSet<SocketAddr>>> = LazyLock::new(Default::default);
+
+// Forwards a connection of the Unix socket or of the activated socket as is
+async fn forward_stream<S: AsyncRead + AsyncWrite + Unpin>(mut inbound: S) -> io::Result<()> {
+    let mut outbound = TcpStream::connect(rocket_address()?).await?;
+    let local = outbound.local_addr()?;
+    if let Ok(mut connections) = FORWARDED_CONNECTIONS.lock() {
+        connections.insert(local);
+    }
+
+    let result = copy_bidirectional(&mut inbound, &mut outbound).await;
+
+    if let Ok(mut connections) = FORWARDED_CONNECTIONS.lock() {
+        connections.remove(&local);
+    }
+    result.map(|_| ())
+}
+
+fn is_forwarded(remote: &SocketAddr) -> bool {
+    FORWARDED_CONNECTIONS.lock().is_ok_and(|connections| connections.contains(remote))
+}
+
+const REFUSED_PATH: &str = "/__vaultwarden/not-forwarded";
+
+/// Refuses the requests which didn't come through the Unix socket or the activated socket,
+/// only attached when Rocket listens on the loopback interface for those
+pub struct ForwardedOnly;
+
+#[rocket::async_trait]
+impl Fairing for ForwardedOnly {
+    fn info(&self) -> Info {
+        Info {
+            name: "Forwarded connections only",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        if request.remote().is_some_and(|remote| is_forwarded(&remote)) {
+            return;
+        }
+        warn!("Refused a request to {} which didn't come through the socket", request.uri().path());
+        // A fairing can't respond itself, the request is sent to a route which refuses it instead
+        request.set_method(Method::Get);
+        request.set_uri(Origin::parse(REFUSED_PATH).expect("valid path"));
+    }
+}
+
+pub fn forwarded_only_routes() -> Vec<Route> {
+    routes![not_forwarded]
+}
+
+#[get("/__vaultwarden/not-forwarded")]
+fn not_forwarded() -> Status {
+    Status::Forbidden
+}
+
+#[cfg(unix)]
+pub async fn start_unix_listener(path: String) {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    // A socket left behind by a previous run would make the bind fail
+    if std::fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        let _ = std::fs::remove_file(&path);
+    }
+    let listener = match tokio::net::UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Unable to listen on the Unix socket {path}: {e}");
+            return;
+        }
+    };
+    // Validated by the config
+    let mode = u32::from_str_radix(&CONFIG.unix_socket_mode(), 8).unwrap_or(0o660);
+    if let Err(e) = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)) {
+        warn!("Unable to set the permissions of the Unix socket {path}: {e}");
+    }
+    info!("Listening on the Unix socket {path}");
+
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        tokio::spawn(async move {
+            if let Err(e) = forward_stream(stream).await {
+                debug!("Unix socket connection closed: {e}");
+            }
+        });
+    }
+}
+
+/// Uses the listening socket systemd passes to the service, as described in `sd_listen_fds(3)`.
+/// The socket can be a TCP or a Unix socket.
+#[cfg(unix)]
+pub async fn start_activated_listener() {
+    use std::os::unix::net::UnixListener;
+
+    let fd = match take_activated_fd() {
+        Ok(fd) => fd,
+        Err(e) => {
+            error!("Unable to use the socket passed by systemd: {e}");
+            return;
+        }
+    };
+
+    // The type of the socket is only known from its address
+    let unix_listener = UnixListener::from(fd);
+    if unix_listener.local_addr().is_ok() {
+        match unix_listener.set_nonblocking(true).and_then(|()| tokio::net::UnixListener::from_std(unix_listener)) {
+            Ok(listener) => {
+                info!("Listening on the Unix socket passed by systemd");
+                loop {
+                    let Ok((stream, _)) = listener.accept().await else {
+                        continue;
+                    };
+                    tokio::spawn(async move {
+                        if let Err(e) = forward_stream(stream).await {
+                            debug!("Activated socket connection closed: {e}");
+                        }
+                    });
+                }
+            }
+            Err(e) => error!("Unable to use the socket passed by systemd: {e}"),
+        }
+        return;
+    }
+
+    let tcp_listener = std::net::TcpListener::from(std::os::fd::OwnedFd::from(unix_listener));
+    match tcp_listener.local_addr().and_then(|address| {
+        tcp_listener.set_nonblocking(true)?;
+        Ok((address, TcpListener::from_std(tcp_listener)?))
+    }) {
+        Ok((address, listener)) => {
+            info!("Listening on {address}, the socket passed by systemd");
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+                tokio::spawn(async move {
+                    if let Err(e) = forward_stream(stream).await {
+                        debug!("Activated socket connection closed: {e}");
+                    }
+                });
+            }
+        }
+        Err(e) => error!("The socket passed by systemd isn't a listening socket: {e}"),
+    }
+}
+
+// The first socket passed by systemd, the others are ignored
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+#[cfg(unix)]
+fn take_activated_fd() -> io::Result<std::os::fd::OwnedFd> {
+    use std::os::fd::FromRawFd;
+
+    // The variables could have been inherited from a parent process, they are only meant for us when the PID matches
+    let listen_pid = std::env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+    if listen_pid != Some(std::process::id()) {
+        return Err(io::Error::other("`LISTEN_PID` is missing or isn't the PID of this process"));
+    }
+    let listen_fds = std::env::var("LISTEN_FDS").ok().and_then(|fds| fds.parse::<i32>().ok()).unwrap_or(0);
+    if listen_fds < 1 {
+        return Err(io::Error::other("`LISTEN_FDS` is missing, check the socket unit of the service"));
+    }
+    if listen_fds > 1 {
+        warn!("systemd passed {listen_fds} sockets, only the first one is used");
+    }
+
+    // SAFETY: systemd passes the sockets starting at file descriptor 3 to the process of `LISTEN_PID`, which was
+    // checked above. The descriptor isn't used anywhere else and this function is only called once at startup.
+    #[allow(unsafe_code)]
+    let fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(SD_LISTEN_FDS_START) };
+    Ok(fd)
+}
+
+const PROXY_V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+// Longest possible v1 header, including the CRLF
+const PROXY_V1_MAX_LEN: usize = 107;
+
+// Reads exactly the PROXY header, the rest of the stream is forwarded as is.
+// Returns `None` for health checks of the proxy itself, which use the `LOCAL` or `UNKNOWN` variants.
+async fn read_proxy_header<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<IpAddr>> {
+    let mut start = [0u8; 12];
+    stream.read_exact(&mut start).await?;
+
+    if start == PROXY_V2_SIGNATURE {
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header).await?;
+        let [version_command, family, len_hi, len_lo] = header;
+        let mut addresses = vec![0u8; usize::from(u16::from_be_bytes([len_hi, len_lo]))];
+        stream.read_exact(&mut addresses).await?;
+
+        if version_command >> 4 != 2 {
+            return Err(io::Error::other("unsupported PROXY protocol version"));
+        }
+        // LOCAL connections are from the proxy itself
+        if version_command & 0x0F == 0 {
+            return Ok(None);
+        }
+        return Ok(match family >> 4 {
+            1 => addresses.first_chunk::<4>().map(|ip| IpAddr::V4(Ipv4Addr::from(*ip))),
+            2 => addresses.first_chunk::<16>().map(|ip| IpAddr::V6(Ipv6Addr::from(*ip))),
+            _ => None,
+        });
+    }
+
+    if !start.starts_with(b"PROXY ") {
+        return Err(io::Error::other("connection didn't start with a PROXY header"));
+    }
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= PROXY_V1_MAX_LEN {
+            return Err(io::Error::other("PROXY header is too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = String::from_utf8_lossy(&line);
+    let mut parts = line.trim_end().split(' ').skip(1);
+    match (parts.next(), parts.next()) {
+        (Some("TCP4" | "TCP6"), Some(source)) => {
+            source.parse().map(Some).map_err(|_| io::Error::other("invalid source address in the PROXY header"))
+        }
+        (Some("UNKNOWN"), _) => Ok(None),
+        _ => Err(io::Error::other("invalid PROXY header")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_net() {
+        let net = IpNet::parse("10.1.0.0/16").unwrap();
+        assert!(net.contains(&"10.1.2.3".parse().unwrap()));
+        assert!(net.contains(&"::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!net.contains(&"10.2.0.1".parse().unwrap()));
+
+        let net = IpNet::parse("fd00::/8").unwrap();
+        assert!(net.contains(&"fd12::1".parse().unwrap()));
+        assert!(!net.contains(&"fe80::1".parse().unwrap()));
+
+        assert!(IpNet::parse("0.0.0.0/0").unwrap().contains(&"1.2.3.4".parse().unwrap()));
+        assert!(IpNet::parse("10.0.0.0/33").is_none());
+        assert!(IpNet::parse("proxy").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_proxy_header_v1() {
+        let mut stream: &[u8] = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET / HTTP/1.1\r\n";
+        assert_eq!(read_proxy_header(&mut stream).await.unwrap(), Some("192.0.2.1".parse().unwrap()));
+        assert_eq!(stream, b"GET / HTTP/1.1\r\n");
+
+        let mut stream: &[u8] = b"PROXY UNKNOWN\r\n";
+        assert_eq!(read_proxy_header(&mut stream).await.unwrap(), None);
+
+        let mut stream: &[u8] = b"GET / HTTP/1.1\r\n";
+        assert!(read_proxy_header(&mut stream).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_proxy_header_v2() {
+        let mut header = PROXY_V2_SIGNATURE.to_vec();
+        header.extend([0x21, 0x11, 0, 12, 192, 0, 2, 1, 198, 51, 100, 1, 0xDC, 0x04, 0x01, 0xBB]);
+        header.extend(b"GET");
+        let mut stream = header.as_slice();
+        assert_eq!(read_proxy_header(&mut stream).await.unwrap(), Some("192.0.2.1".parse().unwrap()));
+        assert_eq!(stream, b"GET");
+    }
+}
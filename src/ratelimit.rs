@@ -1,8 +1,17 @@
-use std::{net::IpAddr, num::NonZeroU32, sync::LazyLock, time::Duration};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    num::NonZeroU32,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
 use governor::{Quota, RateLimiter, clock::DefaultClock, state::keyed::DashMapStateStore};
 
-use crate::{CONFIG, Error};
+use crate::{CONFIG, Error, db::models::UserId, error::ErrorCode, proxy::IpNet};
 
 type Limiter<T = IpAddr> = RateLimiter<T, DashMapStateStore<T>, DefaultClock>;
 
@@ -18,7 +27,29 @@ static LIMITER_ADMIN: LazyLock<Limiter> = LazyLock::new(|| {
     RateLimiter::keyed(Quota::with_period(seconds).expect("Non-zero admin ratelimit seconds").allow_burst(burst))
 });
 
+static LIMITER_ATTACHMENT_SHARE: LazyLock<Limiter> = LazyLock::new(|| {
+    let seconds = Duration::from_secs(CONFIG.attachment_share_ratelimit_seconds());
+    let burst = NonZeroU32::new(CONFIG.attachment_share_ratelimit_max_burst())
+        .expect("Non-zero attachment share ratelimit burst");
+    RateLimiter::keyed(
+        Quota::with_period(seconds).expect("Non-zero attachment share ratelimit seconds").allow_burst(burst),
+    )
+});
+
+static TRUSTED_NETWORKS: LazyLock<Vec<IpNet>> = LazyLock::new(|| {
+    CONFIG.trusted_networks().split(',').map(str::trim).filter(|n| !n.is_empty()).filter_map(IpNet::parse).collect()
+});
+
+/// Whether the IP is in one of the `TRUSTED_NETWORKS`, which are exempt from the login rate limit and new device emails
+pub fn is_trusted_network(ip: &IpAddr) -> bool {
+    TRUSTED_NETWORKS.iter().any(|net| net.contains(ip))
+}
+
 pub fn check_limit_login(ip: &IpAddr) -> Result<(), Error> {
+    if is_trusted_network(ip) {
+        debug!("Login request from {ip} is exempt from the rate limit, it is in TRUSTED_NETWORKS");
+        return Ok(());
+    }
     match LIMITER_LOGIN.check_key(ip) {
         Ok(()) => Ok(()),
         Err(_e) => {
@@ -35,3 +66,164 @@ pub fn check_limit_admin(ip: &IpAddr) -> Result<(), Error> {
         }
     }
 }
+
+pub fn check_limit_attachment_share(ip: &IpAddr) -> Result<(), Error> {
+    match LIMITER_ATTACHMENT_SHARE.check_key(ip) {
+        Ok(()) => Ok(()),
+        Err(_e) => {
+            err_code!("Too many shared attachment downloads", 429);
+        }
+    }
+}
+
+// Failed admin token attempts of an IP, counted over `ADMIN_LOCKOUT_MINUTES`
+struct AdminLoginFailures {
+    count: u32,
+    first_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl AdminLoginFailures {
+    fn new(now: Instant) -> Self {
+        Self {
+            count: 0,
+            first_failure: now,
+            locked_until: None,
+        }
+    }
+
+    fn is_locked(&self, now: Instant) -> bool {
+        self.locked_until.is_some_and(|until| until > now)
+    }
+}
+
+static ADMIN_LOGIN_FAILURES: LazyLock<Mutex<HashMap<IpAddr, AdminLoginFailures>>> = LazyLock::new(Default::default);
+// Totals since startup, shown in the diagnostics
+static ADMIN_LOGIN_FAILURES_TOTAL: AtomicU64 = AtomicU64::new(0);
+static ADMIN_LOCKOUTS_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+fn admin_lockout_duration() -> Duration {
+    Duration::from_secs(CONFIG.admin_lockout_minutes().saturating_mul(60))
+}
+
+pub fn check_admin_lockout(ip: &IpAddr) -> Result<(), Error> {
+    let now = Instant::now();
+    if ADMIN_LOGIN_FAILURES.lock().is_ok_and(|failures| failures.get(ip).is_some_and(|f| f.is_locked(now))) {
+        err_code!("The admin panel is locked after too many failed attempts", 429);
+    }
+    Ok(())
+}
+
+/// Counts a failed admin token attempt, returns true when the IP got locked out by this attempt
+pub fn record_admin_login_failure(ip: &IpAddr) -> bool {
+    ADMIN_LOGIN_FAILURES_TOTAL.fetch_add(1, Ordering::Relaxed);
+
+    let max_attempts = CONFIG.admin_lockout_attempts();
+    if max_attempts == 0 {
+        return false;
+    }
+    let Ok(mut failures) = ADMIN_LOGIN_FAILURES.lock() else {
+        return false;
+    };
+
+    let window = admin_lockout_duration();
+    let now = Instant::now();
+    // Forget the IPs which stopped trying
+    if failures.len() >= 10_000 {
+        failures.retain(|_, f| now.duration_since(f.first_failure) < window || f.is_locked(now));
+    }
+
+    let entry = failures.entry(*ip).or_insert_with(|| AdminLoginFailures::new(now));
+    if now.duration_since(entry.first_failure) >= window && !entry.is_locked(now) {
+        *entry = AdminLoginFailures::new(now);
+    }
+    entry.count = entry.count.saturating_add(1);
+    if entry.count >= max_attempts && entry.locked_until.is_none() {
+        entry.locked_until = Some(now + window);
+        ADMIN_LOCKOUTS_TOTAL.fetch_add(1, Ordering::Relaxed);
+        return true;
+    }
+    false
+}
+
+pub fn clear_admin_login_failures(ip: &IpAddr) {
+    if let Ok(mut failures) = ADMIN_LOGIN_FAILURES.lock() {
+        failures.remove(ip);
+    }
+}
+
+/// Returns the failed admin token attempts and the lockouts since startup, and the IPs locked out right now
+pub fn admin_login_failure_stats() -> (u64, u64, Vec<IpAddr>) {
+    let now = Instant::now();
+    let locked = ADMIN_LOGIN_FAILURES
+        .lock()
+        .map(|failures| failures.iter().filter(|(_, f)| f.is_locked(now)).map(|(ip, _)| *ip).collect())
+        .unwrap_or_default();
+    (ADMIN_LOGIN_FAILURES_TOTAL.load(Ordering::Relaxed), ADMIN_LOCKOUTS_TOTAL.load(Ordering::Relaxed), locked)
+}
+
+// Items created by a user in the current hour, for `USER_ITEM_HOURLY_LIMIT`
+static ITEMS_CREATED: LazyLock<Mutex<HashMap<UserId, (Instant, u64)>>> = LazyLock::new(Default::default);
+const ITEMS_CREATED_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Items counted against the hourly limit of a user, which are given back when this is dropped before `keep`.
+/// This way a request failing to create its items doesn't use up the limit.
+#[derive(Default)]
+#[must_use]
+pub struct ItemCreationCharge {
+    user_id: Option<UserId>,
+    items: u64,
+}
+
+impl ItemCreationCharge {
+    /// Keeps the items counted, once they are created
+    pub fn keep(mut self) {
+        self.user_id = None;
+    }
+
+    /// Keeps `items` of the items counted, for requests which create them one at a time
+    pub fn created(&mut self, items: u64) {
+        self.items = self.items.saturating_sub(items);
+    }
+}
+
+impl Drop for ItemCreationCharge {
+    fn drop(&mut self) {
+        if let Some(user_id) = self.user_id.take()
+            && let Ok(mut created) = ITEMS_CREATED.lock()
+            && let Some(entry) = created.get_mut(&user_id)
+        {
+            entry.1 = entry.1.saturating_sub(self.items);
+        }
+    }
+}
+
+/// Counts the items the user is about to create, and refuses them when the hourly limit would be exceeded
+pub fn check_limit_item_creation(user_id: &UserId, items: u64) -> Result<ItemCreationCharge, Error> {
+    let Some(limit) = CONFIG.user_item_hourly_limit() else {
+        return Ok(ItemCreationCharge::default());
+    };
+    let Ok(mut created) = ITEMS_CREATED.lock() else {
+        return Ok(ItemCreationCharge::default());
+    };
+
+    let now = Instant::now();
+    if created.len() >= 10_000 {
+        created.retain(|_, (start, _)| now.duration_since(*start) < ITEMS_CREATED_WINDOW);
+    }
+
+    let entry = created.entry(user_id.clone()).or_insert((now, 0));
+    if now.duration_since(entry.0) >= ITEMS_CREATED_WINDOW {
+        *entry = (now, 0);
+    }
+    if entry.1.saturating_add(items) > limit {
+        return Err(Error::new_msg(format!("No more than {limit} items can be created per hour"))
+            .with_code(429)
+            .with_error_code(ErrorCode::QuotaExceeded));
+    }
+    entry.1 = entry.1.saturating_add(items);
+    Ok(ItemCreationCharge {
+        user_id: Some(user_id.clone()),
+        items,
+    })
+}
@@ -0,0 +1,431 @@
+//
+// Disaster recovery bundles
+//
+// A bundle is a plain tar archive containing everything needed to bring an instance back:
+// - `manifest.json`        Metadata about the bundle and what it contains
+// - `db.sqlite3`           A consistent copy of the database (SQLite only)
+// - `attachments/...`      All attachment files
+// - `sends/...`            All Send files
+// - `icon_cache.json`      A list of the cached icons, the icons themselves are fetched again when needed
+// - `config.json`          A snapshot of the configuration, with all secrets redacted
+//
+use std::{
+    path::{Component, Path},
+    sync::Arc,
+};
+
+use chrono::Utc;
+use serde_json::Value;
+use tokio::{
+    fs::File,
+    io::{AsyncRead, BufWriter},
+};
+
+use crate::{
+    CONFIG, VERSION,
+    config::PathType,
+    db,
+    error::Error,
+    storage::{self, StorageBackend},
+    util::get_uuid,
+};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const DB_FILE: &str = "db.sqlite3";
+const ATTACHMENTS_DIR: &str = "attachments/";
+const SENDS_DIR: &str = "sends/";
+
+/// Creates a bundle in the temp folder, and returns its path
+pub async fn create_bundle() -> Result<String, Error> {
+    let bundle_path =
+        storage::join_path(&CONFIG.tmp_folder(), &format!("vaultwarden_dr_{}.tar", Utc::now().format("%Y%m%d_%H%M%S")));
+    let mut tar = tar::Writer::new(BufWriter::new(File::create(&bundle_path).await?));
+
+    let database = if db::is_sqlite_url() {
+        let backup_file = tokio::task::spawn_blocking(db::backup_sqlite).await.map_err(std::io::Error::other)??;
+        let result = async {
+            let file = File::open(&backup_file).await?;
+            let size = file.metadata().await?.len();
+            tar.append_reader(DB_FILE, size, file).await
+        }
+        .await;
+        tokio::fs::remove_file(&backup_file).await.ok();
+        result?;
+        Value::from(DB_FILE)
+    } else {
+        Value::from("Not included, use the native dump tools of your database server")
+    };
+
+    let attachments = append_tree(&mut tar, &CONFIG.storage_backend(&PathType::Attachments)?, ATTACHMENTS_DIR).await?;
+    let sends = append_tree(&mut tar, &CONFIG.storage_backend(&PathType::Sends)?, SENDS_DIR).await?;
+
+    let icons = list_files(&CONFIG.storage_backend(&PathType::IconCache)?, "/").await?;
+    tar.append("icon_cache.json", &serde_json::to_vec_pretty(&icons)?).await?;
+
+    tar.append("config.json", &serde_json::to_vec_pretty(&CONFIG.get_support_json())?).await?;
+
+    let manifest = json!({
+        "version": VERSION,
+        "createdAt": Utc::now().to_rfc3339(),
+        "database": database,
+        "attachments": attachments,
+        "sends": sends,
+        "cachedIcons": icons.len(),
+    });
+    tar.append(MANIFEST_FILE, &serde_json::to_vec_pretty(&manifest)?).await?;
+    tar.finish().await?;
+
+    info!("Disaster recovery bundle created at '{bundle_path}'");
+    Ok(bundle_path)
+}
+
+/// Copies a bundle from the temp folder to `DR_BUNDLE_FOLDER`, and returns the new location
+pub async fn upload_bundle(bundle_path: &str) -> Result<String, Error> {
+    let folder = CONFIG.dr_bundle_folder();
+    let file_name = storage::file_name(bundle_path).ok_or_else(|| std::io::Error::other("Invalid bundle path"))?;
+
+    storage::backend_for_path(&folder)?.put_local_file(&file_name, Path::new(bundle_path), true).await?;
+    tokio::fs::remove_file(bundle_path).await.ok();
+
+    Ok(storage::join_path(&folder, &file_name))
+}
+
+// The manifest is the only file read into memory
+const MANIFEST_MAX_SIZE: u64 = 1024 * 1024;
+
+/// Restores the database and files from a bundle.
+/// This should only be done while Vaultwarden is not running.
+pub async fn restore_bundle(bundle_path: &str, overwrite_db: bool) -> Result<(), Error> {
+    let attachments = CONFIG.storage_backend(&PathType::Attachments)?;
+    let sends = CONFIG.storage_backend(&PathType::Sends)?;
+    let mut tar = tar::Reader::new(tokio::io::BufReader::new(File::open(bundle_path).await?));
+
+    let (mut restored_files, mut has_manifest) = (0, false);
+    while let Some((name, size)) = tar.next_file().await? {
+        // Otherwise a crafted bundle could write anywhere the process is allowed to
+        if !is_safe_path(&name) {
+            err!(format!("The bundle contains an invalid path: {name}"))
+        }
+
+        if name == MANIFEST_FILE {
+            if size > MANIFEST_MAX_SIZE {
+                err!("The manifest of the bundle is too large")
+            }
+            let mut data = Vec::new();
+            tar.read_data(&mut data).await?;
+            let manifest: Value = serde_json::from_slice(&data)?;
+            info!(
+                "Restoring bundle created at {} by Vaultwarden {}",
+                manifest["createdAt"].as_str().unwrap_or("unknown"),
+                manifest["version"].as_str().unwrap_or("unknown")
+            );
+            has_manifest = true;
+        } else if name == DB_FILE {
+            restore_database(&mut tar, overwrite_db).await?;
+        } else if let Some(path) = name.strip_prefix(ATTACHMENTS_DIR) {
+            restore_file(&mut tar, &attachments, path).await?;
+            restored_files += 1;
+        } else if let Some(path) = name.strip_prefix(SENDS_DIR) {
+            restore_file(&mut tar, &sends, path).await?;
+            restored_files += 1;
+        }
+    }
+
+    if !has_manifest {
+        err!("The file is not a valid Vaultwarden disaster recovery bundle")
+    }
+    info!("Restored {restored_files} attachment and Send files");
+    Ok(())
+}
+
+/// Only relative paths without any `..` are restored, the storage backends would follow them
+fn is_safe_path(path: &str) -> bool {
+    !path.is_empty() && Path::new(path).components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+// Writes the current file of the bundle to a temporary file first, so it is never held in memory as a whole
+async fn extract_to_temp_file<R: AsyncRead + Unpin>(tar: &mut tar::Reader<R>) -> Result<String, Error> {
+    let temp_path = storage::join_path(&CONFIG.tmp_folder(), &format!("vaultwarden_restore_{}", get_uuid()));
+    let mut file = BufWriter::new(File::create(&temp_path).await?);
+    let result = async {
+        tar.read_data(&mut file).await?;
+        tokio::io::AsyncWriteExt::flush(&mut file).await
+    }
+    .await;
+    if let Err(e) = result {
+        tokio::fs::remove_file(&temp_path).await.ok();
+        return Err(e.into());
+    }
+    Ok(temp_path)
+}
+
+async fn restore_file<R: AsyncRead + Unpin>(
+    tar: &mut tar::Reader<R>,
+    backend: &Arc<dyn StorageBackend>,
+    path: &str,
+) -> Result<(), Error> {
+    let temp_path = extract_to_temp_file(tar).await?;
+    let result = backend.put_local_file(path, Path::new(&temp_path), true).await;
+    tokio::fs::remove_file(&temp_path).await.ok();
+    result
+}
+
+async fn restore_database<R: AsyncRead + Unpin>(tar: &mut tar::Reader<R>, overwrite: bool) -> Result<(), Error> {
+    if !db::is_sqlite_url() {
+        warn!("The bundle contains a SQLite database, but the configured database isn't SQLite, skipping it");
+        return Ok(());
+    }
+
+    let db_url = CONFIG.database_url();
+    let db_path = db_url.strip_prefix("sqlite://").unwrap_or(&db_url);
+    if !overwrite && tokio::fs::try_exists(db_path).await? {
+        err!(format!("The database '{db_path}' already exists, use --force to overwrite it"))
+    }
+
+    let temp_path = extract_to_temp_file(tar).await?;
+    // The temp folder could be on another file system, in which case the file can't be renamed
+    if tokio::fs::rename(&temp_path, db_path).await.is_err() {
+        let result = tokio::fs::copy(&temp_path, db_path).await;
+        tokio::fs::remove_file(&temp_path).await.ok();
+        result?;
+    }
+    // The WAL files belong to the old database, and would corrupt the restored one
+    for suffix in ["-wal", "-shm"] {
+        tokio::fs::remove_file(format!("{db_path}{suffix}")).await.ok();
+    }
+    info!("Restored the database to '{db_path}'");
+    Ok(())
+}
+
+/// Adds all the files of a storage backend to the bundle, below the given directory. Returns the number of files.
+async fn append_tree(
+    tar: &mut tar::Writer<BufWriter<File>>,
+    backend: &Arc<dyn StorageBackend>,
+    dir: &str,
+) -> Result<usize, Error> {
+    let files = list_files(backend, "/").await?;
+    for file in &files {
+        // A file could have been deleted since it was listed
+        if let Some((size, reader)) = backend.open(file).await? {
+            tar.append_reader(&format!("{dir}{file}"), size, reader).await?;
+        }
+    }
+    Ok(files.len())
+}
+
+/// Recursively lists all the files of a storage backend
+async fn list_files(backend: &Arc<dyn StorageBackend>, root: &str) -> Result<Vec<String>, Error> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in backend.list(&dir).await? {
+            if entry.ends_with('/') {
+                if entry != dir && format!("/{entry}") != dir {
+                    dirs.push(entry);
+                }
+            } else {
+                files.push(entry);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// A minimal implementation of the ustar format, which is all we need for the bundles
+mod tar {
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    const BLOCK_SIZE: usize = 512;
+
+    pub struct Writer<W> {
+        inner: W,
+    }
+
+    impl<W: AsyncWrite + Unpin> Writer<W> {
+        pub fn new(inner: W) -> Self {
+            Self {
+                inner,
+            }
+        }
+
+        pub async fn append(&mut self, name: &str, data: &[u8]) -> std::io::Result<()> {
+            self.append_reader(name, data.len() as u64, data).await
+        }
+
+        /// Streams a file of the given size into the archive, without holding it in memory
+        pub async fn append_reader<R: AsyncRead + Unpin>(
+            &mut self,
+            name: &str,
+            size: u64,
+            reader: R,
+        ) -> std::io::Result<()> {
+            let size_usize = usize::try_from(size).map_err(std::io::Error::other)?;
+            self.inner.write_all(&header(name, size_usize)?).await?;
+            // The size is already in the header, a file which changed since can't be written differently
+            let copied = tokio::io::copy(&mut reader.take(size), &mut self.inner).await?;
+            if copied != size {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    format!("File changed while adding it to the archive: {name}"),
+                ));
+            }
+            self.inner.write_all(&vec![0; padding(size_usize)]).await
+        }
+
+        // The archive ends with two empty blocks
+        pub async fn finish(mut self) -> std::io::Result<W> {
+            self.inner.write_all(&[0; BLOCK_SIZE * 2]).await?;
+            self.inner.flush().await?;
+            Ok(self.inner)
+        }
+    }
+
+    pub struct Reader<R> {
+        inner: R,
+        // Size of the file returned by `next_file` which wasn't read yet, and of the padding after it
+        pending: Option<(u64, u64)>,
+    }
+
+    impl<R: AsyncRead + Unpin> Reader<R> {
+        pub fn new(inner: R) -> Self {
+            Self {
+                inner,
+                pending: None,
+            }
+        }
+
+        /// Returns the name and size of the next file, or `None` at the end of the archive.
+        /// Its contents can be read with `read_data`, they are skipped otherwise.
+        pub async fn next_file(&mut self) -> std::io::Result<Option<(String, u64)>> {
+            if let Some((size, padding)) = self.pending.take() {
+                self.skip(size + padding).await?;
+            }
+
+            loop {
+                let mut header = [0; BLOCK_SIZE];
+                self.inner.read_exact(&mut header).await?;
+                if header.iter().all(|b| *b == 0) {
+                    return Ok(None);
+                }
+                if header[257..262] != *b"ustar" {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Not a ustar archive"));
+                }
+
+                let name = String::from_utf8_lossy(until_nul(&header[..100])).into_owned();
+                let size = parse_octal(&header[124..136])?;
+                let padding = u64::try_from(padding(usize::try_from(size).map_err(std::io::Error::other)?))
+                    .map_err(std::io::Error::other)?;
+
+                // Only regular files are used in the bundles, skip anything else
+                if matches!(header[156], b'0' | 0) {
+                    self.pending = Some((size, padding));
+                    return Ok(Some((name, size)));
+                }
+                self.skip(size + padding).await?;
+            }
+        }
+
+        /// Copies the contents of the file returned by the last call of `next_file`
+        pub async fn read_data<W: AsyncWrite + Unpin>(&mut self, out: &mut W) -> std::io::Result<()> {
+            let Some((size, padding)) = self.pending.take() else {
+                return Ok(());
+            };
+            let copied = tokio::io::copy(&mut (&mut self.inner).take(size), out).await?;
+            if copied != size {
+                return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Truncated archive"));
+            }
+            self.skip(padding).await
+        }
+
+        async fn skip(&mut self, len: u64) -> std::io::Result<()> {
+            let skipped = tokio::io::copy(&mut (&mut self.inner).take(len), &mut tokio::io::sink()).await?;
+            if skipped != len {
+                return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Truncated archive"));
+            }
+            Ok(())
+        }
+    }
+
+    fn header(name: &str, size: usize) -> std::io::Result<[u8; BLOCK_SIZE]> {
+        if name.len() > 100 {
+            return Err(std::io::Error::other(format!("File name too long for the archive: {name}")));
+        }
+
+        let mut header = [0; BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..107].copy_from_slice(b"0000600"); // mode
+        header[108..115].copy_from_slice(b"0000000"); // uid
+        header[116..123].copy_from_slice(b"0000000"); // gid
+        header[124..135].copy_from_slice(format!("{size:011o}").as_bytes());
+        let mtime = chrono::Utc::now().timestamp().max(0);
+        header[136..147].copy_from_slice(format!("{mtime:011o}").as_bytes());
+        header[156] = b'0'; // Regular file
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+
+        // The checksum is calculated with the checksum field itself filled with spaces
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|b| u32::from(*b)).sum();
+        header[148..155].copy_from_slice(format!("{checksum:06o}\0").as_bytes());
+
+        Ok(header)
+    }
+
+    fn padding(size: usize) -> usize {
+        (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE
+    }
+
+    fn until_nul(bytes: &[u8]) -> &[u8] {
+        bytes.iter().position(|b| *b == 0).map_or(bytes, |end| &bytes[..end])
+    }
+
+    fn parse_octal(bytes: &[u8]) -> std::io::Result<u64> {
+        let value = String::from_utf8_lossy(until_nul(bytes));
+        u64::from_str_radix(value.trim(), 8).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_roundtrip() {
+            let mut writer = Writer::new(Vec::new());
+            writer.append("manifest.json", b"{}").await.unwrap();
+            writer.append("attachments/cipher/file", &[7; 1000]).await.unwrap();
+            writer.append("empty", &[]).await.unwrap();
+            let archive = writer.finish().await.unwrap();
+            assert_eq!(archive.len() % BLOCK_SIZE, 0);
+
+            let mut reader = Reader::new(archive.as_slice());
+            assert_eq!(reader.next_file().await.unwrap(), Some(("manifest.json".to_owned(), 2)));
+            let mut data = Vec::new();
+            reader.read_data(&mut data).await.unwrap();
+            assert_eq!(data, b"{}");
+            // The contents of a file which isn't read are skipped
+            assert_eq!(reader.next_file().await.unwrap(), Some(("attachments/cipher/file".to_owned(), 1000)));
+            assert_eq!(reader.next_file().await.unwrap(), Some(("empty".to_owned(), 0)));
+            assert_eq!(reader.next_file().await.unwrap(), None);
+        }
+
+        #[tokio::test]
+        async fn test_append_reader() {
+            let mut writer = Writer::new(Vec::new());
+            writer.append_reader("streamed", 3, &b"abcdef"[..]).await.unwrap();
+            // A file which shrunk since its size was read would corrupt the archive
+            assert!(writer.append_reader("shrunk", 10, &b"abc"[..]).await.is_err());
+
+            let mut reader = Reader::new(writer.inner.as_slice());
+            assert_eq!(reader.next_file().await.unwrap(), Some(("streamed".to_owned(), 3)));
+            let mut data = Vec::new();
+            reader.read_data(&mut data).await.unwrap();
+            assert_eq!(data, b"abc");
+        }
+
+        #[test]
+        fn test_long_name() {
+            assert!(header(&"a".repeat(101), 0).is_err());
+        }
+    }
+}
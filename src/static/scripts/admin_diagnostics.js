@@ -422,6 +422,21 @@ function init(dj) {
     }
 }
 
+// ================================
+// Remove the orphaned rows of one category
+function cleanupOrphans(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const category = event.target.dataset.vwCategory;
+    const confirmed = confirm(`Are you sure you want to remove all orphaned ${category}? This can't be undone.`);
+    if (confirmed) {
+        _post(`${BASE_URL}/admin/diagnostics/orphans/${category}/delete`,
+            "Orphaned data removed correctly",
+            "Error removing the orphaned data"
+        );
+    }
+}
+
 // onLoad events
 document.addEventListener("DOMContentLoaded", (event) => {
     const diag_json = JSON.parse(document.getElementById("diagnostics_json").textContent);
@@ -437,4 +452,7 @@ document.addEventListener("DOMContentLoaded", (event) => {
     if (btnCopySupport) {
         btnCopySupport.addEventListener("click", copyToClipboard);
     }
+    document.querySelectorAll("button.vw-cleanup-orphans").forEach(btn => {
+        btn.addEventListener("click", cleanupOrphans);
+    });
 });
\ No newline at end of file
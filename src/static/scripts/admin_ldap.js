@@ -0,0 +1,21 @@
+"use strict";
+/* eslint-env es2017, browser */
+/* global _post:readable, BASE_URL:readable */
+
+function ldapSync(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    event.target.disabled = true;
+    _post(`${BASE_URL}/admin/ldap/sync`,
+        "LDAP sync finished",
+        "Error syncing the LDAP directory"
+    );
+}
+
+// onLoad events
+document.addEventListener("DOMContentLoaded", (/*event*/) => {
+    const btnLdapSync = document.getElementById("ldapSync");
+    if (btnLdapSync) {
+        btnLdapSync.addEventListener("click", ldapSync);
+    }
+});
@@ -79,6 +79,15 @@ function backupDatabase(event) {
     );
 }
 
+function storeDrBundle(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    _post(`${BASE_URL}/admin/recovery/export`,
+        "Disaster recovery bundle stored successfully",
+        "Error creating disaster recovery bundle", null, false
+    );
+}
+
 // Two functions to help check if there were changes to the form fields
 // Useful for example during the smtp test to prevent people from clicking save before testing there new settings
 function initChangeDetection(form) {
@@ -217,6 +226,10 @@ document.addEventListener("DOMContentLoaded", (/*event*/) => {
     if (btnBackupDatabase) {
         btnBackupDatabase.addEventListener("click", backupDatabase);
     }
+    const btnStoreDrBundle = document.getElementById("storeDrBundle");
+    if (btnStoreDrBundle) {
+        btnStoreDrBundle.addEventListener("click", storeDrBundle);
+    }
     const btnDeleteConf = document.getElementById("deleteConf");
     if (btnDeleteConf) {
         btnDeleteConf.addEventListener("click", deleteConf);
@@ -127,6 +127,18 @@ function updateRevisions(event) {
     );
 }
 
+function reregisterPush(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const confirmed = confirm("Are you sure you want to register all push devices again with the push relay?");
+    if (confirmed) {
+        _post(`${BASE_URL}/admin/push/reregister`,
+            "Re-registration started, the progress is shown on the diagnostics page",
+            "Error re-registering the push devices"
+        );
+    }
+}
+
 function inviteUser(event) {
     event.preventDefault();
     event.stopPropagation();
@@ -318,6 +330,10 @@ document.addEventListener("DOMContentLoaded", (/*event*/) => {
     if (btnUpdateRevisions) {
         btnUpdateRevisions.addEventListener("click", updateRevisions);
     }
+    const btnReregisterPush = document.getElementById("reregisterPush");
+    if (btnReregisterPush) {
+        btnReregisterPush.addEventListener("click", reregisterPush);
+    }
     const btnReload = document.getElementById("reload");
     if (btnReload) {
         btnReload.addEventListener("click", reload);
@@ -1,9 +1,20 @@
-use std::sync::LazyLock;
+use std::{
+    path::Path,
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+use rocket::fs::TempFile;
+
+use crate::{CONFIG, error::Error, util::get_display_size};
 
 pub(crate) fn join_path(base: &str, child: &str) -> String {
-    #[cfg(s3)]
-    if s3::is_uri(base) {
-        return s3::join_path(base, child);
+    #[cfg(any(s3, azure))]
+    if object_store::is_uri(base) {
+        return object_store::join_path(base, child);
     }
 
     let base = base.trim_end_matches('/');
@@ -20,37 +31,38 @@ pub(crate) fn join_path(base: &str, child: &str) -> String {
 pub(crate) fn with_extension(path: &str, extension: &str) -> String {
     let extension = extension.trim_start_matches('.');
 
-    #[cfg(s3)]
-    if s3::is_uri(path) {
-        return s3::with_extension(path, extension);
+    #[cfg(any(s3, azure))]
+    if object_store::is_uri(path) {
+        return object_store::with_extension(path, extension);
     }
 
     format!("{path}.{extension}")
 }
 
 pub(crate) fn parent(path: &str) -> Option<String> {
-    #[cfg(s3)]
-    if s3::is_uri(path) {
-        return s3::parent(path);
+    #[cfg(any(s3, azure))]
+    if object_store::is_uri(path) {
+        return object_store::parent(path);
     }
 
     std::path::Path::new(path).parent()?.to_str().map(str::to_owned)
 }
 
 pub(crate) fn file_name(path: &str) -> Option<String> {
-    #[cfg(s3)]
-    if s3::is_uri(path) {
-        return s3::file_name(path);
+    #[cfg(any(s3, azure))]
+    if object_store::is_uri(path) {
+        return object_store::file_name(path);
     }
 
     std::path::Path::new(path).file_name()?.to_str().map(str::to_owned)
 }
 
-pub(crate) fn is_fs_operator(operator: &opendal::Operator) -> bool {
-    operator.info().scheme() == opendal::services::FS_SCHEME
+/// Whether the path is a location in an object store instead of a local path, like `s3://bucket/attachments`
+pub(crate) fn is_object_store(path: &str) -> bool {
+    path.starts_with("s3://") || path.starts_with("azblob://")
 }
 
-pub(crate) fn operator_for_path(path: &str) -> Result<opendal::Operator, crate::Error> {
+pub(crate) fn operator_for_path(path: &str) -> Result<opendal::Operator, Error> {
     // Cache of previously built operators by path
     static OPERATORS_BY_PATH: LazyLock<dashmap::DashMap<String, opendal::Operator>> =
         LazyLock::new(dashmap::DashMap::new);
@@ -65,6 +77,12 @@ pub(crate) fn operator_for_path(path: &str) -> Result<opendal::Operator, crate::
 
         #[cfg(s3)]
         s3::operator_for_path(path)?
+    } else if path.starts_with("azblob://") {
+        #[cfg(not(azure))]
+        return Err(opendal::Error::new(opendal::ErrorKind::ConfigInvalid, "Azure support is not enabled").into());
+
+        #[cfg(azure)]
+        azure::operator_for_path(path)?
     } else {
         let builder = opendal::services::Fs::default().root(path);
         opendal::Operator::new(builder)?.finish()
@@ -75,14 +93,437 @@ pub(crate) fn operator_for_path(path: &str) -> Result<opendal::Operator, crate::
     Ok(operator)
 }
 
+/// Metadata of a stored file
+pub(crate) struct StorageStat {
+    pub size: u64,
+    pub last_modified: Option<SystemTime>,
+}
+
+/// A file opened for streaming from a storage backend
+pub(crate) type FileReader = Box<dyn tokio::io::AsyncRead + Send + Unpin>;
+
+/// Common interface for all the locations files can be stored at (attachments, sends, icon cache, keys, ...).
+/// Routes and models should only use this trait, so adding a new backend doesn't require touching them.
+#[rocket::async_trait]
+pub(crate) trait StorageBackend: Send + Sync {
+    /// Short name of the backend, used in logs and health reports
+    fn kind(&self) -> &'static str;
+
+    async fn put(&self, path: &str, data: Vec<u8>) -> Result<(), Error>;
+
+    /// Streams a Rocket temporary file to the given path, optionally refusing to overwrite an existing file
+    async fn put_temp_file(&self, path: &str, temp_file: &TempFile<'_>, overwrite: bool) -> Result<(), Error>;
+
+    /// Streams a local file, like an assembled resumable upload, to the given path
+    async fn put_local_file(&self, path: &str, local_path: &Path, overwrite: bool) -> Result<(), Error>;
+
+    /// Returns `None` if the file doesn't exist
+    async fn get(&self, path: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Opens a file to stream it, along with its size. Returns `None` if the file doesn't exist
+    async fn open(&self, path: &str) -> Result<Option<(u64, FileReader)>, Error>;
+
+    /// Deleting a file which doesn't exist is not an error
+    async fn delete(&self, path: &str, recursive: bool) -> Result<(), Error>;
+
+    /// Returns `None` if the file doesn't exist
+    async fn stat(&self, path: &str) -> Result<Option<StorageStat>, Error>;
+
+    /// Returns a time limited direct download URL,
+    /// or `None` when the backend can't do this and the file needs to be served by Vaultwarden itself
+    async fn presign(&self, path: &str, expires: Duration) -> Result<Option<String>, Error>;
+
+    /// Lists the entries directly below the given directory, which should end with a `/`
+    async fn list(&self, dir: &str) -> Result<Vec<String>, Error>;
+
+    /// Verifies the backend is reachable and usable
+    async fn health_check(&self) -> Result<(), Error>;
+}
+
+/// Returns the (cached) storage backend for the given path, based on its scheme
+pub(crate) fn backend_for_path(path: &str) -> Result<Arc<dyn StorageBackend>, Error> {
+    static BACKENDS_BY_PATH: LazyLock<dashmap::DashMap<String, Arc<dyn StorageBackend>>> =
+        LazyLock::new(dashmap::DashMap::new);
+
+    if let Some(backend) = BACKENDS_BY_PATH.get(path) {
+        return Ok(Arc::clone(&backend));
+    }
+
+    let operator = operator_for_path(path)?;
+    let backend: Arc<dyn StorageBackend> = if path.starts_with("s3://") {
+        #[cfg(not(s3))]
+        return Err(opendal::Error::new(opendal::ErrorKind::ConfigInvalid, "S3 support is not enabled").into());
+
+        #[cfg(s3)]
+        Arc::new(S3Backend {
+            operator,
+        })
+    } else if path.starts_with("azblob://") {
+        #[cfg(not(azure))]
+        return Err(opendal::Error::new(opendal::ErrorKind::ConfigInvalid, "Azure support is not enabled").into());
+
+        #[cfg(azure)]
+        Arc::new(AzureBackend {
+            operator,
+        })
+    } else {
+        Arc::new(LocalBackend {
+            operator,
+        })
+    };
+
+    BACKENDS_BY_PATH.insert(path.to_owned(), Arc::clone(&backend));
+
+    Ok(backend)
+}
+
+/// Files stored on the local filesystem, served by Vaultwarden itself
+pub(crate) struct LocalBackend {
+    operator: opendal::Operator,
+}
+
+#[rocket::async_trait]
+impl StorageBackend for LocalBackend {
+    fn kind(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(&self, path: &str, data: Vec<u8>) -> Result<(), Error> {
+        opendal_ops::put(&self.operator, path, data).await
+    }
+
+    async fn put_temp_file(&self, path: &str, temp_file: &TempFile<'_>, overwrite: bool) -> Result<(), Error> {
+        opendal_ops::put_temp_file(&self.operator, path, temp_file, overwrite).await
+    }
+
+    async fn put_local_file(&self, path: &str, local_path: &Path, overwrite: bool) -> Result<(), Error> {
+        opendal_ops::put_local_file(&self.operator, path, local_path, overwrite).await
+    }
+
+    async fn get(&self, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        opendal_ops::get(&self.operator, path).await
+    }
+
+    async fn open(&self, path: &str) -> Result<Option<(u64, FileReader)>, Error> {
+        opendal_ops::open(&self.operator, path).await
+    }
+
+    async fn delete(&self, path: &str, recursive: bool) -> Result<(), Error> {
+        opendal_ops::delete(&self.operator, path, recursive).await
+    }
+
+    async fn stat(&self, path: &str) -> Result<Option<StorageStat>, Error> {
+        opendal_ops::stat(&self.operator, path).await
+    }
+
+    async fn presign(&self, _path: &str, _expires: Duration) -> Result<Option<String>, Error> {
+        // Local files are served through our own routes using a signed token
+        Ok(None)
+    }
+
+    async fn list(&self, dir: &str) -> Result<Vec<String>, Error> {
+        opendal_ops::list(&self.operator, dir).await
+    }
+
+    async fn health_check(&self) -> Result<(), Error> {
+        // Make sure the folder is not only readable, but also writable.
+        // Every probe uses its own file, concurrent probes would otherwise delete each other's file.
+        let probe_file = format!(".vw_health_check_{}", crate::util::get_uuid());
+        self.operator.check().await?;
+        self.operator.write(&probe_file, "ok").await?;
+        self.operator.delete(&probe_file).await?;
+        Ok(())
+    }
+}
+
+/// Files stored in an S3 compatible bucket, downloaded directly by the clients using presigned URLs
 #[cfg(s3)]
-mod s3 {
-    use reqwest::Url;
+pub(crate) struct S3Backend {
+    operator: opendal::Operator,
+}
+
+#[cfg(s3)]
+#[rocket::async_trait]
+impl StorageBackend for S3Backend {
+    fn kind(&self) -> &'static str {
+        "s3"
+    }
+
+    async fn put(&self, path: &str, data: Vec<u8>) -> Result<(), Error> {
+        opendal_ops::put(&self.operator, path, data).await
+    }
+
+    async fn put_temp_file(&self, path: &str, temp_file: &TempFile<'_>, overwrite: bool) -> Result<(), Error> {
+        opendal_ops::put_temp_file(&self.operator, path, temp_file, overwrite).await
+    }
+
+    async fn put_local_file(&self, path: &str, local_path: &Path, overwrite: bool) -> Result<(), Error> {
+        opendal_ops::put_local_file(&self.operator, path, local_path, overwrite).await
+    }
+
+    async fn get(&self, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        opendal_ops::get(&self.operator, path).await
+    }
+
+    async fn open(&self, path: &str) -> Result<Option<(u64, FileReader)>, Error> {
+        opendal_ops::open(&self.operator, path).await
+    }
+
+    async fn delete(&self, path: &str, recursive: bool) -> Result<(), Error> {
+        opendal_ops::delete(&self.operator, path, recursive).await
+    }
+
+    async fn stat(&self, path: &str) -> Result<Option<StorageStat>, Error> {
+        opendal_ops::stat(&self.operator, path).await
+    }
+
+    async fn presign(&self, path: &str, expires: Duration) -> Result<Option<String>, Error> {
+        Ok(Some(self.operator.presign_read(path, expires).await?.uri().to_string()))
+    }
+
+    async fn list(&self, dir: &str) -> Result<Vec<String>, Error> {
+        opendal_ops::list(&self.operator, dir).await
+    }
+
+    async fn health_check(&self) -> Result<(), Error> {
+        self.operator.check().await?;
+        Ok(())
+    }
+}
+
+/// Files stored in an Azure Blob Storage container, downloaded directly by the clients using SAS URLs
+#[cfg(azure)]
+pub(crate) struct AzureBackend {
+    operator: opendal::Operator,
+}
+
+#[cfg(azure)]
+#[rocket::async_trait]
+impl StorageBackend for AzureBackend {
+    fn kind(&self) -> &'static str {
+        "azure"
+    }
+
+    async fn put(&self, path: &str, data: Vec<u8>) -> Result<(), Error> {
+        opendal_ops::put(&self.operator, path, data).await
+    }
+
+    async fn put_temp_file(&self, path: &str, temp_file: &TempFile<'_>, overwrite: bool) -> Result<(), Error> {
+        opendal_ops::put_temp_file(&self.operator, path, temp_file, overwrite).await
+    }
 
+    async fn put_local_file(&self, path: &str, local_path: &Path, overwrite: bool) -> Result<(), Error> {
+        opendal_ops::put_local_file(&self.operator, path, local_path, overwrite).await
+    }
+
+    async fn get(&self, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        opendal_ops::get(&self.operator, path).await
+    }
+
+    async fn open(&self, path: &str) -> Result<Option<(u64, FileReader)>, Error> {
+        opendal_ops::open(&self.operator, path).await
+    }
+
+    async fn delete(&self, path: &str, recursive: bool) -> Result<(), Error> {
+        opendal_ops::delete(&self.operator, path, recursive).await
+    }
+
+    async fn stat(&self, path: &str) -> Result<Option<StorageStat>, Error> {
+        opendal_ops::stat(&self.operator, path).await
+    }
+
+    async fn presign(&self, path: &str, expires: Duration) -> Result<Option<String>, Error> {
+        Ok(Some(self.operator.presign_read(path, expires).await?.uri().to_string()))
+    }
+
+    async fn list(&self, dir: &str) -> Result<Vec<String>, Error> {
+        opendal_ops::list(&self.operator, dir).await
+    }
+
+    async fn health_check(&self) -> Result<(), Error> {
+        self.operator.check().await?;
+        Ok(())
+    }
+}
+
+/// Shared implementations for the backends which are built on top of OpenDAL
+mod opendal_ops {
+    use opendal::{ErrorKind, Operator};
+    use rocket::fs::TempFile;
+
+    use super::{FileReader, StorageStat};
     use crate::error::Error;
 
+    pub(super) async fn put(operator: &Operator, path: &str, data: Vec<u8>) -> Result<(), Error> {
+        operator.write(path, data).await?;
+        Ok(())
+    }
+
+    pub(super) async fn put_temp_file(
+        operator: &Operator,
+        path: &str,
+        temp_file: &TempFile<'_>,
+        overwrite: bool,
+    ) -> Result<(), Error> {
+        use futures::AsyncWriteExt as _;
+        use tokio_util::compat::TokioAsyncReadCompatExt as _;
+
+        let mut read_stream = temp_file.open().await?.compat();
+        let mut writer = operator.writer_with(path).if_not_exists(!overwrite).await?.into_futures_async_write();
+        futures::io::copy(&mut read_stream, &mut writer).await?;
+        writer.close().await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn put_local_file(
+        operator: &Operator,
+        path: &str,
+        local_path: &std::path::Path,
+        overwrite: bool,
+    ) -> Result<(), Error> {
+        use futures::AsyncWriteExt as _;
+        use tokio_util::compat::TokioAsyncReadCompatExt as _;
+
+        let mut read_stream = tokio::fs::File::open(local_path).await?.compat();
+        let mut writer = operator.writer_with(path).if_not_exists(!overwrite).await?.into_futures_async_write();
+        futures::io::copy(&mut read_stream, &mut writer).await?;
+        writer.close().await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn get(operator: &Operator, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        match operator.read(path).await {
+            Ok(buffer) => Ok(Some(buffer.to_vec())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub(super) async fn open(operator: &Operator, path: &str) -> Result<Option<(u64, FileReader)>, Error> {
+        use tokio_util::compat::FuturesAsyncReadCompatExt as _;
+
+        let size = match operator.stat(path).await {
+            Ok(meta) => meta.content_length(),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let reader = operator.reader(path).await?.into_futures_async_read(0..size).await?.compat();
+        Ok(Some((size, Box::new(reader))))
+    }
+
+    pub(super) async fn delete(operator: &Operator, path: &str, recursive: bool) -> Result<(), Error> {
+        match operator.delete_with(path).recursive(recursive).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                debug!("File '{path}' already deleted.");
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub(super) async fn stat(operator: &Operator, path: &str) -> Result<Option<StorageStat>, Error> {
+        match operator.stat(path).await {
+            Ok(meta) => Ok(Some(StorageStat {
+                size: meta.content_length(),
+                last_modified: meta.last_modified().map(Into::into),
+            })),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub(super) async fn list(operator: &Operator, dir: &str) -> Result<Vec<String>, Error> {
+        Ok(operator.list(dir).await?.into_iter().map(|entry| entry.path().to_owned()).collect())
+    }
+}
+
+// Totals of what the temp file cleanup job removed since startup, shown on the admin diagnostics page
+static TMP_RECLAIMED_FILES: AtomicU64 = AtomicU64::new(0);
+static TMP_RECLAIMED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of stale temp files removed and the bytes reclaimed since startup
+pub(crate) fn tmp_reclaimed_stats() -> (u64, u64) {
+    (TMP_RECLAIMED_FILES.load(Ordering::Relaxed), TMP_RECLAIMED_BYTES.load(Ordering::Relaxed))
+}
+
+/// Moves the temp file of a failed upload into the quarantine folder.
+/// The file is kept there until the cleanup job considers it stale.
+pub(crate) async fn quarantine_temp_file(temp_file: &mut rocket::fs::TempFile<'_>, upload_path: &str) {
+    let file_name = format!("{}_{}", chrono::Utc::now().format("%Y%m%d_%H%M%S"), upload_path.replace('/', "_"));
+    let quarantine_path = std::path::Path::new(&CONFIG.tmp_quarantine_folder()).join(file_name);
+
+    match temp_file.persist_to(&quarantine_path).await {
+        Ok(()) => warn!("Upload of '{upload_path}' failed, the partial upload was moved to {quarantine_path:?}"),
+        Err(e) => error!("Upload of '{upload_path}' failed and could not be moved to the quarantine folder: {e}"),
+    }
+}
+
+/// Removes leftover temporary uploads and quarantined files older than `TMP_CLEANUP_MAX_AGE`.
+pub(crate) async fn purge_stale_tmp_files() {
+    let max_age = Duration::from_secs(CONFIG.tmp_cleanup_max_age());
+
+    let (tmp_files, tmp_bytes) = purge_stale_files_in(&CONFIG.tmp_folder(), max_age).await;
+    let (quarantine_files, quarantine_bytes) = purge_stale_files_in(&CONFIG.tmp_quarantine_folder(), max_age).await;
+
+    let files = tmp_files + quarantine_files;
+    let bytes = tmp_bytes + quarantine_bytes;
+    if files > 0 {
+        TMP_RECLAIMED_FILES.fetch_add(files, Ordering::Relaxed);
+        TMP_RECLAIMED_BYTES.fetch_add(bytes, Ordering::Relaxed);
+        info!(
+            "Removed {files} stale temp file(s) ({quarantine_files} quarantined), reclaimed {}",
+            get_display_size(i64::try_from(bytes).unwrap_or(i64::MAX))
+        );
+    } else {
+        debug!("No stale temp files found");
+    }
+}
+
+// Only the files directly within `dir` are checked, sub-folders like the quarantine folder are handled on their own
+async fn purge_stale_files_in(dir: &str, max_age: Duration) -> (u64, u64) {
+    let mut files = 0;
+    let mut bytes = 0;
+
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Unable to read temp folder '{dir}': {e}");
+            return (files, bytes);
+        }
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Ok(metadata) = entry.metadata().await else {
+            continue;
+        };
+
+        let is_stale = metadata.modified().ok().and_then(|m| m.elapsed().ok()).is_some_and(|age| age > max_age);
+        if !metadata.is_file() || !is_stale {
+            continue;
+        }
+
+        match tokio::fs::remove_file(entry.path()).await {
+            Ok(()) => {
+                files += 1;
+                bytes += metadata.len();
+            }
+            Err(e) => warn!("Unable to remove stale temp file {:?}: {e}", entry.path()),
+        }
+    }
+
+    (files, bytes)
+}
+
+// Paths in the object stores are URIs, which can have the options of the store in their query string
+#[cfg(any(s3, azure))]
+mod object_store {
+    use reqwest::Url;
+
     pub(super) fn is_uri(path: &str) -> bool {
-        path.starts_with("s3://")
+        super::is_object_store(path)
     }
 
     pub(super) fn join_path(base: &str, child: &str) -> String {
@@ -150,6 +591,11 @@ mod s3 {
             url.set_path(&format!("/{}", segments.join("/")));
         }
     }
+}
+
+#[cfg(s3)]
+mod s3 {
+    use crate::error::Error;
 
     pub(super) fn operator_for_path(path: &str) -> Result<opendal::Operator, Error> {
         use crate::http_client::aws::AwsReqwestConnector;
@@ -254,6 +700,21 @@ mod s3 {
     }
 }
 
+#[cfg(azure)]
+mod azure {
+    use opendal::Configurator;
+
+    use crate::error::Error;
+
+    // `azblob://<container>/<root>`, the account and its credentials are options in the query string,
+    // like `account_name`, `account_key`, `sas_token` or `endpoint`, or taken from the `AZURE_STORAGE_*` environment variables
+    pub(super) fn operator_for_path(path: &str) -> Result<opendal::Operator, Error> {
+        let uri = opendal::OperatorUri::new(path, std::iter::empty::<(String, String)>())?;
+        let config = opendal::services::AzblobConfig::from_uri(&uri)?;
+        Ok(opendal::Operator::new(config.into_builder())?.finish())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -265,6 +726,28 @@ mod tests {
         assert_eq!(parent("data/rsa_key.pem").as_deref(), Some("data"));
         assert_eq!(file_name("data/rsa_key.pem").as_deref(), Some("rsa_key.pem"));
     }
+
+    #[test]
+    fn detects_object_stores() {
+        assert!(is_object_store("s3://bucket/attachments"));
+        assert!(is_object_store("azblob://container/attachments?account_name=vw"));
+        assert!(!is_object_store("data/attachments"));
+        assert!(!is_object_store("/data/s3://attachments"));
+    }
+}
+
+#[cfg(all(test, azure))]
+mod azure_tests {
+    use super::*;
+
+    #[test]
+    fn joins_azure_path_before_query_string() {
+        assert_eq!(
+            join_path("azblob://container/base?account_name=vw", "attachments"),
+            "azblob://container/base/attachments?account_name=vw"
+        );
+        assert_eq!(file_name("azblob://container/base/rsa_key.pem?account_name=vw").as_deref(), Some("rsa_key.pem"));
+    }
 }
 
 #[cfg(all(test, s3))]
@@ -0,0 +1,91 @@
+//
+// Opt-in usage statistics
+//
+// When `USAGE_STATISTICS_URL` is set, anonymized statistics of this instance are posted as JSON to that URL,
+// for example to feed the own monitoring of the operator. Nothing is sent anywhere else.
+// The payload only contains versions, the database type, the number of users rounded to a bucket and which features are enabled.
+// The exact payload can be inspected beforehand at `/admin/diagnostics/usage-statistics`.
+//
+use chrono::Utc;
+use reqwest::Method;
+use serde_json::Value;
+
+use crate::{
+    CONFIG, VERSION,
+    db::{DbConn, DbPool, active_db_type_name, models::User},
+    error::Error,
+    http_client::make_http_request,
+    util::{
+        FeatureFlagFilter, get_active_web_release, is_running_in_container, parse_experimental_client_feature_flags,
+    },
+};
+
+// Upper bounds of the user count buckets, anything above the last one is reported as such
+const USER_COUNT_BUCKETS: [(i64, &str); 7] = [
+    (0, "0"),
+    (10, "1-10"),
+    (50, "11-50"),
+    (100, "51-100"),
+    (500, "101-500"),
+    (1_000, "501-1000"),
+    (5_000, "1001-5000"),
+];
+
+fn user_count_bucket(count: i64) -> &'static str {
+    USER_COUNT_BUCKETS.iter().find(|(max, _)| count <= *max).map_or("5000+", |(_, bucket)| *bucket)
+}
+
+/// Returns the statistics exactly as they are posted
+pub async fn usage_statistics(conn: &DbConn) -> Value {
+    let client_feature_flags: Vec<String> = parse_experimental_client_feature_flags(
+        &CONFIG.experimental_client_feature_flags(),
+        &FeatureFlagFilter::ValidOnly,
+    )
+    .into_keys()
+    .collect();
+
+    json!({
+        "version": VERSION,
+        "webVaultVersion": CONFIG.web_vault_enabled().then(get_active_web_release),
+        "databaseType": active_db_type_name(),
+        "runningInContainer": is_running_in_container(),
+        "arch": std::env::consts::ARCH,
+        "os": std::env::consts::OS,
+        "userCount": user_count_bucket(User::count_all(conn).await),
+        "features": {
+            "signupsAllowed": CONFIG.signups_allowed(),
+            "invitationsAllowed": CONFIG.invitations_allowed(),
+            "mail": CONFIG.mail_enabled(),
+            "push": CONFIG.push_enabled(),
+            "websocket": CONFIG.enable_websocket(),
+            "sso": CONFIG.sso_enabled(),
+            "sends": CONFIG.sends_allowed(),
+            "emergencyAccess": CONFIG.emergency_access_allowed(),
+            "orgEvents": CONFIG.org_events_enabled(),
+            "orgGroups": CONFIG.org_groups_enabled(),
+        },
+        "clientFeatureFlags": client_feature_flags,
+        "generatedAt": Utc::now().to_rfc3339(),
+    })
+}
+
+async fn post_usage_statistics(url: &str, conn: &DbConn) -> Result<(), Error> {
+    let payload = usage_statistics(conn).await;
+    make_http_request(Method::POST, url)?.json(&payload).send().await?.error_for_status()?;
+    Ok(())
+}
+
+pub async fn usage_statistics_job(pool: DbPool) {
+    let Some(url) = CONFIG.usage_statistics_url() else {
+        return;
+    };
+    debug!("Posting the usage statistics");
+    let Ok(conn) = pool.get().await else {
+        error!("Failed to get DB connection while posting the usage statistics");
+        return;
+    };
+
+    if let Err(e) = post_usage_statistics(&url, &conn).await {
+        warn!("Failed to post the usage statistics to '{url}': {e:?}");
+    }
+}
@@ -0,0 +1,295 @@
+//
+// Multi-tenant mode
+//
+// When `TENANTS_FILE` is set, every request is mapped to a tenant based on the hostname it was sent to.
+// Users and organizations belong to the tenant they were created in, and can't be used from another one.
+// Hostnames which don't match any tenant belong to the default tenant, which uses the global settings.
+// The mails to the users of a tenant link to its own domain, and can be sent through its own SMTP server.
+//
+use std::{
+    sync::{Arc, LazyLock, OnceLock},
+    time::Duration,
+};
+
+use moka::sync::Cache;
+use rocket::{
+    Request,
+    request::{FromRequest, Outcome},
+};
+
+use crate::{
+    CONFIG,
+    db::{DbPool, models::User},
+    error::Error,
+    proxy,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Tenant {
+    pub id: String,
+    pub hosts: Vec<String>,
+    pub name: Option<String>,
+    pub signups_allowed: Option<bool>,
+    pub signups_domains_whitelist: Option<String>,
+    pub smtp_from: Option<String>,
+    pub smtp_from_name: Option<String>,
+    // Base URL of the links in the mails, like `DOMAIN`, defaults to https:// with the first host
+    pub domain: Option<String>,
+    // URL of the logo shown in the mails instead of the Vaultwarden logo
+    pub logo_url: Option<String>,
+    pub smtp: Option<TenantSmtp>,
+}
+
+/// The SMTP server of a tenant, used instead of the global one for the mails to its users
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct TenantSmtp {
+    pub host: String,
+    pub security: Option<String>,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl TenantSmtp {
+    /// Same defaults as `SMTP_SECURITY`
+    pub fn security(&self) -> String {
+        self.security.clone().unwrap_or_else(|| "starttls".to_owned())
+    }
+
+    /// Same defaults as `SMTP_PORT`
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or_else(|| match self.security().as_str() {
+            "force_tls" => 465,
+            "starttls" => 587,
+            _ => 25,
+        })
+    }
+}
+
+impl Tenant {
+    /// Same as `Config::is_signup_allowed`, but with the tenant specific settings taking precedence
+    pub fn is_signup_allowed(&self, email: &str) -> bool {
+        let whitelist = self.signups_domains_whitelist.clone().unwrap_or_else(|| CONFIG.signups_domains_whitelist());
+        if whitelist.is_empty() {
+            self.signups_allowed.unwrap_or_else(|| CONFIG.signups_allowed())
+        } else {
+            let domain = email.rsplit_once('@').map(|(_, d)| d.to_lowercase()).unwrap_or_default();
+            !domain.is_empty() && whitelist.split(',').any(|d| d.trim().eq_ignore_ascii_case(&domain))
+        }
+    }
+
+    fn smtp_sender(&self) -> (String, String) {
+        let from = self.smtp_from.clone().unwrap_or_else(|| CONFIG.smtp_from());
+        let from_name =
+            self.smtp_from_name.clone().or_else(|| self.name.clone()).unwrap_or_else(|| CONFIG.smtp_from_name());
+        (from, from_name)
+    }
+
+    fn domain(&self) -> String {
+        match &self.domain {
+            Some(domain) => domain.trim_end_matches('/').to_owned(),
+            None => format!("https://{}", self.hosts[0]),
+        }
+    }
+}
+
+static TENANTS: LazyLock<Vec<Arc<Tenant>>> = LazyLock::new(|| {
+    let path = CONFIG.tenants_file();
+    if path.is_empty() {
+        return Vec::new();
+    }
+    // The file was already validated when the config was loaded
+    load_tenants(&path).unwrap_or_else(|e| {
+        error!("Unable to load the tenants from '{path}': {e:?}");
+        Vec::new()
+    })
+});
+
+// Used to look up the tenant of a mail recipient, only set when multi-tenant mode is enabled
+static POOL: OnceLock<DbPool> = OnceLock::new();
+
+// Tenant id of the recent mail recipients, so sending a mail doesn't need a database lookup every time.
+// The tenant of a user is only set when it is created, so the entries don't need to be invalidated.
+static RECIPIENT_TENANTS: LazyLock<Cache<String, Option<String>>> =
+    LazyLock::new(|| Cache::builder().max_capacity(10_000).time_to_live(Duration::from_secs(3600)).build());
+
+/// Loads and validates a tenants file
+pub fn load_tenants(path: &str) -> Result<Vec<Arc<Tenant>>, Error> {
+    let tenants: Vec<Tenant> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+
+    let mut ids = Vec::new();
+    let mut hosts = Vec::new();
+    for tenant in &tenants {
+        if tenant.id.is_empty() || tenant.id.len() > 64 {
+            err!("Tenant ids must be between 1 and 64 characters long")
+        }
+        if ids.contains(&&tenant.id) {
+            err!(format!("Tenant id '{}' is used more than once", tenant.id))
+        }
+        ids.push(&tenant.id);
+
+        if tenant.hosts.is_empty() {
+            err!(format!("Tenant '{}' has no hosts", tenant.id))
+        }
+        for host in &tenant.hosts {
+            let host = host.to_lowercase();
+            if hosts.contains(&host) {
+                err!(format!("Host '{host}' is used by more than one tenant"))
+            }
+            hosts.push(host);
+        }
+
+        for url in [&tenant.domain, &tenant.logo_url].into_iter().flatten() {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                err!(format!("Tenant '{}' has an invalid URL '{url}', it needs to start with http(s)://", tenant.id))
+            }
+        }
+        if let Some(ref smtp) = tenant.smtp {
+            if !["starttls", "force_tls", "off"].contains(&smtp.security().as_str()) {
+                err!(format!("Tenant '{}' has an invalid SMTP security, use starttls, force_tls or off", tenant.id))
+            }
+            if smtp.username.is_some() != smtp.password.is_some() {
+                err!(format!("Tenant '{}' needs both an SMTP username and password, or neither", tenant.id))
+            }
+        }
+    }
+
+    Ok(tenants.into_iter().map(Arc::new).collect())
+}
+
+pub fn is_enabled() -> bool {
+    !TENANTS.is_empty()
+}
+
+/// Stores the database pool used for the tenant specific mail settings
+pub fn init(pool: &DbPool) {
+    if is_enabled() {
+        info!("Multi-tenant mode enabled with {} tenant(s)", TENANTS.len());
+        POOL.set(pool.clone()).ok();
+    }
+}
+
+fn find_by_host(host: &str) -> Option<Arc<Tenant>> {
+    // Strip the port, taking care of IPv6 addresses
+    let host = match host.rsplit_once(':') {
+        Some((h, port)) if !h.ends_with(':') && port.chars().all(|c| c.is_ascii_digit()) => h,
+        _ => host,
+    };
+    TENANTS.iter().find(|t| t.hosts.iter().any(|h| h.eq_ignore_ascii_case(host))).cloned()
+}
+
+fn find_by_id(id: Option<&str>) -> Option<Arc<Tenant>> {
+    let id = id?;
+    TENANTS.iter().find(|t| t.id == id).cloned()
+}
+
+/// The tenant a request was sent to, `None` being the default tenant
+pub struct RequestTenant(pub Option<Arc<Tenant>>);
+
+impl RequestTenant {
+    pub fn id(&self) -> Option<String> {
+        self.0.as_ref().map(|t| t.id.clone())
+    }
+
+    /// Tests whether a user or organization with the given tenant id can be used from this tenant
+    pub fn matches(&self, tenant_id: Option<&str>) -> bool {
+        self.0.as_ref().map(|t| t.id.as_str()) == tenant_id
+    }
+
+    pub fn is_signup_allowed(&self, email: &str) -> bool {
+        match &self.0 {
+            Some(tenant) => tenant.is_signup_allowed(email),
+            None => CONFIG.is_signup_allowed(email),
+        }
+    }
+
+    /// The mail settings of this tenant, for mails to addresses which don't have an account yet
+    pub fn mail(&self) -> MailTenant {
+        MailTenant(self.0.clone())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestTenant {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if !is_enabled() {
+            return Outcome::Success(RequestTenant(None));
+        }
+
+        // Anyone could choose another tenant with `X-Forwarded-Host`, only a trusted proxy may set it
+        let headers = request.headers();
+        let forwarded_host = match request.remote() {
+            Some(peer) if proxy::is_trusted_proxy(&peer.ip()) => headers.get_one("X-Forwarded-Host"),
+            _ => None,
+        };
+        let host = forwarded_host.or_else(|| headers.get_one("Host")).unwrap_or_default();
+        Outcome::Success(RequestTenant(find_by_host(host)))
+    }
+}
+
+/// The tenant whose settings are used for a mail, `None` being the default tenant with the global settings
+pub struct MailTenant(Option<Arc<Tenant>>);
+
+impl MailTenant {
+    /// Looks up the tenant of the user with the given address
+    pub async fn for_recipient(address: &str) -> Self {
+        let Some(pool) = POOL.get() else {
+            return Self(None);
+        };
+        if let Some(tenant_id) = RECIPIENT_TENANTS.get(address) {
+            return Self(find_by_id(tenant_id.as_deref()));
+        }
+
+        match pool.get().await {
+            Ok(conn) => match User::find_by_mail(address, &conn).await {
+                Some(user) => {
+                    // Addresses without an account aren't cached, they may still register with a tenant
+                    RECIPIENT_TENANTS.insert(address.to_owned(), user.tenant_id.clone());
+                    Self(find_by_id(user.tenant_id.as_deref()))
+                }
+                None => Self(None),
+            },
+            Err(e) => {
+                warn!("Unable to look up the tenant of a mail recipient: {e:?}");
+                Self(None)
+            }
+        }
+    }
+
+    /// Base URL of the links in the mails
+    pub fn domain(&self) -> String {
+        match &self.0 {
+            Some(tenant) => tenant.domain(),
+            None => CONFIG.domain(),
+        }
+    }
+
+    /// Base URL of the images in the mails
+    pub fn img_src(&self) -> String {
+        match &self.0 {
+            Some(tenant) if !CONFIG.smtp_embed_images() => format!("{}/vw_static/", tenant.domain()),
+            _ => CONFIG._smtp_img_src(),
+        }
+    }
+
+    pub fn logo_url(&self) -> Option<String> {
+        self.0.as_ref().and_then(|t| t.logo_url.clone())
+    }
+
+    /// Returns the sender address and name
+    pub fn smtp_sender(&self) -> (String, String) {
+        match &self.0 {
+            Some(tenant) => tenant.smtp_sender(),
+            None => (CONFIG.smtp_from(), CONFIG.smtp_from_name()),
+        }
+    }
+
+    /// The SMTP server of the tenant, if it doesn't use the global one
+    pub fn smtp(&self) -> Option<&TenantSmtp> {
+        self.0.as_ref().and_then(|t| t.smtp.as_ref())
+    }
+}
@@ -1,7 +1,7 @@
 //
 // Web Headers and caching
 //
-use std::{collections::HashMap, env, fmt, io::Cursor, path::Path, str::FromStr};
+use std::{collections::HashMap, env, fmt, io::Cursor, path::Path, str::FromStr, time::SystemTime};
 
 use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
 use num_traits::ToPrimitive;
@@ -66,11 +66,11 @@ impl Fairing for AppHeaders {
         // Obsolete in modern browsers, unsafe (XS-Leak), and largely replaced by CSP
         res.set_raw_header("X-XSS-Protection", "0");
 
-        // The `Cross-Origin-Resource-Policy` header should not be set on images or on the `icon_external` route.
+        // The `Cross-Origin-Resource-Policy` header should not be set on images or on the `icon_external` and `icon_org` routes.
         // Otherwise some clients, like the Bitwarden Desktop, will fail to download the icons
         let mut is_image = true;
         if !(res.headers().get_one("Content-Type").is_some_and(|v| v.starts_with("image/"))
-            || req.route().is_some_and(|v| v.name.as_deref() == Some("icon_external")))
+            || req.route().is_some_and(|v| matches!(v.name.as_deref(), Some("icon_external" | "icon_org"))))
         {
             is_image = false;
             res.set_raw_header("Cross-Origin-Resource-Policy", "same-origin");
@@ -211,23 +211,17 @@ pub struct Cached<R> {
     response: R,
     is_immutable: bool,
     ttl: u64,
+    etag: Option<String>,
+    last_modified: Option<DateTime<Local>>,
 }
 
 impl<R> Cached<R> {
     pub fn long(response: R, is_immutable: bool) -> Cached<R> {
-        Self {
-            response,
-            is_immutable,
-            ttl: 604_800, // 7 days
-        }
+        Self::ttl(response, 604_800, is_immutable) // 7 days
     }
 
     pub fn short(response: R, is_immutable: bool) -> Cached<R> {
-        Self {
-            response,
-            is_immutable,
-            ttl: 600, // 10 minutes
-        }
+        Self::ttl(response, 600, is_immutable) // 10 minutes
     }
 
     pub fn ttl(response: R, ttl: u64, is_immutable: bool) -> Cached<R> {
@@ -235,13 +229,62 @@ impl<R> Cached<R> {
             response,
             is_immutable,
             ttl,
+            etag: None,
+            last_modified: None,
+        }
+    }
+
+    /// Adds an `ETag` (see `content_etag`) and a `Last-Modified` header.
+    /// A conditional request which still matches them gets an empty `304 Not Modified` instead of the response.
+    pub fn with_validators(mut self, etag: String, last_modified: Option<SystemTime>) -> Cached<R> {
+        self.etag = Some(etag);
+        self.last_modified = last_modified.map(DateTime::<Local>::from);
+        self
+    }
+
+    fn is_not_modified(&self, request: &Request<'_>) -> bool {
+        // `If-None-Match` takes precedence, `If-Modified-Since` is only checked without it
+        if let Some(ref etag) = self.etag
+            && request.headers().contains("If-None-Match")
+        {
+            return etag_matches(request, etag);
+        }
+        match (self.last_modified, request.headers().get_one("If-Modified-Since")) {
+            (Some(last_modified), Some(since)) => DateTime::parse_from_rfc2822(&since.replace("GMT", "+0000"))
+                .is_ok_and(|since| last_modified.timestamp() <= since.timestamp()),
+            _ => false,
         }
     }
 }
 
+/// A strong `ETag` of the content, the first half of its SHA-256
+pub fn content_etag(content: &[u8]) -> String {
+    format!("\"{}\"", &crate::crypto::sha256_hex(content)[..32])
+}
+
+/// Whether one of the tags of the `If-None-Match` header of the request is the given `ETag`
+fn etag_matches(request: &Request<'_>, etag: &str) -> bool {
+    request
+        .headers()
+        .get("If-None-Match")
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == etag || tag == "*")
+}
+
 impl<'r, R: 'r + Responder<'r, 'static> + Send> Responder<'r, 'static> for Cached<R> {
     fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
-        let mut res = self.response.respond_to(request)?;
+        let mut res = if self.is_not_modified(request) {
+            Response::build().status(Status::NotModified).finalize()
+        } else {
+            self.response.respond_to(request)?
+        };
+        if let Some(etag) = self.etag {
+            res.set_raw_header("ETag", etag);
+        }
+        if let Some(last_modified) = self.last_modified {
+            res.set_raw_header("Last-Modified", format_datetime_http(&last_modified));
+        }
 
         let cache_control_header = if self.is_immutable {
             format!("public, immutable, max-age={}", self.ttl)
@@ -257,9 +300,32 @@ impl<'r, R: 'r + Responder<'r, 'static> + Send> Responder<'r, 'static> for Cache
     }
 }
 
+/// A JSON response with an `ETag` of its content.
+/// When the `If-None-Match` header of the request already matches, an empty `304 Not Modified` is returned instead.
+pub struct ETagged(pub Value);
+
+impl<'r> Responder<'r, 'static> for ETagged {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let body = self.0.to_string();
+        let etag = content_etag(body.as_bytes());
+
+        let mut res = if etag_matches(request, &etag) {
+            Response::build().status(Status::NotModified).finalize()
+        } else {
+            (ContentType::JSON, body).respond_to(request)?
+        };
+
+        res.set_raw_header("ETag", etag);
+        // The content depends on the user, it may only be stored by the client itself and has to be revalidated every time
+        res.set_raw_header("Cache-Control", "private, no-cache");
+        Ok(res)
+    }
+}
+
 // Log all the routes from the main paths list, and the attachments endpoint
-// Effectively ignores, any static file route, and the alive endpoint
-const LOGGED_ROUTES: [&str; 7] = ["/api", "/admin", "/identity", "/icons", "/attachments", "/events", "/notifications"];
+// Effectively ignores, any static file route, and the alive and readyz endpoints
+const LOGGED_ROUTES: [&str; 8] =
+    ["/api", "/admin", "/identity", "/icons", "/attachments", "/events", "/notifications", "/scim"];
 
 // Boolean is extra debug, when true, we ignore the whitelist above and also print the mounts
 pub struct BetterLogging(pub bool);
@@ -319,6 +385,17 @@ impl Fairing for BetterLogging {
         }
         let uri_path = request.uri().path();
         let uri_path_str = uri_path.url_decode_lossy();
+
+        // Log the use of deprecated routes, so they can be disabled once no client needs them anymore
+        if let Some(name) = request.route().and_then(|r| r.name.as_deref())
+            && crate::api::legacy_route_enabled(name).is_some()
+        {
+            let headers = request.headers();
+            let client = headers.get_one("Bitwarden-Client-Name").unwrap_or("unknown");
+            let version = headers.get_one("Bitwarden-Client-Version").unwrap_or("unknown");
+            warn!(target: "legacy", "Deprecated route {} {uri_path_str} used by client {client} {version}", request.method());
+        }
+
         let uri_subpath = uri_path_str.strip_prefix(&CONFIG.domain_path()).unwrap_or(&uri_path_str);
         if self.0 || LOGGED_ROUTES.iter().any(|r| uri_subpath.starts_with(r)) {
             let status = response.status();
@@ -874,22 +951,20 @@ pub fn is_global(ip: std::net::IpAddr) -> bool {
     ip.is_global()
 }
 
-/// Saves a Rocket temporary file to the OpenDAL Operator at the given path.
+/// Saves a Rocket temporary file to the storage backend of the given path type.
+/// If storing the file fails, the temporary file is moved to the quarantine folder.
 pub async fn save_temp_file(
     path_type: &PathType,
     path: &str,
-    temp_file: rocket::fs::TempFile<'_>,
+    mut temp_file: rocket::fs::TempFile<'_>,
     overwrite: bool,
 ) -> Result<(), crate::Error> {
-    use futures::AsyncWriteExt as _;
-    use tokio_util::compat::TokioAsyncReadCompatExt as _;
+    let backend = CONFIG.storage_backend(path_type)?;
 
-    let operator = CONFIG.opendal_operator_for_path_type(path_type)?;
-
-    let mut read_stream = temp_file.open().await?.compat();
-    let mut writer = operator.writer_with(path).if_not_exists(!overwrite).await?.into_futures_async_write();
-    futures::io::copy(&mut read_stream, &mut writer).await?;
-    writer.close().await?;
+    if let Err(e) = backend.put_temp_file(path, &temp_file, overwrite).await {
+        crate::storage::quarantine_temp_file(&mut temp_file, path).await;
+        return Err(e);
+    }
 
     Ok(())
 }
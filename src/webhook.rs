@@ -0,0 +1,116 @@
+//
+// Webhooks
+//
+// Every event is POSTed as JSON to the webhook of the instance and, when allowed, to the webhook of its organization.
+// The receiver can verify the `X-Vaultwarden-Signature` header, the hex encoded HMAC-SHA256 of `<timestamp>.<body>`
+// with the webhook secret, where the timestamp is the one of the `X-Vaultwarden-Timestamp` header.
+// The deliveries are done in the background and are not retried.
+// Webhooks on internal hosts need to be listed in `HTTP_REQUEST_ALLOW_NON_GLOBAL_HOSTS`.
+// The secrets of the organization webhooks are stored encrypted, see `crypto::encrypt_secret`.
+//
+use std::time::Duration;
+
+use chrono::Utc;
+use reqwest::{Method, header};
+use serde_json::Value;
+
+use crate::{
+    CONFIG,
+    crypto::{decrypt_secret, hmac_sha256_sign},
+    db::{
+        DbConn,
+        models::{Event, Organization},
+    },
+    http_client::make_http_request,
+};
+
+pub fn is_enabled() -> bool {
+    !CONFIG.webhook_url().is_empty() || CONFIG.org_webhooks_allowed()
+}
+
+fn is_sent(events: &str, event_type: i32) -> bool {
+    let mut types = events.split(',').map(str::trim).filter(|t| !t.is_empty()).peekable();
+    types.peek().is_none() || types.any(|t| t.parse::<i32>() == Ok(event_type))
+}
+
+/// Send the events to the configured webhooks.
+/// A user event is saved once without an organization and once for every organization of the user,
+/// only the first one is sent to the webhook of the instance, the others to the webhooks of their organizations.
+pub async fn send_events(events: &[Event], conn: &DbConn) {
+    if !is_enabled() {
+        return;
+    }
+
+    let instance_url = CONFIG.webhook_url();
+    let sent_events = CONFIG.webhook_events();
+    let mut instance_sent = false;
+    for event in events.iter().filter(|e| is_sent(&sent_events, e.event_type)) {
+        let payload = json!({
+            "id": event.uuid,
+            "domain": CONFIG.domain(),
+            "event": event.to_json(),
+        });
+
+        if !instance_url.is_empty() && !instance_sent {
+            instance_sent = true;
+            send(instance_url.clone(), CONFIG.webhook_secret(), event.event_type, payload.clone());
+        }
+
+        if CONFIG.org_webhooks_allowed()
+            && let Some(org_id) = &event.org_uuid
+            && let Some(org) = Organization::find_by_uuid(org_id, conn).await
+            && let (Some(url), Some(secret)) = (org.webhook_url, org.webhook_secret)
+        {
+            let Some(secret) = decrypt_secret(&secret) else {
+                warn!("The webhook secret of organization {org_id} can't be decrypted, it needs to be set again");
+                continue;
+            };
+            send(url, secret, event.event_type, payload);
+        }
+    }
+}
+
+fn send(url: String, secret: String, event_type: i32, payload: Value) {
+    tokio::spawn(async move {
+        let body = payload.to_string();
+        let timestamp = Utc::now().timestamp().to_string();
+        let signature = hmac_sha256_sign(&secret, &format!("{timestamp}.{body}"));
+
+        let request = match make_http_request(Method::POST, &url) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Webhook URL {url} is not allowed: {e}");
+                return;
+            }
+        };
+        let result = request
+            .timeout(Duration::from_secs(CONFIG.webhook_timeout()))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("X-Vaultwarden-Event", event_type.to_string())
+            .header("X-Vaultwarden-Timestamp", timestamp)
+            .header("X-Vaultwarden-Signature", format!("sha256={signature}"))
+            .body(body)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+
+        if let Err(e) = result {
+            warn!("Failed to send event {event_type} to webhook {url}: {e}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_sent() {
+        assert!(is_sent("", 1000));
+        assert!(is_sent(" , ", 1000));
+        assert!(is_sent("1000, 1100", 1000));
+        assert!(is_sent("1000,1100", 1100));
+        assert!(!is_sent("1000,1100", 1500));
+        assert!(!is_sent("10000", 1000));
+    }
+}